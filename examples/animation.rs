@@ -3,8 +3,8 @@
  * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
 
 //! This example creates a 200x200 white rect and allows the user to move it
-//! around by using the arrow keys and rotate with '<'/'>'.
-//! It does this by using the animation API.
+//! around by using the arrow keys and rotate with '<'/'>'. 'C' cycles the
+//! color of the blue rect. It does this by using the animation API.
 
 //! The example also features seamless opaque/transparent split of a
 //! rounded cornered rectangle, which is done automatically during the
@@ -30,17 +30,25 @@ struct App {
     property_key1: PropertyBindingKey<LayoutTransform>,
     property_key2: PropertyBindingKey<LayoutTransform>,
     opacity_key: PropertyBindingKey<f32>,
+    color_key: PropertyBindingKey<ColorF>,
     opacity: f32,
+    color_index: usize,
     angle0: f32,
     angle1: f32,
     angle2: f32,
 }
 
+const COLORS: [ColorF; 3] = [
+    ColorF { r: 0.0, g: 0.0, b: 1.0, a: 0.5 },
+    ColorF { r: 1.0, g: 1.0, b: 0.0, a: 0.5 },
+    ColorF { r: 0.0, g: 1.0, b: 1.0, a: 0.5 },
+];
+
 impl App {
     fn add_rounded_rect(
         &mut self,
         bounds: LayoutRect,
-        color: ColorF,
+        color: PropertyBinding<ColorF>,
         builder: &mut DisplayListBuilder,
         pipeline_id: PipelineId,
         property_key: PropertyBindingKey<LayoutTransform>,
@@ -84,7 +92,7 @@ impl App {
         let clip_id = builder.define_clip(&space_and_clip, clip_bounds, vec![complex_clip], None);
 
         // Fill it with a white rect
-        builder.push_rect(
+        builder.push_rect_with_animation(
             &LayoutPrimitiveInfo::new(LayoutRect::new(LayoutPoint::zero(), bounds.size)),
             &SpaceAndClipInfo {
                 spatial_id,
@@ -115,15 +123,16 @@ impl Example for App {
 
         let bounds = (150, 150).to(250, 250);
         let key0 = self.property_key0;
-        self.add_rounded_rect(bounds, ColorF::new(1.0, 0.0, 0.0, 0.5), builder, pipeline_id, key0, Some(opacity_key));
+        self.add_rounded_rect(bounds, ColorF::new(1.0, 0.0, 0.0, 0.5).into(), builder, pipeline_id, key0, Some(opacity_key));
 
         let bounds = (400, 400).to(600, 600);
         let key1 = self.property_key1;
-        self.add_rounded_rect(bounds, ColorF::new(0.0, 1.0, 0.0, 0.5), builder, pipeline_id, key1, None);
+        self.add_rounded_rect(bounds, ColorF::new(0.0, 1.0, 0.0, 0.5).into(), builder, pipeline_id, key1, None);
 
         let bounds = (200, 500).to(350, 580);
         let key2 = self.property_key2;
-        self.add_rounded_rect(bounds, ColorF::new(0.0, 0.0, 1.0, 0.5), builder, pipeline_id, key2, None);
+        let color = PropertyBinding::Binding(self.color_key, COLORS[self.color_index]);
+        self.add_rounded_rect(bounds, color, builder, pipeline_id, key2, None);
     }
 
     fn on_event(&mut self, win_event: winit::WindowEvent, api: &RenderApi, document_id: DocumentId) -> bool {
@@ -143,6 +152,10 @@ impl Example for App {
                     winit::VirtualKeyCode::Up => (0.0, 0.1),
                     winit::VirtualKeyCode::Right => (1.0, 0.0),
                     winit::VirtualKeyCode::Left => (-1.0, 0.0),
+                    winit::VirtualKeyCode::C => {
+                        self.color_index = (self.color_index + 1) % COLORS.len();
+                        (0.0, 0.0)
+                    }
                     winit::VirtualKeyCode::R => {
                         rebuild_display_list = true;
                         (0.0, 0.0)
@@ -182,6 +195,14 @@ impl Example for App {
                                 value: self.opacity,
                             }
                         ],
+                        colors: vec![
+                            PropertyValue {
+                                key: self.color_key,
+                                value: COLORS[self.color_index],
+                            }
+                        ],
+                        images: Vec::new(),
+                        rects: Vec::new(),
                     },
                 );
                 txn.generate_frame();
@@ -200,7 +221,9 @@ fn main() {
         property_key1: PropertyBindingKey::new(44), // arbitrary magic number
         property_key2: PropertyBindingKey::new(45), // arbitrary magic number
         opacity_key: PropertyBindingKey::new(43),
+        color_key: PropertyBindingKey::new(46),
         opacity: 0.5,
+        color_index: 0,
         angle0: 0.0,
         angle1: 0.0,
         angle2: 0.0,
@@ -7,65 +7,25 @@ not(any(feature = "gfx-hal", feature = "gl")),
 allow(dead_code, unused_imports)
 )]
 
-#[cfg(feature = "gfx-hal")]
-extern crate dirs;
 extern crate env_logger;
 extern crate euclid;
-#[cfg(feature = "dx12")]
-extern crate gfx_backend_dx12 as back;
-#[cfg(feature = "metal")]
-extern crate gfx_backend_metal as back;
-#[cfg(feature = "vulkan")]
-extern crate gfx_backend_vulkan as back;
-#[cfg(feature = "gl")]
-extern crate gfx_backend_empty as back;
+
+// The examples only use the window setup of the embedder skeleton.
+#[allow(dead_code)]
+#[path = "examples_support.rs"]
+pub mod examples_support;
 
 #[cfg(feature = "gl")]
 use gleam::gl;
-#[cfg(feature = "gl")]
-use glutin::{self, ContextTrait};
 use std::env;
-#[cfg(feature = "gl")]
-use std::marker::PhantomData;
 use std::path::PathBuf;
 use webrender;
 use winit;
 use webrender::DebugFlags;
 use webrender::ShaderPrecacheFlags;
 use webrender::api::*;
-#[cfg(feature = "gfx-hal")]
-use webrender::hal::Instance;
-
-struct Notifier {
-    events_proxy: winit::EventsLoopProxy,
-}
-
-impl Notifier {
-    fn new(events_proxy: winit::EventsLoopProxy) -> Notifier {
-        Notifier { events_proxy }
-    }
-}
-
-impl RenderNotifier for Notifier {
-    fn clone(&self) -> Box<RenderNotifier> {
-        Box::new(Notifier {
-            events_proxy: self.events_proxy.clone(),
-        })
-    }
-
-    fn wake_up(&self) {
-        #[cfg(not(target_os = "android"))]
-        let _ = self.events_proxy.wakeup();
-    }
-
-    fn new_frame_ready(&self,
-                       _: DocumentId,
-                       _scrolled: bool,
-                       _composite_needed: bool,
-                       _render_time: Option<u64>) {
-        self.wake_up();
-    }
-}
+#[cfg(any(feature = "gfx-hal", feature = "gl"))]
+use self::examples_support::{create_window, Notifier};
 
 pub trait HandyDandyRectBuilder {
     fn to(&self, x2: i32, y2: i32) -> LayoutRect;
@@ -148,65 +108,12 @@ pub fn main_wrapper<E: Example>(
         .with_multitouch()
         .with_dimensions(winit::dpi::LogicalSize::new(E::WIDTH as f64, E::HEIGHT as f64));
 
+    let (window, init) = create_window(&events_loop, window_builder);
     #[cfg(feature = "gl")]
-    let (gl, init, window) = {
-        let context_builder = glutin::ContextBuilder::new()
-        .with_gl(glutin::GlRequest::GlThenGles {
-            opengl_version: (3, 2),
-            opengles_version: (3, 0),
-        });
-        let window = glutin::WindowedContext::new_windowed(window_builder, context_builder, &events_loop)
-        .unwrap();
-
-        unsafe {
-            window.make_current().ok();
-        }
-
-        let gl = match window.get_api() {
-            glutin::Api::OpenGl => unsafe {
-                gl::GlFns::load_with(|symbol| window.get_proc_address(symbol) as *const _)
-            },
-            glutin::Api::OpenGlEs => unsafe {
-                gl::GlesFns::load_with(|symbol| window.get_proc_address(symbol) as *const _)
-            },
-            glutin::Api::WebGl => unimplemented!(),
-        };
-
-        println!("OpenGL version {}", gl.get_string(gl::VERSION));
-        let init: webrender::DeviceInit<back::Backend> = webrender::DeviceInit {
-            gl: gl.clone(),
-            phantom_data: PhantomData,
-        };
-        (gl, init, window)
-    };
-
-    #[cfg(feature = "gfx-hal")]
-    let (window, init) = {
-        let window = window_builder.build(&events_loop).unwrap();
-        let instance = back::Instance::create("gfx-rs instance", 1);
-        let mut adapters = instance.enumerate_adapters();
-        let adapter = adapters.remove(0);
-        let mut surface = Some(instance.create_surface(&window));
-        let winit::dpi::LogicalSize { width, height } = window.get_inner_size().unwrap();
-        let init = {
-            let cache_dir = dirs::cache_dir().expect("User's cache directory not found");
-            let cache_path = Some(PathBuf::from(&cache_dir).join("pipeline_cache.bin"));
-
-            webrender::DeviceInit {
-                instance: Box::new(instance),
-                adapter,
-                surface,
-                window_size: (width as i32, height as i32),
-                descriptor_count: None,
-                cache_path,
-                save_cache: true,
-            }
-        };
-        (window, init)
-    };
+    println!("OpenGL version {}", window.gl.get_string(gl::VERSION));
 
     println!("Shader resource path: {:?}", res_path);
-    let device_pixel_ratio = window.get_hidpi_factor() as f32;
+    let device_pixel_ratio = window.hidpi_factor() as f32;
     println!("Device pixel ratio: {}", device_pixel_ratio);
 
     println!("Loading shaders...");
@@ -232,20 +139,14 @@ pub fn main_wrapper<E: Example>(
         ..options.unwrap_or(webrender::RendererOptions::default())
     };
 
-    let mut framebuffer_size = {
-        let size = window
-            .get_inner_size()
-            .unwrap()
-            .to_physical(device_pixel_ratio as f64);
-        DeviceIntSize::new(size.width as i32, size.height as i32)
-    };
+    let mut framebuffer_size = window.framebuffer_size();
     let notifier = Box::new(Notifier::new(events_loop.create_proxy()));
     let (mut renderer, sender) = webrender::Renderer::new(init, notifier, opts, None).unwrap();
     let api = sender.create_api();
     let document_id = api.add_document(framebuffer_size, 0);
 
     #[cfg(feature = "gl")]
-    let (external, output) = example.get_image_handlers(&*gl);
+    let (external, output) = example.get_image_handlers(&*window.gl);
 
     #[cfg(feature = "gfx-hal")]
     let (external, output) = (None, None);
@@ -406,9 +307,8 @@ pub fn main_wrapper<E: Example>(
         renderer.render(framebuffer_size).unwrap();
         let _ = renderer.flush_pipeline_info();
         #[cfg(feature = "gl")]
-        example.draw_custom(&*gl);
-        #[cfg(feature = "gl")]
-        window.swap_buffers().ok();
+        example.draw_custom(&*window.gl);
+        window.present();
 
         winit::ControlFlow::Continue
     });
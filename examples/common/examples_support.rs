@@ -0,0 +1,348 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+//! Minimal embedder skeleton.
+//!
+//! Wraps the window creation, adapter/surface selection, `RenderNotifier`
+//! implementation and event loop glue that every embedder has to write, so
+//! that examples and test harnesses can get a renderer on screen with a
+//! handful of calls:
+//!
+//! ```ignore
+//! let embedder = Embedder::new("Sample", 800, 600, RendererOptions::default());
+//! let pipeline_id = PipelineId(0, 0);
+//! embedder.run(|embedder, event| {
+//!     match event {
+//!         Some(winit::WindowEvent::CloseRequested) => return false,
+//!         Some(_) => return true,
+//!         None => {}
+//!     }
+//!     let mut builder = embedder.display_list_builder(pipeline_id);
+//!     let rect = LayoutRect::new(LayoutPoint::new(100.0, 100.0), LayoutSize::new(200.0, 200.0));
+//!     let info = LayoutPrimitiveInfo::new(rect);
+//!     builder.push_rect(&info, &SpaceAndClipInfo::root_scroll(pipeline_id), ColorF::new(0.0, 1.0, 0.0, 1.0));
+//!     embedder.set_display_list(builder);
+//!     true
+//! });
+//! ```
+
+#![cfg_attr(
+not(any(feature = "gfx-hal", feature = "gl")),
+allow(dead_code, unused_imports)
+)]
+
+#[cfg(feature = "gfx-hal")]
+extern crate dirs;
+extern crate env_logger;
+extern crate euclid;
+#[cfg(feature = "dx12")]
+extern crate gfx_backend_dx12 as back;
+#[cfg(feature = "metal")]
+extern crate gfx_backend_metal as back;
+#[cfg(feature = "vulkan")]
+extern crate gfx_backend_vulkan as back;
+#[cfg(feature = "gl")]
+extern crate gfx_backend_empty as back;
+
+#[cfg(feature = "gl")]
+use gleam::gl;
+#[cfg(feature = "gl")]
+use glutin::{self, ContextTrait};
+#[cfg(feature = "gl")]
+use std::marker::PhantomData;
+#[cfg(feature = "gfx-hal")]
+use std::path::PathBuf;
+#[cfg(feature = "gl")]
+use std::rc::Rc;
+use webrender;
+use winit;
+use webrender::api::*;
+#[cfg(feature = "gfx-hal")]
+use webrender::hal::Instance;
+
+/// A `RenderNotifier` that wakes up the winit event loop.
+pub struct Notifier {
+    events_proxy: winit::EventsLoopProxy,
+}
+
+impl Notifier {
+    pub fn new(events_proxy: winit::EventsLoopProxy) -> Notifier {
+        Notifier { events_proxy }
+    }
+}
+
+impl RenderNotifier for Notifier {
+    fn clone(&self) -> Box<RenderNotifier> {
+        Box::new(Notifier {
+            events_proxy: self.events_proxy.clone(),
+        })
+    }
+
+    fn wake_up(&self) {
+        #[cfg(not(target_os = "android"))]
+        let _ = self.events_proxy.wakeup();
+    }
+
+    fn new_frame_ready(&self,
+                       _: DocumentId,
+                       _scrolled: bool,
+                       _composite_needed: bool,
+                       _render_time: Option<u64>) {
+        self.wake_up();
+    }
+}
+
+/// The window along with whatever the backend needs to present into it.
+#[cfg(feature = "gl")]
+pub struct EmbedderWindow {
+    pub window: glutin::WindowedContext,
+    pub gl: Rc<gl::Gl>,
+}
+
+/// The window along with whatever the backend needs to present into it.
+#[cfg(feature = "gfx-hal")]
+pub struct EmbedderWindow {
+    pub window: winit::Window,
+}
+
+#[cfg(any(feature = "gfx-hal", feature = "gl"))]
+impl EmbedderWindow {
+    pub fn hidpi_factor(&self) -> f64 {
+        self.window.get_hidpi_factor()
+    }
+
+    /// Returns the inner size of the window in device pixels.
+    pub fn framebuffer_size(&self) -> DeviceIntSize {
+        let size = self.window
+            .get_inner_size()
+            .unwrap()
+            .to_physical(self.hidpi_factor());
+        DeviceIntSize::new(size.width as i32, size.height as i32)
+    }
+
+    /// Presents the rendered frame, if the backend doesn't do it as part of
+    /// `Renderer::render`.
+    pub fn present(&self) {
+        #[cfg(feature = "gl")]
+        self.window.swap_buffers().ok();
+    }
+}
+
+/// Creates a window and the matching device initialization parameters.
+///
+/// Requests a GL 3.2 context, or GLES 3.0 where desktop GL is unavailable,
+/// and makes it current.
+#[cfg(feature = "gl")]
+pub fn create_window(
+    events_loop: &winit::EventsLoop,
+    window_builder: winit::WindowBuilder,
+) -> (EmbedderWindow, webrender::DeviceInit<back::Backend>) {
+    let context_builder = glutin::ContextBuilder::new()
+        .with_gl(glutin::GlRequest::GlThenGles {
+            opengl_version: (3, 2),
+            opengles_version: (3, 0),
+        });
+    let window = glutin::WindowedContext::new_windowed(window_builder, context_builder, events_loop)
+        .unwrap();
+
+    unsafe {
+        window.make_current().ok();
+    }
+
+    let gl = match window.get_api() {
+        glutin::Api::OpenGl => unsafe {
+            gl::GlFns::load_with(|symbol| window.get_proc_address(symbol) as *const _)
+        },
+        glutin::Api::OpenGlEs => unsafe {
+            gl::GlesFns::load_with(|symbol| window.get_proc_address(symbol) as *const _)
+        },
+        glutin::Api::WebGl => unimplemented!(),
+    };
+
+    let init = webrender::DeviceInit {
+        gl: gl.clone(),
+        phantom_data: PhantomData,
+    };
+    (EmbedderWindow { window, gl }, init)
+}
+
+/// Creates a window and the matching device initialization parameters.
+///
+/// With the hal backends, the first adapter reported by the instance is used.
+#[cfg(feature = "gfx-hal")]
+pub fn create_window(
+    events_loop: &winit::EventsLoop,
+    window_builder: winit::WindowBuilder,
+) -> (EmbedderWindow, webrender::DeviceInit<back::Backend>) {
+    let window = window_builder.build(events_loop).unwrap();
+    let instance = back::Instance::create("gfx-rs instance", 1);
+    let mut adapters = instance.enumerate_adapters();
+    let adapter = adapters.remove(0);
+    let surface = Some(instance.create_surface(&window));
+    let winit::dpi::LogicalSize { width, height } = window.get_inner_size().unwrap();
+    let cache_path = dirs::cache_dir()
+        .map(|cache_dir| PathBuf::from(&cache_dir).join("pipeline_cache.bin"));
+
+    let init = webrender::DeviceInit {
+        instance: Box::new(instance),
+        adapter,
+        surface,
+        window_size: (width as i32, height as i32),
+        descriptor_count: None,
+        save_cache: cache_path.is_some(),
+        cache_path,
+    };
+    (EmbedderWindow { window }, init)
+}
+
+/// A window with a renderer and a single document covering it.
+#[cfg(any(feature = "gfx-hal", feature = "gl"))]
+pub struct Embedder {
+    /// Taken out while `run` drives the loop.
+    events_loop: Option<winit::EventsLoop>,
+    pub window: EmbedderWindow,
+    pub renderer: webrender::Renderer<back::Backend>,
+    pub api: RenderApi,
+    pub document_id: DocumentId,
+    pub framebuffer_size: DeviceIntSize,
+    pub device_pixel_ratio: f32,
+    pub epoch: Epoch,
+}
+
+#[cfg(any(feature = "gfx-hal", feature = "gl"))]
+impl Embedder {
+    /// Opens a window of the given logical size and sets up a renderer and
+    /// a document for it.
+    ///
+    /// The device pixel ratio of `options` is overridden with the one of the
+    /// window.
+    pub fn new(
+        title: &str,
+        width: u32,
+        height: u32,
+        options: webrender::RendererOptions,
+    ) -> Self {
+        let _ = env_logger::try_init();
+
+        let events_loop = winit::EventsLoop::new();
+        let window_builder = winit::WindowBuilder::new()
+            .with_title(title)
+            .with_multitouch()
+            .with_dimensions(winit::dpi::LogicalSize::new(width as f64, height as f64));
+        let (window, init) = create_window(&events_loop, window_builder);
+
+        let device_pixel_ratio = window.hidpi_factor() as f32;
+        let framebuffer_size = window.framebuffer_size();
+        let opts = webrender::RendererOptions {
+            device_pixel_ratio,
+            ..options
+        };
+        let notifier = Box::new(Notifier::new(events_loop.create_proxy()));
+        let (renderer, sender) = webrender::Renderer::new(init, notifier, opts, None).unwrap();
+        let api = sender.create_api();
+        let document_id = api.add_document(framebuffer_size, 0);
+
+        Embedder {
+            events_loop: Some(events_loop),
+            window,
+            renderer,
+            api,
+            document_id,
+            framebuffer_size,
+            device_pixel_ratio,
+            epoch: Epoch(0),
+        }
+    }
+
+    /// The size of the window in layout pixels.
+    pub fn layout_size(&self) -> LayoutSize {
+        self.framebuffer_size.to_f32() / euclid::TypedScale::new(self.device_pixel_ratio)
+    }
+
+    /// Returns a builder covering the whole window.
+    pub fn display_list_builder(&self, pipeline_id: PipelineId) -> DisplayListBuilder {
+        DisplayListBuilder::new(pipeline_id, self.layout_size())
+    }
+
+    /// Sends the display list as the root pipeline of the document and
+    /// requests a new frame.
+    pub fn set_display_list(&mut self, builder: DisplayListBuilder) {
+        let layout_size = self.layout_size();
+        let (pipeline_id, _, display_list) = builder.finalize();
+        let mut txn = Transaction::new();
+        txn.set_display_list(
+            self.epoch,
+            None,
+            layout_size,
+            (pipeline_id, layout_size, display_list),
+            true,
+        );
+        txn.set_root_pipeline(pipeline_id);
+        txn.generate_frame();
+        self.api.send_transaction(self.document_id, txn);
+        self.epoch.0 += 1;
+    }
+
+    /// Renders and presents the most recent frame.
+    pub fn render_frame(&mut self) {
+        self.renderer.update();
+        self.renderer.render(self.framebuffer_size).unwrap();
+        let _ = self.renderer.flush_pipeline_info();
+        self.window.present();
+    }
+
+    fn resize(&mut self, dims: winit::dpi::LogicalSize) {
+        let physical = dims.to_physical(self.device_pixel_ratio as f64);
+        let new_size = (physical.width as i32, physical.height as i32);
+        // Workaround for Rust issue #15701 (E0658).
+        #[cfg(feature = "gfx-hal")]
+        { self.framebuffer_size = self.renderer.resize(Some(new_size)); }
+        #[cfg(feature = "gl")]
+        { self.framebuffer_size = DeviceIntSize::new(new_size.0, new_size.1); }
+
+        self.api.set_window_parameters(
+            self.document_id,
+            self.framebuffer_size,
+            DeviceIntRect::new(DeviceIntPoint::zero(), self.framebuffer_size),
+            self.device_pixel_ratio,
+        );
+    }
+
+    /// Runs the event loop until the callback returns `false` or the window
+    /// is closed, then shuts the renderer down.
+    ///
+    /// The callback is invoked once with `None` before entering the loop, so
+    /// that it can send the initial display list, and then with every window
+    /// event. Resizes are handled before the callback sees them. A frame is
+    /// rendered after each callback invocation.
+    pub fn run<F>(mut self, mut callback: F)
+    where
+        F: FnMut(&mut Embedder, Option<winit::WindowEvent>) -> bool,
+    {
+        if callback(&mut self, None) {
+            self.render_frame();
+
+            let mut events_loop = self.events_loop.take().unwrap();
+            events_loop.run_forever(|global_event| {
+                let event = match global_event {
+                    winit::Event::WindowEvent { event, .. } => event,
+                    _ => return winit::ControlFlow::Continue,
+                };
+                if let winit::WindowEvent::CloseRequested = event {
+                    return winit::ControlFlow::Break;
+                }
+                if let winit::WindowEvent::Resized(dims) = event {
+                    self.resize(dims);
+                }
+                if !callback(&mut self, Some(event)) {
+                    return winit::ControlFlow::Break;
+                }
+                self.render_frame();
+                winit::ControlFlow::Continue
+            });
+        }
+
+        self.renderer.deinit();
+    }
+}
@@ -0,0 +1,54 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+#![cfg_attr(
+not(any(feature = "gfx-hal", feature = "gl")),
+allow(dead_code, unused_imports)
+)]
+
+#[cfg(feature = "gl")]
+extern crate gleam;
+#[cfg(feature = "gl")]
+extern crate glutin;
+extern crate webrender;
+extern crate winit;
+
+#[path = "common/examples_support.rs"]
+mod examples_support;
+
+use webrender::api::*;
+
+#[cfg(any(feature = "gfx-hal", feature = "gl"))]
+fn main() {
+    let embedder = examples_support::Embedder::new(
+        "WebRender Embedder Skeleton",
+        800,
+        600,
+        webrender::RendererOptions::default(),
+    );
+    let pipeline_id = PipelineId(0, 0);
+
+    embedder.run(|embedder, event| {
+        match event {
+            // Only (re)build the display list initially and on resize.
+            None | Some(winit::WindowEvent::Resized(..)) => {}
+            Some(_) => return true,
+        }
+        let mut builder = embedder.display_list_builder(pipeline_id);
+        let rect = LayoutRect::new(LayoutPoint::new(100.0, 100.0), LayoutSize::new(200.0, 200.0));
+        let info = LayoutPrimitiveInfo::new(rect);
+        builder.push_rect(
+            &info,
+            &SpaceAndClipInfo::root_scroll(pipeline_id),
+            ColorF::new(0.0, 1.0, 0.0, 1.0),
+        );
+        embedder.set_display_list(builder);
+        true
+    });
+}
+
+#[cfg(not(any(feature = "gfx-hal", feature = "gl")))]
+fn main() {
+    println!("You need to enable one of the native API features (dx12/gl/metal/vulkan) in order to run this example.");
+}
@@ -60,6 +60,7 @@ impl webrender::ExternalImageHandler for ExternalHandler {
         webrender::ExternalImage {
             uv: TexelRect::new(0.0, 0.0, 1.0, 1.0),
             source: webrender::ExternalImageSource::NativeTexture(self.texture_id),
+            timestamp: None,
         }
     }
     fn unlock(&mut self, _key: ExternalImageId, _channel_index: u8) {}
@@ -74,6 +74,7 @@ impl webrender::ExternalImageHandler for ImageGenerator {
         webrender::ExternalImage {
             uv: TexelRect::new(0.0, 0.0, 1.0, 1.0),
             source: webrender::ExternalImageSource::RawData(&self.current_image),
+            timestamp: None,
         }
     }
     fn unlock(&mut self, _key: ExternalImageId, _channel_index: u8) {}
@@ -72,6 +72,8 @@ impl webrender::ExternalImageHandler for YuvImageProvider {
         webrender::ExternalImage {
             uv: TexelRect::new(0.0, 0.0, 1.0, 1.0),
             source: webrender::ExternalImageSource::NativeTexture(id),
+            // The textures never change.
+            timestamp: Some(0),
         }
     }
     fn unlock(&mut self, _key: ExternalImageId, _channel_index: u8) {
@@ -165,6 +167,7 @@ impl Example for App {
             YuvData::NV12(yuv_chanel1, yuv_chanel2),
             ColorDepth::Color8,
             YuvColorSpace::Rec601,
+            ColorRange::Limited,
             ImageRendering::Auto,
         );
 
@@ -178,6 +181,7 @@ impl Example for App {
             YuvData::PlanarYCbCr(yuv_chanel1, yuv_chanel2_1, yuv_chanel3),
             ColorDepth::Color8,
             YuvColorSpace::Rec601,
+            ColorRange::Limited,
             ImageRendering::Auto,
         );
 
@@ -614,6 +614,14 @@ fn create_vertex_buffer_descriptors(file_name: &str) -> Vec<VertexBufferDesc> {
                 rate: VertexInputRate::Instance(1),
             }
         );
+    } else if file_name.starts_with("cs_svg_filter") {
+        descriptors.push(
+            VertexBufferDesc {
+                binding: 1,
+                stride: mem::size_of::<SvgFilterInstance>() as _,
+                rate: VertexInputRate::Instance(1),
+            }
+        );
     } else if file_name.starts_with("cs_line") {
         descriptors.push(
             VertexBufferDesc {
@@ -622,6 +630,14 @@ fn create_vertex_buffer_descriptors(file_name: &str) -> Vec<VertexBufferDesc> {
                 rate: VertexInputRate::Instance(1),
             }
         );
+    } else if file_name.starts_with("blit") {
+        descriptors.push(
+            VertexBufferDesc {
+                binding: 1,
+                stride: mem::size_of::<BlitInstance>() as _,
+                rate: VertexInputRate::Instance(1),
+            }
+        );
     } else if file_name.starts_with("debug_color") {
         descriptors = vec![
             VertexBufferDesc {
@@ -37,6 +37,9 @@ const bool alpha_pass =
     false;
 #endif
 
+// The output color transforms are only applied by the gfx backend.
+const int output_color_transform = 0;
+
 #if defined(GL_ES)
     #if GL_ES == 1
         #ifdef GL_FRAGMENT_PRECISION_HIGH
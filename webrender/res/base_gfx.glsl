@@ -7,6 +7,7 @@ layout(constant_id = 1) const bool color_target = false;
 layout(constant_id = 2) const bool glyph_transform_f = false;
 layout(constant_id = 3) const bool dithering = false;
 layout(constant_id = 4) const bool debug_overdraw = false;
+layout(constant_id = 5) const int output_color_transform = 0;
 
 #if defined(GL_ES)
     #if GL_ES == 1
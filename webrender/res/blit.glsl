@@ -0,0 +1,31 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+// Copies a rect of a texture layer into the bound target, scaling it
+// with linear filtering. Used by the device for blits the backend can't
+// perform between the given formats, and to composite the frame into an
+// HDR swapchain image, converting it into the output color space.
+
+#include shared,shared_other
+
+varying vec3 vUv;
+
+#ifdef WR_VERTEX_SHADER
+in vec4 aBlitTargetRect;
+in vec4 aBlitSourceRect;
+in int aBlitSourceLayer;
+
+void main(void) {
+    vec2 pos = mix(aBlitTargetRect.xy, aBlitTargetRect.zw, aPosition.xy);
+    vUv.xy = mix(aBlitSourceRect.xy, aBlitSourceRect.zw, aPosition.xy);
+    vUv.z = float(aBlitSourceLayer);
+    gl_Position = uTransform * vec4(pos, 0.0, 1.0);
+}
+#endif
+
+#ifdef WR_FRAGMENT_SHADER
+void main(void) {
+    oFragColor = apply_output_transform(texture(sColor0, vUv));
+}
+#endif
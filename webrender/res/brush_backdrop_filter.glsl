@@ -0,0 +1,74 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+#define VECS_PER_SPECIFIC_BRUSH 3
+
+#include shared,prim_shared,brush
+
+varying vec3 vBackdropUv;
+flat varying vec4 vBackdropUvBounds;
+flat varying int vFilterCount;
+flat varying int vFilterAddress;
+
+#ifdef WR_VERTEX_SHADER
+
+//Note: this function is unsafe for `vi.world_pos.w <= 0.0`
+vec2 snap_device_pos(VertexInfo vi, float device_pixel_scale) {
+    return vi.world_pos.xy * device_pixel_scale / max(0.0, vi.world_pos.w) + vi.snap_offset;
+}
+
+void brush_vs(
+    VertexInfo vi,
+    int prim_address,
+    RectWithSize local_rect,
+    RectWithSize segment_rect,
+    ivec4 user_data,
+    mat4 transform,
+    PictureTask pic_task,
+    int brush_flags,
+    vec4 unused
+) {
+    vec2 snapped_device_pos = snap_device_pos(vi, pic_task.common_data.device_pixel_scale);
+    vec2 texture_size = vec2(textureSize(sPrevPassColor, 0));
+
+    // The backdrop was captured at the screen position of the content.
+    PictureTask src_task = fetch_picture_task(user_data.z);
+    RenderTaskCommonData backdrop_task = fetch_render_task_common_data(user_data.y);
+    vec2 backdrop_uv = snapped_device_pos +
+                       backdrop_task.task_rect.p0 -
+                       src_task.content_origin;
+    vBackdropUv = vec3(backdrop_uv / texture_size, backdrop_task.texture_layer_index);
+
+    RectWithSize backdrop_rect = backdrop_task.task_rect;
+    vBackdropUvBounds = vec4(
+        backdrop_rect.p0 + vec2(0.5),
+        backdrop_rect.p0 + backdrop_rect.size - vec2(0.5)
+    ) / texture_size.xyxy;
+
+    vFilterCount = int(fetch_from_gpu_cache_1(user_data.x).x);
+    vFilterAddress = user_data.x + 1;
+}
+#endif
+
+#ifdef WR_FRAGMENT_SHADER
+Fragment brush_fs() {
+    vec2 uv = clamp(vBackdropUv.xy, vBackdropUvBounds.xy, vBackdropUvBounds.zw);
+    vec4 backdrop = texture(sPrevPassColor, vec3(uv, vBackdropUv.z));
+
+    // The filters operate on non-premultiplied colors, and each of them
+    // clamps its result like the individual filter shaders do.
+    vec4 color = backdrop.a > 0.0 ? vec4(backdrop.rgb / backdrop.a, backdrop.a) : vec4(0.0);
+
+    for (int i = 0; i < vFilterCount; i++) {
+        int address = vFilterAddress + 5 * i;
+        vec4 columns_01[2] = fetch_from_gpu_cache_2(address);
+        vec4 columns_23[2] = fetch_from_gpu_cache_2(address + 2);
+        vec4 offset[2] = fetch_from_gpu_cache_2(address + 3);
+        mat4 m = mat4(columns_01[0], columns_01[1], columns_23[0], columns_23[1]);
+        color = clamp(m * color + offset[1], vec4(0.0), vec4(1.0));
+    }
+
+    return Fragment(vec4(color.rgb * color.a, color.a));
+}
+#endif
@@ -0,0 +1,134 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+#define VECS_PER_SPECIFIC_BRUSH 2
+
+#include shared,prim_shared,brush
+
+#define TWO_PI 6.28318530718
+
+flat varying int vGradientAddress;
+flat varying float vGradientRepeat;
+
+flat varying vec2 vCenter;
+flat varying float vAngle;
+flat varying float vStartOffset;
+flat varying float vEndOffset;
+
+varying vec2 vPos;
+flat varying vec2 vRepeatedSize;
+
+varying vec2 vLocalPos;
+flat varying vec2 vTileRepeat;
+
+#ifdef WR_VERTEX_SHADER
+
+struct ConicGradient {
+    vec2 center;
+    float angle;
+    float start_offset;
+    float end_offset;
+    int extend_mode;
+    vec2 stretch_size;
+};
+
+ConicGradient fetch_conic_gradient(int address) {
+    vec4 data[2] = fetch_from_gpu_cache_2(address);
+    return ConicGradient(
+        data[0].xy,
+        data[0].z,
+        data[0].w,
+        data[1].x,
+        int(data[1].y),
+        data[1].zw
+    );
+}
+
+void brush_vs(
+    VertexInfo vi,
+    int prim_address,
+    RectWithSize local_rect,
+    RectWithSize segment_rect,
+    ivec4 user_data,
+    mat4 transform,
+    PictureTask pic_task,
+    int brush_flags,
+    vec4 texel_rect
+) {
+    ConicGradient gradient = fetch_conic_gradient(prim_address);
+
+    if ((brush_flags & BRUSH_FLAG_SEGMENT_RELATIVE) != 0) {
+        vPos = (vi.local_pos - segment_rect.p0) / segment_rect.size;
+        vPos = vPos * (texel_rect.zw - texel_rect.xy) + texel_rect.xy;
+    } else {
+        vPos = vi.local_pos - local_rect.p0;
+    }
+
+    vCenter = gradient.center;
+    vAngle = gradient.angle;
+    vStartOffset = gradient.start_offset;
+    vEndOffset = gradient.end_offset;
+
+    vec2 tile_repeat = local_rect.size / gradient.stretch_size;
+    vRepeatedSize = gradient.stretch_size;
+
+    vGradientAddress = user_data.x;
+
+    // Whether to repeat the gradient instead of clamping.
+    vGradientRepeat = float(gradient.extend_mode != EXTEND_MODE_CLAMP);
+
+    if (alpha_pass) {
+        vTileRepeat = tile_repeat.xy;
+        vLocalPos = vi.local_pos;
+    }
+}
+#endif
+
+#ifdef WR_FRAGMENT_SHADER
+Fragment brush_fs() {
+
+    vec2 pos;
+    if (alpha_pass) {
+        // Handle top and left inflated edges (see brush_image).
+        vec2 local_pos = max(vPos, vec2(0.0));
+
+        // Apply potential horizontal and vertical repetitions.
+        pos = mod(local_pos, vRepeatedSize);
+
+        vec2 prim_size = vRepeatedSize * vTileRepeat;
+        // Handle bottom and right inflated edges (see brush_image).
+        if (local_pos.x >= prim_size.x) {
+            pos.x = vRepeatedSize.x;
+        }
+        if (local_pos.y >= prim_size.y) {
+            pos.y = vRepeatedSize.y;
+        }
+    } else {
+        // Apply potential horizontal and vertical repetitions.
+        pos = mod(vPos, vRepeatedSize);
+    }
+
+    vec2 pd = pos - vCenter;
+
+    // The angle of this fragment, measured clockwise from the top and
+    // relative to the gradient's start angle, as a fraction of a turn.
+    float turn = fract((atan(pd.x, -pd.y) - vAngle) / TWO_PI);
+
+    float od = vEndOffset - vStartOffset;
+    if (od == 0.0) {
+        discard;
+    }
+    float offset = (turn - vStartOffset) / od;
+
+    vec4 color = sample_gradient(vGradientAddress,
+                                 offset,
+                                 vGradientRepeat);
+
+    if (alpha_pass) {
+        color *= init_transform_fs(vLocalPos);
+    }
+
+    return Fragment(color);
+}
+#endif
@@ -0,0 +1,91 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+#define VECS_PER_SPECIFIC_BRUSH 1
+
+#include shared,prim_shared,brush
+
+varying vec2 vLocalPos;
+
+varying vec3 vUv_From;
+flat varying vec4 vUvBounds_From;
+
+varying vec3 vUv_To;
+flat varying vec4 vUvBounds_To;
+
+flat varying float vProgress;
+
+#ifdef WR_FEATURE_TEXTURE_RECT
+    #define TEX_SIZE(sampler) vec2(1.0)
+#else
+    #define TEX_SIZE(sampler) vec2(textureSize(sampler, 0).xy)
+#endif
+
+#ifdef WR_VERTEX_SHADER
+
+void write_uv_rect(
+    int resource_id,
+    vec2 f,
+    vec2 texture_size,
+    out vec3 uv,
+    out vec4 uv_bounds
+) {
+    ImageResource res = fetch_image_resource(resource_id);
+    vec2 uv0 = res.uv_rect.p0;
+    vec2 uv1 = res.uv_rect.p1;
+
+    uv.xy = mix(uv0, uv1, f);
+    uv.z = res.layer;
+
+    uv_bounds = vec4(min(uv0, uv1) + vec2(0.5), max(uv0, uv1) - vec2(0.5));
+
+    #ifndef WR_FEATURE_TEXTURE_RECT
+        uv.xy /= texture_size;
+        uv_bounds /= texture_size.xyxy;
+    #endif
+}
+
+void brush_vs(
+    VertexInfo vi,
+    int prim_address,
+    RectWithSize local_rect,
+    RectWithSize segment_rect,
+    ivec4 user_data,
+    mat4 transform,
+    PictureTask pic_task,
+    int brush_flags,
+    vec4 unused
+) {
+    vec2 f = (vi.local_pos - local_rect.p0) / local_rect.size;
+
+    vec4 data = fetch_from_gpu_cache_1(prim_address);
+    vProgress = clamp(data.x, 0.0, 1.0);
+
+    if (alpha_pass) {
+        vLocalPos = vi.local_pos;
+    }
+
+    write_uv_rect(user_data.x, f, TEX_SIZE(sColor0), vUv_From, vUvBounds_From);
+    write_uv_rect(user_data.y, f, TEX_SIZE(sColor1), vUv_To, vUvBounds_To);
+}
+#endif
+
+#ifdef WR_FRAGMENT_SHADER
+
+Fragment brush_fs() {
+    vec2 uv_from = clamp(vUv_From.xy, vUvBounds_From.xy, vUvBounds_From.zw);
+    vec2 uv_to = clamp(vUv_To.xy, vUvBounds_To.xy, vUvBounds_To.zw);
+    vec4 from_color = TEX_SAMPLE(sColor0, vec3(uv_from, vUv_From.z));
+    vec4 to_color = TEX_SAMPLE(sColor1, vec3(uv_to, vUv_To.z));
+
+    // Both images are premultiplied, so they can be interpolated directly.
+    vec4 color = mix(from_color, to_color, vProgress);
+
+    if (alpha_pass) {
+        color *= init_transform_fs(vLocalPos);
+    }
+
+    return Fragment(color);
+}
+#endif
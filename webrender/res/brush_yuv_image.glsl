@@ -2,7 +2,7 @@
  * License, v. 2.0. If a copy of the MPL was not distributed with this
  * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
 
-#define VECS_PER_SPECIFIC_BRUSH 1
+#define VECS_PER_SPECIFIC_BRUSH 4
 
 #include shared,prim_shared,brush
 
@@ -11,12 +11,11 @@
 //           need to compile, it might be worth just doing this as an
 //           uber-shader instead.
 
-#define YUV_COLOR_SPACE_REC601 0
-#define YUV_COLOR_SPACE_REC709 1
-
 #define YUV_FORMAT_NV12 0
 #define YUV_FORMAT_PLANAR 1
 #define YUV_FORMAT_INTERLEAVED 2
+#define YUV_FORMAT_P010 3
+#define YUV_FORMAT_P016 4
 
 varying vec2 vLocalPos;
 
@@ -37,39 +36,10 @@ flat varying vec4 vUvBounds_V;
 
 flat varying float vCoefficient;
 flat varying mat3 vYuvColorMatrix;
+flat varying vec3 vYuvOffsetVector;
 flat varying int vFormat;
 
 #ifdef WR_VERTEX_SHADER
-// The constants added to the Y, U and V components are applied in the fragment shader.
-
-// From Rec601:
-// [R]   [1.1643835616438356,  0.0,                 1.5960267857142858   ]   [Y -  16]
-// [G] = [1.1643835616438358, -0.3917622900949137, -0.8129676472377708   ] x [U - 128]
-// [B]   [1.1643835616438356,  2.017232142857143,   8.862867620416422e-17]   [V - 128]
-//
-// For the range [0,1] instead of [0,255].
-//
-// The matrix is stored in column-major.
-const mat3 YuvColorMatrixRec601 = mat3(
-    1.16438,  1.16438, 1.16438,
-    0.0,     -0.39176, 2.01723,
-    1.59603, -0.81297, 0.0
-);
-
-// From Rec709:
-// [R]   [1.1643835616438356,  4.2781193979771426e-17, 1.7927410714285714]   [Y -  16]
-// [G] = [1.1643835616438358, -0.21324861427372963,   -0.532909328559444 ] x [U - 128]
-// [B]   [1.1643835616438356,  2.1124017857142854,     0.0               ]   [V - 128]
-//
-// For the range [0,1] instead of [0,255]:
-//
-// The matrix is stored in column-major.
-const mat3 YuvColorMatrixRec709 = mat3(
-    1.16438,  1.16438,  1.16438,
-    0.0    , -0.21325,  2.11240,
-    1.79274, -0.53291,  0.0
-);
-
 void write_uv_rect(
     int resource_id,
     vec2 f,
@@ -94,13 +64,21 @@ void write_uv_rect(
 
 struct YuvPrimitive {
     float coefficient;
-    int color_space;
     int yuv_format;
+    mat3 yuv_color_matrix;
+    vec3 yuv_offset_vector;
 };
 
+// The color space and range of the image are resolved on the CPU into a
+// matrix, whose columns are each followed by the offset of their channel.
 YuvPrimitive fetch_yuv_primitive(int address) {
-    vec4 data = fetch_from_gpu_cache_1(address);
-    return YuvPrimitive(data.x, int(data.y), int(data.z));
+    vec4 data[4] = fetch_from_gpu_cache_4(address);
+    return YuvPrimitive(
+        data[0].x,
+        int(data[0].y),
+        mat3(data[1].xyz, data[2].xyz, data[3].xyz),
+        vec3(data[1].w, data[2].w, data[3].w)
+    );
 }
 
 void brush_vs(
@@ -118,12 +96,8 @@ void brush_vs(
 
     YuvPrimitive prim = fetch_yuv_primitive(prim_address);
     vCoefficient = prim.coefficient;
-
-    if (prim.color_space == YUV_COLOR_SPACE_REC601) {
-      vYuvColorMatrix = YuvColorMatrixRec601;
-    } else {
-      vYuvColorMatrix = YuvColorMatrixRec709;
-    }
+    vYuvColorMatrix = prim.yuv_color_matrix;
+    vYuvOffsetVector = prim.yuv_offset_vector;
     vFormat = prim.yuv_format;
 
     if (alpha_pass) {
@@ -134,7 +108,9 @@ void brush_vs(
         write_uv_rect(user_data.x, f, TEX_SIZE(sColor0), vUv_Y, vUvBounds_Y);
         write_uv_rect(user_data.y, f, TEX_SIZE(sColor1), vUv_U, vUvBounds_U);
         write_uv_rect(user_data.z, f, TEX_SIZE(sColor2), vUv_V, vUvBounds_V);
-    } else if (vFormat == YUV_FORMAT_NV12) {
+    } else if (vFormat == YUV_FORMAT_NV12 ||
+               vFormat == YUV_FORMAT_P010 ||
+               vFormat == YUV_FORMAT_P016) {
         write_uv_rect(user_data.x, f, TEX_SIZE(sColor0), vUv_Y, vUvBounds_Y);
         write_uv_rect(user_data.y, f, TEX_SIZE(sColor1), vUv_U, vUvBounds_U);
     } else if (vFormat == YUV_FORMAT_INTERLEAVED) {
@@ -156,7 +132,11 @@ Fragment brush_fs() {
         yuv_value.x = TEX_SAMPLE(sColor0, vec3(uv_y, vUv_Y.z)).r;
         yuv_value.y = TEX_SAMPLE(sColor1, vec3(uv_u, vUv_U.z)).r;
         yuv_value.z = TEX_SAMPLE(sColor2, vec3(uv_v, vUv_V.z)).r;
-    } else if (vFormat == YUV_FORMAT_NV12) {
+    } else if (vFormat == YUV_FORMAT_NV12 ||
+               vFormat == YUV_FORMAT_P010 ||
+               vFormat == YUV_FORMAT_P016) {
+        // P010 and P016 have the layout of NV12, with 16 bit R16 and RG16
+        // planes instead of 8 bit ones.
         vec2 uv_y = clamp(vUv_Y.xy, vUvBounds_Y.xy, vUvBounds_Y.zw);
         vec2 uv_uv = clamp(vUv_U.xy, vUvBounds_U.xy, vUvBounds_U.zw);
         yuv_value.x = TEX_SAMPLE(sColor0, vec3(uv_y, vUv_Y.z)).r;
@@ -171,8 +151,7 @@ Fragment brush_fs() {
         yuv_value = vec3(0.0);
     }
 
-    // See the YuvColorMatrix definition for an explanation of where the constants come from.
-    vec3 rgb = vYuvColorMatrix * (yuv_value * vCoefficient - vec3(0.06275, 0.50196, 0.50196));
+    vec3 rgb = vYuvColorMatrix * (yuv_value * vCoefficient - vYuvOffsetVector);
     vec4 color = vec4(rgb, 1.0);
 
     if (alpha_pass) {
@@ -0,0 +1,149 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+#include shared,prim_shared
+
+varying vec3 vInput1Uv;
+varying vec3 vInput2Uv;
+flat varying vec4 vInput1UvRect;
+flat varying vec4 vInput2UvRect;
+flat varying int vFilterKind;
+flat varying int vFilterGenericInt;
+flat varying mat4 vColorMat;
+flat varying vec4 vFilterData;
+
+// Must be kept in sync with SvgFilterInfo in render_task.rs.
+#define FILTER_IDENTITY         0
+#define FILTER_COLOR_MATRIX     1
+#define FILTER_COMPOSITE        2
+
+#define COMPOSITE_OVER          0
+#define COMPOSITE_IN            1
+#define COMPOSITE_OUT           2
+#define COMPOSITE_ATOP          3
+#define COMPOSITE_XOR           4
+#define COMPOSITE_LIGHTER       5
+#define COMPOSITE_ARITHMETIC    6
+
+#ifdef WR_VERTEX_SHADER
+
+in int aFilterRenderTaskAddress;
+in int aFilterInput1TaskAddress;
+in int aFilterInput2TaskAddress;
+in int aFilterKind;
+in int aFilterGenericInt;
+in int aFilterExtraDataAddress;
+
+// The inputs may have been drawn at a different resolution than this
+// task (e.g. downscaled blurs), so map the whole input rect onto it.
+vec3 input_uv(RenderTaskCommonData input_task, vec2 texture_size) {
+    RectWithSize rect = input_task.task_rect;
+    return vec3(
+        (rect.p0 + rect.size * aPosition.xy) / texture_size,
+        input_task.texture_layer_index
+    );
+}
+
+vec4 input_uv_rect(RenderTaskCommonData input_task, vec2 texture_size) {
+    RectWithSize rect = input_task.task_rect;
+    return vec4(rect.p0 + vec2(0.5), rect.p0 + rect.size - vec2(0.5)) / texture_size.xyxy;
+}
+
+void main(void) {
+    RenderTaskCommonData filter_task = fetch_render_task_common_data(aFilterRenderTaskAddress);
+    RenderTaskCommonData input_1_task = fetch_render_task_common_data(aFilterInput1TaskAddress);
+    RenderTaskCommonData input_2_task = fetch_render_task_common_data(aFilterInput2TaskAddress);
+
+    vec2 texture_size_1 = vec2(textureSize(sColor0, 0).xy);
+    vec2 texture_size_2 = vec2(textureSize(sColor1, 0).xy);
+
+    vInput1Uv = input_uv(input_1_task, texture_size_1);
+    vInput1UvRect = input_uv_rect(input_1_task, texture_size_1);
+    vInput2Uv = input_uv(input_2_task, texture_size_2);
+    vInput2UvRect = input_uv_rect(input_2_task, texture_size_2);
+
+    vFilterKind = aFilterKind;
+    vFilterGenericInt = aFilterGenericInt;
+    vColorMat = mat4(1.0);
+    vFilterData = vec4(0.0);
+
+    if (aFilterKind == FILTER_COLOR_MATRIX) {
+        vec4 mat_data[4] = fetch_from_gpu_cache_4(aFilterExtraDataAddress);
+        vColorMat = mat4(mat_data[0], mat_data[1], mat_data[2], mat_data[3]);
+        vFilterData = fetch_from_gpu_cache_1(aFilterExtraDataAddress + 4);
+    } else if (aFilterKind == FILTER_COMPOSITE &&
+               aFilterGenericInt == COMPOSITE_ARITHMETIC) {
+        vFilterData = fetch_from_gpu_cache_1(aFilterExtraDataAddress);
+    }
+
+    RectWithSize target_rect = filter_task.task_rect;
+    vec2 pos = target_rect.p0 + target_rect.size * aPosition.xy;
+
+    gl_Position = uTransform * vec4(pos, 0.0, 1.0);
+}
+
+#endif
+
+#ifdef WR_FRAGMENT_SHADER
+
+vec4 sample_input_1() {
+    vec2 uv = clamp(vInput1Uv.xy, vInput1UvRect.xy, vInput1UvRect.zw);
+    return texture(sColor0, vec3(uv, vInput1Uv.z));
+}
+
+vec4 sample_input_2() {
+    vec2 uv = clamp(vInput2Uv.xy, vInput2UvRect.xy, vInput2UvRect.zw);
+    return texture(sColor1, vec3(uv, vInput2Uv.z));
+}
+
+// Color matrices operate on non-premultiplied colors.
+vec4 color_matrix(vec4 color) {
+    vec4 unpremultiplied = color.a > 0.0 ? vec4(color.rgb / color.a, color.a) : vec4(0.0);
+    vec4 result = clamp(vColorMat * unpremultiplied + vFilterData, vec4(0.0), vec4(1.0));
+    return vec4(result.rgb * result.a, result.a);
+}
+
+// Both inputs are premultiplied, with the first one drawn over the second.
+vec4 composite(vec4 i1, vec4 i2) {
+    switch (vFilterGenericInt) {
+        case COMPOSITE_OVER:
+            return i1 + i2 * (1.0 - i1.a);
+        case COMPOSITE_IN:
+            return i1 * i2.a;
+        case COMPOSITE_OUT:
+            return i1 * (1.0 - i2.a);
+        case COMPOSITE_ATOP:
+            return i1 * i2.a + i2 * (1.0 - i1.a);
+        case COMPOSITE_XOR:
+            return i1 * (1.0 - i2.a) + i2 * (1.0 - i1.a);
+        case COMPOSITE_LIGHTER:
+            return min(i1 + i2, vec4(1.0));
+        case COMPOSITE_ARITHMETIC:
+        default: {
+            vec4 k = vFilterData;
+            vec4 result = clamp(k.x * i1 * i2 + k.y * i1 + k.z * i2 + k.w, vec4(0.0), vec4(1.0));
+            // Keep the result a valid premultiplied color.
+            return vec4(min(result.rgb, vec3(result.a)), result.a);
+        }
+    }
+}
+
+void main(void) {
+    vec4 input_1 = sample_input_1();
+
+    switch (vFilterKind) {
+        case FILTER_COLOR_MATRIX:
+            oFragColor = color_matrix(input_1);
+            break;
+        case FILTER_COMPOSITE:
+            oFragColor = composite(input_1, sample_input_2());
+            break;
+        case FILTER_IDENTITY:
+        default:
+            oFragColor = input_1;
+            break;
+    }
+}
+
+#endif
@@ -20,6 +20,52 @@
 #define TEX_SAMPLE(sampler, tex_coord) texture(sampler, tex_coord)
 #endif
 
+//======================================================================================
+// Output color transforms
+//======================================================================================
+#define OUTPUT_TRANSFORM_NONE       0
+#define OUTPUT_TRANSFORM_SCRGB      1
+#define OUTPUT_TRANSFORM_PQ         2
+
+// The luminance of SDR white in PQ encoded output, in nits (ITU-R BT.2408).
+#define PQ_SDR_WHITE_NITS           203.0
+
+vec3 srgb_to_linear(vec3 color) {
+    vec3 c = max(color, vec3(0.0));
+    return mix(c / 12.92, pow((c + 0.055) / 1.055, vec3(2.4)), step(vec3(0.04045), c));
+}
+
+// SMPTE ST 2084 inverse EOTF.
+vec3 nits_to_pq(vec3 nits) {
+    const float m1 = 0.1593017578125;
+    const float m2 = 78.84375;
+    const float c1 = 0.8359375;
+    const float c2 = 18.8515625;
+    const float c3 = 18.6875;
+    vec3 y = pow(clamp(nits / 10000.0, 0.0, 1.0), vec3(m1));
+    return pow((c1 + c2 * y) / (1.0 + c3 * y), vec3(m2));
+}
+
+// Converts a premultiplied, sRGB encoded color into the color space of the
+// output. This is a no-op except in the pass compositing the frame into an
+// HDR swapchain image.
+vec4 apply_output_transform(vec4 color) {
+    if (output_color_transform == OUTPUT_TRANSFORM_NONE || color.a <= 0.0) {
+        return color;
+    }
+    vec3 rgb = srgb_to_linear(color.rgb / color.a);
+    if (output_color_transform == OUTPUT_TRANSFORM_PQ) {
+        // BT.709 to BT.2020 primaries, in column-major order.
+        const mat3 bt709_to_bt2020 = mat3(
+            0.6274, 0.0691, 0.0164,
+            0.3293, 0.9195, 0.0880,
+            0.0433, 0.0114, 0.8956
+        );
+        rgb = nits_to_pq(bt709_to_bt2020 * rgb * PQ_SDR_WHITE_NITS);
+    }
+    return vec4(rgb * color.a, color.a);
+}
+
 //======================================================================================
 // Vertex shader attributes and uniforms
 //======================================================================================
@@ -2,11 +2,13 @@
  * License, v. 2.0. If a copy of the MPL was not distributed with this
  * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
 
-use api::{AlphaType, ClipMode, DeviceIntRect, DeviceIntPoint, DeviceIntSize, WorldRect};
-use api::{ExternalImageType, FilterOp, ImageRendering, LayoutRect, DeviceRect, DevicePixelScale};
-use api::{YuvColorSpace, YuvFormat, PictureRect, ColorDepth, LayoutPoint, DevicePoint, LayoutSize};
+use api::{AlphaType, ClipMode, ColorF, DeviceIntRect, DeviceIntPoint, DeviceIntSize, WorldRect};
+use api::{ExternalImageType, FilterOp, ImageFormat, ImageRendering, LayoutRect, DeviceRect, DevicePixelScale};
+use api::{YuvFormat, PictureRect, ColorDepth, LayoutPoint, DevicePoint, LayoutSize};
 use clip::{ClipDataStore, ClipNodeFlags, ClipNodeRange, ClipItem, ClipStore, ClipNodeInstance};
 use clip_scroll_tree::{ClipScrollTree, ROOT_SPATIAL_NODE_INDEX, SpatialNodeIndex, CoordinateSystemId};
+use compositor::CompositeTile;
+use debug_colors;
 use glyph_rasterizer::GlyphFormat;
 use gpu_cache::{GpuCache, GpuCacheHandle, GpuCacheAddress};
 use gpu_types::{BrushFlags, BrushInstance, PrimitiveHeaders, ZBufferId, ZBufferIdGenerator};
@@ -20,13 +22,13 @@ use prim_store::{VisibleGradientTile, PrimitiveInstance, PrimitiveOpacity, Segme
 use prim_store::{BrushSegment, ClipMaskKind, ClipTaskIndex};
 use prim_store::image::ImageSource;
 use render_backend::DataStores;
-use render_task::{RenderTaskAddress, RenderTaskId, RenderTaskTree, TileBlit};
+use render_task::{RenderTaskAddress, RenderTaskId, RenderTaskKind, RenderTaskTree, TileBlit};
 use renderer::{BlendMode, ImageBufferKind, ShaderColorMode};
 use renderer::BLOCKS_PER_UV_RECT;
 use resource_cache::{CacheItem, GlyphFetchResult, ImageRequest, ResourceCache, ImageProperties};
 use scene::FilterOpHelpers;
 use smallvec::SmallVec;
-use std::{f32, i32, usize};
+use std::{cmp, f32, i32, usize};
 use tiling::{RenderTargetContext};
 use util::{project_rect, TransformedRectKind};
 
@@ -49,14 +51,24 @@ const CLIP_RECTANGLE_AREA_THRESHOLD: i32 = CLIP_RECTANGLE_TILE_SIZE * CLIP_RECTA
 pub enum BrushBatchKind {
     Solid,
     Image(ImageBufferKind),
+    /// An image drawn under a perspective or rotated transform, sampled
+    /// through the device's anisotropic sampler.
+    AnisotropicImage(ImageBufferKind),
     Blend,
     MixBlend {
         task_id: RenderTaskId,
         source_id: RenderTaskId,
         backdrop_id: RenderTaskId,
     },
-    YuvImage(ImageBufferKind, YuvFormat, ColorDepth, YuvColorSpace),
+    BackdropFilter {
+        task_id: RenderTaskId,
+        source_id: RenderTaskId,
+        backdrop_id: RenderTaskId,
+    },
+    YuvImage(ImageBufferKind, YuvFormat, ColorDepth),
+    CrossFade(ImageBufferKind),
     RadialGradient,
+    ConicGradient,
     LinearGradient,
 }
 
@@ -133,20 +145,111 @@ fn textures_compatible(t1: TextureSource, t2: TextureSource) -> bool {
     t1 == TextureSource::Invalid || t2 == TextureSource::Invalid || t1 == t2
 }
 
+/// Why a primitive started a new batch instead of being added to the
+/// batch of the previous primitive.
+#[derive(Copy, Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "capture", derive(Serialize))]
+#[cfg_attr(feature = "replay", derive(Deserialize))]
+pub enum BatchBreakReason {
+    /// The primitive needs a different shader or shader parameters.
+    Kind,
+    /// The primitive needs a different blend mode.
+    BlendMode,
+    /// The primitive samples from different textures.
+    Textures,
+    /// An earlier compatible batch exists, but it can't be used because
+    /// of overlapping primitives or the lookback limit.
+    Overlap,
+}
+
+impl BatchBreakReason {
+    fn new(previous: &BatchKey, key: &BatchKey) -> Self {
+        if previous.kind != key.kind {
+            BatchBreakReason::Kind
+        } else if previous.blend_mode != key.blend_mode {
+            BatchBreakReason::BlendMode
+        } else if !previous.is_compatible_with(key) {
+            BatchBreakReason::Textures
+        } else {
+            BatchBreakReason::Overlap
+        }
+    }
+
+    /// The code drawn at the batch boundary by the `PRIMITIVE_DBG` overlay.
+    pub fn debug_label(&self) -> &'static str {
+        match *self {
+            BatchBreakReason::Kind => "K",
+            BatchBreakReason::BlendMode => "B",
+            BatchBreakReason::Textures => "T",
+            BatchBreakReason::Overlap => "O",
+        }
+    }
+
+    pub fn debug_color(&self) -> ColorF {
+        match *self {
+            BatchBreakReason::Kind => debug_colors::YELLOW,
+            BatchBreakReason::BlendMode => debug_colors::MAGENTA,
+            BatchBreakReason::Textures => debug_colors::CYAN,
+            BatchBreakReason::Overlap => debug_colors::RED,
+        }
+    }
+}
+
+/// A batch break, with the bounding rect of the primitive that caused it.
+#[derive(Copy, Clone, Debug)]
+#[cfg_attr(feature = "capture", derive(Serialize))]
+#[cfg_attr(feature = "replay", derive(Deserialize))]
+pub struct BatchBreak<Rect> {
+    pub reason: BatchBreakReason,
+    pub rect: Rect,
+}
+
+/// The bounding rects of the primitives of a batch.
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "capture", derive(Serialize))]
+#[cfg_attr(feature = "replay", derive(Deserialize))]
+pub struct BatchRects<Rect> {
+    pub kind: BatchKind,
+    pub rects: Vec<Rect>,
+}
+
+/// The instances a primitive added to an alpha batch.
+struct AlphaBatchItem {
+    rect: PictureRect,
+    z_id: ZBufferId,
+    /// The index of the first instance of the primitive in the batch. The
+    /// primitive owns the instances up to the next item of the batch.
+    first_instance: usize,
+}
+
+/// An opaque primitive that hides everything behind it within its rect.
+struct Occluder {
+    rect: PictureRect,
+    z_id: ZBufferId,
+}
+
 pub struct AlphaBatchList {
     pub batches: Vec<PrimitiveBatch>,
     pub item_rects: Vec<Vec<PictureRect>>,
+    /// The primitives of each batch, for occlusion culling.
+    items: Vec<Vec<AlphaBatchItem>>,
     current_batch_index: usize,
     current_z_id: ZBufferId,
+    lookback_count: usize,
+    /// The batch breaks, only recorded for the debug overlays.
+    breaks: Option<Vec<BatchBreak<PictureRect>>>,
 }
 
 impl AlphaBatchList {
-    fn new() -> Self {
+    fn new(lookback_count: usize, record_debug_info: bool) -> Self {
         AlphaBatchList {
             batches: Vec::new(),
             item_rects: Vec::new(),
+            items: Vec::new(),
             current_z_id: ZBufferId::invalid(),
             current_batch_index: usize::MAX,
+            lookback_count,
+            breaks: if record_debug_info { Some(Vec::new()) } else { None },
         }
     }
 
@@ -164,7 +267,7 @@ impl AlphaBatchList {
 
             match key.blend_mode {
                 BlendMode::SubpixelWithBgColor => {
-                    'outer_multipass: for (batch_index, batch) in self.batches.iter().enumerate().rev().take(10) {
+                    'outer_multipass: for (batch_index, batch) in self.batches.iter().enumerate().rev().take(self.lookback_count) {
                         // Some subpixel batches are drawn in two passes. Because of this, we need
                         // to check for overlaps with every batch (which is a bit different
                         // than the normal batching below).
@@ -181,7 +284,7 @@ impl AlphaBatchList {
                     }
                 }
                 _ => {
-                    'outer_default: for (batch_index, batch) in self.batches.iter().enumerate().rev().take(10) {
+                    'outer_default: for (batch_index, batch) in self.batches.iter().enumerate().rev().take(self.lookback_count) {
                         // For normal batches, we only need to check for overlaps for batches
                         // other than the first batch we consider. If the first batch
                         // is compatible, then we know there isn't any potential overlap
@@ -202,33 +305,103 @@ impl AlphaBatchList {
             }
 
             if selected_batch_index.is_none() {
+                record_batch_break(&mut self.breaks, &self.batches, self.current_batch_index, &key, bounding_rect);
                 let new_batch = PrimitiveBatch::new(key);
                 selected_batch_index = Some(self.batches.len());
                 self.batches.push(new_batch);
                 self.item_rects.push(Vec::new());
+                self.items.push(Vec::new());
             }
 
             self.current_batch_index = selected_batch_index.unwrap();
             self.item_rects[self.current_batch_index].push(*bounding_rect);
+            self.items[self.current_batch_index].push(AlphaBatchItem {
+                rect: *bounding_rect,
+                z_id,
+                first_instance: self.batches[self.current_batch_index].instances.len(),
+            });
             self.current_z_id = z_id;
         }
 
         &mut self.batches[self.current_batch_index].instances
     }
+
+    /// Removes the primitives for which `f` returns false, along with their
+    /// instances, and the batches left empty. Returns the z ids of the
+    /// removed primitives.
+    fn retain_items<F>(&mut self, mut f: F) -> Vec<ZBufferId>
+    where
+        F: FnMut(&AlphaBatchItem) -> bool,
+    {
+        let mut removed = Vec::new();
+        let batches = self.batches.iter_mut().zip(self.items.iter_mut()).zip(self.item_rects.iter_mut());
+        for ((batch, items), item_rects) in batches {
+            let keep = items
+                .iter()
+                .map(|item| f(item))
+                .collect::<Vec<_>>();
+            if keep.iter().all(|keep| *keep) {
+                continue;
+            }
+
+            let mut instances = Vec::with_capacity(batch.instances.len());
+            let mut kept_items = Vec::with_capacity(items.len());
+            for (item_index, item) in items.iter().enumerate() {
+                if !keep[item_index] {
+                    removed.push(item.z_id);
+                    continue;
+                }
+                let end = items
+                    .get(item_index + 1)
+                    .map_or(batch.instances.len(), |next| next.first_instance);
+                kept_items.push(AlphaBatchItem {
+                    first_instance: instances.len(),
+                    ..*item
+                });
+                instances.extend_from_slice(&batch.instances[item.first_instance .. end]);
+            }
+
+            *item_rects = kept_items.iter().map(|item| item.rect).collect();
+            *items = kept_items;
+            batch.instances = instances;
+        }
+
+        let mut batch_index = self.batches.len();
+        while batch_index > 0 {
+            batch_index -= 1;
+            if self.batches[batch_index].instances.is_empty() {
+                self.batches.remove(batch_index);
+                self.item_rects.remove(batch_index);
+                self.items.remove(batch_index);
+            }
+        }
+        self.current_batch_index = usize::MAX;
+
+        removed
+    }
 }
 
 pub struct OpaqueBatchList {
     pub pixel_area_threshold_for_new_batch: f32,
     pub batches: Vec<PrimitiveBatch>,
     pub current_batch_index: usize,
+    lookback_count: usize,
+    /// The batch breaks, only recorded for the debug overlays.
+    breaks: Option<Vec<BatchBreak<PictureRect>>>,
+    /// The primitive rects of each batch, only recorded for the debug
+    /// overlays.
+    item_rects: Option<Vec<Vec<PictureRect>>>,
 }
 
 impl OpaqueBatchList {
-    fn new(pixel_area_threshold_for_new_batch: f32) -> Self {
+    fn new(pixel_area_threshold_for_new_batch: f32, lookback_count: usize, record_debug_info: bool) -> Self {
         OpaqueBatchList {
             batches: Vec::new(),
             pixel_area_threshold_for_new_batch,
             current_batch_index: usize::MAX,
+            lookback_count,
+            breaks: if record_debug_info { Some(Vec::new()) } else { None },
+            item_rects: if record_debug_info { Some(Vec::new()) } else { None },
         }
     }
 
@@ -254,7 +427,7 @@ impl OpaqueBatchList {
                 }
             } else {
                 // Otherwise, look back through a reasonable number of batches.
-                for (batch_index, batch) in self.batches.iter().enumerate().rev().take(10) {
+                for (batch_index, batch) in self.batches.iter().enumerate().rev().take(self.lookback_count) {
                     if batch.key.is_compatible_with(&key) {
                         selected_batch_index = Some(batch_index);
                         break;
@@ -263,14 +436,22 @@ impl OpaqueBatchList {
             }
 
             if selected_batch_index.is_none() {
+                record_batch_break(&mut self.breaks, &self.batches, self.current_batch_index, &key, bounding_rect);
                 let new_batch = PrimitiveBatch::new(key);
                 selected_batch_index = Some(self.batches.len());
                 self.batches.push(new_batch);
+                if let Some(ref mut item_rects) = self.item_rects {
+                    item_rects.push(Vec::new());
+                }
             }
 
             self.current_batch_index = selected_batch_index.unwrap();
         }
 
+        if let Some(ref mut item_rects) = self.item_rects {
+            item_rects[self.current_batch_index].push(*bounding_rect);
+        }
+
         &mut self.batches[self.current_batch_index].instances
     }
 
@@ -287,6 +468,24 @@ impl OpaqueBatchList {
     }
 }
 
+fn record_batch_break(
+    breaks: &mut Option<Vec<BatchBreak<PictureRect>>>,
+    batches: &[PrimitiveBatch],
+    current_batch_index: usize,
+    key: &BatchKey,
+    bounding_rect: &PictureRect,
+) {
+    if let Some(ref mut breaks) = *breaks {
+        // The first batch of a list doesn't break anything.
+        if current_batch_index != usize::MAX {
+            breaks.push(BatchBreak {
+                reason: BatchBreakReason::new(&batches[current_batch_index].key, key),
+                rect: *bounding_rect,
+            });
+        }
+    }
+}
+
 pub struct BatchList {
     pub alpha_batch_list: AlphaBatchList,
     pub opaque_batch_list: OpaqueBatchList,
@@ -294,6 +493,8 @@ pub struct BatchList {
     /// in. Each region will have scissor rect set before drawing.
     pub regions: Vec<DeviceIntRect>,
     pub tile_blits: Vec<TileBlit>,
+    /// The opaque primitives that can hide alpha primitives behind them.
+    occluders: Vec<Occluder>,
 }
 
 impl BatchList {
@@ -301,19 +502,60 @@ impl BatchList {
         screen_size: DeviceIntSize,
         regions: Vec<DeviceIntRect>,
         tile_blits: Vec<TileBlit>,
+        batch_lookback_count: usize,
+        record_debug_info: bool,
     ) -> Self {
         // The threshold for creating a new batch is
         // one quarter the screen size.
         let batch_area_threshold = (screen_size.width * screen_size.height) as f32 / 4.0;
 
         BatchList {
-            alpha_batch_list: AlphaBatchList::new(),
-            opaque_batch_list: OpaqueBatchList::new(batch_area_threshold),
+            alpha_batch_list: AlphaBatchList::new(batch_lookback_count, record_debug_info),
+            opaque_batch_list: OpaqueBatchList::new(
+                batch_area_threshold,
+                batch_lookback_count,
+                record_debug_info,
+            ),
             regions,
             tile_blits,
+            occluders: Vec::new(),
         }
     }
 
+    /// Records an opaque primitive that covers every pixel of `rect`.
+    pub fn add_occluder(&mut self, rect: &PictureRect, z_id: ZBufferId) {
+        if rect.size.width > 0.0 && rect.size.height > 0.0 {
+            self.occluders.push(Occluder { rect: *rect, z_id });
+        }
+    }
+
+    /// Drops the alpha primitives entirely covered by an opaque primitive
+    /// in front of them. The depth test would reject all of their pixels,
+    /// so this only saves the vertex and fragment work. Returns the number
+    /// of culled primitives.
+    fn cull_occluded_primitives(&mut self) -> usize {
+        if self.occluders.is_empty() {
+            return 0;
+        }
+
+        // Front to back, so that the occluders in front of a primitive come
+        // first and the search can stop at the first one behind it.
+        self.occluders.sort_by_key(|occluder| cmp::Reverse(occluder.z_id));
+
+        let occluders = &self.occluders;
+        let mut culled = self.alpha_batch_list.retain_items(|item| {
+            !occluders
+                .iter()
+                .take_while(|occluder| occluder.z_id > item.z_id)
+                .any(|occluder| occluder.rect.contains_rect(&item.rect))
+        });
+
+        // A primitive can have instances in several batches.
+        culled.sort();
+        culled.dedup();
+        culled.len()
+    }
+
     pub fn push_single_instance(
         &mut self,
         key: BatchKey,
@@ -363,8 +605,10 @@ impl BatchList {
         }
     }
 
-    fn finalize(&mut self) {
-        self.opaque_batch_list.finalize()
+    fn finalize(&mut self) -> usize {
+        let culled = self.cull_occluded_primitives();
+        self.opaque_batch_list.finalize();
+        culled
     }
 }
 
@@ -396,6 +640,9 @@ pub struct AlphaBatchContainer {
     /// in. Each region will have scissor rect set before drawing.
     pub regions: Vec<DeviceIntRect>,
     pub tile_blits: Vec<TileBlit>,
+    /// The picture task these batches were built from, unless they were
+    /// merged from several tasks. Used to label GPU markers.
+    pub task_id: Option<RenderTaskId>,
 }
 
 impl AlphaBatchContainer {
@@ -409,6 +656,7 @@ impl AlphaBatchContainer {
             task_scissor_rect,
             regions,
             tile_blits: Vec::new(),
+            task_id: None,
         }
     }
 
@@ -469,18 +717,27 @@ pub struct AlphaBatchBuilder {
     screen_size: DeviceIntSize,
     task_scissor_rect: Option<DeviceIntRect>,
     glyph_fetch_buffer: Vec<GlyphFetchResult>,
+    batch_lookback_count: usize,
+    /// Whether to record the batch breaks and rects for the debug overlays.
+    record_debug_info: bool,
+    /// The picture cache tiles left to a native compositor, in drawing order.
+    pub composite_tiles: Vec<CompositeTile>,
 }
 
 impl AlphaBatchBuilder {
     pub fn new(
         screen_size: DeviceIntSize,
         task_scissor_rect: Option<DeviceIntRect>,
+        batch_lookback_count: usize,
+        record_debug_info: bool,
     ) -> Self {
         let batch_lists = vec![
             BatchList::new(
                 screen_size,
                 Vec::new(),
                 Vec::new(),
+                batch_lookback_count,
+                record_debug_info,
             ),
         ];
 
@@ -489,7 +746,56 @@ impl AlphaBatchBuilder {
             task_scissor_rect,
             screen_size,
             glyph_fetch_buffer: Vec::new(),
+            batch_lookback_count,
+            record_debug_info,
+            composite_tiles: Vec::new(),
+        }
+    }
+
+    /// Returns the batch breaks recorded so far, if `record_debug_info`
+    /// was set.
+    pub fn batch_breaks(&self) -> Vec<BatchBreak<PictureRect>> {
+        let mut breaks = Vec::new();
+        for batch_list in &self.batch_lists {
+            let lists = [
+                &batch_list.opaque_batch_list.breaks,
+                &batch_list.alpha_batch_list.breaks,
+            ];
+            for list in &lists {
+                if let Some(ref list) = **list {
+                    breaks.extend_from_slice(list);
+                }
+            }
         }
+        breaks
+    }
+
+    /// Returns the primitive rects of the batches built so far, if
+    /// `record_debug_info` was set.
+    pub fn batch_rects(&self) -> Vec<BatchRects<PictureRect>> {
+        let mut batch_rects = Vec::new();
+        if !self.record_debug_info {
+            return batch_rects;
+        }
+        for batch_list in &self.batch_lists {
+            let opaque_list = &batch_list.opaque_batch_list;
+            if let Some(ref item_rects) = opaque_list.item_rects {
+                for (batch, rects) in opaque_list.batches.iter().zip(item_rects.iter()) {
+                    batch_rects.push(BatchRects {
+                        kind: batch.key.kind,
+                        rects: rects.clone(),
+                    });
+                }
+            }
+            let alpha_list = &batch_list.alpha_batch_list;
+            for (batch, rects) in alpha_list.batches.iter().zip(alpha_list.item_rects.iter()) {
+                batch_rects.push(BatchRects {
+                    kind: batch.key.kind,
+                    rects: rects.clone(),
+                });
+            }
+        }
+        batch_rects
     }
 
     fn push_new_batch_list(
@@ -501,6 +807,8 @@ impl AlphaBatchBuilder {
             self.screen_size,
             regions,
             tile_blits,
+            self.batch_lookback_count,
+            self.record_debug_info,
         ));
     }
 
@@ -513,13 +821,18 @@ impl AlphaBatchBuilder {
         self.batch_lists.len() == 1
     }
 
+    /// Moves the batches to the containers of the target. Returns the number
+    /// of alpha primitives culled because opaque primitives of the same batch
+    /// list hide them.
     pub fn build(
         mut self,
         batch_containers: &mut Vec<AlphaBatchContainer>,
         merged_batches: &mut AlphaBatchContainer,
-    ) {
+        task_id: RenderTaskId,
+    ) -> usize {
+        let mut culled_primitives = 0;
         for batch_list in &mut self.batch_lists {
-            batch_list.finalize();
+            culled_primitives += batch_list.finalize();
         }
 
         if self.can_merge() {
@@ -534,9 +847,12 @@ impl AlphaBatchBuilder {
                     task_scissor_rect: self.task_scissor_rect,
                     regions: batch_list.regions,
                     tile_blits: batch_list.tile_blits,
+                    task_id: Some(task_id),
                 });
             }
         }
+
+        culled_primitives
     }
 
     pub fn add_pic_to_batch(
@@ -988,9 +1304,11 @@ impl AlphaBatchBuilder {
                                 PrimitiveInstanceKind::ImageBorder { .. } |
                                 PrimitiveInstanceKind::Rectangle { .. } |
                                 PrimitiveInstanceKind::YuvImage { .. } |
+                                PrimitiveInstanceKind::CrossFade { .. } |
                                 PrimitiveInstanceKind::Image { .. } |
                                 PrimitiveInstanceKind::LinearGradient { .. } |
                                 PrimitiveInstanceKind::RadialGradient { .. } |
+                                PrimitiveInstanceKind::ConicGradient { .. } |
                                 PrimitiveInstanceKind::Clear { .. } => {
                                     unreachable!();
                                 }
@@ -1095,6 +1413,17 @@ impl AlphaBatchBuilder {
                                     let tile_cache = picture.tile_cache.as_ref().unwrap();
 
                                     for tile_index in &tile_cache.tiles_to_draw {
+                                        // A native compositor presents the valid tiles
+                                        // below the main framebuffer instead.
+                                        if ctx.native_compositor {
+                                            self.composite_tiles.extend(tile_cache.composite_tile(
+                                                *tile_index,
+                                                ctx.device_pixel_scale,
+                                                ctx.resource_cache,
+                                            ));
+                                            continue;
+                                        }
+
                                         let tile = &tile_cache.tiles[tile_index.0];
 
                                         // Get the local rect of the tile.
@@ -1468,7 +1797,88 @@ impl AlphaBatchBuilder {
                                     PrimitiveInstanceData::from(instance),
                                 );
                             }
-                            PictureCompositeMode::Blit => {
+                            PictureCompositeMode::BackdropFilter => {
+                                // Draw the filtered backdrop first, followed by the content.
+                                let surface = ctx.surfaces[raster_config.surface_index.0]
+                                    .surface
+                                    .as_ref()
+                                    .expect("bug: surface must be allocated by now");
+                                let cache_task_id = surface.resolve_render_task_id();
+                                let backdrop_id = picture.secondary_render_task_id.expect("no backdrop!?");
+
+                                let backdrop_key = BatchKey::new(
+                                    BatchKind::Brush(
+                                        BrushBatchKind::BackdropFilter {
+                                            task_id,
+                                            source_id: cache_task_id,
+                                            backdrop_id,
+                                        },
+                                    ),
+                                    BlendMode::PremultipliedAlpha,
+                                    BatchTextures::no_texture(),
+                                );
+                                let content_key = BatchKey::new(
+                                    BatchKind::Brush(
+                                        BrushBatchKind::Image(ImageBufferKind::Texture2DArray)
+                                    ),
+                                    non_segmented_blend_mode,
+                                    BatchTextures::render_target_cache(),
+                                );
+
+                                let backdrop_task_address = render_tasks.get_task_address(backdrop_id);
+                                let source_task_address = render_tasks.get_task_address(cache_task_id);
+                                let content_uv_rect_address = render_tasks[cache_task_id]
+                                    .get_texture_address(gpu_cache)
+                                    .as_int();
+
+                                let z_id_backdrop = z_id;
+                                let z_id_content = z_generator.next();
+
+                                let backdrop_prim_header_index = prim_headers.push(&prim_header, z_id_backdrop, [
+                                    picture.extra_gpu_data_handle.as_int(gpu_cache),
+                                    backdrop_task_address.0 as i32,
+                                    source_task_address.0 as i32,
+                                ]);
+                                let content_prim_header_index = prim_headers.push(&prim_header, z_id_content, [
+                                    ShaderColorMode::Image as i32 | ((AlphaType::PremultipliedAlpha as i32) << 16),
+                                    RasterizationSpace::Screen as i32,
+                                    get_shader_opacity(1.0),
+                                ]);
+
+                                let backdrop_instance = BrushInstance {
+                                    prim_header_index: backdrop_prim_header_index,
+                                    clip_task_address,
+                                    segment_index: INVALID_SEGMENT_INDEX,
+                                    edge_flags: EdgeAaSegmentMask::empty(),
+                                    brush_flags,
+                                    user_data: 0,
+                                };
+
+                                let content_instance = BrushInstance {
+                                    prim_header_index: content_prim_header_index,
+                                    clip_task_address,
+                                    segment_index: INVALID_SEGMENT_INDEX,
+                                    edge_flags: EdgeAaSegmentMask::empty(),
+                                    brush_flags,
+                                    user_data: content_uv_rect_address,
+                                };
+
+                                self.current_batch_list().push_single_instance(
+                                    backdrop_key,
+                                    bounding_rect,
+                                    z_id_backdrop,
+                                    PrimitiveInstanceData::from(backdrop_instance),
+                                );
+
+                                self.current_batch_list().push_single_instance(
+                                    content_key,
+                                    bounding_rect,
+                                    z_id_content,
+                                    PrimitiveInstanceData::from(content_instance),
+                                );
+                            }
+                            PictureCompositeMode::Blit |
+                            PictureCompositeMode::SvgFilter => {
                                 let surface = ctx.surfaces[raster_config.surface_index.0]
                                     .surface
                                     .as_ref()
@@ -1659,6 +2069,22 @@ impl AlphaBatchBuilder {
                     prim_info.clip_task_index,
                     ctx,
                 );
+
+                // An unclipped, axis-aligned opaque rectangle fills its whole
+                // bounding rect, except for up to half a device pixel on each
+                // side when its edges get snapped. The pixels are those of the
+                // target, which a raster root may scale away from the device.
+                if non_segmented_blend_mode == BlendMode::None {
+                    let target_pixel_scale = match render_tasks[task_id].kind {
+                        RenderTaskKind::Picture(ref task) => task.device_pixel_scale,
+                        _ => ctx.device_pixel_scale,
+                    };
+                    let inset = 1.0 / target_pixel_scale.0;
+                    self.current_batch_list().add_occluder(
+                        &bounding_rect.inflate(-inset, -inset),
+                        z_id,
+                    );
+                }
             }
             PrimitiveInstanceKind::YuvImage { data_handle, segment_instance_index, .. } => {
                 let yuv_image_data = &ctx.data_stores.yuv_image[data_handle].kind;
@@ -1687,6 +2113,16 @@ impl AlphaBatchBuilder {
                         return;
                     }
 
+                    // The YUV shader expects the samples of each plane in the
+                    // red (and green) channel, scaled by the color depth.
+                    let is_packed_rgba = ctx.resource_cache
+                        .get_image_properties(image_key)
+                        .map_or(false, |props| props.descriptor.format == ImageFormat::RGB10A2);
+                    if is_packed_rgba {
+                        warn!("RGB10A2 images can't be used as YUV planes");
+                        return;
+                    }
+
                     textures.colors[channel] = cache_item.texture_id;
                     uv_rect_addresses[channel] = cache_item.uv_rect_handle.as_int(gpu_cache);
                 }
@@ -1703,7 +2139,6 @@ impl AlphaBatchBuilder {
                     buffer_kind,
                     yuv_image_data.format,
                     yuv_image_data.color_depth,
-                    yuv_image_data.color_space,
                 );
 
                 let batch_params = BrushBatchParameters::shared(
@@ -1768,6 +2203,104 @@ impl AlphaBatchBuilder {
                     ctx,
                 );
             }
+            PrimitiveInstanceKind::CrossFade { data_handle, segment_instance_index, .. } => {
+                let cross_fade_data = &ctx.data_stores.cross_fade[data_handle].kind;
+                let mut textures = BatchTextures::no_texture();
+                let mut uv_rect_addresses = [0; 2];
+
+                let image_keys = [cross_fade_data.from_key, cross_fade_data.to_key];
+                for (i, &image_key) in image_keys.iter().enumerate() {
+                    let cache_item = resolve_image(
+                        ImageRequest {
+                            key: image_key,
+                            rendering: cross_fade_data.image_rendering,
+                            tile: None,
+                        },
+                        ctx.resource_cache,
+                        gpu_cache,
+                        deferred_resolves,
+                    );
+
+                    if cache_item.texture_id == TextureSource::Invalid {
+                        warn!("Warnings: skip a PrimitiveKind::CrossFade");
+                        return;
+                    }
+
+                    textures.colors[i] = cache_item.texture_id;
+                    uv_rect_addresses[i] = cache_item.uv_rect_handle.as_int(gpu_cache);
+                }
+
+                // The shader samples both images from the same kind of
+                // texture, which an external image doesn't have to share
+                // with the texture cache.
+                let buffer_kind = get_buffer_kind(textures.colors[0]);
+                if buffer_kind != get_buffer_kind(textures.colors[1]) {
+                    warn!("Warnings: skip a PrimitiveKind::CrossFade with different texture kinds");
+                    return;
+                }
+
+                let batch_params = BrushBatchParameters::shared(
+                    BrushBatchKind::CrossFade(buffer_kind),
+                    textures,
+                    [
+                        uv_rect_addresses[0],
+                        uv_rect_addresses[1],
+                        0,
+                    ],
+                    0,
+                );
+
+                let specified_blend_mode = BlendMode::PremultipliedAlpha;
+
+                let non_segmented_blend_mode = if !prim_common_data.opacity.is_opaque ||
+                    prim_info.clip_task_index != ClipTaskIndex::INVALID ||
+                    transform_kind == TransformedRectKind::Complex
+                {
+                    specified_blend_mode
+                } else {
+                    BlendMode::None
+                };
+
+                debug_assert!(segment_instance_index != SegmentInstanceIndex::INVALID);
+                let (prim_cache_address, segments) = if segment_instance_index == SegmentInstanceIndex::UNUSED {
+                    (gpu_cache.get_address(&prim_common_data.gpu_cache_handle), None)
+                } else {
+                    let segment_instance = &ctx.scratch.segment_instances[segment_instance_index];
+                    let segments = Some(&ctx.scratch.segments[segment_instance.segments_range]);
+                    (gpu_cache.get_address(&segment_instance.gpu_cache_handle), segments)
+                };
+
+                let prim_header = PrimitiveHeader {
+                    local_rect: prim_rect,
+                    local_clip_rect: prim_info.combined_local_clip_rect,
+                    task_address,
+                    specific_prim_address: prim_cache_address,
+                    clip_task_address,
+                    transform_id,
+                };
+
+                let prim_header_index = prim_headers.push(
+                    &prim_header,
+                    z_id,
+                    batch_params.prim_user_data,
+                );
+
+                self.add_segmented_prim_to_batch(
+                    segments,
+                    prim_common_data.opacity,
+                    &batch_params,
+                    specified_blend_mode,
+                    non_segmented_blend_mode,
+                    prim_header_index,
+                    clip_task_address,
+                    bounding_rect,
+                    transform_kind,
+                    render_tasks,
+                    z_id,
+                    prim_info.clip_task_index,
+                    ctx,
+                );
+            }
             PrimitiveInstanceKind::Image { data_handle, image_instance_index, .. } => {
                 let image_data = &ctx.data_stores.image[data_handle].kind;
                 let common_data = &ctx.data_stores.image[data_handle].common;
@@ -1782,6 +2315,8 @@ impl AlphaBatchBuilder {
                     rendering: image_data.image_rendering,
                     tile: None,
                 };
+                let anisotropic = ctx.anisotropic_filtering &&
+                    transform_kind == TransformedRectKind::Complex;
 
                 if image_instance.visible_tiles.is_empty() {
                     let cache_item = match image_data.source {
@@ -1822,7 +2357,7 @@ impl AlphaBatchBuilder {
                     };
 
                     let batch_params = BrushBatchParameters::shared(
-                        BrushBatchKind::Image(get_buffer_kind(cache_item.texture_id)),
+                        get_image_batch_kind(cache_item.texture_id, anisotropic),
                         textures,
                         [
                             ShaderColorMode::Image as i32 | ((image_data.alpha_type as i32) << 16),
@@ -1880,6 +2415,7 @@ impl AlphaBatchBuilder {
                             request.with_tile(tile.tile_offset),
                             image_data.alpha_type,
                             get_shader_opacity(opacity_binding),
+                            anisotropic,
                         ) {
                             let prim_cache_address = gpu_cache.get_address(&tile.handle);
                             let prim_header = PrimitiveHeader {
@@ -2069,6 +2605,81 @@ impl AlphaBatchBuilder {
                     );
                 }
             }
+            PrimitiveInstanceKind::ConicGradient { data_handle, ref visible_tiles_range, .. } => {
+                let prim_data = &ctx.data_stores.conic_grad[data_handle];
+                let specified_blend_mode = BlendMode::PremultipliedAlpha;
+
+                let mut prim_header = PrimitiveHeader {
+                    local_rect: prim_rect,
+                    local_clip_rect: prim_info.combined_local_clip_rect,
+                    task_address,
+                    specific_prim_address: GpuCacheAddress::invalid(),
+                    clip_task_address,
+                    transform_id,
+                };
+
+                if visible_tiles_range.is_empty() {
+                    let non_segmented_blend_mode = if !prim_data.opacity.is_opaque ||
+                        prim_info.clip_task_index != ClipTaskIndex::INVALID ||
+                        transform_kind == TransformedRectKind::Complex
+                    {
+                        specified_blend_mode
+                    } else {
+                        BlendMode::None
+                    };
+
+                    let batch_params = BrushBatchParameters::shared(
+                        BrushBatchKind::ConicGradient,
+                        BatchTextures::no_texture(),
+                        [
+                            prim_data.stops_handle.as_int(gpu_cache),
+                            0,
+                            0,
+                        ],
+                        0,
+                    );
+
+                    prim_header.specific_prim_address = gpu_cache.get_address(&prim_data.gpu_cache_handle);
+
+                    let prim_header_index = prim_headers.push(
+                        &prim_header,
+                        z_id,
+                        batch_params.prim_user_data,
+                    );
+
+                    self.add_segmented_prim_to_batch(
+                        None,
+                        prim_data.opacity,
+                        &batch_params,
+                        specified_blend_mode,
+                        non_segmented_blend_mode,
+                        prim_header_index,
+                        clip_task_address,
+                        bounding_rect,
+                        transform_kind,
+                        render_tasks,
+                        z_id,
+                        prim_info.clip_task_index,
+                        ctx,
+                    );
+                } else {
+                    let visible_tiles = &ctx.scratch.gradient_tiles[*visible_tiles_range];
+
+                    add_gradient_tiles(
+                        visible_tiles,
+                        &prim_data.stops_handle,
+                        BrushBatchKind::ConicGradient,
+                        specified_blend_mode,
+                        bounding_rect,
+                        clip_task_address,
+                        gpu_cache,
+                        self.current_batch_list(),
+                        &prim_header,
+                        prim_headers,
+                        z_id,
+                    );
+                }
+            }
         }
     }
 
@@ -2320,6 +2931,7 @@ fn get_image_tile_params(
     request: ImageRequest,
     alpha_type: AlphaType,
     shader_opacity: i32,
+    anisotropic: bool,
 ) -> Option<(BrushBatchKind, BatchTextures, [i32; 3], GpuCacheAddress)> {
 
     let cache_item = resolve_image(
@@ -2334,7 +2946,7 @@ fn get_image_tile_params(
     } else {
         let textures = BatchTextures::color(cache_item.texture_id);
         Some((
-            BrushBatchKind::Image(get_buffer_kind(cache_item.texture_id)),
+            get_image_batch_kind(cache_item.texture_id, anisotropic),
             textures,
             [
                 ShaderColorMode::Image as i32 | ((alpha_type as i32) << 16),
@@ -2406,6 +3018,10 @@ impl PrimitiveInstance {
         let image_key = match self.kind {
             PrimitiveInstanceKind::Image { data_handle, .. } => {
                 let image_data = &data_stores.image[data_handle].kind;
+                // Animated image keys change without invalidating the tiles.
+                if image_data.is_animated() {
+                    return false;
+                }
                 image_data.key
             }
             PrimitiveInstanceKind::YuvImage { data_handle, .. } => {
@@ -2413,14 +3029,29 @@ impl PrimitiveInstance {
                     &data_stores.yuv_image[data_handle].kind;
                 yuv_image_data.yuv_key[0]
             }
+            PrimitiveInstanceKind::CrossFade { data_handle, .. } => {
+                let cross_fade_data = &data_stores.cross_fade[data_handle].kind;
+                let from_is_external = resource_cache
+                    .get_image_properties(cross_fade_data.from_key)
+                    .map_or(false, |properties| properties.external_image.is_some());
+                if from_is_external {
+                    return false;
+                }
+                cross_fade_data.to_key
+            }
+            PrimitiveInstanceKind::Rectangle { data_handle, .. } => {
+                // Animated colors change without invalidating the tiles.
+                let prim_data = &data_stores.prim[data_handle];
+                return !prim_data.kind.is_animated();
+            }
             PrimitiveInstanceKind::Picture { .. } |
             PrimitiveInstanceKind::TextRun { .. } |
             PrimitiveInstanceKind::LineDecoration { .. } |
             PrimitiveInstanceKind::NormalBorder { .. } |
             PrimitiveInstanceKind::ImageBorder { .. } |
-            PrimitiveInstanceKind::Rectangle { .. } |
             PrimitiveInstanceKind::LinearGradient { .. } |
             PrimitiveInstanceKind::RadialGradient { .. } |
+            PrimitiveInstanceKind::ConicGradient { .. } |
             PrimitiveInstanceKind::Clear { .. } => {
                 return true;
             }
@@ -2813,6 +3444,17 @@ impl ClipBatcher {
     }
 }
 
+/// Select the image brush batch kind for a texture, using the anisotropic
+/// variant when the image is drawn under a complex transform.
+fn get_image_batch_kind(texture: TextureSource, anisotropic: bool) -> BrushBatchKind {
+    let buffer_kind = get_buffer_kind(texture);
+    if anisotropic {
+        BrushBatchKind::AnisotropicImage(buffer_kind)
+    } else {
+        BrushBatchKind::Image(buffer_kind)
+    }
+}
+
 fn get_buffer_kind(texture: TextureSource) -> ImageBufferKind {
     match texture {
         TextureSource::External(ext_image) => {
@@ -2859,3 +3501,128 @@ fn get_clip_task_address(
 
     Some(address)
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use api::{PicturePoint, PictureSize};
+
+    fn rect(x: f32, y: f32, w: f32, h: f32) -> PictureRect {
+        PictureRect::new(PicturePoint::new(x, y), PictureSize::new(w, h))
+    }
+
+    fn alpha_key(kind: BatchKind) -> BatchKey {
+        BatchKey::new(kind, BlendMode::PremultipliedAlpha, BatchTextures::no_texture())
+    }
+
+    fn solid_key() -> BatchKey {
+        alpha_key(BatchKind::Brush(BrushBatchKind::Solid))
+    }
+
+    fn new_batch_list() -> BatchList {
+        BatchList::new(DeviceIntSize::new(1000, 1000), Vec::new(), Vec::new(), 10, false)
+    }
+
+    /// Adds a primitive with `count` instances, tagged with `tag` so that
+    /// the survivors can be told apart.
+    fn push_prim(
+        list: &mut BatchList,
+        key: BatchKey,
+        rect: &PictureRect,
+        z_id: ZBufferId,
+        tag: i32,
+        count: usize,
+    ) {
+        let instances = list.set_params_and_get_batch(key, rect, z_id);
+        for i in 0 .. count {
+            instances.push(PrimitiveInstanceData { data: [tag, i as i32, 0, 0] });
+        }
+    }
+
+    fn tags(batch: &PrimitiveBatch) -> Vec<(i32, i32)> {
+        batch.instances.iter().map(|instance| (instance.data[0], instance.data[1])).collect()
+    }
+
+    #[test]
+    fn culls_primitive_behind_opaque_occluder() {
+        let mut z_generator = ZBufferIdGenerator::new();
+        let mut list = new_batch_list();
+
+        push_prim(&mut list, solid_key(), &rect(10.0, 10.0, 20.0, 20.0), z_generator.next(), 0, 1);
+        list.add_occluder(&rect(0.0, 0.0, 100.0, 100.0), z_generator.next());
+
+        assert_eq!(list.finalize(), 1);
+        assert!(list.alpha_batch_list.batches.is_empty());
+    }
+
+    #[test]
+    fn keeps_primitive_in_front_of_occluder() {
+        let mut z_generator = ZBufferIdGenerator::new();
+        let mut list = new_batch_list();
+
+        list.add_occluder(&rect(0.0, 0.0, 100.0, 100.0), z_generator.next());
+        push_prim(&mut list, solid_key(), &rect(10.0, 10.0, 20.0, 20.0), z_generator.next(), 0, 1);
+
+        assert_eq!(list.finalize(), 0);
+        assert_eq!(list.alpha_batch_list.batches.len(), 1);
+        assert_eq!(tags(&list.alpha_batch_list.batches[0]), vec![(0, 0)]);
+    }
+
+    #[test]
+    fn keeps_primitive_partially_covered_by_occluder() {
+        let mut z_generator = ZBufferIdGenerator::new();
+        let mut list = new_batch_list();
+
+        push_prim(&mut list, solid_key(), &rect(90.0, 10.0, 20.0, 20.0), z_generator.next(), 0, 1);
+        list.add_occluder(&rect(0.0, 0.0, 100.0, 100.0), z_generator.next());
+
+        assert_eq!(list.finalize(), 0);
+        assert_eq!(list.alpha_batch_list.batches.len(), 1);
+    }
+
+    #[test]
+    fn culls_some_primitives_of_a_batch() {
+        let mut z_generator = ZBufferIdGenerator::new();
+        let mut list = new_batch_list();
+
+        // Covered, with several instances.
+        push_prim(&mut list, solid_key(), &rect(10.0, 10.0, 20.0, 20.0), z_generator.next(), 0, 2);
+        // Outside of the occluder.
+        push_prim(&mut list, solid_key(), &rect(200.0, 10.0, 20.0, 20.0), z_generator.next(), 1, 2);
+        // Covered.
+        push_prim(&mut list, solid_key(), &rect(50.0, 50.0, 20.0, 20.0), z_generator.next(), 2, 1);
+        list.add_occluder(&rect(0.0, 0.0, 100.0, 100.0), z_generator.next());
+        // In front of the occluder.
+        push_prim(&mut list, solid_key(), &rect(10.0, 10.0, 20.0, 20.0), z_generator.next(), 3, 1);
+
+        assert_eq!(list.alpha_batch_list.batches.len(), 1);
+        assert_eq!(list.finalize(), 2);
+
+        let alpha = &list.alpha_batch_list;
+        assert_eq!(alpha.batches.len(), 1);
+        assert_eq!(tags(&alpha.batches[0]), vec![(1, 0), (1, 1), (3, 0)]);
+        assert_eq!(alpha.item_rects[0], vec![rect(200.0, 10.0, 20.0, 20.0), rect(10.0, 10.0, 20.0, 20.0)]);
+        let first_instances = alpha.items[0].iter().map(|item| item.first_instance).collect::<Vec<_>>();
+        assert_eq!(first_instances, vec![0, 2]);
+    }
+
+    #[test]
+    fn removes_batches_left_empty() {
+        let mut z_generator = ZBufferIdGenerator::new();
+        let mut list = new_batch_list();
+
+        push_prim(&mut list, solid_key(), &rect(10.0, 10.0, 20.0, 20.0), z_generator.next(), 0, 1);
+        push_prim(&mut list, alpha_key(BatchKind::SplitComposite), &rect(10.0, 10.0, 20.0, 20.0), z_generator.next(), 1, 1);
+        push_prim(&mut list, solid_key(), &rect(200.0, 10.0, 20.0, 20.0), z_generator.next(), 2, 1);
+        list.add_occluder(&rect(0.0, 0.0, 100.0, 100.0), z_generator.next());
+
+        assert_eq!(list.alpha_batch_list.batches.len(), 2);
+        assert_eq!(list.finalize(), 2);
+
+        let alpha = &list.alpha_batch_list;
+        assert_eq!(alpha.batches.len(), 1);
+        assert_eq!(alpha.items.len(), 1);
+        assert_eq!(alpha.item_rects.len(), 1);
+        assert_eq!(tags(&alpha.batches[0]), vec![(2, 0)]);
+    }
+}
@@ -5,7 +5,7 @@
 use api::{BorderRadius, BorderSide, BorderStyle, ColorF, ColorU, DeviceRect, DeviceSize};
 use api::{LayoutSideOffsets, LayoutSizeAu, LayoutPrimitiveInfo, LayoutToDeviceScale};
 use api::{DeviceVector2D, DevicePoint, LayoutRect, LayoutSize, DeviceIntSize};
-use api::{AuHelpers, LayoutPoint, RepeatMode, TexelRect, LayoutVector2D};
+use api::{AuHelpers, LayoutPoint, PremultipliedColorF, RepeatMode, TexelRect, LayoutVector2D};
 use api::NormalBorder as ApiNormalBorder;
 use ellipse::Ellipse;
 use euclid::vec2;
@@ -851,6 +851,27 @@ pub fn get_max_scale_for_border(
     LayoutToDeviceScale::new(MAX_BORDER_RESOLUTION as f32 / r)
 }
 
+/// Writes the instances of a dashed or dotted corner, fails for the other
+/// styles or if the corner is too small to get any dash.
+fn write_dashed_or_dotted_corner(
+    style: BorderStyle,
+    radius: DeviceSize,
+    widths: DeviceSize,
+    segment: BorderSegment,
+    base_instance: &BorderInstance,
+    instances: &mut Vec<BorderInstance>,
+) -> Result<(), ()> {
+    match style {
+        BorderStyle::Dashed => {
+            write_dashed_corner_instances(radius, widths, segment, base_instance, instances)
+        }
+        BorderStyle::Dotted => {
+            write_dotted_corner_instances(radius, widths, segment, base_instance, instances)
+        }
+        _ => Err(()),
+    }
+}
+
 fn add_segment(
     task_rect: DeviceRect,
     style0: BorderStyle,
@@ -884,38 +905,36 @@ fn add_segment(
         BorderSegment::TopRight |
         BorderSegment::BottomLeft |
         BorderSegment::BottomRight => {
-            // TODO(gw): Similarly to the old border code, we don't correctly handle a a corner
-            //           that is dashed on one edge, and dotted on another. We can handle this
-            //           in the future by submitting two instances, each one with one side
-            //           color set to have an alpha of 0.
-            if (style0 == BorderStyle::Dotted && style1 == BorderStyle::Dashed) ||
-               (style0 == BorderStyle::Dashed && style0 == BorderStyle::Dotted) {
-                warn!("TODO: Handle a corner with dotted / dashed transition.");
-            }
-
-            let dashed_or_dotted_corner = match style0 {
-                BorderStyle::Dashed => {
-                    write_dashed_corner_instances(
-                        radius,
-                        widths,
-                        segment,
-                        &base_instance,
-                        instances,
-                    )
+            // A corner that is dashed on one edge and dotted on the other is
+            // drawn as two sets of instances, each one with the color of the
+            // other side made transparent.
+            let is_mixed_corner =
+                (style0 == BorderStyle::Dotted && style1 == BorderStyle::Dashed) ||
+                (style0 == BorderStyle::Dashed && style1 == BorderStyle::Dotted);
+
+            if is_mixed_corner {
+                let side_flags = |style: BorderStyle| {
+                    (segment as i32) |
+                    ((style as i32) << 8) |
+                    ((style as i32) << 16) |
+                    ((do_aa as i32) << 28)
+                };
+                let side0 = BorderInstance {
+                    flags: side_flags(style0),
+                    color1: PremultipliedColorF::TRANSPARENT,
+                    ..base_instance
+                };
+                let side1 = BorderInstance {
+                    flags: side_flags(style1),
+                    color0: PremultipliedColorF::TRANSPARENT,
+                    ..base_instance
+                };
+                for &(style, ref instance) in &[(style0, side0), (style1, side1)] {
+                    if write_dashed_or_dotted_corner(style, radius, widths, segment, instance, instances).is_err() {
+                        instances.push(instance.clone());
+                    }
                 }
-                BorderStyle::Dotted => {
-                    write_dotted_corner_instances(
-                        radius,
-                        widths,
-                        segment,
-                        &base_instance,
-                        instances,
-                    )
-                }
-                _ => Err(()),
-            };
-
-            if dashed_or_dotted_corner.is_err() {
+            } else if write_dashed_or_dotted_corner(style0, radius, widths, segment, &base_instance, instances).is_err() {
                 instances.push(base_instance);
             }
         }
@@ -1138,6 +1157,46 @@ pub fn build_border_instances(
     instances
 }
 
+/// The most tiles a nine-patch segment is split into for the round and
+/// space repeat modes, beyond which it's repeated by the shader instead.
+const MAX_NINE_PATCH_TILES: usize = 256;
+
+/// Returns the start and length of the tiles along one axis of a nine-patch
+/// segment, or None if the segment has to be repeated by the shader.
+fn tile_positions(
+    start: f32,
+    length: f32,
+    tile_length: f32,
+    mode: RepeatMode,
+) -> Option<Vec<(f32, f32)>> {
+    match mode {
+        RepeatMode::Stretch => Some(vec![(start, length)]),
+        RepeatMode::Repeat => None,
+        _ if tile_length <= 0.0 => Some(vec![(start, length)]),
+        RepeatMode::Round => {
+            // Scale the tiles so that a whole number of them fits.
+            let count = (length / tile_length).round().max(1.0) as usize;
+            if count > MAX_NINE_PATCH_TILES {
+                return None;
+            }
+            let tile_length = length / count as f32;
+            Some((0 .. count).map(|i| (start + i as f32 * tile_length, tile_length)).collect())
+        }
+        RepeatMode::Space => {
+            // Fit as many whole tiles as possible, and spread the space
+            // left evenly around them.
+            let count = (length / tile_length).floor() as usize;
+            if count > MAX_NINE_PATCH_TILES {
+                return None;
+            }
+            let space = (length - count as f32 * tile_length) / (count + 1) as f32;
+            Some((0 .. count)
+                .map(|i| (start + space + i as f32 * (tile_length + space), tile_length))
+                .collect())
+        }
+    }
+}
+
 impl NinePatchDescriptor {
     pub fn create_segments(
         &self,
@@ -1185,12 +1244,22 @@ impl NinePatchDescriptor {
         );
         let br_inner = br_outer - vec2(self.widths.right, self.widths.bottom);
 
+        // The length of one tile of the edges in local space, following the
+        // scale of the border width over the slice. The center is scaled
+        // like the top and left edges.
+        let scale = |width: f32, slice: f32| if slice > 0.0 { width / slice } else { 1.0 };
+        let top_tile_width = (px2 - px1) * scale(self.widths.top, py1 - py0);
+        let bottom_tile_width = (px2 - px1) * scale(self.widths.bottom, py3 - py2);
+        let left_tile_height = (py2 - py1) * scale(self.widths.left, px1 - px0);
+        let right_tile_height = (py2 - py1) * scale(self.widths.right, px3 - px2);
+
         fn add_segment(
             segments: &mut Vec<BrushSegment>,
             rect: LayoutRect,
             uv_rect: TexelRect,
             repeat_horizontal: RepeatMode,
-            repeat_vertical: RepeatMode
+            repeat_vertical: RepeatMode,
+            tile_size: LayoutSize,
         ) {
             if uv_rect.uv1.x > uv_rect.uv0.x &&
                uv_rect.uv1.y > uv_rect.uv0.y {
@@ -1201,28 +1270,37 @@ impl NinePatchDescriptor {
                     BrushFlags::SEGMENT_RELATIVE |
                     BrushFlags::SEGMENT_TEXEL_RECT;
 
-                // Enable repeat modes on the segment.
-                if repeat_horizontal == RepeatMode::Repeat {
+                // Enable repeat modes on the segment. Round and space are
+                // done by splitting the segment into stretched tiles.
+                let columns = tile_positions(rect.origin.x, rect.size.width, tile_size.width, repeat_horizontal);
+                let rows = tile_positions(rect.origin.y, rect.size.height, tile_size.height, repeat_vertical);
+                if columns.is_none() || repeat_horizontal == RepeatMode::Repeat {
                     brush_flags |= BrushFlags::SEGMENT_REPEAT_X;
                 }
-                if repeat_vertical == RepeatMode::Repeat {
+                if rows.is_none() || repeat_vertical == RepeatMode::Repeat {
                     brush_flags |= BrushFlags::SEGMENT_REPEAT_Y;
                 }
-
-                let segment = BrushSegment::new(
-                    rect,
-                    true,
-                    EdgeAaSegmentMask::empty(),
-                    [
-                        uv_rect.uv0.x,
-                        uv_rect.uv0.y,
-                        uv_rect.uv1.x,
-                        uv_rect.uv1.y,
-                    ],
-                    brush_flags,
-                );
-
-                segments.push(segment);
+                let columns = columns.unwrap_or_else(|| vec![(rect.origin.x, rect.size.width)]);
+                let rows = rows.unwrap_or_else(|| vec![(rect.origin.y, rect.size.height)]);
+
+                for &(y, height) in &rows {
+                    for &(x, width) in &columns {
+                        let segment = BrushSegment::new(
+                            LayoutRect::new(LayoutPoint::new(x, y), LayoutSize::new(width, height)),
+                            true,
+                            EdgeAaSegmentMask::empty(),
+                            [
+                                uv_rect.uv0.x,
+                                uv_rect.uv0.y,
+                                uv_rect.uv1.x,
+                                uv_rect.uv1.y,
+                            ],
+                            brush_flags,
+                        );
+
+                        segments.push(segment);
+                    }
+                }
             }
         }
 
@@ -1235,7 +1313,8 @@ impl NinePatchDescriptor {
             LayoutRect::from_floats(tl_outer.x, tl_outer.y, tl_inner.x, tl_inner.y),
             TexelRect::new(px0, py0, px1, py1),
             RepeatMode::Stretch,
-            RepeatMode::Stretch
+            RepeatMode::Stretch,
+            LayoutSize::zero(),
         );
         // Top right
         add_segment(
@@ -1243,7 +1322,8 @@ impl NinePatchDescriptor {
             LayoutRect::from_floats(tr_inner.x, tr_outer.y, tr_outer.x, tr_inner.y),
             TexelRect::new(px2, py0, px3, py1),
             RepeatMode::Stretch,
-            RepeatMode::Stretch
+            RepeatMode::Stretch,
+            LayoutSize::zero(),
         );
         // Bottom right
         add_segment(
@@ -1251,7 +1331,8 @@ impl NinePatchDescriptor {
             LayoutRect::from_floats(br_inner.x, br_inner.y, br_outer.x, br_outer.y),
             TexelRect::new(px2, py2, px3, py3),
             RepeatMode::Stretch,
-            RepeatMode::Stretch
+            RepeatMode::Stretch,
+            LayoutSize::zero(),
         );
         // Bottom left
         add_segment(
@@ -1259,7 +1340,8 @@ impl NinePatchDescriptor {
             LayoutRect::from_floats(bl_outer.x, bl_inner.y, bl_inner.x, bl_outer.y),
             TexelRect::new(px0, py2, px1, py3),
             RepeatMode::Stretch,
-            RepeatMode::Stretch
+            RepeatMode::Stretch,
+            LayoutSize::zero(),
         );
 
         // Center
@@ -1269,7 +1351,8 @@ impl NinePatchDescriptor {
                 LayoutRect::from_floats(tl_inner.x, tl_inner.y, tr_inner.x, bl_inner.y),
                 TexelRect::new(px1, py1, px2, py2),
                 self.repeat_horizontal,
-                self.repeat_vertical
+                self.repeat_vertical,
+                LayoutSize::new(top_tile_width, left_tile_height),
             );
         }
 
@@ -1282,6 +1365,7 @@ impl NinePatchDescriptor {
             TexelRect::new(px1, py0, px2, py1),
             self.repeat_horizontal,
             RepeatMode::Stretch,
+            LayoutSize::new(top_tile_width, 0.0),
         );
         // Bottom
         add_segment(
@@ -1290,6 +1374,7 @@ impl NinePatchDescriptor {
             TexelRect::new(px1, py2, px2, py3),
             self.repeat_horizontal,
             RepeatMode::Stretch,
+            LayoutSize::new(bottom_tile_width, 0.0),
         );
         // Left
         add_segment(
@@ -1298,6 +1383,7 @@ impl NinePatchDescriptor {
             TexelRect::new(px0, py1, px1, py2),
             RepeatMode::Stretch,
             self.repeat_vertical,
+            LayoutSize::new(0.0, left_tile_height),
         );
         // Right
         add_segment(
@@ -1306,6 +1392,7 @@ impl NinePatchDescriptor {
             TexelRect::new(px2, py1, px3, py2),
             RepeatMode::Stretch,
             self.repeat_vertical,
+            LayoutSize::new(0.0, right_tile_height),
         );
 
         segments
@@ -6,11 +6,13 @@ use api::{BorderRadius, ClipMode, ComplexClipRegion, DeviceIntRect, DevicePixelS
 use api::{ImageRendering, LayoutRect, LayoutSize, LayoutPoint, LayoutVector2D};
 use api::{BoxShadowClipMode, LayoutToWorldScale, PicturePixel, WorldPixel};
 use api::{PictureRect, LayoutPixel, WorldPoint, WorldSize, WorldRect, LayoutToWorldTransform};
-use api::{ImageKey};
+use api::{ImageKey, PropertyBindingId, PropertyBindingKey};
 use app_units::Au;
 use border::{ensure_no_corner_overlap, BorderRadiusAu};
 use box_shadow::{BLUR_SAMPLE_SCALE, BoxShadowClipSource, BoxShadowCacheKey};
 use clip_scroll_tree::{ClipScrollTree, ROOT_SPATIAL_NODE_INDEX, SpatialNodeIndex};
+#[cfg(feature = "debugger")]
+use debug_server::MemoryBreakdownGroup;
 use ellipse::Ellipse;
 use gpu_cache::{GpuCache, GpuCacheHandle, ToGpuBlocks};
 use gpu_types::{BoxShadowStretchMode};
@@ -21,6 +23,7 @@ use prim_store::{ClipData, ImageMaskData, SpaceMapper, VisibleMaskImageTile};
 use prim_store::{PointKey, PrimitiveInstance, SizeKey, RectangleKey};
 use render_task::to_cache_size;
 use resource_cache::{ImageRequest, ResourceCache};
+use scene::SceneProperties;
 use std::{cmp, u32};
 use util::{extract_inner_rect_safe, project_rect, ScaleOffset};
 
@@ -131,6 +134,20 @@ enum ClipResult {
 pub struct ClipNode {
     pub item: ClipItem,
     pub gpu_cache_handle: GpuCacheHandle,
+    pub rect_binding: Option<ClipRectBinding>,
+}
+
+// The property binding of an animated clip rect. The clip chain
+// nodes referencing the clip are positioned with the default rect,
+// so the offset of the current value from it is kept here.
+#[derive(Debug)]
+#[cfg_attr(feature = "capture", derive(Serialize))]
+#[cfg_attr(feature = "replay", derive(Deserialize))]
+#[derive(MallocSizeOf)]
+pub struct ClipRectBinding {
+    pub id: PropertyBindingId,
+    pub default_rect: LayoutRect,
+    pub offset: LayoutVector2D,
 }
 
 // Convert from an interning key for a clip item
@@ -139,10 +156,20 @@ pub struct ClipNode {
 //           convert them to use named fields.
 impl From<ClipItemKey> for ClipNode {
     fn from(item: ClipItemKey) -> Self {
+        let mut rect_binding = None;
         let item = match item {
             ClipItemKey::Rectangle(size, mode) => {
                 ClipItem::Rectangle(size.into(), mode)
             }
+            ClipItemKey::AnimatedRectangle(id, default_rect) => {
+                let default_rect: LayoutRect = default_rect.into();
+                rect_binding = Some(ClipRectBinding {
+                    id,
+                    default_rect,
+                    offset: LayoutVector2D::zero(),
+                });
+                ClipItem::Rectangle(default_rect.size, ClipMode::Clip)
+            }
             ClipItemKey::RoundedRectangle(size, radius, mode) => {
                 ClipItem::RoundedRectangle(
                     size.into(),
@@ -172,6 +199,7 @@ impl From<ClipItemKey> for ClipNode {
         ClipNode {
             item,
             gpu_cache_handle: GpuCacheHandle::new(),
+            rect_binding,
         }
     }
 }
@@ -351,6 +379,40 @@ impl ClipNodeInfo {
 }
 
 impl ClipNode {
+    // Returns the position of the clip, given the position of a clip
+    // chain node that references it.
+    pub fn local_pos(&self, chain_local_pos: LayoutPoint) -> LayoutPoint {
+        match self.rect_binding {
+            Some(ref rect_binding) => chain_local_pos + rect_binding.offset,
+            None => chain_local_pos,
+        }
+    }
+
+    // Resolve the current value of an animated clip rect. This must
+    // run before any clip chain instance is built for the frame.
+    pub fn update_rect_binding(
+        &mut self,
+        scene_properties: &SceneProperties,
+        gpu_cache: &mut GpuCache,
+    ) {
+        if let Some(ref mut rect_binding) = self.rect_binding {
+            let rect = scene_properties
+                .rect_properties()
+                .get(&rect_binding.id)
+                .cloned()
+                .unwrap_or(rect_binding.default_rect);
+
+            rect_binding.offset = rect.origin - rect_binding.default_rect.origin;
+
+            if let ClipItem::Rectangle(ref mut size, _) = self.item {
+                if *size != rect.size {
+                    *size = rect.size;
+                    gpu_cache.invalidate(&self.gpu_cache_handle);
+                }
+            }
+        }
+    }
+
     pub fn update(
         &mut self,
         gpu_cache: &mut GpuCache,
@@ -481,6 +543,14 @@ impl ClipStore {
         }
     }
 
+    #[cfg(feature = "debugger")]
+    pub fn report_memory_breakdown(&self, group: &mut MemoryBreakdownGroup) {
+        group.add::<ClipChainNode>("clip_chain_nodes", self.clip_chain_nodes.len());
+        group.add::<ClipNodeInstance>("clip_node_instances", self.clip_node_instances.len());
+        group.add::<ClipNodeInfo>("clip_node_info", self.clip_node_info.len());
+        group.add::<ClipNodeCollector>("clip_node_collectors", self.clip_node_collectors.len());
+    }
+
     pub fn get_clip_chain(&self, clip_chain_id: ClipChainId) -> &ClipChainNode {
         &self.clip_chain_nodes[clip_chain_id.0 as usize]
     }
@@ -742,6 +812,9 @@ impl<I: Iterator<Item = ComplexClipRegion>> Iterator for ComplexTranslateIter<I>
 #[derive(Clone, Debug)]
 pub struct ClipRegion<I> {
     pub main: LayoutRect,
+    // The binding of an animated main rect, along with its default
+    // value as given in the display list.
+    pub main_binding: Option<(PropertyBindingKey<LayoutRect>, LayoutRect)>,
     pub image_mask: Option<ImageMask>,
     pub complex_clips: I,
 }
@@ -749,6 +822,7 @@ pub struct ClipRegion<I> {
 impl<J> ClipRegion<ComplexTranslateIter<J>> {
     pub fn create_for_clip_node(
         rect: LayoutRect,
+        rect_binding: Option<PropertyBindingKey<LayoutRect>>,
         complex_clips: J,
         mut image_mask: Option<ImageMask>,
         reference_frame_relative_offset: &LayoutVector2D,
@@ -762,6 +836,7 @@ impl<J> ClipRegion<ComplexTranslateIter<J>> {
 
         ClipRegion {
             main: rect.translate(reference_frame_relative_offset),
+            main_binding: rect_binding.map(|key| (key, rect)),
             image_mask,
             complex_clips: ComplexTranslateIter {
                 source: complex_clips,
@@ -778,6 +853,7 @@ impl ClipRegion<Option<ComplexClipRegion>> {
     ) -> Self {
         ClipRegion {
             main: local_clip.translate(reference_frame_relative_offset),
+            main_binding: None,
             image_mask: None,
             complex_clips: None,
         }
@@ -796,6 +872,7 @@ impl ClipRegion<Option<ComplexClipRegion>> {
 #[cfg_attr(feature = "replay", derive(Deserialize))]
 pub enum ClipItemKey {
     Rectangle(SizeKey, ClipMode),
+    AnimatedRectangle(PropertyBindingId, RectangleKey),
     RoundedRectangle(SizeKey, BorderRadiusAu, ClipMode),
     ImageMask(SizeKey, ImageKey, bool),
     BoxShadow(PointKey, SizeKey, BorderRadiusAu, RectangleKey, Au, BoxShadowClipMode),
@@ -806,6 +883,17 @@ impl ClipItemKey {
         ClipItemKey::Rectangle(size.into(), mode)
     }
 
+    pub fn main_rect<I>(clip_region: &ClipRegion<I>) -> Self {
+        match clip_region.main_binding {
+            Some((key, default_rect)) => {
+                ClipItemKey::AnimatedRectangle(key.id, default_rect.into())
+            }
+            None => {
+                ClipItemKey::rectangle(clip_region.main.size, ClipMode::Clip)
+            }
+        }
+    }
+
     pub fn rounded_rect(size: LayoutSize, mut radii: BorderRadius, mode: ClipMode) -> Self {
         if radii.is_zero() {
             ClipItemKey::rectangle(size, mode)
@@ -1324,6 +1412,7 @@ fn add_clip_node_to_current_chain(
     clip_scroll_tree: &ClipScrollTree,
 ) -> bool {
     let clip_node = &clip_data_store[node.handle];
+    let local_pos = clip_node.local_pos(node.local_pos);
     let clip_spatial_node = &clip_scroll_tree.spatial_nodes[node.spatial_node_index.0 as usize];
     let ref_spatial_node = &clip_scroll_tree.spatial_nodes[spatial_node_index.0 as usize];
 
@@ -1348,7 +1437,7 @@ fn add_clip_node_to_current_chain(
 
     // If we can convert spaces, try to reduce the size of the region
     // requested, and cache the conversion information for the next step.
-    if let Some(clip_rect) = clip_node.item.get_local_clip_rect(node.local_pos) {
+    if let Some(clip_rect) = clip_node.item.get_local_clip_rect(local_pos) {
         match conversion {
             ClipSpaceConversion::Local => {
                 *local_clip_rect = match local_clip_rect.intersection(&clip_rect) {
@@ -1379,7 +1468,7 @@ fn add_clip_node_to_current_chain(
 
     clip_node_info.push(ClipNodeInfo {
         conversion,
-        local_pos: node.local_pos,
+        local_pos,
         handle: node.handle,
         spatial_node_index: node.spatial_node_index,
     });
@@ -5,6 +5,8 @@
 use api::{ExternalScrollId, LayoutPoint, LayoutRect, LayoutVector2D, ReferenceFrameKind};
 use api::{PipelineId, ScrollClamping, ScrollNodeState, ScrollLocation, ScrollSensitivity};
 use api::{LayoutSize, LayoutTransform, PropertyBinding, TransformStyle, WorldPoint};
+#[cfg(feature = "debugger")]
+use debug_server::MemoryBreakdownGroup;
 use gpu_types::TransformPalette;
 use internal_types::{FastHashMap, FastHashSet};
 use print_tree::{PrintableTree, PrintTree, PrintTreePrinter};
@@ -456,6 +458,17 @@ impl ClipScrollTree {
         pt.end_level();
     }
 
+    #[cfg(feature = "debugger")]
+    pub fn report_memory_breakdown(&self, group: &mut MemoryBreakdownGroup) {
+        group.add::<SpatialNode>("spatial_nodes", self.spatial_nodes.len());
+        group.add::<CoordinateSystem>("coord_systems", self.coord_systems.len());
+        group.add::<(ExternalScrollId, (LayoutPoint, ScrollClamping))>(
+            "pending_scroll_offsets",
+            self.pending_scroll_offsets.len(),
+        );
+        group.add::<PipelineId>("pipelines_to_discard", self.pipelines_to_discard.len());
+    }
+
     #[allow(dead_code)]
     pub fn print(&self) {
         if !self.spatial_nodes.is_empty() {
@@ -0,0 +1,90 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+//! Presentation of picture cache tiles.
+//!
+//! By default the renderer draws the cached tiles into the main framebuffer
+//! along with everything else. A native `Compositor` instead receives the
+//! valid tiles of each frame as surfaces, with their offsets and clips, and
+//! presents them with the OS compositor (DirectComposition, CoreAnimation,
+//! Wayland subsurfaces, ...). The main framebuffer is then cleared to
+//! transparent and only holds the content that isn't cached in valid tiles,
+//! so the compositor has to place it above the tile surfaces.
+
+use api::{ColorF, DeviceIntPoint, DeviceIntRect};
+use device::Texture;
+use internal_types::TextureSource;
+
+/// Identifies the surface of a picture cache tile across frames, so that a
+/// native compositor can keep the surfaces of tiles that stay visible.
+#[derive(Copy, Clone, Debug, Eq, Hash, PartialEq)]
+#[cfg_attr(feature = "capture", derive(Serialize))]
+#[cfg_attr(feature = "replay", derive(Deserialize))]
+pub struct NativeSurfaceId(pub u64);
+
+/// A valid picture cache tile of a frame, as recorded at frame building.
+#[derive(Debug)]
+#[cfg_attr(feature = "capture", derive(Serialize))]
+#[cfg_attr(feature = "replay", derive(Deserialize))]
+pub struct CompositeTile {
+    pub id: NativeSurfaceId,
+    pub offset: DeviceIntPoint,
+    pub clip_rect: DeviceIntRect,
+    pub texture: TextureSource,
+    pub texture_layer: i32,
+    pub uv_rect: DeviceIntRect,
+}
+
+/// A picture cache tile handed to a `Compositor`, in device pixels.
+pub struct CompositeSurface<'a> {
+    pub id: NativeSurfaceId,
+    /// The position of the top-left corner of the tile.
+    pub offset: DeviceIntPoint,
+    /// The visible part of the tile.
+    pub clip_rect: DeviceIntRect,
+    /// The texture array holding the tile content.
+    pub texture: &'a Texture,
+    /// The layer of `texture` holding the tile content.
+    pub texture_layer: i32,
+    /// The rect of the tile content within the texture layer.
+    pub uv_rect: DeviceIntRect,
+}
+
+/// Presents the picture cache tiles of each rendered frame.
+///
+/// Tile textures are only valid until the next frame is rendered, so native
+/// compositors have to copy them into their surfaces in `add_surface`, or
+/// before `end_frame` returns.
+pub trait Compositor {
+    /// Returns true if the compositor presents the tiles itself, in which
+    /// case the renderer doesn't draw them into the main framebuffer.
+    fn is_native(&self) -> bool;
+
+    /// Starts a frame. `background_color` is the color the main framebuffer
+    /// would have been cleared to, and should be drawn below the tiles.
+    fn begin_frame(&mut self, background_color: Option<ColorF>);
+
+    /// Adds a tile surface to the frame, in back to front order.
+    fn add_surface(&mut self, surface: &CompositeSurface);
+
+    /// Ends the frame. Surfaces that weren't added this frame are no longer
+    /// visible.
+    fn end_frame(&mut self);
+}
+
+/// The default compositor, for which the renderer draws the tiles into the
+/// main framebuffer with the rest of the frame.
+pub struct DrawCompositor;
+
+impl Compositor for DrawCompositor {
+    fn is_native(&self) -> bool {
+        false
+    }
+
+    fn begin_frame(&mut self, _background_color: Option<ColorF>) {}
+
+    fn add_surface(&mut self, _surface: &CompositeSurface) {}
+
+    fn end_frame(&mut self) {}
+}
@@ -173,6 +173,15 @@ impl DebugColorVertex {
     }
 }
 
+/// The number of vertices of each kind added to a `DebugRenderer` at some
+/// point, to address the ones added afterwards.
+#[derive(Clone, Copy, Debug)]
+pub struct DebugRendererMark {
+    font_vertices: usize,
+    tri_vertices: usize,
+    line_vertices: usize,
+}
+
 pub struct DebugRenderer {
     font_vertices: Vec<DebugFontVertex>,
     font_indices: Vec<u32>,
@@ -186,6 +195,11 @@ pub struct DebugRenderer {
     line_vertices: Vec<DebugColorVertex>,
     line_vao: VAO,
     color_program: Program,
+
+    /// The scale of the text relative to the baked font size.
+    text_scale: f32,
+    /// Overrides the color of all the text, if set.
+    text_color: Option<ColorU>,
 }
 
 impl DebugRenderer {
@@ -222,9 +236,19 @@ impl DebugRenderer {
             font_vao,
             line_vao,
             font_texture,
+            text_scale: 1.0,
+            text_color: None,
         })
     }
 
+    pub fn set_text_scale(&mut self, scale: f32) {
+        self.text_scale = scale.max(0.25);
+    }
+
+    pub fn set_text_color(&mut self, color: Option<ColorU>) {
+        self.text_color = color;
+    }
+
     pub fn deinit<B: hal::Backend>(self, device: &mut Device<B>) {
         device.delete_texture(self.font_texture);
         device.delete_program(self.font_program);
@@ -235,7 +259,7 @@ impl DebugRenderer {
     }
 
     pub fn line_height(&self) -> f32 {
-        debug_font_data::FONT_SIZE as f32 * 1.1
+        debug_font_data::FONT_SIZE as f32 * 1.1 * self.text_scale
     }
 
     /// Draws a line of text at the provided starting coordinates.
@@ -253,6 +277,8 @@ impl DebugRenderer {
         bounds: Option<DeviceRect>,
     ) -> Rect<f32> {
         let mut x_start = x;
+        let scale = self.text_scale;
+        let color = self.text_color.unwrap_or(color);
         let ipw = 1.0 / debug_font_data::BMP_WIDTH as f32;
         let iph = 1.0 / debug_font_data::BMP_HEIGHT as f32;
 
@@ -266,11 +292,11 @@ impl DebugRenderer {
             if c < debug_font_data::GLYPHS.len() {
                 let glyph = &debug_font_data::GLYPHS[c];
 
-                let x0 = (x_start + glyph.xo + 0.5).floor();
-                let y0 = (y + glyph.yo + 0.5).floor();
+                let x0 = (x_start + glyph.xo * scale + 0.5).floor();
+                let y0 = (y + glyph.yo * scale + 0.5).floor();
 
-                let x1 = x0 + glyph.x1 as f32 - glyph.x0 as f32;
-                let y1 = y0 + glyph.y1 as f32 - glyph.y0 as f32;
+                let x1 = x0 + (glyph.x1 as f32 - glyph.x0 as f32) * scale;
+                let y1 = y0 + (glyph.y1 as f32 - glyph.y0 as f32) * scale;
 
                 // If either corner of the glyph will end up out of bounds, drop it.
                 if let Some(b) = bounds {
@@ -288,7 +314,7 @@ impl DebugRenderer {
                 let s1 = glyph.x1 as f32 * ipw;
                 let t1 = glyph.y1 as f32 * iph;
 
-                x_start += glyph.xa;
+                x_start += glyph.xa * scale;
 
                 let vertex_count = self.font_vertices.len() as u32;
 
@@ -367,6 +393,60 @@ impl DebugRenderer {
         self.add_line(p0.x, p1.y, color, p0.x, p0.y, color);
     }
 
+    pub fn mark(&self) -> DebugRendererMark {
+        DebugRendererMark {
+            font_vertices: self.font_vertices.len(),
+            tri_vertices: self.tri_vertices.len(),
+            line_vertices: self.line_vertices.len(),
+        }
+    }
+
+    /// Returns the bounds of the vertices added since `mark`, if any.
+    pub fn bounds_since(&self, mark: DebugRendererMark) -> Option<DeviceRect> {
+        let positions = self.font_vertices[mark.font_vertices ..].iter().map(|v| (v.x, v.y))
+            .chain(self.tri_vertices[mark.tri_vertices ..].iter().map(|v| (v.x, v.y)))
+            .chain(self.line_vertices[mark.line_vertices ..].iter().map(|v| (v.x, v.y)));
+
+        let mut bounds: Option<(DevicePoint, DevicePoint)> = None;
+        for (x, y) in positions {
+            bounds = Some(match bounds {
+                Some((min, max)) => (
+                    DevicePoint::new(min.x.min(x), min.y.min(y)),
+                    DevicePoint::new(max.x.max(x), max.y.max(y)),
+                ),
+                None => (DevicePoint::new(x, y), DevicePoint::new(x, y)),
+            });
+        }
+        bounds.map(|(min, max)| DeviceRect::new(min, (max - min).to_size()))
+    }
+
+    /// Moves the vertices added since `mark` so that `from` is scaled by
+    /// `scale` and ends up at `to`, and multiplies their alpha by `opacity`.
+    pub fn transform_since(
+        &mut self,
+        mark: DebugRendererMark,
+        from: DevicePoint,
+        to: DevicePoint,
+        scale: f32,
+        opacity: f32,
+    ) {
+        let opacity = opacity.max(0.0).min(1.0);
+        let transform = |x: &mut f32, y: &mut f32, color: &mut ColorU| {
+            *x = (*x - from.x) * scale + to.x;
+            *y = (*y - from.y) * scale + to.y;
+            color.a = (color.a as f32 * opacity).round() as u8;
+        };
+        for v in &mut self.font_vertices[mark.font_vertices ..] {
+            transform(&mut v.x, &mut v.y, &mut v.color);
+        }
+        for v in &mut self.tri_vertices[mark.tri_vertices ..] {
+            transform(&mut v.x, &mut v.y, &mut v.color);
+        }
+        for v in &mut self.line_vertices[mark.line_vertices ..] {
+            transform(&mut v.x, &mut v.y, &mut v.color);
+        }
+    }
+
     pub fn render<B: hal::Backend>(
         &mut self,
         device: &mut Device<B>,
@@ -382,8 +462,10 @@ impl DebugRenderer {
                 viewport_size.width as f32,
                 viewport_size.height as f32,
                 0.0,
-                true,
             );
+            let projection = device.surface_origin().apply_to_projection(projection);
+            #[cfg(not(feature = "gleam"))]
+            let projection = device.surface_transform().apply_to_projection(projection);
 
             // Triangles
             if !self.tri_vertices.is_empty() {
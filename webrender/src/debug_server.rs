@@ -4,7 +4,14 @@
 
 use api::{ApiMsg, DebugCommand, DebugFlags, DeviceIntSize};
 use api::channel::MsgSender;
+use gpu_cache::{GpuCacheUpdate, GpuCacheUpdateList};
+use internal_types::{TextureCacheAllocationKind, TextureUpdateList, TextureUpdateSource};
 use print_tree::PrintTreePrinter;
+use std::io::{self, BufRead, BufReader, Write};
+use std::mem;
+use std::net::{TcpListener, TcpStream, ToSocketAddrs};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
 use std::sync::mpsc::{channel, Receiver};
 use std::sync::mpsc::Sender;
 use std::thread;
@@ -12,76 +19,188 @@ use ws;
 use base64::encode;
 use image_loader;
 
+/// Identifies a single debugger client connected to the `DebugServer`.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub struct DebugClientId(usize);
+
+bitflags! {
+    /// The kinds of debugger output a client asked for and didn't receive
+    /// yet. Fetch results are only sent to the clients that requested them.
+    pub struct DebugRequests: u32 {
+        const PASSES = 1 << 0;
+        const SCREENSHOT = 1 << 1;
+        const DOCUMENTS = 1 << 2;
+        const CLIP_SCROLL_TREE = 1 << 3;
+        const RENDER_TASKS = 1 << 4;
+        const UPLOADS = 1 << 5;
+        const MEMORY_BREAKDOWN = 1 << 6;
+    }
+}
+
+/// The outgoing half of a debugger connection. Implement this to bridge
+/// the debugger over an existing devtools protocol.
+pub trait DebugTransport: Send {
+    /// Sends a JSON message to the client. Returns `false` if the client is gone.
+    fn send(&mut self, message: &str) -> bool;
+}
+
+impl DebugTransport for ws::Sender {
+    fn send(&mut self, message: &str) -> bool {
+        ws::Sender::send(self, message).is_ok()
+    }
+}
+
+/// Newline-delimited JSON over a plain socket.
+impl DebugTransport for TcpStream {
+    fn send(&mut self, message: &str) -> bool {
+        self.write_all(message.as_bytes())
+            .and_then(|_| self.write_all(b"\n"))
+            .is_ok()
+    }
+}
+
+/// In-process transport, mostly useful for tests.
+impl DebugTransport for Sender<String> {
+    fn send(&mut self, message: &str) -> bool {
+        Sender::send(self, message.to_owned()).is_ok()
+    }
+}
+
 // Messages that are sent from the render backend to the renderer
 // debug command queue. These are sent in a separate queue so
 // that none of these types are exposed to the RenderApi interfaces.
 // We can't use select!() as it's not stable...
 enum DebugMsg {
-    AddSender(ws::Sender),
-    RemoveSender(ws::util::Token),
+    AddClient(DebugClientId, Box<DebugTransport>),
+    RemoveClient(DebugClientId),
+    Request(DebugClientId, DebugRequests),
 }
 
-// Represents a connection to a client.
-struct Server {
-    ws: ws::Sender,
+/// Registers new debugger clients with the `DebugServer`. It can be cloned
+/// and moved to whichever thread accepts the connections.
+#[derive(Clone)]
+pub struct DebugClientRegistrar {
     debug_tx: Sender<DebugMsg>,
     api_tx: MsgSender<ApiMsg>,
-    debug_flags: DebugFlags,
+    next_client_id: Arc<AtomicUsize>,
+}
+
+impl DebugClientRegistrar {
+    /// Connects a new client, which receives output through `transport`.
+    /// Incoming messages have to be fed into the returned `DebugClient`.
+    pub fn add_client(&self, transport: Box<DebugTransport>) -> DebugClient {
+        let client = self.new_client();
+        client.debug_tx.send(DebugMsg::AddClient(client.id, transport)).ok();
+        client
+    }
+
+    /// Connects a new client that lives on this side of the process,
+    /// returning the receiving end of its output.
+    pub fn add_channel_client(&self) -> (DebugClient, Receiver<String>) {
+        let (tx, rx) = channel();
+        (self.add_client(Box::new(tx)), rx)
+    }
+
+    fn new_client(&self) -> DebugClient {
+        DebugClient {
+            id: DebugClientId(self.next_client_id.fetch_add(1, Ordering::Relaxed)),
+            debug_tx: self.debug_tx.clone(),
+            api_tx: self.api_tx.clone(),
+        }
+    }
+}
+
+/// The incoming half of a debugger connection.
+pub struct DebugClient {
+    id: DebugClientId,
+    debug_tx: Sender<DebugMsg>,
+    api_tx: MsgSender<ApiMsg>,
+}
+
+impl DebugClient {
+    pub fn id(&self) -> DebugClientId {
+        self.id
+    }
+
+    /// Handles a text command received from the client.
+    pub fn handle_message(&mut self, string: &str) {
+        // First, check for flag change commands. Only the toggled flag is
+        // sent, so that clients don't override each other's flags, or the
+        // ones set by the embedder.
+        let flag_change = match string {
+            "enable_profiler" => Some((DebugFlags::PROFILER_DBG, true)),
+            "disable_profiler" => Some((DebugFlags::PROFILER_DBG, false)),
+            "enable_texture_cache_debug" => Some((DebugFlags::TEXTURE_CACHE_DBG, true)),
+            "disable_texture_cache_debug" => Some((DebugFlags::TEXTURE_CACHE_DBG, false)),
+            "enable_render_target_debug" => Some((DebugFlags::RENDER_TARGET_DBG, true)),
+            "disable_render_target_debug" => Some((DebugFlags::RENDER_TARGET_DBG, false)),
+            "enable_gpu_time_queries" => Some((DebugFlags::GPU_TIME_QUERIES, true)),
+            "disable_gpu_time_queries" => Some((DebugFlags::GPU_TIME_QUERIES, false)),
+            "enable_gpu_sample_queries" => Some((DebugFlags::GPU_SAMPLE_QUERIES, true)),
+            "disable_gpu_sample_queries" => Some((DebugFlags::GPU_SAMPLE_QUERIES, false)),
+            _ => None,
+        };
+
+        let cmd = if let Some((flag, enabled)) = flag_change {
+            DebugCommand::SetFlag(flag, enabled)
+        } else {
+            let (cmd, request) = match string {
+                "fetch_passes" => (DebugCommand::FetchPasses, DebugRequests::PASSES),
+                "fetch_screenshot" => (DebugCommand::FetchScreenshot, DebugRequests::SCREENSHOT),
+                "fetch_documents" => (DebugCommand::FetchDocuments, DebugRequests::DOCUMENTS),
+                "fetch_clip_scroll_tree" => {
+                    (DebugCommand::FetchClipScrollTree, DebugRequests::CLIP_SCROLL_TREE)
+                }
+                "fetch_render_tasks" => (DebugCommand::FetchRenderTasks, DebugRequests::RENDER_TASKS),
+                "fetch_uploads" => (DebugCommand::FetchUploads, DebugRequests::UPLOADS),
+                "fetch_memory_breakdown" => {
+                    (DebugCommand::FetchMemoryBreakdown, DebugRequests::MEMORY_BREAKDOWN)
+                }
+                msg => {
+                    error!("unknown msg {}", msg);
+                    return;
+                }
+            };
+            // Queued before the command, so the server knows about the
+            // request by the time the result arrives.
+            self.debug_tx.send(DebugMsg::Request(self.id, request)).ok();
+            cmd
+        };
+
+        let msg = ApiMsg::DebugCommand(cmd);
+        self.api_tx.send(msg).unwrap();
+    }
+
+    /// Disconnects the client from the server.
+    pub fn close(self) {
+        self.debug_tx.send(DebugMsg::RemoveClient(self.id)).ok();
+    }
+}
+
+// Represents a websocket connection to a client.
+struct Server {
+    ws: ws::Sender,
+    client: DebugClient,
 }
 
 impl ws::Handler for Server {
     fn on_open(&mut self, _: ws::Handshake) -> ws::Result<()> {
-        self.debug_tx
-            .send(DebugMsg::AddSender(self.ws.clone()))
+        self.client.debug_tx
+            .send(DebugMsg::AddClient(self.client.id, Box::new(self.ws.clone())))
             .ok();
 
         Ok(())
     }
 
     fn on_close(&mut self, _: ws::CloseCode, _: &str) {
-        self.debug_tx
-            .send(DebugMsg::RemoveSender(self.ws.token()))
+        self.client.debug_tx
+            .send(DebugMsg::RemoveClient(self.client.id))
             .ok();
     }
 
     fn on_message(&mut self, msg: ws::Message) -> ws::Result<()> {
         match msg {
-            ws::Message::Text(string) => {
-                // First, check for flag change commands.
-                let mut set_flags = true;
-                match string.as_str() {
-                    "enable_profiler" => self.debug_flags.insert(DebugFlags::PROFILER_DBG),
-                    "disable_profiler" => self.debug_flags.remove(DebugFlags::PROFILER_DBG),
-                    "enable_texture_cache_debug" => self.debug_flags.insert(DebugFlags::TEXTURE_CACHE_DBG),
-                    "disable_texture_cache_debug" => self.debug_flags.remove(DebugFlags::TEXTURE_CACHE_DBG),
-                    "enable_render_target_debug" => self.debug_flags.insert(DebugFlags::RENDER_TARGET_DBG),
-                    "disable_render_target_debug" => self.debug_flags.remove(DebugFlags::RENDER_TARGET_DBG),
-                    "enable_gpu_time_queries" => self.debug_flags.insert(DebugFlags::GPU_TIME_QUERIES),
-                    "disable_gpu_time_queries" => self.debug_flags.remove(DebugFlags::GPU_TIME_QUERIES),
-                    "enable_gpu_sample_queries" => self.debug_flags.insert(DebugFlags::GPU_SAMPLE_QUERIES),
-                    "disable_gpu_sample_queries" => self.debug_flags.remove(DebugFlags::GPU_SAMPLE_QUERIES),
-                    _ => set_flags = false,
-                };
-
-                let cmd = if set_flags {
-                    DebugCommand::SetFlags(self.debug_flags)
-                } else {
-                    match string.as_str() {
-                        "fetch_passes" => DebugCommand::FetchPasses,
-                        "fetch_screenshot" => DebugCommand::FetchScreenshot,
-                        "fetch_documents" => DebugCommand::FetchDocuments,
-                        "fetch_clip_scroll_tree" => DebugCommand::FetchClipScrollTree,
-                        "fetch_render_tasks" => DebugCommand::FetchRenderTasks,
-                        msg => {
-                            error!("unknown msg {}", msg);
-                            return Ok(());
-                        }
-                    }
-                };
-
-                let msg = ApiMsg::DebugCommand(cmd);
-                self.api_tx.send(msg).unwrap();
-            }
+            ws::Message::Text(string) => self.client.handle_message(&string),
             ws::Message::Binary(..) => {}
         }
 
@@ -89,26 +208,67 @@ impl ws::Handler for Server {
     }
 }
 
+/// Accepts debugger clients speaking newline-delimited JSON over TCP.
+pub fn serve_tcp<A: ToSocketAddrs>(
+    registrar: DebugClientRegistrar,
+    address: A,
+) -> io::Result<thread::JoinHandle<()>> {
+    let listener = TcpListener::bind(address)?;
+    Ok(thread::spawn(move || {
+        for stream in listener.incoming() {
+            let stream = match stream {
+                Ok(stream) => stream,
+                Err(..) => continue,
+            };
+            let transport = match stream.try_clone() {
+                Ok(transport) => transport,
+                Err(..) => continue,
+            };
+            let mut client = registrar.add_client(Box::new(transport));
+            thread::spawn(move || {
+                for line in BufReader::new(stream).lines() {
+                    match line {
+                        Ok(line) => client.handle_message(line.trim()),
+                        Err(..) => break,
+                    }
+                }
+                client.close();
+            });
+        }
+    }))
+}
+
+struct ConnectedClient {
+    id: DebugClientId,
+    pending: DebugRequests,
+    transport: Box<DebugTransport>,
+}
+
 // Spawn a thread for a given renderer, and wait for
 // client connections.
 pub struct DebugServer {
     join_handle: Option<thread::JoinHandle<()>>,
     broadcaster: ws::Sender,
+    registrar: DebugClientRegistrar,
     debug_rx: Receiver<DebugMsg>,
-    senders: Vec<ws::Sender>,
+    clients: Vec<ConnectedClient>,
 }
 
 impl DebugServer {
     pub fn new(api_tx: MsgSender<ApiMsg>) -> DebugServer {
         let (debug_tx, debug_rx) = channel();
+        let registrar = DebugClientRegistrar {
+            debug_tx,
+            api_tx,
+            next_client_id: Arc::new(AtomicUsize::new(0)),
+        };
 
+        let ws_registrar = registrar.clone();
         let socket = ws::Builder::new()
             .build(move |out| {
                 Server {
                     ws: out,
-                    debug_tx: debug_tx.clone(),
-                    api_tx: api_tx.clone(),
-                    debug_flags: DebugFlags::empty(),
+                    client: ws_registrar.new_client(),
                 }
             })
             .unwrap();
@@ -126,42 +286,66 @@ impl DebugServer {
         DebugServer {
             join_handle,
             broadcaster,
+            registrar,
             debug_rx,
-            senders: Vec::new(),
+            clients: Vec::new(),
         }
     }
 
-    pub fn send(&mut self, message: String) {
-        // Add any new connections that have been queued.
+    pub fn registrar(&self) -> DebugClientRegistrar {
+        self.registrar.clone()
+    }
+
+    /// Sends the output of a fetch command to the clients that requested it.
+    pub fn send(&mut self, command: &DebugCommand, message: String) {
+        let kind = match *command {
+            DebugCommand::FetchPasses => DebugRequests::PASSES,
+            DebugCommand::FetchScreenshot => DebugRequests::SCREENSHOT,
+            DebugCommand::FetchDocuments => DebugRequests::DOCUMENTS,
+            DebugCommand::FetchClipScrollTree => DebugRequests::CLIP_SCROLL_TREE,
+            DebugCommand::FetchRenderTasks => DebugRequests::RENDER_TASKS,
+            DebugCommand::FetchUploads => DebugRequests::UPLOADS,
+            DebugCommand::FetchMemoryBreakdown => DebugRequests::MEMORY_BREAKDOWN,
+            _ => DebugRequests::empty(),
+        };
+
+        // Apply any connection changes that have been queued.
         while let Ok(msg) = self.debug_rx.try_recv() {
             match msg {
-                DebugMsg::AddSender(sender) => {
-                    self.senders.push(sender);
+                DebugMsg::AddClient(id, transport) => {
+                    self.clients.push(ConnectedClient {
+                        id,
+                        pending: DebugRequests::empty(),
+                        transport,
+                    });
                 }
-                DebugMsg::RemoveSender(token) => {
-                    self.senders.retain(|sender| sender.token() != token);
+                DebugMsg::RemoveClient(id) => {
+                    self.clients.retain(|client| client.id != id);
+                }
+                DebugMsg::Request(id, request) => {
+                    if let Some(client) = self.clients.iter_mut().find(|client| client.id == id) {
+                        client.pending.insert(request);
+                    }
                 }
             }
         }
 
-        // Broadcast the message to all senders. Keep
-        // track of the ones that failed, so they can
-        // be removed from the active sender list.
-        let mut disconnected_senders = Vec::new();
+        // Send the message to the clients waiting for it. Keep
+        // track of the ones that failed, so they can be
+        // removed from the active client list.
+        let mut disconnected_clients = Vec::new();
 
-        for (i, sender) in self.senders.iter().enumerate() {
-            if let Err(..) = sender.send(message.clone()) {
-                disconnected_senders.push(i);
+        for client in self.clients.iter_mut() {
+            if !client.pending.intersects(kind) {
+                continue;
+            }
+            client.pending.remove(kind);
+            if !client.transport.send(&message) {
+                disconnected_clients.push(client.id);
             }
         }
 
-        // Remove the broken senders from the list
-        // for next broadcast. Remove in reverse
-        // order so the indices are valid for the
-        // entire loop.
-        for i in disconnected_senders.iter().rev() {
-            self.senders.remove(*i);
-        }
+        self.clients.retain(|client| !disconnected_clients.contains(&client.id));
     }
 }
 
@@ -308,6 +492,136 @@ impl Screenshot {
     }
 }
 
+#[derive(Serialize)]
+struct TextureAllocationRecord {
+    texture: u64,
+    op: &'static str,
+    width: i32,
+    height: i32,
+    layers: i32,
+}
+
+#[derive(Serialize)]
+struct TextureUpdateRecord {
+    texture: u64,
+    layer: i32,
+    rect: (i32, i32, i32, i32),
+    source: &'static str,
+    external_id: Option<u64>,
+}
+
+#[derive(Serialize)]
+struct GpuCacheUploadRecord {
+    clear: bool,
+    height: i32,
+    runs: usize,
+    blocks: usize,
+    rows: usize,
+}
+
+/// The texture cache and GPU cache uploads the renderer applied in a frame.
+#[derive(Default, Serialize)]
+pub struct FrameUploads {
+    frame_id: usize,
+    texture_allocations: Vec<TextureAllocationRecord>,
+    texture_updates: Vec<TextureUpdateRecord>,
+    gpu_cache: Vec<GpuCacheUploadRecord>,
+}
+
+impl FrameUploads {
+    pub fn is_empty(&self) -> bool {
+        self.texture_allocations.is_empty() &&
+            self.texture_updates.is_empty() &&
+            self.gpu_cache.is_empty()
+    }
+
+    pub fn set_frame_id(&mut self, frame_id: usize) {
+        self.frame_id = frame_id;
+    }
+
+    pub fn add_texture_update_list(&mut self, list: &TextureUpdateList) {
+        for allocation in &list.allocations {
+            let (op, width, height, layers) = match allocation.kind {
+                TextureCacheAllocationKind::Alloc(ref info) => {
+                    ("alloc", info.width, info.height, info.layer_count)
+                }
+                TextureCacheAllocationKind::Realloc(ref info) => {
+                    ("realloc", info.width, info.height, info.layer_count)
+                }
+                TextureCacheAllocationKind::Free => ("free", 0, 0, 0),
+            };
+            self.texture_allocations.push(TextureAllocationRecord {
+                texture: allocation.id.0,
+                op,
+                width,
+                height,
+                layers,
+            });
+        }
+
+        for update in &list.updates {
+            let (source, external_id) = match update.source {
+                TextureUpdateSource::Bytes { .. } => ("bytes", None),
+                TextureUpdateSource::External { id, .. } => ("external", Some(id.0)),
+                TextureUpdateSource::DebugClear => ("clear", None),
+            };
+            self.texture_updates.push(TextureUpdateRecord {
+                texture: update.id.0,
+                layer: update.layer_index,
+                rect: (
+                    update.rect.origin.x,
+                    update.rect.origin.y,
+                    update.rect.size.width,
+                    update.rect.size.height,
+                ),
+                source,
+                external_id,
+            });
+        }
+    }
+
+    pub fn add_gpu_cache_update_list(&mut self, list: &GpuCacheUpdateList) {
+        // The updates are usually sorted by address (see
+        // `GpuCacheUpdateList::coalesce`), so a row is counted once.
+        let mut rows = 0;
+        let mut last_row = None;
+        for update in &list.updates {
+            let GpuCacheUpdate::Copy { address, .. } = *update;
+            if last_row != Some(address.v) {
+                rows += 1;
+                last_row = Some(address.v);
+            }
+        }
+
+        self.gpu_cache.push(GpuCacheUploadRecord {
+            clear: list.clear,
+            height: list.height,
+            runs: list.updates.len(),
+            blocks: list.blocks.len(),
+            rows,
+        });
+    }
+}
+
+#[derive(Serialize)]
+pub struct UploadHistory<'a> {
+    kind: &'static str,
+    frames: Vec<&'a FrameUploads>,
+}
+
+impl<'a> UploadHistory<'a> {
+    pub fn new() -> Self {
+        UploadHistory {
+            kind: "uploads",
+            frames: Vec::new(),
+        }
+    }
+
+    pub fn add(&mut self, frame: &'a FrameUploads) {
+        self.frames.push(frame);
+    }
+}
+
 // A serializable list of debug information about clip-scroll trees
 // that can be sent to the client
 
@@ -330,6 +644,62 @@ impl ClipScrollTreeList {
     }
 }
 
+#[derive(Serialize)]
+struct MemoryBreakdownEntry {
+    name: &'static str,
+    count: usize,
+    bytes: usize,
+}
+
+/// Element counts and approximate sizes of the backend data structures
+/// of a single document, or of the state shared by all documents.
+#[derive(Serialize)]
+pub struct MemoryBreakdownGroup {
+    name: String,
+    entries: Vec<MemoryBreakdownEntry>,
+}
+
+impl MemoryBreakdownGroup {
+    pub fn new(name: &str) -> Self {
+        MemoryBreakdownGroup {
+            name: name.to_string(),
+            entries: Vec::new(),
+        }
+    }
+
+    /// Records a structure holding `count` elements of type `T`.
+    ///
+    /// The size is approximated as `count * size_of::<T>()`, so it doesn't
+    /// account for spare capacity, hash table overhead or heap memory owned
+    /// by the elements themselves.
+    pub fn add<T>(&mut self, name: &'static str, count: usize) {
+        self.entries.push(MemoryBreakdownEntry {
+            name,
+            count,
+            bytes: count * mem::size_of::<T>(),
+        });
+    }
+}
+
+#[derive(Serialize)]
+pub struct MemoryBreakdown {
+    kind: &'static str,
+    groups: Vec<MemoryBreakdownGroup>,
+}
+
+impl MemoryBreakdown {
+    pub fn new() -> Self {
+        MemoryBreakdown {
+            kind: "memory_breakdown",
+            groups: Vec::new(),
+        }
+    }
+
+    pub fn add(&mut self, group: MemoryBreakdownGroup) {
+        self.groups.push(group);
+    }
+}
+
 #[derive(Serialize)]
 pub struct RenderTaskList {
     kind: &'static str,
@@ -7,7 +7,7 @@ use hal::Device as BackendDevice;
 use rendy_memory::{Block, Heaps, MemoryBlock, MemoryUsageValue, Write};
 
 use std::cell::Cell;
-use std::mem;
+use std::{mem, slice};
 
 pub const MAX_INSTANCE_COUNT: usize = 8192;
 pub const TEXTURE_CACHE_SIZE: usize = 128 << 20; // 128MB
@@ -68,7 +68,7 @@ impl<B: hal::Backend> Buffer<B> {
         }
     }
 
-    fn update_all<T: Copy>(
+    pub(super) fn update_all<T: Copy>(
         &mut self,
         device: &B::Device,
         data: &[T],
@@ -114,6 +114,34 @@ impl<B: hal::Backend> Buffer<B> {
         size as usize
     }
 
+    /// Like `update`, but with the data already encoded as raw bytes. The
+    /// offset is still in units of the stride.
+    fn update_bytes(
+        &mut self,
+        device: &B::Device,
+        data: &[u8],
+        offset: usize,
+        non_coherent_atom_size_mask: u64,
+    ) -> usize {
+        assert_eq!(data.len() % self.stride, 0);
+        let offset = (offset * self.stride) as u64;
+        let size = data.len() as u64;
+        let range = offset
+            .. ((offset + size + non_coherent_atom_size_mask) & !non_coherent_atom_size_mask);
+        unsafe {
+            let mut mapped = self
+                .memory_block
+                .map(device, range)
+                .expect("Mapping memory block failed");
+            mapped
+                .write(device, 0 .. size)
+                .expect("Writer creation failed")
+                .write(data);
+        }
+        self.memory_block.unmap(device);
+        size as usize
+    }
+
     pub(super) fn transit(&self, access: hal::buffer::Access) -> Option<hal::memory::Barrier<B>> {
         let src_state = self.state.get();
         if src_state == access {
@@ -209,6 +237,10 @@ impl<B: hal::Backend> BufferPool<B> {
         &self.buffer
     }
 
+    pub(super) fn size_in_bytes(&self) -> usize {
+        self.buffer.buffer_size
+    }
+
     pub(super) fn reset(&mut self) {
         self.offset = 0;
         self.size = 0;
@@ -252,14 +284,14 @@ impl<B: hal::Backend> InstancePoolBuffer<B> {
         }
     }
 
-    fn update<T: Copy>(&mut self, device: &B::Device, data: &[T]) {
-        self.buffer.update(
+    fn update(&mut self, device: &B::Device, data: &[u8]) {
+        self.buffer.update_bytes(
             device,
             data,
             self.offset,
             self.non_coherent_atom_size_mask as u64,
         );
-        self.last_update_size = data.len();
+        self.last_update_size = data.len() / self.buffer.stride;
         self.offset += self.last_update_size;
     }
 
@@ -310,10 +342,25 @@ impl<B: hal::Backend> InstanceBufferHandler<B> {
     pub(super) fn add<T: Copy>(
         &mut self,
         device: &B::Device,
-        mut data: &[T],
+        data: &[T],
         heaps: &mut Heaps<B>,
     ) {
         assert_eq!(self.data_stride, mem::size_of::<T>());
+        let bytes = unsafe {
+            slice::from_raw_parts(data.as_ptr() as *const u8, data.len() * self.data_stride)
+        };
+        self.add_bytes(device, bytes, heaps);
+    }
+
+    /// Appends instances that are already encoded as raw bytes, e.g. when
+    /// replaying a recorded command stream.
+    pub(super) fn add_bytes(
+        &mut self,
+        device: &B::Device,
+        mut data: &[u8],
+        heaps: &mut Heaps<B>,
+    ) {
+        assert_eq!(data.len() % self.data_stride, 0);
         while !data.is_empty() {
             if self.current_buffer().buffer.buffer_size
                 == self.current_buffer().offset * self.data_stride
@@ -331,11 +378,12 @@ impl<B: hal::Backend> InstanceBufferHandler<B> {
                 }
             }
 
-            let update_size = if (self.current_buffer().offset + data.len()) * self.data_stride
+            let instance_count = data.len() / self.data_stride;
+            let update_size = if (self.current_buffer().offset + instance_count) * self.data_stride
                 > self.current_buffer().buffer.buffer_size
             {
-                self.current_buffer().buffer.buffer_size / self.data_stride
-                    - self.current_buffer().offset
+                (self.current_buffer().buffer.buffer_size / self.data_stride
+                    - self.current_buffer().offset) * self.data_stride
             } else {
                 data.len()
             };
@@ -350,6 +398,10 @@ impl<B: hal::Backend> InstanceBufferHandler<B> {
         &self.buffers[self.current_buffer_index]
     }
 
+    pub(super) fn size_in_bytes(&self) -> usize {
+        self.buffers.iter().map(|buffer| buffer.buffer.buffer_size).sum()
+    }
+
     pub(super) fn reset(&mut self) {
         for buffer in &mut self.buffers {
             buffer.reset();
@@ -357,6 +409,15 @@ impl<B: hal::Backend> InstanceBufferHandler<B> {
         self.current_buffer_index = 0;
     }
 
+    /// Frees all buffers but the first one, keeping the capacity of a
+    /// typical frame.
+    pub(super) fn trim(&mut self, device: &B::Device, heaps: &mut Heaps<B>) {
+        for buffer in self.buffers.drain(1 ..) {
+            buffer.deinit(device, heaps);
+        }
+        self.reset();
+    }
+
     pub(super) fn deinit(self, device: &B::Device, heaps: &mut Heaps<B>) {
         for buffer in self.buffers {
             buffer.deinit(device, heaps);
@@ -429,6 +490,10 @@ impl<B: hal::Backend> VertexBufferHandler<B> {
         &self.buffer
     }
 
+    pub(super) fn size_in_bytes(&self) -> usize {
+        self.buffer.buffer_size
+    }
+
     pub(super) fn reset(&mut self) {
         self.buffer_len = 0;
     }
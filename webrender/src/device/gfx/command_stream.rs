@@ -0,0 +1,131 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+//! Recording of the device-level commands submitted for a frame.
+//!
+//! The renderer can ask the device to record every state change, upload and
+//! draw it issues while rendering a frame. The resulting `CommandStream` can
+//! then be replayed on the same device without running frame building or
+//! batching again, which helps to tell apart issues in frame building from
+//! issues in device submission, and to write replay based microbenchmarks.
+//!
+//! The encoding doesn't contain any backend objects: resources are referred
+//! to by id, and blend and depth states by their WebRender level mode. As a
+//! consequence, a stream can only be replayed while the textures, render
+//! targets and programs it references are still alive.
+
+use api::{ColorF, DeviceIntRect, DeviceIntSize};
+use device::{FBOId, TextureFilter};
+use euclid::Transform3D;
+use std::ops::Range;
+use super::{ProgramId, TextureId};
+
+/// The blend modes of the `Device::set_blend_mode_*` methods.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum RecordedBlendMode {
+    Off,
+    Alpha,
+    PremultipliedAlpha,
+    PremultipliedDestOut,
+    Multiply,
+    Max,
+    Min,
+    SubpixelPass0,
+    SubpixelPass1,
+    SubpixelWithBgColorPass0,
+    SubpixelWithBgColorPass1,
+    SubpixelWithBgColorPass2,
+    SubpixelConstantTextColor(ColorF),
+    SubpixelDualSource,
+    ShowOverdraw,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum RecordedDepthMode {
+    Off,
+    Test,
+    TestAndWrite,
+}
+
+#[derive(Clone, Debug)]
+pub enum DeviceCommand {
+    BindDrawTarget {
+        fbo: FBOId,
+        /// The texture and layer backing `fbo`, if it isn't the main framebuffer.
+        texture: Option<(TextureId, usize)>,
+        dimensions: DeviceIntSize,
+        with_depth: bool,
+    },
+    BindReadTarget(FBOId),
+    BindProgram(ProgramId),
+    SetUniforms {
+        program: ProgramId,
+        projection: Transform3D<f32>,
+    },
+    SwitchMode(i32),
+    BindTexture {
+        slot: usize,
+        texture: TextureId,
+        filter: TextureFilter,
+    },
+    /// Writes the textures bound so far into the descriptor sets of the
+    /// bound program.
+    BindTextures,
+    SetBlendMode(RecordedBlendMode),
+    SetDepthMode(RecordedDepthMode),
+    /// The scissor rect, already in the space of the bound draw target.
+    SetScissor(Option<DeviceIntRect>),
+    /// Whether linearly filtered textures are sampled anisotropically.
+    SetAnisotropicSampling(bool),
+    /// Uploads the instances stored in the given byte range of
+    /// `CommandStream::instance_data` for the bound program.
+    UpdateInstances(Range<usize>),
+    Draw,
+    Clear {
+        color: Option<[f32; 4]>,
+        depth: Option<f32>,
+        rect: Option<DeviceIntRect>,
+    },
+    Blit {
+        src: DeviceIntRect,
+        dest: DeviceIntRect,
+    },
+}
+
+/// The commands of a recorded frame, along with the instance data they upload.
+#[derive(Clone, Debug, Default)]
+pub struct CommandStream {
+    pub commands: Vec<DeviceCommand>,
+    pub instance_data: Vec<u8>,
+}
+
+impl CommandStream {
+    pub fn new() -> Self {
+        CommandStream::default()
+    }
+
+    pub fn push(&mut self, command: DeviceCommand) {
+        self.commands.push(command);
+    }
+
+    pub fn push_instances(&mut self, data: &[u8]) {
+        let start = self.instance_data.len();
+        self.instance_data.extend_from_slice(data);
+        self.commands.push(DeviceCommand::UpdateInstances(start .. self.instance_data.len()));
+    }
+
+    pub fn draw_call_count(&self) -> usize {
+        self.commands
+            .iter()
+            .filter(|command| match **command {
+                DeviceCommand::Draw => true,
+                _ => false,
+            })
+            .count()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.commands.is_empty()
+    }
+}
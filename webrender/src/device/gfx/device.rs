@@ -10,36 +10,43 @@ use api::TextureTarget;
 use api::ImageDescriptor;
 use euclid::Transform3D;
 use internal_types::{FastHashMap, RenderTargetInfo};
+use util::{f16_to_f32, f32_to_f16};
 use rand::{self, Rng};
 use rendy_memory::{Block, Heaps, HeapsConfig, MemoryUsageValue};
 use ron::de::from_str;
 use smallvec::SmallVec;
-use std::cell::Cell;
+use std::borrow::Cow;
+use std::cmp;
+use std::cell::{Cell, RefCell};
 use std::convert::Into;
 use std::collections::hash_map::Entry;
-use std::fs::{File, OpenOptions};
+use std::fs::{self, File, OpenOptions};
 use std::hash::{Hash, Hasher};
 use std::io::prelude::*;
 use std::mem;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::rc::Rc;
 use std::slice;
+use time::precise_time_ns;
 
 use super::blend_state::*;
 use super::buffer::*;
 use super::command::*;
+use super::command_stream::{CommandStream, DeviceCommand, RecordedBlendMode, RecordedDepthMode};
 use super::descriptor::*;
 use super::image::*;
 use super::program::{Program, PUSH_CONSTANT_BLOCK_SIZE};
 use super::render_pass::*;
-use super::{PipelineRequirements, PrimitiveType, TextureId};
+use super::{vertex_types, PipelineRequirements, PrimitiveType, TextureId};
 use super::{LESS_EQUAL_TEST, LESS_EQUAL_WRITE};
 
 use super::super::Capabilities;
 use super::super::{ShaderKind, ExternalTexture, GpuFrameId, TextureSlot, TextureFilter};
-use super::super::{VertexDescriptor, UploadMethod, Texel, ReadPixelsFormat, TextureFlags};
+use super::super::{VertexDescriptor, UploadMethod, Texel, ReadPixelsFormat, ReadbackHandle, TextureFlags};
 use super::super::{Texture, DrawTarget, ReadTarget, FBOId, RBOId, VertexUsageHint, ShaderError, ShaderPrecacheFlags, SharedDepthTarget, ProgramCache};
+use super::super::{create_projection, flip_rows, GpuVendor, SurfaceOrigin, SurfaceTransform, Workarounds, DEPTH_TARGET_POOL_FRAMES};
 use super::super::{depth_target_size_in_bytes, record_gpu_alloc, record_gpu_free};
+use super::super::query::{GpuQueries, QueryCommand, MAX_TIMESTAMP_QUERIES};
 use super::super::super::shader_source;
 
 use hal;
@@ -62,6 +69,98 @@ const COLOR_RANGE: hal::image::SubresourceRange = hal::image::SubresourceRange {
     layers: 0 .. 1,
 };
 
+/// The time the device spent blocked on the swapchain and the frame fences
+/// during a frame, in nanoseconds.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct FrameTimings {
+    /// Waiting for the next swapchain image.
+    pub acquire_time: u64,
+    /// Presenting, which includes waiting for vsync with some present modes.
+    pub present_time: u64,
+    /// Waiting for the GPU to release the resources of an earlier frame.
+    pub fence_wait_time: u64,
+}
+
+/// The pixel format of the main framebuffer.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum OutputFormat {
+    /// 8-bit BGRA, storing the shader output as is. The output of WebRender
+    /// is already sRGB encoded, so this is right for ordinary surfaces.
+    Bgra8Unorm,
+    /// 8-bit BGRA, sRGB encoded by the hardware on write. For compositors
+    /// that treat the output as linear and decode it when sampling.
+    Bgra8Srgb,
+    /// 16-bit float RGBA, for wide color and HDR output, see `ColorSpace`.
+    Rgba16Float,
+}
+
+impl Default for OutputFormat {
+    fn default() -> Self {
+        OutputFormat::Bgra8Unorm
+    }
+}
+
+impl OutputFormat {
+    fn to_hal(self) -> hal::format::Format {
+        match self {
+            OutputFormat::Bgra8Unorm => hal::format::Format::Bgra8Unorm,
+            OutputFormat::Bgra8Srgb => hal::format::Format::Bgra8Srgb,
+            OutputFormat::Rgba16Float => hal::format::Format::Rgba16Sfloat,
+        }
+    }
+}
+
+/// The color space of the main framebuffer. Anything but `Srgb` requires
+/// `OutputFormat::Rgba16Float`. The frame is then rendered and blended in
+/// sRGB space into an RGBA16F target as usual, which is converted into the
+/// color space while compositing it into the swapchain image.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum ColorSpace {
+    /// sRGB encoded output, as with the 8-bit formats.
+    Srgb,
+    /// Linear sRGB primaries, 1.0 being SDR white (scRGB).
+    ScRgb,
+    /// BT.2020 primaries with the PQ transfer function (HDR10), SDR white
+    /// mapped to 203 nits.
+    Hdr10,
+}
+
+impl Default for ColorSpace {
+    fn default() -> Self {
+        ColorSpace::Srgb
+    }
+}
+
+impl ColorSpace {
+    /// The value of the `output_color_transform` specialization constant.
+    pub(super) fn output_transform(self) -> u8 {
+        match self {
+            ColorSpace::Srgb => 0,
+            ColorSpace::ScRgb => 1,
+            ColorSpace::Hdr10 => 2,
+        }
+    }
+}
+
+/// The image the main framebuffer renders into, which is the RGBA16F output
+/// target of the current frame if there is one. A macro, so that it only
+/// borrows the fields it reads.
+macro_rules! main_image {
+    ($device:expr) => {
+        match $device.output_targets.get($device.current_frame_id) {
+            Some(texture_id) => &$device.images[texture_id].core,
+            None => &$device.frame_images[$device.current_frame_id],
+        }
+    };
+}
+
+/// Everything the device is created from.
+///
+/// gfx-hal doesn't report how long a GPU timestamp tick is, so the GPU
+/// timer queries assume a period of one nanosecond. Embedders that show
+/// GPU times on devices with another period, e.g. the `timestampPeriod`
+/// limit of the Vulkan device, should pass it to
+/// `Device::set_timestamp_period` once the renderer is created.
 pub struct DeviceInit<B: hal::Backend> {
     pub instance: Box<hal::Instance<Backend = B>>,
     pub adapter: hal::Adapter<B>,
@@ -72,7 +171,27 @@ pub struct DeviceInit<B: hal::Backend> {
     pub save_cache: bool,
 }
 
+impl<B: hal::Backend> DeviceInit<B> {
+    /// Loads the pipeline cache from, and saves it to, a file in `dir`
+    /// named after the adapter, so caches of different GPUs can share it.
+    pub fn use_pipeline_cache_dir(&mut self, dir: &Path) {
+        if let Err(err) = fs::create_dir_all(dir) {
+            warn!("Failed to create the pipeline cache directory {:?}: {:?}", dir, err);
+            return;
+        }
+        let info = &self.adapter.info;
+        self.cache_path = Some(dir.join(format!(
+            "pipelines-{:04x}-{:04x}.bin",
+            info.vendor,
+            info.device,
+        )));
+        self.save_cache = true;
+    }
+}
+
 const DESCRIPTOR_COUNT: usize = 96;
+/// The number of draws per frame whose passed samples can be counted.
+const MAX_OCCLUSION_QUERIES_PER_FRAME: u32 = 1024;
 const DESCRIPTOR_SET_PER_FRAME: usize = 0;
 const DESCRIPTOR_SET_SAMPLER: usize = 1;
 const DESCRIPTOR_SET_PER_DRAW: usize = 2;
@@ -124,12 +243,9 @@ impl From<ShaderKind> for ShaderGroup {
 
 impl Texture {
     pub fn still_in_flight(&self, frame_id: GpuFrameId, frame_count: usize) -> bool {
-        for i in 0 .. frame_count {
-            if self.bound_in_frame.get() == GpuFrameId(frame_id.0 - i) {
-                return true;
-            }
-        }
-        false
+        frame_id
+            .frames_since(self.bound_in_frame.get())
+            .map_or(false, |age| age < frame_count)
     }
 }
 
@@ -203,6 +319,36 @@ impl DescriptorSetResources {
     }
 }
 
+/// A read back copied into a download buffer on the GPU timeline, along with
+/// what's needed to convert the pixels once the fence is signaled.
+struct PendingReadback<B: hal::Backend> {
+    rect: DeviceIntRect,
+    image_rect: DeviceIntRect,
+    bytes_per_pixel: i32,
+    fmt_mismatch: bool,
+    /// The pixels are half floats, read from an RGBA16F main framebuffer.
+    half_float: bool,
+    flip_y: bool,
+    swizzle: bool,
+    transform: SurfaceTransform,
+    buffer: Buffer<B>,
+    command_pool: hal::CommandPool<B, hal::Graphics>,
+    fence: B::Fence,
+}
+
+impl<B: hal::Backend> PendingReadback<B> {
+    fn size_in_bytes(&self) -> usize {
+        (self.bytes_per_pixel * self.rect.size.width * self.rect.size.height) as usize
+    }
+}
+
+#[derive(Default)]
+struct QueriesInFlight {
+    timestamps: Vec<u32>,
+    /// Occlusion query indices along with the sampler they count for.
+    occlusions: Vec<(u32, u32)>,
+}
+
 pub struct Device<B: hal::Backend> {
     pub device: B::Device,
     heaps: Heaps<B>,
@@ -221,20 +367,49 @@ pub struct Device<B: hal::Backend> {
     pub framebuffers_depth: Vec<B::Framebuffer>,
     frame_images: Vec<ImageCore<B>>,
     frame_depths: Vec<DepthBuffer<B>>,
+    /// The RGBA16F images the frames are rendered into instead of the
+    /// swapchain images if the color space isn't sRGB, one per swapchain
+    /// image. `framebuffers` and `framebuffers_depth` target these then.
+    output_targets: Vec<TextureId>,
+    /// The framebuffers of the swapchain images the output targets are
+    /// composited into.
+    output_framebuffers: Vec<B::Framebuffer>,
+    /// The program compositing the output targets, converting them into the
+    /// color space. `None` until first used.
+    output_program: Option<ProgramId>,
+    /// Whether `draw` targets the swapchain image for the composite.
+    compositing_output: bool,
     pub frame_count: usize,
     pub viewport: hal::pso::Viewport,
+    /// The rotation applied to the main framebuffer content. The viewport
+    /// is in the physical (rotated) space of the surface.
+    surface_transform: SurfaceTransform,
+    /// The origin of the main framebuffer coordinate space.
+    surface_origin: SurfaceOrigin,
     pub sampler_linear: B::Sampler,
     pub sampler_nearest: B::Sampler,
+    /// The linear sampler with anisotropic filtering, if it was requested and
+    /// the adapter supports it.
+    sampler_anisotropic: Option<B::Sampler>,
+    /// Whether linear samplers are replaced with `sampler_anisotropic`.
+    anisotropic_sampling: bool,
     pub current_frame_id: usize,
     current_blend_state: Cell<BlendState>,
     blend_color: Cell<ColorF>,
     current_depth_test: DepthTest,
     // device state
     programs: FastHashMap<ProgramId, Program<B>>,
+    /// The programs drawing the blits the backend can't perform, by the
+    /// format of the draw target. `None` if the program failed to build.
+    blit_programs: FastHashMap<ImageFormat, Option<ProgramId>>,
     shader_modules: FastHashMap<String, (B::ShaderModule, B::ShaderModule)>,
     images: FastHashMap<TextureId, Image<B>>,
     retained_textures: Vec<Texture>,
     fbos: FastHashMap<FBOId, Framebuffer<B>>,
+    /// Framebuffers whose depth target got detached, along with the frame
+    /// they were retired in. They are kept alive until no command buffer
+    /// in flight can reference them anymore.
+    retired_fbos: Vec<(GpuFrameId, FBOId)>,
     rbos: FastHashMap<RBOId, DepthBuffer<B>>,
     descriptor_pools_per_draw: SmallVec<[DescriptorPools<B>; 1]>,
     per_draw_descriptor_bindings: SmallVec<[FastHashMap<DescriptorSetResources, DescriptorSetLocation>; 1]>,
@@ -245,10 +420,27 @@ pub struct Device<B: hal::Backend> {
     bound_program: ProgramId,
     bound_sampler: [TextureFilter; 16],
     bound_read_texture: (TextureId, i32),
+    pending_readbacks: FastHashMap<usize, PendingReadback<B>>,
+    next_readback_id: usize,
     bound_read_fbo: FBOId,
     bound_draw_fbo: FBOId,
     program_mode_id: i32,
     scissor_rect: Option<DeviceIntRect>,
+    /// The stream the submitted commands are recorded into, if recording.
+    command_recorder: RefCell<Option<CommandStream>>,
+
+    // GPU queries requested by the profiler.
+    gpu_queries: Rc<RefCell<GpuQueries>>,
+    queries_supported: bool,
+    timestamp_pool: Option<B::QueryPool>,
+    /// The occlusion query pool, along with the number of frames it has
+    /// room for.
+    occlusion_pool: Option<(B::QueryPool, usize)>,
+    /// The sampler the samples passed by the draws are counted into.
+    active_sampler: Option<u32>,
+    /// The queries written in each frame, which are read back once the
+    /// frame's fence is signaled.
+    queries_in_flight: Vec<QueriesInFlight>,
     //default_read_fbo: FBOId,
     //default_draw_fbo: FBOId,
     device_pixel_ratio: f32,
@@ -262,6 +454,7 @@ pub struct Device<B: hal::Backend> {
 
     // HW or API capabilities
     capabilities: Capabilities,
+    workarounds: Workarounds,
 
     /// Map from texture dimensions to shared depth buffers for render targets.
     ///
@@ -282,7 +475,7 @@ pub struct Device<B: hal::Backend> {
     // frames and GPU frames.
     frame_id: GpuFrameId,
 
-    // Supported features
+    // Features enabled on the logical device
     features: hal::Features,
 
     next_id: usize,
@@ -295,6 +488,16 @@ pub struct Device<B: hal::Backend> {
     cache_path: Option<PathBuf>,
     save_cache: bool,
     wait_for_resize: bool,
+    /// The surface can't present anymore and has to be replaced.
+    surface_lost: bool,
+    /// The present mode requested by the embedder, if any.
+    present_mode: Option<hal::window::PresentMode>,
+    output_format: OutputFormat,
+    color_space: ColorSpace,
+    /// Whether the main framebuffer keeps its content between frames, so
+    /// that partial presents only have to redraw the damaged area.
+    enable_partial_present: bool,
+    frame_timings: FrameTimings,
 }
 
 impl<B: hal::Backend> Device<B> {
@@ -304,6 +507,11 @@ impl<B: hal::Backend> Device<B> {
         upload_method: UploadMethod,
         _cached_programs: Option<Rc<ProgramCache>>,
         heaps_config: HeapsConfig,
+        present_mode: Option<hal::window::PresentMode>,
+        output_format: OutputFormat,
+        color_space: ColorSpace,
+        max_anisotropy: u8,
+        enable_partial_present: bool,
     ) -> Self {
         let DeviceInit {
             instance,
@@ -315,7 +523,12 @@ impl<B: hal::Backend> Device<B> {
             save_cache,
         } = init;
         let renderer_name = "TODO renderer name".to_owned();
-        let features = adapter.physical_device.features();
+        let color_space = if color_space != ColorSpace::Srgb && output_format != OutputFormat::Rgba16Float {
+            warn!("Color space {:?} requires the Rgba16Float output format", color_space);
+            ColorSpace::Srgb
+        } else {
+            color_space
+        };
 
         let memory_properties = adapter.physical_device.memory_properties();
         let mut heaps = {
@@ -358,7 +571,7 @@ impl<B: hal::Backend> Device<B> {
         let limits = adapter.physical_device.limits();
         let max_texture_size = 4400i32; // TODO use limits after it points to the correct texture size
 
-        let (device, queue_group) = {
+        let (device, queue_group, features) = {
             use hal::Capability;
             use hal::queue::QueueFamily;
 
@@ -376,18 +589,24 @@ impl<B: hal::Backend> Device<B> {
 
             let priorities = vec![1.0];
             let (id, families) = (family.id(), [(family, priorities.as_slice())]);
-            let hal::Gpu { device, mut queues } = unsafe {
-                adapter
-                    .physical_device
-                    .open(&families, hal::Features::DUAL_SRC_BLENDING)
-                    .unwrap_or_else(|_| {
-                        adapter
+            // Only request the optional features the adapter has, and fall
+            // back to none of them if the device still fails to open.
+            let requested_features = adapter.physical_device.features() &
+                (hal::Features::DUAL_SRC_BLENDING | hal::Features::SAMPLER_ANISOTROPY);
+            let (hal::Gpu { device, mut queues }, features) = unsafe {
+                match adapter.physical_device.open(&families, requested_features) {
+                    Ok(gpu) => (gpu, requested_features),
+                    Err(err) => {
+                        warn!("Failed to open device with {:?}: {:?}", requested_features, err);
+                        let gpu = adapter
                             .physical_device
                             .open(&families, hal::Features::empty())
-                            .unwrap()
-                    })
+                            .unwrap();
+                        (gpu, hal::Features::empty())
+                    }
+                }
             };
-            (device, queues.take(id).unwrap())
+            (device, queues.take(id).unwrap(), features)
         };
 
         let (
@@ -421,6 +640,9 @@ impl<B: hal::Backend> Device<B> {
                     surface,
                     Some(window_size),
                     None,
+                    present_mode,
+                    output_format,
+                    enable_partial_present,
                 );
                 (
                     Some(swap_chain),
@@ -446,7 +668,12 @@ impl<B: hal::Backend> Device<B> {
                     frame_images,
                     viewport,
                     frame_count,
-                ) = Device::init_resources_without_surface(&device, &mut heaps, window_size);
+                ) = Device::init_resources_without_surface(
+                    &device,
+                    &mut heaps,
+                    window_size,
+                    enable_partial_present,
+                );
                 (
                     None,
                     surface_format,
@@ -479,6 +706,19 @@ impl<B: hal::Backend> Device<B> {
         }
         .expect("sampler_linear failed");
 
+        // 16 is the lowest maximum anisotropy an adapter supporting
+        // anisotropic sampling may report.
+        let sampler_anisotropic = if max_anisotropy > 1 && features.contains(hal::Features::SAMPLER_ANISOTROPY) {
+            let mut info = hal::image::SamplerInfo::new(
+                hal::image::Filter::Linear,
+                hal::image::WrapMode::Clamp,
+            );
+            info.anisotropic = hal::image::Anisotropic::On(max_anisotropy.min(16));
+            Some(unsafe { device.create_sampler(info) }.expect("sampler_anisotropic failed"))
+        } else {
+            None
+        };
+
         let pipeline_requirements: FastHashMap<String, PipelineRequirements> =
             from_str(&shader_source::PIPELINES).expect("Failed to load pipeline requirements");
 
@@ -582,7 +822,7 @@ impl<B: hal::Backend> Device<B> {
             None
         };
 
-        Device {
+        let mut device = Device {
             device,
             heaps,
             limits,
@@ -600,10 +840,18 @@ impl<B: hal::Backend> Device<B> {
             framebuffers_depth,
             frame_images,
             frame_depths,
+            output_targets: Vec::new(),
+            output_framebuffers: Vec::new(),
+            output_program: None,
+            compositing_output: false,
             frame_count,
             viewport,
+            surface_transform: SurfaceTransform::Identity,
+            surface_origin: SurfaceOrigin::native(),
             sampler_linear,
             sampler_nearest,
+            sampler_anisotropic,
+            anisotropic_sampling: false,
             current_frame_id: 0,
             current_blend_state: Cell::new(BlendState::Off),
             current_depth_test: DepthTest::Off,
@@ -618,14 +866,23 @@ impl<B: hal::Backend> Device<B> {
 
             capabilities: Capabilities {
                 supports_multisampling: false, //TODO
+                // Some GLES-class backends can't sample 32-bit float
+                // textures without extensions.
+                supports_float_textures: adapter.physical_device
+                    .format_properties(Some(hal::format::Format::Rgba32Sfloat))
+                    .optimal_tiling
+                    .contains(hal::format::ImageFeature::SAMPLED),
             },
+            workarounds: Workarounds::empty(),
             depth_targets: FastHashMap::default(),
 
             programs: FastHashMap::default(),
+            blit_programs: FastHashMap::default(),
             shader_modules: FastHashMap::default(),
             images: FastHashMap::default(),
             retained_textures: Vec::new(),
             fbos: FastHashMap::default(),
+            retired_fbos: Vec::new(),
             rbos: FastHashMap::default(),
             descriptor_pools_per_draw,
             per_draw_descriptor_bindings,
@@ -637,9 +894,18 @@ impl<B: hal::Backend> Device<B> {
             bound_sampler: [TextureFilter::Linear; 16],
             bound_read_fbo: DEFAULT_READ_FBO,
             bound_read_texture: (INVALID_TEXTURE_ID, 0),
+            pending_readbacks: FastHashMap::default(),
+            next_readback_id: 0,
             bound_draw_fbo: DEFAULT_DRAW_FBO,
             program_mode_id: 0,
             scissor_rect: None,
+            command_recorder: RefCell::new(None),
+            gpu_queries: GpuQueries::new(),
+            queries_supported: true,
+            timestamp_pool: None,
+            occlusion_pool: None,
+            active_sampler: None,
+            queries_in_flight: Vec::new(),
 
             max_texture_size,
             _renderer_name: renderer_name,
@@ -661,7 +927,15 @@ impl<B: hal::Backend> Device<B> {
             bound_locals: 0,
             locals_buffer,
             wait_for_resize: false,
-        }
+            surface_lost: false,
+            present_mode,
+            output_format,
+            color_space,
+            enable_partial_present,
+            frame_timings: FrameTimings::default(),
+        };
+        device.init_output_targets();
+        device
     }
 
     fn load_pipeline_cache(
@@ -699,10 +973,22 @@ impl<B: hal::Backend> Device<B> {
     pub(crate) fn recreate_swapchain(&mut self, window_size: Option<(i32, i32)>) -> DeviceIntSize {
         self.device.wait_idle().unwrap();
 
+        // The swapchain is created in the native orientation of the surface.
+        let window_size = window_size.map(|(width, height)| {
+            if self.surface_transform.is_transposed() {
+                (height, width)
+            } else {
+                (width, height)
+            }
+        });
+
         for (_id, program) in self.programs.drain() {
             program.deinit(&self.device, &mut self.heaps);
         }
+        self.blit_programs.clear();
+        self.output_program = None;
 
+        self.deinit_output_targets();
         for image in self.frame_images.drain(..) {
             image.deinit(&self.device, &mut self.heaps);
         }
@@ -782,6 +1068,9 @@ impl<B: hal::Backend> Device<B> {
                 surface,
                 window_size,
                 self.swap_chain.take(),
+                self.present_mode,
+                self.output_format,
+                self.enable_partial_present,
             );
             (
                 Some(swap_chain),
@@ -806,7 +1095,12 @@ impl<B: hal::Backend> Device<B> {
                 frame_images,
                 viewport,
                 frame_count,
-            ) = Device::init_resources_without_surface(&self.device, &mut self.heaps, window_size.unwrap_or((0,0)));
+            ) = Device::init_resources_without_surface(
+                &self.device,
+                &mut self.heaps,
+                window_size.unwrap_or((0,0)),
+                self.enable_partial_present,
+            );
             (
                 None,
                 surface_format,
@@ -831,6 +1125,7 @@ impl<B: hal::Backend> Device<B> {
         self.surface_format = surface_format;
         self.depth_format = depth_format;
         self.wait_for_resize = false;
+        self.init_output_targets();
 
         let pipeline_cache = unsafe { self.device.create_pipeline_cache(None) }
             .expect("Failed to create pipeline cache");
@@ -844,7 +1139,86 @@ impl<B: hal::Backend> Device<B> {
         } else {
             self.pipeline_cache = Some(pipeline_cache);
         }
-        DeviceIntSize::new(self.viewport.rect.w.into(), self.viewport.rect.h.into())
+        self.framebuffer_size()
+    }
+
+    /// Swaps in a new surface after the previous one was lost. The swapchain
+    /// of the old surface is destroyed, `recreate_swapchain` builds the new one.
+    pub(crate) fn replace_surface(&mut self, surface: B::Surface) {
+        self.device.wait_idle().unwrap();
+        if let Some(swap_chain) = self.swap_chain.take() {
+            unsafe { self.device.destroy_swapchain(swap_chain) };
+        }
+        self.surface = Some(surface);
+        self.surface_lost = false;
+        self.wait_for_resize = true;
+    }
+
+    /// Releases the swapchain and the surface, e.g. before an Android surface
+    /// is destroyed, along with the unused depth targets. Frames are skipped
+    /// until a new surface is set with `replace_surface`.
+    pub(crate) fn suspend(&mut self) {
+        self.device.wait_idle().unwrap();
+        if let Some(swap_chain) = self.swap_chain.take() {
+            unsafe { self.device.destroy_swapchain(swap_chain) };
+        }
+        self.surface = None;
+        self.wait_for_resize = true;
+        self.trim_depth_targets(0);
+    }
+
+    /// Requests a present mode for the swapchain, falling back to the best
+    /// supported one. Takes effect on the next `recreate_swapchain`.
+    pub fn set_present_mode(&mut self, present_mode: Option<hal::window::PresentMode>) {
+        if self.present_mode != present_mode {
+            self.present_mode = present_mode;
+            self.wait_for_resize = true;
+        }
+    }
+
+    /// Whether frames are skipped until the swapchain is recreated.
+    pub fn needs_resize(&self) -> bool {
+        self.wait_for_resize
+    }
+
+    pub fn is_surface_lost(&self) -> bool {
+        self.surface_lost
+    }
+
+    /// Returns the logical size of the main framebuffer, before applying the surface transform.
+    pub fn framebuffer_size(&self) -> DeviceIntSize {
+        self.surface_transform.transform_size(
+            DeviceIntSize::new(self.viewport.rect.w.into(), self.viewport.rect.h.into())
+        )
+    }
+
+    pub fn surface_transform(&self) -> SurfaceTransform {
+        self.surface_transform
+    }
+
+    /// Sets the rotation of the main framebuffer content. hal doesn't expose the
+    /// current transform of the surface, so this has to come from the embedder.
+    /// The swapchain is resized accordingly on the next `recreate_swapchain`.
+    pub fn set_surface_transform(&mut self, transform: SurfaceTransform) {
+        if self.surface_transform != transform {
+            self.surface_transform = transform;
+            self.wait_for_resize = true;
+        }
+    }
+
+    pub fn surface_origin(&self) -> SurfaceOrigin {
+        self.surface_origin
+    }
+
+    /// Sets the origin of the main framebuffer, which the renderer consults
+    /// for projections, scissors, blits and read backs targeting it.
+    pub fn set_surface_origin(&mut self, origin: SurfaceOrigin) {
+        self.surface_origin = origin;
+    }
+
+    /// Maps a rectangle of the main framebuffer into the physical space of the surface.
+    fn transform_framebuffer_rect(&self, rect: DeviceIntRect) -> DeviceIntRect {
+        self.surface_transform.transform_rect(rect, self.framebuffer_size())
     }
 
     fn init_swapchain_resources(
@@ -854,6 +1228,9 @@ impl<B: hal::Backend> Device<B> {
         surface: &mut B::Surface,
         window_size: Option<(i32, i32)>,
         old_swap_chain: Option<B::Swapchain>,
+        preferred_present_mode: Option<hal::window::PresentMode>,
+        output_format: OutputFormat,
+        enable_partial_present: bool,
     ) -> (
         B::Swapchain,
         ImageFormat,
@@ -867,15 +1244,26 @@ impl<B: hal::Backend> Device<B> {
         usize,
     ) {
         let (caps, formats, present_modes) = surface.compatibility(&adapter.physical_device);
-        let present_mode = {
-            use hal::window::PresentMode::*;
-            [Mailbox, Fifo, Relaxed, Immediate]
-                .iter()
-                .cloned()
-                .find(|pm| present_modes.contains(pm))
-                .expect("No PresentMode values specified!")
+        let present_mode = match preferred_present_mode {
+            Some(mode) if present_modes.contains(&mode) => mode,
+            _ => {
+                if let Some(mode) = preferred_present_mode {
+                    warn!("Present mode {:?} is not supported by the surface", mode);
+                }
+                use hal::window::PresentMode::*;
+                [Mailbox, Fifo, Relaxed, Immediate]
+                    .iter()
+                    .cloned()
+                    .find(|pm| present_modes.contains(pm))
+                    .expect("No PresentMode values specified!")
+            }
         };
-        let surface_format = formats.map_or(hal::format::Format::Bgra8Unorm, |formats| {
+        let preferred_format = output_format.to_hal();
+        let surface_format = formats.map_or(preferred_format, |formats| {
+            if formats.contains(&preferred_format) {
+                return preferred_format;
+            }
+            warn!("Output format {:?} is not supported by the surface", output_format);
             formats
                 .into_iter()
                 .find(|format| format == &hal::format::Format::Bgra8Unorm)
@@ -922,10 +1310,18 @@ impl<B: hal::Backend> Device<B> {
                 .expect("create_swapchain failed");
         let depth_format = hal::format::Format::D32Sfloat; //maybe d24s8?
 
-        let render_pass = Device::create_render_passes(device, surface_format, depth_format);
+        let render_pass = Device::create_render_passes(
+            device,
+            surface_format,
+            depth_format,
+            enable_partial_present,
+        );
 
+        // The offscreen color targets are BGRA8 regardless of the output format.
         let image_format = match surface_format {
-            hal::format::Format::Bgra8Unorm => ImageFormat::BGRA8,
+            hal::format::Format::Bgra8Unorm |
+            hal::format::Format::Bgra8Srgb |
+            hal::format::Format::Rgba16Sfloat => ImageFormat::BGRA8,
             f => unimplemented!("Unsupported surface format: {:?}", f),
         };
         let mut frame_depths = Vec::new();
@@ -960,7 +1356,7 @@ impl<B: hal::Backend> Device<B> {
                     .map(|core| {
                         unsafe {
                             device.create_framebuffer(
-                                render_pass.get_render_pass(image_format, false),
+                                render_pass.get_main_render_pass(false),
                                 Some(&core.view),
                                 extent,
                             )
@@ -974,7 +1370,7 @@ impl<B: hal::Backend> Device<B> {
                     .map(|(core, depth)| {
                         unsafe {
                             device.create_framebuffer(
-                                render_pass.get_render_pass(image_format, true),
+                                render_pass.get_main_render_pass(true),
                                 Some(&core.view).into_iter().chain(Some(&depth.core.view)),
                                 extent,
                             )
@@ -1019,6 +1415,7 @@ impl<B: hal::Backend> Device<B> {
         device: &B::Device,
         heaps: &mut Heaps<B>,
         window_size: (i32, i32),
+        enable_partial_present: bool,
     ) -> (
         ImageFormat,
         hal::format::Format,
@@ -1036,6 +1433,7 @@ impl<B: hal::Backend> Device<B> {
             device,
             hal::format::Format::Bgra8Unorm,
             depth_format,
+            enable_partial_present,
         );
 
         let extent = hal::image::Extent {
@@ -1084,7 +1482,7 @@ impl<B: hal::Backend> Device<B> {
                 .map(|core| {
                     unsafe {
                         device.create_framebuffer(
-                            &render_pass.bgra8,
+                            &render_pass.main,
                             Some(&core.view),
                             extent,
                         )
@@ -1098,7 +1496,7 @@ impl<B: hal::Backend> Device<B> {
                 .map(|(core, depth)| {
                     unsafe {
                         device.create_framebuffer(
-                            &render_pass.bgra8_depth,
+                            &render_pass.main_depth,
                             vec![&core.view, &depth.core.view],
                             extent,
                         )
@@ -1131,10 +1529,87 @@ impl<B: hal::Backend> Device<B> {
         )
     }
 
+    /// Creates the RGBA16F output targets for a color space other than sRGB,
+    /// and points the main framebuffers at them. The framebuffers of the
+    /// swapchain images are kept for `composite_output`.
+    fn init_output_targets(&mut self) {
+        if self.color_space == ColorSpace::Srgb || self.swap_chain.is_none() {
+            return;
+        }
+        if self.render_pass.as_ref().unwrap().main_format != hal::format::Format::Rgba16Sfloat {
+            warn!("Color space {:?} needs an Rgba16Float surface", self.color_space);
+            self.color_space = ColorSpace::Srgb;
+            return;
+        }
+
+        let (width, height) = (self.viewport.rect.w as i32, self.viewport.rect.h as i32);
+        let extent = hal::image::Extent {
+            width: width as _,
+            height: height as _,
+            depth: 1,
+        };
+        for i in 0 .. self.frame_images.len() {
+            let image = Image::new(
+                &self.device,
+                &mut self.heaps,
+                ImageFormat::RGBAF16,
+                width,
+                height,
+                1,
+                hal::image::ViewKind::D2Array,
+                1,
+                hal::image::Usage::TRANSFER_SRC
+                    | hal::image::Usage::TRANSFER_DST
+                    | hal::image::Usage::SAMPLED
+                    | hal::image::Usage::COLOR_ATTACHMENT,
+            );
+            let render_pass = self.render_pass.as_ref().unwrap();
+            let (framebuffer, framebuffer_depth) = unsafe {
+                (
+                    self.device.create_framebuffer(
+                        render_pass.get_main_render_pass(false),
+                        Some(&image.core.view),
+                        extent,
+                    ),
+                    self.device.create_framebuffer(
+                        render_pass.get_main_render_pass(true),
+                        Some(&image.core.view).into_iter().chain(Some(&self.frame_depths[i].core.view)),
+                        extent,
+                    ),
+                )
+            };
+            let framebuffer_depth = mem::replace(
+                &mut self.framebuffers_depth[i],
+                framebuffer_depth.expect("create_framebuffer failed"),
+            );
+            unsafe { self.device.destroy_framebuffer(framebuffer_depth) };
+            let framebuffer = mem::replace(
+                &mut self.framebuffers[i],
+                framebuffer.expect("create_framebuffer failed"),
+            );
+            self.output_framebuffers.push(framebuffer);
+
+            let texture_id = self.generate_texture_id();
+            self.images.insert(texture_id, image);
+            self.output_targets.push(texture_id);
+        }
+    }
+
+    fn deinit_output_targets(&mut self) {
+        for framebuffer in self.output_framebuffers.drain(..) {
+            unsafe { self.device.destroy_framebuffer(framebuffer) };
+        }
+        for texture_id in self.output_targets.drain(..) {
+            let image = self.images.remove(&texture_id).unwrap();
+            image.deinit(&self.device, &mut self.heaps);
+        }
+    }
+
     fn create_render_passes(
         device: &<B as hal::Backend>::Device,
-        surface_format: hal::format::Format,
+        main_format: hal::format::Format,
         depth_format: hal::format::Format,
+        enable_partial_present: bool,
     ) -> RenderPass<B> {
         let attachment_r8 = hal::pass::Attachment {
             format: Some(hal::format::Format::R8Unorm),
@@ -1148,8 +1623,13 @@ impl<B: hal::Backend> Device<B> {
                 .. hal::image::Layout::ColorAttachmentOptimal,
         };
 
+        let attachment_rg8 = hal::pass::Attachment {
+            format: Some(hal::format::Format::Rg8Unorm),
+            .. attachment_r8.clone()
+        };
+
         let attachment_bgra8 = hal::pass::Attachment {
-            format: Some(surface_format),
+            format: Some(hal::format::Format::Bgra8Unorm),
             samples: 1,
             ops: hal::pass::AttachmentOps::new(
                 hal::pass::AttachmentLoadOp::DontCare,
@@ -1160,6 +1640,21 @@ impl<B: hal::Backend> Device<B> {
                 .. hal::image::Layout::ColorAttachmentOptimal,
         };
 
+        // With partial present the main framebuffer keeps its content
+        // between frames, so that only the damaged area has to be redrawn.
+        let attachment_main = hal::pass::Attachment {
+            format: Some(main_format),
+            ops: hal::pass::AttachmentOps::new(
+                if enable_partial_present {
+                    hal::pass::AttachmentLoadOp::Load
+                } else {
+                    hal::pass::AttachmentLoadOp::DontCare
+                },
+                hal::pass::AttachmentStoreOp::Store,
+            ),
+            .. attachment_bgra8.clone()
+        };
+
         let attachment_depth = hal::pass::Attachment {
             format: Some(depth_format),
             samples: 1,
@@ -1226,7 +1721,7 @@ impl<B: hal::Backend> Device<B> {
             r8: unsafe {
                 device.create_render_pass(
                     iter::once(&attachment_r8),
-                    &[subpass_r8],
+                    &[subpass_r8.clone()],
                     iter::once(&dependency),
                 )
             }
@@ -1234,6 +1729,22 @@ impl<B: hal::Backend> Device<B> {
             r8_depth: unsafe {
                 device.create_render_pass(
                     iter::once(&attachment_r8).chain(iter::once(&attachment_depth)),
+                    &[subpass_depth_r8.clone()],
+                    iter::once(&dependency).chain(iter::once(&depth_dependency)),
+                )
+            }
+            .expect("create_render_pass failed"),
+            rg8: unsafe {
+                device.create_render_pass(
+                    iter::once(&attachment_rg8),
+                    &[subpass_r8],
+                    iter::once(&dependency),
+                )
+            }
+            .expect("create_render_pass failed"),
+            rg8_depth: unsafe {
+                device.create_render_pass(
+                    iter::once(&attachment_rg8).chain(iter::once(&attachment_depth)),
                     &[subpass_depth_r8],
                     iter::once(&dependency).chain(iter::once(&depth_dependency)),
                 )
@@ -1242,19 +1753,36 @@ impl<B: hal::Backend> Device<B> {
             bgra8: unsafe {
                 device.create_render_pass(
                     iter::once(&attachment_bgra8),
-                    &[subpass_bgra8],
+                    &[subpass_bgra8.clone()],
                     iter::once(&dependency),
                 )
             }
             .expect("create_render_pass failed"),
             bgra8_depth: unsafe {
                 device.create_render_pass(
-                    &[attachment_bgra8, attachment_depth],
+                    &[attachment_bgra8, attachment_depth.clone()],
+                    &[subpass_depth_bgra8.clone()],
+                    &[dependency.clone(), depth_dependency.clone()],
+                )
+            }
+            .expect("create_render_pass failed"),
+            main: unsafe {
+                device.create_render_pass(
+                    iter::once(&attachment_main),
+                    &[subpass_bgra8],
+                    iter::once(&dependency),
+                )
+            }
+            .expect("create_render_pass failed"),
+            main_depth: unsafe {
+                device.create_render_pass(
+                    &[attachment_main, attachment_depth],
                     &[subpass_depth_bgra8],
                     &[dependency, depth_dependency],
                 )
             }
             .expect("create_render_pass failed"),
+            main_format,
         }
     }
 
@@ -1285,7 +1813,47 @@ impl<B: hal::Backend> Device<B> {
 
     /// Returns the limit on texture array layers.
     pub fn max_texture_layers(&self) -> usize {
-        self.limits.max_image_array_layers as usize
+        let max_layers = self.limits.max_image_array_layers as usize;
+        if self.workarounds.contains(Workarounds::LIMIT_TEXTURE_ARRAY_LAYERS) {
+            max_layers.min(Workarounds::MAX_LIMITED_TEXTURE_LAYERS)
+        } else {
+            max_layers
+        }
+    }
+
+    /// Returns the vendor of the adapter we are rendering with.
+    pub fn gpu_vendor(&self) -> GpuVendor {
+        GpuVendor::from_pci_id(self.adapter.info.vendor)
+    }
+
+    /// Returns the vendor-specific id of the adapter we are rendering with.
+    pub fn gpu_device_id(&self) -> Option<usize> {
+        Some(self.adapter.info.device)
+    }
+
+    /// Describes the adapter we are rendering with, for bug reports.
+    pub fn describe_adapter(&self) -> String {
+        let info = &self.adapter.info;
+        format!(
+            "{} ({:?}, vendor 0x{:04x}, device 0x{:04x})",
+            info.name,
+            info.device_type,
+            info.vendor,
+            info.device,
+        )
+    }
+
+    /// Describes the optional features enabled on the device, for bug reports.
+    pub fn describe_features(&self) -> String {
+        format!("{:?}", self.features)
+    }
+
+    pub fn workarounds(&self) -> Workarounds {
+        self.workarounds
+    }
+
+    pub fn set_workarounds(&mut self, workarounds: Workarounds) {
+        self.workarounds = workarounds;
     }
 
     pub fn get_capabilities(&self) -> &Capabilities {
@@ -1296,11 +1864,13 @@ impl<B: hal::Backend> Device<B> {
         self.next_id = (self.next_id + 1) % self.frame_count;
         self.reset_state();
         if self.frame_fence[self.next_id].is_submitted {
+            let wait_start = precise_time_ns();
             unsafe {
                 self.device
                     .wait_for_fence(&self.frame_fence[self.next_id].inner, !0)
             }
             .expect("wait_for_fence failed");
+            self.frame_timings.fence_wait_time += precise_time_ns() - wait_start;
             unsafe {
                 self.device
                     .reset_fence(&self.frame_fence[self.next_id].inner)
@@ -1308,6 +1878,7 @@ impl<B: hal::Backend> Device<B> {
             .expect("reset_fence failed");
             self.frame_fence[self.next_id].is_submitted = false;
         }
+        self.resolve_queries();
         unsafe {
             self.command_pool[self.next_id].reset();
         }
@@ -1316,6 +1887,8 @@ impl<B: hal::Backend> Device<B> {
         self.descriptor_pools_per_frame[self.next_id].reset(&self.device);
         self.reset_program_buffer_offsets();
         self.delete_retained_textures();
+        self.delete_retired_fbos();
+        self.trim_depth_targets(cmp::max(DEPTH_TARGET_POOL_FRAMES, self.frame_count));
     }
 
     pub fn reset_state(&mut self) {
@@ -1367,6 +1940,34 @@ impl<B: hal::Backend> Device<B> {
         shader_name: &str,
         shader_kind: &ShaderKind,
         features: &[&str],
+    ) -> Result<ProgramId, ShaderError> {
+        self.create_program_with_output_transform(shader_name, shader_kind, features, 0)
+    }
+
+    /// Runs `f` as if the pipeline requirements of `shader_name` were missing
+    /// from the shader pack, for testing how missing shaders are handled.
+    #[doc(hidden)]
+    pub fn without_pipeline_requirements<R, F: FnOnce(&mut Self) -> R>(
+        &mut self,
+        shader_name: &str,
+        f: F,
+    ) -> R {
+        let requirements = self.pipeline_requirements.remove(shader_name);
+        let result = f(self);
+        if let Some(requirements) = requirements {
+            self.pipeline_requirements.insert(shader_name.to_owned(), requirements);
+        }
+        result
+    }
+
+    /// Creates a program whose main framebuffer pipelines convert their
+    /// output with `output_transform`, see `ColorSpace::output_transform`.
+    fn create_program_with_output_transform(
+        &mut self,
+        shader_name: &str,
+        shader_kind: &ShaderKind,
+        features: &[&str],
+        output_transform: u8,
     ) -> Result<ProgramId, ShaderError> {
         use std::iter;
         let mut name = String::from(shader_name);
@@ -1378,6 +1979,18 @@ impl<B: hal::Backend> Device<B> {
             }
         }
 
+        // A stripped-down shader pack might not contain every shader, so report the
+        // missing entry to the caller instead of bringing the whole renderer down.
+        let pipeline_requirements = match self.pipeline_requirements.get(&name) {
+            Some(requirements) => requirements.clone(),
+            None => {
+                return Err(ShaderError::Link(
+                    name,
+                    "Can't load pipeline data: missing pipeline requirements entry".to_owned(),
+                ));
+            }
+        };
+
         let shader_group = ShaderGroup::from(*shader_kind);
         if let Entry::Vacant(v) = self.pipeline_layouts.entry(*shader_kind) {
             let layout = unsafe {
@@ -1397,10 +2010,7 @@ impl<B: hal::Backend> Device<B> {
             v.insert(layout);
         };
         let program = Program::create(
-            self.pipeline_requirements
-                .get(&name)
-                .expect(&format!("Can't load pipeline data for: {}!", name))
-                .clone(),
+            pipeline_requirements,
             &self.device,
             &self.pipeline_layouts[shader_kind],
             &mut self.heaps,
@@ -1413,6 +2023,7 @@ impl<B: hal::Backend> Device<B> {
             &mut self.shader_modules,
             self.pipeline_cache.as_ref(),
             self.surface_format,
+            output_transform,
         );
 
         let id = self.generate_program_id();
@@ -1432,8 +2043,10 @@ impl<B: hal::Backend> Device<B> {
 
     pub fn bind_program(&mut self, program_id: &ProgramId) {
         debug_assert!(self.inside_frame);
+        self.record(DeviceCommand::BindProgram(*program_id));
 
         if self.bound_program != *program_id {
+            device_log!(binds, Trace, "program {:?}", program_id);
             self.bound_program = *program_id;
         }
     }
@@ -1442,6 +2055,10 @@ impl<B: hal::Backend> Device<B> {
         debug_assert!(self.inside_frame);
         assert_ne!(self.bound_program, INVALID_PROGRAM_ID);
         assert_eq!(*program_id, self.bound_program);
+        self.record(DeviceCommand::SetUniforms {
+            program: *program_id,
+            projection: *projection,
+        });
 
         if cfg!(feature = "push_constants") {
             self.programs
@@ -1486,6 +2103,7 @@ impl<B: hal::Backend> Device<B> {
     pub fn bind_textures(&mut self) {
         debug_assert!(self.inside_frame);
         assert_ne!(self.bound_program, INVALID_PROGRAM_ID);
+        self.record(DeviceCommand::BindTextures);
         let program = self
             .programs
             .get_mut(&self.bound_program)
@@ -1571,7 +2189,10 @@ impl<B: hal::Backend> Device<B> {
         let (desc_set, _) = self.descriptor_pools_sampler[self.next_id].get_set_by_group(shader_group);
         for &(index, sampler_name) in SAMPLERS.iter() {
             let sampler = match self.bound_sampler[index] {
-                TextureFilter::Linear | TextureFilter::Trilinear => &self.sampler_linear,
+                TextureFilter::Linear | TextureFilter::Trilinear => match self.sampler_anisotropic {
+                    Some(ref sampler) if self.anisotropic_sampling => sampler,
+                    _ => &self.sampler_linear,
+                },
                 TextureFilter::Nearest => &self.sampler_nearest,
             };
             program.bind_sampler(&self.device, desc_set, &sampler, sampler_name);
@@ -1611,6 +2232,14 @@ impl<B: hal::Backend> Device<B> {
 
     fn update_instances<T: Copy>(&mut self, instances: &[T]) {
         assert_ne!(self.bound_program, INVALID_PROGRAM_ID);
+        if let Some(ref mut stream) = *self.command_recorder.borrow_mut() {
+            stream.push_instances(unsafe {
+                slice::from_raw_parts(
+                    instances.as_ptr() as *const u8,
+                    instances.len() * mem::size_of::<T>(),
+                )
+            });
+        }
         self.programs
             .get_mut(&self.bound_program)
             .expect("Program not found.")
@@ -1618,7 +2247,11 @@ impl<B: hal::Backend> Device<B> {
     }
 
     fn draw(&mut self) {
-        let (img, frame_buffer, format, (depth_img, depth_test_changed)) = if self.bound_draw_fbo != DEFAULT_DRAW_FBO {
+        self.record(DeviceCommand::Draw);
+        self.flush_queries();
+        let occlusion_query = self.next_occlusion_query();
+        let main_framebuffer = self.compositing_output || self.bound_draw_fbo == DEFAULT_DRAW_FBO;
+        let (img, frame_buffer, format, (depth_img, depth_test_changed)) = if !main_framebuffer {
             let texture_id = self.fbos[&self.bound_draw_fbo].texture_id;
             let rbo_id = self.fbos[&self.bound_draw_fbo].rbo;
             (
@@ -1637,23 +2270,30 @@ impl<B: hal::Backend> Device<B> {
                     (Some(&self.rbos[&rbo_id].core), depth_test_changed)
                 },
             )
+        } else if self.compositing_output {
+            (
+                &self.frame_images[self.current_frame_id],
+                &self.output_framebuffers[self.current_frame_id],
+                self.surface_format,
+                (None, false)
+            )
         } else {
             let (frame_buffer, depth_image) = match self.current_depth_test {
                 DepthTest::Off => (&self.framebuffers[self.current_frame_id], None),
                 _ => (&self.framebuffers_depth[self.current_frame_id], Some(&self.frame_depths[self.current_frame_id].core)),
             };
             (
-                &self.frame_images[self.current_frame_id],
+                main_image!(self),
                 frame_buffer,
                 self.surface_format,
                 (depth_image, false)
             )
         };
-        let rp = self
-            .render_pass
-            .as_ref()
-            .unwrap()
-            .get_render_pass(format, depth_img.is_some());
+        let rp = if main_framebuffer {
+            self.render_pass.as_ref().unwrap().get_main_render_pass(depth_img.is_some())
+        } else {
+            self.render_pass.as_ref().unwrap().get_render_pass(format, depth_img.is_some())
+        };
 
         let before_state = img.state.get();
         let mut before_depth_state = None;
@@ -1701,6 +2341,16 @@ impl<B: hal::Backend> Device<B> {
         };
         let bound_locals = self.bound_locals;
 
+        if let (Some(id), Some(&(ref pool, _))) = (occlusion_query, self.occlusion_pool.as_ref()) {
+            unsafe {
+                cmd_buffer.reset_query_pool(pool, id .. id + 1);
+                cmd_buffer.begin_query(
+                    hal::query::Query { pool, id },
+                    hal::query::ControlFlags::empty(),
+                );
+            }
+        }
+
         self.programs
             .get_mut(&self.bound_program)
             .expect("Program not found")
@@ -1717,6 +2367,7 @@ impl<B: hal::Backend> Device<B> {
                 self.current_blend_state.get(),
                 self.blend_color.get(),
                 self.current_depth_test,
+                main_framebuffer,
                 self.scissor_rect,
                 self.next_id,
                 self.program_mode_id as u32,
@@ -1729,6 +2380,12 @@ impl<B: hal::Backend> Device<B> {
             self.current_depth_test = DepthTest::Off;
         }
 
+        if let (Some(id), Some(&(ref pool, _))) = (occlusion_query, self.occlusion_pool.as_ref()) {
+            unsafe {
+                cmd_buffer.end_query(hal::query::Query { pool, id });
+            }
+        }
+
         unsafe {
             if let Some(barrier) = img.transit(
                 before_state.0,
@@ -1775,8 +2432,14 @@ impl<B: hal::Backend> Device<B> {
 
     fn bind_texture_impl(&mut self, slot: TextureSlot, id: TextureId, sampler: TextureFilter) {
         debug_assert!(self.inside_frame);
+        self.record(DeviceCommand::BindTexture {
+            slot: slot.0,
+            texture: id,
+            filter: sampler,
+        });
 
         if self.bound_textures[slot.0] != id {
+            device_log!(binds, Trace, "slot {} <- texture {} ({:?})", slot.0, id, sampler);
             self.bound_textures[slot.0] = id;
             self.bound_sampler[slot.0] = sampler;
         }
@@ -1799,7 +2462,9 @@ impl<B: hal::Backend> Device<B> {
 
     pub fn bind_read_target_impl(&mut self, fbo_id: FBOId) {
         debug_assert!(self.inside_frame);
+        self.record(DeviceCommand::BindReadTarget(fbo_id));
         if self.bound_read_fbo != fbo_id {
+            device_log!(targets, Debug, "read target {:?}", fbo_id);
             self.bound_read_fbo = fbo_id;
         }
     }
@@ -1835,8 +2500,10 @@ impl<B: hal::Backend> Device<B> {
     }
 
     pub fn bind_draw_target(&mut self, texture_target: DrawTarget) {
-        let (fbo_id, dimensions, depth_available) = match texture_target {
-            DrawTarget::Default(dim) => (DEFAULT_DRAW_FBO, dim, true),
+        match texture_target {
+            DrawTarget::Default(dim) => {
+                self.bind_draw_target_fbo(DEFAULT_DRAW_FBO, None, dim, true)
+            }
             DrawTarget::Texture {
                 texture,
                 layer,
@@ -1848,33 +2515,59 @@ impl<B: hal::Backend> Device<B> {
                 } else {
                     texture.fbos[layer]
                 };
+                self.bind_draw_target_fbo(
+                    fbo_id,
+                    Some((texture.id, layer)),
+                    texture.get_dimensions(),
+                    with_depth,
+                )
+            }
+        }
+    }
+
+    fn bind_draw_target_fbo(
+        &mut self,
+        fbo_id: FBOId,
+        texture: Option<(TextureId, usize)>,
+        dimensions: DeviceIntSize,
+        depth_available: bool,
+    ) {
+        self.record(DeviceCommand::BindDrawTarget {
+            fbo: fbo_id,
+            texture,
+            dimensions,
+            with_depth: depth_available,
+        });
+        device_log!(
+            targets, Debug,
+            "draw target {:?} texture {:?} {:?} depth {}",
+            fbo_id, texture, dimensions, depth_available
+        );
 
-                let fbo = self.fbos.get_mut(&fbo_id).unwrap();
-                fbo.layer_index = layer as u16;
+        if let Some((texture_id, layer)) = texture {
+            let fbo = self.fbos.get_mut(&fbo_id).unwrap();
+            fbo.layer_index = layer as u16;
 
-                let cmd_buffer = self.command_pool[self.next_id].acquire_command_buffer();
-                unsafe {
-                    cmd_buffer.begin();
-                    let mut src_stage = Some(PipelineStage::empty());
-                    if let Some(barrier) = self.images[&texture.id].core.transit(
-                        hal::image::Access::COLOR_ATTACHMENT_READ
-                            | hal::image::Access::COLOR_ATTACHMENT_WRITE,
-                        hal::image::Layout::ColorAttachmentOptimal,
-                        self.images[&texture.id].core.subresource_range.clone(),
-                        src_stage.as_mut(),
-                    ) {
-                        cmd_buffer.pipeline_barrier(
-                            src_stage.unwrap() .. PipelineStage::COLOR_ATTACHMENT_OUTPUT,
-                            hal::memory::Dependencies::empty(),
-                            &[barrier],
-                        );
-                    }
-                    cmd_buffer.finish();
+            let cmd_buffer = self.command_pool[self.next_id].acquire_command_buffer();
+            unsafe {
+                cmd_buffer.begin();
+                let mut src_stage = Some(PipelineStage::empty());
+                if let Some(barrier) = self.images[&texture_id].core.transit(
+                    hal::image::Access::COLOR_ATTACHMENT_READ
+                        | hal::image::Access::COLOR_ATTACHMENT_WRITE,
+                    hal::image::Layout::ColorAttachmentOptimal,
+                    self.images[&texture_id].core.subresource_range.clone(),
+                    src_stage.as_mut(),
+                ) {
+                    cmd_buffer.pipeline_barrier(
+                        src_stage.unwrap() .. PipelineStage::COLOR_ATTACHMENT_OUTPUT,
+                        hal::memory::Dependencies::empty(),
+                        &[barrier],
+                    );
                 }
-
-                (fbo_id, texture.get_dimensions(), with_depth)
+                cmd_buffer.finish();
             }
-        };
+        }
 
         self.depth_available = depth_available;
         self.bind_draw_target_impl(fbo_id);
@@ -1995,7 +2688,13 @@ impl<B: hal::Backend> Device<B> {
             _ => hal::image::ViewKind::D2,
         };
 
+        // Compressed images can't be rendered to, nor get their mipmaps
+        // generated, so they are only ever uploaded to and sampled.
         let (mip_levels, usage) = match texture.filter {
+            _ if format.is_compressed() => {
+                assert!(render_target.is_none(), "Can't render to a compressed texture");
+                (1, usage_base)
+            }
             TextureFilter::Nearest => (
                 1,
                 usage_base | hal::image::Usage::COLOR_ATTACHMENT,
@@ -2025,16 +2724,28 @@ impl<B: hal::Backend> Device<B> {
             let cmd_buffer = self.command_pool[self.next_id].acquire_command_buffer();
             cmd_buffer.begin();
 
-            if let Some(barrier) = img.core.transit(
-                hal::image::Access::COLOR_ATTACHMENT_READ
-                    | hal::image::Access::COLOR_ATTACHMENT_WRITE,
-                hal::image::Layout::ColorAttachmentOptimal,
+            let (access, layout, stage) = if format.is_compressed() {
+                (
+                    hal::image::Access::SHADER_READ,
+                    hal::image::Layout::ShaderReadOnlyOptimal,
+                    PipelineStage::FRAGMENT_SHADER,
+                )
+            } else {
+                (
+                    hal::image::Access::COLOR_ATTACHMENT_READ
+                        | hal::image::Access::COLOR_ATTACHMENT_WRITE,
+                    hal::image::Layout::ColorAttachmentOptimal,
+                    PipelineStage::COLOR_ATTACHMENT_OUTPUT,
+                )
+            };
+            if let Some(barrier) = img.core.transit(
+                access,
+                layout,
                 img.core.subresource_range.clone(),
                 None,
             ) {
                 cmd_buffer.pipeline_barrier(
-                    PipelineStage::COLOR_ATTACHMENT_OUTPUT
-                        .. PipelineStage::COLOR_ATTACHMENT_OUTPUT,
+                    stage .. stage,
                     hal::memory::Dependencies::empty(),
                     &[barrier],
                 );
@@ -2086,6 +2797,104 @@ impl<B: hal::Backend> Device<B> {
         }
     }
 
+    /// Copies the top left `dest_rect.size` texels of an external texture
+    /// into a layer of `dest`.
+    pub fn copy_external_texture(
+        &mut self,
+        src: &ExternalTexture,
+        dest: &Texture,
+        dest_rect: DeviceIntRect,
+        dest_layer: i32,
+    ) {
+        debug_assert!(self.inside_frame);
+        if !self.images.contains_key(&src.id) {
+            warn!("Unable to copy unknown external texture {}", src.id);
+            return;
+        }
+        dest.bound_in_frame.set(self.frame_id);
+
+        let src_img = &self.images[&src.id].core;
+        let dest_img = &self.images[&dest.id].core;
+        let src_state = src_img.state.get();
+        let dest_state = dest_img.state.get();
+        let cmd_buffer = self.command_pool[self.next_id].acquire_command_buffer();
+
+        unsafe {
+            cmd_buffer.begin();
+            let mut pre_stage = Some(PipelineStage::COLOR_ATTACHMENT_OUTPUT);
+            let barriers = src_img
+                .transit(
+                    hal::image::Access::TRANSFER_READ,
+                    hal::image::Layout::TransferSrcOptimal,
+                    src_img.subresource_range.clone(),
+                    pre_stage.as_mut(),
+                )
+                .into_iter()
+                .chain(dest_img.transit(
+                    hal::image::Access::TRANSFER_WRITE,
+                    hal::image::Layout::TransferDstOptimal,
+                    dest_img.subresource_range.clone(),
+                    pre_stage.as_mut(),
+                ));
+            cmd_buffer.pipeline_barrier(
+                pre_stage.unwrap() .. PipelineStage::TRANSFER,
+                hal::memory::Dependencies::empty(),
+                barriers,
+            );
+
+            cmd_buffer.copy_image(
+                &src_img.image,
+                hal::image::Layout::TransferSrcOptimal,
+                &dest_img.image,
+                hal::image::Layout::TransferDstOptimal,
+                &[hal::command::ImageCopy {
+                    src_subresource: hal::image::SubresourceLayers {
+                        aspects: hal::format::Aspects::COLOR,
+                        level: 0,
+                        layers: 0 .. 1,
+                    },
+                    src_offset: hal::image::Offset { x: 0, y: 0, z: 0 },
+                    dst_subresource: hal::image::SubresourceLayers {
+                        aspects: hal::format::Aspects::COLOR,
+                        level: 0,
+                        layers: dest_layer as _ .. (dest_layer + 1) as _,
+                    },
+                    dst_offset: hal::image::Offset {
+                        x: dest_rect.origin.x,
+                        y: dest_rect.origin.y,
+                        z: 0,
+                    },
+                    extent: hal::image::Extent {
+                        width: dest_rect.size.width as u32,
+                        height: dest_rect.size.height as u32,
+                        depth: 1,
+                    },
+                }],
+            );
+
+            let barriers = src_img
+                .transit(
+                    src_state.0,
+                    src_state.1,
+                    src_img.subresource_range.clone(),
+                    None,
+                )
+                .into_iter()
+                .chain(dest_img.transit(
+                    dest_state.0,
+                    dest_state.1,
+                    dest_img.subresource_range.clone(),
+                    None,
+                ));
+            cmd_buffer.pipeline_barrier(
+                PipelineStage::TRANSFER .. pre_stage.unwrap(),
+                hal::memory::Dependencies::empty(),
+                barriers,
+            );
+            cmd_buffer.finish();
+        }
+    }
+
     /// Copies the contents from one renderable texture to another.
     pub fn blit_renderable_texture(&mut self, dst: &mut Texture, src: &Texture) {
         dst.bound_in_frame.set(self.frame_id);
@@ -2341,6 +3150,7 @@ impl<B: hal::Backend> Device<B> {
         let target = SharedDepthTarget {
             rbo_id,
             refcount: 1,
+            last_used: self.frame_id,
         };
         record_gpu_alloc(depth_target_size_in_bytes(&dimensions));
         self.depth_targets.insert(dimensions, target);
@@ -2348,47 +3158,190 @@ impl<B: hal::Backend> Device<B> {
     }
 
     fn release_depth_target(&mut self, dimensions: DeviceIntSize) {
-        let mut entry = match self.depth_targets.entry(dimensions) {
-            Entry::Occupied(x) => x,
-            Entry::Vacant(..) => panic!("Releasing unknown depth target"),
-        };
-        debug_assert!(entry.get().refcount != 0);
-        entry.get_mut().refcount -= 1;
-        if entry.get().refcount == 0 {
-            let t = entry.remove();
-            let old_rbo = self.rbos.remove(&t.rbo_id).unwrap();
+        let target = match self.depth_targets.get_mut(&dimensions) {
+            Some(target) => target,
+            None => panic!("Releasing unknown depth target"),
+        };
+        debug_assert!(target.refcount != 0);
+        target.refcount -= 1;
+        if target.refcount == 0 {
+            // Keep the depth buffer pooled, it gets deleted by
+            // `trim_depth_targets` if nobody picks it up again.
+            target.last_used = self.frame_id;
+        }
+    }
+
+    /// Deletes the pooled depth targets that haven't been referenced for a while.
+    ///
+    /// A depth target is never deleted while a retired framebuffer which may
+    /// still be in flight references it.
+    fn trim_depth_targets(&mut self, max_age: usize) {
+        let frame_id = self.frame_id;
+        let mut unused = Vec::new();
+        self.depth_targets.retain(|dimensions, target| {
+            if target.refcount != 0 || frame_id.0 - target.last_used.0 < max_age {
+                return true;
+            }
+            unused.push((*dimensions, target.rbo_id));
+            false
+        });
+        for (dimensions, rbo_id) in unused {
+            let old_rbo = self.rbos.remove(&rbo_id).unwrap();
             old_rbo.deinit(&self.device, &mut self.heaps);
             record_gpu_free(depth_target_size_in_bytes(&dimensions));
         }
     }
 
+    /// Deletes the retired framebuffers which are no longer in flight.
+    fn delete_retired_fbos(&mut self) {
+        let frame_id = self.frame_id;
+        let frame_count = self.frame_count;
+        let mut expired = Vec::new();
+        self.retired_fbos.retain(|&(retired_in, fbo_id)| {
+            if frame_id.0 - retired_in.0 < frame_count {
+                return true;
+            }
+            expired.push(fbo_id);
+            false
+        });
+        for fbo_id in expired {
+            let old_fbo = self.fbos.remove(&fbo_id).unwrap();
+            old_fbo.deinit(&self.device);
+        }
+    }
+
+    /// Detaches the depth target from a render target, returning it to the
+    /// depth target pool.
+    ///
+    /// This is called once a render target is done with the passes of a frame,
+    /// so that depth memory is only held while it is actually needed. The depth
+    /// target is attached again by `reuse_render_target` if the next use of the
+    /// render target requires it.
+    pub fn detach_depth_target(&mut self, texture: &mut Texture) {
+        if !texture.supports_depth() {
+            return;
+        }
+        let frame_id = self.frame_id;
+        self.retired_fbos.extend(
+            texture.fbos_with_depth.drain(..).map(|fbo_id| (frame_id, fbo_id))
+        );
+        self.release_depth_target(texture.get_dimensions());
+    }
+
     pub fn blit_render_target(&mut self, src_rect: DeviceIntRect, dest_rect: DeviceIntRect) {
+        self.blit_render_target_impl(src_rect, dest_rect, false, hal::image::Filter::Linear);
+    }
+
+    /// Like `blit_render_target`, but samples the nearest texel, so that
+    /// magnified content stays pixel exact.
+    pub fn blit_render_target_nearest(&mut self, src_rect: DeviceIntRect, dest_rect: DeviceIntRect) {
+        self.blit_render_target_impl(src_rect, dest_rect, false, hal::image::Filter::Nearest);
+    }
+
+    fn blit_render_target_impl(
+        &mut self,
+        src_rect: DeviceIntRect,
+        dest_rect: DeviceIntRect,
+        invert_y: bool,
+        filter: hal::image::Filter,
+    ) {
         debug_assert!(self.inside_frame);
+        self.record(DeviceCommand::Blit {
+            src: src_rect,
+            dest: dest_rect,
+        });
+        self.flush_queries();
+
+        let read_from_framebuffer = self.bound_read_fbo == DEFAULT_READ_FBO;
+        let draw_to_framebuffer = self.bound_draw_fbo == DEFAULT_DRAW_FBO;
+        // Copies within the main framebuffer keep the rotation of its content,
+        // copies between it and a texture have to rotate the content too.
+        let transform = if read_from_framebuffer != draw_to_framebuffer {
+            self.surface_transform
+        } else {
+            SurfaceTransform::Identity
+        };
 
-        let (src_format, src_img, src_layer) = if self.bound_read_fbo != DEFAULT_READ_FBO {
+        let read_texture = if self.bound_read_fbo != DEFAULT_READ_FBO {
             let fbo = &self.fbos[&self.bound_read_fbo];
-            let img = &self.images[&fbo.texture_id];
-            let layer = fbo.layer_index;
-            (img.format, &img.core, layer)
+            Some((fbo.texture_id, fbo.layer_index))
         } else {
-            (
-                self.surface_format,
-                &self.frame_images[self.current_frame_id],
-                0,
-            )
+            None
         };
-
-        let (dest_format, dest_img, dest_layer) = if self.bound_draw_fbo != DEFAULT_DRAW_FBO {
+        let draw_texture = if self.bound_draw_fbo != DEFAULT_DRAW_FBO {
             let fbo = &self.fbos[&self.bound_draw_fbo];
-            let img = &self.images[&fbo.texture_id];
-            let layer = fbo.layer_index;
-            (img.format, &img.core, layer)
+            Some((fbo.texture_id, fbo.layer_index))
         } else {
-            (
-                self.surface_format,
-                &self.frame_images[self.current_frame_id],
-                0,
-            )
+            None
+        };
+        let ((src_format, src_hal_format), (dest_format, dest_hal_format)) = {
+            let target_format = |texture: Option<(TextureId, u16)>| match texture {
+                Some((id, _)) => (self.images[&id].format, hal_format(self.images[&id].format)),
+                None => (self.surface_format, self.output_format.to_hal()),
+            };
+            (target_format(read_texture), target_format(draw_texture))
+        };
+
+        // A quad can be drawn rotated into the main framebuffer, in its
+        // logical space. Reading it back can only mirror the content, which
+        // covers the 180 degree rotation.
+        let mut mirror = false;
+        match (transform, read_texture) {
+            (SurfaceTransform::Identity, _) => {}
+            (_, Some(src)) => {
+                if self.draw_blit(src, src_rect, dest_rect, invert_y, dest_format, transform) {
+                    return;
+                }
+                warn!("Unable to draw a rotated blit into the main framebuffer");
+            }
+            (SurfaceTransform::Rotate180, None) => mirror = true,
+            (_, None) => warn!("Blitting from the {:?} rotated main framebuffer is not supported", transform),
+        }
+
+        let src_rect = if read_from_framebuffer {
+            self.transform_framebuffer_rect(src_rect)
+        } else {
+            src_rect
+        };
+        let dest_rect = if draw_to_framebuffer {
+            self.transform_framebuffer_rect(dest_rect)
+        } else {
+            dest_rect
+        };
+
+        // Copies between the main framebuffer and the BGRA8 targets need a
+        // conversion unless the output format is BGRA8 too.
+        let format_conversion = src_format != dest_format || (
+            self.output_format != OutputFormat::Bgra8Unorm &&
+            read_texture.is_none() != draw_texture.is_none()
+        );
+        let needs_blit = src_rect.size != dest_rect.size || format_conversion || invert_y || mirror;
+        if needs_blit && !self.supports_blit(src_hal_format, dest_hal_format) {
+            match read_texture {
+                // A texture can't be sampled while it's rendered to.
+                Some(src) if draw_texture.map_or(true, |(id, _)| id != src.0) => {
+                    if self.draw_blit(src, src_rect, dest_rect, invert_y, dest_format, SurfaceTransform::Identity) {
+                        return;
+                    }
+                }
+                _ => {}
+            }
+            warn!(
+                "Blitting from {:?} to {:?} is not supported by the backend",
+                src_hal_format,
+                dest_hal_format,
+            );
+        }
+
+        let (src_img, src_layer) = if let Some((texture_id, layer)) = read_texture {
+            (&self.images[&texture_id].core, layer)
+        } else {
+            (main_image!(self), 0)
+        };
+        let (dest_img, dest_layer) = if let Some((texture_id, layer)) = draw_texture {
+            (&self.images[&texture_id].core, layer)
+        } else {
+            (main_image!(self), 0)
         };
 
         let cmd_buffer = self.command_pool[self.next_id].acquire_command_buffer();
@@ -2436,13 +3389,23 @@ impl<B: hal::Backend> Device<B> {
                 );
             }
 
-            if src_rect.size != dest_rect.size || src_format != dest_format {
+            if needs_blit {
+                let (dest_x0, dest_x1) = if mirror {
+                    (dest_rect.max_x(), dest_rect.min_x())
+                } else {
+                    (dest_rect.min_x(), dest_rect.max_x())
+                };
+                let (dest_y0, dest_y1) = if invert_y != mirror {
+                    (dest_rect.max_y(), dest_rect.min_y())
+                } else {
+                    (dest_rect.min_y(), dest_rect.max_y())
+                };
                 cmd_buffer.blit_image(
                     &src_img.image,
                     hal::image::Layout::TransferSrcOptimal,
                     &dest_img.image,
                     hal::image::Layout::TransferDstOptimal,
-                    hal::image::Filter::Linear,
+                    filter,
                     &[hal::command::ImageBlit {
                         src_subresource: hal::image::SubresourceLayers {
                             aspects: hal::format::Aspects::COLOR,
@@ -2464,12 +3427,12 @@ impl<B: hal::Backend> Device<B> {
                             layers: dest_layer .. dest_layer + 1,
                         },
                         dst_bounds: hal::image::Offset {
-                            x: dest_rect.origin.x as i32,
-                            y: dest_rect.origin.y as i32,
+                            x: dest_x0,
+                            y: dest_y0,
                             z: 0,
                         } .. hal::image::Offset {
-                            x: dest_rect.origin.x as i32 + dest_rect.size.width as i32,
-                            y: dest_rect.origin.y as i32 + dest_rect.size.height as i32,
+                            x: dest_x1,
+                            y: dest_y1,
                             z: 1,
                         },
                     }],
@@ -2541,15 +3504,182 @@ impl<B: hal::Backend> Device<B> {
     }
 
     /// Performs a blit while flipping vertically. Useful for blitting textures
-    /// (which use origin-bottom-left) to the main framebuffer (which uses
-    /// origin-top-left).
+    /// (which use origin-top-left) to a main framebuffer with a bottom-left
+    /// `SurfaceOrigin`.
     pub fn blit_render_target_invert_y(
         &mut self,
         src_rect: DeviceIntRect,
         dest_rect: DeviceIntRect,
     ) {
-        debug_assert!(self.inside_frame);
-        self.blit_render_target(src_rect, dest_rect);
+        self.blit_render_target_impl(src_rect, dest_rect, true, hal::image::Filter::Linear);
+    }
+
+    /// Returns true if the backend can blit, with linear filtering, from
+    /// images of `src` format into images of `dest` format.
+    fn supports_blit(&self, src: hal::format::Format, dest: hal::format::Format) -> bool {
+        let features = |format| {
+            self.adapter.physical_device
+                .format_properties(Some(format))
+                .optimal_tiling
+        };
+        features(src).contains(hal::format::ImageFeature::BLIT_SRC | hal::format::ImageFeature::SAMPLED_LINEAR) &&
+            features(dest).contains(hal::format::ImageFeature::BLIT_DST)
+    }
+
+    /// Returns the program drawing blits into targets of `format`, if there
+    /// is one for the format.
+    fn blit_program(&mut self, format: ImageFormat) -> Option<ProgramId> {
+        if let Some(program) = self.blit_programs.get(&format) {
+            return *program;
+        }
+        let features: &[&str] = if format == ImageFormat::R8 {
+            &["ALPHA_TARGET"]
+        } else if format == self.surface_format {
+            &[]
+        } else {
+            return None;
+        };
+        let program = match self.create_program("blit", &ShaderKind::Blit, features) {
+            Ok(program) => Some(program),
+            Err(e) => {
+                error!("Failed to create the blit program: {:?}", e);
+                None
+            }
+        };
+        self.blit_programs.insert(format, program);
+        program
+    }
+
+    /// Draws `src_rect` of the `src` texture layer into `dest_rect` of the
+    /// bound draw target with a textured quad, for blits the backend can't
+    /// perform, or which have to rotate the content by `transform`. Returns
+    /// false if there is no program for `dest_format`.
+    fn draw_blit(
+        &mut self,
+        src: (TextureId, u16),
+        src_rect: DeviceIntRect,
+        dest_rect: DeviceIntRect,
+        invert_y: bool,
+        dest_format: ImageFormat,
+        transform: SurfaceTransform,
+    ) -> bool {
+        let program = match self.blit_program(dest_format) {
+            Some(program) => program,
+            None => return false,
+        };
+
+        let (src_id, src_layer) = src;
+        let extent = self.images[&src_id].kind.extent();
+        let (width, height) = (extent.width as f32, extent.height as f32);
+        let (dest_y0, dest_y1) = if invert_y {
+            (dest_rect.max_y(), dest_rect.min_y())
+        } else {
+            (dest_rect.min_y(), dest_rect.max_y())
+        };
+        let instance = vertex_types::BlitInstance {
+            aBlitTargetRect: [
+                dest_rect.min_x() as f32,
+                dest_y0 as f32,
+                dest_rect.max_x() as f32,
+                dest_y1 as f32,
+            ],
+            aBlitSourceRect: [
+                src_rect.min_x() as f32 / width,
+                src_rect.min_y() as f32 / height,
+                src_rect.max_x() as f32 / width,
+                src_rect.max_y() as f32 / height,
+            ],
+            aBlitSourceLayer: src_layer as i32,
+        };
+        // The rects are in the pixel space of the draw target, like the blit
+        // bounds, or in the logical space of a rotated main framebuffer.
+        let target_size = transform.transform_size(
+            DeviceIntSize::new(self.viewport.rect.w.into(), self.viewport.rect.h.into())
+        );
+        let projection = transform.apply_to_projection(create_projection(
+            0.0,
+            target_size.width as f32,
+            0.0,
+            target_size.height as f32,
+        ));
+
+        // The draw happens behind the back of the caller, so it must not end
+        // up in a recording, where it's covered by the blit command, nor
+        // change the state the caller has set up.
+        let recorder = self.command_recorder.borrow_mut().take();
+        let bound_program = self.bound_program;
+        let bound_locals = self.bound_locals;
+        let bound_desc_set_resources = self.bound_desc_set_resources;
+        let bound_texture = (self.bound_textures[0], self.bound_sampler[0]);
+        let blend_state = self.current_blend_state.get();
+        let depth_test = self.current_depth_test;
+        let scissor_rect = self.scissor_rect.take();
+
+        self.bind_program(&program);
+        self.set_uniforms(&program, &projection);
+        self.bind_texture_impl(TextureSlot(0), src_id, TextureFilter::Linear);
+        self.bind_textures();
+        self.current_blend_state.set(BlendState::Off);
+        self.current_depth_test = DepthTest::Off;
+        self.update_instances(&[instance]);
+        self.draw();
+
+        self.bound_program = bound_program;
+        self.bound_locals = bound_locals;
+        self.bound_desc_set_resources = bound_desc_set_resources;
+        self.bound_textures[0] = bound_texture.0;
+        self.bound_sampler[0] = bound_texture.1;
+        self.current_blend_state.set(blend_state);
+        self.current_depth_test = depth_test;
+        self.scissor_rect = scissor_rect;
+        *self.command_recorder.borrow_mut() = recorder;
+        true
+    }
+
+    /// Draws the output target of the frame into the swapchain image,
+    /// converting it from sRGB into the color space. Blending happens in the
+    /// output target, so this is the only place the conversion is applied.
+    fn composite_output(&mut self) {
+        let program = match self.output_program {
+            Some(program) => program,
+            None => {
+                let output_transform = self.color_space.output_transform();
+                match self.create_program_with_output_transform("blit", &ShaderKind::Blit, &[], output_transform) {
+                    Ok(program) => {
+                        self.output_program = Some(program);
+                        program
+                    }
+                    Err(e) => {
+                        error!("Failed to create the output program: {:?}", e);
+                        return;
+                    }
+                }
+            }
+        };
+
+        let (width, height) = (self.viewport.rect.w as f32, self.viewport.rect.h as f32);
+        let instance = vertex_types::BlitInstance {
+            aBlitTargetRect: [0.0, 0.0, width, height],
+            aBlitSourceRect: [0.0, 0.0, 1.0, 1.0],
+            aBlitSourceLayer: 0,
+        };
+        let projection = create_projection(0.0, width, 0.0, height);
+        let output_target = self.output_targets[self.current_frame_id];
+
+        // Like `draw_blit`, this is not part of what the renderer submitted.
+        let recorder = self.command_recorder.borrow_mut().take();
+        self.bind_program(&program);
+        self.set_uniforms(&program, &projection);
+        self.bind_texture_impl(TextureSlot(0), output_target, TextureFilter::Nearest);
+        self.bind_textures();
+        self.current_blend_state.set(BlendState::Off);
+        self.current_depth_test = DepthTest::Off;
+        self.scissor_rect = None;
+        self.update_instances(&[instance]);
+        self.compositing_output = true;
+        self.draw();
+        self.compositing_output = false;
+        *self.command_recorder.borrow_mut() = recorder;
     }
 
     /// Notifies the device that the contents of a render target are no longer
@@ -2631,6 +3761,30 @@ impl<B: hal::Backend> Device<B> {
         }
     }
 
+    /// Wraps an image the embedder created, e.g. bound to memory imported
+    /// from a dmabuf, an IOSurface or a shared D3D handle through the native
+    /// API of the backend, which gfx-hal doesn't expose. The returned id is
+    /// the `NativeTexture` source of the external images showing it.
+    ///
+    /// The image has to be in the `ShaderReadOnlyOptimal` layout, with the
+    /// `SAMPLED` and `TRANSFER_SRC` usages, whenever the external image
+    /// handler locks it.
+    pub fn import_image(&mut self, image: B::Image, format: ImageFormat, size: DeviceIntSize) -> u32 {
+        let id = self.generate_texture_id();
+        let image = Image::from_raw(&self.device, image, format, size.width, size.height);
+        self.images.insert(id, image);
+        id
+    }
+
+    /// Stops sampling an image added with `import_image`, and gives it back
+    /// to the embedder once the frames in flight, which may use it, completed.
+    pub fn release_imported_image(&mut self, id: u32) -> Option<B::Image> {
+        self.wait_for_resources();
+        self.images
+            .remove(&id)
+            .map(|image| image.core.into_raw(&self.device))
+    }
+
     pub fn delete_texture(&mut self, mut texture: Texture) {
         //debug_assert!(self.inside_frame);
         if texture.size.width + texture.size.height == 0 {
@@ -2652,6 +3806,7 @@ impl<B: hal::Backend> Device<B> {
 
     pub fn switch_mode(&mut self, mode: i32) {
         debug_assert!(self.inside_frame);
+        self.record(DeviceCommand::SwitchMode(mode));
         self.program_mode_id = mode;
     }
 
@@ -2680,6 +3835,7 @@ impl<B: hal::Backend> Device<B> {
 
     pub fn upload_texture_immediate<T: Texel>(&mut self, texture: &Texture, pixels: &[T]) {
         texture.bound_in_frame.set(self.frame_id);
+        device_log!(uploads, Debug, "texture {} <- {:?} immediate", texture.id, texture.size);
         let len = pixels.len() / texture.layer_count as usize;
         for i in 0 .. texture.layer_count {
             let start = len * i as usize;
@@ -2701,6 +3857,231 @@ impl<B: hal::Backend> Device<B> {
         }
     }
 
+    /// Uploads the texels of several rects of the first layer of a texture
+    /// with a single copy. The data of the rects is tightly packed, one rect
+    /// after another, in the order of `rects`.
+    pub fn upload_texture_regions<T: Copy>(
+        &mut self,
+        texture: &Texture,
+        rects: &[DeviceIntRect],
+        data: &[T],
+    ) {
+        debug_assert!(self.inside_frame);
+        if rects.is_empty() {
+            return;
+        }
+        texture.bound_in_frame.set(self.frame_id);
+        device_log!(uploads, Debug, "texture {} <- {} regions", texture.id, rects.len());
+        let data = unsafe {
+            slice::from_raw_parts(data.as_ptr() as *const u8, data.len() * mem::size_of::<T>())
+        };
+        self.images
+            .get_mut(&texture.id)
+            .expect("Texture not found.")
+            .update_regions(
+                &self.device,
+                &mut self.command_pool[self.next_id],
+                &mut self.staging_buffer_pool[self.next_id],
+                rects,
+                0,
+                data,
+            );
+    }
+
+    /// Initializes the contents of all the layers of a freshly created texture.
+    ///
+    /// Unlike `upload_texture_immediate`, this doesn't go through the per-frame
+    /// staging buffers, so it works for textures of any size and outside of a
+    /// frame. The copy is submitted and waited upon right away, leaving the
+    /// image ready to be sampled.
+    ///
+    /// The texels are expected to be tightly packed, layer after layer, in the
+    /// format of the texture. As the only exception, data for `RGBAF16`
+    /// textures may be provided as 32 bit floats, and gets converted. Data of
+    /// any other size is logged as an error and not uploaded.
+    pub fn init_texture<T: Texel>(&mut self, texture: &Texture, pixels: Option<&[T]>) {
+        let pixels = match pixels {
+            Some(pixels) => texels_to_u8_slice(pixels),
+            None => return,
+        };
+
+        // Compressed data is made of rows of blocks instead of pixels.
+        let bpp = texture.format.bytes_per_block() as usize;
+        let block_size = texture.format.block_size() as usize;
+        let width = texture.size.width as usize;
+        let height = texture.size.height as usize;
+        let layer_count = texture.layer_count as usize;
+        let row_size = texture.format.row_size_in_bytes(texture.size.width) as usize;
+        let rows = texture.format.block_rows(texture.size.height) as usize;
+        let layer_size = row_size * rows;
+
+        let converted;
+        let pixels = if pixels.len() == layer_size * layer_count {
+            pixels
+        } else if texture.format == ImageFormat::RGBAF16 &&
+            pixels.len() == 2 * layer_size * layer_count {
+            converted = pixels
+                .chunks(4)
+                .flat_map(|bytes| {
+                    let value = f32::from_bits(
+                        u32::from_ne_bytes([bytes[0], bytes[1], bytes[2], bytes[3]])
+                    );
+                    let half = f32_to_f16(value).to_ne_bytes();
+                    vec![half[0], half[1]]
+                })
+                .collect::<Vec<u8>>();
+            &converted
+        } else {
+            error!(
+                "Texture data of {} bytes doesn't match {}x{}x{} {:?} texture, skipping the upload",
+                pixels.len(), width, height, layer_count, texture.format,
+            );
+            return;
+        };
+
+        // Rows and layers are placed in the staging buffer according to the
+        // copy alignment requirements of the device, which don't necessarily
+        // match the tightly packed input.
+        let pitch_alignment_mask = cmp::max(
+            self.limits.optimal_buffer_copy_pitch_alignment as usize,
+            bpp,
+        ) - 1;
+        let offset_alignment_mask = cmp::max(
+            self.limits.optimal_buffer_copy_offset_alignment as usize,
+            bpp,
+        ) - 1;
+        let row_pitch = (row_size + pitch_alignment_mask) & !pitch_alignment_mask;
+        let layer_pitch = (row_pitch * rows + offset_alignment_mask) & !offset_alignment_mask;
+
+        let staging_data = if row_pitch == row_size && layer_pitch == layer_size {
+            pixels.to_vec()
+        } else {
+            let mut staging_data = vec![0u8; layer_pitch * layer_count];
+            for layer in 0 .. layer_count {
+                for row in 0 .. rows {
+                    let src = layer * layer_size + row * row_size;
+                    let dst = layer * layer_pitch + row * row_pitch;
+                    staging_data[dst .. dst + row_size]
+                        .copy_from_slice(&pixels[src .. src + row_size]);
+                }
+            }
+            staging_data
+        };
+
+        let non_coherent_atom_size_mask = (self.limits.non_coherent_atom_size - 1) as usize;
+        let mut staging_buffer: Buffer<B> = Buffer::new(
+            &self.device,
+            &mut self.heaps,
+            MemoryUsageValue::Upload,
+            hal::buffer::Usage::TRANSFER_SRC,
+            non_coherent_atom_size_mask,
+            staging_data.len(),
+            1,
+        );
+        staging_buffer.update_all(
+            &self.device,
+            &staging_data,
+            non_coherent_atom_size_mask as u64,
+        );
+
+        let mut command_pool = unsafe {
+            self.device.create_command_pool_typed(
+                &self.queue_group,
+                hal::pool::CommandPoolCreateFlags::empty(),
+            )
+        }
+        .expect("create_command_pool_typed failed");
+        unsafe { command_pool.reset() };
+
+        let regions = (0 .. layer_count)
+            .map(|layer| hal::command::BufferImageCopy {
+                buffer_offset: (layer * layer_pitch) as _,
+                buffer_width: (row_pitch / bpp * block_size) as _,
+                buffer_height: (rows * block_size) as _,
+                image_layers: hal::image::SubresourceLayers {
+                    aspects: hal::format::Aspects::COLOR,
+                    level: 0,
+                    layers: layer as _ .. (layer + 1) as _,
+                },
+                image_offset: hal::image::Offset { x: 0, y: 0, z: 0 },
+                image_extent: hal::image::Extent {
+                    width: width as _,
+                    height: height as _,
+                    depth: 1,
+                },
+            })
+            .collect::<Vec<_>>();
+
+        let image = &self.images[&texture.id].core;
+        let mut cmd_buffer = command_pool.acquire_command_buffer::<hal::command::OneShot>();
+        unsafe {
+            cmd_buffer.begin();
+
+            let mut pre_stage = Some(PipelineStage::TOP_OF_PIPE);
+            let barriers = staging_buffer
+                .transit(hal::buffer::Access::TRANSFER_READ)
+                .into_iter()
+                .chain(image.transit(
+                    hal::image::Access::TRANSFER_WRITE,
+                    hal::image::Layout::TransferDstOptimal,
+                    image.subresource_range.clone(),
+                    pre_stage.as_mut(),
+                ));
+            cmd_buffer.pipeline_barrier(
+                pre_stage.unwrap() .. PipelineStage::TRANSFER,
+                hal::memory::Dependencies::empty(),
+                barriers,
+            );
+
+            cmd_buffer.copy_buffer_to_image(
+                &staging_buffer.buffer,
+                &image.image,
+                hal::image::Layout::TransferDstOptimal,
+                &regions,
+            );
+
+            if let Some(barrier) = image.transit(
+                hal::image::Access::SHADER_READ,
+                hal::image::Layout::ShaderReadOnlyOptimal,
+                image.subresource_range.clone(),
+                None,
+            ) {
+                cmd_buffer.pipeline_barrier(
+                    PipelineStage::TRANSFER .. PipelineStage::FRAGMENT_SHADER,
+                    hal::memory::Dependencies::empty(),
+                    &[barrier],
+                );
+            }
+            cmd_buffer.finish();
+        }
+
+        let mut copy_fence = self
+            .device
+            .create_fence(false)
+            .expect("create_fence failed");
+
+        unsafe {
+            self.device
+                .reset_fence(&copy_fence)
+                .expect("reset_fence failed");
+            self.queue_group.queues[0]
+                .submit_nosemaphores(Some(&cmd_buffer), Some(&mut copy_fence));
+            self.device
+                .wait_for_fence(&copy_fence, !0)
+                .expect("wait_for_fence failed");
+            self.device.destroy_fence(copy_fence);
+        }
+
+        staging_buffer.deinit(&self.device, &mut self.heaps);
+        unsafe {
+            self.device.destroy_command_pool(command_pool.into_raw());
+        }
+
+        if texture.filter == TextureFilter::Trilinear {
+            self.generate_mipmaps(texture);
+        }
+    }
+
     #[cfg(feature = "capture")]
     pub fn read_pixels(&mut self, img_desc: &ImageDescriptor) -> Vec<u8> {
         let mut pixels = vec![0; (img_desc.size.width * img_desc.size.height * 4) as usize];
@@ -2724,15 +4105,76 @@ impl<B: hal::Backend> Device<B> {
     ) {
         self.wait_for_resources();
 
+        let readback = self.submit_readback(rect, read_format);
+        assert_eq!(output.len(), readback.size_in_bytes());
+        unsafe { self.device.wait_for_fence(&readback.fence, !0) }
+            .expect("wait_for_fence failed");
+        self.finish_readback(readback, output);
+    }
+
+    /// Starts reading back a rectangle of pixels from the current read target
+    /// without waiting for the GPU. The copy is ordered after all the work
+    /// submitted so far, `poll_readback` returns the pixels once it is done.
+    pub fn request_read_pixels(
+        &mut self,
+        rect: DeviceIntRect,
+        read_format: ReadPixelsFormat,
+    ) -> ReadbackHandle {
+        let readback = self.submit_readback(rect, read_format);
+        let handle = ReadbackHandle(self.next_readback_id);
+        self.next_readback_id += 1;
+        self.pending_readbacks.insert(handle.0, readback);
+        handle
+    }
+
+    /// Returns the pixels of a read back started by `request_read_pixels` if
+    /// the GPU finished it, otherwise hands the handle back.
+    pub fn poll_readback(&mut self, handle: ReadbackHandle) -> Result<Vec<u8>, ReadbackHandle> {
+        let done = {
+            let readback = self.pending_readbacks
+                .get(&handle.0)
+                .expect("BUG: unknown readback handle");
+            unsafe { self.device.get_fence_status(&readback.fence) }
+                .expect("get_fence_status failed")
+        };
+        if !done {
+            return Err(handle);
+        }
+        let readback = self.pending_readbacks.remove(&handle.0).unwrap();
+        let mut output = vec![0; readback.size_in_bytes()];
+        self.finish_readback(readback, &mut output);
+        Ok(output)
+    }
+
+    fn submit_readback(
+        &mut self,
+        rect: DeviceIntRect,
+        read_format: ReadPixelsFormat,
+    ) -> PendingReadback<B> {
         let bytes_per_pixel = match read_format {
             ReadPixelsFormat::Standard(imf) => imf.bytes_per_pixel(),
             ReadPixelsFormat::Rgba8 => 4,
         };
         let size_in_bytes = (bytes_per_pixel * rect.size.width * rect.size.height) as usize;
-        assert_eq!(output.len(), size_in_bytes);
         let capture_read =
             cfg!(feature = "capture") && self.bound_read_texture.0 != INVALID_TEXTURE_ID;
 
+        // Reads from the main framebuffer have to undo the surface transform.
+        // Their rect and rows follow the bottom-left convention of GL, so they
+        // need a vertical flip unless the surface origin is bottom-left too.
+        let main_framebuffer = !capture_read && self.bound_read_fbo == DEFAULT_READ_FBO;
+        let flip_y = main_framebuffer && !self.surface_origin.is_bottom_left();
+        let (transform, image_rect) = if main_framebuffer {
+            let rect = if flip_y {
+                SurfaceOrigin::BottomLeft.convert_rect(rect, self.framebuffer_size().height)
+            } else {
+                rect
+            };
+            (self.surface_transform, self.transform_framebuffer_rect(rect))
+        } else {
+            (SurfaceTransform::Identity, rect)
+        };
+
         let (image, image_format, layer) = if capture_read {
             let img = &self.images[&self.bound_read_texture.0];
             (&img.core, img.format, self.bound_read_texture.1 as u16)
@@ -2743,13 +4185,20 @@ impl<B: hal::Backend> Device<B> {
             (&img.core, img.format, layer)
         } else {
             (
-                &self.frame_images[self.current_frame_id],
+                main_image!(self),
                 self.surface_format,
                 0,
             )
         };
 
-        let (fmt_mismatch, stride) = if bytes_per_pixel < image_format.bytes_per_pixel() {
+        // The main framebuffer is RGBA16F, while the readers expect 8-bit
+        // pixels, so read half floats and convert them on completion.
+        let half_float = main_framebuffer &&
+            self.render_pass.as_ref().unwrap().main_format == hal::format::Format::Rgba16Sfloat;
+        let (fmt_mismatch, stride) = if half_float {
+            assert_eq!(bytes_per_pixel, 4);
+            (false, 2)
+        } else if bytes_per_pixel < image_format.bytes_per_pixel() {
             // Special case which can occur during png save, because we force to read Rgba8 values from an Rgbaf32 texture.
             (
                 true,
@@ -2766,7 +4215,7 @@ impl<B: hal::Backend> Device<B> {
             MemoryUsageValue::Download,
             hal::buffer::Usage::TRANSFER_DST,
             (self.limits.optimal_buffer_copy_pitch_alignment - 1) as usize,
-            output.len(),
+            size_in_bytes,
             stride,
         );
 
@@ -2798,8 +4247,10 @@ impl<B: hal::Backend> Device<B> {
                     None,
                 ));
 
+            // The copy isn't preceded by a wait on the frame fences, so it has
+            // to wait for the rendering into the image.
             cmd_buffer.pipeline_barrier(
-                PipelineStage::TRANSFER .. PipelineStage::TRANSFER,
+                PipelineStage::COLOR_ATTACHMENT_OUTPUT | PipelineStage::TRANSFER .. PipelineStage::TRANSFER,
                 hal::memory::Dependencies::empty(),
                 barriers,
             );
@@ -2810,21 +4261,21 @@ impl<B: hal::Backend> Device<B> {
                 &download_buffer.buffer,
                 &[hal::command::BufferImageCopy {
                     buffer_offset: 0,
-                    buffer_width: rect.size.width as u32,
-                    buffer_height: rect.size.height as u32,
+                    buffer_width: image_rect.size.width as u32,
+                    buffer_height: image_rect.size.height as u32,
                     image_layers: hal::image::SubresourceLayers {
                         aspects: hal::format::Aspects::COLOR,
                         level: 0,
                         layers: layer .. layer + 1,
                     },
                     image_offset: hal::image::Offset {
-                        x: rect.origin.x as i32,
-                        y: rect.origin.y as i32,
+                        x: image_rect.origin.x as i32,
+                        y: image_rect.origin.y as i32,
                         z: 0,
                     },
                     image_extent: hal::image::Extent {
-                        width: rect.size.width as _,
-                        height: rect.size.height as _,
+                        width: image_rect.size.width as _,
+                        height: image_rect.size.height as _,
                         depth: 1 as _,
                     },
                 }],
@@ -2844,22 +4295,50 @@ impl<B: hal::Backend> Device<B> {
             cmd_buffer.finish();
         }
 
-        let mut copy_fence = self
+        let mut fence = self
             .device
             .create_fence(false)
             .expect("create_fence failed");
 
         unsafe {
-            self.device
-                .reset_fence(&copy_fence)
-                .expect("reset_fence failed");
             self.queue_group.queues[0]
-                .submit_nosemaphores(Some(&cmd_buffer), Some(&mut copy_fence));
-            self.device
-                .wait_for_fence(&copy_fence, !0)
-                .expect("wait_for_fence failed");
-            self.device.destroy_fence(copy_fence);
+                .submit_nosemaphores(Some(&cmd_buffer), Some(&mut fence));
+        }
+
+        let swizzle = !capture_read && !half_float &&
+            self.surface_format == ImageFormat::BGRA8 && bytes_per_pixel == 4;
+        PendingReadback {
+            rect,
+            image_rect,
+            bytes_per_pixel,
+            fmt_mismatch,
+            half_float,
+            flip_y,
+            swizzle,
+            transform,
+            buffer: download_buffer,
+            command_pool,
+            fence,
         }
+    }
+
+    /// Copies the pixels of a finished read back into `output` and releases
+    /// its resources.
+    fn finish_readback(&mut self, readback: PendingReadback<B>, output: &mut [u8]) {
+        let PendingReadback {
+            rect,
+            image_rect,
+            bytes_per_pixel,
+            fmt_mismatch,
+            half_float,
+            flip_y,
+            swizzle,
+            transform,
+            buffer: mut download_buffer,
+            mut command_pool,
+            fence,
+        } = readback;
+        unsafe { self.device.destroy_fence(fence) };
 
         let mut data = vec![0; download_buffer.buffer_size];
         let range = 0 .. download_buffer.buffer_size as u64;
@@ -2877,6 +4356,20 @@ impl<B: hal::Backend> Device<B> {
             for i in 0 .. f32_data.len() {
                 data[i] = round_to_int(f32_data[i].min(0f32).max(1f32));
             }
+        } else if half_float {
+            let mut f16_data = vec![0u16; download_buffer.buffer_size / 2];
+            unsafe {
+                let mut mapped = download_buffer
+                    .memory_block
+                    .map(&self.device, range.clone())
+                    .expect("Mapping memory block failed");
+                let slice = mapped.read(&self.device, range).expect("Read failed");
+                f16_data[0 .. slice.len()].copy_from_slice(&slice);
+            }
+            download_buffer.memory_block.unmap(&self.device);
+            for (value, &half) in data.iter_mut().zip(f16_data.iter()) {
+                *value = round_to_int(f16_to_f32(half).max(0.0).min(1.0));
+            }
         } else {
             unsafe {
                 let mut mapped = download_buffer
@@ -2889,19 +4382,26 @@ impl<B: hal::Backend> Device<B> {
             download_buffer.memory_block.unmap(&self.device);
         }
         data.truncate(output.len());
-        if !capture_read && self.surface_format == ImageFormat::BGRA8 {
+        if flip_y && transform == SurfaceTransform::Identity && !swizzle {
+            flip_rows(&mut data, (bytes_per_pixel * rect.size.width) as usize);
+            output.swap_with_slice(&mut data);
+        } else if flip_y || transform != SurfaceTransform::Identity || swizzle {
+            let bpp = bytes_per_pixel as usize;
             let width = rect.size.width as usize;
             let height = rect.size.height as usize;
-            let row_pitch: usize = bytes_per_pixel as usize * width;
-            // Vertical flip the result and convert to RGBA
-            for y in 0 .. height as usize {
-                for x in 0 .. width as usize {
-                    let offset: usize = y * row_pitch + x * 4;
-                    let rev_offset: usize = (height - 1 - y) * row_pitch + x * 4;
-                    output[offset + 0] = data[rev_offset + 2];
-                    output[offset + 1] = data[rev_offset + 1];
-                    output[offset + 2] = data[rev_offset + 0];
-                    output[offset + 3] = data[rev_offset + 3];
+            let row_pitch: usize = bpp * width;
+            let image_row_pitch: usize = bpp * image_rect.size.width as usize;
+            // Vertical flip the result if needed, undo the surface rotation and convert to RGBA
+            for y in 0 .. height {
+                let src_y = if flip_y { height - 1 - y } else { y };
+                for x in 0 .. width {
+                    let offset: usize = y * row_pitch + x * bpp;
+                    let (src_x, src_y) = transform.transform_texel(x, src_y, width, height);
+                    let src_offset: usize = src_y * image_row_pitch + src_x * bpp;
+                    output[offset .. offset + bpp].copy_from_slice(&data[src_offset .. src_offset + bpp]);
+                    if swizzle {
+                        output.swap(offset, offset + 2);
+                    }
                 }
             }
         } else {
@@ -2915,6 +4415,17 @@ impl<B: hal::Backend> Device<B> {
         }
     }
 
+    /// Waits for and releases the read backs nobody polled to completion.
+    fn deinit_readbacks(&mut self) {
+        let readbacks: Vec<_> = self.pending_readbacks.drain().map(|(_, r)| r).collect();
+        for readback in readbacks {
+            unsafe { self.device.wait_for_fence(&readback.fence, !0) }
+                .expect("wait_for_fence failed");
+            let mut output = vec![0; readback.size_in_bytes()];
+            self.finish_readback(readback, &mut output);
+        }
+    }
+
     /// Get texels of a texture into the specified output slice.
     pub fn get_tex_image_into(
         &mut self,
@@ -3071,7 +4582,8 @@ impl<B: hal::Backend> Device<B> {
             stencil: None,
         });
 
-        let (img, frame_buffer, format, depth_img) = if self.bound_draw_fbo != DEFAULT_DRAW_FBO {
+        let main_framebuffer = self.bound_draw_fbo == DEFAULT_DRAW_FBO;
+        let (img, frame_buffer, format, depth_img) = if !main_framebuffer {
             let texture_id = self.fbos[&self.bound_draw_fbo].texture_id;
             let rbo_id = self.fbos[&self.bound_draw_fbo].rbo;
             (
@@ -3090,18 +4602,18 @@ impl<B: hal::Backend> Device<B> {
                 _ => (&self.framebuffers_depth[self.current_frame_id], Some(&self.frame_depths[self.current_frame_id].core)),
             };
             (
-                &self.frame_images[self.current_frame_id],
+                main_image!(self),
                 frame_buffer,
                 self.surface_format,
                 depth_image,
             )
         };
 
-        let render_pass = self
-            .render_pass
-            .as_ref()
-            .unwrap()
-            .get_render_pass(format, depth_img.is_some());
+        let render_pass = if main_framebuffer {
+            self.render_pass.as_ref().unwrap().get_main_render_pass(depth_img.is_some())
+        } else {
+            self.render_pass.as_ref().unwrap().get_render_pass(format, depth_img.is_some())
+        };
 
         let cmd_buffer = self.command_pool[self.next_id].acquire_command_buffer();
         unsafe {
@@ -3189,7 +4701,7 @@ impl<B: hal::Backend> Device<B> {
             (&img.core, fbo.layer_index, dimg)
         } else {
             (
-                &self.frame_images[self.current_frame_id],
+                main_image!(self),
                 0,
                 Some(&self.frame_depths[self.current_frame_id].core),
             )
@@ -3260,7 +4772,14 @@ impl<B: hal::Backend> Device<B> {
         depth: Option<f32>,
         rect: Option<DeviceIntRect>,
     ) {
+        self.record(DeviceCommand::Clear { color, depth, rect });
+        self.flush_queries();
         if let Some(rect) = rect {
+            let rect = if self.bound_draw_fbo == DEFAULT_DRAW_FBO {
+                self.transform_framebuffer_rect(rect)
+            } else {
+                rect
+            };
             let target_rect = if self.bound_draw_fbo != DEFAULT_DRAW_FBO {
                 let extent = &self.images[&self.fbos[&self.bound_draw_fbo].texture_id]
                     .kind
@@ -3295,11 +4814,11 @@ impl<B: hal::Backend> Device<B> {
             self.depth_available,
             "Enabling depth test without depth target"
         );
-        self.current_depth_test = LESS_EQUAL_TEST;
+        self.set_depth_test(LESS_EQUAL_TEST);
     }
 
     pub fn disable_depth(&mut self) {
-        self.current_depth_test = DepthTest::Off;
+        self.set_depth_test(DepthTest::Off);
     }
 
     pub fn set_depth_func(&mut self, _depth_func: DepthFunction) {
@@ -3312,99 +4831,418 @@ impl<B: hal::Backend> Device<B> {
             self.depth_available,
             "Enabling depth test without depth target"
         );
-        self.current_depth_test = LESS_EQUAL_WRITE;
+        self.set_depth_test(LESS_EQUAL_WRITE);
     }
 
     pub fn disable_depth_write(&mut self) {
         if self.current_depth_test != DepthTest::Off {
-            self.current_depth_test = LESS_EQUAL_TEST;
+            self.set_depth_test(LESS_EQUAL_TEST);
         }
     }
 
+    fn set_depth_test(&mut self, depth_test: DepthTest) {
+        let mode = if depth_test == DepthTest::Off {
+            RecordedDepthMode::Off
+        } else if depth_test == LESS_EQUAL_WRITE {
+            RecordedDepthMode::TestAndWrite
+        } else {
+            RecordedDepthMode::Test
+        };
+        self.record(DeviceCommand::SetDepthMode(mode));
+        self.current_depth_test = depth_test;
+    }
+
     pub fn disable_stencil(&self) {
         warn!("disable stencil is missing")
     }
 
     pub fn set_scissor_rect(&mut self, rect: DeviceIntRect) {
+        let rect = if self.bound_draw_fbo == DEFAULT_DRAW_FBO {
+            self.transform_framebuffer_rect(rect)
+        } else {
+            rect
+        };
         self.scissor_rect = Some(rect);
+        self.record(DeviceCommand::SetScissor(self.scissor_rect));
     }
 
     pub fn enable_scissor(&self) {}
 
+    /// Samples the textures of the following draws with anisotropic
+    /// filtering, if the device supports it.
+    pub fn set_anisotropic_sampling(&mut self, enabled: bool) {
+        self.anisotropic_sampling = enabled;
+        self.record(DeviceCommand::SetAnisotropicSampling(enabled));
+    }
+
     pub fn disable_scissor(&mut self) {
         self.scissor_rect = None;
+        self.record(DeviceCommand::SetScissor(None));
     }
 
     pub fn set_blend(&self, enable: bool) {
         if !enable {
-            self.current_blend_state.set(BlendState::Off)
+            self.set_blend_state(BlendState::Off, RecordedBlendMode::Off)
         }
     }
 
     pub fn set_blend_mode_alpha(&self) {
-        self.current_blend_state.set(ALPHA);
+        self.set_blend_state(ALPHA, RecordedBlendMode::Alpha);
     }
 
     pub fn set_blend_mode_premultiplied_alpha(&self) {
-        self.current_blend_state
-            .set(BlendState::PREMULTIPLIED_ALPHA);
+        self.set_blend_state(BlendState::PREMULTIPLIED_ALPHA, RecordedBlendMode::PremultipliedAlpha);
     }
 
     pub fn set_blend_mode_premultiplied_dest_out(&self) {
-        self.current_blend_state.set(PREMULTIPLIED_DEST_OUT);
+        self.set_blend_state(PREMULTIPLIED_DEST_OUT, RecordedBlendMode::PremultipliedDestOut);
     }
 
     pub fn set_blend_mode_multiply(&self) {
-        self.current_blend_state.set(BlendState::MULTIPLY);
+        self.set_blend_state(BlendState::MULTIPLY, RecordedBlendMode::Multiply);
     }
 
     pub fn set_blend_mode_max(&self) {
-        self.current_blend_state.set(MAX);
+        self.set_blend_state(MAX, RecordedBlendMode::Max);
     }
 
     pub fn set_blend_mode_min(&self) {
-        self.current_blend_state.set(MIN);
+        self.set_blend_state(MIN, RecordedBlendMode::Min);
     }
 
     pub fn set_blend_mode_subpixel_pass0(&self) {
-        self.current_blend_state.set(SUBPIXEL_PASS0);
+        self.set_blend_state(SUBPIXEL_PASS0, RecordedBlendMode::SubpixelPass0);
     }
 
     pub fn set_blend_mode_subpixel_pass1(&self) {
-        self.current_blend_state.set(SUBPIXEL_PASS1);
+        self.set_blend_state(SUBPIXEL_PASS1, RecordedBlendMode::SubpixelPass1);
     }
 
     pub fn set_blend_mode_subpixel_with_bg_color_pass0(&self) {
-        self.current_blend_state.set(SUBPIXEL_WITH_BG_COLOR_PASS0);
+        self.set_blend_state(SUBPIXEL_WITH_BG_COLOR_PASS0, RecordedBlendMode::SubpixelWithBgColorPass0);
     }
 
     pub fn set_blend_mode_subpixel_with_bg_color_pass1(&self) {
-        self.current_blend_state.set(SUBPIXEL_WITH_BG_COLOR_PASS1);
+        self.set_blend_state(SUBPIXEL_WITH_BG_COLOR_PASS1, RecordedBlendMode::SubpixelWithBgColorPass1);
     }
 
     pub fn set_blend_mode_subpixel_with_bg_color_pass2(&self) {
-        self.current_blend_state.set(SUBPIXEL_WITH_BG_COLOR_PASS2);
+        self.set_blend_state(SUBPIXEL_WITH_BG_COLOR_PASS2, RecordedBlendMode::SubpixelWithBgColorPass2);
     }
 
     pub fn set_blend_mode_subpixel_constant_text_color(&self, color: ColorF) {
-        self.current_blend_state.set(SUBPIXEL_CONSTANT_TEXT_COLOR);
+        self.set_blend_state(
+            SUBPIXEL_CONSTANT_TEXT_COLOR,
+            RecordedBlendMode::SubpixelConstantTextColor(color),
+        );
         // color is an unpremultiplied color.
         self.blend_color
             .set(ColorF::new(color.r, color.g, color.b, 1.0));
     }
 
     pub fn set_blend_mode_subpixel_dual_source(&self) {
-        self.current_blend_state.set(SUBPIXEL_DUAL_SOURCE);
+        self.set_blend_state(SUBPIXEL_DUAL_SOURCE, RecordedBlendMode::SubpixelDualSource);
     }
 
     pub fn set_blend_mode_show_overdraw(&self) {
-        self.current_blend_state.set(OVERDRAW);
+        self.set_blend_state(OVERDRAW, RecordedBlendMode::ShowOverdraw);
+    }
+
+    fn set_blend_state(&self, state: BlendState, mode: RecordedBlendMode) {
+        self.record(DeviceCommand::SetBlendMode(mode));
+        self.current_blend_state.set(state);
+    }
+
+    fn record(&self, command: DeviceCommand) {
+        if let Some(ref mut stream) = *self.command_recorder.borrow_mut() {
+            stream.push(command);
+        }
+    }
+
+    /// Starts recording the commands submitted to the device into a
+    /// `CommandStream`, replacing any recording in progress.
+    pub fn start_recording(&mut self) {
+        *self.command_recorder.borrow_mut() = Some(CommandStream::new());
+    }
+
+    /// Stops recording and returns the commands recorded since
+    /// `start_recording`.
+    pub fn stop_recording(&mut self) -> Option<CommandStream> {
+        self.command_recorder.borrow_mut().take()
+    }
+
+    /// Re-issues a recorded command stream. This has to be called inside a
+    /// frame, and the resources referenced by the stream have to be alive.
+    ///
+    /// Returns `false` if the replay was aborted because of a missing resource.
+    pub fn replay(&mut self, stream: &CommandStream) -> bool {
+        debug_assert!(self.inside_frame);
+
+        for command in &stream.commands {
+            match *command {
+                DeviceCommand::BindDrawTarget { fbo, texture, dimensions, with_depth } => {
+                    if fbo != DEFAULT_DRAW_FBO && !self.fbos.contains_key(&fbo) {
+                        warn!("Aborting replay, missing draw target {:?}", fbo);
+                        return false;
+                    }
+                    self.bind_draw_target_fbo(fbo, texture, dimensions, with_depth);
+                }
+                DeviceCommand::BindReadTarget(fbo) => {
+                    self.bind_read_target_impl(fbo);
+                }
+                DeviceCommand::BindProgram(program_id) => {
+                    if !self.programs.contains_key(&program_id) {
+                        warn!("Aborting replay, missing program {:?}", program_id);
+                        return false;
+                    }
+                    self.bind_program(&program_id);
+                }
+                DeviceCommand::SetUniforms { program, ref projection } => {
+                    self.set_uniforms(&program, projection);
+                }
+                DeviceCommand::SwitchMode(mode) => {
+                    self.switch_mode(mode);
+                }
+                DeviceCommand::BindTexture { slot, texture, filter } => {
+                    if texture != INVALID_TEXTURE_ID && !self.images.contains_key(&texture) {
+                        warn!("Aborting replay, missing texture {:?}", texture);
+                        return false;
+                    }
+                    self.bind_texture_impl(TextureSlot(slot), texture, filter);
+                }
+                DeviceCommand::BindTextures => {
+                    self.bind_textures();
+                }
+                DeviceCommand::SetBlendMode(mode) => {
+                    match mode {
+                        RecordedBlendMode::Off => self.set_blend(false),
+                        RecordedBlendMode::Alpha => self.set_blend_mode_alpha(),
+                        RecordedBlendMode::PremultipliedAlpha => self.set_blend_mode_premultiplied_alpha(),
+                        RecordedBlendMode::PremultipliedDestOut => self.set_blend_mode_premultiplied_dest_out(),
+                        RecordedBlendMode::Multiply => self.set_blend_mode_multiply(),
+                        RecordedBlendMode::Max => self.set_blend_mode_max(),
+                        RecordedBlendMode::Min => self.set_blend_mode_min(),
+                        RecordedBlendMode::SubpixelPass0 => self.set_blend_mode_subpixel_pass0(),
+                        RecordedBlendMode::SubpixelPass1 => self.set_blend_mode_subpixel_pass1(),
+                        RecordedBlendMode::SubpixelWithBgColorPass0 => self.set_blend_mode_subpixel_with_bg_color_pass0(),
+                        RecordedBlendMode::SubpixelWithBgColorPass1 => self.set_blend_mode_subpixel_with_bg_color_pass1(),
+                        RecordedBlendMode::SubpixelWithBgColorPass2 => self.set_blend_mode_subpixel_with_bg_color_pass2(),
+                        RecordedBlendMode::SubpixelConstantTextColor(color) => {
+                            self.set_blend_mode_subpixel_constant_text_color(color)
+                        }
+                        RecordedBlendMode::SubpixelDualSource => self.set_blend_mode_subpixel_dual_source(),
+                        RecordedBlendMode::ShowOverdraw => self.set_blend_mode_show_overdraw(),
+                    }
+                }
+                DeviceCommand::SetDepthMode(mode) => {
+                    self.set_depth_test(match mode {
+                        RecordedDepthMode::Off => DepthTest::Off,
+                        RecordedDepthMode::Test => LESS_EQUAL_TEST,
+                        RecordedDepthMode::TestAndWrite => LESS_EQUAL_WRITE,
+                    });
+                }
+                DeviceCommand::SetScissor(rect) => {
+                    self.scissor_rect = rect;
+                    self.record(DeviceCommand::SetScissor(rect));
+                }
+                DeviceCommand::SetAnisotropicSampling(enabled) => {
+                    self.set_anisotropic_sampling(enabled);
+                }
+                DeviceCommand::UpdateInstances(ref range) => {
+                    assert_ne!(self.bound_program, INVALID_PROGRAM_ID);
+                    if let Some(ref mut recording) = *self.command_recorder.borrow_mut() {
+                        recording.push_instances(&stream.instance_data[range.clone()]);
+                    }
+                    self.programs
+                        .get_mut(&self.bound_program)
+                        .expect("Program not found.")
+                        .bind_instance_bytes(
+                            &self.device,
+                            &mut self.heaps,
+                            &stream.instance_data[range.clone()],
+                            self.next_id,
+                        );
+                }
+                DeviceCommand::Draw => {
+                    self.draw();
+                }
+                DeviceCommand::Clear { color, depth, rect } => {
+                    self.clear_target(color, depth, rect);
+                }
+                DeviceCommand::Blit { src, dest } => {
+                    self.blit_render_target(src, dest);
+                }
+            }
+        }
+
+        true
+    }
+
+    /// Returns the query state shared with the `GpuProfiler`.
+    pub fn gpu_queries(&self) -> Rc<RefCell<GpuQueries>> {
+        Rc::clone(&self.gpu_queries)
+    }
+
+    /// Sets the number of nanoseconds per timestamp tick, which isn't
+    /// reported by the backend. Defaults to 1, see `DeviceInit`.
+    pub fn set_timestamp_period(&mut self, period_ns: f32) {
+        self.gpu_queries.borrow_mut().timestamp_period = period_ns;
+    }
+
+    fn queries_in_flight(&mut self) -> &mut QueriesInFlight {
+        while self.queries_in_flight.len() <= self.next_id {
+            self.queries_in_flight.push(QueriesInFlight::default());
+        }
+        &mut self.queries_in_flight[self.next_id]
+    }
+
+    /// Records the query commands requested by the profiler since the last
+    /// GPU work, so that they are executed in order with it.
+    fn flush_queries(&mut self) {
+        let commands = mem::replace(&mut self.gpu_queries.borrow_mut().pending, Vec::new());
+        if commands.is_empty() {
+            return;
+        }
+
+        let mut timestamps = Vec::new();
+        for command in commands {
+            match command {
+                QueryCommand::Timestamp(id) => timestamps.push(id),
+                QueryCommand::BeginSampler(id) => self.active_sampler = Some(id),
+                QueryCommand::EndSampler => self.active_sampler = None,
+            }
+        }
+        if timestamps.is_empty() || !self.queries_supported {
+            return;
+        }
+
+        if self.timestamp_pool.is_none() {
+            match unsafe {
+                self.device.create_query_pool(hal::query::Type::Timestamp, MAX_TIMESTAMP_QUERIES)
+            } {
+                Ok(pool) => self.timestamp_pool = Some(pool),
+                Err(err) => {
+                    warn!("Failed to create the timestamp query pool: {:?}", err);
+                    self.queries_supported = false;
+                    return;
+                }
+            }
+        }
+
+        {
+            let pool = self.timestamp_pool.as_ref().unwrap();
+            let cmd_buffer = self.command_pool[self.next_id].acquire_command_buffer();
+            unsafe {
+                cmd_buffer.begin();
+                for &id in &timestamps {
+                    cmd_buffer.reset_query_pool(pool, id .. id + 1);
+                    cmd_buffer.write_timestamp(
+                        PipelineStage::BOTTOM_OF_PIPE,
+                        hal::query::Query { pool, id },
+                    );
+                }
+                cmd_buffer.finish();
+            }
+        }
+        self.queries_in_flight().timestamps.extend(timestamps);
+    }
+
+    /// Allocates an occlusion query for the next draw, if a sampler is active.
+    fn next_occlusion_query(&mut self) -> Option<u32> {
+        let sampler = self.active_sampler?;
+        if self.occlusion_pool.is_none() && self.queries_supported {
+            let count = self.frame_count as u32 * MAX_OCCLUSION_QUERIES_PER_FRAME;
+            match unsafe { self.device.create_query_pool(hal::query::Type::Occlusion, count) } {
+                Ok(pool) => self.occlusion_pool = Some((pool, self.frame_count)),
+                Err(err) => {
+                    warn!("Failed to create the occlusion query pool: {:?}", err);
+                    self.queries_supported = false;
+                }
+            }
+        }
+
+        match self.occlusion_pool {
+            Some((_, frames)) if self.next_id < frames => {}
+            _ => return None,
+        }
+        let base = self.next_id as u32 * MAX_OCCLUSION_QUERIES_PER_FRAME;
+        let in_flight = self.queries_in_flight();
+        if in_flight.occlusions.len() >= MAX_OCCLUSION_QUERIES_PER_FRAME as usize {
+            return None;
+        }
+        let id = base + in_flight.occlusions.len() as u32;
+        in_flight.occlusions.push((id, sampler));
+        Some(id)
+    }
+
+    /// Reads back the queries of the frame whose fence was just waited on.
+    fn resolve_queries(&mut self) {
+        let in_flight = mem::replace(self.queries_in_flight(), QueriesInFlight::default());
+        let mut queries = self.gpu_queries.borrow_mut();
+
+        if let Some(ref pool) = self.timestamp_pool {
+            for id in in_flight.timestamps {
+                if let Some(value) = Self::query_result(&self.device, pool, id) {
+                    queries.timestamps.insert(id, value);
+                }
+            }
+        }
+        if let Some((ref pool, _)) = self.occlusion_pool {
+            for (id, sampler) in in_flight.occlusions {
+                if let Some(value) = Self::query_result(&self.device, pool, id) {
+                    *queries.samples.entry(sampler).or_insert(0) += value;
+                }
+            }
+        }
+    }
+
+    fn query_result(device: &B::Device, pool: &B::QueryPool, id: u32) -> Option<u64> {
+        let mut data = [0u8; 8];
+        match unsafe {
+            device.get_query_pool_results(
+                pool,
+                id .. id + 1,
+                &mut data,
+                8,
+                hal::query::ResultFlags::BITS_64,
+            )
+        } {
+            Ok(true) => Some(u64::from_ne_bytes(data)),
+            Ok(false) => None,
+            Err(err) => {
+                warn!("Failed to read query {}: {:?}", id, err);
+                None
+            }
+        }
     }
 
     pub fn supports_features(&self, features: hal::Features) -> bool {
         self.features.contains(features)
     }
 
+    /// Returns true if pipelines blending with the second output of the
+    /// fragment shader can be created, for single pass subpixel text.
+    pub fn supports_dual_source_blending(&self) -> bool {
+        !self.workarounds.contains(Workarounds::DISABLE_DUAL_SOURCE_BLENDING) &&
+        self.supports_features(hal::Features::DUAL_SRC_BLENDING)
+    }
+
+    /// Returns true if images under a complex transform can be sampled with
+    /// anisotropic filtering, see `set_anisotropic_sampling`.
+    pub fn supports_anisotropic_filtering(&self) -> bool {
+        self.sampler_anisotropic.is_some()
+    }
+
+    /// Returns true if images of the given compressed format can be uploaded
+    /// and sampled with linear filtering.
+    pub fn supports_compressed_format(&self, format: ImageFormat) -> bool {
+        format.is_compressed() &&
+            self.adapter.physical_device
+                .format_properties(Some(hal_format(format)))
+                .optimal_tiling
+                .contains(hal::format::ImageFeature::SAMPLED_LINEAR)
+    }
+
     pub fn echo_driver_messages(&self) {
         warn!("echo_driver_messages is unimplemeneted");
     }
@@ -3417,11 +5255,14 @@ impl<B: hal::Backend> Device<B> {
         unsafe {
             match self.swap_chain.as_mut() {
                 Some(swap_chain) => {
-                    match swap_chain.acquire_image(
+                    let acquire_start = precise_time_ns();
+                    let acquired = swap_chain.acquire_image(
                         !0,
                         Some(&mut self.image_available_semaphore),
                         None,
-                    ) {
+                    );
+                    self.frame_timings.acquire_time += precise_time_ns() - acquire_start;
+                    match acquired {
                         Ok((id, _)) => {
                             self.current_frame_id = id as _;
                             let cmd_buffer = self.command_pool[self.next_id].acquire_command_buffer();
@@ -3441,6 +5282,26 @@ impl<B: hal::Backend> Device<B> {
                                     &[barrier],
                                 );
                             }
+                            // The output target was sampled by the composite
+                            // of the last frame that used it.
+                            if let Some(texture_id) = self.output_targets.get(self.current_frame_id) {
+                                let image = &self.images[texture_id].core;
+                                let mut src_stage = Some(PipelineStage::empty());
+                                if let Some(barrier) = image.transit(
+                                    hal::image::Access::COLOR_ATTACHMENT_READ
+                                        | hal::image::Access::COLOR_ATTACHMENT_WRITE,
+                                    hal::image::Layout::ColorAttachmentOptimal,
+                                    image.subresource_range.clone(),
+                                    src_stage.as_mut(),
+                                ) {
+                                    cmd_buffer.pipeline_barrier(
+                                        src_stage.unwrap()
+                                            .. PipelineStage::COLOR_ATTACHMENT_OUTPUT,
+                                        hal::memory::Dependencies::empty(),
+                                        &[barrier],
+                                    );
+                                }
+                            }
                             let depth_image = &self.frame_depths[self.current_frame_id].core;
                             if let Some(barrier) = depth_image.transit(
                                 hal::image::Access::DEPTH_STENCIL_ATTACHMENT_READ
@@ -3461,7 +5322,10 @@ impl<B: hal::Backend> Device<B> {
                         Err(acq_err) => {
                             match acq_err {
                                 AcquireError::OutOfDate => warn!("AcquireError : OutOfDate"),
-                                AcquireError::SurfaceLost(surf) => warn!("AcquireError : SurfaceLost => {:?}", surf),
+                                AcquireError::SurfaceLost(surf) => {
+                                    warn!("AcquireError : SurfaceLost => {:?}", surf);
+                                    self.surface_lost = true;
+                                }
                                 AcquireError::NotReady => warn!("AcquireError : NotReady"),
                                 AcquireError::DeviceLost(dev) => warn!("AcquireError : DeviceLost => {:?}", dev),
                                 AcquireError::OutOfMemory(mem) => warn!("AcquireError : OutOfMemory => {:?}", mem),
@@ -3479,10 +5343,16 @@ impl<B: hal::Backend> Device<B> {
 
     pub fn submit_to_gpu(&mut self) {
         if self.wait_for_resize {
+            self.gpu_queries.borrow_mut().pending.clear();
+            self.active_sampler = None;
             self.device.wait_idle().unwrap();
             self.reset_next_frame_resources();
             return;
         }
+        self.flush_queries();
+        if !self.output_targets.is_empty() {
+            self.composite_output();
+        }
         {
             let cmd_buffer = self.command_pool[self.next_id].acquire_command_buffer();
             let image = &self.frame_images[self.current_frame_id];
@@ -3520,31 +5390,37 @@ impl<B: hal::Backend> Device<B> {
                     self.frame_fence[self.next_id].is_submitted = true;
 
                     // present frame
-                    match swap_chain
+                    let present_start = precise_time_ns();
+                    let presented = swap_chain
                         .present(
                             &mut self.queue_group.queues[0],
                             self.current_frame_id as _,
                             Some(&self.render_finished_semaphore),
-                        ) {
-                            Ok(suboptimal) => {
-                                match suboptimal {
-                                    Some(_) => {
-                                        warn!("Suboptimal: The swapchain no longer matches the surface");
-                                        self.wait_for_resize = true;
-                                    },
-                                    None => {}
-                                }
+                        );
+                    self.frame_timings.present_time += precise_time_ns() - present_start;
+                    match presented {
+                        Ok(suboptimal) => {
+                            match suboptimal {
+                                Some(_) => {
+                                    warn!("Suboptimal: The swapchain no longer matches the surface");
+                                    self.wait_for_resize = true;
+                                },
+                                None => {}
                             }
-                            Err(presenterr) => {
-                                match presenterr {
-                                    PresentError::OutOfDate => warn!("PresentError : OutOfDate"),
-                                    PresentError::SurfaceLost(surf) => warn!("PresentError : SurfaceLost => {:?}", surf),
-                                    PresentError::DeviceLost(dev) => warn!("PresentError : DeviceLost => {:?}", dev),
-                                    PresentError::OutOfMemory(mem) => warn!("PresentError : OutOfMemory => {:?}", mem),
+                        }
+                        Err(presenterr) => {
+                            match presenterr {
+                                PresentError::OutOfDate => warn!("PresentError : OutOfDate"),
+                                PresentError::SurfaceLost(surf) => {
+                                    warn!("PresentError : SurfaceLost => {:?}", surf);
+                                    self.surface_lost = true;
                                 }
-                                self.wait_for_resize = true;
+                                PresentError::DeviceLost(dev) => warn!("PresentError : DeviceLost => {:?}", dev),
+                                PresentError::OutOfMemory(mem) => warn!("PresentError : OutOfMemory => {:?}", mem),
                             }
+                            self.wait_for_resize = true;
                         }
+                    }
                 }
                 None => {
                     self.queue_group.queues[0].submit_nosemaphores(
@@ -3574,6 +5450,42 @@ impl<B: hal::Backend> Device<B> {
         }
     }
 
+    /// Submits the commands recorded outside of a frame, like texture
+    /// uploads, then waits up to `timeout_ns` for the GPU to finish the work
+    /// of every frame in flight. Returns false if the GPU didn't finish in
+    /// time, in which case the frame resources are left untouched.
+    pub fn wait_idle(&mut self, timeout_ns: u64) -> bool {
+        debug_assert!(!self.inside_frame);
+        if !self.command_pool[self.next_id].command_buffers().is_empty() {
+            unsafe {
+                self.queue_group.queues[0].submit_nosemaphores(
+                    self.command_pool[self.next_id].command_buffers(),
+                    Some(&mut self.frame_fence[self.next_id].inner),
+                );
+            }
+            self.frame_fence[self.next_id].is_submitted = true;
+        }
+        let deadline = precise_time_ns().saturating_add(timeout_ns);
+        for fence in &mut self.frame_fence {
+            if !fence.is_submitted {
+                continue;
+            }
+            let remaining = deadline.saturating_sub(precise_time_ns());
+            match unsafe { self.device.wait_for_fence(&fence.inner, remaining) } {
+                Ok(true) => {}
+                Ok(false) => return false,
+                Err(err) => {
+                    warn!("wait_for_fence failed: {:?}", err);
+                    return false;
+                }
+            }
+            unsafe { self.device.reset_fence(&fence.inner) }.expect("reset_fence failed");
+            fence.is_submitted = false;
+        }
+        self.reset_command_pools();
+        true
+    }
+
     fn reset_command_pools(&mut self) {
         for command_pool in &mut self.command_pool {
             unsafe { command_pool.reset() };
@@ -3581,16 +5493,65 @@ impl<B: hal::Backend> Device<B> {
     }
 
     /// Generates a memory report for the resources managed by the device layer.
+    /// Returns and resets the time spent blocking since the previous call.
+    pub fn take_frame_timings(&mut self) -> FrameTimings {
+        mem::replace(&mut self.frame_timings, FrameTimings::default())
+    }
+
+    /// Frees the pooled resources which aren't needed to render the next
+    /// frame, in response to a low memory signal of the OS: the instance
+    /// buffers grown by heavier frames and the unused depth targets. This
+    /// waits for the GPU to be idle, since the resources of the frames in
+    /// flight are released too.
+    pub fn on_memory_pressure(&mut self) {
+        self.device.wait_idle().expect("wait_idle failed");
+        for program in self.programs.values_mut() {
+            for instance_buffer in program.instance_buffer.iter_mut() {
+                instance_buffer.trim(&self.device, &mut self.heaps);
+            }
+        }
+        self.trim_depth_targets(0);
+    }
+
     pub fn report_memory(&self) -> MemoryReport {
         let mut report = MemoryReport::default();
         for dim in self.depth_targets.keys() {
             report.depth_target_textures += depth_target_size_in_bytes(dim);
         }
+        for depth in &self.frame_depths {
+            if let Some(ref block) = depth.core.memory_block {
+                report.depth_target_textures += block.size() as usize;
+            }
+        }
+
+        // The images of a swapchain aren't allocated by us, so estimate
+        // their size from the viewport and the format.
+        if let Some(ref render_pass) = self.render_pass {
+            let bytes_per_pixel = render_pass.main_format.surface_desc().bits as usize / 8;
+            let pixels = self.viewport.rect.w as usize * self.viewport.rect.h as usize;
+            for image in &self.frame_images {
+                report.swap_chain += match image.memory_block {
+                    Some(ref block) => block.size() as usize,
+                    None => pixels * bytes_per_pixel,
+                };
+            }
+        }
+
+        for program in self.programs.values() {
+            program.report_memory(&mut report);
+        }
+        for staging_buffer_pool in &self.staging_buffer_pool {
+            report.upload_buffers += staging_buffer_pool.size_in_bytes();
+        }
+        for readback in self.pending_readbacks.values() {
+            report.upload_buffers += readback.buffer.buffer_size;
+        }
         report
     }
 
     pub fn deinit(mut self) {
         self.device.wait_idle().unwrap();
+        self.deinit_readbacks();
         for mut texture in self.retained_textures {
             texture.id = 0;
         }
@@ -3661,8 +5622,14 @@ impl<B: hal::Backend> Device<B> {
             for framebuffer_depth in self.framebuffers_depth {
                 self.device.destroy_framebuffer(framebuffer_depth);
             }
+            for framebuffer in self.output_framebuffers {
+                self.device.destroy_framebuffer(framebuffer);
+            }
             self.device.destroy_sampler(self.sampler_linear);
             self.device.destroy_sampler(self.sampler_nearest);
+            if let Some(sampler) = self.sampler_anisotropic {
+                self.device.destroy_sampler(sampler);
+            }
             if let Some(dp) = self.desc_pool_locals {
                 dp.deinit(&self.device);
             }
@@ -3693,6 +5660,12 @@ impl<B: hal::Backend> Device<B> {
             for fence in self.frame_fence {
                 self.device.destroy_fence(fence.inner);
             }
+            if let Some(pool) = self.timestamp_pool {
+                self.device.destroy_query_pool(pool);
+            }
+            if let Some((pool, _)) = self.occlusion_pool {
+                self.device.destroy_query_pool(pool);
+            }
             self.device
                 .destroy_semaphore(self.image_available_semaphore);
             self.device
@@ -3723,60 +5696,29 @@ impl<'a, B: hal::Backend> TextureUploader<'a, B> {
         let data = unsafe {
             slice::from_raw_parts(data.as_ptr() as *const u8, data.len() * mem::size_of::<T>())
         };
+        if self.texture.format.is_compressed() {
+            return self.upload_compressed(rect, layer_index, stride, data);
+        }
         let data_stride: usize = self.texture.format.bytes_per_pixel() as usize;
-        let width = rect.size.width as usize;
-        let height = rect.size.height as usize;
-        let size = width * height * data_stride;
-        let mut new_data = vec![0u8; size];
-        let data = if stride.is_some() {
-            let row_length = (stride.unwrap()) as usize;
-
-            if data_stride == 4 {
-                for j in 0 .. height {
-                    for i in 0 .. width {
-                        let offset = i * data_stride + j * data_stride * width;
-                        let src = &data[j * row_length + i * data_stride ..];
-                        assert!(
-                            offset + 3 < new_data.len(),
-                            "offset={:?}, data len={:?}, data stride={:?}",
-                            offset,
-                            new_data.len(),
-                            data_stride,
-                        ); // optimization
-                           // convert from BGRA
-                        new_data[offset + 0] = src[0];
-                        new_data[offset + 1] = src[1];
-                        new_data[offset + 2] = src[2];
-                        new_data[offset + 3] = src[3];
-                    }
-                }
-            } else {
-                for j in 0 .. height {
-                    for i in 0 .. width {
-                        let offset = i * data_stride + j * data_stride * width;
-                        let src = &data[j * row_length + i * data_stride ..];
-                        for i in 0 .. data_stride {
-                            new_data[offset + i] = src[i];
-                        }
-                    }
-                }
+        let row_size = rect.size.width as usize * data_stride;
+        let rows = rect.size.height as usize;
+        let size = row_size * rows;
+        let data = match pack_rows(data, row_size, rows, stride) {
+            Some(data) => data,
+            None => {
+                warn!(
+                    "Skipping upload of {:?} to texture {}, {} bytes with stride {:?} are too short",
+                    rect,
+                    self.texture.id,
+                    data.len(),
+                    stride,
+                );
+                return 0;
             }
-
-            new_data.as_slice()
-        } else {
-            data
-        };
-        assert_eq!(
-            data.len(),
-            width * height * data_stride,
-            "data len = {}, width = {}, height = {}, data stride = {}",
-            data.len(),
-            width,
-            height,
-            data_stride
-        );
+        };
 
         self.texture.bound_in_frame.set(self.device.frame_id);
+        device_log!(uploads, Debug, "texture {} <- {:?} layer {}", self.texture.id, rect, layer_index);
         self.device
             .images
             .get_mut(&self.texture.id)
@@ -3787,7 +5729,7 @@ impl<'a, B: hal::Backend> TextureUploader<'a, B> {
                 &mut self.device.staging_buffer_pool[self.device.next_id],
                 rect,
                 layer_index,
-                data,
+                &data,
             );
 
         if self.texture.filter == TextureFilter::Trilinear {
@@ -3795,6 +5737,50 @@ impl<'a, B: hal::Backend> TextureUploader<'a, B> {
         }
         size
     }
+
+    /// Uploads the blocks of a compressed texture covering `rect`, whose
+    /// origin is on the corner of a block. Padded rows get packed tightly.
+    fn upload_compressed(
+        &mut self,
+        rect: DeviceIntRect,
+        layer_index: i32,
+        stride: Option<i32>,
+        data: &[u8],
+    ) -> usize {
+        let format = self.texture.format;
+        let row_size = format.row_size_in_bytes(rect.size.width) as usize;
+        let rows = format.block_rows(rect.size.height) as usize;
+        let size = row_size * rows;
+        let data = match pack_rows(data, row_size, rows, stride) {
+            Some(data) => data,
+            None => {
+                warn!(
+                    "Skipping compressed upload of {:?} to texture {}, {} bytes with stride {:?} are too short",
+                    rect,
+                    self.texture.id,
+                    data.len(),
+                    stride,
+                );
+                return 0;
+            }
+        };
+
+        self.texture.bound_in_frame.set(self.device.frame_id);
+        device_log!(uploads, Debug, "texture {} <- {:?} layer {} compressed", self.texture.id, rect, layer_index);
+        self.device
+            .images
+            .get_mut(&self.texture.id)
+            .expect("Texture not found.")
+            .update(
+                &self.device.device,
+                &mut self.device.command_pool[self.device.next_id],
+                &mut self.device.staging_buffer_pool[self.device.next_id],
+                rect,
+                layer_index,
+                &data,
+            );
+        size
+    }
 }
 
 fn texels_to_u8_slice<T: Texel>(texels: &[T]) -> &[u8] {
@@ -3805,3 +5791,47 @@ fn texels_to_u8_slice<T: Texel>(texels: &[T]) -> &[u8] {
         )
     }
 }
+
+/// Returns `rows` rows of `row_size` bytes, starting `stride` bytes apart in
+/// `data`, packed tightly. Raw data of external images may extend past the
+/// last row, as it is read from an offset into the whole image buffer.
+/// Returns `None` if `data` is too short to hold the rows.
+fn pack_rows(data: &[u8], row_size: usize, rows: usize, stride: Option<i32>) -> Option<Cow<[u8]>> {
+    if rows == 0 {
+        return Some(Cow::Borrowed(&[]));
+    }
+    let stride = stride.map_or(row_size, |stride| stride as usize);
+    if stride < row_size || data.len() < (rows - 1) * stride + row_size {
+        return None;
+    }
+    if stride == row_size {
+        return Some(Cow::Borrowed(&data[.. rows * row_size]));
+    }
+    Some(Cow::Owned(
+        (0 .. rows)
+            .flat_map(|row| &data[row * stride .. row * stride + row_size])
+            .cloned()
+            .collect(),
+    ))
+}
+
+#[cfg(test)]
+mod test {
+    use super::pack_rows;
+
+    #[test]
+    fn pack_rows_ignores_data_past_the_rect() {
+        let data: Vec<u8> = (0 .. 16).collect();
+        assert_eq!(&*pack_rows(&data, 4, 2, None).unwrap(), &data[.. 8]);
+        assert_eq!(&*pack_rows(&data, 2, 3, Some(5)).unwrap(), &[0, 1, 5, 6, 10, 11]);
+    }
+
+    #[test]
+    fn pack_rows_rejects_short_data() {
+        let data: Vec<u8> = (0 .. 16).collect();
+        assert!(pack_rows(&data, 4, 5, None).is_none());
+        assert!(pack_rows(&data, 4, 3, Some(7)).is_none());
+        assert!(pack_rows(&data, 4, 2, Some(3)).is_none());
+        assert_eq!(&*pack_rows(&data, 4, 3, Some(6)).unwrap(), &[0, 1, 2, 3, 6, 7, 8, 9, 12, 13, 14, 15]);
+    }
+}
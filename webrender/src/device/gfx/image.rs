@@ -104,6 +104,14 @@ impl<B: hal::Backend> ImageCore<B> {
         }
     }
 
+    /// Destroys the view of an image the device doesn't own, and gives the
+    /// image back.
+    pub(super) fn into_raw(self, device: &B::Device) -> B::Image {
+        debug_assert!(self.memory_block.is_none());
+        unsafe { device.destroy_image_view(self.view) };
+        self.image
+    }
+
     fn _reset(&self) {
         self.state
             .set((hal::image::Access::empty(), hal::image::Layout::Undefined));
@@ -152,6 +160,27 @@ impl<B: hal::Backend> ImageCore<B> {
     }
 }
 
+/// The format textures of `format` are created with.
+pub(super) fn hal_format(format: ImageFormat) -> hal::format::Format {
+    match format {
+        ImageFormat::R8 => hal::format::Format::R8Unorm,
+        ImageFormat::R16 => hal::format::Format::R16Unorm,
+        ImageFormat::RG8 => hal::format::Format::Rg8Unorm,
+        ImageFormat::RG16 => hal::format::Format::Rg16Unorm,
+        ImageFormat::RGBA8 => hal::format::Format::Rgba8Unorm,
+        ImageFormat::BGRA8 => hal::format::Format::Bgra8Unorm,
+        ImageFormat::RGBAF32 => hal::format::Format::Rgba32Sfloat,
+        ImageFormat::RGBAF16 => hal::format::Format::Rgba16Sfloat,
+        ImageFormat::RGB10A2 => hal::format::Format::A2b10g10r10UnormPack32,
+        ImageFormat::RGBAI32 => hal::format::Format::Rgba32Sint,
+        ImageFormat::BC1 => hal::format::Format::Bc1RgbaUnorm,
+        ImageFormat::BC3 => hal::format::Format::Bc3Unorm,
+        ImageFormat::BC7 => hal::format::Format::Bc7Unorm,
+        ImageFormat::ETC2 => hal::format::Format::Etc2R8g8b8a8Unorm,
+        ImageFormat::ASTC4x4 => hal::format::Format::Astc4x4Unorm,
+    }
+}
+
 pub(super) struct Image<B: hal::Backend> {
     pub(super) core: ImageCore<B>,
     pub(super) kind: hal::image::Kind,
@@ -170,15 +199,7 @@ impl<B: hal::Backend> Image<B> {
         mip_levels: hal::image::Level,
         usage: hal::image::Usage,
     ) -> Self {
-        let format = match image_format {
-            ImageFormat::R8 => hal::format::Format::R8Unorm,
-            ImageFormat::R16 => hal::format::Format::R16Unorm,
-            ImageFormat::RG8 => hal::format::Format::Rg8Unorm,
-            ImageFormat::RGBA8 => hal::format::Format::Rgba8Unorm,
-            ImageFormat::BGRA8 => hal::format::Format::Bgra8Unorm,
-            ImageFormat::RGBAF32 => hal::format::Format::Rgba32Sfloat,
-            ImageFormat::RGBAI32 => hal::format::Format::Rgba32Sint,
-        };
+        let format = hal_format(image_format);
         let kind = hal::image::Kind::D2(image_width as _, image_height as _, image_depth as _, 1);
 
         let core = ImageCore::create(
@@ -203,6 +224,36 @@ impl<B: hal::Backend> Image<B> {
         }
     }
 
+    /// Wraps a single layer image the device doesn't own, which is expected
+    /// to be ready to be sampled.
+    pub(super) fn from_raw(
+        device: &B::Device,
+        image: B::Image,
+        image_format: ImageFormat,
+        image_width: i32,
+        image_height: i32,
+    ) -> Self {
+        let kind = hal::image::Kind::D2(image_width as _, image_height as _, 1, 1);
+        let core = ImageCore::from_image(
+            device,
+            image,
+            hal::image::ViewKind::D2Array,
+            hal_format(image_format),
+            hal::image::SubresourceRange {
+                aspects: hal::format::Aspects::COLOR,
+                levels: 0 .. 1,
+                layers: 0 .. 1,
+            },
+        );
+        core.state.set((hal::image::Access::SHADER_READ, hal::image::Layout::ShaderReadOnlyOptimal));
+
+        Image {
+            core,
+            kind,
+            format: image_format,
+        }
+    }
+
     pub(super) fn update(
         &self,
         device: &B::Device,
@@ -211,12 +262,65 @@ impl<B: hal::Backend> Image<B> {
         rect: DeviceIntRect,
         layer_index: i32,
         image_data: &[u8],
+    ) {
+        self.update_regions(
+            device,
+            cmd_pool,
+            staging_buffer_pool,
+            &[rect],
+            layer_index,
+            image_data,
+        )
+    }
+
+    /// Copies tightly packed texels into several rects of a layer at once.
+    /// The data of the rects follows each other in `image_data`, in order.
+    pub(super) fn update_regions(
+        &self,
+        device: &B::Device,
+        cmd_pool: &mut CommandPool<B>,
+        staging_buffer_pool: &mut BufferPool<B>,
+        rects: &[DeviceIntRect],
+        layer_index: i32,
+        image_data: &[u8],
     ) {
         use hal::pso::PipelineStage;
-        let pos = rect.origin;
-        let size = rect.size;
-        staging_buffer_pool.add(device, image_data, self.format.bytes_per_pixel() as usize - 1);
+        // The offsets of compressed data have to be aligned to whole blocks.
+        let bytes_per_block = self.format.bytes_per_block() as usize;
+        let block_size = self.format.block_size();
+        staging_buffer_pool.add(device, image_data, bytes_per_block - 1);
         let buffer = staging_buffer_pool.buffer();
+
+        let mut offset = staging_buffer_pool.buffer_offset;
+        let regions = rects
+            .iter()
+            .map(|rect| {
+                let region = hal::command::BufferImageCopy {
+                    buffer_offset: offset as _,
+                    buffer_width: ((rect.size.width + block_size - 1) / block_size * block_size) as _,
+                    buffer_height: ((rect.size.height + block_size - 1) / block_size * block_size) as _,
+                    image_layers: hal::image::SubresourceLayers {
+                        aspects: hal::format::Aspects::COLOR,
+                        level: 0,
+                        layers: layer_index as _ .. (layer_index + 1) as _,
+                    },
+                    image_offset: hal::image::Offset {
+                        x: rect.origin.x as i32,
+                        y: rect.origin.y as i32,
+                        z: 0,
+                    },
+                    image_extent: hal::image::Extent {
+                        width: rect.size.width as u32,
+                        height: rect.size.height as u32,
+                        depth: 1,
+                    },
+                };
+                offset += self.format.size_in_bytes(rect.size.width, rect.size.height);
+                region
+            })
+            .collect::<Vec<_>>();
+        debug_assert_eq!(offset - staging_buffer_pool.buffer_offset, image_data.len());
+
         let cmd_buffer = cmd_pool.acquire_command_buffer();
 
         unsafe {
@@ -244,26 +348,7 @@ impl<B: hal::Backend> Image<B> {
                 &buffer.buffer,
                 &self.core.image,
                 hal::image::Layout::TransferDstOptimal,
-                &[hal::command::BufferImageCopy {
-                    buffer_offset: staging_buffer_pool.buffer_offset as _,
-                    buffer_width: size.width as _,
-                    buffer_height: size.height as _,
-                    image_layers: hal::image::SubresourceLayers {
-                        aspects: hal::format::Aspects::COLOR,
-                        level: 0,
-                        layers: layer_index as _ .. (layer_index + 1) as _,
-                    },
-                    image_offset: hal::image::Offset {
-                        x: pos.x as i32,
-                        y: pos.y as i32,
-                        z: 0,
-                    },
-                    image_extent: hal::image::Extent {
-                        width: size.width as u32,
-                        height: size.height as u32,
-                        depth: 1,
-                    },
-                }],
+                &regions,
             );
 
             if let Some(barrier) = self.core.transit(
@@ -312,9 +397,11 @@ impl<B: hal::Backend> Framebuffer<B> {
             height: texture.size.height as _,
             depth: 1,
         };
+        // Only the formats `RenderPass` has passes for can be rendered to.
         let format = match texture.format {
-            ImageFormat::R8 => hal::format::Format::R8Unorm,
-            ImageFormat::BGRA8 => hal::format::Format::Bgra8Unorm,
+            ImageFormat::R8 |
+            ImageFormat::RG8 |
+            ImageFormat::BGRA8 => hal_format(texture.format),
             f => unimplemented!("TODO image format missing {:?}", f),
         };
         let image_view = unsafe {
@@ -5,14 +5,18 @@
 mod blend_state;
 mod buffer;
 mod command;
+mod command_stream;
 mod descriptor;
 mod device;
 mod image;
 mod program;
 mod render_pass;
+mod validation;
 pub(crate) mod vertex_types;
 
+pub use self::command_stream::*;
 pub use self::device::*;
+pub use self::validation::*;
 
 use gpu_types;
 use hal;
@@ -137,6 +141,21 @@ impl PrimitiveType for gpu_types::ScalingInstance {
     }
 }
 
+impl PrimitiveType for gpu_types::SvgFilterInstance {
+    type Primitive = vertex_types::SvgFilterInstance;
+    fn to_primitive_type(&self) -> vertex_types::SvgFilterInstance {
+        vertex_types::SvgFilterInstance {
+            aData: [0, 0, 0, 0],
+            aFilterRenderTaskAddress: self.task_address.0 as i32,
+            aFilterInput1TaskAddress: self.input_1_task_address.0 as i32,
+            aFilterInput2TaskAddress: self.input_2_task_address.0 as i32,
+            aFilterKind: self.kind as i32,
+            aFilterGenericInt: self.generic_int as i32,
+            aFilterExtraDataAddress: self.extra_data_address,
+        }
+    }
+}
+
 impl PrimitiveType for tiling::LineDecorationJob {
     type Primitive = vertex_types::LineDecorationInstance;
     fn to_primitive_type(&self) -> vertex_types::LineDecorationInstance {
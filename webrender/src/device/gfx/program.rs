@@ -2,7 +2,7 @@
  * License, v. 2.0. If a copy of the MPL was not distributed with this
  * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
 
-use api::{ColorF, DeviceIntRect, ImageFormat};
+use api::{ColorF, DeviceIntRect, ImageFormat, MemoryReport};
 use hal::{self, Device as BackendDevice};
 use internal_types::FastHashMap;
 use smallvec::SmallVec;
@@ -29,6 +29,10 @@ pub(super) const PUSH_CONSTANT_BLOCK_SIZE: usize = 17; // 68 / 4
 const SPECIALIZATION_CONSTANT_COUNT: usize = 5;
 // Size of a specialization constant variable in bytes.
 const SPECIALIZATION_CONSTANT_SIZE: usize = 4;
+// The id of the `output_color_transform` specialization constant, which
+// follows the feature constants and is only set for the main framebuffer
+// pipelines of the program compositing the frame into the swapchain.
+const OUTPUT_TRANSFORM_CONSTANT_ID: usize = SPECIALIZATION_CONSTANT_COUNT;
 const SPECIALIZATION_FEATURES: &'static [&'static [&'static str]] = &[
     &["ALPHA_PASS"],
     &["COLOR_TARGET"],
@@ -57,10 +61,39 @@ const QUAD: [vertex_types::Vertex; 6] = [
     },
 ];
 
+fn shader_set<'a, B: hal::Backend>(
+    vs_module: &'a B::ShaderModule,
+    fs_module: &'a B::ShaderModule,
+    constants: &'a [hal::pso::SpecializationConstant],
+    specialization_data: &'a [u8],
+) -> hal::pso::GraphicsShaderSet<'a, B> {
+    let specialization = hal::pso::Specialization {
+        constants: Borrowed(constants),
+        data: Borrowed(specialization_data),
+    };
+    hal::pso::GraphicsShaderSet {
+        vertex: hal::pso::EntryPoint {
+            entry: ENTRY_NAME,
+            module: vs_module,
+            specialization: specialization.clone(),
+        },
+        hull: None,
+        domain: None,
+        geometry: None,
+        fragment: Some(hal::pso::EntryPoint {
+            entry: ENTRY_NAME,
+            module: fs_module,
+            specialization,
+        }),
+    }
+}
 
 pub(crate) struct Program<B: hal::Backend> {
     bindings_map: FastHashMap<String, u32>,
     pipelines: FastHashMap<(hal::pso::BlendState, hal::pso::DepthTest), B::GraphicsPipeline>,
+    /// Variants of the pipelines for the main framebuffer, if its format
+    /// isn't compatible with the offscreen targets.
+    main_pipelines: FastHashMap<(hal::pso::BlendState, hal::pso::DepthTest), B::GraphicsPipeline>,
     pub(super) vertex_buffer: SmallVec<[VertexBufferHandler<B>; 1]>,
     pub(super) index_buffer: Option<SmallVec<[VertexBufferHandler<B>; 1]>>,
     pub(super) instance_buffer: SmallVec<[InstanceBufferHandler<B>; 1]>,
@@ -85,6 +118,7 @@ impl<B: hal::Backend> Program<B> {
         shader_modules: &mut FastHashMap<String, (B::ShaderModule, B::ShaderModule)>,
         pipeline_cache: Option<&B::PipelineCache>,
         surface_format: ImageFormat,
+        output_transform: u8,
     ) -> Program<B> {
         if !shader_modules.contains_key(shader_name) {
             let vs_file = format!("{}.vert.spv", shader_name);
@@ -111,49 +145,33 @@ impl<B: hal::Backend> Program<B> {
 
         let (vs_module, fs_module) = shader_modules.get(shader_name).unwrap();
 
-        let mut constants = Vec::with_capacity(SPECIALIZATION_CONSTANT_COUNT);
+        let mut constants = Vec::with_capacity(SPECIALIZATION_CONSTANT_COUNT + 1);
         let mut specialization_data =
-            vec![0; SPECIALIZATION_CONSTANT_COUNT * SPECIALIZATION_CONSTANT_SIZE];
-        for i in 0 .. SPECIALIZATION_CONSTANT_COUNT {
+            vec![0; (SPECIALIZATION_CONSTANT_COUNT + 1) * SPECIALIZATION_CONSTANT_SIZE];
+        for i in 0 .. SPECIALIZATION_CONSTANT_COUNT + 1 {
             constants.push(hal::pso::SpecializationConstant {
                 id: i as _,
                 range: (SPECIALIZATION_CONSTANT_SIZE * i) as _
                     .. (SPECIALIZATION_CONSTANT_SIZE * (i + 1)) as _,
             });
+            if i == OUTPUT_TRANSFORM_CONSTANT_ID {
+                continue;
+            }
             for (index, feature) in SPECIALIZATION_FEATURES[i].iter().enumerate() {
                 if features.contains(feature) {
                     specialization_data[SPECIALIZATION_CONSTANT_SIZE * i] = (index + 1) as u8;
                 }
             }
         }
+        let mut main_specialization_data = specialization_data.clone();
+        main_specialization_data[SPECIALIZATION_CONSTANT_SIZE * OUTPUT_TRANSFORM_CONSTANT_ID] =
+            output_transform;
 
-        let pipelines = {
-            let (vs_entry, fs_entry) = (
-                hal::pso::EntryPoint::<B> {
-                    entry: ENTRY_NAME,
-                    module: &vs_module,
-                    specialization: hal::pso::Specialization {
-                        constants: Borrowed(&constants),
-                        data: Borrowed(&specialization_data.as_slice()),
-                    },
-                },
-                hal::pso::EntryPoint::<B> {
-                    entry: ENTRY_NAME,
-                    module: &fs_module,
-                    specialization: hal::pso::Specialization {
-                        constants: Borrowed(&constants),
-                        data: Borrowed(&specialization_data.as_slice()),
-                    },
-                },
-            );
-
-            let shader_entries = hal::pso::GraphicsShaderSet {
-                vertex: vs_entry,
-                hull: None,
-                domain: None,
-                geometry: None,
-                fragment: Some(fs_entry),
-            };
+        let (pipelines, main_pipelines) = {
+            let shader_entries =
+                shader_set::<B>(vs_module, fs_module, &constants, &specialization_data);
+            let main_shader_entries =
+                shader_set::<B>(vs_module, fs_module, &constants, &main_specialization_data);
 
             use hal::pso::{BlendState, DepthTest};
             use super::blend_state::*;
@@ -170,6 +188,9 @@ impl<B: hal::Backend> Program<B> {
                     (BlendState::Off, LESS_EQUAL_TEST),
                 ]
                 .into_iter(),
+                ShaderKind::Cache(VertexArrayKind::SvgFilter) => {
+                    [(BlendState::Off, DepthTest::Off)].into_iter()
+                }
                 ShaderKind::Cache(VertexArrayKind::Border)
                 | ShaderKind::Cache(VertexArrayKind::LineDecoration) => {
                     [(BlendState::PREMULTIPLIED_ALPHA, DepthTest::Off)].into_iter()
@@ -219,6 +240,11 @@ impl<B: hal::Backend> Program<B> {
                 ShaderKind::DebugColor | ShaderKind::DebugFont => {
                     [(BlendState::PREMULTIPLIED_ALPHA, DepthTest::Off)].into_iter()
                 }
+                ShaderKind::Blit => [
+                    (BlendState::Off, DepthTest::Off),
+                    (BlendState::Off, LESS_EQUAL_TEST),
+                ]
+                .into_iter(),
                 _ => [
                     (BlendState::Off, DepthTest::Off),
                     (BlendState::Off, LESS_EQUAL_TEST),
@@ -244,17 +270,26 @@ impl<B: hal::Backend> Program<B> {
                 ShaderKind::Cache(VertexArrayKind::Scale) if features.contains(&"ALPHA_TARGET") => {
                     ImageFormat::R8
                 }
+                ShaderKind::Blit if features.contains(&"ALPHA_TARGET") => ImageFormat::R8,
                 _ => surface_format,
             };
 
-            let create_desc = |(blend_state, depth_test)| {
+            let create_desc = |(blend_state, depth_test), main_framebuffer: bool| {
+                let depth_enabled = depth_test != hal::pso::DepthTest::Off;
                 let subpass = hal::pass::Subpass {
                     index: 0,
-                    main_pass: render_pass
-                        .get_render_pass(format, depth_test != hal::pso::DepthTest::Off),
+                    main_pass: if main_framebuffer {
+                        render_pass.get_main_render_pass(depth_enabled)
+                    } else {
+                        render_pass.get_render_pass(format, depth_enabled)
+                    },
                 };
                 let mut pipeline_descriptor = hal::pso::GraphicsPipelineDesc::new(
-                    shader_entries.clone(),
+                    if main_framebuffer {
+                        main_shader_entries.clone()
+                    } else {
+                        shader_entries.clone()
+                    },
                     hal::Primitive::TriangleList,
                     hal::pso::Rasterizer::FILL,
                     &pipeline_layout,
@@ -281,28 +316,49 @@ impl<B: hal::Backend> Program<B> {
                 pipeline_descriptor
             };
 
-            let pipelines_descriptors = pipeline_states.clone().map(|ps| create_desc(*ps));
+            let pipelines_descriptors = pipeline_states.clone().map(|ps| create_desc(*ps, false));
 
             let pipelines =
                 unsafe { device.create_graphics_pipelines(pipelines_descriptors, pipeline_cache) }
                     .into_iter();
 
             let mut states = pipeline_states
+                .clone()
                 .cloned()
                 .zip(pipelines.map(|pipeline| pipeline.expect("Pipeline creation failed")))
                 .collect::<FastHashMap<(hal::pso::BlendState, hal::pso::DepthTest), B::GraphicsPipeline>>();
 
+            let with_main_variants = format == surface_format && render_pass.has_main_variants();
+            let mut main_states = FastHashMap::default();
+            if with_main_variants {
+                let pipelines_descriptors = pipeline_states.clone().map(|ps| create_desc(*ps, true));
+                let pipelines =
+                    unsafe { device.create_graphics_pipelines(pipelines_descriptors, pipeline_cache) }
+                        .into_iter();
+                main_states.extend(pipeline_states
+                    .cloned()
+                    .zip(pipelines.map(|pipeline| pipeline.expect("Pipeline creation failed"))));
+            }
+
             if features.contains(&"DEBUG_OVERDRAW") {
                 let pipeline_state = (OVERDRAW, LESS_EQUAL_TEST);
-                let pipeline_descriptor = create_desc(pipeline_state);
+                let pipeline_descriptor = create_desc(pipeline_state, false);
                 let pipeline = unsafe {
                     device.create_graphics_pipeline(&pipeline_descriptor, pipeline_cache)
                 }
                 .expect("Pipeline creation failed");
                 states.insert(pipeline_state, pipeline);
+                if with_main_variants {
+                    let pipeline_descriptor = create_desc(pipeline_state, true);
+                    let pipeline = unsafe {
+                        device.create_graphics_pipeline(&pipeline_descriptor, pipeline_cache)
+                    }
+                    .expect("Pipeline creation failed");
+                    main_states.insert(pipeline_state, pipeline);
+                }
             }
 
-            states
+            (states, main_states)
         };
 
         let vertex_buffer_stride = match shader_kind {
@@ -333,6 +389,10 @@ impl<B: hal::Backend> Program<B> {
             ShaderKind::Cache(VertexArrayKind::LineDecoration) => {
                 mem::size_of::<vertex_types::LineDecorationInstance>()
             }
+            ShaderKind::Cache(VertexArrayKind::SvgFilter) => {
+                mem::size_of::<vertex_types::SvgFilterInstance>()
+            }
+            ShaderKind::Blit => mem::size_of::<vertex_types::BlitInstance>(),
             sk if sk.is_debug() => 1,
             _ => unreachable!(),
         };
@@ -379,6 +439,7 @@ impl<B: hal::Backend> Program<B> {
         Program {
             bindings_map,
             pipelines,
+            main_pipelines,
             vertex_buffer,
             index_buffer,
             instance_buffer,
@@ -400,6 +461,17 @@ impl<B: hal::Backend> Program<B> {
         self.instance_buffer[buffer_id].add(device, instances, heaps);
     }
 
+    pub(super) fn bind_instance_bytes(
+        &mut self,
+        device: &B::Device,
+        heaps: &mut Heaps<B>,
+        instances: &[u8],
+        buffer_id: usize,
+    ) {
+        assert!(!instances.is_empty());
+        self.instance_buffer[buffer_id].add_bytes(device, instances, heaps);
+    }
+
     pub(super) fn bind_texture(
         &self,
         device: &B::Device,
@@ -470,6 +542,7 @@ impl<B: hal::Backend> Program<B> {
         blend_state: hal::pso::BlendState,
         blend_color: ColorF,
         depth_test: hal::pso::DepthTest,
+        main_framebuffer: bool,
         scissor_rect: Option<DeviceIntRect>,
         next_id: usize,
         program_mode_id: u32,
@@ -502,9 +575,13 @@ impl<B: hal::Backend> Program<B> {
                 ),
                 None => cmd_buffer.set_scissors(0, &[viewport.rect]),
             }
+            let pipelines = if main_framebuffer && !self.main_pipelines.is_empty() {
+                &self.main_pipelines
+            } else {
+                &self.pipelines
+            };
             cmd_buffer.bind_graphics_pipeline(
-                &self
-                    .pipelines
+                pipelines
                     .get(&(blend_state, depth_test))
                     .expect(&format!(
                         "The blend state {:?} with depth test {:?} not found for {} program!",
@@ -581,6 +658,20 @@ impl<B: hal::Backend> Program<B> {
         }
     }
 
+    pub(super) fn report_memory(&self, report: &mut MemoryReport) {
+        for vertex_buffer in &self.vertex_buffer {
+            report.vertex_buffers += vertex_buffer.size_in_bytes();
+        }
+        if let Some(ref index_buffer) = self.index_buffer {
+            for index_buffer in index_buffer {
+                report.vertex_buffers += index_buffer.size_in_bytes();
+            }
+        }
+        for instance_buffer in &self.instance_buffer {
+            report.instance_buffers += instance_buffer.size_in_bytes();
+        }
+    }
+
     pub(super) fn deinit(mut self, device: &B::Device, heaps: &mut Heaps<B>) {
         for mut vertex_buffer in self.vertex_buffer {
             vertex_buffer.deinit(device, heaps);
@@ -593,7 +684,7 @@ impl<B: hal::Backend> Program<B> {
         for mut instance_buffer in self.instance_buffer {
             instance_buffer.deinit(device, heaps);
         }
-        for pipeline in self.pipelines.drain() {
+        for pipeline in self.pipelines.drain().chain(self.main_pipelines.drain()) {
             unsafe { device.destroy_graphics_pipeline(pipeline.1) };
         }
     }
@@ -8,8 +8,15 @@ use hal::Device;
 pub(super) struct RenderPass<B: hal::Backend> {
     pub(super) r8: B::RenderPass,
     pub(super) r8_depth: B::RenderPass,
+    pub(super) rg8: B::RenderPass,
+    pub(super) rg8_depth: B::RenderPass,
     pub(super) bgra8: B::RenderPass,
     pub(super) bgra8_depth: B::RenderPass,
+    /// The passes of the main framebuffer, whose format may differ from
+    /// the offscreen BGRA8 targets.
+    pub(super) main: B::RenderPass,
+    pub(super) main_depth: B::RenderPass,
+    pub(super) main_format: hal::format::Format,
 }
 
 impl<B: hal::Backend> RenderPass<B> {
@@ -21,18 +28,38 @@ impl<B: hal::Backend> RenderPass<B> {
         match format {
             ImageFormat::R8 if depth_enabled => &self.r8_depth,
             ImageFormat::R8 => &self.r8,
+            ImageFormat::RG8 if depth_enabled => &self.rg8_depth,
+            ImageFormat::RG8 => &self.rg8,
             ImageFormat::BGRA8 if depth_enabled => &self.bgra8_depth,
             ImageFormat::BGRA8 => &self.bgra8,
             f => unimplemented!("No render pass for image format {:?}", f),
         }
     }
 
+    pub(super) fn get_main_render_pass(&self, depth_enabled: bool) -> &B::RenderPass {
+        if depth_enabled {
+            &self.main_depth
+        } else {
+            &self.main
+        }
+    }
+
+    /// Whether pipelines drawing to the main framebuffer need their own
+    /// variants, because its format isn't compatible with the BGRA8 passes.
+    pub(super) fn has_main_variants(&self) -> bool {
+        self.main_format != hal::format::Format::Bgra8Unorm
+    }
+
     pub(super) fn deinit(self, device: &B::Device) {
         unsafe {
             device.destroy_render_pass(self.r8);
             device.destroy_render_pass(self.r8_depth);
+            device.destroy_render_pass(self.rg8);
+            device.destroy_render_pass(self.rg8_depth);
             device.destroy_render_pass(self.bgra8);
             device.destroy_render_pass(self.bgra8_depth);
+            device.destroy_render_pass(self.main);
+            device.destroy_render_pass(self.main_depth);
         }
     }
 }
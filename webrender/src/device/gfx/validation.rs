@@ -0,0 +1,234 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+//! Routing of the backend validation layer messages and of the device
+//! diagnostics.
+//!
+//! The hal backends report validation messages through the `log` crate.
+//! Identical messages tend to repeat on every draw call, so the
+//! `ValidationLogSink` wraps the logger of the embedder, forwards the first
+//! occurrence of each message, and only reports the repeats sparsely and up
+//! to a fixed number of messages per frame. It also counts the validation
+//! errors, which the renderer exposes per frame.
+//!
+//! The `device_log!` diagnostics go through the sink as well, which only
+//! forwards the categories selected with `with_device_log_categories`.
+
+use internal_types::FastHashMap;
+use log::{self, Level, LevelFilter, Log, Metadata, Record, SetLoggerError};
+use std::env;
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+
+/// The log targets of the hal backends start with this.
+const VALIDATION_TARGET_PREFIX: &'static str = "gfx_backend_";
+/// The log targets of `device_log!` are this followed by the category.
+const DEVICE_LOG_TARGET_PREFIX: &'static str = "webrender::device::";
+/// The number of validation messages forwarded per frame at most.
+const MAX_MESSAGES_PER_FRAME: usize = 32;
+/// A repeated message is forwarded again every this many occurrences.
+const REPEAT_REPORT_INTERVAL: usize = 1000;
+/// The number of distinct messages counted at most. Messages usually embed
+/// object handles, so the counts are forgotten once there are this many, to
+/// keep the memory of long running sessions bounded.
+const MAX_TRACKED_MESSAGES: usize = 1024;
+/// The validation layer loaded by `enable_validation_layers`.
+const VALIDATION_LAYER: &'static str = "VK_LAYER_KHRONOS_validation";
+
+static ENABLED: AtomicBool = AtomicBool::new(false);
+static FRAME_ERRORS: AtomicUsize = AtomicUsize::new(0);
+static FRAME_FORWARDED: AtomicUsize = AtomicUsize::new(0);
+static FRAME_SUPPRESSED: AtomicUsize = AtomicUsize::new(0);
+
+/// Asks the Vulkan loader to load the validation layers into instances
+/// created afterwards, unless the environment already picks the layers.
+/// Has to be called before the `hal::Instance` is created.
+pub fn enable_validation_layers() {
+    if env::var_os("VK_INSTANCE_LAYERS").is_none() {
+        env::set_var("VK_INSTANCE_LAYERS", VALIDATION_LAYER);
+    }
+}
+
+bitflags! {
+    /// The categories of the `device_log!` diagnostics, which are only
+    /// emitted with the `device_log` feature.
+    pub struct DeviceLogCategories: u8 {
+        /// Programs and textures bound for the draw calls.
+        const BINDS = 1 << 0;
+        /// Texture uploads.
+        const UPLOADS = 1 << 1;
+        /// Read and draw target selection.
+        const TARGETS = 1 << 2;
+    }
+}
+
+impl DeviceLogCategories {
+    fn from_target(target: &str) -> Option<Self> {
+        if !target.starts_with(DEVICE_LOG_TARGET_PREFIX) {
+            return None;
+        }
+        match &target[DEVICE_LOG_TARGET_PREFIX.len() ..] {
+            "binds" => Some(DeviceLogCategories::BINDS),
+            "uploads" => Some(DeviceLogCategories::UPLOADS),
+            "targets" => Some(DeviceLogCategories::TARGETS),
+            _ => None,
+        }
+    }
+}
+
+pub struct ValidationLogSink {
+    inner: Box<Log>,
+    /// How many times each validation message was seen, for at most
+    /// `MAX_TRACKED_MESSAGES` messages.
+    seen: Mutex<FastHashMap<String, usize>>,
+    device_log_categories: DeviceLogCategories,
+}
+
+impl ValidationLogSink {
+    pub fn new(inner: Box<Log>) -> Self {
+        ValidationLogSink {
+            inner,
+            seen: Mutex::new(FastHashMap::default()),
+            device_log_categories: DeviceLogCategories::all(),
+        }
+    }
+
+    /// Only forwards the device diagnostics of the given categories. All of
+    /// them are forwarded by default.
+    pub fn with_device_log_categories(mut self, categories: DeviceLogCategories) -> Self {
+        self.device_log_categories = categories;
+        self
+    }
+
+    fn is_filtered_out(&self, target: &str) -> bool {
+        DeviceLogCategories::from_target(target)
+            .map_or(false, |category| !self.device_log_categories.contains(category))
+    }
+
+    /// Installs the sink as the global logger.
+    pub fn install(self, max_level: LevelFilter) -> Result<(), SetLoggerError> {
+        log::set_logger(Box::leak(Box::new(self)))?;
+        log::set_max_level(max_level);
+        Ok(())
+    }
+}
+
+impl Log for ValidationLogSink {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        !self.is_filtered_out(metadata.target()) && self.inner.enabled(metadata)
+    }
+
+    fn log(&self, record: &Record) {
+        if self.is_filtered_out(record.target()) {
+            return;
+        }
+        if !ENABLED.load(Ordering::Relaxed) ||
+            record.level() > Level::Warn ||
+            !record.target().starts_with(VALIDATION_TARGET_PREFIX) {
+            self.inner.log(record);
+            return;
+        }
+
+        if record.level() == Level::Error {
+            FRAME_ERRORS.fetch_add(1, Ordering::Relaxed);
+        }
+
+        let count = {
+            let message = record.args().to_string();
+            let mut seen = self.seen.lock().unwrap();
+            if seen.len() >= MAX_TRACKED_MESSAGES && !seen.contains_key(&message) {
+                seen.clear();
+            }
+            let count = seen.entry(message).or_insert(0);
+            *count += 1;
+            *count
+        };
+        if count != 1 && count % REPEAT_REPORT_INTERVAL != 0 {
+            return;
+        }
+        if FRAME_FORWARDED.fetch_add(1, Ordering::Relaxed) >= MAX_MESSAGES_PER_FRAME {
+            FRAME_SUPPRESSED.fetch_add(1, Ordering::Relaxed);
+            return;
+        }
+
+        if count == 1 {
+            self.inner.log(record);
+        } else {
+            self.inner.log(&Record::builder()
+                .args(format_args!("{} (seen {} times)", record.args(), count))
+                .level(record.level())
+                .target(record.target())
+                .module_path(record.module_path())
+                .file(record.file())
+                .line(record.line())
+                .build());
+        }
+    }
+
+    fn flush(&self) {
+        self.inner.flush();
+    }
+}
+
+pub(crate) fn set_validation_enabled(enabled: bool) {
+    ENABLED.store(enabled, Ordering::Relaxed);
+}
+
+/// Resets the per frame limits and returns the number of validation
+/// errors reported since the previous call.
+pub(crate) fn end_validation_frame() -> usize {
+    FRAME_FORWARDED.store(0, Ordering::Relaxed);
+    let suppressed = FRAME_SUPPRESSED.swap(0, Ordering::Relaxed);
+    if suppressed != 0 {
+        warn!("{} validation messages were suppressed this frame", suppressed);
+    }
+    FRAME_ERRORS.swap(0, Ordering::Relaxed)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::sync::Arc;
+
+    struct RecordingLog(Arc<Mutex<Vec<String>>>);
+
+    impl Log for RecordingLog {
+        fn enabled(&self, _: &Metadata) -> bool {
+            true
+        }
+
+        fn log(&self, record: &Record) {
+            self.0.lock().unwrap().push(record.target().to_string());
+        }
+
+        fn flush(&self) {}
+    }
+
+    #[test]
+    fn device_log_categories_are_filtered() {
+        let targets = Arc::new(Mutex::new(Vec::new()));
+        let sink = ValidationLogSink::new(Box::new(RecordingLog(targets.clone())))
+            .with_device_log_categories(DeviceLogCategories::UPLOADS);
+
+        for target in &[
+            "webrender::device::binds",
+            "webrender::device::uploads",
+            "webrender::device::targets",
+            "webrender::renderer",
+        ] {
+            sink.log(&Record::builder()
+                .args(format_args!("message"))
+                .level(Level::Debug)
+                .target(target)
+                .build());
+        }
+
+        assert_eq!(
+            *targets.lock().unwrap(),
+            vec!["webrender::device::uploads".to_string(), "webrender::renderer".to_string()]
+        );
+        assert!(!sink.enabled(&Metadata::builder().target("webrender::device::binds").build()));
+        assert!(sink.enabled(&Metadata::builder().target("webrender::device::uploads").build()));
+    }
+}
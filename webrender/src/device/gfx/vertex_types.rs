@@ -24,6 +24,18 @@ pub struct ScalingInstance {
     pub aScaleSourceTaskAddress: i32,
 }
 
+#[derive(Debug, Clone, Copy)]
+#[allow(non_snake_case)]
+pub struct SvgFilterInstance {
+    pub aData: [i32; 4],
+    pub aFilterRenderTaskAddress: i32,
+    pub aFilterInput1TaskAddress: i32,
+    pub aFilterInput2TaskAddress: i32,
+    pub aFilterKind: i32,
+    pub aFilterGenericInt: i32,
+    pub aFilterExtraDataAddress: i32,
+}
+
 #[derive(Debug, Clone, Copy)]
 #[allow(non_snake_case)]
 pub struct BlurInstance {
@@ -60,6 +72,14 @@ pub struct DebugFontVertex {
     pub aColorTexCoord: [f32; 2],
 }
 
+#[derive(Debug, Clone, Copy)]
+#[allow(non_snake_case)]
+pub struct BlitInstance {
+    pub aBlitTargetRect: [f32; 4],
+    pub aBlitSourceRect: [f32; 4],
+    pub aBlitSourceLayer: i32,
+}
+
 #[derive(Debug, Clone, Copy)]
 #[allow(non_snake_case)]
 pub struct LineDecorationInstance {
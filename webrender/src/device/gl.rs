@@ -5,12 +5,14 @@
 use super::Capabilities;
 use super::desc;
 use super::{ExternalTexture, FBOId, GpuFrameId, IBOId, RBOId, ProgramCache, ProgramCacheEntry, ReadPixelsFormat};
+use super::ReadbackHandle;
 use super::{ShaderError, ShaderKind, ShaderPrecacheFlags, SharedDepthTarget, Texel, Texture, TextureFlags};
 use super::{DrawTarget, TextureFilter, ReadTarget, TextureSampler, TextureSlot, UploadMethod, VBOId};
 use super::{VertexArrayKind, VertexAttribute, VertexAttributeKind, VertexDescriptor, VertexUsageHint};
 use super::{build_shader_main_string, build_shader_prefix_string, do_build_shader_string, record_gpu_alloc, record_gpu_free};
 use super::{SHADER_KIND_VERTEX, SHADER_KIND_FRAGMENT, depth_target_size_in_bytes, ProgramBinary, ProgramSourceInfo};
-use super::{SHADERS, ProgramSourceDigest};
+use super::{SHADERS, ProgramSourceDigest, GpuVendor, Workarounds, DEPTH_TARGET_POOL_FRAMES};
+use super::{flip_rows, SurfaceOrigin};
 
 use api::{ColorF, ImageFormat, MemoryReport};
 use api::{DeviceIntPoint, DeviceIntRect, DeviceIntSize};
@@ -24,7 +26,6 @@ use log::Level;
 use sha2::{Digest, Sha256};
 use smallvec::SmallVec;
 use std::cmp;
-use std::collections::hash_map::Entry;
 use std::marker::PhantomData;
 use std::mem;
 use std::path::PathBuf;
@@ -64,6 +65,7 @@ impl PrimitiveType for gpu_types::BlurInstance { }
 impl PrimitiveType for gpu_types::ClipMaskInstance { }
 impl PrimitiveType for gpu_types::PrimitiveInstanceData { }
 impl PrimitiveType for gpu_types::ScalingInstance { }
+impl PrimitiveType for gpu_types::SvgFilterInstance { }
 impl PrimitiveType for LineDecorationJob { }
 
 pub fn get_gl_target(target: TextureTarget) -> gl::GLuint {
@@ -457,6 +459,14 @@ pub struct Device<B> {
     program_mode_id: UniformLocation,
     default_read_fbo: FBOId,
     default_draw_fbo: FBOId,
+    /// The origin of the default framebuffer, and its size as of the last
+    /// time it was bound for drawing.
+    surface_origin: SurfaceOrigin,
+    default_framebuffer_size: DeviceIntSize,
+    /// Read backs are done synchronously, so requests just keep the pixels
+    /// until they are polled.
+    completed_readbacks: FastHashMap<usize, Vec<u8>>,
+    next_readback_id: usize,
 
     /// Track depth state for assertions. Note that the default FBO has depth,
     /// so this defaults to true.
@@ -467,6 +477,7 @@ pub struct Device<B> {
 
     // HW or API capabilities
     capabilities: Capabilities,
+    workarounds: Workarounds,
 
     bgra_format_internal: gl::GLuint,
     bgra_format_external: gl::GLuint,
@@ -633,7 +644,11 @@ impl<B> Device<B> {
 
             capabilities: Capabilities {
                 supports_multisampling: false, //TODO
+                // Fetching texels from RGBA32F textures is core in both GL 3
+                // and GLES 3.
+                supports_float_textures: true,
             },
+            workarounds: Workarounds::empty(),
 
             bgra_format_internal,
             bgra_format_external,
@@ -648,6 +663,10 @@ impl<B> Device<B> {
             program_mode_id: UniformLocation::INVALID,
             default_read_fbo: FBOId(0),
             default_draw_fbo: FBOId(0),
+            surface_origin: SurfaceOrigin::native(),
+            default_framebuffer_size: DeviceIntSize::zero(),
+            completed_readbacks: FastHashMap::default(),
+            next_readback_id: 0,
 
             depth_available: true,
 
@@ -693,7 +712,44 @@ impl<B> Device<B> {
 
     /// Returns the limit on texture array layers.
     pub fn max_texture_layers(&self) -> usize {
-        self.max_texture_layers as usize
+        if self.workarounds.contains(Workarounds::LIMIT_TEXTURE_ARRAY_LAYERS) {
+            (self.max_texture_layers as usize).min(Workarounds::MAX_LIMITED_TEXTURE_LAYERS)
+        } else {
+            self.max_texture_layers as usize
+        }
+    }
+
+    /// Returns the vendor-specific id of the GPU. GL doesn't expose it.
+    pub fn gpu_device_id(&self) -> Option<usize> {
+        None
+    }
+
+    /// Describes the GPU and driver, for bug reports.
+    pub fn describe_adapter(&self) -> String {
+        format!(
+            "{} ({}, {})",
+            self.gl.get_string(gl::RENDERER),
+            self.gl.get_string(gl::VENDOR),
+            self.gl.get_string(gl::VERSION),
+        )
+    }
+
+    /// Describes the extensions supported by the context, for bug reports.
+    pub fn describe_features(&self) -> String {
+        self.extensions.join(" ")
+    }
+
+    pub fn workarounds(&self) -> Workarounds {
+        self.workarounds
+    }
+
+    pub fn set_workarounds(&mut self, workarounds: Workarounds) {
+        self.workarounds = workarounds;
+    }
+
+    /// Returns the vendor of the GPU, as reported by the driver.
+    pub fn gpu_vendor(&self) -> GpuVendor {
+        GpuVendor::from_name(&self.gl.get_string(gl::VENDOR))
     }
 
     pub fn get_capabilities(&self) -> &Capabilities {
@@ -869,7 +925,10 @@ impl<B> Device<B> {
         target: DrawTarget,
     ) {
         let (fbo_id, dimensions, depth_available) = match target {
-            DrawTarget::Default(d) => (self.default_draw_fbo, d, true),
+            DrawTarget::Default(d) => {
+                self.default_framebuffer_size = d;
+                (self.default_draw_fbo, d, true)
+            }
             DrawTarget::Texture { texture, layer, with_depth } => {
                 let dim = texture.get_dimensions();
                 if with_depth {
@@ -920,6 +979,36 @@ impl<B> Device<B> {
         self.gl.delete_framebuffers(&[fbo.0]);
     }
 
+    /// Copies the top left `dest_rect.size` texels of an external texture
+    /// into a layer of `dest`, which must be a render target.
+    pub fn copy_external_texture(
+        &mut self,
+        src: &ExternalTexture,
+        dest: &Texture,
+        dest_rect: DeviceIntRect,
+        dest_layer: i32,
+    ) {
+        debug_assert!(self.inside_frame);
+        let fbo = self.create_fbo();
+        fbo.bind(self.gl(), FBOTarget::Read);
+        self.gl.framebuffer_texture_2d(
+            gl::READ_FRAMEBUFFER,
+            gl::COLOR_ATTACHMENT0,
+            src.target,
+            src.id,
+            0,
+        );
+        self.bind_draw_target(DrawTarget::Texture {
+            texture: dest,
+            layer: dest_layer as usize,
+            with_depth: false,
+        });
+        let src_rect = DeviceIntRect::new(DeviceIntPoint::zero(), dest_rect.size);
+        self.blit_render_target(src_rect, dest_rect);
+        self.bound_read_fbo.bind(self.gl(), FBOTarget::Read);
+        self.delete_fbo(fbo);
+    }
+
     pub fn bind_external_draw_target(&mut self, fbo_id: FBOId) {
         debug_assert!(self.inside_frame);
 
@@ -1356,6 +1445,7 @@ impl<B> Device<B> {
 
     fn acquire_depth_target(&mut self, dimensions: DeviceIntSize) -> RBOId {
         let gl = &self.gl;
+        let frame_id = self.frame_id;
         let target = self.depth_targets.entry(dimensions).or_insert_with(|| {
             let renderbuffer_ids = gl.gen_renderbuffers(1);
             let depth_rb = renderbuffer_ids[0];
@@ -1366,32 +1456,65 @@ impl<B> Device<B> {
                 dimensions.width as _,
                 dimensions.height as _,
             );
+            record_gpu_alloc(depth_target_size_in_bytes(&dimensions));
             SharedDepthTarget {
                 rbo_id: RBOId(depth_rb),
                 refcount: 0,
+                last_used: frame_id,
             }
         });
-        if target.refcount == 0 {
-            record_gpu_alloc(depth_target_size_in_bytes(&dimensions));
-        }
         target.refcount += 1;
         target.rbo_id
     }
 
     fn release_depth_target(&mut self, dimensions: DeviceIntSize) {
-        let mut entry = match self.depth_targets.entry(dimensions) {
-            Entry::Occupied(x) => x,
-            Entry::Vacant(..) => panic!("Releasing unknown depth target"),
+        let target = match self.depth_targets.get_mut(&dimensions) {
+            Some(target) => target,
+            None => panic!("Releasing unknown depth target"),
         };
-        debug_assert!(entry.get().refcount != 0);
-        entry.get_mut().refcount -= 1;
-        if entry.get().refcount == 0 {
-            let (dimensions, target) = entry.remove_entry();
-            self.gl.delete_renderbuffers(&[target.rbo_id.0]);
-            record_gpu_free(depth_target_size_in_bytes(&dimensions));
+        debug_assert!(target.refcount != 0);
+        target.refcount -= 1;
+        if target.refcount == 0 {
+            // Keep the renderbuffer pooled, it gets deleted by
+            // `trim_depth_targets` if nobody picks it up again.
+            target.last_used = self.frame_id;
         }
     }
 
+    /// Deletes the pooled depth targets that haven't been referenced for
+    /// `max_age` frames.
+    fn trim_depth_targets(&mut self, max_age: usize) {
+        let frame_id = self.frame_id;
+        let gl = &self.gl;
+        self.depth_targets.retain(|dimensions, target| {
+            if target.refcount != 0 ||
+                frame_id.0 - target.last_used.0 < max_age {
+                return true;
+            }
+            gl.delete_renderbuffers(&[target.rbo_id.0]);
+            record_gpu_free(depth_target_size_in_bytes(dimensions));
+            false
+        });
+    }
+
+    /// Detaches the depth target from a render target, returning it to the
+    /// depth target pool.
+    ///
+    /// This is called once a render target is done with the passes of a frame,
+    /// so that depth memory is only held while it is actually needed. The depth
+    /// target is attached again by `reuse_render_target` if the next use of the
+    /// render target requires it.
+    pub fn detach_depth_target(&mut self, texture: &mut Texture) {
+        if !texture.supports_depth() {
+            return;
+        }
+        if texture.fbos_with_depth.contains(&self.bound_draw_fbo) {
+            self.reset_draw_target();
+        }
+        self.deinit_fbos(&mut texture.fbos_with_depth);
+        self.release_depth_target(texture.get_dimensions());
+    }
+
     pub fn blit_render_target(&mut self, src_rect: DeviceIntRect, dest_rect: DeviceIntRect) {
         debug_assert!(self.inside_frame);
 
@@ -1409,6 +1532,25 @@ impl<B> Device<B> {
         );
     }
 
+    /// Like `blit_render_target`, but samples the nearest texel, so that
+    /// magnified content stays pixel exact.
+    pub fn blit_render_target_nearest(&mut self, src_rect: DeviceIntRect, dest_rect: DeviceIntRect) {
+        debug_assert!(self.inside_frame);
+
+        self.gl.blit_framebuffer(
+            src_rect.origin.x,
+            src_rect.origin.y,
+            src_rect.origin.x + src_rect.size.width,
+            src_rect.origin.y + src_rect.size.height,
+            dest_rect.origin.x,
+            dest_rect.origin.y,
+            dest_rect.origin.x + dest_rect.size.width,
+            dest_rect.origin.y + dest_rect.size.height,
+            gl::COLOR_BUFFER_BIT,
+            gl::NEAREST,
+        );
+    }
+
     /// Performs a blit while flipping vertically. Useful for blitting textures
     /// (which use origin-bottom-left) to the main framebuffer (which uses
     /// origin-top-left).
@@ -1510,6 +1652,7 @@ impl<B> Device<B> {
                 VertexArrayKind::VectorCover => &desc::VECTOR_COVER,
                 VertexArrayKind::Border => &desc::BORDER,
                 VertexArrayKind::Scale => &desc::SCALE,
+                VertexArrayKind::SvgFilter => &desc::SVG_FILTER,
             };
 
             self.link_program(&mut program, vertex_descriptor)?;
@@ -1729,6 +1872,16 @@ impl<B> Device<B> {
         }
     }
 
+    /// Initializes the contents of all the layers of a freshly created texture.
+    ///
+    /// The texels are expected to be tightly packed, layer after layer, in the
+    /// format of the texture.
+    pub fn init_texture<T: Texel>(&mut self, texture: &Texture, pixels: Option<&[T]>) {
+        if let Some(pixels) = pixels {
+            self.upload_texture_immediate(texture, pixels);
+        }
+    }
+
     /// Performs an immediate (non-PBO) texture upload.
     pub fn upload_texture_immediate<T: Texel>(
         &mut self,
@@ -1801,6 +1954,16 @@ impl<B> Device<B> {
         let size_in_bytes = (bytes_per_pixel * rect.size.width * rect.size.height) as usize;
         assert_eq!(output.len(), size_in_bytes);
 
+        // Reads from the default framebuffer are given and returned with a
+        // bottom-left origin, so a top-left surface needs a vertical flip.
+        let flip_y = self.bound_read_fbo == self.default_read_fbo &&
+            !self.surface_origin.is_bottom_left();
+        let rect = if flip_y {
+            SurfaceOrigin::BottomLeft.convert_rect(rect, self.default_framebuffer_size.height)
+        } else {
+            rect
+        };
+
         self.gl.flush();
         self.gl.read_pixels_into_buffer(
             rect.origin.x as _,
@@ -1811,6 +1974,36 @@ impl<B> Device<B> {
             desc.pixel_type,
             output,
         );
+
+        if flip_y {
+            flip_rows(output, (bytes_per_pixel * rect.size.width) as usize);
+        }
+    }
+
+    /// Reads back a rectangle of pixels from the current read target. GL
+    /// doesn't use staging buffers here, so the pixels are ready right away.
+    pub fn request_read_pixels(
+        &mut self,
+        rect: DeviceIntRect,
+        format: ReadPixelsFormat,
+    ) -> ReadbackHandle {
+        let bytes_per_pixel = match format {
+            ReadPixelsFormat::Standard(imf) => imf.bytes_per_pixel(),
+            ReadPixelsFormat::Rgba8 => 4,
+        };
+        let mut pixels = vec![0; (bytes_per_pixel * rect.size.width * rect.size.height) as usize];
+        self.read_pixels_into(rect, format, &mut pixels);
+        let handle = ReadbackHandle(self.next_readback_id);
+        self.next_readback_id += 1;
+        self.completed_readbacks.insert(handle.0, pixels);
+        handle
+    }
+
+    /// Returns the pixels of a read back started by `request_read_pixels`.
+    pub fn poll_readback(&mut self, handle: ReadbackHandle) -> Result<Vec<u8>, ReadbackHandle> {
+        Ok(self.completed_readbacks
+            .remove(&handle.0)
+            .expect("BUG: unknown readback handle"))
     }
 
     /// Get texels of a texture into the specified output slice.
@@ -2147,6 +2340,8 @@ impl<B> Device<B> {
 
         self.gl.active_texture(gl::TEXTURE0);
 
+        self.trim_depth_targets(DEPTH_TARGET_POOL_FRAMES);
+
         self.frame_id.0 += 1;
 
         // Declare startup complete after the first ten frames. This number is
@@ -2326,6 +2521,35 @@ impl<B> Device<B> {
         supports_extension(&self.extensions, extension)
     }
 
+    pub fn surface_origin(&self) -> SurfaceOrigin {
+        self.surface_origin
+    }
+
+    /// Sets the origin of the default framebuffer, which the renderer consults
+    /// for projections, scissors, blits and read backs targeting it.
+    pub fn set_surface_origin(&mut self, origin: SurfaceOrigin) {
+        self.surface_origin = origin;
+    }
+
+    /// Returns true if blending with the second output of the fragment
+    /// shader is available, for single pass subpixel text.
+    pub fn supports_dual_source_blending(&self) -> bool {
+        !self.workarounds.contains(Workarounds::DISABLE_DUAL_SOURCE_BLENDING) &&
+        self.supports_extension("GL_ARB_blend_func_extended") &&
+            self.supports_extension("GL_ARB_explicit_attrib_location")
+    }
+
+    /// Anisotropic sampling is only implemented by the gfx-hal device.
+    pub fn supports_anisotropic_filtering(&self) -> bool {
+        false
+    }
+
+    /// Compressed texture uploads are only implemented by the gfx-hal device,
+    /// as the texture cache is made of texture arrays.
+    pub fn supports_compressed_format(&self, _format: ImageFormat) -> bool {
+        false
+    }
+
     pub fn echo_driver_messages(&self) {
         for msg in self.gl.get_debug_messages() {
             let level = match msg.severity {
@@ -2382,6 +2606,16 @@ impl<B> Device<B> {
                 external: gl::RGBA,
                 pixel_type: gl::FLOAT,
             },
+            ImageFormat::RGBAF16 => FormatDesc {
+                internal: gl::RGBA16F,
+                external: gl::RGBA,
+                pixel_type: gl::HALF_FLOAT,
+            },
+            ImageFormat::RGB10A2 => FormatDesc {
+                internal: gl::RGB10_A2,
+                external: gl::RGBA,
+                pixel_type: gl::UNSIGNED_INT_2_10_10_10_REV,
+            },
             ImageFormat::RGBAI32 => FormatDesc {
                 internal: gl::RGBA32I,
                 external: gl::RGBA_INTEGER,
@@ -2392,10 +2626,35 @@ impl<B> Device<B> {
                 external: gl::RG,
                 pixel_type: gl::UNSIGNED_BYTE,
             },
+            ImageFormat::RG16 => FormatDesc {
+                internal: gl::RG16,
+                external: gl::RG,
+                pixel_type: gl::UNSIGNED_SHORT,
+            },
+            ImageFormat::BC1 |
+            ImageFormat::BC3 |
+            ImageFormat::BC7 |
+            ImageFormat::ETC2 |
+            ImageFormat::ASTC4x4 => {
+                panic!("Compressed format {:?} isn't supported by the GL device", format)
+            }
         }
     }
 
+    /// Waits for the GPU to finish all the submitted work. GL has no way to
+    /// bound the wait, so `_timeout_ns` is ignored and this always returns true.
+    pub fn wait_idle(&mut self, _timeout_ns: u64) -> bool {
+        self.gl.finish();
+        true
+    }
+
     /// Generates a memory report for the resources managed by the device layer.
+    /// Frees the pooled resources which aren't needed to render the next
+    /// frame, in response to a low memory signal of the OS.
+    pub fn on_memory_pressure(&mut self) {
+        self.trim_depth_targets(0);
+    }
+
     pub fn report_memory(&self) -> MemoryReport {
         let mut report = MemoryReport::default();
         for dim in self.depth_targets.keys() {
@@ -2548,8 +2807,16 @@ impl<'a> UploadTarget<'a> {
             ImageFormat::BGRA8 => (self.bgra_format, 4, gl::UNSIGNED_BYTE),
             ImageFormat::RGBA8 => (gl::RGBA, 4, gl::UNSIGNED_BYTE),
             ImageFormat::RG8 => (gl::RG, 2, gl::UNSIGNED_BYTE),
+            ImageFormat::RG16 => (gl::RG, 4, gl::UNSIGNED_SHORT),
             ImageFormat::RGBAF32 => (gl::RGBA, 16, gl::FLOAT),
+            ImageFormat::RGBAF16 => (gl::RGBA, 8, gl::HALF_FLOAT),
+            ImageFormat::RGB10A2 => (gl::RGBA, 4, gl::UNSIGNED_INT_2_10_10_10_REV),
             ImageFormat::RGBAI32 => (gl::RGBA_INTEGER, 16, gl::INT),
+            ImageFormat::BC1 |
+            ImageFormat::BC3 |
+            ImageFormat::BC7 |
+            ImageFormat::ETC2 |
+            ImageFormat::ASTC4x4 => unreachable!(),
         };
 
         let row_length = match chunk.stride {
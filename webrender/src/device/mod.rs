@@ -4,11 +4,12 @@
 
 use super::shader_source::SHADERS;
 use api::{DeviceIntPoint, DeviceIntRect, DeviceIntSize, ImageFormat, TextureTarget};
-use api::VoidPtrToSizeFn;
+use api::{BatchingParameters, VoidPtrToSizeFn};
 use euclid::Transform3D;
 #[cfg(feature = "gleam")]
 use gleam::gl as gleam_gl;
 use internal_types::{FastHashMap, LayerIndex, ORTHO_FAR_PLANE, ORTHO_NEAR_PLANE};
+use sha2::{Digest, Sha256};
 use std::borrow::Cow;
 use std::cell::{Cell, RefCell};
 use std::ops::Add;
@@ -71,6 +72,18 @@ impl GpuFrameId {
     pub fn new(value: usize) -> Self {
         GpuFrameId(value)
     }
+
+    /// Returns how many frames ago `earlier` was, or `None` if it is a
+    /// later frame than this one.
+    pub fn frames_since(self, earlier: GpuFrameId) -> Option<usize> {
+        self.0.checked_sub(earlier.0)
+    }
+}
+
+impl Into<usize> for GpuFrameId {
+    fn into(self) -> usize {
+        self.0
+    }
 }
 
 impl Add<usize> for GpuFrameId {
@@ -164,6 +177,25 @@ pub enum ReadPixelsFormat {
     Rgba8,
 }
 
+/// Identifies a read back started by `Device::request_read_pixels`.
+#[derive(Debug, Eq, Hash, PartialEq)]
+pub struct ReadbackHandle(usize);
+
+/// Returns a digest of the built-in shader sources, which tells builds
+/// with different shaders apart in bug reports.
+pub fn shader_manifest_digest() -> String {
+    let mut names: Vec<_> = SHADERS.keys().collect();
+    names.sort();
+
+    let mut hasher = Sha256::new();
+    for name in names {
+        hasher.input(name.as_bytes());
+        hasher.input(SHADERS[name].digest.as_bytes());
+    }
+    let digest: ProgramSourceDigest = hasher.into();
+    digest.to_string()
+}
+
 // Get a shader string by name, from the built in resources or
 // an override path, if supplied.
 fn get_shader_source(shader_name: &str, base_path: Option<&PathBuf>) -> Cow<'static, str> {
@@ -366,7 +398,9 @@ impl Texture {
     /// Returns true if this texture was used within `threshold` frames of
     /// the current frame.
     pub fn used_recently(&self, current_frame_id: GpuFrameId, threshold: usize) -> bool {
-        self.last_frame_used + threshold >= current_frame_id
+        current_frame_id
+            .frames_since(self.last_frame_used)
+            .map_or(true, |age| age <= threshold)
     }
 
     /// Returns the flags for this texture.
@@ -383,10 +417,7 @@ impl Texture {
     /// this texture consumes.
     pub fn layer_size_in_bytes(&self) -> usize {
         assert!(self.layer_count > 0 || self.size.width + self.size.height == 0);
-        let bpp = self.format.bytes_per_pixel() as usize;
-        let w = self.size.width as usize;
-        let h = self.size.height as usize;
-        bpp * w * h
+        self.format.size_in_bytes(self.size.width, self.size.height)
     }
 
     /// Returns the number of bytes (generally in GPU memory) that this texture
@@ -513,8 +544,11 @@ pub enum VertexUsageHint {
     Stream,
 }
 
+#[derive(Debug)]
 pub struct Capabilities {
     pub supports_multisampling: bool,
+    /// Whether RGBA32F textures can be sampled from.
+    pub supports_float_textures: bool,
 }
 
 #[derive(Clone, Debug)]
@@ -538,6 +572,10 @@ pub enum ShaderKind {
     DebugColor,
     #[cfg(all(not(feature = "gleam")))]
     DebugFont,
+    /// The textured quad the device falls back to for blits the backend
+    /// doesn't support.
+    #[cfg(all(not(feature = "gleam")))]
+    Blit,
 }
 
 #[derive(Eq, PartialEq, Hash, Debug, Copy, Clone)]
@@ -552,15 +590,28 @@ pub enum VertexArrayKind {
     Border,
     Scale,
     LineDecoration,
+    SvgFilter,
 }
 
+/// Number of frames an unreferenced depth target is kept in the pool before
+/// it gets deleted.
+const DEPTH_TARGET_POOL_FRAMES: usize = 30;
+
 /// A refcounted depth target, which may be shared by multiple textures across
 /// the device.
+///
+/// Depth targets are pooled separately from the color targets: they are only
+/// attached to a render target for the duration of the passes that need depth,
+/// and are kept around for a few frames once unreferenced so that targets of
+/// the same size can pick them up again.
 struct SharedDepthTarget {
     /// The Render Buffer Object representing the depth target.
     rbo_id: RBOId,
-    /// Reference count. When this drops to zero, the RBO is deleted.
+    /// Reference count. When this drops to zero, the RBO stays in the pool
+    /// until it is reused or trimmed.
     refcount: usize,
+    /// The last frame in which the depth target was released.
+    last_used: GpuFrameId,
 }
 
 #[cfg(debug_assertions)]
@@ -605,26 +656,24 @@ impl<'a> DrawTarget<'a> {
     }
 
     /// Given a scissor rect, convert it to the right coordinate space
-    /// depending on the draw target kind. If no scissor rect was supplied,
-    /// returns a scissor rect that encloses the entire render target.
+    /// depending on the draw target kind and the origin of the main
+    /// framebuffer. If no scissor rect was supplied, returns a scissor rect
+    /// that encloses the entire render target.
     pub fn build_scissor_rect(
         &self,
         scissor_rect: Option<DeviceIntRect>,
         framebuffer_target_rect: DeviceIntRect,
+        surface_origin: SurfaceOrigin,
     ) -> DeviceIntRect {
         let dimensions = self.dimensions();
 
         match scissor_rect {
             Some(scissor_rect) => {
-                // Note: `framebuffer_target_rect` needs a Y-flip before going to GL
                 if self.is_default() {
-                    let mut rect = scissor_rect
+                    let rect = scissor_rect
                         .intersection(&framebuffer_target_rect.to_i32())
                         .unwrap_or(DeviceIntRect::zero());
-                    if cfg!(feature = "gleam") {
-                        rect.origin.y = dimensions.height as i32 - rect.origin.y - rect.size.height;
-                    }
-                    rect
+                    surface_origin.convert_rect(rect, dimensions.height)
                 } else {
                     scissor_rect
                 }
@@ -729,27 +778,320 @@ impl Into<TextureSlot> for TextureSampler {
     }
 }
 
+/// Creates an orthographic projection. Projections targeting the main
+/// framebuffer have to go through `SurfaceOrigin::apply_to_projection` too.
 pub(crate) fn create_projection(
     left: f32,
     right: f32,
     bottom: f32,
     top: f32,
-    main_frame_buffer: bool
 ) -> Transform3D<f32> {
-    let projection = Transform3D::ortho(
+    Transform3D::ortho(
         left,
         right,
         bottom,
         top,
         ORTHO_NEAR_PLANE,
         ORTHO_FAR_PLANE,
-    );
-    if main_frame_buffer && cfg!(not(feature = "gleam")) {
-        return projection.post_scale(1.0, -1.0, 1.0);
+    )
+}
+
+/// The rotation the main framebuffer content is rendered with, relative to the
+/// native orientation of the surface. Rendering pre-rotated content saves the
+/// compositor from rotating every frame on mobile devices.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum SurfaceTransform {
+    Identity,
+    /// Rotated 90 degrees clockwise.
+    Rotate90,
+    Rotate180,
+    /// Rotated 270 degrees clockwise.
+    Rotate270,
+}
+
+impl Default for SurfaceTransform {
+    fn default() -> Self {
+        SurfaceTransform::Identity
+    }
+}
+
+impl SurfaceTransform {
+    /// Returns true if the width and height of the surface are swapped.
+    pub fn is_transposed(&self) -> bool {
+        match *self {
+            SurfaceTransform::Rotate90 | SurfaceTransform::Rotate270 => true,
+            SurfaceTransform::Identity | SurfaceTransform::Rotate180 => false,
+        }
+    }
+
+    /// Converts between the logical and the physical size of the surface.
+    pub fn transform_size(&self, size: DeviceIntSize) -> DeviceIntSize {
+        if self.is_transposed() {
+            DeviceIntSize::new(size.height, size.width)
+        } else {
+            size
+        }
+    }
+
+    /// Maps a rectangle of the logical framebuffer of `size` into the
+    /// physical (rotated) surface.
+    pub fn transform_rect(&self, rect: DeviceIntRect, size: DeviceIntSize) -> DeviceIntRect {
+        let (x, y) = (rect.origin.x, rect.origin.y);
+        let (w, h) = (rect.size.width, rect.size.height);
+        match *self {
+            SurfaceTransform::Identity => rect,
+            SurfaceTransform::Rotate90 => DeviceIntRect::new(
+                DeviceIntPoint::new(size.height - (y + h), x),
+                DeviceIntSize::new(h, w),
+            ),
+            SurfaceTransform::Rotate180 => DeviceIntRect::new(
+                DeviceIntPoint::new(size.width - (x + w), size.height - (y + h)),
+                rect.size,
+            ),
+            SurfaceTransform::Rotate270 => DeviceIntRect::new(
+                DeviceIntPoint::new(y, size.width - (x + w)),
+                DeviceIntSize::new(h, w),
+            ),
+        }
+    }
+
+    /// Maps the texel `(x, y)` of a logical `width` x `height` region to its
+    /// position inside the matching physical region.
+    pub fn transform_texel(&self, x: usize, y: usize, width: usize, height: usize) -> (usize, usize) {
+        match *self {
+            SurfaceTransform::Identity => (x, y),
+            SurfaceTransform::Rotate90 => (height - 1 - y, x),
+            SurfaceTransform::Rotate180 => (width - 1 - x, height - 1 - y),
+            SurfaceTransform::Rotate270 => (y, width - 1 - x),
+        }
+    }
+
+    /// Applies the rotation to a projection targeting the main framebuffer.
+    pub fn apply_to_projection(&self, projection: Transform3D<f32>) -> Transform3D<f32> {
+        let (m11, m12, m21, m22) = match *self {
+            SurfaceTransform::Identity => return projection,
+            SurfaceTransform::Rotate90 => (0.0, 1.0, -1.0, 0.0),
+            SurfaceTransform::Rotate180 => (-1.0, 0.0, 0.0, -1.0),
+            SurfaceTransform::Rotate270 => (0.0, -1.0, 1.0, 0.0),
+        };
+        projection.post_mul(&Transform3D::row_major(
+            m11, m12, 0.0, 0.0,
+            m21, m22, 0.0, 0.0,
+            0.0, 0.0, 1.0, 0.0,
+            0.0, 0.0, 0.0, 1.0,
+        ))
+    }
+}
+
+/// The origin of the coordinate space of the main framebuffer, in which its
+/// rows are stored, and scissor, clear and read back rects are given.
+///
+/// WebRender's device space always has a top-left origin, so rects going to
+/// a bottom-left based framebuffer need a vertical flip. GL framebuffers are
+/// bottom-left based and the hal backends top-left based, but embedders can
+/// switch the origin at runtime, e.g. to match how a compositor samples the
+/// surface. Render target textures always use a top-left origin.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum SurfaceOrigin {
+    TopLeft,
+    BottomLeft,
+}
+
+impl Default for SurfaceOrigin {
+    fn default() -> Self {
+        SurfaceOrigin::native()
+    }
+}
+
+impl SurfaceOrigin {
+    /// The origin of the framebuffers of the backend.
+    pub fn native() -> Self {
+        if cfg!(feature = "gleam") {
+            SurfaceOrigin::BottomLeft
+        } else {
+            SurfaceOrigin::TopLeft
+        }
+    }
+
+    pub fn is_bottom_left(&self) -> bool {
+        *self == SurfaceOrigin::BottomLeft
+    }
+
+    /// Converts a rect between device space and the space of a framebuffer
+    /// of the given height. The conversion is its own inverse.
+    pub fn convert_rect(&self, rect: DeviceIntRect, height: i32) -> DeviceIntRect {
+        match *self {
+            SurfaceOrigin::TopLeft => rect,
+            SurfaceOrigin::BottomLeft => DeviceIntRect::new(
+                DeviceIntPoint::new(rect.origin.x, height - rect.origin.y - rect.size.height),
+                rect.size,
+            ),
+        }
+    }
+
+    /// Same as `convert_rect`, but returns `None` if the rect isn't within
+    /// the height of the framebuffer or the math overflows.
+    pub fn checked_convert_rect(&self, rect: DeviceIntRect, height: i32) -> Option<DeviceIntRect> {
+        match *self {
+            SurfaceOrigin::TopLeft => Some(rect),
+            SurfaceOrigin::BottomLeft => {
+                let y = height
+                    .checked_sub(rect.size.height)?
+                    .checked_sub(rect.origin.y)?;
+                Some(DeviceIntRect::new(DeviceIntPoint::new(rect.origin.x, y), rect.size))
+            }
+        }
+    }
+
+    /// Adjusts a projection from device space, created by `create_projection`
+    /// with a `bottom` of the framebuffer height and a `top` of zero, so
+    /// that rows end up in the order of this origin.
+    pub fn apply_to_projection(&self, projection: Transform3D<f32>) -> Transform3D<f32> {
+        match *self {
+            SurfaceOrigin::BottomLeft => projection,
+            SurfaceOrigin::TopLeft => projection.post_scale(1.0, -1.0, 1.0),
+        }
     }
-    projection
 }
 
+/// Reverses the order of the rows of tightly packed pixels in place.
+pub fn flip_rows(pixels: &mut [u8], row_size: usize) {
+    if row_size == 0 {
+        return;
+    }
+    let row_count = pixels.len() / row_size;
+    for y in 0 .. row_count / 2 {
+        let (top, bottom) = pixels.split_at_mut((row_count - 1 - y) * row_size);
+        top[y * row_size .. (y + 1) * row_size].swap_with_slice(&mut bottom[.. row_size]);
+    }
+}
+
+/// The GPU vendors we tune defaults for.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum GpuVendor {
+    Amd,
+    Apple,
+    Arm,
+    Imagination,
+    Intel,
+    Nvidia,
+    Qualcomm,
+    Unknown,
+}
+
+impl GpuVendor {
+    /// Maps a PCI vendor id, as reported by the adapter, to a vendor.
+    pub fn from_pci_id(id: usize) -> Self {
+        match id {
+            0x1002 | 0x1022 => GpuVendor::Amd,
+            0x106B => GpuVendor::Apple,
+            0x13B5 => GpuVendor::Arm,
+            0x1010 => GpuVendor::Imagination,
+            0x8086 => GpuVendor::Intel,
+            0x10DE => GpuVendor::Nvidia,
+            0x5143 => GpuVendor::Qualcomm,
+            _ => GpuVendor::Unknown,
+        }
+    }
+
+    /// Maps a driver vendor string (e.g. `GL_VENDOR`) to a vendor.
+    pub fn from_name(name: &str) -> Self {
+        let name = name.to_lowercase();
+        if name.contains("amd") || name.contains("ati ") {
+            GpuVendor::Amd
+        } else if name.contains("apple") {
+            GpuVendor::Apple
+        } else if name.contains("arm") {
+            GpuVendor::Arm
+        } else if name.contains("imagination") {
+            GpuVendor::Imagination
+        } else if name.contains("intel") {
+            GpuVendor::Intel
+        } else if name.contains("nvidia") {
+            GpuVendor::Nvidia
+        } else if name.contains("qualcomm") {
+            GpuVendor::Qualcomm
+        } else {
+            GpuVendor::Unknown
+        }
+    }
+
+    /// Returns the default batching parameters for this vendor's GPUs,
+    /// which `DebugCommand::SetBatchingParameters` can override. No vendor
+    /// has measured values yet, so all of them use the values batching was
+    /// hardcoded to before it was tunable.
+    pub fn default_batching_parameters(&self) -> BatchingParameters {
+        BatchingParameters::default()
+    }
+}
+
+bitflags! {
+    /// Driver bugs and limitations worked around on specific adapters.
+    ///
+    /// Detected from `WORKAROUND_TABLE` when the renderer is created, unless
+    /// `RendererOptions::workarounds` overrides them.
+    #[cfg_attr(feature = "capture", derive(Serialize))]
+    #[cfg_attr(feature = "replay", derive(Deserialize))]
+    #[derive(Default)]
+    pub struct Workarounds: u32 {
+        /// Don't use dual-source blending for subpixel text, even if the
+        /// device advertises it.
+        const DISABLE_DUAL_SOURCE_BLENDING = 1 << 0;
+        /// Texture arrays are implemented as a single texture of stacked
+        /// layers, subject to the texture size limit, so keep the layer
+        /// count of the shared texture cache low.
+        const LIMIT_TEXTURE_ARRAY_LAYERS = 1 << 1;
+    }
+}
+
+/// An entry of `WORKAROUND_TABLE`.
+struct WorkaroundEntry {
+    /// The vendor the entry applies to, or `None` for every vendor.
+    vendor: Option<GpuVendor>,
+    /// The PCI device ids the entry applies to, or empty for every device
+    /// of the vendor. Entries with device ids never match backends that
+    /// can't report them.
+    device_ids: &'static [usize],
+    /// Whether the entry only applies on macOS.
+    macos_only: bool,
+    workarounds: Workarounds,
+}
+
+/// The known driver quirks, keyed by adapter.
+const WORKAROUND_TABLE: &[WorkaroundEntry] = &[
+    // On MBP integrated Intel GPUs, texture arrays appear to be implemented
+    // as a single texture of stacked layers. Allocating more than 32 512x512
+    // regions results in a dimension longer than 16k (the max texture size),
+    // causing incorrect behavior. The vendor isn't always reported on macOS,
+    // so this applies to every adapter there.
+    WorkaroundEntry {
+        vendor: None,
+        device_ids: &[],
+        macos_only: true,
+        workarounds: Workarounds::LIMIT_TEXTURE_ARRAY_LAYERS,
+    },
+];
+
+impl Workarounds {
+    /// The maximum number of texture array layers with
+    /// `LIMIT_TEXTURE_ARRAY_LAYERS`, i.e. 32MB of RGBA8 512x512 regions.
+    pub const MAX_LIMITED_TEXTURE_LAYERS: usize = 32;
+
+    /// Collects the workarounds of every table entry matching the adapter.
+    pub fn detect(vendor: GpuVendor, device_id: Option<usize>) -> Self {
+        WORKAROUND_TABLE
+            .iter()
+            .filter(|entry| entry.vendor.map_or(true, |v| v == vendor))
+            .filter(|entry| {
+                entry.device_ids.is_empty() ||
+                    device_id.map_or(false, |id| entry.device_ids.contains(&id))
+            })
+            .filter(|entry| !entry.macos_only || cfg!(target_os = "macos"))
+            .fold(Workarounds::empty(), |acc, entry| acc | entry.workarounds)
+    }
+}
+
+
 pub(crate) mod desc {
     #![cfg_attr(not(feature = "gleam"), allow(dead_code))]
     use device::{VertexAttribute, VertexAttributeKind, VertexDescriptor};
@@ -914,6 +1256,48 @@ pub(crate) mod desc {
         ],
     };
 
+    pub const SVG_FILTER: VertexDescriptor = VertexDescriptor {
+        vertex_attributes: &[
+            VertexAttribute {
+                name: "aPosition",
+                count: 2,
+                kind: VertexAttributeKind::F32,
+            },
+        ],
+        instance_attributes: &[
+            VertexAttribute {
+                name: "aFilterRenderTaskAddress",
+                count: 1,
+                kind: VertexAttributeKind::I32,
+            },
+            VertexAttribute {
+                name: "aFilterInput1TaskAddress",
+                count: 1,
+                kind: VertexAttributeKind::I32,
+            },
+            VertexAttribute {
+                name: "aFilterInput2TaskAddress",
+                count: 1,
+                kind: VertexAttributeKind::I32,
+            },
+            VertexAttribute {
+                name: "aFilterKind",
+                count: 1,
+                kind: VertexAttributeKind::I32,
+            },
+            VertexAttribute {
+                name: "aFilterGenericInt",
+                count: 1,
+                kind: VertexAttributeKind::I32,
+            },
+            VertexAttribute {
+                name: "aFilterExtraDataAddress",
+                count: 1,
+                kind: VertexAttributeKind::I32,
+            },
+        ],
+    };
+
     pub const CLIP: VertexDescriptor = VertexDescriptor {
         vertex_attributes: &[
             VertexAttribute {
@@ -1061,3 +1445,94 @@ pub(crate) mod desc {
         ],
     };
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use euclid::TypedPoint3D;
+
+    fn rect(x: i32, y: i32, w: i32, h: i32) -> DeviceIntRect {
+        DeviceIntRect::new(DeviceIntPoint::new(x, y), DeviceIntSize::new(w, h))
+    }
+
+    #[test]
+    fn native_surface_origin() {
+        if cfg!(feature = "gleam") {
+            assert_eq!(SurfaceOrigin::native(), SurfaceOrigin::BottomLeft);
+        } else {
+            assert_eq!(SurfaceOrigin::native(), SurfaceOrigin::TopLeft);
+        }
+        assert_eq!(SurfaceOrigin::default(), SurfaceOrigin::native());
+    }
+
+    #[test]
+    fn surface_origin_rects() {
+        let r = rect(10, 20, 30, 40);
+        assert_eq!(SurfaceOrigin::TopLeft.convert_rect(r, 100), r);
+        assert_eq!(SurfaceOrigin::BottomLeft.convert_rect(r, 100), rect(10, 40, 30, 40));
+        for &origin in &[SurfaceOrigin::TopLeft, SurfaceOrigin::BottomLeft] {
+            assert_eq!(origin.convert_rect(origin.convert_rect(r, 100), 100), r);
+            assert_eq!(origin.checked_convert_rect(r, 100), Some(origin.convert_rect(r, 100)));
+        }
+        assert_eq!(SurfaceOrigin::BottomLeft.checked_convert_rect(r, 50), None);
+        assert_eq!(SurfaceOrigin::BottomLeft.checked_convert_rect(rect(0, 0, 1, 1), i32::min_value()), None);
+    }
+
+    #[test]
+    fn surface_origin_projection() {
+        let projection = create_projection(0.0, 100.0, 100.0, 0.0);
+        let top = TypedPoint3D::new(0.0, 0.0, 0.0);
+        let bottom_left = SurfaceOrigin::BottomLeft.apply_to_projection(projection);
+        let top_left = SurfaceOrigin::TopLeft.apply_to_projection(projection);
+        assert_eq!(bottom_left.transform_point3d(&top).map(|p| p.y), Some(1.0));
+        assert_eq!(top_left.transform_point3d(&top).map(|p| p.y), Some(-1.0));
+    }
+
+    #[test]
+    fn surface_transform_projection() {
+        // A quad drawn with the rotated projection of the logical framebuffer
+        // has to cover the physical rect the blits and scissors are mapped to.
+        let size = DeviceIntSize::new(100, 50);
+        let r = rect(10, 5, 30, 20);
+        let transforms = [
+            SurfaceTransform::Identity,
+            SurfaceTransform::Rotate90,
+            SurfaceTransform::Rotate180,
+            SurfaceTransform::Rotate270,
+        ];
+        for &transform in &transforms {
+            let logical = transform.apply_to_projection(
+                create_projection(0.0, size.width as f32, 0.0, size.height as f32)
+            );
+            let physical_size = transform.transform_size(size);
+            let physical = create_projection(0.0, physical_size.width as f32, 0.0, physical_size.height as f32);
+
+            let project = |projection: &Transform3D<f32>, x: i32, y: i32| {
+                projection.transform_point3d(&TypedPoint3D::new(x as f32, y as f32, 0.0)).unwrap()
+            };
+            let corners: Vec<_> = [(r.min_x(), r.min_y()), (r.max_x(), r.max_y())]
+                .iter()
+                .map(|&(x, y)| project(&logical, x, y))
+                .collect();
+            let expected = transform.transform_rect(r, size);
+            let min = project(&physical, expected.min_x(), expected.min_y());
+            let max = project(&physical, expected.max_x(), expected.max_y());
+
+            let near = |a: f32, b: f32| (a - b).abs() < 1e-5;
+            assert!(near(corners[0].x.min(corners[1].x), min.x), "{:?}", transform);
+            assert!(near(corners[0].y.min(corners[1].y), min.y), "{:?}", transform);
+            assert!(near(corners[0].x.max(corners[1].x), max.x), "{:?}", transform);
+            assert!(near(corners[0].y.max(corners[1].y), max.y), "{:?}", transform);
+        }
+    }
+
+    #[test]
+    fn flip_pixel_rows() {
+        let mut pixels = vec![1, 1, 2, 2, 3, 3];
+        flip_rows(&mut pixels, 2);
+        assert_eq!(pixels, vec![3, 3, 2, 2, 1, 1]);
+        let mut pixels = vec![1, 2, 3, 4];
+        flip_rows(&mut pixels, 2);
+        assert_eq!(pixels, vec![3, 4, 1, 2]);
+    }
+}
@@ -2,10 +2,56 @@
  * License, v. 2.0. If a copy of the MPL was not distributed with this
  * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
 
+use std::cell::RefCell;
 use std::mem;
+use std::rc::Rc;
 
 use device::GpuFrameId;
+use internal_types::FastHashMap;
+
+const MAX_PROFILE_FRAMES: usize = 4;
+const MAX_TIMERS_PER_FRAME: u32 = 256;
+const MAX_SAMPLERS_PER_FRAME: u32 = 16;
+
+/// The number of timestamp queries the device needs to provide: a begin and
+/// an end timestamp for every timer of every profiled frame.
+pub const MAX_TIMESTAMP_QUERIES: u32 = MAX_PROFILE_FRAMES as u32 * MAX_TIMERS_PER_FRAME * 2;
+
+/// A query operation requested by the profiler. The device records them into
+/// its command buffers in submission order, right before the next GPU work.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum QueryCommand {
+    /// Writes the timestamp query with the given index.
+    Timestamp(u32),
+    /// Counts the samples passed by the following draws into the given sampler.
+    BeginSampler(u32),
+    EndSampler,
+}
+
+/// State shared between the `GpuProfiler` and the device: the requested query
+/// commands, and the results the device read back once the GPU finished the
+/// frames they were recorded in.
+pub struct GpuQueries {
+    pub pending: Vec<QueryCommand>,
+    /// Timestamps in GPU ticks, by timestamp query index.
+    pub timestamps: FastHashMap<u32, u64>,
+    /// Sample counts, by sampler index.
+    pub samples: FastHashMap<u32, u64>,
+    /// The number of nanoseconds per timestamp tick. The backend doesn't
+    /// report it, so it is one until `Device::set_timestamp_period` is called.
+    pub timestamp_period: f32,
+}
 
+impl GpuQueries {
+    pub fn new() -> Rc<RefCell<Self>> {
+        Rc::new(RefCell::new(GpuQueries {
+            pending: Vec::new(),
+            timestamps: FastHashMap::default(),
+            samples: FastHashMap::default(),
+            timestamp_period: 1.0,
+        }))
+    }
+}
 
 pub trait NamedTag {
     fn get_label(&self) -> &str;
@@ -15,6 +61,8 @@ pub trait NamedTag {
 pub struct GpuTimer<T> {
     pub tag: T,
     pub time_ns: u64,
+    /// The group the timer was started in, see `GpuProfiler::set_timer_group`.
+    pub group: u32,
 }
 
 #[derive(Debug, Clone)]
@@ -26,7 +74,7 @@ pub struct GpuSampler<T> {
 pub struct QuerySet<T> {
     set: Vec<u32>,
     data: Vec<T>,
-    pending: u32,
+    pending: Option<u32>,
 }
 
 impl<T> QuerySet<T> {
@@ -34,25 +82,25 @@ impl<T> QuerySet<T> {
         QuerySet {
             set: Vec::new(),
             data: Vec::new(),
-            pending: 0,
+            pending: None,
         }
     }
 
     fn reset(&mut self) {
         self.data.clear();
-        self.pending = 0;
+        self.pending = None;
     }
 
     fn add(&mut self, value: T) -> Option<u32> {
-        assert_eq!(self.pending, 0);
+        assert_eq!(self.pending, None);
         self.set.get(self.data.len()).cloned().map(|query_id| {
             self.data.push(value);
-            self.pending = query_id;
+            self.pending = Some(query_id);
             query_id
         })
     }
 
-    fn take<F: Fn(&mut T, u32)>(&mut self, fun: F) -> Vec<T> {
+    fn take<F: FnMut(&mut T, u32)>(&mut self, mut fun: F) -> Vec<T> {
         let mut data = mem::replace(&mut self.data, Vec::new());
         for (value, &query) in data.iter_mut().zip(self.set.iter()) {
             fun(value, query)
@@ -62,41 +110,45 @@ impl<T> QuerySet<T> {
 }
 
 pub struct GpuFrameProfile<T> {
+    queries: Rc<RefCell<GpuQueries>>,
+    /// The index of this profile in the profiler, which selects the range of
+    /// query indices it uses.
+    index: u32,
     timers: QuerySet<GpuTimer<T>>,
     samplers: QuerySet<GpuSampler<T>>,
     frame_id: GpuFrameId,
     inside_frame: bool,
+    timer_group: u32,
 }
 
 impl<T> GpuFrameProfile<T> {
-    fn new() -> Self {
+    fn new(queries: Rc<RefCell<GpuQueries>>, index: u32) -> Self {
         GpuFrameProfile {
+            queries,
+            index,
             timers: QuerySet::new(),
             samplers: QuerySet::new(),
             frame_id: GpuFrameId::new(0),
             inside_frame: false,
+            timer_group: 0,
         }
     }
 
-    fn enable_timers(&mut self, _count: i32) {
-        self.timers.set = Vec::new();
+    fn enable_timers(&mut self, count: u32) {
+        let base = self.index * MAX_TIMERS_PER_FRAME;
+        self.timers.set = (base .. base + count).collect();
     }
 
     fn disable_timers(&mut self) {
-        if !self.timers.set.is_empty() {
-            self.timers.set.clear();
-        }
         self.timers.set = Vec::new();
     }
 
-    fn enable_samplers(&mut self, _count: i32) {
-        self.samplers.set = Vec::new();
+    fn enable_samplers(&mut self, count: u32) {
+        let base = self.index * MAX_SAMPLERS_PER_FRAME;
+        self.samplers.set = (base .. base + count).collect();
     }
 
     fn disable_samplers(&mut self) {
-        if !self.samplers.set.is_empty() {
-            self.samplers.set.clear();
-        }
         self.samplers.set = Vec::new();
     }
 
@@ -105,6 +157,7 @@ impl<T> GpuFrameProfile<T> {
         self.timers.reset();
         self.samplers.reset();
         self.inside_frame = true;
+        self.timer_group = 0;
     }
 
     fn end_frame(&mut self) {
@@ -115,15 +168,15 @@ impl<T> GpuFrameProfile<T> {
 
     fn finish_timer(&mut self) {
         debug_assert!(self.inside_frame);
-        if self.timers.pending != 0 {
-            self.timers.pending = 0;
+        if let Some(query) = self.timers.pending.take() {
+            self.queries.borrow_mut().pending.push(QueryCommand::Timestamp(query * 2 + 1));
         }
     }
 
     fn finish_sampler(&mut self) {
         debug_assert!(self.inside_frame);
-        if self.samplers.pending != 0 {
-            self.samplers.pending = 0;
+        if self.samplers.pending.take().is_some() {
+            self.queries.borrow_mut().pending.push(QueryCommand::EndSampler);
         }
     }
 }
@@ -134,7 +187,8 @@ impl<T: NamedTag> GpuFrameProfile<T> {
 
         let marker = GpuMarker::new(tag.get_label());
 
-        if let Some(_query) = self.timers.add(GpuTimer { tag, time_ns: 0 }) {
+        if let Some(query) = self.timers.add(GpuTimer { tag, time_ns: 0, group: self.timer_group }) {
+            self.queries.borrow_mut().pending.push(QueryCommand::Timestamp(query * 2));
         }
 
         GpuTimeQuery(marker)
@@ -143,7 +197,8 @@ impl<T: NamedTag> GpuFrameProfile<T> {
     fn start_sampler(&mut self, tag: T) -> GpuSampleQuery {
         self.finish_sampler();
 
-        if let Some(_query) = self.samplers.add(GpuSampler { tag, count: 0 }) {
+        if let Some(query) = self.samplers.add(GpuSampler { tag, count: 0 }) {
+            self.queries.borrow_mut().pending.push(QueryCommand::BeginSampler(query));
         }
 
         GpuSampleQuery
@@ -151,14 +206,29 @@ impl<T: NamedTag> GpuFrameProfile<T> {
 
     fn build_samples(&mut self) -> (GpuFrameId, Vec<GpuTimer<T>>, Vec<GpuSampler<T>>) {
         debug_assert!(!self.inside_frame);
-
+        let mut queries = self.queries.borrow_mut();
+        let queries = &mut *queries;
+        let period = queries.timestamp_period as f64;
+        let timestamps = &mut queries.timestamps;
+        let samples = &mut queries.samples;
+
+        // Results that didn't arrive yet are reported as zero rather than
+        // blocking, as the device only reads them back once the frame they
+        // were recorded in retired.
         (
             self.frame_id,
-            self.timers.take(|timer, _query| {
-                timer.time_ns = 0
+            self.timers.take(|timer, query| {
+                let start = timestamps.remove(&(query * 2));
+                let end = timestamps.remove(&(query * 2 + 1));
+                timer.time_ns = match (start, end) {
+                    (Some(start), Some(end)) if end >= start => {
+                        ((end - start) as f64 * period) as u64
+                    }
+                    _ => 0,
+                };
             }),
-            self.samplers.take(|sampler, _query| {
-                sampler.count = 0
+            self.samplers.take(|sampler, query| {
+                sampler.count = samples.remove(&query).unwrap_or(0)
             }),
         )
     }
@@ -177,10 +247,9 @@ pub struct GpuProfiler<T> {
 }
 
 impl<T> GpuProfiler<T> {
-    pub fn new() -> Self {
-        const MAX_PROFILE_FRAMES: usize = 4;
+    pub fn new(queries: Rc<RefCell<GpuQueries>>) -> Self {
         let frames = (0 .. MAX_PROFILE_FRAMES)
-            .map(|_| GpuFrameProfile::new())
+            .map(|index| GpuFrameProfile::new(Rc::clone(&queries), index as u32))
             .collect();
 
         GpuProfiler {
@@ -190,8 +259,6 @@ impl<T> GpuProfiler<T> {
     }
 
     pub fn enable_timers(&mut self) {
-        const MAX_TIMERS_PER_FRAME: i32 = 256;
-
         for frame in &mut self.frames {
             frame.enable_timers(MAX_TIMERS_PER_FRAME);
         }
@@ -204,11 +271,6 @@ impl<T> GpuProfiler<T> {
     }
 
     pub fn enable_samplers(&mut self) {
-        const MAX_SAMPLERS_PER_FRAME: i32 = 16;
-        if cfg!(target_os = "macos") {
-            warn!("Expect macOS driver bugs related to sample queries")
-        }
-
         for frame in &mut self.frames {
             frame.enable_samplers(MAX_SAMPLERS_PER_FRAME);
         }
@@ -243,6 +305,13 @@ impl<T: NamedTag> GpuProfiler<T> {
         self.frames[self.next_frame].start_sampler(tag)
     }
 
+    /// Sets the group of the timers started from now on in the current
+    /// frame, e.g. the kind of render pass being drawn. Resets to zero at
+    /// the start of each frame.
+    pub fn set_timer_group(&mut self, group: u32) {
+        self.frames[self.next_frame].timer_group = group;
+    }
+
     pub fn finish_sampler(&mut self, _sampler: GpuSampleQuery) {
         self.frames[self.next_frame].finish_sampler()
     }
@@ -256,6 +325,7 @@ impl<T: NamedTag> GpuProfiler<T> {
     }
 }
 
+/// A no-op, gfx-hal 0.2 command buffers can't record debug markers.
 #[must_use]
 pub struct GpuMarker;
 
@@ -272,3 +342,58 @@ impl GpuMarker {
 pub struct GpuTimeQuery(GpuMarker);
 #[must_use]
 pub struct GpuSampleQuery;
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    struct Tag;
+
+    impl NamedTag for Tag {
+        fn get_label(&self) -> &str {
+            "tag"
+        }
+    }
+
+    #[test]
+    fn timer_results_are_scaled_by_the_timestamp_period() {
+        let queries = GpuQueries::new();
+        queries.borrow_mut().timestamp_period = 2.5;
+
+        let mut profile = GpuFrameProfile::new(Rc::clone(&queries), 1);
+        profile.enable_timers(MAX_TIMERS_PER_FRAME);
+        profile.begin_frame(GpuFrameId::new(1));
+        let _query = profile.start_timer(Tag);
+        profile.end_frame();
+
+        // The second profile uses the second range of timestamp queries.
+        let start = 2 * MAX_TIMERS_PER_FRAME;
+        assert_eq!(
+            queries.borrow().pending,
+            vec![QueryCommand::Timestamp(start), QueryCommand::Timestamp(start + 1)],
+        );
+
+        queries.borrow_mut().timestamps.insert(start, 1000);
+        queries.borrow_mut().timestamps.insert(start + 1, 1400);
+
+        let (_, timers, _) = profile.build_samples();
+        assert_eq!(timers.len(), 1);
+        assert_eq!(timers[0].time_ns, 1000);
+    }
+
+    #[test]
+    fn missing_timer_results_are_zero() {
+        let queries = GpuQueries::new();
+
+        let mut profile = GpuFrameProfile::new(Rc::clone(&queries), 0);
+        profile.enable_timers(MAX_TIMERS_PER_FRAME);
+        profile.begin_frame(GpuFrameId::new(1));
+        let _query = profile.start_timer(Tag);
+        profile.end_frame();
+
+        queries.borrow_mut().timestamps.insert(0, 1000);
+
+        let (_, timers, _) = profile.build_samples();
+        assert_eq!(timers[0].time_ns, 0);
+    }
+}
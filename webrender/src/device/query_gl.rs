@@ -17,6 +17,8 @@ pub trait NamedTag {
 pub struct GpuTimer<T> {
     pub tag: T,
     pub time_ns: u64,
+    /// The group the timer was started in, see `GpuProfiler::set_timer_group`.
+    pub group: u32,
 }
 
 #[derive(Debug, Clone)]
@@ -69,6 +71,7 @@ pub struct GpuFrameProfile<T> {
     samplers: QuerySet<GpuSampler<T>>,
     frame_id: GpuFrameId,
     inside_frame: bool,
+    timer_group: u32,
     ext_debug_marker: bool
 }
 
@@ -80,6 +83,7 @@ impl<T> GpuFrameProfile<T> {
             samplers: QuerySet::new(),
             frame_id: GpuFrameId::new(0),
             inside_frame: false,
+            timer_group: 0,
             ext_debug_marker
         }
     }
@@ -111,6 +115,7 @@ impl<T> GpuFrameProfile<T> {
         self.timers.reset();
         self.samplers.reset();
         self.inside_frame = true;
+        self.timer_group = 0;
     }
 
     fn end_frame(&mut self) {
@@ -142,7 +147,7 @@ impl<T: NamedTag> GpuFrameProfile<T> {
 
         let marker = GpuMarker::new(&self.gl, tag.get_label(), self.ext_debug_marker);
 
-        if let Some(query) = self.timers.add(GpuTimer { tag, time_ns: 0 }) {
+        if let Some(query) = self.timers.add(GpuTimer { tag, time_ns: 0, group: self.timer_group }) {
             self.gl.begin_query(gl::TIME_ELAPSED, query);
         }
 
@@ -258,10 +263,21 @@ impl<T: NamedTag> GpuProfiler<T> {
         self.frames[self.next_frame].start_sampler(tag)
     }
 
+    /// Sets the group of the timers started from now on in the current
+    /// frame, e.g. the kind of render pass being drawn. Resets to zero at
+    /// the start of each frame.
+    pub fn set_timer_group(&mut self, group: u32) {
+        self.frames[self.next_frame].timer_group = group;
+    }
+
     pub fn finish_sampler(&mut self, _sampler: GpuSampleQuery) {
         self.frames[self.next_frame].finish_sampler()
     }
 
+    pub fn markers_enabled(&self) -> bool {
+        self.ext_debug_marker
+    }
+
     pub fn start_marker(&mut self, label: &str) -> GpuMarker {
         GpuMarker::new(&self.gl, label, self.ext_debug_marker)
     }
@@ -5,13 +5,13 @@
 use api::{AlphaType, BorderDetails, BorderDisplayItem, BuiltDisplayListIter};
 use api::{ClipId, ColorF, ComplexClipRegion, DeviceIntPoint, DeviceIntRect, DeviceIntSize};
 use api::{DisplayItemRef, ExtendMode, ExternalScrollId, AuHelpers};
-use api::{FilterOp, FontInstanceKey, GlyphInstance, GlyphOptions, RasterSpace, GradientStop};
+use api::{FilterOp, FilterPrimitive, FilterPrimitiveInput, FontInstanceKey, GlyphInstance, GlyphOptions, RasterSpace, GradientStop};
 use api::{IframeDisplayItem, ImageKey, ImageRendering, ItemRange, LayoutPoint, ColorDepth};
 use api::{LayoutPrimitiveInfo, LayoutRect, LayoutSize, LayoutTransform, LayoutVector2D};
-use api::{LineOrientation, LineStyle, NinePatchBorderSource, PipelineId};
+use api::{LineOrientation, LineStyle, LostPipelineTreatment, NinePatchBorderSource, PipelineId};
 use api::{PropertyBinding, ReferenceFrame, ReferenceFrameKind, ScrollFrameDisplayItem, ScrollSensitivity};
 use api::{Shadow, SpaceAndClipInfo, SpatialId, SpecificDisplayItem, StackingContext, StickyFrameDisplayItem, TexelRect};
-use api::{ClipMode, TransformStyle, YuvColorSpace, YuvData};
+use api::{ColorRange, TransformStyle, YuvColorSpace, YuvData};
 use app_units::Au;
 use clip::{ClipChainId, ClipRegion, ClipItemKey, ClipStore};
 use clip_scroll_tree::{ROOT_SPATIAL_NODE_INDEX, ClipScrollTree, SpatialNodeIndex};
@@ -22,13 +22,15 @@ use image::simplify_repeated_primitive;
 use intern::{Handle, Internable, InternDebug};
 use internal_types::{FastHashMap, FastHashSet};
 use picture::{Picture3DContext, PictureCompositeMode, PicturePrimitive, PictureOptions, PrimitiveList, TileCache};
+use picture::is_supported_backdrop_filter;
 use prim_store::{PrimitiveInstance, PrimitiveKeyKind, PrimitiveSceneData};
 use prim_store::{PrimitiveInstanceKind, NinePatchDescriptor, PrimitiveStore};
 use prim_store::{PrimitiveStoreStats, ScrollNodeAndClipChain, PictureIndex};
 use prim_store::{register_prim_chase_id, get_line_decoration_sizes};
 use prim_store::borders::{ImageBorder, NormalBorderPrim};
-use prim_store::gradient::{GradientStopKey, LinearGradient, RadialGradient, RadialGradientParams};
-use prim_store::image::{Image, YuvImage};
+use prim_store::gradient::{ConicGradient, ConicGradientParams, GradientStopKey, LinearGradient};
+use prim_store::gradient::{RadialGradient, RadialGradientParams, hard_stop_bands};
+use prim_store::image::{CrossFade, Image, YuvImage};
 use prim_store::line_dec::{LineDecoration, LineDecorationCacheKey};
 use prim_store::picture::{Picture, PictureCompositeKey, PictureKey};
 use prim_store::text_run::TextRun;
@@ -212,10 +214,18 @@ impl<'a> DisplayListFlattener<'a> {
             RasterSpace::Screen,
         );
 
-        flattener.flatten_items(
-            &mut root_pipeline.display_list.iter(),
-            root_pipeline.pipeline_id,
-            LayoutVector2D::zero(),
+        if flattener.should_flatten_pipeline_content(root_pipeline_id) {
+            flattener.flatten_items(
+                &mut root_pipeline.display_list.iter(),
+                root_pipeline.pipeline_id,
+                LayoutVector2D::zero(),
+            );
+        }
+
+        flattener.add_lost_pipeline_overlay(
+            root_pipeline_id,
+            ScrollNodeAndClipChain::new(ROOT_SPATIAL_NODE_INDEX, ClipChainId::NONE),
+            &LayoutRect::new(LayoutPoint::zero(), root_pipeline.viewport_size),
         );
 
         flattener.pop_stacking_context();
@@ -225,6 +235,7 @@ impl<'a> DisplayListFlattener<'a> {
         new_scene.root_pipeline_id = Some(root_pipeline_id);
         new_scene.pipeline_epochs = scene.pipeline_epochs.clone();
         new_scene.pipelines = scene.pipelines.clone();
+        new_scene.lost_pipelines = scene.lost_pipelines.clone();
 
         FrameBuilder::with_display_list_flattener(
             view.inner_rect,
@@ -533,6 +544,7 @@ impl<'a> DisplayListFlattener<'a> {
         let complex_clips = self.get_complex_clips(pipeline_id, item.complex_clip().0);
         let clip_region = ClipRegion::create_for_clip_node(
             *item.clip_rect(),
+            None,
             complex_clips,
             info.image_mask,
             reference_frame_relative_offset,
@@ -589,6 +601,8 @@ impl<'a> DisplayListFlattener<'a> {
         spatial_node_index: SpatialNodeIndex,
         origin: LayoutPoint,
         filters: ItemRange<FilterOp>,
+        filter_primitives: ItemRange<FilterPrimitive>,
+        backdrop_filters: ItemRange<FilterOp>,
         reference_frame_relative_offset: &LayoutVector2D,
         is_backface_visible: bool,
     ) {
@@ -603,6 +617,8 @@ impl<'a> DisplayListFlattener<'a> {
             let display_list = self.scene.get_display_list_for_pipeline(pipeline_id);
             CompositeOps::new(
                 stacking_context.filter_ops_for_compositing(display_list, filters),
+                filter_primitives_for_compositing(display_list.get(filter_primitives)),
+                backdrop_filters_for_compositing(display_list.get(backdrop_filters)),
                 stacking_context.mix_blend_mode_for_compositing(),
             )
         };
@@ -682,15 +698,58 @@ impl<'a> DisplayListFlattener<'a> {
             ScrollFrameKind::PipelineRoot,
         );
 
-        self.flatten_items(
-            &mut pipeline.display_list.iter(),
-            pipeline.pipeline_id,
-            LayoutVector2D::zero(),
+        if self.should_flatten_pipeline_content(iframe_pipeline_id) {
+            self.flatten_items(
+                &mut pipeline.display_list.iter(),
+                pipeline.pipeline_id,
+                LayoutVector2D::zero(),
+            );
+        }
+
+        self.add_lost_pipeline_overlay(
+            iframe_pipeline_id,
+            ScrollNodeAndClipChain::new(spatial_node_index, clip_chain_index),
+            &iframe_rect,
         );
 
         self.pipeline_clip_chain_stack.pop();
     }
 
+    /// Returns false for lost pipelines whose content is replaced by a
+    /// placeholder, in which case their display list is skipped entirely.
+    fn should_flatten_pipeline_content(&self, pipeline_id: PipelineId) -> bool {
+        match self.config.lost_pipeline_treatment {
+            LostPipelineTreatment::Placeholder(..) => !self.scene.is_pipeline_lost(pipeline_id),
+            LostPipelineTreatment::Freeze |
+            LostPipelineTreatment::Tint(..) => true,
+        }
+    }
+
+    /// Adds the tint or placeholder rectangle for a lost pipeline, if any.
+    fn add_lost_pipeline_overlay(
+        &mut self,
+        pipeline_id: PipelineId,
+        clip_and_scroll: ScrollNodeAndClipChain,
+        rect: &LayoutRect,
+    ) {
+        if !self.scene.is_pipeline_lost(pipeline_id) {
+            return;
+        }
+
+        let color = match self.config.lost_pipeline_treatment {
+            LostPipelineTreatment::Freeze => return,
+            LostPipelineTreatment::Tint(color) |
+            LostPipelineTreatment::Placeholder(color) => color,
+        };
+
+        self.add_solid_rectangle(
+            clip_and_scroll,
+            &LayoutPrimitiveInfo::new(*rect),
+            PropertyBinding::Value(color),
+            LayoutVector2D::zero(),
+        );
+    }
+
     fn flatten_item<'b>(
         &'b mut self,
         item: DisplayItemRef<'a, 'b>,
@@ -730,6 +789,18 @@ impl<'a> DisplayListFlattener<'a> {
                     info.yuv_data,
                     info.color_depth,
                     info.color_space,
+                    info.color_range,
+                    info.image_rendering,
+                    reference_frame_relative_offset,
+                );
+            }
+            SpecificDisplayItem::CrossFade(ref info) => {
+                self.add_cross_fade(
+                    clip_and_scroll,
+                    &prim_info,
+                    info.from_key,
+                    info.to_key,
+                    info.progress,
                     info.image_rendering,
                     reference_frame_relative_offset,
                 );
@@ -773,7 +844,8 @@ impl<'a> DisplayListFlattener<'a> {
                 );
             }
             SpecificDisplayItem::Gradient(ref info) => {
-                if let Some(prim_key_kind) = self.create_linear_gradient_prim(
+                let drawn_as_bands = self.add_hard_stop_gradient(
+                    clip_and_scroll,
                     &prim_info,
                     info.gradient.start_point,
                     info.gradient.end_point,
@@ -782,15 +854,28 @@ impl<'a> DisplayListFlattener<'a> {
                     info.tile_size,
                     info.tile_spacing,
                     pipeline_id,
-                    None,
-                ) {
-                    self.add_nonshadowable_primitive(
-                        clip_and_scroll,
+                    reference_frame_relative_offset,
+                );
+                if !drawn_as_bands {
+                    if let Some(prim_key_kind) = self.create_linear_gradient_prim(
                         &prim_info,
-                        Vec::new(),
-                        prim_key_kind,
-                        reference_frame_relative_offset,
-                    );
+                        info.gradient.start_point,
+                        info.gradient.end_point,
+                        item.gradient_stops(),
+                        info.gradient.extend_mode,
+                        info.tile_size,
+                        info.tile_spacing,
+                        pipeline_id,
+                        None,
+                    ) {
+                        self.add_nonshadowable_primitive(
+                            clip_and_scroll,
+                            &prim_info,
+                            Vec::new(),
+                            prim_key_kind,
+                            reference_frame_relative_offset,
+                        );
+                    }
                 }
             }
             SpecificDisplayItem::RadialGradient(ref info) => {
@@ -815,6 +900,27 @@ impl<'a> DisplayListFlattener<'a> {
                     reference_frame_relative_offset,
                 );
             }
+            SpecificDisplayItem::ConicGradient(ref info) => {
+                let prim_key_kind = self.create_conic_gradient_prim(
+                    &prim_info,
+                    info.gradient.center,
+                    info.gradient.angle,
+                    info.gradient.start_offset,
+                    info.gradient.end_offset,
+                    item.gradient_stops(),
+                    info.gradient.extend_mode,
+                    info.tile_size,
+                    info.tile_spacing,
+                    pipeline_id,
+                );
+                self.add_nonshadowable_primitive(
+                    clip_and_scroll,
+                    &prim_info,
+                    Vec::new(),
+                    prim_key_kind,
+                    reference_frame_relative_offset,
+                );
+            }
             SpecificDisplayItem::BoxShadow(ref box_shadow_info) => {
                 let bounds = box_shadow_info
                     .box_bounds
@@ -852,6 +958,8 @@ impl<'a> DisplayListFlattener<'a> {
                     clip_and_scroll.spatial_node_index,
                     item.rect().origin,
                     item.filters(),
+                    item.filter_primitives(),
+                    item.backdrop_filters(),
                     &reference_frame_relative_offset,
                     prim_info.is_backface_visible,
                 );
@@ -881,6 +989,7 @@ impl<'a> DisplayListFlattener<'a> {
                 let complex_clips = self.get_complex_clips(pipeline_id, item.complex_clip().0);
                 let clip_region = ClipRegion::create_for_clip_node(
                     *item.clip_rect(),
+                    info.rect_binding,
                     complex_clips,
                     info.image_mask,
                     &reference_frame_relative_offset,
@@ -1447,6 +1556,8 @@ impl<'a> DisplayListFlattener<'a> {
             // PictureCompositeKey::Identity and won't have a surface to be
             // clipped.
             if stacking_context.composite_ops.filters.is_empty() &&
+               stacking_context.composite_ops.filter_primitives.is_empty() &&
+               stacking_context.composite_ops.backdrop_filters.is_empty() &&
                stacking_context.composite_ops.mix_blend_mode.is_none() {
                 cur_instance.clip_chain_id = clip_chain.take().unwrap();
             }
@@ -1506,6 +1617,88 @@ impl<'a> DisplayListFlattener<'a> {
             self.prim_store.optimize_picture_if_possible(current_pic_index);
         }
 
+        // The SVG filter graph is applied by a single picture.
+        if !stacking_context.composite_ops.filter_primitives.is_empty() {
+            let filter_primitives = &stacking_context.composite_ops.filter_primitives;
+            let composite_mode = Some(PictureCompositeMode::SvgFilter);
+
+            let filter_pic_index = PictureIndex(self.prim_store.pictures
+                .alloc()
+                .init(PicturePrimitive::new_image(
+                    composite_mode,
+                    Picture3DContext::Out,
+                    stacking_context.pipeline_id,
+                    None,
+                    true,
+                    stacking_context.requested_raster_space,
+                    PrimitiveList::new(
+                        vec![cur_instance.clone()],
+                        &self.interners,
+                    ),
+                    stacking_context.spatial_node_index,
+                    max_clip,
+                    None,
+                    PictureOptions::default(),
+                ))
+            );
+            self.prim_store.pictures[filter_pic_index.0].filter_primitives = filter_primitives.clone();
+
+            current_pic_index = filter_pic_index;
+            cur_instance = create_prim_instance(
+                current_pic_index,
+                PictureCompositeKey::svg_filter(filter_primitives),
+                stacking_context.is_backface_visible,
+                ClipChainId::NONE,
+                stacking_context.spatial_node_index,
+                &mut self.interners,
+            );
+
+            if cur_instance.is_chased() {
+                println!("\tis an SVG filter picture for a stacking context with {:?}", filter_primitives);
+            }
+        }
+
+        // The backdrop is filtered and drawn underneath the filtered content.
+        if !stacking_context.composite_ops.backdrop_filters.is_empty() {
+            let backdrop_filters = &stacking_context.composite_ops.backdrop_filters;
+            let composite_mode = Some(PictureCompositeMode::BackdropFilter);
+
+            let backdrop_pic_index = PictureIndex(self.prim_store.pictures
+                .alloc()
+                .init(PicturePrimitive::new_image(
+                    composite_mode,
+                    Picture3DContext::Out,
+                    stacking_context.pipeline_id,
+                    None,
+                    true,
+                    stacking_context.requested_raster_space,
+                    PrimitiveList::new(
+                        vec![cur_instance.clone()],
+                        &self.interners,
+                    ),
+                    stacking_context.spatial_node_index,
+                    max_clip,
+                    None,
+                    PictureOptions::default(),
+                ))
+            );
+            self.prim_store.pictures[backdrop_pic_index.0].backdrop_filters = backdrop_filters.clone();
+
+            current_pic_index = backdrop_pic_index;
+            cur_instance = create_prim_instance(
+                current_pic_index,
+                PictureCompositeKey::backdrop_filter(backdrop_filters),
+                stacking_context.is_backface_visible,
+                ClipChainId::NONE,
+                stacking_context.spatial_node_index,
+                &mut self.interners,
+            );
+
+            if cur_instance.is_chased() {
+                println!("\tis a backdrop filter picture for a stacking context with {:?}", backdrop_filters);
+            }
+        }
+
         // Same for mix-blend-mode.
         if let Some(mix_blend_mode) = stacking_context.composite_ops.mix_blend_mode {
             let composite_mode = Some(PictureCompositeMode::MixBlend(mix_blend_mode));
@@ -1678,7 +1871,7 @@ impl<'a> DisplayListFlattener<'a> {
         let handle = self
             .interners
             .clip
-            .intern(&ClipItemKey::rectangle(clip_region.main.size, ClipMode::Clip), || ());
+            .intern(&ClipItemKey::main_rect(&clip_region), || ());
 
         parent_clip_chain_index = self
             .clip_store
@@ -2051,14 +2244,16 @@ impl<'a> DisplayListFlattener<'a> {
         &mut self,
         clip_and_scroll: ScrollNodeAndClipChain,
         info: &LayoutPrimitiveInfo,
-        color: ColorF,
+        color: PropertyBinding<ColorF>,
         reference_frame_relative_offset: LayoutVector2D,
     ) {
-        if color.a == 0.0 {
-            // Don't add transparent rectangles to the draw list, but do consider them for hit
-            // testing. This allows specifying invisible hit testing areas.
-            self.add_primitive_to_hit_testing_list(info, clip_and_scroll);
-            return;
+        if let PropertyBinding::Value(value) = color {
+            if value.a == 0.0 {
+                // Don't add transparent rectangles to the draw list, but do consider them for hit
+                // testing. This allows specifying invisible hit testing areas.
+                self.add_primitive_to_hit_testing_list(info, clip_and_scroll);
+                return;
+            }
         }
 
         self.add_primitive(
@@ -2263,6 +2458,88 @@ impl<'a> DisplayListFlattener<'a> {
         }
     }
 
+    /// Draw an untiled, axis aligned linear gradient whose colors only change
+    /// at hard stops (such as a striped background) as a set of solid
+    /// rectangles, rather than interpolating a gradient table. Returns false
+    /// if the gradient isn't eligible, in which case nothing was added.
+    fn add_hard_stop_gradient(
+        &mut self,
+        clip_and_scroll: ScrollNodeAndClipChain,
+        info: &LayoutPrimitiveInfo,
+        start_point: LayoutPoint,
+        end_point: LayoutPoint,
+        stops: ItemRange<GradientStop>,
+        extend_mode: ExtendMode,
+        stretch_size: LayoutSize,
+        tile_spacing: LayoutSize,
+        pipeline_id: PipelineId,
+        reference_frame_relative_offset: LayoutVector2D,
+    ) -> bool {
+        if tile_spacing != LayoutSize::zero() ||
+           stretch_size.width < info.rect.size.width ||
+           stretch_size.height < info.rect.size.height {
+            return false;
+        }
+
+        let horizontal = start_point.y == end_point.y;
+        if !horizontal && start_point.x != end_point.x {
+            return false;
+        }
+
+        // The gradient line, measured from the primitive origin along the axis.
+        let (start, end, length) = if horizontal {
+            (start_point.x, end_point.x, info.rect.size.width)
+        } else {
+            (start_point.y, end_point.y, info.rect.size.height)
+        };
+        let delta = end - start;
+        if delta == 0.0 {
+            return false;
+        }
+
+        let bands = {
+            let display_list = self.scene.get_display_list_for_pipeline(pipeline_id);
+            let stops: Vec<GradientStop> = display_list.get(stops).collect();
+            let t0 = -start / delta;
+            let t1 = (length - start) / delta;
+
+            match hard_stop_bands(&stops, extend_mode, t0.min(t1), t0.max(t1)) {
+                Some(bands) => bands,
+                None => return false,
+            }
+        };
+
+        for (t0, t1, color) in bands {
+            let p0 = start + t0 * delta;
+            let p1 = start + t1 * delta;
+            let mut band_info = info.clone();
+            if horizontal {
+                band_info.rect.origin.x += p0.min(p1);
+                band_info.rect.size.width = (p1 - p0).abs();
+            } else {
+                band_info.rect.origin.y += p0.min(p1);
+                band_info.rect.size.height = (p1 - p0).abs();
+            }
+
+            if color.a == 0.0 {
+                self.add_primitive_to_hit_testing_list(&band_info, clip_and_scroll);
+                continue;
+            }
+
+            self.add_nonshadowable_primitive(
+                clip_and_scroll,
+                &band_info,
+                Vec::new(),
+                PrimitiveKeyKind::Rectangle {
+                    color: PropertyBinding::Value(color).into(),
+                },
+                reference_frame_relative_offset,
+            );
+        }
+
+        true
+    }
+
     pub fn create_linear_gradient_prim(
         &mut self,
         info: &LayoutPrimitiveInfo,
@@ -2374,6 +2651,47 @@ impl<'a> DisplayListFlattener<'a> {
         }
     }
 
+    pub fn create_conic_gradient_prim(
+        &mut self,
+        info: &LayoutPrimitiveInfo,
+        center: LayoutPoint,
+        angle: f32,
+        start_offset: f32,
+        end_offset: f32,
+        stops: ItemRange<GradientStop>,
+        extend_mode: ExtendMode,
+        stretch_size: LayoutSize,
+        mut tile_spacing: LayoutSize,
+        pipeline_id: PipelineId,
+    ) -> ConicGradient {
+        let mut prim_rect = info.rect;
+        simplify_repeated_primitive(&stretch_size, &mut tile_spacing, &mut prim_rect);
+
+        let display_list = self.scene.get_display_list_for_pipeline(pipeline_id);
+
+        let params = ConicGradientParams {
+            angle,
+            start_offset,
+            end_offset,
+        };
+
+        let stops = display_list.get(stops).map(|stop| {
+            GradientStopKey {
+                offset: stop.offset,
+                color: stop.color.into(),
+            }
+        }).collect();
+
+        ConicGradient {
+            extend_mode,
+            center: center.into(),
+            params,
+            stretch_size: stretch_size.into(),
+            tile_spacing: tile_spacing.into(),
+            stops,
+        }
+    }
+
     pub fn add_text(
         &mut self,
         clip_and_scroll: ScrollNodeAndClipChain,
@@ -2466,7 +2784,7 @@ impl<'a> DisplayListFlattener<'a> {
         stretch_size: LayoutSize,
         mut tile_spacing: LayoutSize,
         sub_rect: Option<TexelRect>,
-        image_key: ImageKey,
+        image_key: PropertyBinding<ImageKey>,
         image_rendering: ImageRendering,
         alpha_type: AlphaType,
         color: ColorF,
@@ -2516,12 +2834,15 @@ impl<'a> DisplayListFlattener<'a> {
         yuv_data: YuvData,
         color_depth: ColorDepth,
         color_space: YuvColorSpace,
+        color_range: ColorRange,
         image_rendering: ImageRendering,
         reference_frame_relative_offset: LayoutVector2D,
     ) {
         let format = yuv_data.get_format();
         let yuv_key = match yuv_data {
-            YuvData::NV12(plane_0, plane_1) => [plane_0, plane_1, ImageKey::DUMMY],
+            YuvData::NV12(plane_0, plane_1) |
+            YuvData::P010(plane_0, plane_1) |
+            YuvData::P016(plane_0, plane_1) => [plane_0, plane_1, ImageKey::DUMMY],
             YuvData::PlanarYCbCr(plane_0, plane_1, plane_2) => [plane_0, plane_1, plane_2],
             YuvData::InterleavedYCbCr(plane_0) => [plane_0, ImageKey::DUMMY, ImageKey::DUMMY],
         };
@@ -2535,6 +2856,31 @@ impl<'a> DisplayListFlattener<'a> {
                 yuv_key,
                 format,
                 color_space,
+                color_range,
+                image_rendering,
+            },
+            reference_frame_relative_offset,
+        );
+    }
+
+    pub fn add_cross_fade(
+        &mut self,
+        clip_and_scroll: ScrollNodeAndClipChain,
+        info: &LayoutPrimitiveInfo,
+        from_key: ImageKey,
+        to_key: ImageKey,
+        progress: PropertyBinding<f32>,
+        image_rendering: ImageRendering,
+        reference_frame_relative_offset: LayoutVector2D,
+    ) {
+        self.add_nonshadowable_primitive(
+            clip_and_scroll,
+            info,
+            Vec::new(),
+            CrossFade {
+                from_key,
+                to_key,
+                progress,
                 image_rendering,
             },
             reference_frame_relative_offset,
@@ -2815,3 +3161,57 @@ fn create_prim_instance(
         spatial_node_index,
     )
 }
+
+/// Returns the SVG filter primitives of a stacking context. A primitive may
+/// only read the output of a primitive that comes before it, so a graph with
+/// a forward or out of range reference is dropped as a whole instead of being
+/// rendered as a different graph.
+fn filter_primitives_for_compositing<I>(primitives: I) -> Vec<FilterPrimitive>
+where
+    I: IntoIterator<Item = FilterPrimitive>,
+{
+    let primitives: Vec<FilterPrimitive> = primitives
+        .into_iter()
+        .map(|primitive| primitive.sanitize())
+        .collect();
+    for (i, primitive) in primitives.iter().enumerate() {
+        let inputs = match *primitive {
+            FilterPrimitive::Blur { input, .. } |
+            FilterPrimitive::ColorMatrix { input, .. } => [input, input],
+            FilterPrimitive::Composite { input1, input2, .. } => [input1, input2],
+        };
+        let is_valid = inputs.iter().all(|input| match *input {
+            FilterPrimitiveInput::OutputOfPrimitiveIndex(index) => index < i,
+            FilterPrimitiveInput::Original |
+            FilterPrimitiveInput::Previous => true,
+        });
+        if !is_valid {
+            warn!(
+                "Invalid input of filter primitive {} {:?}, ignoring the filter primitives",
+                i,
+                primitive,
+            );
+            return Vec::new();
+        }
+    }
+    primitives
+}
+
+/// Returns the backdrop filters of a stacking context. Only filters that can
+/// be expressed as a color matrix can be applied to the backdrop, so a list
+/// with any other filter is dropped as a whole, like an invalid CSS
+/// `backdrop-filter` value.
+fn backdrop_filters_for_compositing<I>(filters: I) -> Vec<FilterOp>
+where
+    I: IntoIterator<Item = FilterOp>,
+{
+    let filters: Vec<FilterOp> = filters
+        .into_iter()
+        .map(|filter| filter.sanitize())
+        .collect();
+    if let Some(filter) = filters.iter().find(|filter| !is_supported_backdrop_filter(filter)) {
+        warn!("Unsupported backdrop filter {:?}, ignoring the backdrop filters", filter);
+        return Vec::new();
+    }
+    filters
+}
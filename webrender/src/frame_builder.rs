@@ -2,16 +2,20 @@
  * License, v. 2.0. If a copy of the MPL was not distributed with this
  * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
 
-use api::{ColorF, DeviceIntPoint, DevicePixelScale, LayoutPixel, PicturePixel, RasterPixel};
-use api::{DeviceIntRect, DeviceIntSize, DocumentLayer, FontRenderMode, DebugFlags};
-use api::{LayoutPoint, LayoutRect, LayoutSize, PipelineId, RasterSpace, WorldPoint, WorldRect, WorldPixel};
+use api::{BatchingParameters, ColorF, DeviceIntPoint, DevicePixelScale, LayoutPixel, PicturePixel, RasterPixel};
+use api::{DeviceIntRect, DeviceIntSize, DeviceRect, DeviceSize, DeviceVector2D};
+use api::{DocumentLayer, FontRenderMode, DebugFlags, ImageKey};
+use api::{LayoutPoint, LayoutRect, LayoutSize, LayoutToWorldTransform, LostPipelineTreatment, PipelineId};
+use api::{PropertyBindingId, RasterSpace};
+use api::{WorldPoint, WorldRect, WorldPixel};
 use clip::{ClipDataStore, ClipStore};
 use clip_scroll_tree::{ClipScrollTree, ROOT_SPATIAL_NODE_INDEX, SpatialNodeIndex};
+use device::Workarounds;
 use display_list_flattener::{DisplayListFlattener};
 use gpu_cache::GpuCache;
 use gpu_types::{PrimitiveHeaders, TransformPalette, UvRectKind, ZBufferIdGenerator};
 use hit_test::{HitTester, HitTestingRun};
-use internal_types::{FastHashMap, PlaneSplitter};
+use internal_types::{FastHashMap, FastHashSet, PlaneSplitter};
 use picture::{PictureSurface, PictureUpdateState, SurfaceInfo, ROOT_SURFACE_INDEX, SurfaceIndex};
 use picture::{RetainedTiles, TileCache, DirtyRegion};
 use prim_store::{PrimitiveStore, SpaceMapper, PictureIndex, PrimitiveDebugId, PrimitiveScratchBuffer};
@@ -20,6 +24,7 @@ use prim_store::{PrimitiveStoreStats};
 use profiler::{FrameProfileCounters, GpuCacheProfileCounters, TextureCacheProfileCounters};
 use render_backend::{DataStores, FrameStamp};
 use render_task::{RenderTask, RenderTaskId, RenderTaskLocation, RenderTaskTree};
+use render_task::MAX_BLUR_STD_DEVIATION;
 use resource_cache::{ResourceCache};
 use scene::{ScenePipeline, SceneProperties};
 use segment::SegmentBuilder;
@@ -53,6 +58,17 @@ pub struct FrameBuilderConfig {
     pub dual_source_blending_is_enabled: bool,
     pub chase_primitive: ChasePrimitive,
     pub enable_picture_caching: bool,
+    pub lost_pipeline_treatment: LostPipelineTreatment,
+    pub batching_parameters: BatchingParameters,
+    pub max_blur_std_dev: f32,
+    /// The driver workarounds enabled for the adapter we render with.
+    pub workarounds: Workarounds,
+    /// Whether picture cache tiles are presented by a native compositor
+    /// rather than drawn into the main framebuffer.
+    pub native_compositor: bool,
+    /// Whether the device can sample images anisotropically, see
+    /// `RendererOptions::max_anisotropy`.
+    pub anisotropic_filtering: bool,
 }
 
 /// A builder structure for `tiling::Frame`
@@ -68,6 +84,204 @@ pub struct FrameBuilder {
     pub clip_store: ClipStore,
     pub hit_testing_runs: Vec<HitTestingRun>,
     pub config: FrameBuilderConfig,
+    /// Tracks the screen areas affected by property updates between frames.
+    dirty_rect_tracker: DirtyRectTracker,
+}
+
+/// Tracks which parts of the screen are affected by dynamic property updates
+/// (scrolling, animated transforms and opacities) and image updates between
+/// two frames built from the same scene.
+///
+/// During visibility, every visible primitive reports its clipped world rect
+/// along with the spatial node and the property bindings it depends on. A
+/// primitive contributes to the dirty rect if any of those changed since the
+/// last frame, and so does the area covered by the same dependencies in the
+/// last frame, to repaint what was uncovered.
+pub struct DirtyRectTracker {
+    /// The screen rect and scale of the last frame, if any. Any change of
+    /// those invalidates the whole screen.
+    last_screen: Option<(WorldRect, DevicePixelScale)>,
+    /// World transforms of the spatial nodes, as of the last frame.
+    spatial_node_transforms: Vec<LayoutToWorldTransform>,
+    /// Float property values, as of the last frame.
+    float_properties: FastHashMap<PropertyBindingId, f32>,
+    /// Color property values, as of the last frame.
+    color_properties: FastHashMap<PropertyBindingId, ColorF>,
+    /// Image key property values, as of the last frame.
+    image_properties: FastHashMap<PropertyBindingId, ImageKey>,
+    /// Rect property values, as of the last frame.
+    rect_properties: FastHashMap<PropertyBindingId, LayoutRect>,
+    /// Per spatial node, whether its world transform changed this frame.
+    changed_spatial_nodes: Vec<bool>,
+    /// The properties that changed this frame.
+    changed_bindings: FastHashSet<PropertyBindingId>,
+    /// Areas covered by the primitives of each spatial node, for the current
+    /// and the last frame.
+    node_rects: FastHashMap<SpatialNodeIndex, WorldRect>,
+    last_node_rects: FastHashMap<SpatialNodeIndex, WorldRect>,
+    /// Areas covered by the primitives depending on each property binding,
+    /// for the current and the last frame.
+    binding_rects: FastHashMap<PropertyBindingId, WorldRect>,
+    last_binding_rects: FastHashMap<PropertyBindingId, WorldRect>,
+    /// Accumulated dirty rect of the current frame.
+    dirty_rect: WorldRect,
+    /// True if the whole screen needs to be repainted this frame.
+    full_invalidation: bool,
+}
+
+impl DirtyRectTracker {
+    pub fn new() -> Self {
+        DirtyRectTracker {
+            last_screen: None,
+            spatial_node_transforms: Vec::new(),
+            float_properties: FastHashMap::default(),
+            color_properties: FastHashMap::default(),
+            image_properties: FastHashMap::default(),
+            rect_properties: FastHashMap::default(),
+            changed_spatial_nodes: Vec::new(),
+            changed_bindings: FastHashSet::default(),
+            node_rects: FastHashMap::default(),
+            last_node_rects: FastHashMap::default(),
+            binding_rects: FastHashMap::default(),
+            last_binding_rects: FastHashMap::default(),
+            dirty_rect: WorldRect::zero(),
+            full_invalidation: true,
+        }
+    }
+
+    /// Compares the updated spatial tree and properties against the last
+    /// frame. Must be called after the spatial tree has been updated.
+    fn begin_frame(
+        &mut self,
+        screen_world_rect: WorldRect,
+        device_pixel_scale: DevicePixelScale,
+        clip_scroll_tree: &ClipScrollTree,
+        scene_properties: &SceneProperties,
+    ) {
+        self.full_invalidation = self.last_screen != Some((screen_world_rect, device_pixel_scale));
+        self.last_screen = Some((screen_world_rect, device_pixel_scale));
+        self.dirty_rect = WorldRect::zero();
+
+        self.changed_spatial_nodes.clear();
+        for (i, node) in clip_scroll_tree.spatial_nodes.iter().enumerate() {
+            let transform = node.world_content_transform.to_transform().into_owned();
+            let changed = if i < self.spatial_node_transforms.len() {
+                let changed = self.spatial_node_transforms[i] != transform;
+                self.spatial_node_transforms[i] = transform;
+                changed
+            } else {
+                self.spatial_node_transforms.push(transform);
+                true
+            };
+            self.changed_spatial_nodes.push(changed);
+        }
+        self.spatial_node_transforms.truncate(clip_scroll_tree.spatial_nodes.len());
+
+        self.changed_bindings.clear();
+        collect_changed_bindings(
+            &mut self.float_properties,
+            scene_properties.float_properties(),
+            &mut self.changed_bindings,
+        );
+        collect_changed_bindings(
+            &mut self.color_properties,
+            scene_properties.color_properties(),
+            &mut self.changed_bindings,
+        );
+        collect_changed_bindings(
+            &mut self.image_properties,
+            scene_properties.image_properties(),
+            &mut self.changed_bindings,
+        );
+        collect_changed_bindings(
+            &mut self.rect_properties,
+            scene_properties.rect_properties(),
+            &mut self.changed_bindings,
+        );
+
+        mem::swap(&mut self.node_rects, &mut self.last_node_rects);
+        self.node_rects.clear();
+        mem::swap(&mut self.binding_rects, &mut self.last_binding_rects);
+        self.binding_rects.clear();
+    }
+
+    /// Records a visible primitive. `content_changed` is set for primitives
+    /// whose content got updated since the last frame, such as images.
+    pub fn add_primitive(
+        &mut self,
+        spatial_node_index: SpatialNodeIndex,
+        world_rect: WorldRect,
+        bindings: &[PropertyBindingId],
+        content_changed: bool,
+    ) {
+        let mut is_dirty = content_changed ||
+            self.changed_spatial_nodes
+                .get(spatial_node_index.0 as usize)
+                .cloned()
+                .unwrap_or(true);
+
+        let node_rect = self.node_rects
+            .entry(spatial_node_index)
+            .or_insert(WorldRect::zero());
+        *node_rect = node_rect.union(&world_rect);
+
+        for id in bindings {
+            is_dirty |= self.changed_bindings.contains(id);
+            let binding_rect = self.binding_rects
+                .entry(*id)
+                .or_insert(WorldRect::zero());
+            *binding_rect = binding_rect.union(&world_rect);
+        }
+
+        if is_dirty {
+            self.dirty_rect = self.dirty_rect.union(&world_rect);
+        }
+    }
+
+    /// Returns the dirty rect of the frame, or None if the whole screen needs
+    /// to be repainted.
+    fn end_frame(&mut self) -> Option<WorldRect> {
+        for (index, rect) in &self.last_node_rects {
+            let changed = self.changed_spatial_nodes
+                .get(index.0 as usize)
+                .cloned()
+                .unwrap_or(true);
+            if changed {
+                self.dirty_rect = self.dirty_rect.union(rect);
+            }
+        }
+        for (id, rect) in &self.last_binding_rects {
+            if self.changed_bindings.contains(id) {
+                self.dirty_rect = self.dirty_rect.union(rect);
+            }
+        }
+
+        if self.full_invalidation {
+            None
+        } else {
+            Some(self.dirty_rect)
+        }
+    }
+}
+
+/// Adds the bindings whose value changed, appeared or disappeared since the
+/// last frame to `changed`, and remembers the current values.
+fn collect_changed_bindings<T: Clone + PartialEq>(
+    last: &mut FastHashMap<PropertyBindingId, T>,
+    current: &FastHashMap<PropertyBindingId, T>,
+    changed: &mut FastHashSet<PropertyBindingId>,
+) {
+    for (id, value) in current {
+        if last.get(id) != Some(value) {
+            changed.insert(*id);
+        }
+    }
+    for id in last.keys() {
+        if !current.contains_key(id) {
+            changed.insert(*id);
+        }
+    }
+    *last = current.clone();
 }
 
 pub struct FrameVisibilityContext<'a> {
@@ -87,10 +301,14 @@ pub struct FrameVisibilityState<'a> {
     pub tile_cache: Option<TileCache>,
     pub retained_tiles: &'a mut RetainedTiles,
     pub data_stores: &'a mut DataStores,
+    pub dirty_rect_tracker: &'a mut DirtyRectTracker,
 }
 
 pub struct FrameBuildingContext<'a> {
     pub device_pixel_scale: DevicePixelScale,
+    /// The device pixel scale with the pinch zoom rounded up to a raster
+    /// scale step, for the pictures rasterized in local space.
+    pub snapped_device_pixel_scale: DevicePixelScale,
     pub scene_properties: &'a SceneProperties,
     pub pipelines: &'a FastHashMap<PipelineId, Arc<ScenePipeline>>,
     pub screen_world_rect: WorldRect,
@@ -192,7 +410,14 @@ impl FrameBuilder {
                 dual_source_blending_is_supported: false,
                 chase_primitive: ChasePrimitive::Nothing,
                 enable_picture_caching: false,
+                lost_pipeline_treatment: LostPipelineTreatment::default(),
+                batching_parameters: BatchingParameters::default(),
+                max_blur_std_dev: MAX_BLUR_STD_DEVIATION,
+                workarounds: Workarounds::empty(),
+                native_compositor: false,
+                anisotropic_filtering: false,
             },
+            dirty_rect_tracker: DirtyRectTracker::new(),
         }
     }
 
@@ -223,6 +448,7 @@ impl FrameBuilder {
             window_size,
             pending_retained_tiles: RetainedTiles::new(),
             config: flattener.config,
+            dirty_rect_tracker: DirtyRectTracker::new(),
         }
     }
 
@@ -261,6 +487,7 @@ impl FrameBuilder {
         render_tasks: &mut RenderTaskTree,
         profile_counters: &mut FrameProfileCounters,
         device_pixel_scale: DevicePixelScale,
+        snapped_device_pixel_scale: DevicePixelScale,
         scene_properties: &SceneProperties,
         transform_palette: &mut TransformPalette,
         data_stores: &mut DataStores,
@@ -282,6 +509,7 @@ impl FrameBuilder {
 
         let frame_context = FrameBuildingContext {
             device_pixel_scale,
+            snapped_device_pixel_scale,
             scene_properties,
             pipelines,
             screen_world_rect,
@@ -301,6 +529,7 @@ impl FrameBuilder {
             0.0,
             screen_world_rect,
             clip_scroll_tree,
+            device_pixel_scale,
         );
         surfaces.push(root_surface);
 
@@ -344,6 +573,7 @@ impl FrameBuilder {
                 tile_cache: None,
                 retained_tiles: &mut retained_tiles,
                 data_stores,
+                dirty_rect_tracker: &mut self.dirty_rect_tracker,
             };
 
             self.prim_store.update_visibility(
@@ -421,6 +651,7 @@ impl FrameBuilder {
             child_tasks,
             UvRectKind::Rect,
             root_spatial_node_index,
+            frame_context.device_pixel_scale,
         );
 
         let render_task_id = frame_state.render_tasks.add(root_render_task);
@@ -440,6 +671,7 @@ impl FrameBuilder {
         clip_scroll_tree: &mut ClipScrollTree,
         pipelines: &FastHashMap<PipelineId, Arc<ScenePipeline>>,
         device_pixel_scale: DevicePixelScale,
+        snapped_device_pixel_scale: DevicePixelScale,
         layer: DocumentLayer,
         pan: WorldPoint,
         texture_cache_profile: &mut TextureCacheProfileCounters,
@@ -470,13 +702,21 @@ impl FrameBuilder {
             Some(&mut transform_palette),
         );
         self.clip_store.clear_old_instances();
+        data_stores.update_property_bindings(scene_properties, gpu_cache);
 
-        let mut render_tasks = RenderTaskTree::new(stamp.frame_id());
+        let mut render_tasks = RenderTaskTree::new(stamp.frame_id(), self.config.max_blur_std_dev);
         let mut surfaces = Vec::new();
 
         let screen_size = self.screen_rect.size.to_i32();
         let screen_world_rect = (self.screen_rect.to_f32() / device_pixel_scale).round_out();
 
+        self.dirty_rect_tracker.begin_frame(
+            screen_world_rect,
+            device_pixel_scale,
+            clip_scroll_tree,
+            scene_properties,
+        );
+
         let main_render_task_id = self.build_layer_screen_rects_and_cull_layers(
             screen_world_rect,
             clip_scroll_tree,
@@ -486,6 +726,7 @@ impl FrameBuilder {
             &mut render_tasks,
             &mut profile_counters,
             device_pixel_scale,
+            snapped_device_pixel_scale,
             scene_properties,
             &mut transform_palette,
             data_stores,
@@ -534,6 +775,7 @@ impl FrameBuilder {
         let mut z_generator = ZBufferIdGenerator::new();
         let use_dual_source_blending = self.config.dual_source_blending_is_enabled &&
                                        self.config.dual_source_blending_is_supported;
+        let mut occluded_primitives = 0;
 
         for pass in &mut passes {
             let mut ctx = RenderTargetContext {
@@ -541,11 +783,15 @@ impl FrameBuilder {
                 prim_store: &self.prim_store,
                 resource_cache,
                 use_dual_source_blending,
+                batching_parameters: self.config.batching_parameters,
                 clip_scroll_tree,
                 data_stores,
                 surfaces: &surfaces,
                 scratch,
                 screen_world_rect,
+                debug_flags,
+                native_compositor: self.config.native_compositor,
+                anisotropic_filtering: self.config.anisotropic_filtering,
             };
 
             pass.build(
@@ -562,16 +808,44 @@ impl FrameBuilder {
             match pass.kind {
                 RenderPassKind::MainFramebuffer(ref color) => {
                     has_texture_cache_tasks |= color.must_be_drawn();
+                    occluded_primitives += color.occluded_primitives;
+
+                    // Mark where batches break, and why.
+                    for batch_break in &color.batch_breaks {
+                        let marker = DeviceRect::new(
+                            batch_break.rect.origin,
+                            DeviceSize::new(10.0, 10.0),
+                        );
+                        let debug_color = batch_break.reason.debug_color();
+                        scratch.push_debug_rect(marker, debug_color);
+                        scratch.push_debug_string(
+                            marker.bottom_right() + DeviceVector2D::new(1.0, 0.0),
+                            debug_color,
+                            batch_break.reason.debug_label().to_string(),
+                        );
+                    }
                 }
                 RenderPassKind::OffScreen { ref texture_cache, ref color, .. } => {
                     has_texture_cache_tasks |= !texture_cache.is_empty();
                     has_texture_cache_tasks |= color.must_be_drawn();
+                    occluded_primitives += color.targets
+                        .iter()
+                        .map(|target| target.occluded_primitives)
+                        .sum::<usize>();
                 }
             }
         }
 
         let gpu_cache_frame_id = gpu_cache.end_frame(gpu_cache_profile).frame_id();
 
+        let dirty_rect = self.dirty_rect_tracker
+            .end_frame()
+            .map(|rect| (rect * device_pixel_scale).round_out().to_i32());
+
+        profile_counters.blurs.set(render_tasks.blur_count);
+        profile_counters.downscaled_blurs.set(render_tasks.downscaled_blur_count);
+        profile_counters.occluded_primitives.set(occluded_primitives);
+
         render_tasks.write_task_data(device_pixel_scale);
 
         resource_cache.end_frame(texture_cache_profile);
@@ -589,6 +863,7 @@ impl FrameBuilder {
             deferred_resolves,
             gpu_cache_frame_id,
             has_been_rendered: false,
+            dirty_rect,
             has_texture_cache_tasks,
             prim_headers,
             debug_items: mem::replace(&mut scratch.debug_items, Vec::new()),
@@ -608,3 +883,166 @@ impl FrameBuilder {
         )
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use api::{DynamicProperties, ExternalScrollId, LayoutTransform, LayoutVector2D};
+    use api::{PropertyBinding, PropertyBindingKey, ReferenceFrameKind, ScrollClamping};
+    use api::{ScrollSensitivity, TransformStyle, WorldSize};
+    use spatial_node::ScrollFrameKind;
+
+    type TestPrimitive<'a> = (SpatialNodeIndex, WorldRect, &'a [PropertyBindingId]);
+
+    const NO_BINDINGS: &[PropertyBindingId] = &[];
+
+    /// A spatial tree with a scroll frame in a root reference frame.
+    struct TestScene {
+        clip_scroll_tree: ClipScrollTree,
+        properties: SceneProperties,
+        root: SpatialNodeIndex,
+        scroll: SpatialNodeIndex,
+    }
+
+    impl TestScene {
+        fn new(properties: SceneProperties) -> Self {
+            let mut clip_scroll_tree = ClipScrollTree::new();
+            let root = clip_scroll_tree.add_reference_frame(
+                None,
+                TransformStyle::Flat,
+                PropertyBinding::Value(LayoutTransform::identity()),
+                ReferenceFrameKind::Transform,
+                LayoutVector2D::zero(),
+                PipelineId::dummy(),
+            );
+            let scroll = clip_scroll_tree.add_scroll_frame(
+                root,
+                Some(scroll_id()),
+                PipelineId::dummy(),
+                &LayoutRect::new(LayoutPoint::zero(), LayoutSize::new(100.0, 100.0)),
+                &LayoutSize::new(100.0, 1000.0),
+                ScrollSensitivity::ScriptAndInputEvents,
+                ScrollFrameKind::Explicit,
+            );
+            clip_scroll_tree.update_tree(WorldPoint::zero(), &properties, None);
+            TestScene { clip_scroll_tree, properties, root, scroll }
+        }
+
+        fn scroll_to(&mut self, origin: LayoutPoint) {
+            self.clip_scroll_tree.scroll_node(origin, scroll_id(), ScrollClamping::ToContentBounds);
+            self.clip_scroll_tree.update_tree(WorldPoint::zero(), &self.properties, None);
+        }
+
+        fn set_float(&mut self, key: PropertyBindingKey<f32>, value: f32) {
+            self.properties.set_properties(DynamicProperties {
+                floats: vec![key.with(value)],
+                ..DynamicProperties::default()
+            });
+            self.properties.flush_pending_updates();
+        }
+
+        /// Tracks a frame made of the given primitives, and returns its
+        /// dirty rect.
+        fn track_frame(
+            &self,
+            tracker: &mut DirtyRectTracker,
+            screen_rect: WorldRect,
+            device_pixel_scale: DevicePixelScale,
+            primitives: &[TestPrimitive],
+        ) -> Option<WorldRect> {
+            tracker.begin_frame(screen_rect, device_pixel_scale, &self.clip_scroll_tree, &self.properties);
+            for &(spatial_node_index, rect, bindings) in primitives {
+                tracker.add_primitive(spatial_node_index, rect, bindings, false);
+            }
+            tracker.end_frame()
+        }
+    }
+
+    fn scroll_id() -> ExternalScrollId {
+        ExternalScrollId(1, PipelineId::dummy())
+    }
+
+    fn world_rect(x: f32, y: f32, w: f32, h: f32) -> WorldRect {
+        WorldRect::new(WorldPoint::new(x, y), WorldSize::new(w, h))
+    }
+
+    fn screen_rect() -> WorldRect {
+        world_rect(0.0, 0.0, 400.0, 400.0)
+    }
+
+    #[test]
+    fn unchanged_frame_is_clean() {
+        let scene = TestScene::new(SceneProperties::new());
+        let scale = DevicePixelScale::new(1.0);
+        let primitives = [
+            (scene.root, world_rect(0.0, 0.0, 50.0, 50.0), NO_BINDINGS),
+            (scene.scroll, world_rect(100.0, 0.0, 50.0, 50.0), NO_BINDINGS),
+        ];
+
+        let mut tracker = DirtyRectTracker::new();
+        assert_eq!(scene.track_frame(&mut tracker, screen_rect(), scale, &primitives), None);
+        assert_eq!(
+            scene.track_frame(&mut tracker, screen_rect(), scale, &primitives),
+            Some(WorldRect::zero()),
+        );
+    }
+
+    #[test]
+    fn changed_binding_dirties_current_and_last_rects() {
+        let key = PropertyBindingKey::new(1);
+        let mut scene = TestScene::new(SceneProperties::new());
+        scene.set_float(key, 1.0);
+        let scale = DevicePixelScale::new(1.0);
+        let bindings = [key.id];
+        let static_rect = world_rect(200.0, 200.0, 50.0, 50.0);
+
+        let mut tracker = DirtyRectTracker::new();
+        scene.track_frame(&mut tracker, screen_rect(), scale, &[
+            (scene.root, world_rect(0.0, 0.0, 50.0, 50.0), &bindings[..]),
+            (scene.root, static_rect, NO_BINDINGS),
+        ]);
+
+        scene.set_float(key, 0.5);
+        let dirty_rect = scene.track_frame(&mut tracker, screen_rect(), scale, &[
+            (scene.root, world_rect(20.0, 0.0, 50.0, 50.0), &bindings[..]),
+            (scene.root, static_rect, NO_BINDINGS),
+        ]);
+        assert_eq!(dirty_rect, Some(world_rect(0.0, 0.0, 70.0, 50.0)));
+    }
+
+    #[test]
+    fn changed_transform_dirties_current_and_last_rects() {
+        let mut scene = TestScene::new(SceneProperties::new());
+        let scale = DevicePixelScale::new(1.0);
+        let static_rect = world_rect(200.0, 200.0, 50.0, 50.0);
+
+        let mut tracker = DirtyRectTracker::new();
+        scene.track_frame(&mut tracker, screen_rect(), scale, &[
+            (scene.scroll, world_rect(0.0, 50.0, 50.0, 50.0), NO_BINDINGS),
+            (scene.root, static_rect, NO_BINDINGS),
+        ]);
+
+        scene.scroll_to(LayoutPoint::new(0.0, 50.0));
+        let dirty_rect = scene.track_frame(&mut tracker, screen_rect(), scale, &[
+            (scene.scroll, world_rect(0.0, 0.0, 50.0, 50.0), NO_BINDINGS),
+            (scene.root, static_rect, NO_BINDINGS),
+        ]);
+        assert_eq!(dirty_rect, Some(world_rect(0.0, 0.0, 50.0, 100.0)));
+    }
+
+    #[test]
+    fn screen_changes_invalidate_everything() {
+        let scene = TestScene::new(SceneProperties::new());
+        let primitives = [(scene.root, world_rect(0.0, 0.0, 50.0, 50.0), NO_BINDINGS)];
+        let scale = DevicePixelScale::new(1.0);
+
+        let mut tracker = DirtyRectTracker::new();
+        scene.track_frame(&mut tracker, screen_rect(), scale, &primitives);
+
+        let resized = world_rect(0.0, 0.0, 300.0, 400.0);
+        assert_eq!(scene.track_frame(&mut tracker, resized, scale, &primitives), None);
+
+        let scaled = DevicePixelScale::new(2.0);
+        assert_eq!(scene.track_frame(&mut tracker, resized, scaled, &primitives), None);
+    }
+}
@@ -4,6 +4,8 @@
 
 #[cfg(feature = "pathfinder")]
 use api::DeviceIntPoint;
+#[cfg(feature = "debugger")]
+use debug_server::MemoryBreakdownGroup;
 use glyph_rasterizer::{FontInstance, GlyphFormat, GlyphKey, GlyphRasterizer};
 use internal_types::FastHashMap;
 use render_task::RenderTaskCache;
@@ -117,6 +119,16 @@ impl GlyphCache {
             .expect("BUG: Unable to find glyph key cache!")
     }
 
+    #[cfg(feature = "debugger")]
+    pub fn report_memory_breakdown(&self, group: &mut MemoryBreakdownGroup) {
+        let glyph_count = self.glyph_key_caches
+            .values()
+            .map(|glyph_key_cache| glyph_key_cache.len())
+            .sum();
+        group.add::<(FontInstance, GlyphKeyCache)>("glyph_key_caches", self.glyph_key_caches.len());
+        group.add::<(GlyphKey, GlyphCacheEntry)>("cached_glyphs", glyph_count);
+    }
+
     pub fn clear(&mut self) {
         for (_, glyph_key_cache) in &mut self.glyph_key_caches {
             glyph_key_cache.clear()
@@ -73,7 +73,7 @@ struct CacheLocation {
 }
 
 /// A single texel in RGBAF32 texture - 16 bytes.
-#[derive(Copy, Clone, Debug, MallocSizeOf)]
+#[derive(Copy, Clone, Debug, PartialEq, MallocSizeOf)]
 #[cfg_attr(feature = "capture", derive(Serialize))]
 #[cfg_attr(feature = "replay", derive(Deserialize))]
 pub struct GpuBlockData {
@@ -145,7 +145,7 @@ impl GpuCacheHandle {
 // A unique address in the GPU cache. These are uploaded
 // as part of the primitive instances, to allow the vertex
 // shader to fetch the specific data.
-#[derive(Copy, Debug, Clone, MallocSizeOf, Eq, PartialEq)]
+#[derive(Copy, Debug, Clone, MallocSizeOf, Eq, Hash, PartialEq)]
 #[cfg_attr(feature = "capture", derive(Serialize))]
 #[cfg_attr(feature = "replay", derive(Deserialize))]
 pub struct GpuCacheAddress {
@@ -321,6 +321,94 @@ pub struct GpuCacheUpdateList {
     /// Whole state GPU block metadata for debugging.
     #[cfg_attr(feature = "serde", serde(skip))]
     pub debug_commands: Vec<GpuCacheDebugCmd>,
+    /// The number of rows the updates moved through before and after
+    /// `coalesce`, for profiling.
+    #[cfg_attr(feature = "serde", serde(skip))]
+    pub requested_rows: usize,
+    #[cfg_attr(feature = "serde", serde(skip))]
+    pub coalesced_rows: usize,
+}
+
+impl GpuCacheUpdateList {
+    /// Reorders the pending copies by texture address and merges the ones
+    /// that write contiguous blocks of the same row. The renderer then
+    /// touches each dirty row once, with fewer and larger uploads, instead
+    /// of following the order in which primitives requested their data.
+    ///
+    /// The list is left untouched if any two copies overlap, since their
+    /// relative order matters in that case.
+    pub fn coalesce(&mut self) {
+        self.requested_rows = touched_rows(&self.updates);
+        self.coalesced_rows = self.requested_rows;
+        if self.updates.len() < 2 {
+            return;
+        }
+
+        let mut order: Vec<usize> = (0 .. self.updates.len()).collect();
+        order.sort_by_key(|&i| match self.updates[i] {
+            GpuCacheUpdate::Copy { address, .. } => (address.v, address.u),
+        });
+
+        for pair in order.windows(2) {
+            let (prev_address, prev_count) = match self.updates[pair[0]] {
+                GpuCacheUpdate::Copy { address, block_count, .. } => (address, block_count),
+            };
+            let next_address = match self.updates[pair[1]] {
+                GpuCacheUpdate::Copy { address, .. } => address,
+            };
+            if prev_address.v == next_address.v &&
+                prev_address.u as usize + prev_count > next_address.u as usize {
+                return;
+            }
+        }
+
+        let mut updates = Vec::with_capacity(self.updates.len());
+        let mut blocks = Vec::with_capacity(self.blocks.len());
+
+        for i in order {
+            let (block_index, block_count, address) = match self.updates[i] {
+                GpuCacheUpdate::Copy { block_index, block_count, address } => {
+                    (block_index, block_count, address)
+                }
+            };
+            blocks.extend_from_slice(&self.blocks[block_index .. block_index + block_count]);
+
+            if let Some(&mut GpuCacheUpdate::Copy { block_count: ref mut run_count, address: run_address, .. }) = updates.last_mut() {
+                if run_address.v == address.v &&
+                    run_address.u as usize + *run_count == address.u as usize {
+                    *run_count += block_count;
+                    continue;
+                }
+            }
+
+            updates.push(GpuCacheUpdate::Copy {
+                block_index: blocks.len() - block_count,
+                block_count,
+                address,
+            });
+        }
+
+        self.coalesced_rows = touched_rows(&updates);
+        self.updates = updates;
+        self.blocks = blocks;
+    }
+}
+
+/// Returns the number of rows the updates write to, counting a row again
+/// each time the updates come back to it.
+fn touched_rows(updates: &[GpuCacheUpdate]) -> usize {
+    let mut rows = 0;
+    let mut current_row = None;
+    for update in updates {
+        let row = match *update {
+            GpuCacheUpdate::Copy { address, .. } => address.v,
+        };
+        if current_row != Some(row) {
+            current_row = Some(row);
+            rows += 1;
+        }
+    }
+    rows
 }
 
 // Holds the free lists of fixed size blocks. Mostly
@@ -831,14 +919,18 @@ impl GpuCache {
     pub fn extract_updates(&mut self) -> GpuCacheUpdateList {
         let clear = self.pending_clear;
         self.pending_clear = false;
-        GpuCacheUpdateList {
+        let mut list = GpuCacheUpdateList {
             frame_id: self.now.frame_id(),
             clear,
             height: self.texture.height,
             debug_commands: mem::replace(&mut self.texture.debug_commands, Vec::new()),
             updates: mem::replace(&mut self.texture.updates, Vec::new()),
             blocks: mem::replace(&mut self.texture.pending_blocks, Vec::new()),
-        }
+            requested_rows: 0,
+            coalesced_rows: 0,
+        };
+        list.coalesce();
+        list
     }
 
     /// Sets the current debug flags for the system.
@@ -868,3 +960,37 @@ fn test_struct_sizes() {
     // them small helps reduce memory overhead.
     assert_eq!(mem::size_of::<Block>(), 24, "Block size changed");
 }
+
+#[test]
+fn test_coalesce_updates() {
+    fn block(value: f32) -> GpuBlockData {
+        GpuBlockData { data: [value; 4] }
+    }
+
+    let mut list = GpuCacheUpdateList {
+        frame_id: FrameId::INVALID,
+        clear: false,
+        height: 2,
+        updates: vec![
+            GpuCacheUpdate::Copy { block_index: 0, block_count: 2, address: GpuCacheAddress::new(2, 1) },
+            GpuCacheUpdate::Copy { block_index: 2, block_count: 1, address: GpuCacheAddress::new(8, 0) },
+            GpuCacheUpdate::Copy { block_index: 3, block_count: 2, address: GpuCacheAddress::new(0, 1) },
+        ],
+        blocks: vec![block(2.0), block(3.0), block(8.0), block(0.0), block(1.0)],
+        debug_commands: Vec::new(),
+        requested_rows: 0,
+        coalesced_rows: 0,
+    };
+    list.coalesce();
+    assert_eq!((list.requested_rows, list.coalesced_rows), (3, 2));
+
+    let runs: Vec<_> = list.updates.iter().map(|update| match *update {
+        GpuCacheUpdate::Copy { block_index, block_count, address } => {
+            (block_index, block_count, address.u, address.v)
+        }
+    }).collect();
+    assert_eq!(runs, vec![(0, 1, 8, 0), (1, 4, 0, 1)]);
+
+    let values: Vec<f32> = list.blocks.iter().map(|b| b.data[0]).collect();
+    assert_eq!(values, vec![8.0, 0.0, 1.0, 2.0, 3.0]);
+}
@@ -181,7 +181,7 @@ impl HitTester {
         for node in &clip_store.clip_chain_nodes {
             let clip_node = &clip_data_store[node.handle];
             self.clip_chains.push(HitTestClipChainNode {
-                region: HitTestClipNode::new(node.local_pos, clip_node),
+                region: HitTestClipNode::new(clip_node.local_pos(node.local_pos), clip_node),
                 spatial_node_index: node.spatial_node_index,
                 parent_clip_chain_id: HitTestClipChainId(node.parent_clip_chain_id.0),
             });
@@ -397,8 +397,8 @@ pub fn for_each_tile_in_range(
 mod tests {
     use super::*;
     use std::collections::HashSet;
-    use api::{LayoutRect, DeviceIntSize};
-    use euclid::{rect, size2};
+    use api::{DeviceIntSize, ImageDescriptor, ImageFormat, LayoutRect};
+    use euclid::{point2, rect, size2};
 
     // this checks some additional invariants
     fn checked_for_each_tile(
@@ -454,4 +454,40 @@ mod tests {
         );
         assert_eq!(count, 0);
     }
+
+    #[test]
+    fn compressed_block_sizes() {
+        assert_eq!(ImageFormat::BGRA8.block_rows(13), 13);
+        assert_eq!(ImageFormat::BGRA8.row_size_in_bytes(13), 13 * 4);
+        assert_eq!(ImageFormat::BGRA8.bytes_per_block(), ImageFormat::BGRA8.bytes_per_pixel());
+
+        assert_eq!(ImageFormat::BC1.block_rows(0), 0);
+        assert_eq!(ImageFormat::BC1.block_rows(4), 1);
+        assert_eq!(ImageFormat::BC1.block_rows(13), 4);
+        assert_eq!(ImageFormat::BC1.row_size_in_bytes(13), 4 * 8);
+        assert_eq!(ImageFormat::BC3.row_size_in_bytes(16), 4 * 16);
+        assert_eq!(ImageFormat::BC7.size_in_bytes(5, 9), 2 * 3 * 16);
+    }
+
+    #[test]
+    fn compressed_offsets() {
+        let mut descriptor = ImageDescriptor::new(64, 32, ImageFormat::BC1, false, false);
+        descriptor.offset = 100;
+        assert_eq!(descriptor.compute_stride(), 16 * 8);
+        assert_eq!(descriptor.compute_total_size(), 16 * 8 * 8);
+        assert_eq!(descriptor.compute_offset(point2(0, 0)), 100);
+        assert_eq!(descriptor.compute_offset(point2(8, 4)), 100 + 16 * 8 + 2 * 8);
+
+        descriptor.stride = Some(200);
+        assert_eq!(descriptor.compute_offset(point2(4, 8)), 100 + 2 * 200 + 8);
+
+        let descriptor = ImageDescriptor::new(64, 32, ImageFormat::BGRA8, false, false);
+        assert_eq!(descriptor.compute_offset(point2(3, 5)), 5 * 64 * 4 + 3 * 4);
+    }
+
+    #[test]
+    #[should_panic]
+    fn compressed_formats_have_no_bytes_per_pixel() {
+        ImageFormat::ETC2.bytes_per_pixel();
+    }
 }
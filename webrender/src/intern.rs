@@ -34,6 +34,8 @@
 //! cache invalidation.
 
 use api::{LayoutPrimitiveInfo};
+#[cfg(feature = "debugger")]
+use debug_server::MemoryBreakdownGroup;
 use internal_types::FastHashMap;
 use malloc_size_of::MallocSizeOf;
 use profiler::ResourceProfileCounter;
@@ -170,6 +172,17 @@ where
 
         debug_assert!(data_iter.next().is_none());
     }
+
+    /// Iterate over all the items in the store, e.g. to refresh the
+    /// ones that depend on animated properties.
+    pub fn iter_mut(&mut self) -> impl Iterator<Item = &mut T> {
+        self.items.iter_mut().filter_map(|item| item.as_mut())
+    }
+
+    #[cfg(feature = "debugger")]
+    pub fn report_memory_breakdown(&self, name: &'static str, group: &mut MemoryBreakdownGroup) {
+        group.add::<Option<T>>(name, self.items.len());
+    }
 }
 
 /// Retrieve an item from the store via handle
@@ -63,6 +63,14 @@ pub type UpdateList = intern::UpdateList<YuvImageKey>;
 pub type Interner = intern::Interner<YuvImageKey, PrimitiveSceneData, Marker>;
 }
 
+pub mod cross_fade {
+common!();
+use ::prim_store::image::{CrossFadeKey, CrossFadeTemplate};
+pub type Store = intern::DataStore<CrossFadeKey, CrossFadeTemplate, Marker>;
+pub type UpdateList = intern::UpdateList<CrossFadeKey>;
+pub type Interner = intern::Interner<CrossFadeKey, PrimitiveSceneData, Marker>;
+}
+
 pub mod line_decoration {
 use ::prim_store::line_dec::{LineDecorationKey, LineDecorationTemplate};
 common!();
@@ -87,6 +95,14 @@ pub type UpdateList = intern::UpdateList<RadialGradientKey>;
 pub type Interner = intern::Interner<RadialGradientKey, PrimitiveSceneData, Marker>;
 }
 
+pub mod conic_grad {
+common!();
+use ::prim_store::gradient::{ConicGradientKey, ConicGradientTemplate};
+pub type Store = intern::DataStore<ConicGradientKey, ConicGradientTemplate, Marker>;
+pub type UpdateList = intern::UpdateList<ConicGradientKey>;
+pub type Interner = intern::Interner<ConicGradientKey, PrimitiveSceneData, Marker>;
+}
+
 pub mod picture {
 common!();
 use ::prim_store::picture::{PictureKey, PictureTemplate};
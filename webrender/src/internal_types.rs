@@ -286,6 +286,7 @@ pub struct RenderedDocument {
 pub enum DebugOutput {
     FetchDocuments(String),
     FetchClipScrollTree(String),
+    FetchMemoryBreakdown(String),
     #[cfg(feature = "capture")]
     SaveCapture(CaptureConfig, Vec<ExternalCaptureImage>),
     #[cfg(feature = "replay")]
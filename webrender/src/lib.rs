@@ -51,6 +51,27 @@ macro_rules! matches {
     }
 }
 
+/// Logs device diagnostics of a category (`binds`, `uploads` or `targets`)
+/// under the `webrender::device::<category>` log target. A
+/// `ValidationLogSink` installed as the logger only forwards the categories
+/// it was configured with. These are emitted for every draw, so they are
+/// only compiled in with the `device_log` feature.
+#[cfg(feature = "device_log")]
+macro_rules! device_log {
+    ($category:ident, $level:ident, $($arg:tt)+) => {
+        log!(
+            target: concat!("webrender::device::", stringify!($category)),
+            ::log::Level::$level,
+            $($arg)+
+        )
+    }
+}
+
+#[cfg(not(feature = "device_log"))]
+macro_rules! device_log {
+    ($category:ident, $level:ident, $($arg:tt)+) => {}
+}
+
 #[macro_use]
 extern crate bitflags;
 #[macro_use]
@@ -79,6 +100,7 @@ mod box_shadow;
 mod capture;
 mod clip;
 mod clip_scroll_tree;
+mod compositor;
 mod debug_colors;
 mod debug_font_data;
 mod debug_render;
@@ -106,10 +128,12 @@ mod picture;
 mod prim_store;
 mod print_tree;
 mod profiler;
-mod record;
+pub mod record;
 mod render_backend;
 mod render_task;
 mod renderer;
+#[cfg(feature = "replay")]
+pub mod replay;
 mod resource_cache;
 mod scene;
 mod scene_builder;
@@ -184,7 +208,6 @@ extern crate plane_split;
 extern crate rayon;
 extern crate rendy_memory;
 extern crate ron;
-#[cfg(feature = "debugger")]
 extern crate serde_json;
 extern crate sha2;
 extern crate smallvec;
@@ -203,14 +226,25 @@ pub extern crate webrender_api;
 extern crate webrender_build;
 
 #[doc(hidden)]
-pub use device::{build_shader_strings, ReadPixelsFormat, UploadMethod, VertexUsageHint};
+pub use device::{build_shader_strings, ReadPixelsFormat, ReadbackHandle, UploadMethod, VertexUsageHint};
 pub use device::{ProgramBinary, ProgramCache, ProgramCacheObserver, ShaderPrecacheFlags};
-pub use device::{Device, DeviceInit};
+pub use device::{Device, DeviceInit, SurfaceOrigin, SurfaceTransform};
+pub use compositor::{CompositeSurface, Compositor, DrawCompositor, NativeSurfaceId};
+pub use device::{GpuVendor, Texture, Workarounds};
+#[cfg(not(feature = "gleam"))]
+pub use device::{CommandStream, DeviceCommand, RecordedBlendMode, RecordedDepthMode};
+#[cfg(not(feature = "gleam"))]
+pub use device::{enable_validation_layers, ColorSpace, DeviceLogCategories, OutputFormat, ValidationLogSink};
+#[cfg(feature = "debugger")]
+pub use debug_server::{serve_tcp, DebugClient, DebugClientId, DebugClientRegistrar, DebugTransport};
+#[cfg(not(feature = "gleam"))]
+pub use renderer::SplitScreen;
 pub use frame_builder::ChasePrimitive;
 pub use renderer::{AsyncPropertySampler, CpuProfile, DebugFlags, OutputImageHandler, RendererKind};
 pub use renderer::{ExternalImage, ExternalImageHandler, ExternalImageSource, GpuProfile};
-pub use renderer::{GraphicsApi, GraphicsApiInfo, PipelineInfo, Renderer, RendererOptions};
-pub use renderer::{RendererStats, SceneBuilderHooks, ThreadListener};
+pub use renderer::{GraphicsApi, GraphicsApiInfo, PipelineInfo, Renderer, RendererError, RendererOptions};
+pub use renderer::{FramePacingInfo, RendererStats, SceneBuilderHooks, ThreadListener};
+pub use profiler::{GpuPassKind, PassBudget, PassBudgetAlert};
 pub use renderer::MAX_VERTEX_TEXTURE_WIDTH;
 pub use rendy_memory::{DynamicConfig, HeapsConfig, LinearConfig};
 pub use shade::{Shaders, WrShaders};
@@ -6,10 +6,11 @@ use api::{DeviceRect, FilterOp, MixBlendMode, PipelineId, PremultipliedColorF, P
 use api::{DeviceIntRect, DevicePoint, LayoutRect, PictureToRasterTransform, LayoutPixel, PropertyBinding, PropertyBindingId};
 use api::{DevicePixelScale, RasterRect, RasterSpace, ColorF, ImageKey, DirtyRect, WorldSize, ClipMode, LayoutSize};
 use api::{PicturePixel, RasterPixel, WorldPixel, WorldRect, ImageFormat, ImageDescriptor, WorldVector2D, LayoutPoint};
-use api::{DebugFlags, DeviceVector2D};
+use api::{DebugFlags, DeviceVector2D, FilterPrimitive};
 use box_shadow::{BLUR_SAMPLE_SCALE};
 use clip::{ClipChainId, ClipChainNode, ClipItem};
 use clip_scroll_tree::{ROOT_SPATIAL_NODE_INDEX, ClipScrollTree, SpatialNodeIndex, CoordinateSystemId};
+use compositor::{CompositeTile, NativeSurfaceId};
 use debug_colors;
 use device::TextureFilter;
 use euclid::{TypedScale, vec3, TypedRect, TypedPoint2D, TypedSize2D};
@@ -103,6 +104,11 @@ const MAX_DIRTY_RECTS: usize = 3;
 ///  in WorldPixel coordinates.
 const MAX_SURFACE_SIZE: f32 = 4096.0;
 
+/// The granularity of the automatic raster scale of surfaces that
+/// establish a raster root, so that animated transforms don't end up
+/// rasterizing glyphs and render tasks at a new scale every frame.
+pub const RASTER_SCALE_STEP: f32 = 0.25;
+
 
 /// The maximum number of primitives to look for in a display
 /// list, trying to find unique primitives.
@@ -689,6 +695,28 @@ impl TileCache {
         }
     }
 
+    /// Describes a tile drawn this frame as a compositor surface, or returns
+    /// None if no part of it is visible.
+    pub fn composite_tile(
+        &self,
+        tile_index: TileIndex,
+        device_pixel_scale: DevicePixelScale,
+        resource_cache: &ResourceCache,
+    ) -> Option<CompositeTile> {
+        let tile = &self.tiles[tile_index.0];
+        let clip_rect = tile.world_rect.intersection(&self.world_bounding_rect)?;
+        let cache_item = resource_cache.get_texture_cache_item(&tile.handle);
+
+        Some(CompositeTile {
+            id: NativeSurfaceId(tile.id.0 as u64),
+            offset: (tile.world_rect.origin * device_pixel_scale).round().to_i32(),
+            clip_rect: (clip_rect * device_pixel_scale).round_out().to_i32(),
+            texture: cache_item.texture_id,
+            texture_layer: cache_item.texture_layer,
+            uv_rect: cache_item.uv_rect,
+        })
+    }
+
     /// Get the tile coordinates for a given rectangle.
     fn get_tile_coords_for_rect(
         &self,
@@ -935,7 +963,8 @@ impl TileCache {
         let root_clip_node = &frame_state
             .data_stores
             .clip[root_clip_chain_node.handle];
-        if let Some(clip_rect) = root_clip_node.item.get_local_clip_rect(root_clip_chain_node.local_pos) {
+        let root_clip_pos = root_clip_node.local_pos(root_clip_chain_node.local_pos);
+        if let Some(clip_rect) = root_clip_node.item.get_local_clip_rect(root_clip_pos) {
             self.map_local_to_world.set_target_spatial_node(
                 root_clip_chain_node.spatial_node_index,
                 frame_context.clip_scroll_tree,
@@ -1030,7 +1059,7 @@ impl TileCache {
         let mut world_clips: SmallVec<[(RectangleKey, SpatialNodeIndex); 4]> = SmallVec::default();
 
         // Some primitives can not be cached (e.g. external video images)
-        let is_cacheable = prim_instance.is_cacheable(
+        let mut is_cacheable = prim_instance.is_cacheable(
             &data_stores,
             resource_cache,
         );
@@ -1086,12 +1115,20 @@ impl TileCache {
                 image_keys.extend_from_slice(&yuv_image_data.yuv_key);
                 true
             }
+            PrimitiveInstanceKind::CrossFade { data_handle, .. } => {
+                let cross_fade_data = &data_stores.cross_fade[data_handle].kind;
+                opacity_bindings.push(cross_fade_data.progress.into());
+                image_keys.push(cross_fade_data.from_key);
+                image_keys.push(cross_fade_data.to_key);
+                true
+            }
             PrimitiveInstanceKind::TextRun { .. } |
             PrimitiveInstanceKind::LineDecoration { .. } |
             PrimitiveInstanceKind::Clear { .. } |
             PrimitiveInstanceKind::NormalBorder { .. } |
             PrimitiveInstanceKind::LinearGradient { .. } |
             PrimitiveInstanceKind::RadialGradient { .. } |
+            PrimitiveInstanceKind::ConicGradient { .. } |
             PrimitiveInstanceKind::ImageBorder { .. } => {
                 // These don't contribute dependencies
                 true
@@ -1109,6 +1146,7 @@ impl TileCache {
         while current_clip_chain_id != ClipChainId::NONE {
             let clip_chain_node = &clip_chain_nodes[current_clip_chain_id.0 as usize];
             let clip_node = &data_stores.clip[clip_chain_node.handle];
+            let clip_local_pos = clip_node.local_pos(clip_chain_node.local_pos);
 
             // We can skip the root clip node - it will be taken care of by the
             // world bounding rect calculated for the cache.
@@ -1117,6 +1155,11 @@ impl TileCache {
                 continue;
             }
 
+            // Animated clip rects change without invalidating the tiles.
+            if clip_node.rect_binding.is_some() {
+                is_cacheable = false;
+            }
+
             self.map_local_to_world.set_target_spatial_node(
                 clip_chain_node.spatial_node_index,
                 clip_scroll_tree,
@@ -1130,7 +1173,7 @@ impl TileCache {
                     let clip_spatial_node = &clip_scroll_tree.spatial_nodes[clip_chain_node.spatial_node_index.0 as usize];
 
                     let local_clip_rect = LayoutRect::new(
-                        clip_chain_node.local_pos,
+                        clip_local_pos,
                         size,
                     );
 
@@ -1199,7 +1242,7 @@ impl TileCache {
                 }
 
                 let local_clip_rect = LayoutRect::new(
-                    clip_chain_node.local_pos,
+                    clip_local_pos,
                     LayoutSize::zero(),
                 );
                 if let Some(world_clip_rect) = self.map_local_to_world.map(&local_clip_rect) {
@@ -1596,6 +1639,8 @@ pub struct SurfaceInfo {
     pub tasks: Vec<RenderTaskId>,
     /// How much the local surface rect should be inflated (for blur radii).
     pub inflation_factor: f32,
+    /// The scale from the raster space of this surface to device pixels.
+    pub device_pixel_scale: DevicePixelScale,
 }
 
 impl SurfaceInfo {
@@ -1605,6 +1650,7 @@ impl SurfaceInfo {
         inflation_factor: f32,
         world_rect: WorldRect,
         clip_scroll_tree: &ClipScrollTree,
+        device_pixel_scale: DevicePixelScale,
     ) -> Self {
         let map_surface_to_world = SpaceMapper::new_with_target(
             ROOT_SPATIAL_NODE_INDEX,
@@ -1630,6 +1676,7 @@ impl SurfaceInfo {
             surface_spatial_node_index,
             tasks: Vec::new(),
             inflation_factor,
+            device_pixel_scale,
         }
     }
 
@@ -1666,6 +1713,13 @@ pub enum PictureCompositeMode {
     MixBlend(MixBlendMode),
     /// Apply a CSS filter.
     Filter(FilterOp),
+    /// Apply the SVG filter primitives stored in `filter_primitives`
+    /// of the picture.
+    SvgFilter,
+    /// Draw the backdrop underneath the picture, filtered by the
+    /// `backdrop_filters` of the picture, which are all expressible as a
+    /// color matrix, see `is_supported_backdrop_filter`.
+    BackdropFilter,
     /// Draw to intermediate surface, copy straight across. This
     /// is used for CSS isolation, and plane splitting.
     Blit,
@@ -1853,12 +1907,18 @@ impl PrimitiveList {
                 PrimitiveInstanceKind::RadialGradient { data_handle, ..} => {
                     &interners.radial_grad[data_handle]
                 }
+                PrimitiveInstanceKind::ConicGradient { data_handle, ..} => {
+                    &interners.conic_grad[data_handle]
+                }
                 PrimitiveInstanceKind::TextRun { data_handle, .. } => {
                     &interners.text_run[data_handle]
                 }
                 PrimitiveInstanceKind::YuvImage { data_handle, .. } => {
                     &interners.yuv_image[data_handle]
                 }
+                PrimitiveInstanceKind::CrossFade { data_handle, .. } => {
+                    &interners.cross_fade[data_handle]
+                }
             };
 
             // Get the key for the cluster that this primitive should
@@ -1936,9 +1996,9 @@ pub struct PicturePrimitive {
     // in this picture.
     pub apply_local_clip_rect: bool,
 
-    // If a mix-blend-mode, contains the render task for
-    // the readback of the framebuffer that we use to sample
-    // from in the mix-blend-mode shader.
+    // If a mix-blend-mode or backdrop filter, contains the
+    // render task for the capture of the backdrop that we
+    // sample from in the composite shader.
     // For drop-shadow filter, this will store the original
     // picture task which would be rendered on screen after
     // blur pass.
@@ -1978,6 +2038,13 @@ pub struct PicturePrimitive {
     /// If Some(..) the tile cache that is associated with this picture.
     pub tile_cache: Option<TileCache>,
 
+    /// The SVG filter graph applied by a `PictureCompositeMode::SvgFilter`.
+    pub filter_primitives: Vec<FilterPrimitive>,
+
+    /// The filters applied to the backdrop by a
+    /// `PictureCompositeMode::BackdropFilter`.
+    pub backdrop_filters: Vec<FilterOp>,
+
     /// The config options for this picture.
     options: PictureOptions,
 }
@@ -2089,10 +2156,25 @@ impl PicturePrimitive {
             local_clip_rect,
             gpu_location: GpuCacheHandle::new(),
             tile_cache,
+            filter_primitives: Vec::new(),
+            backdrop_filters: Vec::new(),
             options,
         }
     }
 
+    /// The total standard deviation of the blurs in the SVG filter graph,
+    /// which bounds how far the filter output spreads out.
+    fn svg_filter_blur_radius(&self) -> f32 {
+        self.filter_primitives
+            .iter()
+            .map(|primitive| match *primitive {
+                FilterPrimitive::Blur { std_deviation, .. } => std_deviation,
+                FilterPrimitive::ColorMatrix { .. } |
+                FilterPrimitive::Composite { .. } => 0.0,
+            })
+            .sum()
+    }
+
     pub fn take_context(
         &mut self,
         pic_index: PictureIndex,
@@ -2354,6 +2436,53 @@ impl PicturePrimitive {
         }
     }
 
+    /// Pick the scale a surface establishing a raster root is rasterized
+    /// at. The embedder can request one with a local raster space, otherwise
+    /// it follows the scale of the transform into the parent raster root, so
+    /// that magnified content doesn't get blurry. Either way, the scale is
+    /// clamped to keep the surface within the memory budget.
+    fn raster_device_pixel_scale(
+        &self,
+        surface: &SurfaceInfo,
+        parent_raster_spatial_node_index: SpatialNodeIndex,
+        parent_device_pixel_scale: DevicePixelScale,
+        frame_context: &FrameBuildingContext,
+    ) -> DevicePixelScale {
+        let scale = match self.requested_raster_space {
+            // Follow the pinch zoom by steps, so that the picture is composited
+            // with the animated zoom rather than rasterized again every frame.
+            RasterSpace::Local(scale) => {
+                scale.max(RASTER_SCALE_STEP) * frame_context.snapped_device_pixel_scale.0
+            }
+            RasterSpace::Screen => {
+                let xf = frame_context.clip_scroll_tree.get_relative_transform(
+                    surface.surface_spatial_node_index,
+                    parent_raster_spatial_node_index,
+                ).expect("BUG: unable to get relative transform");
+
+                // The scale of the local axes around the origin of the surface,
+                // which is where a perspective transform is evaluated.
+                let scale = if xf.m44 > 0.0 {
+                    (xf.m11 * xf.m11 + xf.m12 * xf.m12).sqrt()
+                        .max((xf.m21 * xf.m21 + xf.m22 * xf.m22).sqrt()) / xf.m44
+                } else {
+                    1.0
+                };
+                let scale = (scale / RASTER_SCALE_STEP).ceil().max(1.0) * RASTER_SCALE_STEP;
+
+                scale * parent_device_pixel_scale.0
+            }
+        };
+
+        // Don't allocate more device pixels than a surface of the maximum
+        // size would take at the device pixel ratio.
+        let bounds = surface.map_local_to_surface.bounds.size;
+        let max_scale = MAX_SURFACE_SIZE * frame_context.device_pixel_scale.0 /
+            (bounds.width * bounds.height).sqrt();
+
+        TypedScale::new(scale.min(max_scale))
+    }
+
     /// Called during initial picture traversal, before we know the
     /// bounding rect of children. It is possible to determine the
     /// surface / raster config now though.
@@ -2385,6 +2514,7 @@ impl PicturePrimitive {
         if let Some(composite_mode) = actual_composite_mode {
             // Retrieve the positioning node information for the parent surface.
             let parent_raster_spatial_node_index = state.current_surface().raster_spatial_node_index;
+            let parent_device_pixel_scale = state.current_surface().device_pixel_scale;
             let surface_spatial_node_index = self.spatial_node_index;
 
             let inflation_factor = match composite_mode {
@@ -2399,6 +2529,9 @@ impl PicturePrimitive {
                         0.0
                     }
                 }
+                PictureCompositeMode::SvgFilter => {
+                    BLUR_SAMPLE_SCALE * self.svg_filter_blur_radius()
+                }
                 _ => {
                     0.0
                 }
@@ -2424,6 +2557,7 @@ impl PicturePrimitive {
                     inflation_factor,
                     frame_context.screen_world_rect,
                     &frame_context.clip_scroll_tree,
+                    parent_device_pixel_scale,
                 )
             };
 
@@ -2437,9 +2571,19 @@ impl PicturePrimitive {
                     inflation_factor,
                     frame_context.screen_world_rect,
                     &frame_context.clip_scroll_tree,
+                    parent_device_pixel_scale,
                 );
             };
 
+            if surface.raster_spatial_node_index != parent_raster_spatial_node_index {
+                surface.device_pixel_scale = self.raster_device_pixel_scale(
+                    &surface,
+                    parent_raster_spatial_node_index,
+                    parent_device_pixel_scale,
+                    frame_context,
+                );
+            }
+
             self.raster_config = Some(RasterConfig {
                 composite_mode,
                 establishes_raster_root: surface.raster_spatial_node_index != parent_raster_spatial_node_index,
@@ -2520,7 +2664,8 @@ impl PicturePrimitive {
 
         // Inflate the local bounding rect if required by the filter effect.
         let inflation_size = match self.raster_config {
-            Some(RasterConfig { surface_index, composite_mode: PictureCompositeMode::Filter(FilterOp::Blur(_)), .. }) => {
+            Some(RasterConfig { surface_index, composite_mode: PictureCompositeMode::Filter(FilterOp::Blur(_)), .. }) |
+            Some(RasterConfig { surface_index, composite_mode: PictureCompositeMode::SvgFilter, .. }) => {
                 Some(state.surfaces[surface_index.0].inflation_factor)
             }
             Some(RasterConfig { composite_mode: PictureCompositeMode::Filter(FilterOp::DropShadow(_, blur_radius, _)), .. }) => {
@@ -2612,9 +2757,13 @@ impl PicturePrimitive {
             }
         };
 
-        let (raster_spatial_node_index, child_tasks) = {
+        let (raster_spatial_node_index, device_pixel_scale, child_tasks) = {
             let surface_info = &mut frame_state.surfaces[raster_config.surface_index.0];
-            (surface_info.raster_spatial_node_index, surface_info.take_render_tasks())
+            (
+                surface_info.raster_spatial_node_index,
+                surface_info.device_pixel_scale,
+                surface_info.take_render_tasks(),
+            )
         };
         let surfaces = &mut frame_state.surfaces;
 
@@ -2632,7 +2781,7 @@ impl PicturePrimitive {
             &map_pic_to_raster,
             &map_raster_to_world,
             clipped_prim_bounding_rect,
-            frame_context.device_pixel_scale,
+            device_pixel_scale,
         ) {
             Some(info) => info,
             None => return false,
@@ -2656,9 +2805,9 @@ impl PicturePrimitive {
                 return true;
             }
             PictureCompositeMode::Filter(FilterOp::Blur(blur_radius)) => {
-                let blur_std_deviation = blur_radius * frame_context.device_pixel_scale.0;
+                let blur_std_deviation = blur_radius * device_pixel_scale.0;
                 let inflation_factor = surfaces[raster_config.surface_index.0].inflation_factor;
-                let inflation_factor = (inflation_factor * frame_context.device_pixel_scale.0).ceil() as i32;
+                let inflation_factor = (inflation_factor * device_pixel_scale.0).ceil() as i32;
 
                 // The clipped field is the part of the picture that is visible
                 // on screen. The unclipped field is the screen-space rect of
@@ -2677,7 +2826,7 @@ impl PicturePrimitive {
                     &pic_rect,
                     &transform,
                     &device_rect,
-                    frame_context.device_pixel_scale,
+                    device_pixel_scale,
                     true,
                 );
 
@@ -2689,6 +2838,7 @@ impl PicturePrimitive {
                     child_tasks,
                     uv_rect_kind,
                     pic_context.raster_spatial_node_index,
+                    device_pixel_scale,
                 );
 
                 let picture_task_id = frame_state.render_tasks.add(picture_task);
@@ -2708,7 +2858,7 @@ impl PicturePrimitive {
                 PictureSurface::RenderTask(render_task_id)
             }
             PictureCompositeMode::Filter(FilterOp::DropShadow(offset, blur_radius, color)) => {
-                let blur_std_deviation = blur_radius * frame_context.device_pixel_scale.0;
+                let blur_std_deviation = blur_radius * device_pixel_scale.0;
                 let blur_range = (blur_std_deviation * BLUR_SAMPLE_SCALE).ceil() as i32;
 
                 // The clipped field is the part of the picture that is visible
@@ -2728,7 +2878,7 @@ impl PicturePrimitive {
                     &pic_rect,
                     &transform,
                     &device_rect,
-                    frame_context.device_pixel_scale,
+                    device_pixel_scale,
                     true,
                 );
 
@@ -2740,6 +2890,7 @@ impl PicturePrimitive {
                     child_tasks,
                     uv_rect_kind,
                     pic_context.raster_spatial_node_index,
+                    device_pixel_scale,
                 );
                 picture_task.mark_for_saving();
 
@@ -2793,7 +2944,54 @@ impl PicturePrimitive {
                     &pic_rect,
                     &transform,
                     &clipped,
-                    frame_context.device_pixel_scale,
+                    device_pixel_scale,
+                    true,
+                );
+
+                let picture_task = RenderTask::new_picture(
+                    RenderTaskLocation::Dynamic(None, clipped.size),
+                    unclipped.size,
+                    pic_index,
+                    clipped.origin,
+                    child_tasks,
+                    uv_rect_kind,
+                    pic_context.raster_spatial_node_index,
+                    device_pixel_scale,
+                );
+
+                let backdrop_task_id = frame_state.render_tasks.add(
+                    RenderTask::new_backdrop_capture(clipped)
+                );
+
+                self.secondary_render_task_id = Some(backdrop_task_id);
+                surfaces[surface_index.0].tasks.push(backdrop_task_id);
+
+                let render_task_id = frame_state.render_tasks.add(picture_task);
+                surfaces[surface_index.0].tasks.push(render_task_id);
+                PictureSurface::RenderTask(render_task_id)
+            }
+            PictureCompositeMode::BackdropFilter => {
+                // The filter chain is stored as a count, followed by a
+                // color matrix for each filter.
+                if let Some(mut request) = frame_state.gpu_cache.request(&mut self.extra_gpu_data_handle) {
+                    let matrices: Vec<[f32; 20]> = self.backdrop_filters
+                        .iter()
+                        .filter_map(backdrop_filter_color_matrix)
+                        .collect();
+
+                    request.push([matrices.len() as f32, 0.0, 0.0, 0.0]);
+                    for m in &matrices {
+                        for i in 0..5 {
+                            request.push([m[i*4], m[i*4+1], m[i*4+2], m[i*4+3]]);
+                        }
+                    }
+                }
+
+                let uv_rect_kind = calculate_uv_rect_kind(
+                    &pic_rect,
+                    &transform,
+                    &clipped,
+                    device_pixel_scale,
                     true,
                 );
 
@@ -2805,14 +3003,17 @@ impl PicturePrimitive {
                     child_tasks,
                     uv_rect_kind,
                     pic_context.raster_spatial_node_index,
+                    device_pixel_scale,
                 );
 
-                let readback_task_id = frame_state.render_tasks.add(
-                    RenderTask::new_readback(clipped)
+                // Only the part of the backdrop that ends up visible
+                // under the picture is read back.
+                let backdrop_task_id = frame_state.render_tasks.add(
+                    RenderTask::new_backdrop_capture(clipped)
                 );
 
-                self.secondary_render_task_id = Some(readback_task_id);
-                surfaces[surface_index.0].tasks.push(readback_task_id);
+                self.secondary_render_task_id = Some(backdrop_task_id);
+                surfaces[surface_index.0].tasks.push(backdrop_task_id);
 
                 let render_task_id = frame_state.render_tasks.add(picture_task);
                 surfaces[surface_index.0].tasks.push(render_task_id);
@@ -2831,7 +3032,7 @@ impl PicturePrimitive {
                     &pic_rect,
                     &transform,
                     &clipped,
-                    frame_context.device_pixel_scale,
+                    device_pixel_scale,
                     true,
                 );
 
@@ -2843,12 +3044,58 @@ impl PicturePrimitive {
                     child_tasks,
                     uv_rect_kind,
                     pic_context.raster_spatial_node_index,
+                    device_pixel_scale,
                 );
 
                 let render_task_id = frame_state.render_tasks.add(picture_task);
                 surfaces[surface_index.0].tasks.push(render_task_id);
                 PictureSurface::RenderTask(render_task_id)
             }
+            PictureCompositeMode::SvgFilter => {
+                let inflation_factor = surfaces[raster_config.surface_index.0].inflation_factor;
+                let inflation_factor = (inflation_factor * device_pixel_scale.0).ceil() as i32;
+
+                // As for blurs, draw enough of the picture around the visible
+                // area for the filter graph to produce correct results there.
+                let device_rect = clipped
+                    .inflate(inflation_factor, inflation_factor)
+                    .intersection(&unclipped.to_i32())
+                    .unwrap();
+
+                let uv_rect_kind = calculate_uv_rect_kind(
+                    &pic_rect,
+                    &transform,
+                    &device_rect,
+                    device_pixel_scale,
+                    true,
+                );
+
+                let picture_task = RenderTask::new_picture(
+                    RenderTaskLocation::Dynamic(None, device_rect.size),
+                    unclipped.size,
+                    pic_index,
+                    device_rect.origin,
+                    child_tasks,
+                    uv_rect_kind,
+                    pic_context.raster_spatial_node_index,
+                    device_pixel_scale,
+                );
+
+                let picture_task_id = frame_state.render_tasks.add(picture_task);
+
+                let render_task_id = RenderTask::new_svg_filter(
+                    &self.filter_primitives,
+                    picture_task_id,
+                    device_rect.size,
+                    uv_rect_kind,
+                    device_pixel_scale,
+                    frame_state.render_tasks,
+                );
+
+                surfaces[surface_index.0].tasks.push(render_task_id);
+
+                PictureSurface::RenderTask(render_task_id)
+            }
             PictureCompositeMode::Blit => {
                 // The SplitComposite shader used for 3d contexts doesn't snap
                 // to pixels, so we shouldn't snap our uv coordinates either.
@@ -2861,7 +3108,7 @@ impl PicturePrimitive {
                     &pic_rect,
                     &transform,
                     &clipped,
-                    frame_context.device_pixel_scale,
+                    device_pixel_scale,
                     supports_snapping,
                 );
 
@@ -2873,6 +3120,7 @@ impl PicturePrimitive {
                     child_tasks,
                     uv_rect_kind,
                     pic_context.raster_spatial_node_index,
+                    device_pixel_scale,
                 );
 
                 let render_task_id = frame_state.render_tasks.add(picture_task);
@@ -2973,6 +3221,89 @@ fn calculate_uv_rect_kind(
     }
 }
 
+/// Returns whether a filter can be applied to the backdrop of a picture,
+/// which needs it to be expressible as a color matrix. Blur and drop-shadow
+/// aren't supported: the backdrop is only copied when the picture is
+/// composited, after the earlier passes that could blur it have run.
+pub fn is_supported_backdrop_filter(filter: &FilterOp) -> bool {
+    backdrop_filter_color_matrix(filter).is_some()
+}
+
+/// Returns the color matrix (in the column-major layout of
+/// `FilterOp::ColorMatrix`) that applies a backdrop filter, if it has one.
+fn backdrop_filter_color_matrix(filter: &FilterOp) -> Option<[f32; 20]> {
+    fn rgb_matrix(m: [[f32; 3]; 3], offset: f32) -> [f32; 20] {
+        [
+            m[0][0], m[0][1], m[0][2], 0.0,
+            m[1][0], m[1][1], m[1][2], 0.0,
+            m[2][0], m[2][1], m[2][2], 0.0,
+            0.0, 0.0, 0.0, 1.0,
+            offset, offset, offset, 0.0,
+        ]
+    }
+
+    fn scale(amount: f32, offset: f32) -> [f32; 20] {
+        rgb_matrix([[amount, 0.0, 0.0], [0.0, amount, 0.0], [0.0, 0.0, amount]], offset)
+    }
+
+    // These match the matrices used by the brush_blend shader.
+    let lum_r = 0.2126;
+    let lum_g = 0.7152;
+    let lum_b = 0.0722;
+
+    let matrix = match *filter {
+        FilterOp::Identity => scale(1.0, 0.0),
+        FilterOp::Brightness(amount) => scale(amount, 0.0),
+        FilterOp::Contrast(amount) => scale(amount, 0.5 - 0.5 * amount),
+        FilterOp::Invert(amount) => scale(1.0 - 2.0 * amount, amount),
+        FilterOp::Grayscale(amount) => {
+            let inv = 1.0 - amount;
+            rgb_matrix([
+                [lum_r + (1.0 - lum_r) * inv, lum_r - lum_r * inv, lum_r - lum_r * inv],
+                [lum_g - lum_g * inv, lum_g + (1.0 - lum_g) * inv, lum_g - lum_g * inv],
+                [lum_b - lum_b * inv, lum_b - lum_b * inv, lum_b + (1.0 - lum_b) * inv],
+            ], 0.0)
+        }
+        FilterOp::HueRotate(angle) => {
+            let (s, c) = angle.to_radians().sin_cos();
+            rgb_matrix([
+                [lum_r + (1.0 - lum_r) * c - lum_r * s, lum_r - lum_r * c + 0.143 * s, lum_r - lum_r * c - (1.0 - lum_r) * s],
+                [lum_g - lum_g * c - lum_g * s, lum_g + (1.0 - lum_g) * c + 0.140 * s, lum_g - lum_g * c + lum_g * s],
+                [lum_b - lum_b * c + (1.0 - lum_b) * s, lum_b - lum_b * c - 0.283 * s, lum_b + (1.0 - lum_b) * c + lum_b * s],
+            ], 0.0)
+        }
+        FilterOp::Saturate(amount) => {
+            let inv = 1.0 - amount;
+            rgb_matrix([
+                [inv * lum_r + amount, inv * lum_r, inv * lum_r],
+                [inv * lum_g, inv * lum_g + amount, inv * lum_g],
+                [inv * lum_b, inv * lum_b, inv * lum_b + amount],
+            ], 0.0)
+        }
+        FilterOp::Sepia(amount) => {
+            let inv = 1.0 - amount;
+            rgb_matrix([
+                [0.393 + 0.607 * inv, 0.349 - 0.349 * inv, 0.272 - 0.272 * inv],
+                [0.769 - 0.769 * inv, 0.686 + 0.314 * inv, 0.534 - 0.534 * inv],
+                [0.189 - 0.189 * inv, 0.168 - 0.168 * inv, 0.131 + 0.869 * inv],
+            ], 0.0)
+        }
+        FilterOp::Opacity(_, amount) => {
+            let mut matrix = scale(1.0, 0.0);
+            matrix[15] = amount;
+            matrix
+        }
+        FilterOp::ColorMatrix(matrix) => matrix,
+        // These can't be expressed as a color matrix.
+        FilterOp::Blur(..) |
+        FilterOp::DropShadow(..) |
+        FilterOp::SrgbToLinear |
+        FilterOp::LinearToSrgb => return None,
+    };
+
+    Some(matrix)
+}
+
 fn create_raster_mappers(
     surface_spatial_node_index: SpatialNodeIndex,
     raster_spatial_node_index: SpatialNodeIndex,
@@ -486,6 +486,291 @@ impl IsVisible for RadialGradient {
 
 ////////////////////////////////////////////////////////////////////////////////
 
+/// Hashable conic gradient parameters, for use during prim interning.
+#[cfg_attr(feature = "capture", derive(Serialize))]
+#[cfg_attr(feature = "replay", derive(Deserialize))]
+#[derive(Debug, Clone, MallocSizeOf, PartialEq)]
+pub struct ConicGradientParams {
+    pub angle: f32,
+    pub start_offset: f32,
+    pub end_offset: f32,
+}
+
+impl Eq for ConicGradientParams {}
+
+impl hash::Hash for ConicGradientParams {
+    fn hash<H: hash::Hasher>(&self, state: &mut H) {
+        self.angle.to_bits().hash(state);
+        self.start_offset.to_bits().hash(state);
+        self.end_offset.to_bits().hash(state);
+    }
+}
+
+/// Identifying key for a conic gradient.
+#[cfg_attr(feature = "capture", derive(Serialize))]
+#[cfg_attr(feature = "replay", derive(Deserialize))]
+#[derive(Debug, Clone, Eq, PartialEq, Hash, MallocSizeOf)]
+pub struct ConicGradientKey {
+    pub common: PrimKeyCommonData,
+    pub extend_mode: ExtendMode,
+    pub center: PointKey,
+    pub params: ConicGradientParams,
+    pub stretch_size: SizeKey,
+    pub stops: Vec<GradientStopKey>,
+    pub tile_spacing: SizeKey,
+}
+
+impl ConicGradientKey {
+    pub fn new(
+        is_backface_visible: bool,
+        prim_size: LayoutSize,
+        conic_grad: ConicGradient,
+    ) -> Self {
+        ConicGradientKey {
+            common: PrimKeyCommonData {
+                is_backface_visible,
+                prim_size: prim_size.into(),
+            },
+            extend_mode: conic_grad.extend_mode,
+            center: conic_grad.center,
+            params: conic_grad.params,
+            stretch_size: conic_grad.stretch_size,
+            stops: conic_grad.stops,
+            tile_spacing: conic_grad.tile_spacing,
+        }
+    }
+}
+
+impl InternDebug for ConicGradientKey {}
+
+impl AsInstanceKind<ConicGradientDataHandle> for ConicGradientKey {
+    /// Construct a primitive instance that matches the type
+    /// of primitive key.
+    fn as_instance_kind(
+        &self,
+        data_handle: ConicGradientDataHandle,
+        _prim_store: &mut PrimitiveStore,
+        _reference_frame_relative_offset: LayoutVector2D,
+    ) -> PrimitiveInstanceKind {
+        PrimitiveInstanceKind::ConicGradient {
+            data_handle,
+            visible_tiles_range: GradientTileRange::empty(),
+        }
+    }
+}
+
+#[cfg_attr(feature = "capture", derive(Serialize))]
+#[cfg_attr(feature = "replay", derive(Deserialize))]
+#[derive(MallocSizeOf)]
+pub struct ConicGradientTemplate {
+    pub common: PrimTemplateCommonData,
+    pub extend_mode: ExtendMode,
+    pub center: LayoutPoint,
+    pub params: ConicGradientParams,
+    pub stretch_size: LayoutSize,
+    pub tile_spacing: LayoutSize,
+    pub stops: Vec<GradientStop>,
+    pub stops_handle: GpuCacheHandle,
+}
+
+impl Deref for ConicGradientTemplate {
+    type Target = PrimTemplateCommonData;
+    fn deref(&self) -> &Self::Target {
+        &self.common
+    }
+}
+
+impl DerefMut for ConicGradientTemplate {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.common
+    }
+}
+
+impl From<ConicGradientKey> for ConicGradientTemplate {
+    fn from(item: ConicGradientKey) -> Self {
+        let common = PrimTemplateCommonData::with_key_common(item.common);
+
+        let stops = item.stops.iter().map(|stop| {
+            GradientStop {
+                offset: stop.offset,
+                color: stop.color.into(),
+            }
+        }).collect();
+
+        ConicGradientTemplate {
+            common,
+            center: item.center.into(),
+            extend_mode: item.extend_mode,
+            params: item.params,
+            stretch_size: item.stretch_size.into(),
+            tile_spacing: item.tile_spacing.into(),
+            stops,
+            stops_handle: GpuCacheHandle::new(),
+        }
+    }
+}
+
+impl ConicGradientTemplate {
+    /// Update the GPU cache for a given primitive template. This may be called multiple
+    /// times per frame, by each primitive reference that refers to this interned
+    /// template. The initial request call to the GPU cache ensures that work is only
+    /// done if the cache entry is invalid (due to first use or eviction).
+    pub fn update(
+        &mut self,
+        frame_state: &mut FrameBuildingState,
+    ) {
+        if let Some(mut request) =
+            frame_state.gpu_cache.request(&mut self.common.gpu_cache_handle) {
+            // write_prim_gpu_blocks
+            request.push(self.gpu_blocks()[0]);
+            request.push(self.gpu_blocks()[1]);
+        }
+
+        if let Some(mut request) = frame_state.gpu_cache.request(&mut self.stops_handle) {
+            GradientGpuBlockBuilder::build(
+                false,
+                &mut request,
+                &self.stops,
+            );
+        }
+
+        self.opacity = PrimitiveOpacity::translucent();
+    }
+
+    /// The primitive data the brush_conic_gradient shader reads, for the
+    /// whole primitive or for each of its tiles.
+    pub fn gpu_blocks(&self) -> [[f32; 4]; 2] {
+        [
+            [
+                self.center.x,
+                self.center.y,
+                self.params.angle,
+                self.params.start_offset,
+            ],
+            [
+                self.params.end_offset,
+                pack_as_float(self.extend_mode as u32),
+                self.stretch_size.width,
+                self.stretch_size.height,
+            ],
+        ]
+    }
+}
+
+pub type ConicGradientDataHandle = intern_types::conic_grad::Handle;
+
+pub struct ConicGradient {
+    pub extend_mode: ExtendMode,
+    pub center: PointKey,
+    pub params: ConicGradientParams,
+    pub stretch_size: SizeKey,
+    pub stops: Vec<GradientStopKey>,
+    pub tile_spacing: SizeKey,
+}
+
+impl Internable for ConicGradient {
+    type Marker = intern_types::conic_grad::Marker;
+    type Source = ConicGradientKey;
+    type StoreData = ConicGradientTemplate;
+    type InternData = PrimitiveSceneData;
+
+    /// Build a new key from self with `info`.
+    fn build_key(
+        self,
+        info: &LayoutPrimitiveInfo,
+    ) -> ConicGradientKey {
+        ConicGradientKey::new(
+            info.is_backface_visible,
+            info.rect.size,
+            self,
+        )
+    }
+}
+
+impl IsVisible for ConicGradient {
+    fn is_visible(&self) -> bool {
+        true
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////
+
+/// The maximum number of solid bands a gradient made only of hard stops is
+/// split into, before falling back to drawing it with the gradient shader.
+pub const MAX_HARD_STOP_BANDS: usize = 64;
+
+/// If every color change in a gradient happens at a hard stop (two stops at
+/// the same offset), return the solid bands that cover the range [t0, t1] of
+/// the gradient line, as (start, end, color). A repeating gradient repeats
+/// the bands of its [0, 1] period, while a clamped one extends its first and
+/// last colors. Returns None for gradients with any smooth transition, or
+/// that would need more than MAX_HARD_STOP_BANDS bands.
+pub fn hard_stop_bands(
+    stops: &[GradientStop],
+    extend_mode: ExtendMode,
+    t0: f32,
+    t1: f32,
+) -> Option<Vec<(f32, f32, ColorF)>> {
+    if stops.len() < 2 || !(t1 - t0 <= MAX_HARD_STOP_BANDS as f32) {
+        return None;
+    }
+
+    let mut period = Vec::new();
+    for pair in stops.windows(2) {
+        if pair[0].offset == pair[1].offset {
+            continue;
+        }
+        if pair[0].color != pair[1].color {
+            return None;
+        }
+        period.push((pair[0].offset, pair[1].offset, pair[0].color));
+    }
+
+    if period.is_empty() {
+        return None;
+    }
+
+    let mut bands = Vec::new();
+    {
+        let mut push_band = |start: f32, end: f32, color: ColorF| {
+            let (start, end) = (start.max(t0), end.min(t1));
+            if start < end {
+                bands.push((start, end, color));
+            }
+        };
+
+        match extend_mode {
+            ExtendMode::Clamp => {
+                push_band(t0, 0.0, stops[0].color);
+                for &(start, end, color) in &period {
+                    push_band(start, end, color);
+                }
+                push_band(1.0, t1, stops[stops.len() - 1].color);
+            }
+            ExtendMode::Repeat => {
+                let first = t0.floor() as i32;
+                let last = t1.ceil() as i32;
+                if (last - first) as usize * period.len() > MAX_HARD_STOP_BANDS {
+                    return None;
+                }
+                for i in first .. last {
+                    for &(start, end, color) in &period {
+                        push_band(start + i as f32, end + i as f32, color);
+                    }
+                }
+            }
+        }
+    }
+
+    if bands.len() > MAX_HARD_STOP_BANDS {
+        return None;
+    }
+
+    Some(bands)
+}
+
+////////////////////////////////////////////////////////////////////////////////
+
 // The gradient entry index for the first color stop
 pub const GRADIENT_DATA_FIRST_STOP: usize = 0;
 // The gradient entry index for the last color stop
@@ -712,3 +997,47 @@ fn test_struct_sizes() {
     assert_eq!(mem::size_of::<RadialGradientTemplate>(), 120, "RadialGradientTemplate size changed");
     assert_eq!(mem::size_of::<RadialGradientKey>(), 88, "RadialGradientKey size changed");
 }
+
+#[cfg(test)]
+fn hard_stops(colors: &[(f32, ColorF)]) -> Vec<GradientStop> {
+    // Each color covers the range up to the next offset.
+    let mut stops = Vec::new();
+    for (i, &(offset, color)) in colors.iter().enumerate() {
+        let end = colors.get(i + 1).map_or(1.0, |&(next, _)| next);
+        stops.push(GradientStop { offset, color });
+        stops.push(GradientStop { offset: end, color });
+    }
+    stops
+}
+
+#[test]
+fn test_hard_stop_bands() {
+    let red = ColorF::new(1.0, 0.0, 0.0, 1.0);
+    let green = ColorF::new(0.0, 1.0, 0.0, 1.0);
+    let stops = hard_stops(&[(0.0, red), (0.5, green)]);
+
+    // Clamped gradients extend the first and last colors.
+    assert_eq!(
+        hard_stop_bands(&stops, ExtendMode::Clamp, -0.5, 1.5),
+        Some(vec![(-0.5, 0.0, red), (0.0, 0.5, red), (0.5, 1.0, green), (1.0, 1.5, green)]),
+    );
+
+    // Repeating gradients repeat the period before the gradient start too.
+    assert_eq!(
+        hard_stop_bands(&stops, ExtendMode::Repeat, -0.75, 0.5),
+        Some(vec![(-0.75, -0.5, red), (-0.5, 0.0, green), (0.0, 0.5, red)]),
+    );
+
+    // Smooth transitions need the gradient shader.
+    let smooth = [
+        GradientStop { offset: 0.0, color: red },
+        GradientStop { offset: 1.0, color: green },
+    ];
+    assert_eq!(hard_stop_bands(&smooth, ExtendMode::Clamp, 0.0, 1.0), None);
+
+    // The number of bands is capped.
+    let bands = hard_stop_bands(&stops, ExtendMode::Repeat, 0.0, 32.0);
+    assert_eq!(bands.map(|bands| bands.len()), Some(MAX_HARD_STOP_BANDS));
+    assert_eq!(hard_stop_bands(&stops, ExtendMode::Repeat, 0.0, 32.5), None);
+    assert_eq!(hard_stop_bands(&stops, ExtendMode::Clamp, 0.0, 65.0), None);
+}
@@ -3,9 +3,10 @@
  * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
 
 use api::{
-    AlphaType, ColorDepth, ColorF, ColorU, DeviceIntRect, DeviceIntSideOffsets,
+    AlphaType, ColorDepth, ColorF, ColorRange, ColorU, DeviceIntRect, DeviceIntSideOffsets,
     DeviceIntSize, ImageRendering, LayoutRect, LayoutSize, LayoutPrimitiveInfo,
-    PremultipliedColorF, Shadow, TileOffset, YuvColorSpace, YuvFormat, LayoutVector2D,
+    PremultipliedColorF, PropertyBinding, PropertyBindingId, Shadow, TileOffset,
+    YuvColorSpace, YuvFormat, LayoutVector2D,
 };
 use api::ImageKey as ApiImageKey;
 use display_list_flattener::{AsInstanceKind, CreateShadow, IsVisible};
@@ -24,6 +25,8 @@ use render_task::{
     RenderTaskCacheKeyKind
 };
 use resource_cache::ImageRequest;
+use scene::SceneProperties;
+use std::hash;
 use util::pack_as_float;
 
 #[derive(Debug)]
@@ -70,7 +73,7 @@ pub struct ImageInstance {
 #[cfg_attr(feature = "replay", derive(Deserialize))]
 #[derive(Debug, Clone, Eq, PartialEq, MallocSizeOf, Hash)]
 pub struct Image {
-    pub key: ApiImageKey,
+    pub key: PropertyBinding<ApiImageKey>,
     pub stretch_size: SizeKey,
     pub tile_spacing: SizeKey,
     pub color: ColorU,
@@ -143,7 +146,9 @@ pub enum ImageSource {
 #[cfg_attr(feature = "replay", derive(Deserialize))]
 #[derive(MallocSizeOf)]
 pub struct ImageData {
+    /// The image key for the current frame.
     pub key: ApiImageKey,
+    pub key_binding: PropertyBinding<ApiImageKey>,
     pub stretch_size: LayoutSize,
     pub tile_spacing: LayoutSize,
     pub color: ColorF,
@@ -155,8 +160,14 @@ pub struct ImageData {
 
 impl From<Image> for ImageData {
     fn from(image: Image) -> Self {
+        let key = match image.key {
+            PropertyBinding::Value(key) |
+            PropertyBinding::Binding(_, key) => key,
+        };
+
         ImageData {
-            key: image.key,
+            key,
+            key_binding: image.key,
             color: image.color.into(),
             stretch_size: image.stretch_size.into(),
             tile_spacing: image.tile_spacing.into(),
@@ -169,6 +180,34 @@ impl From<Image> for ImageData {
 }
 
 impl ImageData {
+    /// Returns the property binding of an animated image key, if any.
+    pub fn key_binding(&self) -> Option<PropertyBindingId> {
+        match self.key_binding {
+            PropertyBinding::Binding(key, _) => Some(key.id),
+            PropertyBinding::Value(..) => None,
+        }
+    }
+
+    /// Returns true if the image key depends on the current scene properties.
+    pub fn is_animated(&self) -> bool {
+        self.key_binding().is_some()
+    }
+
+    /// Resolve the image key of an animated image for the current frame.
+    /// This must run before the primitives are processed.
+    pub fn update_key_binding(
+        &mut self,
+        scene_properties: &SceneProperties,
+    ) {
+        let key = scene_properties.resolve_image_key(&self.key_binding);
+        if key != self.key {
+            self.key = key;
+            // Whether the image needs to be pre-rendered depends on the
+            // properties of the new image.
+            self.source = ImageSource::Default;
+        }
+    }
+
     /// Update the GPU cache for a given primitive template. This may be called multiple
     /// times per frame, by each primitive reference that refers to this interned
     /// template. The initial request call to the GPU cache ensures that work is only
@@ -386,6 +425,7 @@ pub struct YuvImage {
     pub yuv_key: [ApiImageKey; 3],
     pub format: YuvFormat,
     pub color_space: YuvColorSpace,
+    pub color_range: ColorRange,
     pub image_rendering: ImageRendering,
 }
 
@@ -434,6 +474,7 @@ pub struct YuvImageData {
     pub yuv_key: [ApiImageKey; 3],
     pub format: YuvFormat,
     pub color_space: YuvColorSpace,
+    pub color_range: ColorRange,
     pub image_rendering: ImageRendering,
 }
 
@@ -444,6 +485,7 @@ impl From<YuvImage> for YuvImageData {
             yuv_key: image.yuv_key,
             format: image.format,
             color_space: image.color_space,
+            color_range: image.color_range,
             image_rendering: image.image_rendering,
         }
     }
@@ -480,15 +522,59 @@ impl YuvImageData {
     }
 
     pub fn write_prim_gpu_blocks(&self, request: &mut GpuDataRequest) {
+        // The samples of P010 images are in the high bits of 16 bit integers,
+        // so they never need rescaling.
+        let rescaling_factor = match self.format {
+            YuvFormat::P010 | YuvFormat::P016 => 1.0,
+            _ => self.color_depth.rescaling_factor(),
+        };
         request.push([
-            self.color_depth.rescaling_factor(),
-            pack_as_float(self.color_space as u32),
+            rescaling_factor,
             pack_as_float(self.format as u32),
+            0.0,
             0.0
         ]);
+
+        // The columns of the matrix, each followed by the offset subtracted
+        // from the matching channel before applying it.
+        let (matrix, offsets) = yuv_to_rgb_matrix(self.color_space, self.color_range);
+        for (column, offset) in matrix.iter().zip(offsets.iter()) {
+            request.push([column[0], column[1], column[2], *offset]);
+        }
     }
 }
 
+/// Returns the columns of the matrix converting normalized YUV samples to
+/// RGB, and the offsets to subtract from the samples beforehand.
+fn yuv_to_rgb_matrix(
+    color_space: YuvColorSpace,
+    color_range: ColorRange,
+) -> ([[f32; 3]; 3], [f32; 3]) {
+    // The luma weights of red and blue.
+    let (kr, kb) = match color_space {
+        YuvColorSpace::Rec601 => (0.299, 0.114),
+        YuvColorSpace::Rec709 => (0.2126, 0.0722),
+        YuvColorSpace::Rec2020 => (0.2627, 0.0593),
+    };
+    let kg = 1.0 - kr - kb;
+
+    // Limited range samples only cover 219 (luma) and 224 (chroma) of the
+    // 255 steps of 8 bit values.
+    let (y_scale, c_scale, y_offset) = match color_range {
+        ColorRange::Limited => (255.0 / 219.0, 255.0 / 224.0, 16.0 / 255.0),
+        ColorRange::Full => (1.0, 1.0, 0.0),
+    };
+    let c_offset = 128.0 / 255.0;
+
+    let matrix = [
+        [y_scale, y_scale, y_scale],
+        [0.0, -2.0 * kb * (1.0 - kb) / kg * c_scale, 2.0 * (1.0 - kb) * c_scale],
+        [2.0 * (1.0 - kr) * c_scale, -2.0 * kr * (1.0 - kr) / kg * c_scale, 0.0],
+    ];
+
+    (matrix, [y_offset, c_offset, c_offset])
+}
+
 pub type YuvImageTemplate = PrimTemplate<YuvImageData>;
 
 impl From<YuvImageKey> for YuvImageTemplate {
@@ -529,6 +615,199 @@ impl IsVisible for YuvImage {
     }
 }
 
+////////////////////////////////////////////////////////////////////////////////
+
+#[cfg_attr(feature = "capture", derive(Serialize))]
+#[cfg_attr(feature = "replay", derive(Deserialize))]
+#[derive(Debug, Clone, MallocSizeOf, PartialEq)]
+pub struct CrossFade {
+    pub from_key: ApiImageKey,
+    pub to_key: ApiImageKey,
+    pub progress: PropertyBinding<f32>,
+    pub image_rendering: ImageRendering,
+}
+
+impl Eq for CrossFade {}
+
+impl hash::Hash for CrossFade {
+    fn hash<H: hash::Hasher>(&self, state: &mut H) {
+        self.from_key.hash(state);
+        self.to_key.hash(state);
+        match self.progress {
+            PropertyBinding::Value(value) => {
+                0u8.hash(state);
+                value.to_bits().hash(state);
+            }
+            PropertyBinding::Binding(key, value) => {
+                1u8.hash(state);
+                key.id.hash(state);
+                value.to_bits().hash(state);
+            }
+        }
+        self.image_rendering.hash(state);
+    }
+}
+
+pub type CrossFadeKey = PrimKey<CrossFade>;
+
+impl CrossFadeKey {
+    pub fn new(
+        is_backface_visible: bool,
+        prim_size: LayoutSize,
+        cross_fade: CrossFade,
+    ) -> Self {
+        CrossFadeKey {
+            common: PrimKeyCommonData {
+                is_backface_visible,
+                prim_size: prim_size.into(),
+            },
+            kind: cross_fade,
+        }
+    }
+}
+
+impl InternDebug for CrossFadeKey {}
+
+impl AsInstanceKind<CrossFadeDataHandle> for CrossFadeKey {
+    /// Construct a primitive instance that matches the type
+    /// of primitive key.
+    fn as_instance_kind(
+        &self,
+        data_handle: CrossFadeDataHandle,
+        _prim_store: &mut PrimitiveStore,
+        _reference_frame_relative_offset: LayoutVector2D,
+    ) -> PrimitiveInstanceKind {
+        PrimitiveInstanceKind::CrossFade {
+            data_handle,
+            segment_instance_index: SegmentInstanceIndex::INVALID
+        }
+    }
+}
+
+#[cfg_attr(feature = "capture", derive(Serialize))]
+#[cfg_attr(feature = "replay", derive(Deserialize))]
+#[derive(MallocSizeOf)]
+pub struct CrossFadeData {
+    pub from_key: ApiImageKey,
+    pub to_key: ApiImageKey,
+    pub progress: PropertyBinding<f32>,
+    pub image_rendering: ImageRendering,
+}
+
+impl From<CrossFade> for CrossFadeData {
+    fn from(cross_fade: CrossFade) -> Self {
+        CrossFadeData {
+            from_key: cross_fade.from_key,
+            to_key: cross_fade.to_key,
+            progress: cross_fade.progress,
+            image_rendering: cross_fade.image_rendering,
+        }
+    }
+}
+
+impl CrossFadeData {
+    /// Returns the property binding of an animated progress, if any.
+    pub fn progress_binding(&self) -> Option<PropertyBindingId> {
+        match self.progress {
+            PropertyBinding::Binding(key, _) => Some(key.id),
+            PropertyBinding::Value(..) => None,
+        }
+    }
+
+    /// Returns true if the GPU data of this template depends on the
+    /// current scene properties.
+    pub fn is_animated(&self) -> bool {
+        self.progress_binding().is_some()
+    }
+
+    /// Update the GPU cache for a given primitive template. This may be called multiple
+    /// times per frame, by each primitive reference that refers to this interned
+    /// template. The initial request call to the GPU cache ensures that work is only
+    /// done if the cache entry is invalid (due to first use or eviction).
+    pub fn update(
+        &mut self,
+        common: &mut PrimTemplateCommonData,
+        frame_state: &mut FrameBuildingState,
+        scene_properties: &SceneProperties,
+    ) {
+        // The progress of an animated cross-fade may have changed since the
+        // GPU cache entry was written.
+        if self.is_animated() {
+            frame_state.gpu_cache.invalidate(&common.gpu_cache_handle);
+        }
+
+        if let Some(mut request) = frame_state.gpu_cache.request(&mut common.gpu_cache_handle) {
+            self.write_prim_gpu_blocks(&mut request, scene_properties);
+        };
+
+        for &key in &[self.from_key, self.to_key] {
+            frame_state.resource_cache.request_image(
+                ImageRequest {
+                    key,
+                    rendering: self.image_rendering,
+                    tile: None,
+                },
+                frame_state.gpu_cache,
+            );
+        }
+
+        common.opacity = PrimitiveOpacity::translucent();
+    }
+
+    pub fn write_prim_gpu_blocks(
+        &self,
+        request: &mut GpuDataRequest,
+        scene_properties: &SceneProperties,
+    ) {
+        request.push([
+            scene_properties.resolve_float(&self.progress),
+            0.0,
+            0.0,
+            0.0,
+        ]);
+    }
+}
+
+pub type CrossFadeTemplate = PrimTemplate<CrossFadeData>;
+
+impl From<CrossFadeKey> for CrossFadeTemplate {
+    fn from(cross_fade: CrossFadeKey) -> Self {
+        let common = PrimTemplateCommonData::with_key_common(cross_fade.common);
+
+        CrossFadeTemplate {
+            common,
+            kind: cross_fade.kind.into(),
+        }
+    }
+}
+
+pub use intern_types::cross_fade::Handle as CrossFadeDataHandle;
+
+impl Internable for CrossFade {
+    type Marker = intern_types::cross_fade::Marker;
+    type Source = CrossFadeKey;
+    type StoreData = CrossFadeTemplate;
+    type InternData = PrimitiveSceneData;
+
+    /// Build a new key from self with `info`.
+    fn build_key(
+        self,
+        info: &LayoutPrimitiveInfo,
+    ) -> CrossFadeKey {
+        CrossFadeKey::new(
+            info.is_backface_visible,
+            info.rect.size,
+            self
+        )
+    }
+}
+
+impl IsVisible for CrossFade {
+    fn is_visible(&self) -> bool {
+        true
+    }
+}
+
 #[test]
 #[cfg(target_pointer_width = "64")]
 fn test_struct_sizes() {
@@ -539,10 +818,59 @@ fn test_struct_sizes() {
     //     test expectations and move on.
     // (b) You made a structure larger. This is not necessarily a problem, but should only
     //     be done with care, and after checking if talos performance regresses badly.
-    assert_eq!(mem::size_of::<Image>(), 56, "Image size changed");
-    assert_eq!(mem::size_of::<ImageTemplate>(), 108, "ImageTemplate size changed");
-    assert_eq!(mem::size_of::<ImageKey>(), 68, "ImageKey size changed");
+    assert_eq!(mem::size_of::<Image>(), 68, "Image size changed");
+    assert_eq!(mem::size_of::<ImageTemplate>(), 128, "ImageTemplate size changed");
+    assert_eq!(mem::size_of::<ImageKey>(), 80, "ImageKey size changed");
     assert_eq!(mem::size_of::<YuvImage>(), 36, "YuvImage size changed");
     assert_eq!(mem::size_of::<YuvImageTemplate>(), 56, "YuvImageTemplate size changed");
     assert_eq!(mem::size_of::<YuvImageKey>(), 48, "YuvImageKey size changed");
 }
+
+#[test]
+fn test_yuv_to_rgb_matrix() {
+    fn assert_matrix(
+        color_space: YuvColorSpace,
+        color_range: ColorRange,
+        expected: [[f32; 3]; 3],
+        expected_offsets: [f32; 3],
+    ) {
+        let (matrix, offsets) = yuv_to_rgb_matrix(color_space, color_range);
+        for (column, expected_column) in matrix.iter().zip(expected.iter()) {
+            for (value, expected_value) in column.iter().zip(expected_column.iter()) {
+                assert!(
+                    (value - expected_value).abs() < 1e-4,
+                    "{:?} {:?}: {:?} != {:?}", color_space, color_range, matrix, expected,
+                );
+            }
+        }
+        for (value, expected_value) in offsets.iter().zip(expected_offsets.iter()) {
+            assert!((value - expected_value).abs() < 1e-6);
+        }
+    }
+
+    // The Y, Cb and Cr columns of the conversions given by the standards.
+    assert_matrix(
+        YuvColorSpace::Rec601,
+        ColorRange::Full,
+        [[1.0, 1.0, 1.0], [0.0, -0.344136, 1.772], [1.402, -0.714136, 0.0]],
+        [0.0, 128.0 / 255.0, 128.0 / 255.0],
+    );
+    assert_matrix(
+        YuvColorSpace::Rec601,
+        ColorRange::Limited,
+        [[1.164383, 1.164383, 1.164383], [0.0, -0.391762, 2.017232], [1.596027, -0.812968, 0.0]],
+        [16.0 / 255.0, 128.0 / 255.0, 128.0 / 255.0],
+    );
+    assert_matrix(
+        YuvColorSpace::Rec709,
+        ColorRange::Full,
+        [[1.0, 1.0, 1.0], [0.0, -0.187324, 1.8556], [1.5748, -0.468124, 0.0]],
+        [0.0, 128.0 / 255.0, 128.0 / 255.0],
+    );
+    assert_matrix(
+        YuvColorSpace::Rec709,
+        ColorRange::Limited,
+        [[1.164383, 1.164383, 1.164383], [0.0, -0.213249, 2.112402], [1.792741, -0.532909, 0.0]],
+        [16.0 / 255.0, 128.0 / 255.0, 128.0 / 255.0],
+    );
+}
@@ -6,7 +6,7 @@ use api::{BorderRadius, ClipMode, ColorF, PictureRect, ColorU, LayoutVector2D};
 use api::{DeviceIntRect, DevicePixelScale, DeviceRect, WorldVector2D};
 use api::{FilterOp, ImageRendering, TileOffset, RepeatMode, WorldPoint, WorldSize};
 use api::{LayoutPoint, LayoutRect, LayoutSideOffsets, LayoutSize};
-use api::{PremultipliedColorF, PropertyBinding, Shadow};
+use api::{PremultipliedColorF, PropertyBinding, PropertyBindingId, Shadow};
 use api::{WorldPixel, BoxShadowClipMode, WorldRect, LayoutToWorldScale};
 use api::{PicturePixel, RasterPixel, LineStyle, LineOrientation, AuHelpers};
 use api::{LayoutPrimitiveInfo};
@@ -17,6 +17,8 @@ use clip::{ClipStore};
 use clip_scroll_tree::{ClipScrollTree, SpatialNodeIndex, ROOT_SPATIAL_NODE_INDEX};
 use clip::{ClipDataStore, ClipNodeFlags, ClipChainId, ClipChainInstance, ClipItem};
 use debug_colors;
+#[cfg(feature = "debugger")]
+use debug_server::MemoryBreakdownGroup;
 use debug_render::DebugItem;
 use display_list_flattener::{AsInstanceKind, CreateShadow, IsVisible};
 use euclid::{SideOffsets2D, TypedTransform3D, TypedRect, TypedScale, TypedSize2D};
@@ -31,8 +33,9 @@ use malloc_size_of::MallocSizeOf;
 use picture::{PictureCompositeMode, PicturePrimitive, PictureUpdateState};
 use picture::{ClusterIndex, PrimitiveList, SurfaceIndex, RetainedTiles, RasterConfig};
 use prim_store::borders::{ImageBorderDataHandle, NormalBorderDataHandle};
-use prim_store::gradient::{LinearGradientDataHandle, RadialGradientDataHandle};
-use prim_store::image::{ImageDataHandle, ImageInstance, VisibleImageTile, YuvImageDataHandle};
+use prim_store::gradient::{ConicGradientDataHandle, LinearGradientDataHandle, RadialGradientDataHandle};
+use prim_store::image::{CrossFadeDataHandle, ImageDataHandle, ImageInstance, VisibleImageTile};
+use prim_store::image::YuvImageDataHandle;
 use prim_store::line_dec::LineDecorationDataHandle;
 use prim_store::picture::PictureDataHandle;
 use prim_store::text_run::{TextRunDataHandle, TextRunPrimitive};
@@ -42,7 +45,7 @@ use render_backend::DataStores;
 use render_task::{RenderTask, RenderTaskCacheKey, to_cache_size};
 use render_task::{RenderTaskCacheKeyKind, RenderTaskId, RenderTaskCacheEntryHandle};
 use renderer::{MAX_VERTEX_TEXTURE_WIDTH};
-use resource_cache::{ImageProperties, ImageRequest};
+use resource_cache::{ImageProperties, ImageRequest, ResourceCache};
 use scene::SceneProperties;
 use segment::SegmentBuilder;
 use std::{cmp, fmt, hash, ops, u32, usize, mem};
@@ -362,7 +365,7 @@ pub enum PrimitiveKeyKind {
     /// Clear an existing rect, used for special effects on some platforms.
     Clear,
     Rectangle {
-        color: ColorU,
+        color: PropertyBinding<ColorU>,
     },
 }
 
@@ -691,7 +694,7 @@ impl AsInstanceKind<PrimitiveDataHandle> for PrimitiveKey {
 #[derive(MallocSizeOf)]
 pub enum PrimitiveTemplateKind {
     Rectangle {
-        color: ColorF,
+        color: PropertyBinding<ColorF>,
     },
     Clear,
 }
@@ -778,10 +781,28 @@ impl From<PrimitiveKey> for PrimitiveTemplate {
 }
 
 impl PrimitiveTemplateKind {
+    /// Returns the property binding of an animated color, if any.
+    pub fn color_binding(&self) -> Option<PropertyBindingId> {
+        match *self {
+            PrimitiveTemplateKind::Rectangle { color: PropertyBinding::Binding(key, _), .. } => {
+                Some(key.id)
+            }
+            PrimitiveTemplateKind::Rectangle { .. } |
+            PrimitiveTemplateKind::Clear => None,
+        }
+    }
+
+    /// Returns true if the GPU data of this template depends on the
+    /// current scene properties.
+    pub fn is_animated(&self) -> bool {
+        self.color_binding().is_some()
+    }
+
     /// Write any GPU blocks for the primitive template to the given request object.
     fn write_prim_gpu_blocks(
         &self,
-        request: &mut GpuDataRequest
+        request: &mut GpuDataRequest,
+        scene_properties: &SceneProperties,
     ) {
         match *self {
             PrimitiveTemplateKind::Clear => {
@@ -789,7 +810,7 @@ impl PrimitiveTemplateKind {
                 request.push(PremultipliedColorF::BLACK);
             }
             PrimitiveTemplateKind::Rectangle { ref color, .. } => {
-                request.push(color.premultiplied());
+                request.push(scene_properties.resolve_color(color).premultiplied());
             }
         }
     }
@@ -803,9 +824,16 @@ impl PrimitiveTemplate {
     pub fn update(
         &mut self,
         frame_state: &mut FrameBuildingState,
+        scene_properties: &SceneProperties,
     ) {
+        // The color of an animated rectangle may have changed since the
+        // GPU cache entry was written.
+        if self.kind.is_animated() {
+            frame_state.gpu_cache.invalidate(&self.common.gpu_cache_handle);
+        }
+
         if let Some(mut request) = frame_state.gpu_cache.request(&mut self.common.gpu_cache_handle) {
-            self.kind.write_prim_gpu_blocks(&mut request);
+            self.kind.write_prim_gpu_blocks(&mut request, scene_properties);
         }
 
         self.opacity = match self.kind {
@@ -813,7 +841,7 @@ impl PrimitiveTemplate {
                 PrimitiveOpacity::translucent()
             }
             PrimitiveTemplateKind::Rectangle { ref color, .. } => {
-                PrimitiveOpacity::from_alpha(color.a)
+                PrimitiveOpacity::from_alpha(scene_properties.resolve_color(color).a)
             }
         };
     }
@@ -968,7 +996,6 @@ impl BrushSegment {
         root_spatial_node_index: SpatialNodeIndex,
         surface_index: SurfaceIndex,
         pic_state: &mut PictureState,
-        frame_context: &FrameBuildingContext,
         frame_state: &mut FrameBuildingState,
         clip_data_store: &mut ClipDataStore,
     ) -> ClipMaskKind {
@@ -979,12 +1006,13 @@ impl BrushSegment {
                     return ClipMaskKind::None;
                 }
 
+                let device_pixel_scale = frame_state.surfaces[surface_index.0].device_pixel_scale;
                 let (device_rect, _) = match get_raster_rects(
                     clip_chain.pic_clip_rect,
                     &pic_state.map_pic_to_raster,
                     &pic_state.map_raster_to_world,
                     prim_bounding_rect,
-                    frame_context.device_pixel_scale,
+                    device_pixel_scale,
                 ) {
                     Some(info) => info,
                     None => {
@@ -996,6 +1024,7 @@ impl BrushSegment {
                     device_rect.to_i32(),
                     clip_chain.clips_range,
                     root_spatial_node_index,
+                    device_pixel_scale,
                     frame_state.clip_store,
                     frame_state.gpu_cache,
                     frame_state.resource_cache,
@@ -1251,7 +1280,11 @@ impl IsVisible for PrimitiveKeyKind {
                 true
             }
             PrimitiveKeyKind::Rectangle { ref color, .. } => {
-                color.a > 0
+                match *color {
+                    PropertyBinding::Value(value) => value.a > 0,
+                    // An animated color may become visible later.
+                    PropertyBinding::Binding(..) => true,
+                }
             }
         }
     }
@@ -1268,7 +1301,7 @@ impl CreateShadow for PrimitiveKeyKind {
         match *self {
             PrimitiveKeyKind::Rectangle { .. } => {
                 PrimitiveKeyKind::Rectangle {
-                    color: shadow.color.into(),
+                    color: PropertyBinding::Value(shadow.color.into()),
                 }
             }
             PrimitiveKeyKind::Clear => {
@@ -1333,6 +1366,11 @@ pub enum PrimitiveInstanceKind {
         data_handle: YuvImageDataHandle,
         segment_instance_index: SegmentInstanceIndex,
     },
+    CrossFade {
+        /// Handle to the common interned data for this primitive.
+        data_handle: CrossFadeDataHandle,
+        segment_instance_index: SegmentInstanceIndex,
+    },
     Image {
         /// Handle to the common interned data for this primitive.
         data_handle: ImageDataHandle,
@@ -1348,6 +1386,11 @@ pub enum PrimitiveInstanceKind {
         data_handle: RadialGradientDataHandle,
         visible_tiles_range: GradientTileRange,
     },
+    ConicGradient {
+        /// Handle to the common interned data for this primitive.
+        data_handle: ConicGradientDataHandle,
+        visible_tiles_range: GradientTileRange,
+    },
     /// Clear out a rect, used for special effects.
     Clear {
         /// Handle to the common interned data for this primitive.
@@ -1490,12 +1533,18 @@ impl PrimitiveInstance {
             PrimitiveInstanceKind::RadialGradient { data_handle, .. } => {
                 data_handle.uid()
             }
+            PrimitiveInstanceKind::ConicGradient { data_handle, .. } => {
+                data_handle.uid()
+            }
             PrimitiveInstanceKind::TextRun { data_handle, .. } => {
                 data_handle.uid()
             }
             PrimitiveInstanceKind::YuvImage { data_handle, .. } => {
                 data_handle.uid()
             }
+            PrimitiveInstanceKind::CrossFade { data_handle, .. } => {
+                data_handle.uid()
+            }
         }
     }
 }
@@ -1601,7 +1650,6 @@ impl PrimitiveScratchBuffer {
         self.debug_items.clear();
     }
 
-    #[allow(dead_code)]
     pub fn push_debug_rect(
         &mut self,
         rect: DeviceRect,
@@ -1613,7 +1661,6 @@ impl PrimitiveScratchBuffer {
         });
     }
 
-    #[allow(dead_code)]
     pub fn push_debug_string(
         &mut self,
         position: DevicePoint,
@@ -1681,6 +1728,15 @@ impl PrimitiveStore {
         }
     }
 
+    #[cfg(feature = "debugger")]
+    pub fn report_memory_breakdown(&self, group: &mut MemoryBreakdownGroup) {
+        group.add::<PicturePrimitive>("pictures", self.pictures.len());
+        group.add::<PrimitiveInstance>("prim_instances", self.prim_count());
+        group.add::<TextRunPrimitive>("text_runs", self.text_runs.len());
+        group.add::<ImageInstance>("images", self.images.len());
+        group.add::<OpacityBinding>("opacity_bindings", self.opacity_bindings.len());
+    }
+
     #[allow(unused)]
     pub fn print_picture_tree(&self, root: PictureIndex) {
         use print_tree::PrintTree;
@@ -1747,6 +1803,93 @@ impl PrimitiveStore {
         }
     }
 
+    /// Collects the property bindings a primitive depends on, for the
+    /// purpose of dirty rect tracking. Returns true if the content of the
+    /// primitive itself changed since the last frame.
+    fn collect_dirty_rect_dependencies(
+        &self,
+        prim_instance: &PrimitiveInstance,
+        data_stores: &DataStores,
+        clip_store: &ClipStore,
+        resource_cache: &ResourceCache,
+        bindings: &mut SmallVec<[PropertyBindingId; 4]>,
+    ) -> bool {
+        // Animated clip rects change the visible area of the primitive.
+        let mut clip_chain_id = prim_instance.clip_chain_id;
+        while clip_chain_id != ClipChainId::NONE {
+            let clip_chain_node = clip_store.get_clip_chain(clip_chain_id);
+            if let Some(ref rect_binding) = data_stores.clip[clip_chain_node.handle].rect_binding {
+                bindings.push(rect_binding.id);
+            }
+            clip_chain_id = clip_chain_node.parent_clip_chain_id;
+        }
+
+        let opacity_binding_index = match prim_instance.kind {
+            PrimitiveInstanceKind::Picture { pic_index, .. } => {
+                let pic = &self.pictures[pic_index.0];
+                if let Some(PictureCompositeMode::Filter(FilterOp::Opacity(binding, _))) = pic.requested_composite_mode {
+                    if let PropertyBinding::Binding(key, _) = binding {
+                        bindings.push(key.id);
+                    }
+                }
+                return false;
+            }
+            PrimitiveInstanceKind::Rectangle { data_handle, opacity_binding_index, .. } => {
+                if let Some(id) = data_stores.prim[data_handle].kind.color_binding() {
+                    bindings.push(id);
+                }
+                opacity_binding_index
+            }
+            PrimitiveInstanceKind::Image { data_handle, image_instance_index, .. } => {
+                let image_data = &data_stores.image[data_handle].kind;
+                if let Some(id) = image_data.key_binding() {
+                    bindings.push(id);
+                }
+                if resource_cache.is_image_dirty(image_data.key) {
+                    return true;
+                }
+                self.images[image_instance_index].opacity_binding_index
+            }
+            PrimitiveInstanceKind::YuvImage { data_handle, .. } => {
+                let yuv_image_data = &data_stores.yuv_image[data_handle].kind;
+                return yuv_image_data.yuv_key
+                    .iter()
+                    .any(|key| resource_cache.is_image_dirty(*key));
+            }
+            PrimitiveInstanceKind::CrossFade { data_handle, .. } => {
+                let cross_fade_data = &data_stores.cross_fade[data_handle].kind;
+                if let Some(id) = cross_fade_data.progress_binding() {
+                    bindings.push(id);
+                }
+                return resource_cache.is_image_dirty(cross_fade_data.from_key) ||
+                    resource_cache.is_image_dirty(cross_fade_data.to_key);
+            }
+            PrimitiveInstanceKind::ImageBorder { data_handle, .. } => {
+                let border_data = &data_stores.image_border[data_handle].kind;
+                return resource_cache.is_image_dirty(border_data.request.key);
+            }
+            PrimitiveInstanceKind::TextRun { .. } |
+            PrimitiveInstanceKind::LineDecoration { .. } |
+            PrimitiveInstanceKind::Clear { .. } |
+            PrimitiveInstanceKind::NormalBorder { .. } |
+            PrimitiveInstanceKind::LinearGradient { .. } |
+            PrimitiveInstanceKind::RadialGradient { .. } |
+            PrimitiveInstanceKind::ConicGradient { .. } => {
+                return false;
+            }
+        };
+
+        if opacity_binding_index != OpacityBindingIndex::INVALID {
+            for binding in &self.opacity_bindings[opacity_binding_index].bindings {
+                if let PropertyBinding::Binding(key, _) = *binding {
+                    bindings.push(key.id);
+                }
+            }
+        }
+
+        false
+    }
+
     /// Update visibility pass - update each primitive visibility struct, and
     /// build the clip chain instance if appropriate.
     pub fn update_visibility(
@@ -1997,9 +2140,11 @@ impl PrimitiveStore {
                         PrimitiveInstanceKind::ImageBorder { .. } => debug_colors::ORANGE,
                         PrimitiveInstanceKind::Rectangle { .. } => ColorF { r: 0.8, g: 0.8, b: 0.8, a: 0.5 },
                         PrimitiveInstanceKind::YuvImage { .. } => debug_colors::BLUE,
+                        PrimitiveInstanceKind::CrossFade { .. } => debug_colors::BLUE,
                         PrimitiveInstanceKind::Image { .. } => debug_colors::BLUE,
                         PrimitiveInstanceKind::LinearGradient { .. } => debug_colors::PINK,
                         PrimitiveInstanceKind::RadialGradient { .. } => debug_colors::PINK,
+                        PrimitiveInstanceKind::ConicGradient { .. } => debug_colors::PINK,
                         PrimitiveInstanceKind::Clear { .. } => debug_colors::CYAN,
                     };
                     if debug_color.a != 0.0 {
@@ -2020,6 +2165,21 @@ impl PrimitiveStore {
                 );
 
                 prim_instance.visibility_info = vis_index;
+
+                let mut bindings = SmallVec::<[PropertyBindingId; 4]>::new();
+                let content_changed = self.collect_dirty_rect_dependencies(
+                    prim_instance,
+                    frame_state.data_stores,
+                    frame_state.clip_store,
+                    frame_state.resource_cache,
+                    &mut bindings,
+                );
+                frame_state.dirty_rect_tracker.add_primitive(
+                    prim_instance.spatial_node_index,
+                    clipped_world_rect,
+                    &bindings,
+                    content_changed,
+                );
             }
 
         }
@@ -2086,8 +2246,10 @@ impl PrimitiveStore {
             PrimitiveInstanceKind::NormalBorder { .. } |
             PrimitiveInstanceKind::ImageBorder { .. } |
             PrimitiveInstanceKind::YuvImage { .. } |
+            PrimitiveInstanceKind::CrossFade { .. } |
             PrimitiveInstanceKind::LinearGradient { .. } |
             PrimitiveInstanceKind::RadialGradient { .. } |
+            PrimitiveInstanceKind::ConicGradient { .. } |
             PrimitiveInstanceKind::LineDecoration { .. } => {
                 // These prims don't support opacity collapse
             }
@@ -2215,9 +2377,11 @@ impl PrimitiveStore {
                 PrimitiveInstanceKind::NormalBorder { .. } |
                 PrimitiveInstanceKind::ImageBorder { .. } |
                 PrimitiveInstanceKind::YuvImage { .. } |
+                PrimitiveInstanceKind::CrossFade { .. } |
                 PrimitiveInstanceKind::Image { .. } |
                 PrimitiveInstanceKind::LinearGradient { .. } |
                 PrimitiveInstanceKind::RadialGradient { .. } |
+                PrimitiveInstanceKind::ConicGradient { .. } |
                 PrimitiveInstanceKind::Clear { .. } => {
                     None
                 }
@@ -2331,9 +2495,11 @@ impl PrimitiveStore {
             PrimitiveInstanceKind::NormalBorder { .. } |
             PrimitiveInstanceKind::ImageBorder { .. } |
             PrimitiveInstanceKind::YuvImage { .. } |
+            PrimitiveInstanceKind::CrossFade { .. } |
             PrimitiveInstanceKind::Image { .. } |
             PrimitiveInstanceKind::LinearGradient { .. } |
             PrimitiveInstanceKind::RadialGradient { .. } |
+            PrimitiveInstanceKind::ConicGradient { .. } |
             PrimitiveInstanceKind::LineDecoration { .. } => {
                 self.prepare_interned_prim_for_render(
                     prim_instance,
@@ -2455,6 +2621,9 @@ impl PrimitiveStore {
         scratch: &mut PrimitiveScratchBuffer,
     ) {
         let is_chased = prim_instance.is_chased();
+        // Cached render tasks and glyphs are rasterized at the scale of
+        // the surface the primitive is drawn into.
+        let device_pixel_scale = frame_state.surfaces[pic_context.surface_index.0].device_pixel_scale;
 
         match &mut prim_instance.kind {
             PrimitiveInstanceKind::LineDecoration { data_handle, ref mut cache_handle, .. } => {
@@ -2476,7 +2645,7 @@ impl PrimitiveStore {
                 if let Some(cache_key) = line_dec_data.cache_key.as_ref() {
                     // TODO(gw): Do we ever need / want to support scales for text decorations
                     //           based on the current transform?
-                    let scale_factor = TypedScale::new(1.0) * frame_context.device_pixel_scale;
+                    let scale_factor = TypedScale::new(1.0) * device_pixel_scale;
                     let task_size = (LayoutSize::from_au(cache_key.size) * scale_factor).ceil().to_i32();
 
                     // Request a pre-rendered image task.
@@ -2526,7 +2695,7 @@ impl PrimitiveStore {
                     prim_offset,
                     &prim_data.font,
                     &prim_data.glyphs,
-                    frame_context.device_pixel_scale,
+                    device_pixel_scale,
                     &transform,
                     pic_context,
                     frame_state.resource_cache,
@@ -2540,7 +2709,7 @@ impl PrimitiveStore {
 
                 // Update the template this instane references, which may refresh the GPU
                 // cache with any shared template data.
-                prim_data.update(frame_state);
+                prim_data.update(frame_state, frame_context.scene_properties);
             }
             PrimitiveInstanceKind::NormalBorder { data_handle, ref mut cache_handles, .. } => {
                 let prim_data = &mut data_stores.normal_border[*data_handle];
@@ -2555,7 +2724,7 @@ impl PrimitiveStore {
                 //           scale factor from the world transform to get an appropriately
                 //           sized border task.
                 let world_scale = LayoutToWorldScale::new(1.0);
-                let mut scale = world_scale * frame_context.device_pixel_scale;
+                let mut scale = world_scale * device_pixel_scale;
                 let max_scale = get_max_scale_for_border(&border_data.border.radius,
                                                          &border_data.widths);
                 scale.0 = scale.0.min(max_scale.0);
@@ -2611,7 +2780,7 @@ impl PrimitiveStore {
 
                 // Update the template this instane references, which may refresh the GPU
                 // cache with any shared template data.
-                prim_data.update(frame_state);
+                prim_data.update(frame_state, frame_context.scene_properties);
 
                 update_opacity_binding(
                     &mut self.opacity_bindings,
@@ -2619,9 +2788,18 @@ impl PrimitiveStore {
                     frame_context.scene_properties,
                 );
 
+                // The segments embed the color as well, so they need to be
+                // rewritten if it is animated.
+                if prim_data.kind.is_animated() &&
+                   *segment_instance_index != SegmentInstanceIndex::UNUSED {
+                    let segment_instance = &scratch.segment_instances[*segment_instance_index];
+                    frame_state.gpu_cache.invalidate(&segment_instance.gpu_cache_handle);
+                }
+
                 write_segment(*segment_instance_index, frame_state, scratch, |request| {
                     prim_data.kind.write_prim_gpu_blocks(
                         request,
+                        frame_context.scene_properties,
                     );
                 });
             }
@@ -2636,6 +2814,32 @@ impl PrimitiveStore {
                     yuv_image_data.kind.write_prim_gpu_blocks(request);
                 });
             }
+            PrimitiveInstanceKind::CrossFade { data_handle, segment_instance_index, .. } => {
+                let cross_fade_data = &mut data_stores.cross_fade[*data_handle];
+
+                // Update the template this instane references, which may refresh the GPU
+                // cache with any shared template data.
+                cross_fade_data.kind.update(
+                    &mut cross_fade_data.common,
+                    frame_state,
+                    frame_context.scene_properties,
+                );
+
+                // The segments embed the progress as well, so they need to be
+                // rewritten if it is animated.
+                if cross_fade_data.kind.is_animated() &&
+                   *segment_instance_index != SegmentInstanceIndex::UNUSED {
+                    let segment_instance = &scratch.segment_instances[*segment_instance_index];
+                    frame_state.gpu_cache.invalidate(&segment_instance.gpu_cache_handle);
+                }
+
+                write_segment(*segment_instance_index, frame_state, scratch, |request| {
+                    cross_fade_data.kind.write_prim_gpu_blocks(
+                        request,
+                        frame_context.scene_properties,
+                    );
+                });
+            }
             PrimitiveInstanceKind::Image { data_handle, image_instance_index, .. } => {
                 let prim_data = &mut data_stores.image[*data_handle];
                 let common_data = &mut prim_data.common;
@@ -2842,6 +3046,40 @@ impl PrimitiveStore {
                 // TODO(gw): Consider whether it's worth doing segment building
                 //           for gradient primitives.
             }
+            PrimitiveInstanceKind::ConicGradient { data_handle, ref mut visible_tiles_range, .. } => {
+                let prim_data = &mut data_stores.conic_grad[*data_handle];
+
+                // Update the template this instane references, which may refresh the GPU
+                // cache with any shared template data.
+                prim_data.update(frame_state);
+
+                if prim_data.tile_spacing != LayoutSize::zero() {
+                    let prim_info = &scratch.prim_info[prim_instance.visibility_info.0 as usize];
+                    let prim_rect = LayoutRect::new(
+                        prim_instance.prim_origin,
+                        prim_data.common.prim_size,
+                    );
+                    let gpu_blocks = prim_data.gpu_blocks();
+
+                    *visible_tiles_range = decompose_repeated_primitive(
+                        &prim_info.combined_local_clip_rect,
+                        &prim_rect,
+                        &prim_data.stretch_size,
+                        &prim_data.tile_spacing,
+                        prim_context,
+                        frame_state,
+                        &mut scratch.gradient_tiles,
+                        &mut |_, mut request| {
+                            request.push(gpu_blocks[0]);
+                            request.push(gpu_blocks[1]);
+                        },
+                    );
+
+                    if visible_tiles_range.is_empty() {
+                        prim_instance.visibility_info = PrimitiveVisibilityIndex::INVALID;
+                    }
+                }
+            }
             _ => {
                 unreachable!();
             }
@@ -3121,7 +3359,8 @@ impl PrimitiveInstance {
 
         let segment_instance_index = match self.kind {
             PrimitiveInstanceKind::Rectangle { ref mut segment_instance_index, .. } |
-            PrimitiveInstanceKind::YuvImage { ref mut segment_instance_index, .. } => {
+            PrimitiveInstanceKind::YuvImage { ref mut segment_instance_index, .. } |
+            PrimitiveInstanceKind::CrossFade { ref mut segment_instance_index, .. } => {
                 segment_instance_index
             }
             PrimitiveInstanceKind::Image { data_handle, image_instance_index, .. } => {
@@ -3145,6 +3384,7 @@ impl PrimitiveInstance {
             PrimitiveInstanceKind::Clear { .. } |
             PrimitiveInstanceKind::LinearGradient { .. } |
             PrimitiveInstanceKind::RadialGradient { .. } |
+            PrimitiveInstanceKind::ConicGradient { .. } |
             PrimitiveInstanceKind::LineDecoration { .. } => {
                 // These primitives don't support / need segments.
                 return;
@@ -3226,6 +3466,7 @@ impl PrimitiveInstance {
                 &segments_store[segment_instance.segments_range]
             }
             PrimitiveInstanceKind::YuvImage { segment_instance_index, .. } |
+            PrimitiveInstanceKind::CrossFade { segment_instance_index, .. } |
             PrimitiveInstanceKind::Rectangle { segment_instance_index, .. } => {
                 debug_assert!(segment_instance_index != SegmentInstanceIndex::INVALID);
 
@@ -3273,6 +3514,9 @@ impl PrimitiveInstance {
 
                 prim_data.brush_segments.as_slice()
             }
+            PrimitiveInstanceKind::ConicGradient { .. } => {
+                return false;
+            }
         };
 
         // If there are no segments, early out to avoid setting a valid
@@ -3297,7 +3541,6 @@ impl PrimitiveInstance {
                 root_spatial_node_index,
                 pic_context.surface_index,
                 pic_state,
-                frame_context,
                 frame_state,
                 &mut data_stores.clip,
             );
@@ -3337,7 +3580,6 @@ impl PrimitiveInstance {
                     root_spatial_node_index,
                     pic_context.surface_index,
                     pic_state,
-                    frame_context,
                     frame_state,
                     &mut data_stores.clip,
                 );
@@ -3397,17 +3639,19 @@ impl PrimitiveInstance {
         }
 
         if prim_info.clip_chain.needs_mask {
+            let device_pixel_scale = frame_state.surfaces[pic_context.surface_index.0].device_pixel_scale;
             if let Some((device_rect, _)) = get_raster_rects(
                 prim_info.clip_chain.pic_clip_rect,
                 &pic_state.map_pic_to_raster,
                 &pic_state.map_raster_to_world,
                 prim_info.clipped_world_rect,
-                frame_context.device_pixel_scale,
+                device_pixel_scale,
             ) {
                 let clip_task = RenderTask::new_mask(
                     device_rect,
                     prim_info.clip_chain.clips_range,
                     root_spatial_node_index,
+                    device_pixel_scale,
                     frame_state.clip_store,
                     frame_state.gpu_cache,
                     frame_state.resource_cache,
@@ -3536,8 +3780,8 @@ fn test_struct_sizes() {
     //     be done with care, and after checking if talos performance regresses badly.
     assert_eq!(mem::size_of::<PrimitiveInstance>(), 96, "PrimitiveInstance size changed");
     assert_eq!(mem::size_of::<PrimitiveInstanceKind>(), 40, "PrimitiveInstanceKind size changed");
-    assert_eq!(mem::size_of::<PrimitiveTemplate>(), 40, "PrimitiveTemplate size changed");
-    assert_eq!(mem::size_of::<PrimitiveTemplateKind>(), 20, "PrimitiveTemplateKind size changed");
-    assert_eq!(mem::size_of::<PrimitiveKey>(), 20, "PrimitiveKey size changed");
-    assert_eq!(mem::size_of::<PrimitiveKeyKind>(), 5, "PrimitiveKeyKind size changed");
+    assert_eq!(mem::size_of::<PrimitiveTemplate>(), 48, "PrimitiveTemplate size changed");
+    assert_eq!(mem::size_of::<PrimitiveTemplateKind>(), 28, "PrimitiveTemplateKind size changed");
+    assert_eq!(mem::size_of::<PrimitiveKey>(), 28, "PrimitiveKey size changed");
+    assert_eq!(mem::size_of::<PrimitiveKeyKind>(), 16, "PrimitiveKeyKind size changed");
 }
@@ -3,8 +3,9 @@
  * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
 
 use api::{
-    ColorU, FilterOp, LayoutSize, LayoutPrimitiveInfo, MixBlendMode,
-    PropertyBinding, PropertyBindingId, LayoutVector2D,
+    ColorU, CompositeOperator, FilterOp, FilterPrimitive, FilterPrimitiveInput,
+    LayoutSize, LayoutPrimitiveInfo, MixBlendMode, PropertyBinding, PropertyBindingId,
+    LayoutVector2D,
 };
 use app_units::Au;
 use display_list_flattener::{AsInstanceKind, IsVisible};
@@ -41,6 +42,12 @@ pub enum PictureCompositeKey {
     SrgbToLinear,
     LinearToSrgb,
 
+    // SVG filter primitives
+    SvgFilter(Vec<FilterPrimitiveKey>),
+
+    // Backdrop filters
+    BackdropFilter(Vec<PictureCompositeKey>),
+
     // MixBlendMode
     Multiply,
     Screen,
@@ -117,6 +124,14 @@ impl From<Option<PictureCompositeMode>> for PictureCompositeKey {
                     }
                 }
             }
+            Some(PictureCompositeMode::SvgFilter) => {
+                // The filter primitives live on the picture rather than in
+                // the composite mode, see `PictureCompositeKey::svg_filter`.
+                unreachable!("bug: SVG filter keys are built from the filter primitives");
+            }
+            Some(PictureCompositeMode::BackdropFilter) => {
+                unreachable!("bug: backdrop filter keys are built from the filters");
+            }
             Some(PictureCompositeMode::Blit) |
             Some(PictureCompositeMode::TileCache { .. }) |
             None => {
@@ -126,6 +141,79 @@ impl From<Option<PictureCompositeMode>> for PictureCompositeKey {
     }
 }
 
+impl PictureCompositeKey {
+    pub fn svg_filter(primitives: &[FilterPrimitive]) -> Self {
+        PictureCompositeKey::SvgFilter(
+            primitives.iter().map(|primitive| (*primitive).into()).collect()
+        )
+    }
+
+    pub fn backdrop_filter(filters: &[FilterOp]) -> Self {
+        PictureCompositeKey::BackdropFilter(
+            filters.iter().map(|filter| Some(PictureCompositeMode::Filter(*filter)).into()).collect()
+        )
+    }
+}
+
+/// A hashable version of `FilterPrimitive`.
+#[cfg_attr(feature = "capture", derive(Serialize))]
+#[cfg_attr(feature = "replay", derive(Deserialize))]
+#[derive(Debug, Clone, MallocSizeOf, PartialEq, Hash, Eq)]
+pub enum FilterPrimitiveKey {
+    Blur(FilterPrimitiveInput, Au),
+    ColorMatrix(FilterPrimitiveInput, [Au; 20]),
+    Composite(FilterPrimitiveInput, FilterPrimitiveInput, CompositeOperatorKey),
+}
+
+#[cfg_attr(feature = "capture", derive(Serialize))]
+#[cfg_attr(feature = "replay", derive(Deserialize))]
+#[derive(Debug, Clone, MallocSizeOf, PartialEq, Hash, Eq)]
+pub enum CompositeOperatorKey {
+    Over,
+    In,
+    Out,
+    Atop,
+    Xor,
+    Lighter,
+    Arithmetic([Au; 4]),
+}
+
+impl From<FilterPrimitive> for FilterPrimitiveKey {
+    fn from(primitive: FilterPrimitive) -> Self {
+        match primitive {
+            FilterPrimitive::Blur { input, std_deviation } => {
+                FilterPrimitiveKey::Blur(input, Au::from_f32_px(std_deviation))
+            }
+            FilterPrimitive::ColorMatrix { input, matrix } => {
+                let mut quantized_values: [Au; 20] = [Au(0); 20];
+                for (value, result) in matrix.iter().zip(quantized_values.iter_mut()) {
+                    *result = Au::from_f32_px(*value);
+                }
+                FilterPrimitiveKey::ColorMatrix(input, quantized_values)
+            }
+            FilterPrimitive::Composite { input1, input2, operator } => {
+                let operator = match operator {
+                    CompositeOperator::Over => CompositeOperatorKey::Over,
+                    CompositeOperator::In => CompositeOperatorKey::In,
+                    CompositeOperator::Out => CompositeOperatorKey::Out,
+                    CompositeOperator::Atop => CompositeOperatorKey::Atop,
+                    CompositeOperator::Xor => CompositeOperatorKey::Xor,
+                    CompositeOperator::Lighter => CompositeOperatorKey::Lighter,
+                    CompositeOperator::Arithmetic(k) => {
+                        CompositeOperatorKey::Arithmetic([
+                            Au::from_f32_px(k[0]),
+                            Au::from_f32_px(k[1]),
+                            Au::from_f32_px(k[2]),
+                            Au::from_f32_px(k[3]),
+                        ])
+                    }
+                };
+                FilterPrimitiveKey::Composite(input1, input2, operator)
+            }
+        }
+    }
+}
+
 #[cfg_attr(feature = "capture", derive(Serialize))]
 #[cfg_attr(feature = "replay", derive(Deserialize))]
 #[derive(Debug, Clone, Eq, MallocSizeOf, PartialEq, Hash)]
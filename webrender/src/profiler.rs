@@ -8,7 +8,12 @@ use device::query::{GpuSampler, GpuTimer, NamedTag};
 use euclid::{Point2D, Rect, Size2D, vec2};
 use internal_types::FastHashMap;
 use renderer::MAX_VERTEX_TEXTURE_WIDTH;
+use serde::Serialize;
+use serde_json;
 use std::collections::vec_deque::VecDeque;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
 use std::{f32, mem};
 use time::precise_time_ns;
 
@@ -303,6 +308,9 @@ pub struct FrameProfileCounters {
     pub targets_used: IntProfileCounter,
     pub targets_changed: IntProfileCounter,
     pub targets_created: IntProfileCounter,
+    pub blurs: IntProfileCounter,
+    pub downscaled_blurs: IntProfileCounter,
+    pub occluded_primitives: IntProfileCounter,
 }
 
 impl FrameProfileCounters {
@@ -313,6 +321,9 @@ impl FrameProfileCounters {
             targets_used: IntProfileCounter::new("Used targets"),
             targets_changed: IntProfileCounter::new("Changed targets"),
             targets_created: IntProfileCounter::new("Created targets"),
+            blurs: IntProfileCounter::new("Blurs"),
+            downscaled_blurs: IntProfileCounter::new("Downscaled blurs"),
+            occluded_primitives: IntProfileCounter::new("Occluded primitives"),
         }
     }
     pub fn reset_targets(&mut self) {
@@ -326,6 +337,7 @@ impl FrameProfileCounters {
 pub struct TextureCacheProfileCounters {
     pub pages_a8_linear: ResourceProfileCounter,
     pub pages_a16_linear: ResourceProfileCounter,
+    pub pages_rg8_linear: ResourceProfileCounter,
     pub pages_rgba8_linear: ResourceProfileCounter,
     pub pages_rgba8_nearest: ResourceProfileCounter,
 }
@@ -335,6 +347,7 @@ impl TextureCacheProfileCounters {
         TextureCacheProfileCounters {
             pages_a8_linear: ResourceProfileCounter::new("Texture A8 cached pages"),
             pages_a16_linear: ResourceProfileCounter::new("Texture A16 cached pages"),
+            pages_rg8_linear: ResourceProfileCounter::new("Texture RG8 cached pages"),
             pages_rgba8_linear: ResourceProfileCounter::new("Texture RGBA8 cached pages (L)"),
             pages_rgba8_nearest: ResourceProfileCounter::new("Texture RGBA8 cached pages (N)"),
         }
@@ -348,6 +361,9 @@ pub struct GpuCacheProfileCounters {
     pub updated_rows: IntProfileCounter,
     pub updated_blocks: IntProfileCounter,
     pub saved_blocks: IntProfileCounter,
+    pub updated_runs: IntProfileCounter,
+    pub requested_rows: IntProfileCounter,
+    pub coalesced_rows: IntProfileCounter,
 }
 
 impl GpuCacheProfileCounters {
@@ -358,6 +374,9 @@ impl GpuCacheProfileCounters {
             allocated_blocks: IntProfileCounter::new("GPU cache blocks: total"),
             updated_blocks: IntProfileCounter::new("GPU cache blocks: updated"),
             saved_blocks: IntProfileCounter::new("GPU cache blocks: saved"),
+            updated_runs: IntProfileCounter::new("GPU cache runs: updated"),
+            requested_rows: IntProfileCounter::new("GPU cache rows: requested"),
+            coalesced_rows: IntProfileCounter::new("GPU cache rows: coalesced"),
         }
     }
 }
@@ -385,6 +404,10 @@ pub struct IpcProfileCounters {
     pub send_time: TimeProfileCounter,
     pub total_time: TimeProfileCounter,
     pub display_lists: ResourceProfileCounter,
+    /// The most API messages waiting in the render backend at once.
+    pub max_queue_depth: IntProfileCounter,
+    /// The latency-critical messages processed ahead of the others.
+    pub priority_messages: IntProfileCounter,
 }
 
 macro_rules! declare_intern_profile_counters {
@@ -456,6 +479,8 @@ impl BackendProfileCounters {
                 send_time: TimeProfileCounter::new("Display List Send Time", false),
                 total_time: TimeProfileCounter::new("Total Display List Time", false),
                 display_lists: ResourceProfileCounter::new("Display Lists Sent"),
+                max_queue_depth: IntProfileCounter::new("Max API Queue Depth"),
+                priority_messages: IntProfileCounter::new("Priority API Messages"),
             },
             intern: InternProfileCounters {
                 prim: ResourceProfileCounter::new("Interned primitives"),
@@ -466,8 +491,10 @@ impl BackendProfileCounters {
                 normal_border: ResourceProfileCounter::new("Interned normal borders"),
                 picture: ResourceProfileCounter::new("Interned pictures"),
                 radial_grad: ResourceProfileCounter::new("Interned radial gradients"),
+                conic_grad: ResourceProfileCounter::new("Interned conic gradients"),
                 text_run: ResourceProfileCounter::new("Interned text runs"),
                 yuv_image: ResourceProfileCounter::new("Interned YUV images"),
+                cross_fade: ResourceProfileCounter::new("Interned cross-fades"),
                 clip: ResourceProfileCounter::new("Interned clips"),
             },
         }
@@ -480,6 +507,8 @@ impl BackendProfileCounters {
         self.ipc.consume_time.reset();
         self.ipc.send_time.reset();
         self.ipc.display_lists.reset();
+        self.ipc.max_queue_depth.reset();
+        self.ipc.priority_messages.reset();
     }
 }
 
@@ -979,7 +1008,12 @@ impl Profiler {
         );
 
         let rect1 = self.draw_bar(
-            "GPU cache blocks",
+            &format!(
+                "GPU cache blocks ({} runs, {} -> {} rows)",
+                counters.updated_runs.value,
+                counters.requested_rows.value,
+                counters.coalesced_rows.value,
+            ),
             ColorU::new(0xFF, 0xFF, 0, 0xFF),
             &[
                 (color_updated, &counters.updated_blocks),
@@ -1012,6 +1046,7 @@ impl Profiler {
             &format!("primitives ({}):", counters.total_primitives.value),
             ColorU::new(0xFF, 0xFF, 0xFF, 0xFF),
             &[
+                (ColorU::new(0xFF, 0, 0, 0xFF), &counters.occluded_primitives),
                 (ColorU::new(0, 0, 0xFF, 0xFF), &counters.visible_primitives),
                 (ColorU::new(0, 0, 0, 0xFF), &counters.total_primitives),
             ],
@@ -1029,7 +1064,21 @@ impl Profiler {
             debug_renderer,
         );
 
-        let total_rect = rect0.union(&rect1).inflate(10.0, 10.0);
+        let mut total_rect = rect0.union(&rect1);
+        if counters.blurs.value > 0 {
+            let rect2 = self.draw_bar(
+                &format!("blurs ({}):", counters.blurs.value),
+                ColorU::new(0xFF, 0xFF, 0xFF, 0xFF),
+                &[
+                    (ColorU::new(0xFF, 0, 0, 0xFF), &counters.downscaled_blurs),
+                    (ColorU::new(0, 0, 0, 0xFF), &counters.blurs),
+                ],
+                debug_renderer,
+            );
+            total_rect = total_rect.union(&rect2);
+        }
+
+        let total_rect = total_rect.inflate(10.0, 10.0);
         debug_renderer.add_quad(
             total_rect.origin.x,
             total_rect.origin.y,
@@ -1108,9 +1157,12 @@ impl Profiler {
         Profiler::draw_counters(
             &[
                 &backend_profile.resources.texture_cache.pages_a8_linear,
+                &backend_profile.resources.texture_cache.pages_rg8_linear,
                 &backend_profile.resources.texture_cache.pages_rgba8_linear,
                 &backend_profile.resources.texture_cache.pages_rgba8_nearest,
                 &backend_profile.ipc.display_lists,
+                &backend_profile.ipc.max_queue_depth,
+                &backend_profile.ipc.priority_messages,
             ],
             debug_renderer,
             true,
@@ -1258,6 +1310,115 @@ impl Profiler {
     }
 }
 
+/// The kinds of render passes GPU time budgets can be set for. Used as the
+/// timer group of the GPU profiler.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum GpuPassKind {
+    /// Anything drawn outside of the render passes, e.g. debug overlays.
+    Other = 0,
+    MainFramebuffer = 1,
+    ColorTarget = 2,
+    AlphaTarget = 3,
+    TextureCache = 4,
+}
+
+impl GpuPassKind {
+    fn from_group(group: u32) -> Self {
+        match group {
+            1 => GpuPassKind::MainFramebuffer,
+            2 => GpuPassKind::ColorTarget,
+            3 => GpuPassKind::AlphaTarget,
+            4 => GpuPassKind::TextureCache,
+            _ => GpuPassKind::Other,
+        }
+    }
+}
+
+/// A GPU time budget for all the passes of a kind in a frame.
+#[derive(Clone, Copy, Debug)]
+pub struct PassBudget {
+    pub kind: GpuPassKind,
+    pub max_time_ns: u64,
+    /// How many consecutive frames have to exceed the budget to raise an alert.
+    pub consecutive_frames: usize,
+}
+
+/// Raised when the passes of a kind exceeded their budget for the
+/// configured number of consecutive frames.
+#[derive(Clone, Debug)]
+pub struct PassBudgetAlert {
+    pub kind: GpuPassKind,
+    pub budget_ns: u64,
+    /// The GPU time of the passes in the last frame.
+    pub time_ns: u64,
+    pub consecutive_frames: usize,
+    /// The batches that took the most time in the last frame, most
+    /// expensive first.
+    pub top_batches: Vec<(&'static str, u64)>,
+}
+
+/// The number of batches listed in a `PassBudgetAlert`.
+const ALERT_TOP_BATCH_COUNT: usize = 5;
+
+pub struct PassBudgetTracker {
+    budgets: Vec<PassBudget>,
+    frames_over_budget: Vec<usize>,
+}
+
+impl PassBudgetTracker {
+    pub fn new(budgets: Vec<PassBudget>) -> Self {
+        PassBudgetTracker {
+            frames_over_budget: vec![0; budgets.len()],
+            budgets,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.budgets.is_empty()
+    }
+
+    /// Checks the GPU timers of a frame against the budgets. An alert is
+    /// returned each time a budget was exceeded for its number of
+    /// consecutive frames.
+    pub fn update(&mut self, timers: &[GpuTimer<GpuProfileTag>]) -> Vec<PassBudgetAlert> {
+        let mut alerts = Vec::new();
+        for (budget, frames_over_budget) in self.budgets.iter().zip(self.frames_over_budget.iter_mut()) {
+            let mut batches: Vec<(&'static str, u64)> = Vec::new();
+            for timer in timers {
+                if GpuPassKind::from_group(timer.group) != budget.kind {
+                    continue;
+                }
+                match batches.iter_mut().find(|&&mut (label, _)| label == timer.tag.label) {
+                    Some(&mut (_, ref mut time_ns)) => *time_ns += timer.time_ns,
+                    None => batches.push((timer.tag.label, timer.time_ns)),
+                }
+            }
+            let time_ns = batches.iter().map(|&(_, time_ns)| time_ns).sum();
+
+            if time_ns <= budget.max_time_ns {
+                *frames_over_budget = 0;
+                continue;
+            }
+            *frames_over_budget += 1;
+            if *frames_over_budget < budget.consecutive_frames.max(1) {
+                continue;
+            }
+            *frames_over_budget = 0;
+
+            batches.sort_by(|a, b| b.1.cmp(&a.1));
+            batches.truncate(ALERT_TOP_BATCH_COUNT);
+            alerts.push(PassBudgetAlert {
+                kind: budget.kind,
+                budget_ns: budget.max_time_ns,
+                time_ns,
+                consecutive_frames: budget.consecutive_frames,
+                top_batches: batches,
+            });
+        }
+        alerts
+    }
+}
+
 pub struct ChangeIndicator {
     counter: u32,
 }
@@ -1307,3 +1468,138 @@ impl ChangeIndicator {
         );
     }
 }
+
+const TRACE_THREAD_BACKEND: u32 = 1;
+const TRACE_THREAD_RENDERER: u32 = 2;
+const TRACE_THREAD_GPU: u32 = 3;
+
+#[derive(Serialize)]
+struct TraceThreadName {
+    name: &'static str,
+}
+
+/// An event of the chrome://tracing JSON format. Timestamps and durations
+/// are in microseconds.
+#[derive(Serialize)]
+struct TraceEvent<'a, A> {
+    name: &'a str,
+    ph: &'static str,
+    ts: f64,
+    dur: f64,
+    pid: u32,
+    tid: u32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    args: Option<A>,
+}
+
+/// Writes the CPU and GPU time spans of the rendered frames to a file that
+/// chrome://tracing can load. The GPU timers only measure durations, so
+/// the passes and batches of a frame are laid out one after another from
+/// the start of the frame on the renderer thread.
+pub struct ChromeTrace {
+    writer: BufWriter<File>,
+    start_ns: u64,
+    has_events: bool,
+}
+
+impl ChromeTrace {
+    pub fn new(path: &Path) -> io::Result<Self> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        writer.write_all(b"{\"traceEvents\":[\n")?;
+
+        let mut trace = ChromeTrace {
+            writer,
+            start_ns: precise_time_ns(),
+            has_events: false,
+        };
+        for &(tid, name) in &[
+            (TRACE_THREAD_BACKEND, "Render Backend"),
+            (TRACE_THREAD_RENDERER, "Renderer"),
+            (TRACE_THREAD_GPU, "GPU"),
+        ] {
+            trace.write_event(&TraceEvent {
+                name: "thread_name",
+                ph: "M",
+                ts: 0.0,
+                dur: 0.0,
+                pid: 0,
+                tid,
+                args: Some(TraceThreadName { name }),
+            })?;
+        }
+
+        Ok(trace)
+    }
+
+    /// Adds the spans of a frame the renderer started drawing at
+    /// `renderer_start_ns`. The backend span ends where the renderer one
+    /// starts.
+    pub fn add_frame(
+        &mut self,
+        backend_time_ns: u64,
+        renderer_start_ns: u64,
+        renderer_time_ns: u64,
+        gpu_timers: &[GpuTimer<GpuProfileTag>],
+    ) -> io::Result<()> {
+        self.add_span(
+            "Frame Build",
+            TRACE_THREAD_BACKEND,
+            renderer_start_ns.saturating_sub(backend_time_ns),
+            backend_time_ns,
+        )?;
+        self.add_span("Render", TRACE_THREAD_RENDERER, renderer_start_ns, renderer_time_ns)?;
+
+        // Consecutive timers of the same group belong to the same pass.
+        let mut pass_start_ns = renderer_start_ns;
+        let mut remaining = gpu_timers;
+        while let Some(first) = remaining.first() {
+            let count = remaining
+                .iter()
+                .position(|timer| timer.group != first.group)
+                .unwrap_or(remaining.len());
+            let (pass, rest) = remaining.split_at(count);
+            remaining = rest;
+
+            let pass_time_ns = pass.iter().map(|timer| timer.time_ns).sum();
+            let name = format!("{:?}", GpuPassKind::from_group(first.group));
+            self.add_span(&name, TRACE_THREAD_GPU, pass_start_ns, pass_time_ns)?;
+
+            let mut batch_start_ns = pass_start_ns;
+            for timer in pass {
+                self.add_span(timer.tag.label, TRACE_THREAD_GPU, batch_start_ns, timer.time_ns)?;
+                batch_start_ns += timer.time_ns;
+            }
+            pass_start_ns += pass_time_ns;
+        }
+
+        Ok(())
+    }
+
+    /// Terminates the JSON and flushes the file.
+    pub fn finish(mut self) -> io::Result<()> {
+        self.writer.write_all(b"\n]}\n")?;
+        self.writer.flush()
+    }
+
+    fn add_span(&mut self, name: &str, tid: u32, start_ns: u64, time_ns: u64) -> io::Result<()> {
+        let ts = start_ns.saturating_sub(self.start_ns) as f64 / 1000.0;
+        self.write_event(&TraceEvent {
+            name,
+            ph: "X",
+            ts,
+            dur: time_ns as f64 / 1000.0,
+            pid: 0,
+            tid,
+            args: None::<()>,
+        })
+    }
+
+    fn write_event<A: Serialize>(&mut self, event: &TraceEvent<A>) -> io::Result<()> {
+        if self.has_events {
+            self.writer.write_all(b",\n")?;
+        }
+        self.has_events = true;
+        serde_json::to_writer(&mut self.writer, event)?;
+        Ok(())
+    }
+}
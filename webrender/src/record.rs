@@ -2,15 +2,16 @@
  * License, v. 2.0. If a copy of the MPL was not distributed with this
  * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
 
-use api::{ApiMsg, FrameMsg, SceneMsg};
-use bincode::serialize;
-use byteorder::{LittleEndian, WriteBytesExt};
+use api::{ApiMsg, FrameMsg, RenderApi, SceneMsg};
+use bincode::{deserialize, serialize};
+use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
+use fxhash;
 use std::any::TypeId;
 use std::fmt::Debug;
 use std::fs::File;
-use std::io::Write;
+use std::io::{self, BufReader, Read, Write};
 use std::mem;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
 pub static WEBRENDER_RECORDING_HEADER: u64 = 0xbeefbeefbeefbe01u64;
 
@@ -61,6 +62,122 @@ impl ApiRecordingReceiver for BinaryRecorder {
     }
 }
 
+/// Plays a recording made by `BinaryRecorder` back into a render backend,
+/// one frame at a time. The embedder renders each frame and hands the
+/// pixels back, so that their hashes can be compared against the ones of
+/// a known good build to bisect regressions.
+pub struct Player {
+    file: BufReader<File>,
+    frame_index: u32,
+    output_hook: Option<Box<FnMut(u32, u64)>>,
+}
+
+impl Player {
+    pub fn new(path: &Path) -> io::Result<Player> {
+        let mut file = BufReader::new(File::open(path)?);
+
+        let header = file.read_u64::<LittleEndian>()?;
+        if header != WEBRENDER_RECORDING_HEADER {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "missing recording header",
+            ));
+        }
+
+        let apimsg_type_id = unsafe {
+            assert!(mem::size_of::<TypeId>() == mem::size_of::<u64>());
+            mem::transmute::<TypeId, u64>(TypeId::of::<ApiMsg>())
+        };
+        let written_apimsg_type_id = file.read_u64::<LittleEndian>()?;
+        if written_apimsg_type_id != apimsg_type_id {
+            warn!(
+                "Recording ApiMsg type mismatch: expected 0x{:x}, found 0x{:x}",
+                apimsg_type_id,
+                written_apimsg_type_id,
+            );
+        }
+
+        Ok(Player {
+            file,
+            frame_index: 0,
+            output_hook: None,
+        })
+    }
+
+    /// The number of frames played so far.
+    pub fn frame_index(&self) -> u32 {
+        self.frame_index
+    }
+
+    /// Sets a hook receiving the index and the output hash of every frame
+    /// passed to `frame_rendered`.
+    pub fn set_output_hook<F>(&mut self, hook: F)
+    where
+        F: FnMut(u32, u64) + 'static,
+    {
+        self.output_hook = Some(Box::new(hook));
+    }
+
+    /// Sends the messages and payloads of the next frame, up to and
+    /// including the transaction generating it. Returns false once the
+    /// recording has been played entirely.
+    pub fn step(&mut self, api: &RenderApi) -> io::Result<bool> {
+        let mut sent_any = false;
+
+        loop {
+            let len = match self.file.read_u32::<LittleEndian>() {
+                Ok(len) => len,
+                Err(ref e) if e.kind() == io::ErrorKind::UnexpectedEof => break,
+                Err(e) => return Err(e),
+            };
+            sent_any = true;
+
+            // Payloads are signaled by a zero length.
+            if len == 0 {
+                let data = self.read_data()?;
+                api.send_payload(&data);
+                continue;
+            }
+
+            let mut buffer = vec![0; len as usize];
+            self.file.read_exact(&mut buffer)?;
+            let msg: ApiMsg = deserialize(&buffer)
+                .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+            let generates_frame = match msg {
+                ApiMsg::UpdateDocument(_, ref txn) => txn.generate_frame,
+                _ => false,
+            };
+            api.send_message(msg);
+
+            if generates_frame {
+                break;
+            }
+        }
+
+        if sent_any {
+            self.frame_index += 1;
+        }
+        Ok(sent_any)
+    }
+
+    /// Hashes the pixels the last frame was rendered to and passes the
+    /// hash to the output hook. The hash is stable across platforms.
+    pub fn frame_rendered(&mut self, pixels: &[u8]) -> u64 {
+        let hash = fxhash::hash64(pixels);
+        if let Some(ref mut hook) = self.output_hook {
+            hook(self.frame_index, hash);
+        }
+        hash
+    }
+
+    fn read_data(&mut self) -> io::Result<Vec<u8>> {
+        let len = self.file.read_u32::<LittleEndian>()?;
+        let mut buffer = vec![0; len as usize];
+        self.file.read_exact(&mut buffer)?;
+        Ok(buffer)
+    }
+}
+
 pub fn should_record_msg(msg: &ApiMsg) -> bool {
     match *msg {
         ApiMsg::UpdateResources(..) |
@@ -74,7 +191,8 @@ pub fn should_record_msg(msg: &ApiMsg) -> bool {
             for msg in &msgs.scene_ops {
                 match *msg {
                     SceneMsg::SetDisplayList { .. } |
-                    SceneMsg::SetRootPipeline { .. } => return true,
+                    SceneMsg::SetRootPipeline { .. } |
+                    SceneMsg::MarkPipelineLost(..) => return true,
                     _ => {}
                 }
             }
@@ -14,7 +14,7 @@ use api::{BuiltDisplayListIter, SpecificDisplayItem};
 use api::{DevicePixelScale, DeviceIntPoint, DeviceIntRect, DeviceIntSize};
 use api::{DocumentId, DocumentLayer, ExternalScrollId, FrameMsg, HitTestFlags, HitTestResult};
 use api::{IdNamespace, LayoutPoint, PipelineId, RenderNotifier, SceneMsg, ScrollClamping};
-use api::{MemoryReport};
+use api::{BatchingParameters, MemoryReport};
 use api::{ScrollLocation, ScrollNodeState, TransactionMsg, ResourceUpdate, BlobImageKey};
 use api::{NotificationRequest, Checkpoint};
 use api::channel::{MsgReceiver, MsgSender, Payload};
@@ -31,12 +31,14 @@ use hit_test::{HitTest, HitTester};
 use intern_types;
 use internal_types::{DebugOutput, FastHashMap, FastHashSet, RenderedDocument, ResultMsg};
 use malloc_size_of::{MallocSizeOf, MallocSizeOfOps};
-use picture::RetainedTiles;
+use picture::{RetainedTiles, RASTER_SCALE_STEP};
 use prim_store::{PrimitiveScratchBuffer, PrimitiveInstance};
 use prim_store::{PrimitiveInstanceKind, PrimTemplateCommonData};
 use profiler::{BackendProfileCounters, IpcProfileCounters, ResourceProfileCounters};
 use record::ApiRecordingReceiver;
 use renderer::{AsyncPropertySampler, PipelineInfo};
+#[cfg(feature = "debugger")]
+use render_task::{RenderTask, RenderTaskData};
 use resource_cache::ResourceCache;
 #[cfg(feature = "replay")]
 use resource_cache::PlainCacheOwn;
@@ -50,10 +52,11 @@ use serde::{Serialize, Deserialize};
 use serde_json;
 #[cfg(any(feature = "capture", feature = "replay"))]
 use std::path::PathBuf;
+use std::collections::VecDeque;
 use std::sync::atomic::{AtomicUsize, Ordering};
 use std::mem::replace;
 use std::sync::mpsc::{channel, Sender, Receiver};
-use std::time::{UNIX_EPOCH, SystemTime};
+use std::time::{Duration, UNIX_EPOCH, SystemTime};
 use std::u32;
 #[cfg(feature = "replay")]
 use tiling::Frame;
@@ -81,6 +84,20 @@ impl DocumentView {
             self.pinch_zoom_factor
         )
     }
+
+    /// The scale factor with the pinch zoom rounded up to a raster scale
+    /// step. Pictures rasterized in local space use it, so that they keep
+    /// their raster scale during most of a zoom gesture, and are only
+    /// composited with the exact zoom.
+    pub fn snapped_scale_factor(&self) -> DevicePixelScale {
+        let pinch_zoom = (self.pinch_zoom_factor / RASTER_SCALE_STEP).ceil().max(1.0) *
+            RASTER_SCALE_STEP;
+        DevicePixelScale::new(
+            self.device_pixel_ratio *
+            self.page_zoom_factor *
+            pinch_zoom
+        )
+    }
 }
 
 #[derive(Copy, Clone, Hash, MallocSizeOf, PartialEq, PartialOrd, Debug, Eq, Ord)]
@@ -106,8 +123,17 @@ impl FrameId {
     }
 
     /// Advances this FrameId to the next frame.
+    ///
+    /// Frame ids are compared to tell which of two frames is older, so they
+    /// are never allowed to wrap around.
     pub fn advance(&mut self) {
-        self.0 += 1;
+        self.0 = self.0.checked_add(1).expect("FrameId overflow");
+    }
+
+    /// Returns the id of the frame `frames` frames before this one, or `None`
+    /// if that would precede the first frame.
+    pub fn checked_sub(self, frames: usize) -> Option<FrameId> {
+        self.0.checked_sub(frames).map(FrameId)
     }
 
     /// An invalid sentinel FrameId, which will always compare less than
@@ -229,6 +255,13 @@ macro_rules! declare_data_stores {
                 )+
             }
 
+            #[cfg(feature = "debugger")]
+            fn report_memory_breakdown(&self, group: &mut debug_server::MemoryBreakdownGroup) {
+                $(
+                    self.$name.report_memory_breakdown(stringify!($name), group);
+                )+
+            }
+
             fn apply_updates(
                 &mut self,
                 updates: InternerUpdates,
@@ -248,6 +281,21 @@ macro_rules! declare_data_stores {
 enumerate_interners!(declare_data_stores);
 
 impl DataStores {
+    /// Resolves the animated property bindings of the interned clips and
+    /// images for the current frame.
+    pub fn update_property_bindings(
+        &mut self,
+        scene_properties: &SceneProperties,
+        gpu_cache: &mut GpuCache,
+    ) {
+        for clip_node in self.clip.iter_mut() {
+            clip_node.update_rect_binding(scene_properties, gpu_cache);
+        }
+        for image in self.image.iter_mut() {
+            image.kind.update_key_binding(scene_properties);
+        }
+    }
+
     pub fn as_common_data(
         &self,
         prim_inst: &PrimitiveInstance
@@ -286,6 +334,10 @@ impl DataStores {
                 let prim_data = &self.radial_grad[data_handle];
                 &prim_data.common
             }
+            PrimitiveInstanceKind::ConicGradient { data_handle, .. } => {
+                let prim_data = &self.conic_grad[data_handle];
+                &prim_data.common
+            }
             PrimitiveInstanceKind::TextRun { data_handle, .. }  => {
                 let prim_data = &self.text_run[data_handle];
                 &prim_data.common
@@ -294,10 +346,57 @@ impl DataStores {
                 let prim_data = &self.yuv_image[data_handle];
                 &prim_data.common
             }
+            PrimitiveInstanceKind::CrossFade { data_handle, .. } => {
+                let prim_data = &self.cross_fade[data_handle];
+                &prim_data.common
+            }
+        }
+    }
+}
+
+/// A scroll of a node in progress, started by
+/// `FrameMsg::AnimateScrollNodeWithId`.
+struct ScrollAnimation {
+    from: LayoutPoint,
+    to: LayoutPoint,
+    clamp: ScrollClamping,
+    start_time: u64,
+    duration: u64,
+}
+
+impl ScrollAnimation {
+    /// Returns the scroll origin at the given time, and whether the
+    /// animation is over.
+    fn sample(&self, time: u64) -> (LayoutPoint, bool) {
+        let elapsed = time.saturating_sub(self.start_time);
+        if elapsed >= self.duration {
+            return (self.to, true);
         }
+        // Ease out, so the scroll starts at the speed of the gesture and
+        // settles at the end.
+        let t = 1.0 - elapsed as f32 / self.duration as f32;
+        (self.from.lerp(self.to, 1.0 - t * t * t), false)
     }
 }
 
+/// Scrolls the nodes of `animations` to their position at `time`, and drops
+/// the animations that are over.
+fn sample_scroll_animations_at(
+    animations: &mut FastHashMap<ExternalScrollId, ScrollAnimation>,
+    clip_scroll_tree: &mut ClipScrollTree,
+    time: u64,
+) {
+    animations.retain(|&id, animation| {
+        let (origin, finished) = animation.sample(time);
+        clip_scroll_tree.scroll_node(origin, id, animation.clamp.clone());
+        !finished
+    });
+}
+
+fn duration_ns(duration: Duration) -> u64 {
+    duration.as_secs() * 1_000_000_000 + duration.subsec_nanos() as u64
+}
+
 struct Document {
     // The latest built scene, usable to build frames.
     // received from the scene builder thread.
@@ -346,6 +445,14 @@ struct Document {
     /// where we want to recycle the memory each new display list, to avoid constantly
     /// re-allocating and moving memory around.
     scratch: PrimitiveScratchBuffer,
+
+    /// The number of render tasks in the last built frame. The render task
+    /// tree itself is handed over to the renderer with the frame.
+    last_render_task_count: usize,
+
+    /// The scroll animations in progress. They are keyed by external id
+    /// rather than node index, so they carry over to the next scene.
+    scroll_animations: FastHashMap<ExternalScrollId, ScrollAnimation>,
 }
 
 impl Document {
@@ -379,6 +486,8 @@ impl Document {
             has_built_scene: false,
             data_stores: DataStores::default(),
             scratch: PrimitiveScratchBuffer::new(),
+            last_render_task_count: 0,
+            scroll_animations: FastHashMap::default(),
         }
     }
 
@@ -468,6 +577,30 @@ impl Document {
                     ..DocumentOps::nop()
                 };
             }
+            FrameMsg::AnimateScrollNodeWithId(origin, id, clamp, duration) => {
+                profile_scope!("AnimateScrollNodeWithId");
+
+                // Start from where the node is, including mid-way through a
+                // previous animation. Nodes that aren't in the tree yet jump.
+                let from = self.clip_scroll_tree
+                    .get_scroll_node_state()
+                    .iter()
+                    .find(|state| state.id == id)
+                    .map_or(origin, |state| LayoutPoint::zero() - state.scroll_offset);
+                self.scroll_animations.insert(id, ScrollAnimation {
+                    from,
+                    to: origin,
+                    clamp,
+                    start_time: precise_time_ns(),
+                    duration: duration_ns(duration),
+                });
+                self.frame_is_valid = false;
+
+                return DocumentOps {
+                    scroll: true,
+                    ..DocumentOps::nop()
+                };
+            }
             FrameMsg::GetScrollNodeState(tx) => {
                 profile_scope!("GetScrollNodeState");
                 tx.send(self.get_scroll_node_state()).unwrap();
@@ -489,6 +622,20 @@ impl Document {
         DocumentOps::nop()
     }
 
+    /// Moves the animated scroll nodes to their position for the frame being
+    /// built, and drops the animations that are over.
+    fn sample_scroll_animations(&mut self) {
+        if self.scroll_animations.is_empty() {
+            return;
+        }
+        sample_scroll_animations_at(
+            &mut self.scroll_animations,
+            &mut self.clip_scroll_tree,
+            precise_time_ns(),
+        );
+        self.hit_tester_is_valid = false;
+    }
+
     fn build_frame(
         &mut self,
         resource_cache: &mut ResourceCache,
@@ -496,6 +643,8 @@ impl Document {
         resource_profile: &mut ResourceProfileCounters,
         debug_flags: DebugFlags,
     ) -> RenderedDocument {
+        self.sample_scroll_animations();
+
         let accumulated_scale_factor = self.view.accumulated_scale_factor();
         let pan = self.view.pan.to_f32() / accumulated_scale_factor;
 
@@ -514,6 +663,7 @@ impl Document {
                 &mut self.clip_scroll_tree,
                 &self.scene.pipelines,
                 accumulated_scale_factor,
+                self.view.snapped_scale_factor(),
                 self.view.layer,
                 pan,
                 &mut resource_profile.texture_cache,
@@ -530,8 +680,10 @@ impl Document {
             frame
         };
 
-        self.frame_is_valid = true;
+        // Animated scrolls need a new frame each time one is generated.
+        self.frame_is_valid = self.scroll_animations.is_empty();
         self.hit_tester_is_valid = true;
+        self.last_render_task_count = frame.render_tasks.tasks.len();
 
         let is_new_scene = self.has_built_scene;
         self.has_built_scene = false;
@@ -647,6 +799,103 @@ impl DocumentOps {
 /// The namespace_id should start from 1.
 static NEXT_NAMESPACE_ID: AtomicUsize = AtomicUsize::new(1);
 
+/// The number of latency-critical messages processed in a row before a
+/// waiting message of the regular queue gets its turn.
+const MAX_PRIORITY_MESSAGES_IN_A_ROW: usize = 8;
+
+/// Returns the document a message is about, if any.
+fn api_msg_document(msg: &ApiMsg) -> Option<DocumentId> {
+    match *msg {
+        ApiMsg::AddDocument(document_id, ..) |
+        ApiMsg::UpdateDocument(document_id, ..) |
+        ApiMsg::DeleteDocument(document_id) => Some(document_id),
+        _ => None,
+    }
+}
+
+/// The messages received but not processed yet.
+///
+/// Latency-critical messages skip ahead of the regular ones, but no more
+/// than `MAX_PRIORITY_MESSAGES_IN_A_ROW` at a time, so that a stream of
+/// scrolls can't starve display list transactions. The messages of a
+/// document are processed in order though: a scroll or property update
+/// waits behind the regular messages of its document, which could add
+/// the scrolled node or set older property values.
+struct ApiMsgQueue {
+    regular: VecDeque<ApiMsg>,
+    priority: VecDeque<ApiMsg>,
+    /// How many messages of `priority` were processed in a row.
+    priority_streak: usize,
+}
+
+impl ApiMsgQueue {
+    fn new() -> Self {
+        ApiMsgQueue {
+            regular: VecDeque::new(),
+            priority: VecDeque::new(),
+            priority_streak: 0,
+        }
+    }
+
+    fn len(&self) -> usize {
+        self.regular.len() + self.priority.len()
+    }
+
+    fn is_empty(&self) -> bool {
+        self.regular.is_empty() && self.priority.is_empty()
+    }
+
+    fn push(&mut self, msg: ApiMsg, latency_critical: bool) {
+        let document_id = api_msg_document(&msg);
+        let document_queued = self.regular
+            .iter()
+            .any(|queued| document_id.is_some() && api_msg_document(queued) == document_id);
+        if latency_critical && !document_queued {
+            self.priority.push_back(msg);
+        } else {
+            self.regular.push_back(msg);
+        }
+    }
+
+    /// Returns the next message to process, and whether it skipped ahead.
+    fn pop(&mut self) -> Option<(ApiMsg, bool)> {
+        let take_priority = !self.priority.is_empty() &&
+            (self.regular.is_empty() || self.priority_streak < MAX_PRIORITY_MESSAGES_IN_A_ROW);
+        if take_priority {
+            self.priority_streak += 1;
+            self.priority.pop_front().map(|msg| (msg, true))
+        } else {
+            self.priority_streak = 0;
+            self.regular.pop_front().map(|msg| (msg, false))
+        }
+    }
+}
+
+/// Whether a message only scrolls, updates dynamic properties or hit tests
+/// an existing document, and can skip ahead of heavy transactions. Such
+/// messages don't carry payloads, so the regular messages still consume
+/// them in order.
+fn is_latency_critical(msg: &ApiMsg, documents: &FastHashMap<DocumentId, Document>) -> bool {
+    match *msg {
+        ApiMsg::UpdateDocument(document_id, ref txn) => {
+            documents.contains_key(&document_id) &&
+            txn.scene_ops.is_empty() &&
+            txn.resource_updates.is_empty() &&
+            !txn.frame_ops.is_empty() &&
+            txn.frame_ops.iter().all(|op| match *op {
+                FrameMsg::Scroll(..) |
+                FrameMsg::ScrollNodeWithId(..) |
+                FrameMsg::AnimateScrollNodeWithId(..) |
+                FrameMsg::UpdateDynamicProperties(..) |
+                FrameMsg::AppendDynamicProperties(..) |
+                FrameMsg::HitTest(..) => true,
+                _ => false,
+            })
+        }
+        _ => false,
+    }
+}
+
 #[cfg(any(feature = "capture", feature = "replay"))]
 #[cfg_attr(feature = "capture", derive(Serialize))]
 #[cfg_attr(feature = "replay", derive(Deserialize))]
@@ -677,6 +926,9 @@ pub struct RenderBackend {
     resource_cache: ResourceCache,
 
     frame_config: FrameBuilderConfig,
+    /// The batching parameters the renderer was created with, restored when
+    /// a debug override is cleared.
+    default_batching_parameters: BatchingParameters,
     documents: FastHashMap<DocumentId, Document>,
 
     notifier: Box<RenderNotifier>,
@@ -686,7 +938,19 @@ pub struct RenderBackend {
     debug_flags: DebugFlags,
     namespace_alloc_by_client: bool,
 
+    /// Whether the renderer is suspended, see `Renderer::suspend`.
+    suspended: bool,
+    /// The documents that requested a frame while suspended.
+    frames_requested_while_suspended: FastHashSet<DocumentId>,
+
+    /// The messages received but not processed yet.
+    queue: ApiMsgQueue,
+
     recycler: Recycler,
+
+    /// Whether the notifier was last told that blob images are being
+    /// rasterized asynchronously, see `RendererOptions::async_blob_rasterization`.
+    blob_rasterization_pending: bool,
 }
 
 impl RenderBackend {
@@ -718,6 +982,7 @@ impl RenderBackend {
             default_device_pixel_ratio,
             resource_cache,
             gpu_cache: GpuCache::new(),
+            default_batching_parameters: frame_config.batching_parameters,
             frame_config,
             documents: FastHashMap::default(),
             notifier,
@@ -726,7 +991,11 @@ impl RenderBackend {
             size_of_ops,
             debug_flags,
             namespace_alloc_by_client,
+            suspended: false,
+            frames_requested_while_suspended: FastHashSet::default(),
+            queue: ApiMsgQueue::new(),
             recycler: Recycler::new(),
+            blob_rasterization_pending: false,
         }
     }
 
@@ -831,6 +1100,11 @@ impl RenderBackend {
 
                 txn.removed_pipelines.push(pipeline_id);
             }
+            SceneMsg::MarkPipelineLost(pipeline_id) => {
+                profile_scope!("MarkPipelineLost");
+
+                txn.lost_pipelines.push(pipeline_id);
+            }
         }
     }
 
@@ -855,6 +1129,12 @@ impl RenderBackend {
                         let has_built_scene = txn.built_scene.is_some();
                         if let Some(doc) = self.documents.get_mut(&txn.document_id) {
 
+                            // Forget the per-pipeline state of the removed
+                            // pipelines, so it doesn't pile up over navigations.
+                            for pipeline_id in &txn.removed_pipelines {
+                                doc.output_pipelines.remove(pipeline_id);
+                                doc.discard_frame_state_for_pipeline(*pipeline_id);
+                            }
                             doc.removed_pipelines.append(&mut txn.removed_pipelines);
 
                             if let Some(mut built_scene) = txn.built_scene.take() {
@@ -880,12 +1160,24 @@ impl RenderBackend {
                             if let Some(tx) = result_tx {
                                 tx.send(SceneSwapResult::Aborted).unwrap();
                             }
+                            // Blob images are shared between documents, so keep the
+                            // results and stop tracking the requests as in flight.
+                            self.resource_cache.add_rasterized_blob_images(
+                                replace(&mut txn.rasterized_blobs, Vec::new())
+                            );
+                            self.update_blob_rasterization_pending();
                             continue;
                         }
 
-                        self.resource_cache.add_rasterized_blob_images(
+                        let completed_async_blobs = self.resource_cache.add_rasterized_blob_images(
                             replace(&mut txn.rasterized_blobs, Vec::new())
                         );
+                        if completed_async_blobs {
+                            // The last frame was built without these blob images.
+                            if let Some(doc) = self.documents.get_mut(&txn.document_id) {
+                                doc.frame_is_valid = false;
+                            }
+                        }
                         if let Some((rasterizer, info)) = txn.blob_rasterizer.take() {
                             self.resource_cache.set_blob_rasterizer(rasterizer, info);
                         }
@@ -919,14 +1211,14 @@ impl RenderBackend {
                 }
             }
 
-            keep_going = match self.api_rx.recv() {
-                Ok(msg) => {
+            keep_going = match self.next_api_msg(&mut profile_counters) {
+                Some(msg) => {
                     if let Some(ref mut r) = self.recorder {
                         r.write_msg(frame_counter, &msg);
                     }
                     self.process_api_msg(msg, &mut profile_counters, &mut frame_counter)
                 }
-                Err(..) => { false }
+                None => { false }
             };
         }
 
@@ -955,6 +1247,58 @@ impl RenderBackend {
 
     }
 
+    /// Returns the next message to process, blocking until one arrives.
+    /// Latency-critical messages may skip ahead, see `ApiMsgQueue`.
+    fn next_api_msg(&mut self, profile_counters: &mut BackendProfileCounters) -> Option<ApiMsg> {
+        if self.queue.is_empty() {
+            match self.api_rx.recv() {
+                Ok(msg) => self.enqueue_api_msg(msg),
+                Err(..) => return None,
+            }
+        }
+        while let Ok(msg) = self.api_rx.try_recv() {
+            self.enqueue_api_msg(msg);
+        }
+
+        let ipc = &mut profile_counters.ipc;
+        let depth = self.queue.len();
+        if depth > ipc.max_queue_depth.get() {
+            ipc.max_queue_depth.set(depth);
+        }
+
+        self.queue.pop().map(|(msg, skipped_ahead)| {
+            if skipped_ahead {
+                ipc.priority_messages.inc();
+            }
+            msg
+        })
+    }
+
+    fn enqueue_api_msg(&mut self, msg: ApiMsg) {
+        let latency_critical = is_latency_critical(&msg, &self.documents);
+        self.queue.push(msg, latency_critical);
+    }
+
+    fn set_debug_flags(&mut self, flags: DebugFlags) {
+        self.resource_cache.set_debug_flags(flags);
+        self.gpu_cache.set_debug_flags(flags);
+
+        // If we're toggling on the GPU cache debug display, we
+        // need to blow away the cache. This is because we only
+        // send allocation/free notifications to the renderer
+        // thread when the debug display is enabled, and thus
+        // enabling it when the cache is partially populated will
+        // give the renderer an incomplete view of the world.
+        // And since we might as well drop all the debugging state
+        // from the renderer when we disable the debug display,
+        // we just clear the cache on toggle.
+        let changed = self.debug_flags ^ flags;
+        if changed.contains(DebugFlags::GPU_CACHE_DBG) {
+            self.gpu_cache.clear();
+        }
+        self.debug_flags = flags;
+    }
+
     fn process_api_msg(
         &mut self,
         msg: ApiMsg,
@@ -1047,9 +1391,51 @@ impl RenderBackend {
                 self.result_tx.send(msg).unwrap();
                 self.notifier.wake_up();
             }
+            ApiMsg::SetTextureCacheBudget(budget_bytes) => {
+                // Raising the budget takes effect lazily, lowering it evicts
+                // the least recently used entries right away. Evictions free
+                // textures that the last published frames may still use, so
+                // the renderer is told to drop them like on memory pressure.
+                let evicted = self.resource_cache.set_texture_cache_budget(budget_bytes);
+                if evicted {
+                    let pending_update = self.resource_cache.pending_updates();
+                    let msg = ResultMsg::UpdateResources {
+                        updates: pending_update,
+                        memory_pressure: true,
+                    };
+                    self.result_tx.send(msg).unwrap();
+                    self.notifier.wake_up();
+                }
+            }
             ApiMsg::ReportMemory(tx) => {
                 self.report_memory(tx);
             }
+            ApiMsg::SetSuspended(suspended) => {
+                self.suspended = suspended;
+                if !suspended {
+                    let document_ids = replace(
+                        &mut self.frames_requested_while_suspended,
+                        FastHashSet::default(),
+                    );
+                    for document_id in document_ids {
+                        if !self.documents.contains_key(&document_id) {
+                            continue;
+                        }
+                        self.update_document(
+                            document_id,
+                            Vec::new(),
+                            None,
+                            Vec::new(),
+                            Vec::new(),
+                            true,
+                            false,
+                            frame_counter,
+                            profile_counters,
+                            false,
+                        );
+                    }
+                }
+            }
             ApiMsg::DebugCommand(option) => {
                 let msg = match option {
                     DebugCommand::EnableDualSourceBlending(enable) => {
@@ -1073,6 +1459,10 @@ impl RenderBackend {
                         let json = self.get_clip_scroll_tree_for_debugger();
                         ResultMsg::DebugOutput(DebugOutput::FetchClipScrollTree(json))
                     }
+                    DebugCommand::FetchMemoryBreakdown => {
+                        let json = self.get_memory_breakdown_for_debugger();
+                        ResultMsg::DebugOutput(DebugOutput::FetchMemoryBreakdown(json))
+                    }
                     #[cfg(feature = "capture")]
                     DebugCommand::SaveCapture(root, bits) => {
                         let output = self.save_capture(root, bits, profile_counters);
@@ -1125,6 +1515,17 @@ impl RenderBackend {
                         self.resource_cache.clear(mask);
                         return true;
                     }
+                    DebugCommand::SetBatchingParameters(parameters) => {
+                        let parameters = parameters.unwrap_or(self.default_batching_parameters);
+                        self.frame_config.batching_parameters = parameters;
+
+                        self.low_priority_scene_tx.send(SceneBuilderRequest::SetFrameBuilderConfig(
+                            self.frame_config.clone()
+                        )).unwrap();
+
+                        // The renderer needs the resolved parameters to split draw calls.
+                        ResultMsg::DebugCommand(DebugCommand::SetBatchingParameters(Some(parameters)))
+                    }
                     DebugCommand::SimulateLongSceneBuild(time_ms) => {
                         self.scene_tx.send(SceneBuilderRequest::SimulateLongSceneBuild(time_ms)).unwrap();
                         return true;
@@ -1136,30 +1537,31 @@ impl RenderBackend {
                         return true;
                     }
                     DebugCommand::SetFlags(flags) => {
-                        self.resource_cache.set_debug_flags(flags);
-                        self.gpu_cache.set_debug_flags(flags);
-
-                        // If we're toggling on the GPU cache debug display, we
-                        // need to blow away the cache. This is because we only
-                        // send allocation/free notifications to the renderer
-                        // thread when the debug display is enabled, and thus
-                        // enabling it when the cache is partially populated will
-                        // give the renderer an incomplete view of the world.
-                        // And since we might as well drop all the debugging state
-                        // from the renderer when we disable the debug display,
-                        // we just clear the cache on toggle.
-                        let changed = self.debug_flags ^ flags;
-                        if changed.contains(DebugFlags::GPU_CACHE_DBG) {
-                            self.gpu_cache.clear();
-                        }
-                        self.debug_flags = flags;
-
+                        self.set_debug_flags(flags);
+                        ResultMsg::DebugCommand(option)
+                    }
+                    DebugCommand::SetFlag(flag, enabled) => {
+                        // The renderer merges the flag into its own flags,
+                        // which the embedder may have set directly.
+                        let mut flags = self.debug_flags;
+                        flags.set(flag, enabled);
+                        self.set_debug_flags(flags);
                         ResultMsg::DebugCommand(option)
                     }
                     _ => ResultMsg::DebugCommand(option),
                 };
+                let changes_overlay = match msg {
+                    ResultMsg::DebugCommand(DebugCommand::SetFlags(..)) |
+                    ResultMsg::DebugCommand(DebugCommand::SetFlag(..)) |
+                    ResultMsg::DebugCommand(DebugCommand::SetDebugCursor(..)) => true,
+                    _ => false,
+                };
                 self.result_tx.send(msg).unwrap();
-                self.notifier.wake_up();
+                if changes_overlay {
+                    self.notifier.render_needed();
+                } else {
+                    self.notifier.wake_up();
+                }
             }
             ApiMsg::ShutDown => {
                 info!("Recycling stats: {:?}", self.recycler);
@@ -1189,6 +1591,7 @@ impl RenderBackend {
             document_id,
             display_list_updates: Vec::new(),
             removed_pipelines: Vec::new(),
+            lost_pipelines: Vec::new(),
             epoch_updates: Vec::new(),
             request_scene_build: None,
             blob_rasterizer: None,
@@ -1340,6 +1743,13 @@ impl RenderBackend {
             render_frame = false;
         }
 
+        // While suspended there is no surface to render to, so the frame is
+        // built on resume, with all the transactions received until then.
+        if self.suspended && render_frame {
+            self.frames_requested_while_suspended.insert(document_id);
+            render_frame = false;
+        }
+
         // Avoid re-building the frame if the current built frame is still valid.
         let build_frame = render_frame && !doc.frame_is_valid;
 
@@ -1422,12 +1832,52 @@ impl RenderBackend {
             } else if render_frame {
                 doc.rendered_frame_is_valid = true;
             }
+            scroll |= !doc.scroll_animations.is_empty();
             self.notifier.new_frame_ready(document_id, scroll, render_frame, frame_build_time);
         }
 
         if !doc.hit_tester_is_valid {
             doc.rebuild_hit_tester();
         }
+
+        self.send_async_blob_requests(document_id);
+    }
+
+    /// Sends the blob images that the last frame of the document was missing
+    /// to the (low priority) scene builder, which rasterizes them and sends
+    /// them back as a transaction that renders a new frame.
+    fn send_async_blob_requests(&mut self, document_id: DocumentId) {
+        let (blob_rasterizer, blob_requests) = self.resource_cache.create_async_blob_requests();
+        if blob_rasterizer.is_some() {
+            let txn = Box::new(Transaction {
+                document_id,
+                display_list_updates: Vec::new(),
+                removed_pipelines: Vec::new(),
+                lost_pipelines: Vec::new(),
+                epoch_updates: Vec::new(),
+                request_scene_build: None,
+                blob_rasterizer,
+                blob_requests,
+                resource_updates: Vec::new(),
+                frame_ops: Vec::new(),
+                rasterized_blobs: Vec::new(),
+                notifications: Vec::new(),
+                set_root_pipeline: None,
+                render_frame: true,
+                invalidate_rendered_frame: false,
+            });
+            self.low_priority_scene_tx.send(SceneBuilderRequest::Transaction(txn)).unwrap();
+        }
+
+        self.update_blob_rasterization_pending();
+    }
+
+    fn update_blob_rasterization_pending(&mut self) {
+        let pending = self.resource_cache.has_pending_blob_rasterization();
+        if pending != self.blob_rasterization_pending {
+            self.blob_rasterization_pending = pending;
+            self.notifier.blob_rasterization_pending(pending);
+        }
     }
 
     #[cfg(not(feature = "debugger"))]
@@ -1517,6 +1967,39 @@ impl RenderBackend {
         serde_json::to_string(&debug_root).unwrap()
     }
 
+    #[cfg(not(feature = "debugger"))]
+    fn get_memory_breakdown_for_debugger(&self) -> String {
+        String::new()
+    }
+
+    #[cfg(feature = "debugger")]
+    fn get_memory_breakdown_for_debugger(&self) -> String {
+        let mut breakdown = debug_server::MemoryBreakdown::new();
+
+        for (id, doc) in &self.documents {
+            let mut group = debug_server::MemoryBreakdownGroup::new(
+                &format!("document {:?}", id),
+            );
+
+            doc.clip_scroll_tree.report_memory_breakdown(&mut group);
+            if let Some(ref frame_builder) = doc.frame_builder {
+                frame_builder.prim_store.report_memory_breakdown(&mut group);
+                frame_builder.clip_store.report_memory_breakdown(&mut group);
+            }
+            doc.data_stores.report_memory_breakdown(&mut group);
+            group.add::<RenderTask>("render_tasks", doc.last_render_task_count);
+            group.add::<RenderTaskData>("render_task_data", doc.last_render_task_count);
+
+            breakdown.add(group);
+        }
+
+        let mut group = debug_server::MemoryBreakdownGroup::new("resource cache");
+        self.resource_cache.report_memory_breakdown(&mut group);
+        breakdown.add(group);
+
+        serde_json::to_string(&breakdown).unwrap()
+    }
+
     fn report_memory(&mut self, tx: MsgSender<MemoryReport>) {
         let mut report = MemoryReport::default();
         let ops = self.size_of_ops.as_mut().unwrap();
@@ -1583,12 +2066,14 @@ impl ToDebugString for SpecificDisplayItem {
             SpecificDisplayItem::PushReferenceFrame(..) => String::from("push_reference_frame"),
             SpecificDisplayItem::PushStackingContext(..) => String::from("push_stacking_context"),
             SpecificDisplayItem::RadialGradient(..) => String::from("radial_gradient"),
+            SpecificDisplayItem::ConicGradient(..) => String::from("conic_gradient"),
             SpecificDisplayItem::Rectangle(..) => String::from("rectangle"),
             SpecificDisplayItem::ScrollFrame(..) => String::from("scroll_frame"),
             SpecificDisplayItem::SetGradientStops => String::from("set_gradient_stops"),
             SpecificDisplayItem::StickyFrame(..) => String::from("sticky_frame"),
             SpecificDisplayItem::Text(..) => String::from("text"),
             SpecificDisplayItem::YuvImage(..) => String::from("yuv_image"),
+            SpecificDisplayItem::CrossFade(..) => String::from("cross_fade"),
             SpecificDisplayItem::PushCacheMarker(..) => String::from("push_cache_marker"),
             SpecificDisplayItem::PopCacheMarker => String::from("pop_cache_marker"),
         }
@@ -1746,6 +2231,8 @@ impl RenderBackend {
                 has_built_scene: false,
                 data_stores,
                 scratch: PrimitiveScratchBuffer::new(),
+                last_render_task_count: 0,
+                scroll_animations: FastHashMap::default(),
             };
 
             let frame_name = format!("frame-{}-{}", (id.0).0, id.1);
@@ -1796,3 +2283,133 @@ impl RenderBackend {
         }
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use api::{DynamicProperties, PropertyBinding, PropertyBindingKey};
+
+    fn float_properties(key: PropertyBindingKey<f32>, value: f32) -> FrameMsg {
+        FrameMsg::UpdateDynamicProperties(DynamicProperties {
+            floats: vec![key.with(value)],
+            ..DynamicProperties::default()
+        })
+    }
+
+    /// Pops all messages, applying the property updates of `document_id`.
+    fn apply_properties(queue: &mut ApiMsgQueue, document_id: DocumentId) -> SceneProperties {
+        let mut properties = SceneProperties::new();
+        while let Some((msg, _)) = queue.pop() {
+            let txn = match msg {
+                ApiMsg::UpdateDocument(id, txn) if id == document_id => txn,
+                _ => continue,
+            };
+            for op in txn.frame_ops {
+                if let FrameMsg::UpdateDynamicProperties(props) = op {
+                    properties.set_properties(props);
+                    properties.flush_pending_updates();
+                }
+            }
+        }
+        properties
+    }
+
+    #[test]
+    fn priority_messages_keep_document_order() {
+        let document_id = DocumentId(IdNamespace(1), 0);
+        let key = PropertyBindingKey::new(1);
+        let mut queue = ApiMsgQueue::new();
+
+        let mut txn = TransactionMsg::frame_message(float_properties(key, 1.0));
+        txn.scene_ops.push(SceneMsg::SetRootPipeline(PipelineId(1, 0)));
+        queue.push(ApiMsg::UpdateDocument(document_id, txn), false);
+        let txn = TransactionMsg::frame_message(float_properties(key, 2.0));
+        queue.push(ApiMsg::UpdateDocument(document_id, txn), true);
+
+        let properties = apply_properties(&mut queue, document_id);
+        assert_eq!(properties.resolve_float(&PropertyBinding::Binding(key, 0.0)), 2.0);
+    }
+
+    #[test]
+    fn priority_messages_skip_other_documents() {
+        let document_id = DocumentId(IdNamespace(1), 0);
+        let other_document_id = DocumentId(IdNamespace(1), 1);
+        let scroll = FrameMsg::ScrollNodeWithId(
+            LayoutPoint::zero(),
+            ExternalScrollId(1, PipelineId(1, 0)),
+            ScrollClamping::NoClamping,
+        );
+        let mut queue = ApiMsgQueue::new();
+
+        let txn = TransactionMsg::scene_message(SceneMsg::SetRootPipeline(PipelineId(1, 0)));
+        queue.push(ApiMsg::UpdateDocument(document_id, txn), false);
+        queue.push(ApiMsg::UpdateDocument(document_id, TransactionMsg::frame_message(scroll.clone())), true);
+        queue.push(ApiMsg::UpdateDocument(other_document_id, TransactionMsg::frame_message(scroll)), true);
+
+        // The scroll of the other document skips ahead, the one waiting for
+        // the display list of its document doesn't.
+        let order: Vec<_> = (0 .. 3)
+            .map(|_| match queue.pop() {
+                Some((ApiMsg::UpdateDocument(id, txn), skipped_ahead)) => {
+                    (id == document_id, txn.scene_ops.is_empty(), skipped_ahead)
+                }
+                _ => panic!("unexpected message"),
+            })
+            .collect();
+        assert_eq!(order, vec![
+            (false, true, true),
+            (true, false, false),
+            (true, true, false),
+        ]);
+        assert!(queue.is_empty());
+    }
+
+    #[test]
+    fn scroll_animations_interpolate_and_finish() {
+        use api::{LayoutRect, LayoutSize, LayoutTransform, LayoutVector2D};
+        use api::{ReferenceFrameKind, ScrollSensitivity, TransformStyle};
+        use spatial_node::ScrollFrameKind;
+
+        let pipeline_id = PipelineId::dummy();
+        let id = ExternalScrollId(1, pipeline_id);
+        let mut clip_scroll_tree = ClipScrollTree::new();
+        let root = clip_scroll_tree.add_reference_frame(
+            None,
+            TransformStyle::Flat,
+            PropertyBinding::Value(LayoutTransform::identity()),
+            ReferenceFrameKind::Transform,
+            LayoutVector2D::zero(),
+            pipeline_id,
+        );
+        clip_scroll_tree.add_scroll_frame(
+            root,
+            Some(id),
+            pipeline_id,
+            &LayoutRect::new(LayoutPoint::zero(), LayoutSize::new(100.0, 100.0)),
+            &LayoutSize::new(100.0, 1000.0),
+            ScrollSensitivity::ScriptAndInputEvents,
+            ScrollFrameKind::Explicit,
+        );
+
+        let mut animations = FastHashMap::default();
+        animations.insert(id, ScrollAnimation {
+            from: LayoutPoint::zero(),
+            to: LayoutPoint::new(0.0, 200.0),
+            clamp: ScrollClamping::ToContentBounds,
+            start_time: 1000,
+            duration: 1000,
+        });
+        let scroll_offset = |clip_scroll_tree: &ClipScrollTree| {
+            clip_scroll_tree.get_scroll_node_state()[0].scroll_offset
+        };
+
+        // Half way through, the ease out curve has covered 7/8 of the way.
+        sample_scroll_animations_at(&mut animations, &mut clip_scroll_tree, 1500);
+        assert_eq!(scroll_offset(&clip_scroll_tree), LayoutVector2D::new(0.0, -175.0));
+        assert!(animations.contains_key(&id));
+
+        sample_scroll_animations_at(&mut animations, &mut clip_scroll_tree, 2000);
+        assert_eq!(scroll_offset(&clip_scroll_tree), LayoutVector2D::new(0.0, -200.0));
+        assert!(animations.is_empty());
+    }
+}
@@ -3,7 +3,8 @@
  * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
 
 use api::{DeviceIntPoint, DeviceIntRect, DeviceIntSize, DeviceSize, DeviceIntSideOffsets};
-use api::{DevicePixelScale, ImageDescriptor, ImageFormat, LayoutPoint};
+use api::{CompositeOperator, DevicePixelScale, FilterPrimitive, FilterPrimitiveInput};
+use api::{ImageDescriptor, ImageFormat, LayoutPoint};
 use api::{LineStyle, LineOrientation, LayoutSize, DirtyRect};
 #[cfg(feature = "pathfinder")]
 use api::FontRenderMode;
@@ -11,6 +12,8 @@ use border::BorderSegmentCacheKey;
 use box_shadow::{BoxShadowCacheKey};
 use clip::{ClipDataStore, ClipItem, ClipStore, ClipNodeRange};
 use clip_scroll_tree::SpatialNodeIndex;
+#[cfg(feature = "debugger")]
+use debug_server::MemoryBreakdownGroup;
 use device::TextureFilter;
 #[cfg(feature = "pathfinder")]
 use euclid::{TypedPoint2D, TypedVector2D};
@@ -86,27 +89,53 @@ pub struct RenderTaskTree {
     pub cacheable_render_tasks: Vec<RenderTaskId>,
     next_saved: SavedTargetIndex,
     frame_id: FrameId,
+    /// Blurs with a larger standard deviation are rendered at a reduced
+    /// resolution. See `RenderTask::new_blur`.
+    max_blur_std_dev: f32,
+    /// The number of blurs added to the tree.
+    pub blur_count: usize,
+    /// The number of blurs that had to be downscaled.
+    pub downscaled_blur_count: usize,
+    /// The first task that has each task as a child, by task index. Children
+    /// are always added before their parents. Only used for GPU markers.
+    #[cfg(feature = "gleam")]
+    parents: Vec<Option<RenderTaskId>>,
 }
 
 impl RenderTaskTree {
-    pub fn new(frame_id: FrameId) -> Self {
+    pub fn new(frame_id: FrameId, max_blur_std_dev: f32) -> Self {
         RenderTaskTree {
             tasks: Vec::new(),
             task_data: Vec::new(),
             cacheable_render_tasks: Vec::new(),
             next_saved: SavedTargetIndex(0),
             frame_id,
+            max_blur_std_dev,
+            blur_count: 0,
+            downscaled_blur_count: 0,
+            #[cfg(feature = "gleam")]
+            parents: Vec::new(),
         }
     }
 
     pub fn add(&mut self, task: RenderTask) -> RenderTaskId {
-        let index = self.tasks.len() as _;
-        self.tasks.push(task);
-        RenderTaskId {
-            index,
+        let id = RenderTaskId {
+            index: self.tasks.len() as _,
             #[cfg(debug_assertions)]
             frame_id: self.frame_id,
+        };
+        #[cfg(feature = "gleam")]
+        {
+            for child in &task.children {
+                let parent = &mut self.parents[child.index as usize];
+                if parent.is_none() {
+                    *parent = Some(id);
+                }
+            }
+            self.parents.push(None);
         }
+        self.tasks.push(task);
+        id
     }
 
     /// Assign the render tasks from the tree rooted at `id` to the `passes`
@@ -157,6 +186,39 @@ impl RenderTaskTree {
         RenderTaskAddress(id.index)
     }
 
+    #[cfg(feature = "gleam")]
+    pub fn get_task_id(&self, address: RenderTaskAddress) -> RenderTaskId {
+        debug_assert!((address.0 as usize) < self.tasks.len());
+        RenderTaskId {
+            index: address.0,
+            #[cfg(debug_assertions)]
+            frame_id: self.frame_id,
+        }
+    }
+
+    /// Returns the chain of tasks leading from a root of the tree down to
+    /// `id` (inclusive). Shared tasks are attributed to their first parent.
+    #[cfg(feature = "gleam")]
+    pub fn get_ancestry(&self, id: RenderTaskId) -> Vec<RenderTaskId> {
+        let mut ancestry = vec![id];
+        let mut current = id;
+        while let Some(parent) = self.parents[current.index as usize] {
+            ancestry.push(parent);
+            current = parent;
+        }
+        ancestry.reverse();
+        ancestry
+    }
+
+    /// A short description of the task, used to label GPU markers.
+    #[cfg(feature = "gleam")]
+    pub fn get_marker_label(&self, id: RenderTaskId) -> String {
+        match self[id].kind {
+            RenderTaskKind::Picture(ref task) => format!("Picture {:?}", task.pic_index),
+            ref kind => format!("{} #{}", kind.as_str(), id.index),
+        }
+    }
+
     pub fn write_task_data(&mut self, device_pixel_scale: DevicePixelScale) {
         for task in &self.tasks {
             self.task_data.push(task.write_task_data(device_pixel_scale));
@@ -239,6 +301,7 @@ pub struct CacheMaskTask {
     pub actual_rect: DeviceIntRect,
     pub root_spatial_node_index: SpatialNodeIndex,
     pub clip_node_range: ClipNodeRange,
+    pub device_pixel_scale: DevicePixelScale,
 }
 
 #[derive(Debug)]
@@ -269,6 +332,9 @@ pub struct PictureTask {
     pub uv_rect_handle: GpuCacheHandle,
     pub root_spatial_node_index: SpatialNodeIndex,
     uv_rect_kind: UvRectKind,
+    /// The scale the picture is rasterized at, which differs from the
+    /// device pixel ratio when it establishes a raster root.
+    pub device_pixel_scale: DevicePixelScale,
 }
 
 #[derive(Debug)]
@@ -298,6 +364,88 @@ pub struct ScalingTask {
     uv_rect_kind: UvRectKind,
 }
 
+/// The operation applied by a single node of an SVG filter graph.
+#[derive(Debug)]
+#[cfg_attr(feature = "capture", derive(Serialize))]
+#[cfg_attr(feature = "replay", derive(Deserialize))]
+pub enum SvgFilterInfo {
+    /// Copies the input across. Blurs read their source from the previous
+    /// pass, so this is used to bring any other input there first.
+    Identity,
+    ColorMatrix([f32; 20]),
+    Composite(CompositeOperator),
+}
+
+impl SvgFilterInfo {
+    /// Note: has to match `FILTER_*` in cs_svg_filter.glsl.
+    pub fn kind(&self) -> u32 {
+        match *self {
+            SvgFilterInfo::Identity => 0,
+            SvgFilterInfo::ColorMatrix(..) => 1,
+            SvgFilterInfo::Composite(..) => 2,
+        }
+    }
+
+    /// Note: has to match `COMPOSITE_*` in cs_svg_filter.glsl.
+    pub fn generic_int(&self) -> u32 {
+        match *self {
+            SvgFilterInfo::Identity |
+            SvgFilterInfo::ColorMatrix(..) => 0,
+            SvgFilterInfo::Composite(operator) => match operator {
+                CompositeOperator::Over => 0,
+                CompositeOperator::In => 1,
+                CompositeOperator::Out => 2,
+                CompositeOperator::Atop => 3,
+                CompositeOperator::Xor => 4,
+                CompositeOperator::Lighter => 5,
+                CompositeOperator::Arithmetic(..) => 6,
+            },
+        }
+    }
+
+    /// Whether the shader reads extra parameters from the GPU cache.
+    pub fn has_extra_data(&self) -> bool {
+        match *self {
+            SvgFilterInfo::ColorMatrix(..) |
+            SvgFilterInfo::Composite(CompositeOperator::Arithmetic(..)) => true,
+            SvgFilterInfo::Identity |
+            SvgFilterInfo::Composite(..) => false,
+        }
+    }
+}
+
+#[derive(Debug)]
+#[cfg_attr(feature = "capture", derive(Serialize))]
+#[cfg_attr(feature = "replay", derive(Deserialize))]
+pub struct SvgFilterTask {
+    pub info: SvgFilterInfo,
+    /// The tasks this node reads from. Only the previous node of the graph
+    /// is a child of this task, any other input is a saved render target.
+    pub inputs: Vec<RenderTaskId>,
+    pub uv_rect_handle: GpuCacheHandle,
+    pub extra_gpu_cache_handle: GpuCacheHandle,
+    uv_rect_kind: UvRectKind,
+}
+
+impl SvgFilterTask {
+    fn write_extra_gpu_blocks(&mut self, gpu_cache: &mut GpuCache) {
+        if let Some(mut request) = gpu_cache.request(&mut self.extra_gpu_cache_handle) {
+            match self.info {
+                SvgFilterInfo::ColorMatrix(ref m) => {
+                    for i in 0..5 {
+                        request.push([m[i*4], m[i*4+1], m[i*4+2], m[i*4+3]]);
+                    }
+                }
+                SvgFilterInfo::Composite(CompositeOperator::Arithmetic(k)) => {
+                    request.push(k);
+                }
+                SvgFilterInfo::Identity |
+                SvgFilterInfo::Composite(..) => {}
+            }
+        }
+    }
+}
+
 #[derive(Debug)]
 #[cfg(feature = "pathfinder")]
 #[cfg_attr(feature = "capture", derive(Serialize))]
@@ -373,11 +521,34 @@ pub enum RenderTaskKind {
     HorizontalBlur(BlurTask),
     #[allow(dead_code)]
     Glyph(GlyphTask),
-    Readback(DeviceIntRect),
+    /// A copy of the given screen rect of the target that is being drawn
+    /// to, taken right before the composite that samples it.
+    BackdropCapture(DeviceIntRect),
     Scaling(ScalingTask),
     Blit(BlitTask),
     Border(BorderTask),
     LineDecoration(LineDecorationTask),
+    SvgFilter(SvgFilterTask),
+}
+
+#[cfg(feature = "gleam")]
+impl RenderTaskKind {
+    pub fn as_str(&self) -> &'static str {
+        match *self {
+            RenderTaskKind::Picture(..) => "Picture",
+            RenderTaskKind::CacheMask(..) => "CacheMask",
+            RenderTaskKind::ClipRegion(..) => "ClipRegion",
+            RenderTaskKind::VerticalBlur(..) => "VerticalBlur",
+            RenderTaskKind::HorizontalBlur(..) => "HorizontalBlur",
+            RenderTaskKind::Glyph(..) => "Glyph",
+            RenderTaskKind::BackdropCapture(..) => "BackdropCapture",
+            RenderTaskKind::Scaling(..) => "Scaling",
+            RenderTaskKind::Blit(..) => "Blit",
+            RenderTaskKind::Border(..) => "Border",
+            RenderTaskKind::LineDecoration(..) => "LineDecoration",
+            RenderTaskKind::SvgFilter(..) => "SvgFilter",
+        }
+    }
 }
 
 #[derive(Debug, Copy, Clone, PartialEq)]
@@ -430,6 +601,7 @@ impl RenderTask {
         children: Vec<RenderTaskId>,
         uv_rect_kind: UvRectKind,
         root_spatial_node_index: SpatialNodeIndex,
+        device_pixel_scale: DevicePixelScale,
     ) -> Self {
         let size = match location {
             RenderTaskLocation::Dynamic(_, size) => size,
@@ -452,17 +624,18 @@ impl RenderTask {
                 uv_rect_handle: GpuCacheHandle::new(),
                 uv_rect_kind,
                 root_spatial_node_index,
+                device_pixel_scale,
             }),
             clear_mode: ClearMode::Transparent,
             saved_index: None,
         }
     }
 
-    pub fn new_readback(screen_rect: DeviceIntRect) -> Self {
+    pub fn new_backdrop_capture(screen_rect: DeviceIntRect) -> Self {
         RenderTask::with_dynamic_location(
             screen_rect.size,
             Vec::new(),
-            RenderTaskKind::Readback(screen_rect),
+            RenderTaskKind::BackdropCapture(screen_rect),
             ClearMode::Transparent,
         )
     }
@@ -528,6 +701,7 @@ impl RenderTask {
         outer_rect: DeviceIntRect,
         clip_node_range: ClipNodeRange,
         root_spatial_node_index: SpatialNodeIndex,
+        device_pixel_scale: DevicePixelScale,
         clip_store: &mut ClipStore,
         gpu_cache: &mut GpuCache,
         resource_cache: &mut ResourceCache,
@@ -602,6 +776,7 @@ impl RenderTask {
                 actual_rect: outer_rect,
                 clip_node_range,
                 root_spatial_node_index,
+                device_pixel_scale,
             }),
             ClearMode::One,
         )
@@ -631,7 +806,8 @@ impl RenderTask {
     //           |
     //    DownscalingTask(s): Each downscaling task reduces the size of render target to
     //           ^            half. Also reduce the std deviation to half until the std
-    //           |            deviation less than 4.0.
+    //           |            deviation is less than the tree's max_blur_std_dev
+    //           |            (MAX_BLUR_STD_DEVIATION by default).
     //           |
     //           |
     //    VerticalBlurTask: Apply the separable vertical blur to the primitive.
@@ -657,7 +833,7 @@ impl RenderTask {
         let mut adjusted_blur_target_size = blur_target_size;
         let mut downscaling_src_task_id = src_task_id;
         let mut scale_factor = 1.0;
-        while adjusted_blur_std_deviation > MAX_BLUR_STD_DEVIATION {
+        while adjusted_blur_std_deviation > render_tasks.max_blur_std_dev {
             if adjusted_blur_target_size.width < MIN_DOWNSCALING_RT_SIZE ||
                adjusted_blur_target_size.height < MIN_DOWNSCALING_RT_SIZE {
                 break;
@@ -674,6 +850,11 @@ impl RenderTask {
             downscaling_src_task_id = render_tasks.add(downscaling_task);
         }
 
+        render_tasks.blur_count += 1;
+        if scale_factor > 1.0 {
+            render_tasks.downscaled_blur_count += 1;
+        }
+
         let blur_task_v = RenderTask::with_dynamic_location(
             adjusted_blur_target_size,
             vec![downscaling_src_task_id],
@@ -738,6 +919,128 @@ impl RenderTask {
         )
     }
 
+    // Construct the render tasks for a list of SVG filter primitives,
+    // applied to the contents of a picture, and return the task holding
+    // the final result.
+    //
+    // The graph is flattened into a chain, in the order of the primitives,
+    // with every node being the only child of the next one. This keeps
+    // each task in a single pass. Inputs that are not the previous node
+    // are read from saved render targets instead.
+    pub fn new_svg_filter(
+        filter_primitives: &[FilterPrimitive],
+        content_task_id: RenderTaskId,
+        target_size: DeviceIntSize,
+        uv_rect_kind: UvRectKind,
+        device_pixel_scale: DevicePixelScale,
+        render_tasks: &mut RenderTaskTree,
+    ) -> RenderTaskId {
+        let mut outputs: Vec<RenderTaskId> = Vec::with_capacity(filter_primitives.len());
+        let mut cur_task_id = content_task_id;
+
+        let resolve_input = |input: FilterPrimitiveInput, prev_id, outputs: &[RenderTaskId]| {
+            match input {
+                FilterPrimitiveInput::Original => content_task_id,
+                FilterPrimitiveInput::Previous => prev_id,
+                // The flattener drops graphs with forward or out of range
+                // references, so the output always exists.
+                FilterPrimitiveInput::OutputOfPrimitiveIndex(index) => outputs[index],
+            }
+        };
+
+        for primitive in filter_primitives {
+            let (info, inputs) = match *primitive {
+                FilterPrimitive::Blur { input, std_deviation } if std_deviation > 0.0 => {
+                    let input_task_id = resolve_input(input, cur_task_id, &outputs);
+                    let src_task_id = if input_task_id == cur_task_id {
+                        cur_task_id
+                    } else {
+                        render_tasks[input_task_id].mark_for_saving();
+                        let copy_task = RenderTask::new_svg_filter_node(
+                            SvgFilterInfo::Identity,
+                            vec![input_task_id],
+                            cur_task_id,
+                            target_size,
+                            uv_rect_kind,
+                        );
+                        render_tasks.add(copy_task)
+                    };
+
+                    let blur_task = RenderTask::new_blur(
+                        std_deviation * device_pixel_scale.0,
+                        src_task_id,
+                        render_tasks,
+                        RenderTargetKind::Color,
+                        ClearMode::Transparent,
+                    );
+                    cur_task_id = render_tasks.add(blur_task);
+                    outputs.push(cur_task_id);
+                    continue;
+                }
+                FilterPrimitive::Blur { input, .. } => {
+                    (
+                        SvgFilterInfo::Identity,
+                        vec![resolve_input(input, cur_task_id, &outputs)],
+                    )
+                }
+                FilterPrimitive::ColorMatrix { input, matrix } => {
+                    (
+                        SvgFilterInfo::ColorMatrix(matrix),
+                        vec![resolve_input(input, cur_task_id, &outputs)],
+                    )
+                }
+                FilterPrimitive::Composite { input1, input2, operator } => {
+                    (
+                        SvgFilterInfo::Composite(operator),
+                        vec![
+                            resolve_input(input1, cur_task_id, &outputs),
+                            resolve_input(input2, cur_task_id, &outputs),
+                        ],
+                    )
+                }
+            };
+
+            for &input_task_id in &inputs {
+                if input_task_id != cur_task_id {
+                    render_tasks[input_task_id].mark_for_saving();
+                }
+            }
+
+            let task = RenderTask::new_svg_filter_node(
+                info,
+                inputs,
+                cur_task_id,
+                target_size,
+                uv_rect_kind,
+            );
+            cur_task_id = render_tasks.add(task);
+            outputs.push(cur_task_id);
+        }
+
+        cur_task_id
+    }
+
+    fn new_svg_filter_node(
+        info: SvgFilterInfo,
+        inputs: Vec<RenderTaskId>,
+        prev_task_id: RenderTaskId,
+        target_size: DeviceIntSize,
+        uv_rect_kind: UvRectKind,
+    ) -> Self {
+        RenderTask::with_dynamic_location(
+            target_size,
+            vec![prev_task_id],
+            RenderTaskKind::SvgFilter(SvgFilterTask {
+                info,
+                inputs,
+                uv_rect_handle: GpuCacheHandle::new(),
+                extra_gpu_cache_handle: GpuCacheHandle::new(),
+                uv_rect_kind,
+            }),
+            ClearMode::Transparent,
+        )
+    }
+
     #[cfg(feature = "pathfinder")]
     pub fn new_glyph(
         location: RenderTaskLocation,
@@ -765,7 +1068,7 @@ impl RenderTask {
     fn uv_rect_kind(&self) -> UvRectKind {
         match self.kind {
             RenderTaskKind::CacheMask(..) |
-            RenderTaskKind::Readback(..) => {
+            RenderTaskKind::BackdropCapture(..) => {
                 unreachable!("bug: unexpected render task");
             }
 
@@ -782,6 +1085,10 @@ impl RenderTask {
                 task.uv_rect_kind
             }
 
+            RenderTaskKind::SvgFilter(ref task) => {
+                task.uv_rect_kind
+            }
+
             RenderTaskKind::ClipRegion(..) |
             RenderTaskKind::Glyph(_) |
             RenderTaskKind::Border(..) |
@@ -829,15 +1136,24 @@ impl RenderTask {
                 [1.0, 0.0]
             }
             RenderTaskKind::ClipRegion(..) |
-            RenderTaskKind::Readback(..) |
+            RenderTaskKind::BackdropCapture(..) |
             RenderTaskKind::Scaling(..) |
             RenderTaskKind::Border(..) |
             RenderTaskKind::LineDecoration(..) |
+            RenderTaskKind::SvgFilter(..) |
             RenderTaskKind::Blit(..) => {
                 [0.0; 2]
             }
         };
 
+        // Pictures and the clip masks of their primitives are rasterized
+        // at the scale of the surface they belong to.
+        let device_pixel_scale = match self.kind {
+            RenderTaskKind::Picture(ref task) => task.device_pixel_scale,
+            RenderTaskKind::CacheMask(ref task) => task.device_pixel_scale,
+            _ => device_pixel_scale,
+        };
+
         let (mut target_rect, target_index) = self.get_target_rect();
         // The primitives inside a fixed-location render task
         // are already placed to their corresponding positions,
@@ -869,8 +1185,11 @@ impl RenderTask {
             RenderTaskKind::HorizontalBlur(ref info) => {
                 gpu_cache.get_address(&info.uv_rect_handle)
             }
+            RenderTaskKind::SvgFilter(ref info) => {
+                gpu_cache.get_address(&info.uv_rect_handle)
+            }
             RenderTaskKind::ClipRegion(..) |
-            RenderTaskKind::Readback(..) |
+            RenderTaskKind::BackdropCapture(..) |
             RenderTaskKind::Scaling(..) |
             RenderTaskKind::Blit(..) |
             RenderTaskKind::Border(..) |
@@ -923,7 +1242,7 @@ impl RenderTask {
 
     pub fn target_kind(&self) -> RenderTargetKind {
         match self.kind {
-            RenderTaskKind::Readback(..) => RenderTargetKind::Color,
+            RenderTaskKind::BackdropCapture(..) => RenderTargetKind::Color,
 
             RenderTaskKind::LineDecoration(..) => RenderTargetKind::Color,
 
@@ -950,7 +1269,8 @@ impl RenderTask {
                 RenderTargetKind::Color
             }
 
-            RenderTaskKind::Blit(..) => {
+            RenderTaskKind::Blit(..) |
+            RenderTaskKind::SvgFilter(..) => {
                 RenderTargetKind::Color
             }
         }
@@ -977,7 +1297,11 @@ impl RenderTask {
             RenderTaskKind::Picture(ref mut info) => {
                 (&mut info.uv_rect_handle, info.uv_rect_kind)
             }
-            RenderTaskKind::Readback(..) |
+            RenderTaskKind::SvgFilter(ref mut info) => {
+                info.write_extra_gpu_blocks(gpu_cache);
+                (&mut info.uv_rect_handle, info.uv_rect_kind)
+            }
+            RenderTaskKind::BackdropCapture(..) |
             RenderTaskKind::Scaling(..) |
             RenderTaskKind::Blit(..) |
             RenderTaskKind::ClipRegion(..) |
@@ -1028,8 +1352,8 @@ impl RenderTask {
                 pt.new_level("HorizontalBlur".to_owned());
                 task.print_with(pt);
             }
-            RenderTaskKind::Readback(ref rect) => {
-                pt.new_level("Readback".to_owned());
+            RenderTaskKind::BackdropCapture(ref rect) => {
+                pt.new_level("BackdropCapture".to_owned());
                 pt.add_item(format!("rect: {:?}", rect));
             }
             RenderTaskKind::Scaling(ref kind) => {
@@ -1046,6 +1370,11 @@ impl RenderTask {
             RenderTaskKind::Glyph(..) => {
                 pt.new_level("Glyph".to_owned());
             }
+            RenderTaskKind::SvgFilter(ref task) => {
+                pt.new_level("SvgFilter".to_owned());
+                pt.add_item(format!("info: {:?}", task.info));
+                pt.add_item(format!("inputs: {:?}", task.inputs));
+            }
         }
 
         pt.add_item(format!("clear to: {:?}", self.clear_mode));
@@ -1132,6 +1461,15 @@ impl RenderTaskCache {
         self.cache_entries.clear();
     }
 
+    #[cfg(feature = "debugger")]
+    pub fn report_memory_breakdown(&self, group: &mut MemoryBreakdownGroup) {
+        group.add::<(RenderTaskCacheKey, FreeListHandle<RenderTaskCacheMarker>)>(
+            "cached_render_task_keys",
+            self.map.len(),
+        );
+        group.add::<RenderTaskCacheEntry>("cached_render_tasks", self.cache_entries.len());
+    }
+
     pub fn begin_frame(
         &mut self,
         texture_cache: &mut TextureCache,
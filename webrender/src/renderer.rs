@@ -22,21 +22,23 @@
 //! like 'render now', most of interesting commands from the consumer go over
 //! that channel and operate on the `RenderBackend`.
 
-use api::{BlobImageHandler, ColorF, ColorU, DeviceIntPoint, DeviceIntRect, DeviceIntSize};
-use api::{DocumentId, Epoch, ExternalImageId};
+use api::{BatchingParameters, BlobImageHandler, ColorF, ColorU, DeviceIntPoint, DeviceIntRect, DeviceIntSize};
+use api::{DevicePoint, DeviceRect, DocumentId, Epoch, ExternalImageId};
 use api::{ExternalImageType, FontRenderMode, FrameMsg, ImageFormat, PipelineId};
-use api::{ImageRendering, Checkpoint, NotificationRequest};
+use api::{ImageRendering, Checkpoint, LostPipelineTreatment, NotificationRequest};
+use api::{ProfilerOverlay, ProfilerOverlayCorner};
 use api::{MemoryReport, VoidPtrToSizeFn};
 use api::{RenderApiSender, RenderNotifier, TexelRect, TextureTarget};
 use api::{channel};
 use api::DebugCommand;
 pub use api::DebugFlags;
 use api::channel::PayloadReceiverHelperMethods;
-use batch::{BatchKind, BatchTextures, BrushBatchKind};
+use batch::{BatchKind, BatchRects, BatchTextures, BrushBatchKind};
 #[cfg(any(feature = "capture", feature = "replay"))]
 use capture::{CaptureConfig, ExternalCaptureImage, PlainExternalImage};
+use compositor::{CompositeSurface, Compositor, DrawCompositor};
 use debug_colors;
-use debug_render::{DebugItem, DebugRenderer};
+use debug_render::{DebugItem, DebugRenderer, DebugRendererMark};
 use device::desc;
 #[cfg(feature = "replay")]
 use device::IdType;
@@ -44,9 +46,17 @@ use device::{DepthFunction, Device, GpuFrameId, UploadMethod, Texture, PBO};
 use device::{DrawTarget, ExternalTexture, FBOId, ReadTarget};
 use device::{ShaderError, TextureFilter, TextureFlags, VertexUsageHint, VAO};
 use device::{create_projection, DeviceInit, PrimitiveType, ShaderPrecacheFlags, TextureSampler, VertexArrayKind};
-use device::{ProgramCache, ReadPixelsFormat};
+use device::{ProgramCache, ReadPixelsFormat, ReadbackHandle, Workarounds};
+use device::shader_manifest_digest;
+#[cfg(not(feature = "gleam"))]
+use device::{ColorSpace, CommandStream, OutputFormat, SurfaceTransform};
+#[cfg(not(feature = "gleam"))]
+use device::{end_validation_frame, set_validation_enabled};
+use device::SurfaceOrigin;
 use device::query::GpuTimer;
 #[cfg(feature = "gleam")]
+use device::query::GpuMarker;
+#[cfg(feature = "gleam")]
 use device::{CustomVAO, Program, VBO};
 use euclid::rect;
 use euclid::Transform3D;
@@ -55,20 +65,21 @@ use frame_builder::{ChasePrimitive, FrameBuilderConfig};
 use gleam::gl;
 use glyph_rasterizer::{GlyphFormat, GlyphRasterizer};
 use gpu_cache::{GpuBlockData, GpuCacheUpdate, GpuCacheUpdateList};
-use gpu_cache::{GpuCacheDebugChunk, GpuCacheDebugCmd};
+use gpu_cache::{GpuCacheAddress, GpuCacheDebugChunk, GpuCacheDebugCmd};
 #[cfg(feature = "pathfinder")]
 use gpu_glyph_renderer::GpuGlyphRenderer;
-use gpu_types::ScalingInstance;
+use gpu_types::{ScalingInstance, SvgFilterInstance};
 use hal;
 use internal_types::{TextureSource, ResourceCacheError};
-use internal_types::{CacheTextureId, DebugOutput, FastHashMap, LayerIndex, RenderedDocument, ResultMsg};
+use internal_types::{CacheTextureId, DebugOutput, FastHashMap, FastHashSet, LayerIndex, RenderedDocument, ResultMsg};
 use internal_types::{TextureCacheAllocationKind, TextureCacheUpdate, TextureUpdateList, TextureUpdateSource};
 use internal_types::{RenderTargetInfo, SavedTargetIndex};
 use malloc_size_of::MallocSizeOfOps;
 use prim_store::DeferredResolve;
 use profiler::{BackendProfileCounters, FrameProfileCounters, TimeProfileCounter,
                GpuProfileTag, RendererProfileCounters, RendererProfileTimers};
-use profiler::{Profiler, ChangeIndicator};
+use profiler::{Profiler, ChangeIndicator, ChromeTrace};
+use profiler::{GpuPassKind, PassBudget, PassBudgetAlert, PassBudgetTracker};
 use device::query::GpuProfiler;
 use rayon::{ThreadPool, ThreadPoolBuilder};
 use record::ApiRecordingReceiver;
@@ -79,8 +90,11 @@ use smallvec::SmallVec;
 #[cfg(not(feature = "gleam"))]
 use rendy_memory::HeapsConfig;
 use render_task::{RenderTask, RenderTaskKind, RenderTaskTree};
+#[cfg(feature = "gleam")]
+use render_task::{RenderTaskAddress, RenderTaskId};
+use render_task::MAX_BLUR_STD_DEVIATION;
 use resource_cache::ResourceCache;
-use util::drain_filter;
+use util::{drain_filter, CheckedRectHelpers};
 
 use std;
 use std::cmp;
@@ -90,13 +104,16 @@ use std::f32;
 use std::marker::PhantomData;
 use std::mem;
 use std::os::raw::c_void;
-use std::path::PathBuf;
+use std::fs::File;
+use std::io;
+use std::path::{Path, PathBuf};
 use std::rc::Rc;
-use std::sync::Arc;
+use std::sync::{Arc, Mutex, MutexGuard};
 use std::sync::mpsc::{channel, Receiver};
 use std::thread;
+use std::time::Duration;
 use std::cell::RefCell;
-use texture_cache::TextureCache;
+use texture_cache::{TextureCache, TEXTURE_REGION_DIMENSIONS};
 use thread_profiler::{register_thread_with_profiler, write_profile};
 use tiling::{AlphaRenderTarget, ColorRenderTarget};
 use tiling::{BlitJob, BlitJobSource, RenderPass, RenderPassKind, RenderTargetList};
@@ -105,10 +122,11 @@ use tiling::{Frame, RenderTarget, RenderTargetKind, TextureCacheRenderTarget};
 use tiling::GlyphJob;
 use time::precise_time_ns;
 
+use serde_json;
+
 cfg_if! {
     if #[cfg(feature = "debugger")] {
-        use serde_json;
-        use debug_server::{self, DebugServer};
+        use debug_server::{self, DebugClientRegistrar, DebugServer};
     } else {
         use api::ApiMsg;
         use api::channel::MsgSender;
@@ -124,6 +142,23 @@ const GPU_CACHE_RESIZE_TEST: bool = false;
 /// Number of GPU blocks per UV rectangle provided for an image.
 pub const BLOCKS_PER_UV_RECT: usize = 2;
 
+/// How long building shaders ahead of their first use may take after each
+/// rendered frame, in nanoseconds.
+const SHADER_PRECACHE_BUDGET_NS: u64 = 4_000_000;
+
+/// The size of the framebuffer region magnified by the `ZOOM_DBG` overlay,
+/// and how many times it's magnified.
+const ZOOM_DEBUG_SOURCE_SIZE: i32 = 64;
+const ZOOM_DEBUG_FACTOR: i32 = 8;
+
+/// How many removed pipelines are kept for `flush_pipeline_info` before the
+/// list is compacted. Embedders that never flush it only keep the most recent.
+const MAX_REMOVED_PIPELINES: usize = 1024;
+
+/// How many frames of uploads are kept for the debugger.
+#[cfg(feature = "debugger")]
+const UPLOAD_HISTORY_LENGTH: usize = 64;
+
 const GPU_TAG_BRUSH_LINEAR_GRADIENT: GpuProfileTag = GpuProfileTag {
     label: "B_LinearGradient",
     color: debug_colors::POWDERBLUE,
@@ -132,14 +167,26 @@ const GPU_TAG_BRUSH_RADIAL_GRADIENT: GpuProfileTag = GpuProfileTag {
     label: "B_RadialGradient",
     color: debug_colors::LIGHTPINK,
 };
+const GPU_TAG_BRUSH_CONIC_GRADIENT: GpuProfileTag = GpuProfileTag {
+    label: "B_ConicGradient",
+    color: debug_colors::HOTPINK,
+};
 const GPU_TAG_BRUSH_YUV_IMAGE: GpuProfileTag = GpuProfileTag {
     label: "B_YuvImage",
     color: debug_colors::DARKGREEN,
 };
+const GPU_TAG_BRUSH_CROSS_FADE: GpuProfileTag = GpuProfileTag {
+    label: "B_CrossFade",
+    color: debug_colors::TEAL,
+};
 const GPU_TAG_BRUSH_MIXBLEND: GpuProfileTag = GpuProfileTag {
     label: "B_MixBlend",
     color: debug_colors::MAGENTA,
 };
+const GPU_TAG_BRUSH_BACKDROP_FILTER: GpuProfileTag = GpuProfileTag {
+    label: "B_BackdropFilter",
+    color: debug_colors::ORCHID,
+};
 const GPU_TAG_BRUSH_BLEND: GpuProfileTag = GpuProfileTag {
     label: "B_Blend",
     color: debug_colors::ORANGE,
@@ -192,6 +239,10 @@ const GPU_TAG_SCALE: GpuProfileTag = GpuProfileTag {
     label: "Scale",
     color: debug_colors::GHOSTWHITE,
 };
+const GPU_TAG_SVG_FILTER: GpuProfileTag = GpuProfileTag {
+    label: "SvgFilter",
+    color: debug_colors::LEMONCHIFFON,
+};
 
 const GPU_SAMPLER_TAG_ALPHA: GpuProfileTag = GpuProfileTag {
     label: "Alpha Targets",
@@ -212,7 +263,6 @@ const GPU_SAMPLER_TAG_TRANSPARENT: GpuProfileTag = GpuProfileTag {
 const TEXTURE_CACHE_DBG_CLEAR_COLOR: [f32; 4] = [0.0, 0.0, 0.8, 1.0];
 
 impl BatchKind {
-    #[cfg(feature = "debugger")]
     fn debug_name(&self) -> &'static str {
         match *self {
             BatchKind::SplitComposite => "SplitComposite",
@@ -220,10 +270,14 @@ impl BatchKind {
                 match kind {
                     BrushBatchKind::Solid => "Brush (Solid)",
                     BrushBatchKind::Image(..) => "Brush (Image)",
+                    BrushBatchKind::AnisotropicImage(..) => "Brush (AnisotropicImage)",
                     BrushBatchKind::Blend => "Brush (Blend)",
                     BrushBatchKind::MixBlend { .. } => "Brush (Composite)",
+                    BrushBatchKind::BackdropFilter { .. } => "Brush (BackdropFilter)",
                     BrushBatchKind::YuvImage(..) => "Brush (YuvImage)",
+                    BrushBatchKind::CrossFade(..) => "Brush (CrossFade)",
                     BrushBatchKind::RadialGradient => "Brush (RadialGradient)",
+                    BrushBatchKind::ConicGradient => "Brush (ConicGradient)",
                     BrushBatchKind::LinearGradient => "Brush (LinearGradient)",
                 }
             }
@@ -231,17 +285,29 @@ impl BatchKind {
         }
     }
 
+    #[cfg(not(feature = "gleam"))]
+    fn uses_anisotropic_sampling(&self) -> bool {
+        match *self {
+            BatchKind::Brush(BrushBatchKind::AnisotropicImage(..)) => true,
+            _ => false,
+        }
+    }
+
     fn sampler_tag(&self) -> GpuProfileTag {
         match *self {
             BatchKind::SplitComposite => GPU_TAG_PRIM_SPLIT_COMPOSITE,
             BatchKind::Brush(kind) => {
                 match kind {
                     BrushBatchKind::Solid => GPU_TAG_BRUSH_SOLID,
-                    BrushBatchKind::Image(..) => GPU_TAG_BRUSH_IMAGE,
+                    BrushBatchKind::Image(..) |
+                    BrushBatchKind::AnisotropicImage(..) => GPU_TAG_BRUSH_IMAGE,
                     BrushBatchKind::Blend => GPU_TAG_BRUSH_BLEND,
                     BrushBatchKind::MixBlend { .. } => GPU_TAG_BRUSH_MIXBLEND,
+                    BrushBatchKind::BackdropFilter { .. } => GPU_TAG_BRUSH_BACKDROP_FILTER,
                     BrushBatchKind::YuvImage(..) => GPU_TAG_BRUSH_YUV_IMAGE,
+                    BrushBatchKind::CrossFade(..) => GPU_TAG_BRUSH_CROSS_FADE,
                     BrushBatchKind::RadialGradient => GPU_TAG_BRUSH_RADIAL_GRADIENT,
+                    BrushBatchKind::ConicGradient => GPU_TAG_BRUSH_CONIC_GRADIENT,
                     BrushBatchKind::LinearGradient => GPU_TAG_BRUSH_LINEAR_GRADIENT,
                 }
             }
@@ -258,6 +324,43 @@ fn flag_changed(before: DebugFlags, after: DebugFlags, select: DebugFlags) -> Op
     }
 }
 
+/// Moves the profiler drawn into `debug_renderer` since `mark` to the corner
+/// of the framebuffer selected by `overlay`, keeping its margin to the
+/// top-left corner as the margin to that corner.
+fn place_profiler_overlay(
+    debug_renderer: &mut DebugRenderer,
+    mark: DebugRendererMark,
+    overlay: &ProfilerOverlay,
+    framebuffer_size: DeviceIntSize,
+) {
+    let bounds = match debug_renderer.bounds_since(mark) {
+        Some(bounds) => bounds,
+        None => return,
+    };
+    let margin = bounds.origin.to_vector() * overlay.scale;
+    let size = bounds.size * overlay.scale;
+    let framebuffer_size = framebuffer_size.to_f32();
+    let x = match overlay.corner {
+        ProfilerOverlayCorner::TopLeft | ProfilerOverlayCorner::BottomLeft => margin.x,
+        ProfilerOverlayCorner::TopRight | ProfilerOverlayCorner::BottomRight => {
+            framebuffer_size.width - margin.x - size.width
+        }
+    };
+    let y = match overlay.corner {
+        ProfilerOverlayCorner::TopLeft | ProfilerOverlayCorner::TopRight => margin.y,
+        ProfilerOverlayCorner::BottomLeft | ProfilerOverlayCorner::BottomRight => {
+            framebuffer_size.height - margin.y - size.height
+        }
+    };
+    debug_renderer.transform_since(
+        mark,
+        bounds.origin,
+        DevicePoint::new(x, y),
+        overlay.scale,
+        overlay.opacity,
+    );
+}
+
 #[repr(C)]
 #[derive(Copy, Clone, Debug)]
 pub enum ShaderColorMode {
@@ -421,6 +524,11 @@ struct TextureResolver<B: hal::Backend> {
     /// pass.
     dummy_cache_texture: Texture,
 
+    /// The A8 counterpart of `dummy_cache_texture`, bound in place of the
+    /// alpha output of the previous pass, so that the sampler always sees
+    /// the format it expects.
+    dummy_alpha_texture: Texture,
+
     /// The outputs of the previous pass, if applicable.
     prev_pass_color: Option<ActiveTexture>,
     prev_pass_alpha: Option<ActiveTexture>,
@@ -461,11 +569,22 @@ impl<B: hal::Backend> TextureResolver<B> {
                 None,
                 1,
             );
+        let dummy_alpha_texture = device
+            .create_texture(
+                TextureTarget::Array,
+                ImageFormat::R8,
+                1,
+                1,
+                TextureFilter::Linear,
+                None,
+                1,
+            );
 
         TextureResolver {
             texture_cache_map: FastHashMap::default(),
             external_images: FastHashMap::default(),
             dummy_cache_texture,
+            dummy_alpha_texture,
             prev_pass_alpha: None,
             prev_pass_color: None,
             saved_targets: Vec::default(),
@@ -476,6 +595,7 @@ impl<B: hal::Backend> TextureResolver<B> {
 
     fn deinit(self, device: &mut Device<B>) {
         device.delete_texture(self.dummy_cache_texture);
+        device.delete_texture(self.dummy_alpha_texture);
 
         for (_id, texture) in self.texture_cache_map {
             device.delete_texture(texture);
@@ -524,8 +644,12 @@ impl<B: hal::Backend> TextureResolver<B> {
     }
 
     /// Transfers ownership of a render target back to the pool.
-    fn return_to_pool(&mut self, device: &mut Device<B>, target: Texture) {
+    ///
+    /// The depth target, if any, goes back to the depth target pool right away,
+    /// and is attached again when the target gets reused for a pass with depth.
+    fn return_to_pool(&mut self, device: &mut Device<B>, mut target: Texture) {
         device.invalidate_render_target(&target);
+        device.detach_depth_target(&mut target);
         self.render_target_pool.push(target);
     }
 
@@ -586,7 +710,7 @@ impl<B: hal::Backend> TextureResolver<B> {
             TextureSource::PrevPassAlpha => {
                 let texture = match self.prev_pass_alpha {
                     Some(ref at) => &at.texture,
-                    None => &self.dummy_cache_texture,
+                    None => &self.dummy_alpha_texture,
                 };
                 device.bind_texture(sampler, texture);
             }
@@ -628,7 +752,7 @@ impl<B: hal::Backend> TextureResolver<B> {
             TextureSource::PrevPassAlpha => Some(
                 match self.prev_pass_alpha {
                     Some(ref at) => &at.texture,
-                    None => &self.dummy_cache_texture,
+                    None => &self.dummy_alpha_texture,
                 }
             ),
             TextureSource::PrevPassColor => Some(
@@ -656,9 +780,11 @@ impl<B: hal::Backend> TextureResolver<B> {
         // use size_of_op.
         for t in self.texture_cache_map.values() {
             report.texture_cache_textures += t.size_in_bytes();
+            report.texture_formats.add(t.get_format(), t.size_in_bytes());
         }
         for t in self.render_target_pool.iter() {
             report.render_target_textures += t.size_in_bytes();
+            report.texture_formats.add(t.get_format(), t.size_in_bytes());
         }
 
         report
@@ -696,6 +822,234 @@ impl CacheRow {
     }
 }
 
+/// The CPU side of the GPU cache updates.
+///
+/// Update lists are applied on the worker pool as they arrive from the
+/// render backend: blocks are copied into the row mirror, and the dirty rows
+/// or the runs of scattered blocks are coalesced into upload regions, already
+/// converted to the texture format. This leaves only the device copy to the
+/// renderer when it prepares the frame.
+struct GpuCacheStaging {
+    /// Update lists waiting to be applied, in arrival order.
+    pending: Mutex<Vec<GpuCacheUpdateList>>,
+    /// The applied updates. Lists are only taken out of `pending` while
+    /// holding this lock, which keeps them applied in order.
+    data: Mutex<GpuCacheStagingData>,
+}
+
+struct GpuCacheStagingData {
+    /// Per-row data, if updates are uploaded by row.
+    rows: Option<Vec<CacheRow>>,
+    /// True if `regions` and `blocks` have to be gathered again from the
+    /// dirty rows.
+    rows_changed: bool,
+    /// The texels to upload, covering either runs of updated blocks or
+    /// ranges of dirty rows.
+    regions: Vec<DeviceIntRect>,
+    /// The blocks to upload, in the order of `regions`.
+    blocks: Vec<GpuBlockData>,
+    /// The height requested by the update lists since the last flush.
+    max_height: i32,
+    /// The most recent frame of the update lists since the last flush.
+    frame_id: FrameId,
+    updated_blocks: usize,
+    updated_runs: usize,
+    requested_rows: usize,
+    coalesced_rows: usize,
+    /// Time spent applying update lists since the last flush, in
+    /// nanoseconds.
+    update_time: u64,
+}
+
+impl GpuCacheStaging {
+    fn new(use_rows: bool) -> Self {
+        GpuCacheStaging {
+            pending: Mutex::new(Vec::new()),
+            data: Mutex::new(GpuCacheStagingData {
+                rows: if use_rows { Some(Vec::new()) } else { None },
+                rows_changed: false,
+                regions: Vec::new(),
+                blocks: Vec::new(),
+                max_height: 0,
+                frame_id: FrameId::INVALID,
+                updated_blocks: 0,
+                updated_runs: 0,
+                requested_rows: 0,
+                coalesced_rows: 0,
+                update_time: 0,
+            }),
+        }
+    }
+
+    /// Applies the pending update lists, and returns the staged updates.
+    fn apply_pending(&self) -> MutexGuard<GpuCacheStagingData> {
+        let mut data = self.data.lock().unwrap();
+        let lists = mem::replace(&mut *self.pending.lock().unwrap(), Vec::new());
+        if !lists.is_empty() {
+            let start_time = precise_time_ns();
+            for list in &lists {
+                data.apply(list);
+            }
+            data.gather();
+            data.update_time += precise_time_ns() - start_time;
+        }
+        data
+    }
+
+    /// Returns the heap size of the rows applied so far, as measured by
+    /// `size_of_blocks` for each row. Unlike `apply_pending`, this leaves
+    /// the pending update lists alone.
+    fn size_of_rows<F>(&self, size_of_blocks: F) -> usize
+    where
+        F: Fn(*const GpuBlockData) -> usize,
+    {
+        match self.data.lock().unwrap().rows {
+            Some(ref rows) => rows
+                .iter()
+                .map(|row| size_of_blocks(row.cpu_blocks.as_ptr()))
+                .sum(),
+            None => 0,
+        }
+    }
+}
+
+impl GpuCacheStagingData {
+    fn apply(&mut self, updates: &GpuCacheUpdateList) {
+        if updates.clear {
+            // The texture is recreated, so earlier updates don't matter.
+            if let Some(ref mut rows) = self.rows {
+                rows.clear();
+            }
+            self.regions.clear();
+            self.blocks.clear();
+            self.max_height = 0;
+        }
+        self.max_height = cmp::max(self.max_height, updates.height);
+        self.frame_id = cmp::max(self.frame_id, updates.frame_id);
+        self.updated_blocks += updates.blocks.len();
+        self.updated_runs += updates.updates.len();
+        self.requested_rows += updates.requested_rows;
+        self.coalesced_rows += updates.coalesced_rows;
+
+        match self.rows {
+            Some(ref mut rows) => {
+                for update in &updates.updates {
+                    match *update {
+                        GpuCacheUpdate::Copy {
+                            block_index,
+                            block_count,
+                            address,
+                        } => {
+                            let row = address.v as usize;
+
+                            // Ensure that the CPU-side shadow copy of the GPU cache data has enough
+                            // rows to apply this patch.
+                            while rows.len() <= row {
+                                // Add a new row.
+                                rows.push(CacheRow::new());
+                            }
+
+                            // This row is dirty (needs to be updated in GPU texture).
+                            rows[row].is_dirty = true;
+
+                            // Copy the blocks from the patch array in the shadow CPU copy.
+                            let block_offset = address.u as usize;
+                            let data = &mut rows[row].cpu_blocks;
+                            for i in 0 .. block_count {
+                                data[block_offset + i] = updates.blocks[block_index + i];
+                            }
+                        }
+                    }
+                }
+                self.rows_changed = true;
+            }
+            None => {
+                for update in &updates.updates {
+                    match *update {
+                        GpuCacheUpdate::Copy {
+                            block_index,
+                            block_count,
+                            address,
+                        } => {
+                            if block_count == 0 {
+                                continue;
+                            }
+                            self.blocks.extend_from_slice(
+                                &updates.blocks[block_index .. block_index + block_count]
+                            );
+
+                            // Extend the previous region if this run directly
+                            // follows it on the same row.
+                            let origin = DeviceIntPoint::new(address.u as i32, address.v as i32);
+                            if let Some(last) = self.regions.last_mut() {
+                                if last.origin.y == origin.y &&
+                                    last.origin.x + last.size.width == origin.x {
+                                    last.size.width += block_count as i32;
+                                    continue;
+                                }
+                            }
+                            self.regions.push(DeviceIntRect::new(
+                                origin,
+                                DeviceIntSize::new(block_count as i32, 1),
+                            ));
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    /// Brings `regions` and `blocks` up to date with the applied updates.
+    fn gather(&mut self) {
+        if let Some(ref rows) = self.rows {
+            if self.rows_changed {
+                self.regions.clear();
+                self.blocks.clear();
+                for (row_index, row) in rows.iter().enumerate() {
+                    if !row.is_dirty {
+                        continue;
+                    }
+                    // Consecutive dirty rows are uploaded as a single region.
+                    let origin = DeviceIntPoint::new(0, row_index as i32);
+                    let extends_last = self.regions.last().map_or(false, |last| {
+                        last.origin.y + last.size.height == origin.y
+                    });
+                    if extends_last {
+                        self.regions.last_mut().unwrap().size.height += 1;
+                    } else {
+                        self.regions.push(DeviceIntRect::new(
+                            origin,
+                            DeviceIntSize::new(MAX_VERTEX_TEXTURE_WIDTH as i32, 1),
+                        ));
+                    }
+                    self.blocks.extend_from_slice(&*row.cpu_blocks);
+                }
+                self.rows_changed = false;
+            }
+        }
+    }
+
+    /// Marks the staged updates as uploaded, and returns the number of
+    /// uploaded rows.
+    fn finish_upload(&mut self) -> usize {
+        let mut updated_rows = 0;
+        if let Some(ref mut rows) = self.rows {
+            for row in rows.iter_mut().filter(|row| row.is_dirty) {
+                row.is_dirty = false;
+                updated_rows += 1;
+            }
+        }
+        self.regions.clear();
+        self.blocks.clear();
+        self.max_height = 0;
+        self.updated_blocks = 0;
+        self.updated_runs = 0;
+        self.requested_rows = 0;
+        self.coalesced_rows = 0;
+        updated_rows
+    }
+}
+
 /// The bus over which CPU and GPU versions of the GPU cache
 /// get synchronized.
 enum GpuCacheBus {
@@ -704,8 +1058,6 @@ enum GpuCacheBus {
     PixelBuffer {
         /// PBO used for transfers.
         buffer: PBO,
-        /// Per-row data.
-        rows: Vec<CacheRow>,
     },
     /// Shader-based scattering updates. Currently rendered by a set
     /// of points into the GPU texture, each carrying a `GpuBlockData`.
@@ -719,15 +1071,20 @@ enum GpuCacheBus {
         buf_position: VBO<[u16; 2]>,
         /// VBO for gpu block data.
         buf_value: VBO<GpuBlockData>,
-        /// Currently stored block count.
-        count: usize,
     },
+    /// Scattered updates. Without a point rendering pipeline, every run of
+    /// consecutive blocks becomes a copy region, and all the regions are
+    /// uploaded with a single transfer, avoiding full-row uploads of sparse
+    /// updates.
+    #[cfg(not(feature = "gleam"))]
+    Scatter,
 }
 
 /// The device-specific representation of the cache texture in gpu_cache.rs
 struct GpuCacheTexture<B: hal::Backend> {
     texture: Option<Texture>,
     bus: GpuCacheBus,
+    staging: Arc<GpuCacheStaging>,
     phantom_data: PhantomData<B>,
 }
 
@@ -775,9 +1132,6 @@ impl<B: hal::Backend> GpuCacheTexture<B> {
     }
 
     fn new(device: &mut Device<B>, use_scatter: bool) -> Result<Self, RendererError> {
-        if use_scatter && cfg!(not(feature = "gleam")) {
-            warn!("GpuCacheBus::Scatter is not supported with gfx backend");
-        }
         let bus;
         #[cfg(feature = "gleam")]
         {
@@ -800,28 +1154,30 @@ impl<B: hal::Backend> GpuCacheTexture<B> {
                     vao,
                     buf_position,
                     buf_value,
-                    count: 0,
                 }
             } else {
                 let buffer = device.create_pbo();
                 bus = GpuCacheBus::PixelBuffer {
                     buffer,
-                    rows: Vec::new(),
                 }
             }
         }
         #[cfg(not(feature = "gleam"))]
         {
-            let buffer = device.create_pbo();
-            bus = GpuCacheBus::PixelBuffer {
-                buffer,
-                rows: Vec::new(),
+            if use_scatter {
+                bus = GpuCacheBus::Scatter;
+            } else {
+                let buffer = device.create_pbo();
+                bus = GpuCacheBus::PixelBuffer {
+                    buffer,
+                }
             }
         };
 
         Ok(GpuCacheTexture {
             texture: None,
             bus,
+            staging: Arc::new(GpuCacheStaging::new(!use_scatter)),
             phantom_data: PhantomData,
         })
     }
@@ -841,6 +1197,8 @@ impl<B: hal::Backend> GpuCacheTexture<B> {
                 device.delete_vbo(buf_position);
                 device.delete_vbo(buf_value);
             }
+            #[cfg(not(feature = "gleam"))]
+            GpuCacheBus::Scatter { .. } => {}
         }
     }
 
@@ -848,155 +1206,138 @@ impl<B: hal::Backend> GpuCacheTexture<B> {
         self.texture.as_ref().map_or(0, |t| t.get_dimensions().height)
     }
 
-    fn prepare_for_updates(
-        &mut self,
-        device: &mut Device<B>,
-        _total_block_count: usize,
-        max_height: i32,
-    ) {
-        self.ensure_texture(device, max_height);
-        match self.bus {
-            GpuCacheBus::PixelBuffer { .. } => {},
-            #[cfg(feature = "gleam")]
-            GpuCacheBus::Scatter {
-                ref mut buf_position,
-                ref mut buf_value,
-                ref mut count,
-                ..
-            } => {
-                *count = 0;
-                if _total_block_count > buf_value.allocated_count() {
-                    device.allocate_vbo(buf_position, _total_block_count, VertexUsageHint::Stream);
-                    device.allocate_vbo(buf_value,    _total_block_count, VertexUsageHint::Stream);
-                }
-            }
-        }
+    /// Queues an update list, and schedules its application on the worker
+    /// pool.
+    fn queue_update(&self, updates: GpuCacheUpdateList, workers: &ThreadPool) {
+        self.staging.pending.lock().unwrap().push(updates);
+        let staging = Arc::clone(&self.staging);
+        workers.spawn(move || {
+            staging.apply_pending();
+        });
     }
 
-    fn update(&mut self, _device: &mut Device<B>, updates: &GpuCacheUpdateList) {
-        match self.bus {
-            GpuCacheBus::PixelBuffer { ref mut rows, .. } => {
-                for update in &updates.updates {
-                    match *update {
-                        GpuCacheUpdate::Copy {
-                            block_index,
-                            block_count,
-                            address,
-                        } => {
-                            let row = address.v as usize;
+    /// Returns true if there are updates that haven't been uploaded yet.
+    fn has_pending_updates(&self) -> bool {
+        if !self.staging.pending.lock().unwrap().is_empty() {
+            return true;
+        }
+        // A worker holding the lock is still applying updates.
+        match self.staging.data.try_lock() {
+            Ok(data) => !data.regions.is_empty(),
+            Err(_) => true,
+        }
+    }
 
-                            // Ensure that the CPU-side shadow copy of the GPU cache data has enough
-                            // rows to apply this patch.
-                            while rows.len() <= row {
-                                // Add a new row.
-                                rows.push(CacheRow::new());
-                            }
+    /// Applies the update lists the worker pool hasn't picked up yet, and
+    /// copies the staged updates to the texture.
+    fn flush(&mut self, device: &mut Device<B>) -> GpuCacheUploadProfile {
+        let staging = Arc::clone(&self.staging);
+        let mut staged = staging.apply_pending();
 
-                            // This row is dirty (needs to be updated in GPU texture).
-                            rows[row].is_dirty = true;
+        let height = cmp::max(self.get_height(), staged.max_height);
+        self.ensure_texture(device, height);
 
-                            // Copy the blocks from the patch array in the shadow CPU copy.
-                            let block_offset = address.u as usize;
-                            let data = &mut rows[row].cpu_blocks;
-                            for i in 0 .. block_count {
-                                data[block_offset + i] = updates.blocks[block_index + i];
-                            }
-                        }
-                    }
+        let start_time = precise_time_ns();
+        let texture = self.texture.as_ref().unwrap();
+        match self.bus {
+            GpuCacheBus::PixelBuffer { ref buffer } => {
+                if !staged.regions.is_empty() {
+                    upload_gpu_cache_rows(device, texture, buffer, &staged.regions, &staged.blocks);
                 }
             }
             #[cfg(feature = "gleam")]
-            GpuCacheBus::Scatter {
-                ref buf_position,
-                ref buf_value,
-                ref mut count,
-                ..
-            } => {
-                //TODO: re-use this heap allocation
-                // Unused positions will be left as 0xFFFF, which translates to
-                // (1.0, 1.0) in the vertex output position and gets culled out
-                let mut position_data = vec![[!0u16; 2]; updates.blocks.len()];
-                let size = self.texture.as_ref().unwrap().get_dimensions().to_usize();
-
-                for update in &updates.updates {
-                    match *update {
-                        GpuCacheUpdate::Copy {
-                            block_index,
-                            block_count,
-                            address,
-                        } => {
-                            // Convert the absolute texel position into normalized
-                            let y = ((2*address.v as usize + 1) << 15) / size.height;
-                            for i in 0 .. block_count {
-                                let x = ((2*address.u as usize + 2*i + 1) << 15) / size.width;
-                                position_data[block_index + i] = [x as _, y as _];
-                            }
+            GpuCacheBus::Scatter { ref program, ref vao, ref mut buf_position, ref mut buf_value } => {
+                if !staged.regions.is_empty() {
+                    // Convert the absolute texel positions into normalized
+                    // coordinates.
+                    let size = texture.get_dimensions().to_usize();
+                    let mut position_data = Vec::with_capacity(staged.blocks.len());
+                    for region in &staged.regions {
+                        let y = ((2 * region.origin.y as usize + 1) << 15) / size.height;
+                        for i in 0 .. region.size.width as usize {
+                            let x = ((2 * region.origin.x as usize + 2 * i + 1) << 15) / size.width;
+                            position_data.push([x as u16, y as u16]);
                         }
                     }
-                }
 
-                _device.fill_vbo(buf_value, &updates.blocks, *count);
-                _device.fill_vbo(buf_position, &position_data, *count);
-                *count += position_data.len();
-            }
-        }
-    }
-
-    fn flush(&mut self, device: &mut Device<B>) -> usize {
-        let texture = self.texture.as_ref().unwrap();
-        match self.bus {
-            GpuCacheBus::PixelBuffer { ref buffer, ref mut rows } => {
-                let rows_dirty = rows
-                    .iter()
-                    .filter(|row| row.is_dirty)
-                    .count();
-                if rows_dirty == 0 {
-                    return 0
-                }
-
-                let mut uploader = device.upload_texture(
-                    texture,
-                    buffer,
-                    rows_dirty * MAX_VERTEX_TEXTURE_WIDTH,
-                );
-
-                for (row_index, row) in rows.iter_mut().enumerate() {
-                    if !row.is_dirty {
-                        continue;
+                    if staged.blocks.len() > buf_value.allocated_count() {
+                        device.allocate_vbo(buf_position, staged.blocks.len(), VertexUsageHint::Stream);
+                        device.allocate_vbo(buf_value, staged.blocks.len(), VertexUsageHint::Stream);
                     }
-
-                    let rect = DeviceIntRect::new(
-                        DeviceIntPoint::new(0, row_index as i32),
-                        DeviceIntSize::new(MAX_VERTEX_TEXTURE_WIDTH as i32, 1),
+                    device.fill_vbo(buf_value, &staged.blocks, 0);
+                    device.fill_vbo(buf_position, &position_data, 0);
+
+                    device.disable_depth();
+                    device.set_blend(false);
+                    device.bind_program(program);
+                    device.bind_custom_vao(vao);
+                    device.bind_draw_target(
+                        DrawTarget::Texture {
+                            texture,
+                            layer: 0,
+                            with_depth: false,
+                        },
                     );
-
-                    uploader.upload(rect, 0, None, &*row.cpu_blocks);
-
-                    row.is_dirty = false;
+                    device.draw_nonindexed_points(0, position_data.len() as _);
                 }
-
-                rows_dirty
             }
-            #[cfg(feature = "gleam")]
-            GpuCacheBus::Scatter { ref program, ref vao, count, .. } => {
-                device.disable_depth();
-                device.set_blend(false);
-                device.bind_program(program);
-                device.bind_custom_vao(vao);
-                device.bind_draw_target(
-                    DrawTarget::Texture {
-                        texture,
-                        layer: 0,
-                        with_depth: false,
-                    },
-                );
-                device.draw_nonindexed_points(0, count as _);
-                0
+            #[cfg(not(feature = "gleam"))]
+            GpuCacheBus::Scatter => {
+                device.upload_texture_regions(texture, &staged.regions, &staged.blocks);
             }
         }
+        let upload_time = precise_time_ns() - start_time;
+
+        GpuCacheUploadProfile {
+            frame_id: staged.frame_id,
+            height,
+            updated_blocks: staged.updated_blocks,
+            updated_runs: staged.updated_runs,
+            requested_rows: staged.requested_rows,
+            coalesced_rows: staged.coalesced_rows,
+            update_time: mem::replace(&mut staged.update_time, 0),
+            upload_time,
+            updated_rows: staged.finish_upload(),
+        }
+    }
+}
+
+/// Uploads the coalesced dirty rows of the GPU cache.
+fn upload_gpu_cache_rows<B: hal::Backend>(
+    device: &mut Device<B>,
+    texture: &Texture,
+    buffer: &PBO,
+    regions: &[DeviceIntRect],
+    blocks: &[GpuBlockData],
+) {
+    let mut uploader = device.upload_texture(texture, buffer, blocks.len());
+    let mut offset = 0;
+    for rect in regions {
+        let count = rect.size.width as usize * rect.size.height as usize;
+        uploader.upload(*rect, 0, None, &blocks[offset .. offset + count]);
+        offset += count;
     }
 }
 
+/// The outcome of a GPU cache flush.
+struct GpuCacheUploadProfile {
+    /// The most recent frame of the uploaded update lists.
+    frame_id: FrameId,
+    /// The height of the texture the updates were uploaded to.
+    height: i32,
+    updated_rows: usize,
+    updated_blocks: usize,
+    updated_runs: usize,
+    /// The rows the updates moved through before and after coalescing.
+    requested_rows: usize,
+    coalesced_rows: usize,
+    /// Time spent applying update lists on the renderer thread and on the
+    /// worker pool, in nanoseconds.
+    update_time: u64,
+    /// Time spent copying the updates to the texture, in nanoseconds.
+    upload_time: u64,
+}
+
 struct VertexDataTexture<B: hal::Backend> {
     texture: Option<Texture>,
     format: ImageFormat,
@@ -1018,9 +1359,12 @@ impl<B: hal::Backend> VertexDataTexture<B> {
         self.texture.as_ref().unwrap()
     }
 
-    /// Returns an estimate of the GPU memory consumed by this VertexDataTexture.
-    fn size_in_bytes(&self) -> usize {
-        self.texture.as_ref().map_or(0, |t| t.size_in_bytes())
+    /// Adds an estimate of the GPU memory consumed by this VertexDataTexture
+    /// to the report.
+    fn report_memory(&self, report: &mut MemoryReport) {
+        let size = self.texture.as_ref().map_or(0, |t| t.size_in_bytes());
+        report.vertex_data_textures += size;
+        report.texture_formats.add(self.format, size);
     }
 
     fn update<T>(&mut self, device: &mut Device<B>, data: &mut Vec<T>) {
@@ -1111,25 +1455,42 @@ struct TargetSelector {
 struct LazyInitializedDebugRenderer<B: hal::Backend> {
     debug_renderer: Option<DebugRenderer>,
     failed: bool,
+    text_scale: f32,
+    text_color: Option<ColorU>,
     phantom_data: PhantomData<B>,
 }
 
 impl<B: hal::Backend> LazyInitializedDebugRenderer<B> {
-    pub fn new() -> Self {
+    pub fn new(text_scale: f32, text_color: Option<ColorU>) -> Self {
         Self {
             debug_renderer: None,
             failed: false,
+            text_scale,
+            text_color,
             phantom_data: PhantomData,
         }
     }
 
+    fn set_text_options(&mut self, scale: f32, color: Option<ColorU>) {
+        self.text_scale = scale;
+        self.text_color = color;
+        if let Some(ref mut debug_renderer) = self.debug_renderer {
+            debug_renderer.set_text_scale(scale);
+            debug_renderer.set_text_color(color);
+        }
+    }
+
     pub fn get_mut<'a>(&'a mut self, device: &mut Device<B>) -> Option<&'a mut DebugRenderer> {
         if self.failed {
             return None;
         }
         if self.debug_renderer.is_none() {
             match DebugRenderer::new(device) {
-                Ok(renderer) => { self.debug_renderer = Some(renderer); }
+                Ok(mut renderer) => {
+                    renderer.set_text_scale(self.text_scale);
+                    renderer.set_text_color(self.text_color);
+                    self.debug_renderer = Some(renderer);
+                }
                 Err(_) => {
                     // The shader compilation code already logs errors.
                     self.failed = true;
@@ -1166,6 +1527,7 @@ pub struct RendererVAOs {
     border_vao: VAO,
     line_vao: VAO,
     scale_vao: VAO,
+    svg_filter_vao: VAO,
 }
 
 /// The renderer is responsible for submitting to the GPU the work prepared by the
@@ -1178,12 +1540,33 @@ pub struct Renderer<B: hal::Backend> {
     debug_server: DebugServer,
     pub device: Device<B>,
     pending_texture_updates: Vec<TextureUpdateList>,
-    pending_gpu_cache_updates: Vec<GpuCacheUpdateList>,
     pending_gpu_cache_clear: bool,
     pending_shader_updates: Vec<PathBuf>,
     active_documents: Vec<(DocumentId, RenderedDocument)>,
+    /// Whether something changed since the last `render` that has to be
+    /// composited. See `needs_render`.
+    content_changed: bool,
 
     shaders: Rc<RefCell<Shaders<B>>>,
+    /// Whether shaders are still being built between frames.
+    shader_precache_pending: bool,
+    shader_precache_callback: Option<Box<Fn()>>,
+    /// Presents the picture cache tiles of each frame.
+    compositor: Box<Compositor>,
+    #[cfg(not(feature = "gleam"))]
+    precache_flags: ShaderPrecacheFlags,
+    /// Whether validation messages of the backend are counted.
+    #[cfg(not(feature = "gleam"))]
+    gpu_validation: bool,
+    /// The number of validation errors reported during the last frame.
+    #[cfg(not(feature = "gleam"))]
+    gpu_validation_errors: usize,
+
+    pass_budgets: PassBudgetTracker,
+    pass_budget_alerts: Vec<PassBudgetAlert>,
+
+    /// Called with the timings of every frame rendered to the main framebuffer.
+    frame_pacing_hook: Option<Box<FnMut(FramePacingInfo)>>,
 
     pub gpu_glyph_renderer: GpuGlyphRenderer<B>,
 
@@ -1193,11 +1576,23 @@ pub struct Renderer<B: hal::Backend> {
     enable_clear_scissor: bool,
     debug: LazyInitializedDebugRenderer<B>,
     debug_flags: DebugFlags,
+    batching_parameters: BatchingParameters,
     backend_profile_counters: BackendProfileCounters,
     profile_counters: RendererProfileCounters,
     resource_upload_time: u64,
+    gpu_cache_update_time: u64,
     gpu_cache_upload_time: u64,
     profiler: Profiler,
+    profiler_overlay: ProfilerOverlay,
+    /// The position magnified by the `ZOOM_DBG` overlay.
+    debug_cursor: Option<DeviceIntPoint>,
+    /// Holds the region around `debug_cursor` while it's magnified.
+    zoom_debug_texture: Option<Texture>,
+    options_dump: OptionsDump,
+    /// The most recent errors, for `DebugCommand::DumpEnvironment`.
+    recent_errors: VecDeque<String>,
+    /// The trace started with `start_trace`, if any.
+    trace: Option<ChromeTrace>,
     new_frame_indicator: ChangeIndicator,
     new_scene_indicator: ChangeIndicator,
     slow_frame_indicator: ChangeIndicator,
@@ -1212,6 +1607,8 @@ pub struct Renderer<B: hal::Backend> {
     transforms_texture: VertexDataTexture<B>,
     render_task_texture: VertexDataTexture<B>,
     gpu_cache_texture: GpuCacheTexture<B>,
+    /// The worker pool, which also applies GPU cache updates.
+    workers: Arc<ThreadPool>,
 
     /// When the GPU cache debugger is enabled, we keep track of the live blocks
     /// in the GPU cache so that we can use them for the debug display. This
@@ -1234,6 +1631,9 @@ pub struct Renderer<B: hal::Backend> {
     /// Optional trait object that allows the client
     /// application to provide external buffers for image data.
     external_image_handler: Option<Box<ExternalImageHandler>>,
+    /// The external images resolved for the last rendered frame, with the
+    /// timestamps they were locked with.
+    external_image_stamps: FastHashMap<(ExternalImageId, u8, GpuCacheAddress), ExternalImageStamp>,
 
     /// Optional trait object that allows the client
     /// application to provide a texture handle to
@@ -1259,10 +1659,68 @@ pub struct Renderer<B: hal::Backend> {
 
     framebuffer_size: Option<DeviceIntSize>,
 
+    /// Whether rendering is stopped until `resume`.
+    #[cfg(not(feature = "gleam"))]
+    suspended: bool,
+    /// Tells the render backend about suspension.
+    #[cfg(not(feature = "gleam"))]
+    api_tx: channel::MsgSender<::api::ApiMsg>,
+
+    /// The area of the framebuffer that changed in the last rendered frame,
+    /// None if all of it did.
+    dirty_rect: Option<DeviceIntRect>,
+
+    /// Whether to record the device commands of the next rendered frame.
+    #[cfg(not(feature = "gleam"))]
+    record_next_frame: bool,
+    #[cfg(not(feature = "gleam"))]
+    recorded_frame: Option<CommandStream>,
+
+    /// Whether `render_with_dirty_rects` may redraw only the damaged area.
+    #[cfg(not(feature = "gleam"))]
+    enable_partial_present: bool,
+    /// Set while rendering a frame with `render_with_dirty_rects`.
+    #[cfg(not(feature = "gleam"))]
+    partial_present_requested: bool,
+    /// The area of each swapchain image that changed since it was last
+    /// rendered to, None if all of it did.
+    #[cfg(not(feature = "gleam"))]
+    image_damage: Vec<Option<DeviceIntRect>>,
+    /// The area of the main framebuffer redrawn by the current frame, None
+    /// if it's redrawn entirely.
+    #[cfg(not(feature = "gleam"))]
+    partial_present_rect: Option<DeviceIntRect>,
+    /// Set to compare two rendering paths side by side.
+    #[cfg(not(feature = "gleam"))]
+    split_screen: Option<SplitScreen>,
+
+    /// The pixel to read back once the next frame is presented.
+    scheduled_pixel_read: Option<DeviceIntPoint>,
+    /// The read back of the pixel scheduled by `schedule_read_pixel_at`.
+    pending_pixel_read: Option<ReadbackHandle>,
+
+    /// The read back of the screenshot requested by the debugger.
+    #[cfg(feature = "debugger")]
+    pending_debug_screenshot: Option<(ReadbackHandle, DeviceIntSize)>,
+    /// The uploads of the most recent frames, for the debugger.
+    #[cfg(feature = "debugger")]
+    upload_history: VecDeque<debug_server::FrameUploads>,
+    /// The uploads of the frame being rendered.
+    #[cfg(feature = "debugger")]
+    current_uploads: debug_server::FrameUploads,
+
     #[cfg(feature = "capture")]
     read_fbo: FBOId,
+    /// The capture waiting for the render targets of the next frame, with
+    /// the copies of the alpha and color targets of the passes drawn so far.
+    #[cfg(feature = "capture")]
+    target_capture: Option<(CaptureConfig, Vec<(Option<Texture>, Option<Texture>)>)>,
     #[cfg(feature = "replay")]
     owned_external_images: FastHashMap<(ExternalImageId, u8), ExternalTexture>,
+    /// The render targets loaded from a capture, shown along with the render
+    /// target pool by `DebugFlags::RENDER_TARGET_DBG`.
+    #[cfg(feature = "replay")]
+    captured_targets: Vec<Texture>,
     phantom_data: PhantomData<B>,
 }
 
@@ -1272,6 +1730,18 @@ pub enum RendererError {
     Thread(std::io::Error),
     Resource(ResourceCacheError),
     MaxTextureSize,
+    /// A recorded command stream references device resources that no longer
+    /// exist.
+    InvalidCommandStream,
+    /// A rect that doesn't fit its target, or whose math would overflow.
+    /// The operation using it was skipped.
+    InvalidRect(DeviceIntRect),
+    /// The device can't sample RGBA32F textures, which the GPU cache is
+    /// stored in.
+    UnsupportedGpuCacheFormat,
+    /// The GPU didn't finish its work within the timeout given to
+    /// `Renderer::wait_idle`, and is likely hung.
+    GpuTimeout,
 }
 
 impl From<ShaderError> for RendererError {
@@ -1292,6 +1762,97 @@ impl From<ResourceCacheError> for RendererError {
     }
 }
 
+/// The number of errors kept for `DebugCommand::DumpEnvironment`.
+const RECENT_ERRORS_LENGTH: usize = 16;
+
+/// The `RendererOptions` the renderer was created with, as written by
+/// `DebugCommand::DumpEnvironment`. Handlers and hooks are left out.
+#[derive(Serialize)]
+struct OptionsDump {
+    device_pixel_ratio: f32,
+    resource_override_path: Option<PathBuf>,
+    enable_aa: bool,
+    enable_dithering: bool,
+    enable_subpixel_aa: bool,
+    enable_clear_scissor: bool,
+    max_texture_size: Option<i32>,
+    scatter_gpu_cache_updates: bool,
+    upload_method: String,
+    renderer_kind: String,
+    precache_flags: u32,
+    debug_flags: u32,
+    disable_dual_source_blending: bool,
+    enable_picture_caching: bool,
+    support_low_priority_transactions: bool,
+    atlas_external_images: bool,
+    async_blob_rasterization: bool,
+    workarounds: Option<String>,
+    #[cfg(not(feature = "gleam"))]
+    enable_gpu_validation: bool,
+    #[cfg(not(feature = "gleam"))]
+    present_mode: Option<String>,
+    #[cfg(not(feature = "gleam"))]
+    output_format: String,
+    #[cfg(not(feature = "gleam"))]
+    color_space: String,
+}
+
+impl OptionsDump {
+    fn new(options: &RendererOptions) -> Self {
+        OptionsDump {
+            device_pixel_ratio: options.device_pixel_ratio,
+            resource_override_path: options.resource_override_path.clone(),
+            enable_aa: options.enable_aa,
+            enable_dithering: options.enable_dithering,
+            enable_subpixel_aa: options.enable_subpixel_aa,
+            enable_clear_scissor: options.enable_clear_scissor,
+            max_texture_size: options.max_texture_size,
+            scatter_gpu_cache_updates: options.scatter_gpu_cache_updates,
+            upload_method: format!("{:?}", options.upload_method),
+            renderer_kind: format!("{:?}", options.renderer_kind),
+            precache_flags: options.precache_flags.bits(),
+            debug_flags: options.debug_flags.bits(),
+            disable_dual_source_blending: options.disable_dual_source_blending,
+            enable_picture_caching: options.enable_picture_caching,
+            support_low_priority_transactions: options.support_low_priority_transactions,
+            atlas_external_images: options.atlas_external_images,
+            async_blob_rasterization: options.async_blob_rasterization,
+            workarounds: options.workarounds.map(|workarounds| format!("{:?}", workarounds)),
+            #[cfg(not(feature = "gleam"))]
+            enable_gpu_validation: options.enable_gpu_validation,
+            #[cfg(not(feature = "gleam"))]
+            present_mode: options.present_mode.map(|mode| format!("{:?}", mode)),
+            #[cfg(not(feature = "gleam"))]
+            output_format: format!("{:?}", options.output_format),
+            #[cfg(not(feature = "gleam"))]
+            color_space: format!("{:?}", options.color_space),
+        }
+    }
+}
+
+/// The texture cache configuration, as written by
+/// `DebugCommand::DumpEnvironment`.
+#[derive(Serialize)]
+struct TextureCacheDump {
+    max_texture_size: i32,
+    max_texture_layers: usize,
+    region_size: i32,
+}
+
+/// Everything needed to reproduce backend-specific issues, written as
+/// JSON by `DebugCommand::DumpEnvironment`.
+#[derive(Serialize)]
+struct EnvironmentDump<'a> {
+    adapter: String,
+    features: String,
+    capabilities: String,
+    workarounds: String,
+    options: &'a OptionsDump,
+    shader_digest: String,
+    texture_cache: TextureCacheDump,
+    recent_errors: &'a VecDeque<String>,
+}
+
 impl<B: hal::Backend> Renderer<B> {
     /// Initializes WebRender and creates a `Renderer` and `RenderApiSender`.
     ///
@@ -1325,6 +1886,22 @@ impl<B: hal::Backend> Renderer<B> {
         let gl_type = ();
 
         let debug_server = DebugServer::new(api_tx.clone());
+        let options_dump = OptionsDump::new(&options);
+
+        #[cfg(not(feature = "gleam"))]
+        let init = {
+            let mut init = init;
+            if let Some(ref dir) = options.cached_pipelines_path {
+                init.use_pipeline_cache_dir(dir);
+            }
+            init
+        };
+        #[cfg(not(feature = "gleam"))]
+        {
+            if options.enable_gpu_validation {
+                set_validation_enabled(true);
+            }
+        }
 
         let mut device = Device::new(
             init,
@@ -1333,16 +1910,28 @@ impl<B: hal::Backend> Renderer<B> {
             options.cached_programs.take(),
             #[cfg(not(feature = "gleam"))]
             options.heaps_config,
+            #[cfg(not(feature = "gleam"))]
+            options.present_mode,
+            #[cfg(not(feature = "gleam"))]
+            options.output_format,
+            #[cfg(not(feature = "gleam"))]
+            options.color_space,
+            #[cfg(not(feature = "gleam"))]
+            options.max_anisotropy,
+            #[cfg(not(feature = "gleam"))]
+            options.enable_partial_present,
         );
 
-        #[cfg(feature = "gleam")]
-        let ext_dual_source_blending = !options.disable_dual_source_blending &&
-            device.supports_extension("GL_ARB_blend_func_extended") &&
-            device.supports_extension("GL_ARB_explicit_attrib_location");
+        let workarounds = options.workarounds.unwrap_or_else(|| {
+            Workarounds::detect(device.gpu_vendor(), device.gpu_device_id())
+        });
+        if !workarounds.is_empty() {
+            info!("Enabling driver workarounds: {:?}", workarounds);
+        }
+        device.set_workarounds(workarounds);
 
-        #[cfg(not(feature = "gleam"))]
         let ext_dual_source_blending = !options.disable_dual_source_blending &&
-            device.supports_features(hal::Features::DUAL_SRC_BLENDING);
+            device.supports_dual_source_blending();
 
         // 512 is the minimum that the texture cache can work with.
         const MIN_TEXTURE_SIZE: i32 = 512;
@@ -1361,6 +1950,22 @@ impl<B: hal::Backend> Renderer<B> {
         }
         let max_texture_size = device.max_texture_size();
         let max_texture_layers = device.max_texture_layers();
+        let supported_compressed_formats: Vec<ImageFormat> = [
+            ImageFormat::BC1,
+            ImageFormat::BC3,
+            ImageFormat::BC7,
+            ImageFormat::ETC2,
+            ImageFormat::ASTC4x4,
+        ].iter().cloned().filter(|&format| device.supports_compressed_format(format)).collect();
+
+        // The GPU cache stores rects, UVs and addresses along with colors,
+        // which need the full float precision. There is no fallback encoding,
+        // so fail early instead of rendering garbage. The shaders only fetch
+        // texels, which Vulkan, Metal and D3D12 support for RGBA32F.
+        if !device.get_capabilities().supports_float_textures {
+            error!("Device can't sample float textures");
+            return Err(RendererError::UnsupportedGpuCacheFormat);
+        }
 
         register_thread_with_profiler("Compositor".to_owned());
 
@@ -1371,6 +1976,18 @@ impl<B: hal::Backend> Renderer<B> {
             None => Rc::new(RefCell::new(Shaders::new(&mut device, gl_type, &options)?)),
         };
 
+        // GL builds `ASYNC_COMPILE` shaders right away, see `LazilyCompiledShader::new`.
+        let shader_precache_pending = !cfg!(feature = "gleam") &&
+            options.precache_flags.contains(ShaderPrecacheFlags::ASYNC_COMPILE) &&
+            !options.precache_flags.contains(ShaderPrecacheFlags::FULL_COMPILE);
+        let shader_precache_callback = options.shader_precache_callback.take();
+        let compositor = options.compositor.take().unwrap_or_else(|| Box::new(DrawCompositor));
+        if !shader_precache_pending && !options.precache_flags.is_empty() {
+            if let Some(ref callback) = shader_precache_callback {
+                callback();
+            }
+        }
+
         let backend_profile_counters = BackendProfileCounters::new();
 
         let dither_matrix_texture = if options.enable_dithering {
@@ -1484,6 +2101,7 @@ impl<B: hal::Backend> Renderer<B> {
         let border_vao = device.create_vao_with_new_instances(&desc::BORDER, &prim_vao);
         let scale_vao = device.create_vao_with_new_instances(&desc::SCALE, &prim_vao);
         let line_vao = device.create_vao_with_new_instances(&desc::LINE, &prim_vao);
+        let svg_filter_vao = device.create_vao_with_new_instances(&desc::SVG_FILTER, &prim_vao);
         let texture_cache_upload_pbo = device.create_pbo();
 
         let texture_resolver = TextureResolver::new(&mut device);
@@ -1508,12 +2126,22 @@ impl<B: hal::Backend> Renderer<B> {
             (false, _) => FontRenderMode::Mono,
         };
 
+        let batching_parameters = options.batching_parameters.unwrap_or_else(|| {
+            device.gpu_vendor().default_batching_parameters()
+        });
+
         let config = FrameBuilderConfig {
             default_font_render_mode,
             dual_source_blending_is_enabled: true,
             dual_source_blending_is_supported: ext_dual_source_blending,
             chase_primitive: options.chase_primitive,
             enable_picture_caching: options.enable_picture_caching,
+            lost_pipeline_treatment: options.lost_pipeline_treatment,
+            batching_parameters,
+            max_blur_std_dev: options.max_blur_std_dev,
+            workarounds,
+            native_compositor: compositor.is_native(),
+            anisotropic_filtering: device.supports_anisotropic_filtering(),
         };
 
         let device_pixel_ratio = options.device_pixel_ratio;
@@ -1551,6 +2179,8 @@ impl<B: hal::Backend> Renderer<B> {
         let namespace_alloc_by_client = options.namespace_alloc_by_client;
 
         let blob_image_handler = options.blob_image_handler.take();
+        let atlas_external_images = options.atlas_external_images;
+        let async_blob_rasterization = options.async_blob_rasterization;
         let thread_listener_for_render_backend = thread_listener.clone();
         let thread_listener_for_scene_builder = thread_listener.clone();
         let thread_listener_for_lp_scene_builder = thread_listener.clone();
@@ -1558,7 +2188,7 @@ impl<B: hal::Backend> Renderer<B> {
         let rb_thread_name = format!("WRRenderBackend#{}", options.renderer_id.unwrap_or(0));
         let scene_thread_name = format!("WRSceneBuilder#{}", options.renderer_id.unwrap_or(0));
         let lp_scene_thread_name = format!("WRSceneBuilderLP#{}", options.renderer_id.unwrap_or(0));
-        let glyph_rasterizer = GlyphRasterizer::new(workers)?;
+        let glyph_rasterizer = GlyphRasterizer::new(Arc::clone(&workers))?;
 
         let (scene_builder, scene_tx, scene_rx) = SceneBuilder::new(
             config,
@@ -1622,6 +2252,9 @@ impl<B: hal::Backend> Renderer<B> {
                 texture_cache,
                 glyph_rasterizer,
                 blob_image_handler,
+                atlas_external_images,
+                async_blob_rasterization,
+                supported_compressed_formats,
             );
 
             let mut backend = RenderBackend::new(
@@ -1651,7 +2284,9 @@ impl<B: hal::Backend> Renderer<B> {
             #[cfg(feature = "gleam")]
             Rc::clone(device.rc_gl()),
             #[cfg(feature = "gleam")]
-            device.supports_extension("GL_EXT_debug_marker")
+            device.supports_extension("GL_EXT_debug_marker"),
+            #[cfg(not(feature = "gleam"))]
+            device.gpu_queries(),
         );
 
         #[cfg(feature = "capture")]
@@ -1663,17 +2298,37 @@ impl<B: hal::Backend> Renderer<B> {
             device,
             active_documents: Vec::new(),
             pending_texture_updates: Vec::new(),
-            pending_gpu_cache_updates: Vec::new(),
             pending_gpu_cache_clear: false,
             pending_shader_updates: Vec::new(),
+            content_changed: true,
             shaders,
-            debug: LazyInitializedDebugRenderer::new(),
+            shader_precache_pending,
+            shader_precache_callback,
+            compositor,
+            #[cfg(not(feature = "gleam"))]
+            precache_flags: options.precache_flags,
+            #[cfg(not(feature = "gleam"))]
+            gpu_validation: options.enable_gpu_validation,
+            #[cfg(not(feature = "gleam"))]
+            gpu_validation_errors: 0,
+            pass_budgets: PassBudgetTracker::new(mem::replace(&mut options.pass_budgets, Vec::new())),
+            pass_budget_alerts: Vec::new(),
+            frame_pacing_hook: None,
+            debug: LazyInitializedDebugRenderer::new(options.debug_text_scale, options.debug_text_color),
             debug_flags: DebugFlags::empty(),
+            batching_parameters,
             backend_profile_counters: BackendProfileCounters::new(),
             profile_counters: RendererProfileCounters::new(),
             resource_upload_time: 0,
+            gpu_cache_update_time: 0,
             gpu_cache_upload_time: 0,
             profiler: Profiler::new(),
+            profiler_overlay: ProfilerOverlay::default(),
+            debug_cursor: None,
+            zoom_debug_texture: None,
+            options_dump,
+            recent_errors: VecDeque::new(),
+            trace: None,
             new_frame_indicator: ChangeIndicator::new(),
             new_scene_indicator: ChangeIndicator::new(),
             slow_frame_indicator: ChangeIndicator::new(),
@@ -1690,6 +2345,7 @@ impl<B: hal::Backend> Renderer<B> {
                 border_vao,
                 scale_vao,
                 line_vao,
+                svg_filter_vao,
             },
             transforms_texture,
             prim_header_i_texture,
@@ -1698,24 +2354,57 @@ impl<B: hal::Backend> Renderer<B> {
             pipeline_info: PipelineInfo::default(),
             dither_matrix_texture,
             external_image_handler: None,
+            external_image_stamps: FastHashMap::default(),
             output_image_handler: None,
             size_of_ops: make_size_of_ops(),
             output_targets: FastHashMap::default(),
             cpu_profiles: VecDeque::new(),
             gpu_profiles: VecDeque::new(),
             gpu_cache_texture,
+            workers,
             gpu_cache_debug_chunks: Vec::new(),
             gpu_cache_frame_id: FrameId::INVALID,
             gpu_cache_overflow: false,
             texture_cache_upload_pbo,
             texture_resolver,
             renderer_errors: Vec::new(),
+            scheduled_pixel_read: None,
+            pending_pixel_read: None,
+            #[cfg(feature = "debugger")]
+            pending_debug_screenshot: None,
+            #[cfg(feature = "debugger")]
+            upload_history: VecDeque::new(),
+            #[cfg(feature = "debugger")]
+            current_uploads: debug_server::FrameUploads::default(),
             #[cfg(feature = "capture")]
             read_fbo,
+            #[cfg(feature = "capture")]
+            target_capture: None,
             #[cfg(feature = "replay")]
             owned_external_images: FastHashMap::default(),
+            #[cfg(feature = "replay")]
+            captured_targets: Vec::new(),
             notifications: Vec::new(),
             framebuffer_size: None,
+            #[cfg(not(feature = "gleam"))]
+            suspended: false,
+            #[cfg(not(feature = "gleam"))]
+            api_tx: api_tx.clone(),
+            dirty_rect: None,
+            #[cfg(not(feature = "gleam"))]
+            record_next_frame: false,
+            #[cfg(not(feature = "gleam"))]
+            recorded_frame: None,
+            #[cfg(not(feature = "gleam"))]
+            enable_partial_present: options.enable_partial_present,
+            #[cfg(not(feature = "gleam"))]
+            partial_present_requested: false,
+            #[cfg(not(feature = "gleam"))]
+            image_damage: Vec::new(),
+            #[cfg(not(feature = "gleam"))]
+            partial_present_rect: None,
+            #[cfg(not(feature = "gleam"))]
+            split_screen: None,
             phantom_data: PhantomData,
         };
 
@@ -1731,6 +2420,13 @@ impl<B: hal::Backend> Renderer<B> {
         self.device.max_texture_size()
     }
 
+    /// Returns true if images of the given block compressed format can be
+    /// added. Embedders have to check this before adding compressed images,
+    /// and fall back to uncompressed data otherwise.
+    pub fn supports_compressed_format(&self, format: ImageFormat) -> bool {
+        self.device.supports_compressed_format(format)
+    }
+
     pub fn get_graphics_api_info(&self) -> GraphicsApiInfo {
         #[cfg(feature = "gleam")]
         let api_info = GraphicsApiInfo {
@@ -1767,14 +2463,21 @@ impl<B: hal::Backend> Renderer<B> {
     /// Should be called before `render()`, as texture cache updates are done here.
     pub fn update(&mut self) {
         profile_scope!("update");
+        self.send_screenshot_for_debugger();
         // Pull any pending results and return the most recent.
         while let Ok(msg) = self.result_rx.try_recv() {
             match msg {
                 ResultMsg::PublishPipelineInfo(mut pipeline_info) => {
+                    for pipeline_id in &pipeline_info.removed_pipelines {
+                        self.pipeline_info.epochs.remove(pipeline_id);
+                    }
                     for (pipeline_id, epoch) in pipeline_info.epochs {
                         self.pipeline_info.epochs.insert(pipeline_id, epoch);
                     }
                     self.pipeline_info.removed_pipelines.extend(pipeline_info.removed_pipelines.drain(..));
+                    if self.pipeline_info.removed_pipelines.len() > MAX_REMOVED_PIPELINES {
+                        self.pipeline_info.compact();
+                    }
                 }
                 ResultMsg::PublishDocument(
                     document_id,
@@ -1785,6 +2488,7 @@ impl<B: hal::Backend> Renderer<B> {
                     if doc.is_new_scene {
                         self.new_scene_indicator.changed();
                     }
+                    self.content_changed = true;
 
                     // Add a new document to the active set, expressed as a `Vec` in order
                     // to re-order based on `DocumentLayer` during rendering.
@@ -1840,7 +2544,7 @@ impl<B: hal::Backend> Renderer<B> {
                             },
                         }
                     }
-                    self.pending_gpu_cache_updates.push(list);
+                    self.queue_gpu_cache_update(list);
                 }
                 ResultMsg::UpdateResources {
                     updates,
@@ -1858,6 +2562,7 @@ impl<B: hal::Backend> Renderer<B> {
                     // not in a frame.
                     if memory_pressure {
                         self.texture_resolver.retain_targets(&mut self.device, |_| false);
+                        self.device.on_memory_pressure();
                     }
 
                     self.device.end_frame();
@@ -1881,11 +2586,17 @@ impl<B: hal::Backend> Renderer<B> {
                 }
                 ResultMsg::RefreshShader(path) => {
                     self.pending_shader_updates.push(path);
+                    self.content_changed = true;
                 }
                 ResultMsg::DebugOutput(output) => match output {
-                    DebugOutput::FetchDocuments(string) |
+                    DebugOutput::FetchDocuments(string) => {
+                        self.debug_server.send(&DebugCommand::FetchDocuments, string);
+                    }
                     DebugOutput::FetchClipScrollTree(string) => {
-                        self.debug_server.send(string);
+                        self.debug_server.send(&DebugCommand::FetchClipScrollTree, string);
+                    }
+                    DebugOutput::FetchMemoryBreakdown(string) => {
+                        self.debug_server.send(&DebugCommand::FetchMemoryBreakdown, string);
                     }
                     #[cfg(feature = "capture")]
                     DebugOutput::SaveCapture(config, deferred) => {
@@ -1905,32 +2616,145 @@ impl<B: hal::Backend> Renderer<B> {
     }
 
     #[cfg(not(feature = "debugger"))]
-    fn get_screenshot_for_debugger(&mut self) -> String {
+    fn request_screenshot_for_debugger(&mut self) {
         // Avoid unused param warning.
         let _ = &self.debug_server;
-        String::new()
     }
 
+    #[cfg(not(feature = "debugger"))]
+    fn send_screenshot_for_debugger(&mut self) {}
+
+    /// Rebuilds the swapchain, the depth buffers and the framebuffers of the
+    /// main window for the new window size, or for the current size of the
+    /// surface if `None`. Returns the new framebuffer size.
     #[cfg(not(feature = "gleam"))]
     pub fn resize(&mut self, window_size: Option<(i32, i32)>) -> DeviceIntSize {
+        if self.device.is_surface_lost() {
+            warn!("The surface was lost, call replace_surface instead of resize");
+            return self.device.framebuffer_size();
+        }
         self.shaders.borrow_mut().reset();
         let size = self.device.recreate_swapchain(window_size);
+        self.image_damage.clear();
         if let Some(debug_renderer) = self.debug.take() {
             debug_renderer.deinit(&mut self.device);
         }
+        // Recreating the swapchain dropped the programs, build them again.
+        if self.precache_flags.contains(ShaderPrecacheFlags::FULL_COMPILE) {
+            self.shaders.borrow_mut().precache_step(&mut self.device, u64::max_value());
+        } else if self.precache_flags.contains(ShaderPrecacheFlags::ASYNC_COMPILE) {
+            self.shader_precache_pending = true;
+        }
         size
     }
 
+    /// Returns true once the swapchain no longer matches the surface, e.g. the
+    /// window was resized or the surface was lost. Frames are skipped until
+    /// `resize`, or `replace_surface` for a lost surface, is called.
+    #[cfg(not(feature = "gleam"))]
+    pub fn needs_resize(&self) -> bool {
+        self.device.needs_resize()
+    }
+
+    #[cfg(not(feature = "gleam"))]
+    pub fn is_surface_lost(&self) -> bool {
+        self.device.is_surface_lost()
+    }
 
-    #[cfg(feature = "debugger")]
-    fn get_screenshot_for_debugger(&mut self) -> String {
-        use api::ImageDescriptor;
+    /// Recovers from a lost surface by rendering to a new surface of the
+    /// same window. Returns the new framebuffer size.
+    #[cfg(not(feature = "gleam"))]
+    pub fn replace_surface(&mut self, surface: B::Surface, window_size: Option<(i32, i32)>) -> DeviceIntSize {
+        self.device.replace_surface(surface);
+        self.resize(window_size)
+    }
+
+    /// Stops rendering until `resume`, e.g. when an Android activity pauses
+    /// and its surface is about to be destroyed. Waits for the GPU, then
+    /// drops the swapchain and the render targets, but keeps the texture and
+    /// GPU caches. The render backend keeps applying transactions meanwhile,
+    /// and builds the frames they request on resume.
+    #[cfg(not(feature = "gleam"))]
+    pub fn suspend(&mut self) {
+        if self.suspended {
+            return;
+        }
+        self.api_tx.send(::api::ApiMsg::SetSuspended(true)).unwrap();
+        self.device.begin_frame();
+        self.device.wait_for_resources_and_reset();
+        self.texture_resolver.retain_targets(&mut self.device, |_| false);
+        self.device.end_frame();
+        self.device.suspend();
+        self.image_damage.clear();
+        self.suspended = true;
+    }
 
-        let desc = ImageDescriptor::new(1024, 768, ImageFormat::BGRA8, true, false);
-        let data = self.device.read_pixels(&desc);
-        let screenshot = debug_server::Screenshot::new(desc.size, data);
+    /// Resumes rendering to the new surface of the window after `suspend`.
+    /// Returns the new framebuffer size.
+    #[cfg(not(feature = "gleam"))]
+    pub fn resume(&mut self, surface: B::Surface, window_size: Option<(i32, i32)>) -> DeviceIntSize {
+        let size = self.replace_surface(surface, window_size);
+        if self.suspended {
+            self.suspended = false;
+            self.content_changed = true;
+            self.api_tx.send(::api::ApiMsg::SetSuspended(false)).unwrap();
+        }
+        size
+    }
 
-        serde_json::to_string(&screenshot).unwrap()
+    /// Switches the present mode of the main window, e.g. `Immediate` to
+    /// trade tearing for latency, and recreates the swapchain. `None` picks
+    /// the best mode the surface supports.
+    #[cfg(not(feature = "gleam"))]
+    pub fn set_present_mode(&mut self, present_mode: Option<hal::window::PresentMode>) -> DeviceIntSize {
+        self.device.set_present_mode(present_mode);
+        self.resize(None)
+    }
+
+    /// Sets the rotation of the rendered content relative to the native
+    /// orientation of the surface (e.g. the pre-transform of an Android
+    /// surface). Rendering stops until the swapchain is recreated by `resize`.
+    #[cfg(not(feature = "gleam"))]
+    pub fn set_surface_transform(&mut self, transform: SurfaceTransform) {
+        self.device.set_surface_transform(transform);
+    }
+
+    /// Sets the origin of the main framebuffer, e.g. to match how the
+    /// compositor samples it. Defaults to the native origin of the backend.
+    pub fn set_surface_origin(&mut self, origin: SurfaceOrigin) {
+        self.device.set_surface_origin(origin);
+    }
+
+
+    #[cfg(feature = "debugger")]
+    fn request_screenshot_for_debugger(&mut self) {
+        if self.pending_debug_screenshot.is_some() {
+            return;
+        }
+        let size = DeviceIntSize::new(1024, 768);
+        let handle = self.device.request_read_pixels(
+            DeviceIntRect::new(DeviceIntPoint::zero(), size),
+            ReadPixelsFormat::Rgba8,
+        );
+        self.pending_debug_screenshot = Some((handle, size));
+    }
+
+    /// Sends the screenshot requested by the debugger once its pixels are
+    /// read back.
+    #[cfg(feature = "debugger")]
+    fn send_screenshot_for_debugger(&mut self) {
+        if let Some((handle, size)) = self.pending_debug_screenshot.take() {
+            match self.device.poll_readback(handle) {
+                Ok(data) => {
+                    let screenshot = debug_server::Screenshot::new(size, data);
+                    let json = serde_json::to_string(&screenshot).unwrap();
+                    self.debug_server.send(&DebugCommand::FetchScreenshot, json);
+                }
+                Err(handle) => {
+                    self.pending_debug_screenshot = Some((handle, size));
+                }
+            }
+        }
     }
 
     #[cfg(not(feature = "debugger"))]
@@ -1995,6 +2819,11 @@ impl<B: hal::Backend> Renderer<B> {
             "Readbacks",
             target.readbacks.len(),
         );
+        debug_target.add(
+            debug_server::BatchKind::Cache,
+            "SVG Filters",
+            target.svg_filters.iter().map(|&(_, ref instances)| instances.len()).sum(),
+        );
         debug_target.add(
             debug_server::BatchKind::Cache,
             "Vertical Blur",
@@ -2065,6 +2894,33 @@ impl<B: hal::Backend> Renderer<B> {
         serde_json::to_string(&debug_passes).unwrap()
     }
 
+    #[cfg(feature = "debugger")]
+    fn record_frame_uploads(&mut self, frame_id: GpuFrameId) {
+        let mut uploads = mem::replace(&mut self.current_uploads, debug_server::FrameUploads::default());
+        if uploads.is_empty() {
+            return;
+        }
+        uploads.set_frame_id(frame_id.into());
+        if self.upload_history.len() == UPLOAD_HISTORY_LENGTH {
+            self.upload_history.pop_front();
+        }
+        self.upload_history.push_back(uploads);
+    }
+
+    #[cfg(not(feature = "debugger"))]
+    fn get_uploads_for_debugger(&self) -> String {
+        String::new()
+    }
+
+    #[cfg(feature = "debugger")]
+    fn get_uploads_for_debugger(&self) -> String {
+        let mut history = debug_server::UploadHistory::new();
+        for frame in &self.upload_history {
+            history.add(frame);
+        }
+        serde_json::to_string(&history).unwrap()
+    }
+
     #[cfg(not(feature = "debugger"))]
     fn get_render_tasks_for_debugger(&self) -> String {
         String::new()
@@ -2096,18 +2952,23 @@ impl<B: hal::Backend> Renderer<B> {
                 panic!("Should be handled by render backend");
             }
             DebugCommand::FetchDocuments |
-            DebugCommand::FetchClipScrollTree => {}
+            DebugCommand::FetchClipScrollTree |
+            DebugCommand::FetchMemoryBreakdown => {}
             DebugCommand::FetchRenderTasks => {
                 let json = self.get_render_tasks_for_debugger();
-                self.debug_server.send(json);
+                self.debug_server.send(&command, json);
             }
             DebugCommand::FetchPasses => {
                 let json = self.get_passes_for_debugger();
-                self.debug_server.send(json);
+                self.debug_server.send(&command, json);
+            }
+            DebugCommand::FetchUploads => {
+                let json = self.get_uploads_for_debugger();
+                self.debug_server.send(&command, json);
             }
             DebugCommand::FetchScreenshot => {
-                let json = self.get_screenshot_for_debugger();
-                self.debug_server.send(json);
+                self.request_screenshot_for_debugger();
+                self.send_screenshot_for_debugger();
             }
             DebugCommand::SaveCapture(..) |
             DebugCommand::LoadCapture(..) => {
@@ -2117,30 +2978,121 @@ impl<B: hal::Backend> Renderer<B> {
             | DebugCommand::SimulateLongSceneBuild(_)
             | DebugCommand::SimulateLongLowPrioritySceneBuild(_) => {}
             DebugCommand::InvalidateGpuCache => {
-                match self.gpu_cache_texture.bus {
-                    GpuCacheBus::PixelBuffer { ref mut rows, .. } => {
+                let mut staged = self.gpu_cache_texture.staging.apply_pending();
+                let staged = &mut *staged;
+                match staged.rows {
+                    Some(ref mut rows) => {
                         info!("Invalidating GPU caches");
                         for row in rows {
                             row.is_dirty = true;
                         }
+                        staged.rows_changed = true;
                     }
-                    #[cfg(feature = "gleam")]
-                    GpuCacheBus::Scatter { .. } => {
+                    None => {
                         warn!("Unable to invalidate scattered GPU cache");
                     }
                 }
+                staged.gather();
             }
             DebugCommand::SetFlags(flags) => {
                 self.set_debug_flags(flags);
             }
+            DebugCommand::SetFlag(flag, enabled) => {
+                let mut flags = self.debug_flags;
+                flags.set(flag, enabled);
+                self.set_debug_flags(flags);
+            }
+            DebugCommand::SetBatchingParameters(parameters) => {
+                // The render backend resolves `None` to the vendor defaults
+                // before forwarding the command.
+                if let Some(parameters) = parameters {
+                    self.batching_parameters = parameters;
+                }
+            }
+            DebugCommand::SetProfilerOverlay(overlay) => {
+                self.profiler_overlay = overlay;
+            }
+            DebugCommand::SetDebugCursor(position) => {
+                self.set_debug_cursor(position);
+            }
+            DebugCommand::DumpEnvironment(path) => {
+                self.dump_environment(&path);
+            }
         }
     }
 
+    /// Starts recording the time spans of the render backend, the renderer
+    /// and the GPU passes and batches of every rendered frame to `path`, in
+    /// the JSON format of chrome://tracing. A trace in progress is finished
+    /// first.
+    pub fn start_trace(&mut self, path: &Path) -> io::Result<()> {
+        self.stop_trace()?;
+        self.trace = Some(ChromeTrace::new(path)?);
+        Ok(())
+    }
+
+    /// Finishes the trace started with `start_trace`, if any.
+    pub fn stop_trace(&mut self) -> io::Result<()> {
+        match self.trace.take() {
+            Some(trace) => trace.finish(),
+            None => Ok(()),
+        }
+    }
+
+    /// Writes the adapter, the options in effect, the texture cache
+    /// configuration and the recent errors to `path` as JSON.
+    fn dump_environment(&self, path: &Path) {
+        let dump = EnvironmentDump {
+            adapter: self.device.describe_adapter(),
+            features: self.device.describe_features(),
+            capabilities: format!("{:?}", self.device.get_capabilities()),
+            workarounds: format!("{:?}", self.device.workarounds()),
+            options: &self.options_dump,
+            shader_digest: shader_manifest_digest(),
+            texture_cache: TextureCacheDump {
+                max_texture_size: self.device.max_texture_size(),
+                max_texture_layers: self.device.max_texture_layers(),
+                region_size: TEXTURE_REGION_DIMENSIONS,
+            },
+            recent_errors: &self.recent_errors,
+        };
+
+        let result = File::create(path)
+            .map_err(serde_json::Error::io)
+            .and_then(|file| serde_json::to_writer_pretty(file, &dump));
+        match result {
+            Ok(()) => info!("Environment written to {:?}", path),
+            Err(e) => error!("Unable to write the environment to {:?}: {:?}", path, e),
+        }
+    }
+
+    /// Returns a handle that can connect additional debugger clients, e.g. an
+    /// in-process channel or a bridge to the embedder's devtools protocol.
+    #[cfg(feature = "debugger")]
+    pub fn debug_client_registrar(&self) -> DebugClientRegistrar {
+        self.debug_server.registrar()
+    }
+
     /// Set a callback for handling external images.
     pub fn set_external_image_handler(&mut self, handler: Box<ExternalImageHandler>) {
         self.external_image_handler = Some(handler);
     }
 
+    /// Makes an image the embedder created from a native handle available
+    /// as the `ExternalImageSource::NativeTexture` of external images, see
+    /// `Device::import_image`.
+    #[cfg(not(feature = "gleam"))]
+    pub fn import_native_image(&mut self, image: B::Image, format: ImageFormat, size: DeviceIntSize) -> u32 {
+        self.device.import_image(image, format, size)
+    }
+
+    /// Gives back an image added with `import_native_image`, once the
+    /// external image handler won't be asked to lock it anymore.
+    #[cfg(not(feature = "gleam"))]
+    pub fn release_native_image(&mut self, id: u32) -> Option<B::Image> {
+        self.device.release_imported_image(id)
+    }
+
     /// Set a callback for handling external outputs.
     pub fn set_output_image_handler(&mut self, handler: Box<OutputImageHandler>) {
         self.output_image_handler = Some(handler);
@@ -2153,6 +3105,92 @@ impl<B: hal::Backend> Renderer<B> {
         (cpu_profiles, gpu_profiles)
     }
 
+    /// Returns the batch lists of the last frame of each active document in
+    /// a canonical text form, see `Frame::write_batch_lists`. Used to check
+    /// that changes to the batching code don't change its output.
+    pub fn get_batch_lists(&self) -> String {
+        let mut output = String::new();
+        for (index, &(_, ref render_doc)) in self.active_documents.iter().enumerate() {
+            output.push_str(&format!("document {}\n", index));
+            render_doc.frame.write_batch_lists(&mut output);
+        }
+        output
+    }
+
+    /// Sets a callback receiving the timings of every frame rendered to the
+    /// main framebuffer, e.g. to adapt the animation timing of the embedder.
+    pub fn set_frame_pacing_hook(&mut self, hook: Box<FnMut(FramePacingInfo)>) {
+        self.frame_pacing_hook = Some(hook);
+    }
+
+    /// Sets the scale and the color override of the debug overlay text.
+    pub fn set_debug_text_options(&mut self, scale: f32, color: Option<ColorU>) {
+        self.debug.set_text_options(scale, color);
+    }
+
+    /// Sets the GPU time budgets of the render passes. Exceeding a budget
+    /// raises a `PassBudgetAlert`. Needs `DebugFlags::GPU_TIME_QUERIES`.
+    pub fn set_pass_budgets(&mut self, budgets: Vec<PassBudget>) {
+        self.pass_budgets = PassBudgetTracker::new(budgets);
+    }
+
+    /// Retrieve (and clear) the alerts raised for exceeded pass budgets.
+    pub fn take_pass_budget_alerts(&mut self) -> Vec<PassBudgetAlert> {
+        mem::replace(&mut self.pass_budget_alerts, Vec::new())
+    }
+
+    /// Returns the area of the framebuffer that changed in the last rendered
+    /// frame, or None if the whole framebuffer has to be considered damaged.
+    ///
+    /// This only covers the content of the documents, so it is None whenever a
+    /// debug overlay is displayed.
+    pub fn get_dirty_rect(&self) -> Option<DeviceIntRect> {
+        self.dirty_rect
+    }
+
+    /// Records the device commands submitted for the document content of the
+    /// next rendered frame. The recording is retrieved with
+    /// `take_recorded_frame`.
+    #[cfg(not(feature = "gleam"))]
+    pub fn record_next_frame(&mut self) {
+        self.record_next_frame = true;
+    }
+
+    #[cfg(not(feature = "gleam"))]
+    pub fn take_recorded_frame(&mut self) -> Option<CommandStream> {
+        self.recorded_frame.take()
+    }
+
+    /// Submits a recorded frame again as a new frame, without going through
+    /// frame building, batching or resource updates.
+    ///
+    /// The textures and render targets referenced by the recording must not
+    /// have been freed since, so this is mostly meant to be called right
+    /// after rendering the recorded frame, e.g. in a benchmark loop.
+    #[cfg(not(feature = "gleam"))]
+    pub fn replay_frame(
+        &mut self,
+        stream: &CommandStream,
+    ) -> Result<RendererStats, Vec<RendererError>> {
+        let mut stats = RendererStats::empty();
+
+        self.device.set_next_frame_id();
+        self.device.begin_frame();
+        if self.device.replay(stream) {
+            stats.total_draw_calls = stream.draw_call_count();
+        } else {
+            self.renderer_errors.push(RendererError::InvalidCommandStream);
+        }
+        self.device.submit_to_gpu();
+        self.device.end_frame();
+
+        if self.renderer_errors.is_empty() {
+            Ok(stats)
+        } else {
+            Err(mem::replace(&mut self.renderer_errors, Vec::new()))
+        }
+    }
+
     /// Returns `true` if the active rendered documents (that need depth buffer)
     /// intersect on the main framebuffer, in which case we don't clear
     /// the whole depth and instead clear each document area separately.
@@ -2166,23 +3204,157 @@ impl<B: hal::Backend> Renderer<B> {
                     _ => None,
                 }
             })
-            .collect::<Vec<_>>();
+            .collect::<Vec<_>>();
+
+        for (i, rect) in document_rects.iter().enumerate() {
+            for other in &document_rects[i+1 ..] {
+                if rect.intersects(other) {
+                    return true
+                }
+            }
+        }
+
+        false
+    }
+
+    /// Sets where and how the profiler overlay is drawn.
+    pub fn set_profiler_overlay(&mut self, overlay: ProfilerOverlay) {
+        self.profiler_overlay = overlay;
+    }
+
+    /// Sets the position magnified by the `ZOOM_DBG` overlay, in framebuffer
+    /// pixels from the top-left corner.
+    pub fn set_debug_cursor(&mut self, position: Option<DeviceIntPoint>) {
+        if self.debug_cursor != position {
+            self.debug_cursor = position;
+            self.content_changed = true;
+        }
+    }
+
+    /// Enables or disables the split screen mode, see `SplitScreen`.
+    #[cfg(not(feature = "gleam"))]
+    pub fn set_split_screen(&mut self, split_screen: Option<SplitScreen>) {
+        self.split_screen = split_screen;
+        self.content_changed = true;
+    }
+
+    /// Returns the parts of the main framebuffer to draw the main pass into,
+    /// along with the debug flags to add while drawing each of them. The
+    /// whole framebuffer is drawn at once unless the split screen mode is on.
+    #[cfg(not(feature = "gleam"))]
+    fn main_framebuffer_views(
+        &self,
+        framebuffer_size: DeviceIntSize,
+    ) -> Vec<(Option<DeviceIntRect>, DebugFlags)> {
+        let split_screen = match self.split_screen {
+            Some(split_screen) => split_screen,
+            None => return vec![(None, DebugFlags::empty())],
+        };
+        let half_width = framebuffer_size.width / 2;
+        let halves = [
+            (
+                DeviceIntRect::new(
+                    DeviceIntPoint::zero(),
+                    DeviceIntSize::new(half_width, framebuffer_size.height),
+                ),
+                split_screen.left_flags,
+            ),
+            (
+                DeviceIntRect::new(
+                    DeviceIntPoint::new(half_width, 0),
+                    DeviceIntSize::new(framebuffer_size.width - half_width, framebuffer_size.height),
+                ),
+                split_screen.right_flags,
+            ),
+        ];
+        halves
+            .iter()
+            .filter_map(|&(half, flags)| {
+                // Keep honoring the damage of a partial present.
+                let rect = match self.partial_present_rect {
+                    Some(rect) => rect.intersection(&half)?,
+                    None => half,
+                };
+                Some((Some(rect), flags))
+            })
+            .collect()
+    }
 
-        for (i, rect) in document_rects.iter().enumerate() {
-            for other in &document_rects[i+1 ..] {
-                if rect.intersects(other) {
-                    return true
-                }
+    #[cfg(feature = "gleam")]
+    fn main_framebuffer_views(
+        &self,
+        _framebuffer_size: DeviceIntSize,
+    ) -> Vec<(Option<DeviceIntRect>, DebugFlags)> {
+        vec![(None, DebugFlags::empty())]
+    }
+
+    /// Builds shaders that weren't used yet, within a time budget.
+    fn precache_shaders_step(&mut self) {
+        if !self.shader_precache_pending {
+            return;
+        }
+        let deadline = precise_time_ns() + SHADER_PRECACHE_BUDGET_NS;
+        if self.shaders.borrow_mut().precache_step(&mut self.device, deadline) {
+            self.shader_precache_pending = false;
+            if let Some(ref callback) = self.shader_precache_callback {
+                callback();
             }
         }
+    }
 
-        false
+    /// Flushes the pending texture cache updates and waits up to `timeout`
+    /// for the GPU to finish all the submitted work. Once this returns `Ok`,
+    /// the GPU no longer uses any renderer resource, so it's safe to call
+    /// `deinit` or to suspend the device.
+    pub fn wait_idle(&mut self, timeout: Duration) -> Result<(), RendererError> {
+        if !self.pending_texture_updates.is_empty() {
+            self.device.begin_frame();
+            self.update_texture_cache();
+            self.device.end_frame();
+        }
+        let timeout_ns = timeout.as_secs()
+            .saturating_mul(1_000_000_000)
+            .saturating_add(timeout.subsec_nanos() as u64);
+        if !self.device.wait_idle(timeout_ns) {
+            error!("The GPU didn't become idle within {:?}", timeout);
+            return Err(RendererError::GpuTimeout);
+        }
+        Ok(())
     }
 
     pub fn notify_slow_frame(&mut self) {
         self.slow_frame_indicator.changed();
     }
 
+    /// Returns the number of validation errors the backend reported while
+    /// rendering the last frame. Always zero unless
+    /// `RendererOptions::enable_gpu_validation` is set.
+    #[cfg(not(feature = "gleam"))]
+    pub fn gpu_validation_error_count(&self) -> usize {
+        self.gpu_validation_errors
+    }
+
+    /// Returns true if calling `render` would produce a different image than
+    /// the last one, or has pending work to do.
+    ///
+    /// This accounts for the frames and resource updates received by the last
+    /// `update`, pending GPU cache and texture updates, shaders still being
+    /// built and debug overlay changes. Embedders that only composite on
+    /// demand can skip `render` on idle frames while this returns false: new
+    /// content, including animated properties, is signalled through
+    /// `RenderNotifier::new_frame_ready`, and debug overlay changes through
+    /// `RenderNotifier::render_needed`.
+    pub fn needs_render(&self) -> bool {
+        self.content_changed ||
+            self.shader_precache_pending ||
+            self.pending_gpu_cache_clear ||
+            !self.pending_texture_updates.is_empty() ||
+            self.gpu_cache_texture.has_pending_updates() ||
+            !self.pending_shader_updates.is_empty() ||
+            // The profiler graphs only move forward when rendering.
+            self.debug_flags.contains(DebugFlags::PROFILER_DBG)
+    }
+
     /// Renders the current frame.
     ///
     /// A Frame is supplied by calling [`generate_frame()`][webrender_api::Transaction::generate_frame].
@@ -2190,9 +3362,34 @@ impl<B: hal::Backend> Renderer<B> {
         &mut self,
         framebuffer_size: DeviceIntSize,
     ) -> Result<RendererStats, Vec<RendererError>> {
+        #[cfg(not(feature = "gleam"))]
+        {
+            if self.suspended {
+                return Ok(RendererStats::empty());
+            }
+        }
+
         self.framebuffer_size = Some(framebuffer_size);
 
         let result = self.render_impl(Some(framebuffer_size));
+        if let Err(ref errors) = result {
+            for error in errors {
+                if self.recent_errors.len() == RECENT_ERRORS_LENGTH {
+                    self.recent_errors.pop_front();
+                }
+                self.recent_errors.push_back(format!("{:?}", error));
+            }
+        }
+        #[cfg(feature = "capture")]
+        self.save_captured_targets();
+        self.content_changed = false;
+        self.precache_shaders_step();
+        #[cfg(not(feature = "gleam"))]
+        {
+            if self.gpu_validation {
+                self.gpu_validation_errors = end_validation_frame();
+            }
+        }
 
         drain_filter(
             &mut self.notifications,
@@ -2208,6 +3405,52 @@ impl<B: hal::Backend> Renderer<B> {
         result
     }
 
+    /// Renders the current frame like `render`, but only redraws the area of
+    /// the main framebuffer that changed since the acquired swapchain image
+    /// was last rendered to, if `RendererOptions::enable_partial_present` is
+    /// set. The rest of the image keeps the content of its previous frames.
+    ///
+    /// The redrawn area is returned by `get_partial_present_rect`. The whole
+    /// image is still presented, as the swapchain can't present regions.
+    #[cfg(not(feature = "gleam"))]
+    pub fn render_with_dirty_rects(
+        &mut self,
+        framebuffer_size: DeviceIntSize,
+    ) -> Result<RendererStats, Vec<RendererError>> {
+        self.partial_present_requested = self.enable_partial_present;
+        let result = self.render(framebuffer_size);
+        self.partial_present_requested = false;
+        result
+    }
+
+    /// Returns the area of the main framebuffer redrawn by the last frame
+    /// rendered with `render_with_dirty_rects`, or None if it was redrawn
+    /// entirely.
+    #[cfg(not(feature = "gleam"))]
+    pub fn get_partial_present_rect(&self) -> Option<DeviceIntRect> {
+        self.partial_present_rect
+    }
+
+    /// Adds the dirty rect of the current frame to the damage of all the
+    /// swapchain images, and returns (and resets) the damage of the image
+    /// rendered to.
+    #[cfg(not(feature = "gleam"))]
+    fn take_image_damage(&mut self, dirty_rect: Option<DeviceIntRect>) -> Option<DeviceIntRect> {
+        if self.image_damage.len() != self.device.frame_count {
+            self.image_damage = vec![None; self.device.frame_count];
+        }
+        for damage in &mut self.image_damage {
+            *damage = match (*damage, dirty_rect) {
+                (Some(rect), Some(dirty_rect)) => Some(rect.union(&dirty_rect)),
+                _ => None,
+            };
+        }
+        mem::replace(
+            &mut self.image_damage[self.device.current_frame_id],
+            Some(DeviceIntRect::zero()),
+        )
+    }
+
     // If framebuffer_size is None, don't render
     // to the main frame buffer. This is useful
     // to update texture cache render tasks but
@@ -2239,9 +3482,17 @@ impl<B: hal::Backend> Renderer<B> {
                 self.gpu_profiles
                     .push_back(GpuProfile::new(gpu_frame_id, &timers));
             }
+            if !self.pass_budgets.is_empty() {
+                for alert in self.pass_budgets.update(&timers) {
+                    warn!("GPU time budget exceeded: {:?}", alert);
+                    self.pass_budget_alerts.push(alert);
+                }
+            }
             profile_timers.gpu_samples = timers;
             samplers
         };
+        // The profiler overlay consumes the samples, sum them up beforehand.
+        let gpu_time = profile_timers.gpu_samples.iter().map(|timer| timer.time_ns).sum::<u64>();
 
         #[cfg(not(feature="gleam"))]
         self.device.set_next_frame_id();
@@ -2261,6 +3512,14 @@ impl<B: hal::Backend> Renderer<B> {
             frame_id
         });
 
+        #[cfg(not(feature = "gleam"))]
+        {
+            if self.record_next_frame {
+                self.record_next_frame = false;
+                self.device.start_recording();
+            }
+        }
+
         profile_timers.cpu_time.profile(|| {
             let clear_depth_value = if self.are_documents_intersecting_depth() {
                 None
@@ -2273,6 +3532,36 @@ impl<B: hal::Backend> Renderer<B> {
             // sort by the document layer id
             active_documents.sort_by_key(|&(_, ref render_doc)| render_doc.frame.layer);
 
+            let overlay_flags = DebugFlags::PROFILER_DBG | DebugFlags::RENDER_TARGET_DBG |
+                DebugFlags::TEXTURE_CACHE_DBG | DebugFlags::GPU_CACHE_DBG | DebugFlags::EPOCHS |
+                DebugFlags::NEW_FRAME_INDICATOR | DebugFlags::NEW_SCENE_INDICATOR |
+                DebugFlags::SLOW_FRAME_INDICATOR | DebugFlags::SHOW_OVERDRAW |
+                DebugFlags::PICTURE_CACHING_DBG | DebugFlags::PRIMITIVE_DBG |
+                DebugFlags::SHOW_BATCH_RECTS | DebugFlags::ZOOM_DBG;
+            let initial_dirty_rect = if self.debug_flags.intersects(overlay_flags) {
+                None
+            } else {
+                Some(DeviceIntRect::zero())
+            };
+            let dirty_rect = active_documents
+                .iter()
+                // A frame that was already rendered doesn't change anything.
+                .filter(|&&(_, RenderedDocument { ref frame, .. })| !frame.has_been_rendered)
+                .fold(initial_dirty_rect, |dirty_rect, &(_, RenderedDocument { ref frame, .. })| {
+                    match (dirty_rect, frame.dirty_rect) {
+                        (Some(rect), Some(frame_rect)) => Some(rect.union(&frame_rect)),
+                        _ => None,
+                    }
+                });
+
+            #[cfg(not(feature = "gleam"))]
+            {
+                self.partial_present_rect = match framebuffer_size {
+                    Some(_) if self.partial_present_requested => self.take_image_damage(dirty_rect),
+                    _ => None,
+                };
+            }
+
             // don't clear the framebuffer if one of the rendered documents will overwrite it
             if let Some(framebuffer_size) = framebuffer_size {
                 let needs_color_clear = !active_documents
@@ -2289,10 +3578,18 @@ impl<B: hal::Backend> Renderer<B> {
                     } else {
                         None
                     };
-                    self.device.reset_draw_target();
-                    self.device.enable_depth_write();
-                    self.device.clear_target(clear_color, clear_depth_value, None);
-                    self.device.disable_depth_write();
+                    #[cfg(feature = "gleam")]
+                    let clear_rect = None;
+                    #[cfg(not(feature = "gleam"))]
+                    let clear_rect = self.partial_present_rect.map(|rect| {
+                        self.device.surface_origin().convert_rect(rect, framebuffer_size.height)
+                    });
+                    if clear_rect.map_or(true, |rect| !rect.is_empty()) {
+                        self.device.reset_draw_target();
+                        self.device.enable_depth_write();
+                        self.device.clear_target(clear_color, clear_depth_value, clear_rect);
+                        self.device.disable_depth_write();
+                    }
                 }
             }
 
@@ -2301,8 +3598,9 @@ impl<B: hal::Backend> Renderer<B> {
                 self.owned_external_images.iter().map(|(key, value)| (*key, value.clone()))
             );
 
-            for &mut (_, RenderedDocument { ref mut frame, .. }) in &mut active_documents {
+            for &mut (document_id, RenderedDocument { ref mut frame, .. }) in &mut active_documents {
                 frame.profile_counters.reset_targets();
+                stats.occluded_primitives += frame.profile_counters.occluded_primitives.get();
                 self.prepare_gpu_cache(frame);
                 assert!(frame.gpu_cache_frame_id <= self.gpu_cache_frame_id,
                     "Received frame depends on a later GPU cache epoch ({:?}) than one we received last via `UpdateGpuCache` ({:?})",
@@ -2316,20 +3614,25 @@ impl<B: hal::Backend> Renderer<B> {
                     &mut stats
                 );
 
-                if self.debug_flags.contains(DebugFlags::PROFILER_DBG) {
+                if self.debug_flags.contains(DebugFlags::PROFILER_DBG) &&
+                    self.profiler_overlay.document.map_or(true, |id| id == document_id) {
                     frame_profiles.push(frame.profile_counters.clone());
                 }
             }
 
             self.unlock_external_images();
             self.active_documents = active_documents;
+            self.dirty_rect = dirty_rect;
         });
 
         let current_time = precise_time_ns();
-        if framebuffer_size.is_some() {
+        let frame_interval = if framebuffer_size.is_some() {
             let ns = current_time - self.last_time;
             self.profile_counters.frame_time.set(ns);
-        }
+            ns
+        } else {
+            0
+        };
 
         if self.max_recorded_profiles > 0 {
             while self.cpu_profiles.len() >= self.max_recorded_profiles {
@@ -2344,11 +3647,32 @@ impl<B: hal::Backend> Renderer<B> {
             self.cpu_profiles.push_back(cpu_profile);
         }
 
+        // The GPU samples are the ones of the previous frame, whose queries
+        // have resolved by now.
+        if self.trace.is_some() {
+            let backend_time_ns = self.backend_profile_counters.total_time.get();
+            let renderer_time_ns = profile_timers.cpu_time.get();
+            let result = self.trace.as_mut().unwrap().add_frame(
+                backend_time_ns,
+                current_time - renderer_time_ns,
+                renderer_time_ns,
+                &profile_timers.gpu_samples,
+            );
+            if let Err(e) = result {
+                error!("Unable to write the trace: {:?}", e);
+                self.trace = None;
+            }
+        }
+
+        #[cfg(feature = "debugger")]
+        self.record_frame_uploads(cpu_frame_id);
+
         if self.debug_flags.contains(DebugFlags::PROFILER_DBG) {
             if let Some(framebuffer_size) = framebuffer_size {
                 //TODO: take device/pixel ratio into equation?
                 if let Some(debug_renderer) = self.debug.get_mut(&mut self.device) {
                     let screen_fraction = 1.0 / framebuffer_size.to_f32().area();
+                    let mark = debug_renderer.mark();
                     self.profiler.draw_profile(
                         &frame_profiles,
                         &self.backend_profile_counters,
@@ -2359,6 +3683,14 @@ impl<B: hal::Backend> Renderer<B> {
                         debug_renderer,
                         self.debug_flags.contains(DebugFlags::COMPACT_PROFILER),
                     );
+                    // The debug renderer draws after the last document was
+                    // composited, so the overlay only needs to be placed.
+                    place_profiler_overlay(
+                        debug_renderer,
+                        mark,
+                        &self.profiler_overlay,
+                        framebuffer_size,
+                    );
                 }
             }
         }
@@ -2407,9 +3739,20 @@ impl<B: hal::Backend> Renderer<B> {
         self.profile_counters.frame_counter.inc();
         stats.resource_upload_time = self.resource_upload_time;
         self.resource_upload_time = 0;
+        stats.gpu_cache_update_time = self.gpu_cache_update_time;
+        self.gpu_cache_update_time = 0;
         stats.gpu_cache_upload_time = self.gpu_cache_upload_time;
         self.gpu_cache_upload_time = 0;
 
+        // The debug overlays aren't part of the recording, as they upload
+        // their vertices outside of the instance buffers.
+        #[cfg(not(feature = "gleam"))]
+        {
+            if let Some(stream) = self.device.stop_recording() {
+                self.recorded_frame = Some(stream);
+            }
+        }
+
         profile_timers.cpu_time.profile(|| {
             let _gm = self.gpu_profile.start_marker("end frame");
             self.gpu_profile.end_frame();
@@ -2423,6 +3766,42 @@ impl<B: hal::Backend> Renderer<B> {
         });
         if framebuffer_size.is_some() {
             self.last_time = current_time;
+            // `schedule_read_pixel_at` doesn't schedule anything while a
+            // read is pending, so this never drops an unpolled read back.
+            if let Some(point) = self.scheduled_pixel_read.take() {
+                if self.pixel_rect(point).is_some() {
+                    self.pending_pixel_read = Some(self.device.request_read_pixels(
+                        DeviceIntRect::new(point, DeviceIntSize::new(1, 1)),
+                        ReadPixelsFormat::Rgba8,
+                    ));
+                } else {
+                    warn!("Dropping the read of pixel {:?} outside of the framebuffer", point);
+                }
+            }
+        }
+
+        #[cfg(not(feature = "gleam"))]
+        {
+            let timings = self.device.take_frame_timings();
+            stats.swapchain_acquire_time = timings.acquire_time;
+            stats.vsync_wait_time = timings.present_time;
+            stats.fence_wait_time = timings.fence_wait_time;
+        }
+        if gpu_time != 0 {
+            stats.cpu_gpu_bubble_time = frame_interval.saturating_sub(gpu_time);
+        }
+        if framebuffer_size.is_some() {
+            if let Some(ref mut hook) = self.frame_pacing_hook {
+                hook(FramePacingInfo {
+                    frame_interval,
+                    cpu_time: profile_timers.cpu_time.get(),
+                    gpu_time,
+                    swapchain_acquire_time: stats.swapchain_acquire_time,
+                    vsync_wait_time: stats.vsync_wait_time,
+                    fence_wait_time: stats.fence_wait_time,
+                    cpu_gpu_bubble_time: stats.cpu_gpu_bubble_time,
+                });
+            }
         }
 
         if self.renderer_errors.is_empty() {
@@ -2432,6 +3811,15 @@ impl<B: hal::Backend> Renderer<B> {
         }
     }
 
+    /// Hands a GPU cache update list to the worker pool, which applies it
+    /// to the CPU side of the cache ahead of the next flush.
+    fn queue_gpu_cache_update(&mut self, update_list: GpuCacheUpdateList) {
+        #[cfg(feature = "debugger")]
+        self.current_uploads.add_gpu_cache_update_list(&update_list);
+
+        self.gpu_cache_texture.queue_update(update_list, &self.workers);
+    }
+
     fn update_gpu_cache(&mut self) {
         let _gm = self.gpu_profile.start_marker("gpu cache update");
 
@@ -2439,72 +3827,53 @@ impl<B: hal::Backend> Renderer<B> {
         // always pass an extra update list with at least one block in it.
         let gpu_cache_height = self.gpu_cache_texture.get_height();
         if gpu_cache_height != 0 && GPU_CACHE_RESIZE_TEST {
-            self.pending_gpu_cache_updates.push(GpuCacheUpdateList {
+            self.queue_gpu_cache_update(GpuCacheUpdateList {
                 frame_id: FrameId::INVALID,
                 clear: false,
                 height: gpu_cache_height,
                 blocks: vec![[1f32; 4].into()],
                 updates: Vec::new(),
                 debug_commands: Vec::new(),
+                requested_rows: 0,
+                coalesced_rows: 0,
             });
         }
 
-        let (updated_blocks, max_requested_height) = self
-            .pending_gpu_cache_updates
-            .iter()
-            .fold((0, gpu_cache_height), |(count, height), list| {
-                (count + list.blocks.len(), cmp::max(height, list.height))
-            });
+        // Only the lists the workers haven't picked up yet are applied here,
+        // the rest of the flush is the copy to the texture.
+        let profile = self.gpu_cache_texture.flush(&mut self.device);
 
-        if max_requested_height > self.get_max_texture_size() && !self.gpu_cache_overflow {
+        if profile.height > self.get_max_texture_size() && !self.gpu_cache_overflow {
             self.gpu_cache_overflow = true;
             self.renderer_errors.push(RendererError::MaxTextureSize);
         }
-
-        // Note: if we decide to switch to scatter-style GPU cache update
-        // permanently, we can have this code nicer with `BufferUploader` kind
-        // of helper, similarly to how `TextureUploader` API is used.
-        self.gpu_cache_texture.prepare_for_updates(
-            &mut self.device,
-            updated_blocks,
-            max_requested_height,
-        );
-
-        for update_list in self.pending_gpu_cache_updates.drain(..) {
-            assert!(update_list.height <= max_requested_height);
-            if update_list.frame_id > self.gpu_cache_frame_id {
-                self.gpu_cache_frame_id = update_list.frame_id
-            }
-            self.gpu_cache_texture
-                .update(&mut self.device, &update_list);
+        if profile.frame_id > self.gpu_cache_frame_id {
+            self.gpu_cache_frame_id = profile.frame_id;
         }
-
-        let mut upload_time = TimeProfileCounter::new("GPU cache upload time", false);
-        let updated_rows = upload_time.profile(|| {
-            return self.gpu_cache_texture.flush(&mut self.device);
-        });
-        self.gpu_cache_upload_time += upload_time.get();
+        self.gpu_cache_update_time += profile.update_time;
+        self.gpu_cache_upload_time += profile.upload_time;
 
         let counters = &mut self.backend_profile_counters.resources.gpu_cache;
-        counters.updated_rows.set(updated_rows);
-        counters.updated_blocks.set(updated_blocks);
+        counters.updated_rows.set(profile.updated_rows);
+        counters.updated_blocks.set(profile.updated_blocks);
+        counters.updated_runs.set(profile.updated_runs);
+        counters.requested_rows.set(profile.requested_rows);
+        counters.coalesced_rows.set(profile.coalesced_rows);
     }
 
     fn prepare_gpu_cache(&mut self, frame: &Frame) {
         if self.pending_gpu_cache_clear {
-            #[cfg(feature="gleam")]
-            let use_scatter = matches!(self.gpu_cache_texture.bus, GpuCacheBus::Scatter { .. });
-            #[cfg(not(feature="gleam"))]
-            let use_scatter = false;
-
-            let new_cache = GpuCacheTexture::new(&mut self.device, use_scatter).unwrap();
-            let old_cache = mem::replace(&mut self.gpu_cache_texture, new_cache);
-            old_cache.deinit(&mut self.device);
+            // The CPU side of the cache is reset when the workers apply the
+            // clearing update list.
+            if let Some(texture) = self.gpu_cache_texture.texture.take() {
+                self.device.delete_texture(texture);
+            }
             self.pending_gpu_cache_clear = false;
         }
 
-        let deferred_update_list = self.update_deferred_resolves(&frame.deferred_resolves);
-        self.pending_gpu_cache_updates.extend(deferred_update_list);
+        if let Some(update_list) = self.update_deferred_resolves(&frame.deferred_resolves) {
+            self.queue_gpu_cache_update(update_list);
+        }
 
         self.update_gpu_cache();
 
@@ -2520,6 +3889,11 @@ impl<B: hal::Backend> Renderer<B> {
         let _gm = self.gpu_profile.start_marker("texture cache update");
         let mut pending_texture_updates = mem::replace(&mut self.pending_texture_updates, vec![]);
 
+        #[cfg(feature = "debugger")]
+        for update_list in &pending_texture_updates {
+            self.current_uploads.add_texture_update_list(update_list);
+        }
+
         let mut upload_time = TimeProfileCounter::new("Resource upload time", false);
         upload_time.profile(|| {
             for update_list in pending_texture_updates.drain(..) {
@@ -2578,6 +3952,15 @@ impl<B: hal::Backend> Renderer<B> {
                     let TextureCacheUpdate { id, rect, stride, offset, layer_index, source } = update;
                     let texture = &self.texture_resolver.texture_cache_map[&id];
 
+                    if !rect.is_within(texture.get_dimensions()) ||
+                        layer_index < 0 || layer_index >= texture.get_layer_count() ||
+                        offset < 0
+                    {
+                        warn!("Skipping texture cache update {:?} for {:?}", rect, id);
+                        self.renderer_errors.push(RendererError::InvalidRect(rect));
+                        continue;
+                    }
+
                     let bytes_uploaded = match source {
                         TextureUpdateSource::Bytes { data } => {
                             let mut uploader = self.device.upload_texture(
@@ -2591,17 +3974,17 @@ impl<B: hal::Backend> Renderer<B> {
                             )
                         }
                         TextureUpdateSource::External { id, channel_index } => {
-                            let mut uploader = self.device.upload_texture(
-                                texture,
-                                &self.texture_cache_upload_pbo,
-                                0,
-                            );
                             let handler = self.external_image_handler
                                 .as_mut()
                                 .expect("Found external image, but no handler set!");
                             // The filter is only relevant for NativeTexture external images.
                             let size = match handler.lock(id, channel_index, ImageRendering::Auto).source {
                                 ExternalImageSource::RawData(data) => {
+                                    let mut uploader = self.device.upload_texture(
+                                        texture,
+                                        &self.texture_cache_upload_pbo,
+                                        0,
+                                    );
                                     uploader.upload(
                                         rect, layer_index, stride,
                                         &data[offset as usize ..],
@@ -2609,16 +3992,33 @@ impl<B: hal::Backend> Renderer<B> {
                                 }
                                 ExternalImageSource::Invalid => {
                                     // Create a local buffer to fill the pbo.
-                                    let bpp = texture.get_format().bytes_per_pixel();
-                                    let width = stride.unwrap_or(rect.size.width * bpp);
-                                    let total_size = width * rect.size.height;
+                                    let format = texture.get_format();
+                                    let width = stride.unwrap_or(format.row_size_in_bytes(rect.size.width));
+                                    let total_size = width * format.block_rows(rect.size.height);
                                     // WR haven't support RGBAF32 format in texture_cache, so
                                     // we use u8 type here.
                                     let dummy_data: Vec<u8> = vec![255; total_size as usize];
+                                    let mut uploader = self.device.upload_texture(
+                                        texture,
+                                        &self.texture_cache_upload_pbo,
+                                        0,
+                                    );
                                     uploader.upload(rect, layer_index, stride, &dummy_data)
                                 }
                                 ExternalImageSource::NativeTexture(eid) => {
-                                    panic!("Unexpected external texture {:?} for the texture cache update of {:?}", eid, id);
+                                    // An atlased external texture, which the
+                                    // resource cache only allows for 2D textures.
+                                    let external_texture = ExternalTexture::new(
+                                        eid,
+                                        TextureTarget::Default,
+                                    );
+                                    self.device.copy_external_texture(
+                                        &external_texture,
+                                        texture,
+                                        rect,
+                                        layer_index,
+                                    );
+                                    0
                                 }
                             };
                             handler.unlock(id, channel_index);
@@ -2696,12 +4096,15 @@ impl<B: hal::Backend> Renderer<B> {
         let batched = !self.debug_flags.contains(DebugFlags::DISABLE_BATCHING);
 
         if batched {
-            self.device
-                .update_vao_instances(vao, data, VertexUsageHint::Stream);
-            self.device
-                .draw_indexed_triangles_instanced_u16(6, data.len() as i32);
-            self.profile_counters.draw_calls.inc();
-            stats.total_draw_calls += 1;
+            let max_instances = self.batching_parameters.max_instances_per_draw.max(1);
+            for chunk in data.chunks(max_instances) {
+                self.device
+                    .update_vao_instances(vao, chunk, VertexUsageHint::Stream);
+                self.device
+                    .draw_indexed_triangles_instanced_u16(6, chunk.len() as i32);
+                self.profile_counters.draw_calls.inc();
+                stats.total_draw_calls += 1;
+            }
         } else {
             for i in 0 .. data.len() {
                 self.device
@@ -2754,24 +4157,34 @@ impl<B: hal::Backend> Renderer<B> {
             layer: readback_layer.0 as usize,
             with_depth: false,
         };
-        self.device.bind_draw_target(cache_draw_target);
-
-        let mut src = DeviceIntRect::new(
-            source_screen_origin + (backdrop_rect.origin - backdrop_screen_origin),
-            readback_rect.size,
-        );
-        let mut dest = readback_rect.to_i32();
-
-        // Need to invert the y coordinates and flip the image vertically when
-        // reading back from the framebuffer.
-        if cfg!(feature = "gleam") && draw_target.is_default() {
-            src.origin.y = draw_target.dimensions().height as i32 - src.size.height - src.origin.y;
+        let src = DeviceIntRect::new(source_screen_origin, readback_rect.size)
+            .checked_translate(&(backdrop_rect.origin - backdrop_screen_origin))
+            .and_then(|src| {
+                // Need to invert the y coordinates and flip the image vertically when
+                // reading back from a framebuffer with a bottom-left origin.
+                if draw_target.is_default() {
+                    self.device.surface_origin().checked_convert_rect(src, draw_target.dimensions().height)
+                } else {
+                    Some(src)
+                }
+            });
+        let mut dest = readback_rect;
+        if draw_target.is_default() && self.device.surface_origin().is_bottom_left() {
             dest.origin.y += dest.size.height;
             dest.size.height = -dest.size.height;
         }
 
-        self.device.bind_read_target(draw_target.into());
-        self.device.blit_render_target(src, dest);
+        match src {
+            Some(src) if readback_rect.checked_max().is_some() => {
+                self.device.bind_draw_target(cache_draw_target);
+                self.device.bind_read_target(draw_target.into());
+                self.device.blit_render_target(src, dest);
+            }
+            _ => {
+                warn!("Skipping readback of {:?} for {:?}", readback_rect, backdrop_rect);
+                self.renderer_errors.push(RendererError::InvalidRect(readback_rect));
+            }
+        }
 
         // Restore draw target to current pass render target + layer, and reset
         // the read target.
@@ -2820,6 +4233,10 @@ impl<B: hal::Backend> Renderer<B> {
                 }
             };
             debug_assert_eq!(source_rect.size, blit.target_rect.size);
+            if source_rect.checked_max().is_none() || blit.target_rect.checked_max().is_none() {
+                self.renderer_errors.push(RendererError::InvalidRect(blit.target_rect));
+                continue;
+            }
             self.device.blit_render_target(
                 source_rect,
                 blit.target_rect,
@@ -2840,18 +4257,21 @@ impl<B: hal::Backend> Renderer<B> {
 
         let _timer = self.gpu_profile.start_timer(GPU_TAG_SCALE);
 
-        match source {
+        let bound = match source {
             TextureSource::PrevPassColor => {
                 self.shaders.borrow_mut().cs_scale_rgba8.bind(&mut self.device,
                                                               &projection,
-                                                              &mut self.renderer_errors);
+                                                              &mut self.renderer_errors)
             }
             TextureSource::PrevPassAlpha => {
                 self.shaders.borrow_mut().cs_scale_a8.bind(&mut self.device,
                                                            &projection,
-                                                           &mut self.renderer_errors);
+                                                           &mut self.renderer_errors)
             }
             _ => unreachable!(),
+        };
+        if !bound {
+            return;
         }
 
         self.draw_instanced_batch(
@@ -2862,6 +4282,88 @@ impl<B: hal::Backend> Renderer<B> {
         );
     }
 
+    fn handle_svg_filters(
+        &mut self,
+        svg_filters: &[(BatchTextures, Vec<SvgFilterInstance>)],
+        projection: &Transform3D<f32>,
+        stats: &mut RendererStats,
+    ) {
+        if svg_filters.is_empty() {
+            return;
+        }
+
+        let _timer = self.gpu_profile.start_timer(GPU_TAG_SVG_FILTER);
+
+        let bound = self.shaders.borrow_mut().cs_svg_filter.bind(
+            &mut self.device,
+            &projection,
+            &mut self.renderer_errors,
+        );
+        if !bound {
+            return;
+        }
+
+        for &(ref textures, ref instances) in svg_filters {
+            self.draw_instanced_batch(
+                instances,
+                VertexArrayKind::SvgFilter,
+                textures,
+                stats,
+            );
+        }
+    }
+
+    /// Opens a nested GPU marker for every task on the path from the root of
+    /// the render task tree down to `task_id`, so that external GPU profilers
+    /// show the same hierarchy as the scene (picture -> filter -> blur).
+    /// The markers are closed when the returned vector is dropped.
+    ///
+    /// Only the GL device forwards markers. The command buffers of gfx-hal
+    /// 0.2 (`RawCommandBuffer`) have no debug marker commands, so markers
+    /// are no-ops on the hal device.
+    #[cfg(feature = "gleam")]
+    fn start_task_markers(
+        &mut self,
+        render_tasks: &RenderTaskTree,
+        task_id: RenderTaskId,
+    ) -> Vec<GpuMarker> {
+        if !self.gpu_profile.markers_enabled() {
+            return Vec::new();
+        }
+
+        render_tasks
+            .get_ancestry(task_id)
+            .into_iter()
+            .map(|id| self.gpu_profile.start_marker(&render_tasks.get_marker_label(id)))
+            .collect()
+    }
+
+    /// Emits a single event marker per distinct task in an instanced draw,
+    /// labelled with the full task path, since the draw itself can't be split.
+    #[cfg(feature = "gleam")]
+    fn place_task_markers<I>(&mut self, render_tasks: &RenderTaskTree, addresses: I)
+    where
+        I: Iterator<Item = RenderTaskAddress>,
+    {
+        if !self.gpu_profile.markers_enabled() {
+            return;
+        }
+
+        let mut placed = FastHashSet::default();
+        for address in addresses {
+            if !placed.insert(address.0) {
+                continue;
+            }
+            let path = render_tasks
+                .get_ancestry(render_tasks.get_task_id(address))
+                .into_iter()
+                .map(|id| render_tasks.get_marker_label(id))
+                .collect::<Vec<_>>()
+                .join(" > ");
+            self.gpu_profile.place_marker(&path);
+        }
+    }
+
     fn draw_color_target(
         &mut self,
         draw_target: DrawTarget,
@@ -2888,6 +4390,23 @@ impl<B: hal::Backend> Renderer<B> {
             FramebufferKind::Other
         };
 
+        // Only the damaged area of the main framebuffer is redrawn when
+        // presenting partially.
+        #[cfg(feature = "gleam")]
+        let partial_present = false;
+        #[cfg(not(feature = "gleam"))]
+        let partial_present = draw_target.is_default() && self.partial_present_rect.is_some();
+        #[cfg(not(feature = "gleam"))]
+        let framebuffer_target_rect = match self.partial_present_rect {
+            Some(rect) if partial_present => {
+                match framebuffer_target_rect.intersection(&rect) {
+                    Some(rect) if !rect.is_empty() => rect,
+                    _ => return,
+                }
+            }
+            _ => framebuffer_target_rect,
+        };
+
         {
             let _timer = self.gpu_profile.start_timer(GPU_TAG_SETUP_TARGET);
             self.device.bind_draw_target(draw_target);
@@ -2921,14 +4440,13 @@ impl<B: hal::Backend> Renderer<B> {
                 // whole screen is covered, no need for scissor
                 None
             } else {
-                let mut rect = framebuffer_target_rect.to_i32();
-                // Note: `framebuffer_target_rect` needs a Y-flip before going to GL
+                // Note: `framebuffer_target_rect` needs a Y-flip for a bottom-left surface origin
                 // Note: at this point, the target rectangle is not guaranteed to be within the main framebuffer bounds
                 // but `clear_target_rect` is totally fine with negative origin, as long as width & height are positive
-                if cfg!(feature = "gleam") {
-                    rect.origin.y = draw_target.dimensions().height as i32 - rect.origin.y - rect.size.height;
-                }
-                Some(rect)
+                Some(self.device.surface_origin().convert_rect(
+                    framebuffer_target_rect.to_i32(),
+                    draw_target.dimensions().height,
+                ))
             };
 
             self.device.clear_target(clear_color, depth_clear, clear_rect);
@@ -2951,10 +4469,15 @@ impl<B: hal::Backend> Renderer<B> {
             let _timer = self.gpu_profile.start_timer(GPU_TAG_BLUR);
 
             self.set_blend(false, framebuffer_kind);
-            self.shaders.borrow_mut().cs_blur_rgba8
+            let bound = self.shaders.borrow_mut().cs_blur_rgba8
                 .bind(&mut self.device, projection, &mut self.renderer_errors);
 
-            if !target.vertical_blurs.is_empty() {
+            if bound && !target.vertical_blurs.is_empty() {
+                #[cfg(feature = "gleam")]
+                self.place_task_markers(
+                    render_tasks,
+                    target.vertical_blurs.iter().map(|blur| blur.task_address),
+                );
                 self.draw_instanced_batch(
                     &target.vertical_blurs,
                     VertexArrayKind::Blur,
@@ -2963,7 +4486,12 @@ impl<B: hal::Backend> Renderer<B> {
                 );
             }
 
-            if !target.horizontal_blurs.is_empty() {
+            if bound && !target.horizontal_blurs.is_empty() {
+                #[cfg(feature = "gleam")]
+                self.place_task_markers(
+                    render_tasks,
+                    target.horizontal_blurs.iter().map(|blur| blur.task_address),
+                );
                 self.draw_instanced_batch(
                     &target.horizontal_blurs,
                     VertexArrayKind::Blur,
@@ -2975,6 +4503,8 @@ impl<B: hal::Backend> Renderer<B> {
 
         self.handle_scaling(&target.scalings, TextureSource::PrevPassColor, projection, stats);
 
+        self.handle_svg_filters(&target.svg_filters, projection, stats);
+
         // Small helper fn to iterate a regions list, also invoking the closure
         // if there are no regions.
         fn iterate_regions<F>(
@@ -2991,15 +4521,28 @@ impl<B: hal::Backend> Renderer<B> {
         }
 
         for alpha_batch_container in &target.alpha_batch_containers {
+            #[cfg(feature = "gleam")]
+            let _task_markers = match alpha_batch_container.task_id {
+                Some(task_id) => self.start_task_markers(render_tasks, task_id),
+                None => Vec::new(),
+            };
+
             let uses_scissor = alpha_batch_container.task_scissor_rect.is_some() ||
-                               !alpha_batch_container.regions.is_empty();
+                               !alpha_batch_container.regions.is_empty() ||
+                               partial_present;
 
             let scissor_rect;
             if uses_scissor {
                 self.device.enable_scissor();
+                let task_scissor_rect = if partial_present {
+                    alpha_batch_container.task_scissor_rect.or(Some(framebuffer_target_rect))
+                } else {
+                    alpha_batch_container.task_scissor_rect
+                };
                 scissor_rect = draw_target.build_scissor_rect(
-                    alpha_batch_container.task_scissor_rect,
+                    task_scissor_rect,
                     framebuffer_target_rect,
+                    self.device.surface_origin(),
                 );
                 self.device.set_scissor_rect(scissor_rect)
             }
@@ -3020,12 +4563,16 @@ impl<B: hal::Backend> Renderer<B> {
                     .iter()
                     .rev()
                 {
-                    self.shaders.borrow_mut()
+                    let bound = self.shaders.borrow_mut()
                         .get(&batch.key, self.debug_flags)
                         .bind(
                             &mut self.device, projection,
                             &mut self.renderer_errors,
                         );
+                    if !bound {
+                        // The shader is unavailable, this has already been logged.
+                        continue;
+                    }
 
                     let _timer = self.gpu_profile.start_timer(batch.key.kind.sampler_tag());
 
@@ -3036,6 +4583,7 @@ impl<B: hal::Backend> Renderer<B> {
                                 let scissor_rect = draw_target.build_scissor_rect(
                                     Some(region),
                                     framebuffer_target_rect,
+                                    self.device.surface_origin(),
                                 );
                                 self.device.set_scissor_rect(scissor_rect);
                             }
@@ -3045,12 +4593,18 @@ impl<B: hal::Backend> Renderer<B> {
                             #[cfg(all(not(feature = "gleam"), not(feature = "push_constants")))]
                             self.device.set_uniforms(&program, projection);
 
+                            #[cfg(not(feature = "gleam"))]
+                            self.device.set_anisotropic_sampling(batch.key.kind.uses_anisotropic_sampling());
+
                             self.draw_instanced_batch(
                                 &batch.instances,
                                 VertexArrayKind::Primitive,
                                 &batch.key.textures,
                                 stats
                             );
+
+                            #[cfg(not(feature = "gleam"))]
+                            self.device.set_anisotropic_sampling(false);
                         }
                     );
                 }
@@ -3066,12 +4620,16 @@ impl<B: hal::Backend> Renderer<B> {
                 let mut prev_blend_mode = BlendMode::None;
 
                 for batch in &alpha_batch_container.alpha_batches {
-                    self.shaders.borrow_mut()
+                    let bound = self.shaders.borrow_mut()
                         .get(&batch.key, self.debug_flags)
                         .bind(
                             &mut self.device, projection,
                             &mut self.renderer_errors,
                         );
+                    if !bound {
+                        // The shader is unavailable, this has already been logged.
+                        continue;
+                    }
 
                     if batch.key.blend_mode != prev_blend_mode {
                         match batch.key.blend_mode {
@@ -3111,17 +4669,21 @@ impl<B: hal::Backend> Renderer<B> {
                     }
 
                     // Handle special case readback for composites.
-                    if let BatchKind::Brush(BrushBatchKind::MixBlend { task_id, source_id, backdrop_id }) = batch.key.kind {
-                        // composites can't be grouped together because
-                        // they may overlap and affect each other.
-                        debug_assert_eq!(batch.instances.len(), 1);
-                        self.handle_readback_composite(
-                            draw_target,
-                            uses_scissor,
-                            &render_tasks[source_id],
-                            &render_tasks[task_id],
-                            &render_tasks[backdrop_id],
-                        );
+                    match batch.key.kind {
+                        BatchKind::Brush(BrushBatchKind::MixBlend { task_id, source_id, backdrop_id }) |
+                        BatchKind::Brush(BrushBatchKind::BackdropFilter { task_id, source_id, backdrop_id }) => {
+                            // composites can't be grouped together because
+                            // they may overlap and affect each other.
+                            debug_assert_eq!(batch.instances.len(), 1);
+                            self.handle_readback_composite(
+                                draw_target,
+                                uses_scissor,
+                                &render_tasks[source_id],
+                                &render_tasks[task_id],
+                                &render_tasks[backdrop_id],
+                            );
+                        }
+                        _ => {}
                     }
 
                     let _timer = self.gpu_profile.start_timer(batch.key.kind.sampler_tag());
@@ -3133,6 +4695,7 @@ impl<B: hal::Backend> Renderer<B> {
                                 let scissor_rect = draw_target.build_scissor_rect(
                                     Some(region),
                                     framebuffer_target_rect,
+                                    self.device.surface_origin(),
                                 );
                                 self.device.set_scissor_rect(scissor_rect);
                             }
@@ -3142,6 +4705,9 @@ impl<B: hal::Backend> Renderer<B> {
                             #[cfg(all(not(feature = "gleam"), not(feature = "push_constants")))]
                             self.device.set_uniforms(&program, projection);
 
+                            #[cfg(not(feature = "gleam"))]
+                            self.device.set_anisotropic_sampling(batch.key.kind.uses_anisotropic_sampling());
+
                             self.draw_instanced_batch(
                                 &batch.instances,
                                 VertexArrayKind::Primitive,
@@ -3149,6 +4715,9 @@ impl<B: hal::Backend> Renderer<B> {
                                 stats
                             );
 
+                            #[cfg(not(feature = "gleam"))]
+                            self.device.set_anisotropic_sampling(false);
+
                             if batch.key.blend_mode == BlendMode::SubpixelWithBgColor {
                                 self.set_blend_mode_subpixel_with_bg_color_pass1(framebuffer_kind);
                                 self.device.switch_mode(ShaderColorMode::SubpixelWithBgColorPass1 as _);
@@ -3231,9 +4800,9 @@ impl<B: hal::Backend> Renderer<B> {
                         blit.size,
                     );
 
-                    if cfg!(feature = "gleam") {
+                    if self.device.surface_origin().is_bottom_left() {
                         // Modify the src/dest rects since we are blitting from the framebuffer
-                        src_rect.origin.y = draw_target.dimensions().height as i32 - src_rect.size.height - src_rect.origin.y;
+                        src_rect = SurfaceOrigin::BottomLeft.convert_rect(src_rect, draw_target.dimensions().height);
                         dest_rect.origin.y += dest_rect.size.height;
                         dest_rect.size.height = -dest_rect.size.height;
                     }
@@ -3248,6 +4817,12 @@ impl<B: hal::Backend> Renderer<B> {
             }
         }
 
+        // The debug renderer only draws into the main framebuffer, so the
+        // batches of cache targets are outlined in the targets themselves.
+        if !draw_target.is_default() && self.debug_flags.contains(DebugFlags::SHOW_BATCH_RECTS) {
+            self.draw_batch_rects_into_target(&target.batch_rects);
+        }
+
         // For any registered image outputs on this render target,
         // get the texture from caller and blit it.
         for output in &target.outputs {
@@ -3336,10 +4911,15 @@ impl<B: hal::Backend> Renderer<B> {
             let _timer = self.gpu_profile.start_timer(GPU_TAG_BLUR);
 
             self.set_blend(false, FramebufferKind::Other);
-            self.shaders.borrow_mut().cs_blur_a8
+            let bound = self.shaders.borrow_mut().cs_blur_a8
                 .bind(&mut self.device, projection, &mut self.renderer_errors);
 
-            if !target.vertical_blurs.is_empty() {
+            if bound && !target.vertical_blurs.is_empty() {
+                #[cfg(feature = "gleam")]
+                self.place_task_markers(
+                    render_tasks,
+                    target.vertical_blurs.iter().map(|blur| blur.task_address),
+                );
                 self.draw_instanced_batch(
                     &target.vertical_blurs,
                     VertexArrayKind::Blur,
@@ -3348,7 +4928,12 @@ impl<B: hal::Backend> Renderer<B> {
                 );
             }
 
-            if !target.horizontal_blurs.is_empty() {
+            if bound && !target.horizontal_blurs.is_empty() {
+                #[cfg(feature = "gleam")]
+                self.place_task_markers(
+                    render_tasks,
+                    target.horizontal_blurs.iter().map(|blur| blur.task_address),
+                );
                 self.draw_instanced_batch(
                     &target.horizontal_blurs,
                     VertexArrayKind::Blur,
@@ -3371,17 +4956,19 @@ impl<B: hal::Backend> Renderer<B> {
             // draw rounded cornered rectangles
             if !target.clip_batcher.rectangles.is_empty() {
                 let _gm2 = self.gpu_profile.start_marker("clip rectangles");
-                self.shaders.borrow_mut().cs_clip_rectangle.bind(
+                let bound = self.shaders.borrow_mut().cs_clip_rectangle.bind(
                     &mut self.device,
                     projection,
                     &mut self.renderer_errors,
                 );
-                self.draw_instanced_batch(
-                    &target.clip_batcher.rectangles,
-                    VertexArrayKind::Clip,
-                    &BatchTextures::no_texture(),
-                    stats,
-                );
+                if bound {
+                    self.draw_instanced_batch(
+                        &target.clip_batcher.rectangles,
+                        VertexArrayKind::Clip,
+                        &BatchTextures::no_texture(),
+                        stats,
+                    );
+                }
             }
             // draw box-shadow clips
             for (mask_texture_id, items) in target.clip_batcher.box_shadows.iter() {
@@ -3393,8 +4980,11 @@ impl<B: hal::Backend> Renderer<B> {
                         TextureSource::Invalid,
                     ],
                 };
-                self.shaders.borrow_mut().cs_clip_box_shadow
+                let bound = self.shaders.borrow_mut().cs_clip_box_shadow
                     .bind(&mut self.device, projection, &mut self.renderer_errors);
+                if !bound {
+                    continue;
+                }
                 self.draw_instanced_batch(
                     items,
                     VertexArrayKind::Clip,
@@ -3413,8 +5003,11 @@ impl<B: hal::Backend> Renderer<B> {
                         TextureSource::Invalid,
                     ],
                 };
-                self.shaders.borrow_mut().cs_clip_image
+                let bound = self.shaders.borrow_mut().cs_clip_image
                     .bind(&mut self.device, projection, &mut self.renderer_errors);
+                if !bound {
+                    continue;
+                }
                 self.draw_instanced_batch(
                     items,
                     VertexArrayKind::Clip,
@@ -3446,7 +5039,6 @@ impl<B: hal::Backend> Renderer<B> {
                 target_size.width as f32,
                 0.0,
                 target_size.height as f32,
-                false,
             );
             (target_size, projection)
         };
@@ -3491,33 +5083,37 @@ impl<B: hal::Backend> Renderer<B> {
             self.set_blend_mode_premultiplied_alpha(FramebufferKind::Other);
 
             if !target.border_segments_solid.is_empty() {
-                self.shaders.borrow_mut().cs_border_solid.bind(
+                let bound = self.shaders.borrow_mut().cs_border_solid.bind(
                     &mut self.device,
                     &projection,
                     &mut self.renderer_errors,
                 );
 
-                self.draw_instanced_batch(
-                    &target.border_segments_solid,
-                    VertexArrayKind::Border,
-                    &BatchTextures::no_texture(),
-                    stats,
-                );
+                if bound {
+                    self.draw_instanced_batch(
+                        &target.border_segments_solid,
+                        VertexArrayKind::Border,
+                        &BatchTextures::no_texture(),
+                        stats,
+                    );
+                }
             }
 
             if !target.border_segments_complex.is_empty() {
-                self.shaders.borrow_mut().cs_border_segment.bind(
+                let bound = self.shaders.borrow_mut().cs_border_segment.bind(
                     &mut self.device,
                     &projection,
                     &mut self.renderer_errors,
                 );
 
-                self.draw_instanced_batch(
-                    &target.border_segments_complex,
-                    VertexArrayKind::Border,
-                    &BatchTextures::no_texture(),
-                    stats,
-                );
+                if bound {
+                    self.draw_instanced_batch(
+                        &target.border_segments_complex,
+                        VertexArrayKind::Border,
+                        &BatchTextures::no_texture(),
+                        stats,
+                    );
+                }
             }
 
             self.set_blend(false, FramebufferKind::Other);
@@ -3531,18 +5127,20 @@ impl<B: hal::Backend> Renderer<B> {
             self.set_blend_mode_premultiplied_alpha(FramebufferKind::Other);
 
             if !target.line_decorations.is_empty() {
-                self.shaders.borrow_mut().cs_line_decoration.bind(
+                let bound = self.shaders.borrow_mut().cs_line_decoration.bind(
                     &mut self.device,
                     &projection,
                     &mut self.renderer_errors,
                 );
 
-                self.draw_instanced_batch(
-                    &target.line_decorations,
-                    VertexArrayKind::LineDecoration,
-                    &BatchTextures::no_texture(),
-                    stats,
-                );
+                if bound {
+                    self.draw_instanced_batch(
+                        &target.line_decorations,
+                        VertexArrayKind::LineDecoration,
+                        &BatchTextures::no_texture(),
+                        stats,
+                    );
+                }
             }
 
             self.set_blend(false, FramebufferKind::Other);
@@ -3552,20 +5150,22 @@ impl<B: hal::Backend> Renderer<B> {
         if !target.horizontal_blurs.is_empty() {
             let _timer = self.gpu_profile.start_timer(GPU_TAG_BLUR);
 
-            {
+            let bound = {
                 let mut shaders = self.shaders.borrow_mut();
                 match target.target_kind {
                     RenderTargetKind::Alpha => &mut shaders.cs_blur_a8,
                     RenderTargetKind::Color => &mut shaders.cs_blur_rgba8,
-                }.bind(&mut self.device, &projection, &mut self.renderer_errors);
+                }.bind(&mut self.device, &projection, &mut self.renderer_errors)
+            };
+
+            if bound {
+                self.draw_instanced_batch(
+                    &target.horizontal_blurs,
+                    VertexArrayKind::Blur,
+                    &BatchTextures::no_texture(),
+                    stats,
+                );
             }
-
-            self.draw_instanced_batch(
-                &target.horizontal_blurs,
-                VertexArrayKind::Blur,
-                &BatchTextures::no_texture(),
-                stats,
-            );
         }
 
         // Blit any Pathfinder glyphs to the cache texture.
@@ -3603,6 +5203,11 @@ impl<B: hal::Backend> Renderer<B> {
             .as_mut()
             .expect("Found external image, but no handler set!");
 
+        // The UV rects written for the last rendered frame are still in the
+        // GPU cache if nothing else has been written to it since.
+        let gpu_cache_unchanged = !self.gpu_cache_texture.has_pending_updates();
+        let mut stamps = FastHashMap::default();
+
         let mut list = GpuCacheUpdateList {
             frame_id: FrameId::INVALID,
             clear: false,
@@ -3610,6 +5215,8 @@ impl<B: hal::Backend> Renderer<B> {
             blocks: Vec::new(),
             updates: Vec::new(),
             debug_commands: Vec::new(),
+            requested_rows: 0,
+            coalesced_rows: 0,
         };
 
         for deferred_resolve in deferred_resolves {
@@ -3627,9 +5234,24 @@ impl<B: hal::Backend> Renderer<B> {
                 }
             };
 
+            let key = (ext_image.id, ext_image.channel_index, deferred_resolve.address);
+            let stamp = image.timestamp.map(|timestamp| ExternalImageStamp {
+                timestamp,
+                uv: image.uv,
+                gpu_cache_frame_id: self.gpu_cache_frame_id,
+            });
+            let previous_stamp = self.external_image_stamps.get(&key);
+            let is_unchanged = match (&stamp, previous_stamp) {
+                (&Some(ref stamp), Some(previous)) => stamp.timestamp == previous.timestamp,
+                _ => false,
+            };
+
             // In order to produce the handle, the external image handler may call into
-            // the GL context and change some states.
-            self.device.reset_state();
+            // the GL context and change some states. It doesn't for images it returns
+            // with an unchanged timestamp.
+            if !is_unchanged {
+                self.device.reset_state();
+            }
 
             let texture = match image.source {
                 ExternalImageSource::NativeTexture(texture_id) => {
@@ -3654,6 +5276,15 @@ impl<B: hal::Backend> Renderer<B> {
                 .external_images
                 .insert((ext_image.id, ext_image.channel_index), texture);
 
+            if let Some(stamp) = stamp {
+                let uv_rect_is_valid = gpu_cache_unchanged && is_unchanged &&
+                    previous_stamp.map_or(false, |previous| previous.has_same_uv_rect(&stamp));
+                stamps.insert(key, stamp);
+                if uv_rect_is_valid {
+                    continue;
+                }
+            }
+
             list.updates.push(GpuCacheUpdate::Copy {
                 block_index: list.blocks.len(),
                 block_count: BLOCKS_PER_UV_RECT,
@@ -3663,7 +5294,13 @@ impl<B: hal::Backend> Renderer<B> {
             list.blocks.push([0f32; 4].into());
         }
 
-        Some(list)
+        self.external_image_stamps = stamps;
+
+        if list.updates.is_empty() {
+            None
+        } else {
+            Some(list)
+        }
     }
 
     fn unlock_external_images(&mut self) {
@@ -3849,27 +5486,93 @@ impl<B: hal::Backend> Renderer<B> {
                 RenderPassKind::MainFramebuffer(ref target) => {
                     if let Some(framebuffer_size) = framebuffer_size {
                         stats.color_target_count += 1;
-
-                        let clear_color = frame.background_color.map(|color| color.to_array());
+                        self.gpu_profile.set_timer_group(GpuPassKind::MainFramebuffer as u32);
+
+                        // With a native compositor, the tiles are presented
+                        // below the main framebuffer, which has to let them
+                        // show through.
+                        let native_compositor = self.compositor.is_native();
+                        let clear_color = if native_compositor {
+                            Some([0.0, 0.0, 0.0, 0.0])
+                        } else {
+                            frame.background_color.map(|color| color.to_array())
+                        };
                         let projection = create_projection(
                             0.0,
                             framebuffer_size.width as f32,
                             framebuffer_size.height as f32,
                             0.0,
-                            true,
                         );
+                        let projection = self.device.surface_origin().apply_to_projection(projection);
+                        #[cfg(not(feature = "gleam"))]
+                        let projection = self.device.surface_transform().apply_to_projection(projection);
+
+                        let debug_flags = self.debug_flags;
+                        #[cfg(not(feature = "gleam"))]
+                        let partial_present_rect = self.partial_present_rect;
+                        for (rect, flags) in self.main_framebuffer_views(framebuffer_size) {
+                            #[cfg(not(feature = "gleam"))]
+                            {
+                                if rect.is_some() {
+                                    self.partial_present_rect = rect;
+                                }
+                            }
+                            #[cfg(feature = "gleam")]
+                            let _ = rect;
+                            self.debug_flags = debug_flags | flags;
 
-                        self.draw_color_target(
-                            DrawTarget::Default(framebuffer_size),
-                            target,
-                            frame.inner_rect,
-                            framebuffer_depth_is_ready,
-                            clear_color,
-                            &frame.render_tasks,
-                            &projection,
-                            frame_id,
-                            stats,
-                        );
+                            self.draw_color_target(
+                                DrawTarget::Default(framebuffer_size),
+                                target,
+                                frame.inner_rect,
+                                framebuffer_depth_is_ready,
+                                clear_color,
+                                &frame.render_tasks,
+                                &projection,
+                                frame_id,
+                                stats,
+                            );
+                        }
+                        self.debug_flags = debug_flags;
+                        #[cfg(not(feature = "gleam"))]
+                        {
+                            self.partial_present_rect = partial_present_rect;
+                            if self.split_screen.is_some() {
+                                if let Some(debug_renderer) = self.debug.get_mut(&mut self.device) {
+                                    let x = (framebuffer_size.width / 2) as f32;
+                                    let color = debug_colors::WHITE.into();
+                                    debug_renderer.add_quad(
+                                        x - 1.0,
+                                        0.0,
+                                        x + 1.0,
+                                        framebuffer_size.height as f32,
+                                        color,
+                                        color,
+                                    );
+                                }
+                            }
+                        }
+
+                        // The tile blits of the target are done, so the
+                        // tile textures are up to date.
+                        if native_compositor {
+                            self.compositor.begin_frame(frame.background_color);
+                            for tile in &target.composite_tiles {
+                                let texture = match self.texture_resolver.resolve(&tile.texture) {
+                                    Some(texture) => texture,
+                                    None => continue,
+                                };
+                                self.compositor.add_surface(&CompositeSurface {
+                                    id: tile.id,
+                                    offset: tile.offset,
+                                    clip_rect: tile.clip_rect,
+                                    texture,
+                                    texture_layer: tile.texture_layer,
+                                    uv_rect: tile.uv_rect,
+                                });
+                            }
+                            self.compositor.end_frame();
+                        }
                     }
 
                     (None, None)
@@ -3882,6 +5585,7 @@ impl<B: hal::Backend> Renderer<B> {
                     // cache targets have already been updated and can be
                     // skipped this time.
                     if !frame.has_been_rendered {
+                        self.gpu_profile.set_timer_group(GpuPassKind::TextureCache as u32);
                         for (&(texture_id, target_index), target) in texture_cache {
                             self.draw_texture_cache_target(
                                 &texture_id,
@@ -3893,6 +5597,7 @@ impl<B: hal::Backend> Renderer<B> {
                         }
                     }
 
+                    self.gpu_profile.set_timer_group(GpuPassKind::AlphaTarget as u32);
                     for (target_index, target) in alpha.targets.iter().enumerate() {
                         stats.alpha_target_count += 1;
                         let draw_target = DrawTarget::Texture {
@@ -3906,7 +5611,6 @@ impl<B: hal::Backend> Renderer<B> {
                             draw_target.dimensions().width as f32,
                             0.0,
                             draw_target.dimensions().height as f32,
-                            false,
                         );
 
                         self.draw_alpha_target(
@@ -3918,6 +5622,7 @@ impl<B: hal::Backend> Renderer<B> {
                         );
                     }
 
+                    self.gpu_profile.set_timer_group(GpuPassKind::ColorTarget as u32);
                     for (target_index, target) in color.targets.iter().enumerate() {
                         stats.color_target_count += 1;
                         let draw_target = DrawTarget::Texture {
@@ -3931,7 +5636,6 @@ impl<B: hal::Backend> Renderer<B> {
                             draw_target.dimensions().width as f32,
                             0.0,
                             draw_target.dimensions().height as f32,
-                            false,
                         );
 
                         self.draw_color_target(
@@ -3951,6 +5655,13 @@ impl<B: hal::Backend> Renderer<B> {
                 }
             };
 
+            #[cfg(feature = "capture")]
+            {
+                if self.target_capture.is_some() {
+                    self.copy_pass_targets_for_capture(cur_alpha.as_ref(), cur_color.as_ref());
+                }
+            }
+
             self.texture_resolver.end_pass(
                 &mut self.device,
                 cur_alpha,
@@ -3959,12 +5670,15 @@ impl<B: hal::Backend> Renderer<B> {
         }
 
         self.texture_resolver.end_frame(&mut self.device, frame_id);
+        self.gpu_profile.set_timer_group(GpuPassKind::Other as u32);
 
         if let Some(framebuffer_size) = framebuffer_size {
             self.draw_frame_debug_items(&frame.debug_items);
+            self.draw_batch_rects_debug(frame);
             self.draw_render_target_debug(framebuffer_size);
             self.draw_texture_cache_debug(framebuffer_size);
             self.draw_gpu_cache_debug(framebuffer_size);
+            self.draw_zoom_debug(framebuffer_size);
         }
         self.draw_epoch_debug();
 
@@ -3990,6 +5704,9 @@ impl<B: hal::Backend> Renderer<B> {
     }
 
     pub fn set_debug_flags(&mut self, flags: DebugFlags) {
+        if flags != self.debug_flags {
+            self.content_changed = true;
+        }
         if let Some(enabled) = flag_changed(self.debug_flags, flags, DebugFlags::GPU_TIME_QUERIES) {
             if enabled {
                 self.gpu_profile.enable_timers();
@@ -4051,6 +5768,62 @@ impl<B: hal::Backend> Renderer<B> {
         }
     }
 
+    fn draw_batch_rects_debug(&mut self, frame: &Frame) {
+        if !self.debug_flags.contains(DebugFlags::SHOW_BATCH_RECTS) {
+            return;
+        }
+
+        let debug_renderer = match self.debug.get_mut(&mut self.device) {
+            Some(render) => render,
+            None => return,
+        };
+
+        for pass in &frame.passes {
+            let target = match pass.kind {
+                RenderPassKind::MainFramebuffer(ref target) => target,
+                RenderPassKind::OffScreen { .. } => continue,
+            };
+            for batch in &target.batch_rects {
+                let color = batch.kind.sampler_tag().color.into();
+                for item_rect in &batch.rects {
+                    debug_renderer.add_rect(&item_rect.round_out().to_i32(), color);
+                }
+                if let Some(item_rect) = batch.rects.first() {
+                    debug_renderer.add_text(
+                        item_rect.origin.x + 2.0,
+                        item_rect.origin.y + 14.0,
+                        batch.kind.debug_name(),
+                        color,
+                        None,
+                    );
+                }
+            }
+        }
+    }
+
+    /// Outlines the primitives of the batches of the bound target, one
+    /// pixel wide.
+    fn draw_batch_rects_into_target(&mut self, batch_rects: &[BatchRects<DeviceRect>]) {
+        for batch in batch_rects {
+            let color = batch.kind.sampler_tag().color.to_array();
+            for item_rect in &batch.rects {
+                let item_rect = item_rect.round_out().to_i32();
+                let (x0, y0) = (item_rect.origin.x, item_rect.origin.y);
+                let (x1, y1) = (item_rect.max_x() - 1, item_rect.max_y() - 1);
+                let (width, height) = (item_rect.size.width, item_rect.size.height);
+                let edges = [
+                    rect(x0, y0, width, 1),
+                    rect(x0, y1, width, 1),
+                    rect(x0, y0, 1, height),
+                    rect(x1, y0, 1, height),
+                ];
+                for edge in &edges {
+                    self.device.clear_target(Some(color), None, Some(*edge));
+                }
+            }
+        }
+    }
+
     fn draw_render_target_debug(&mut self, framebuffer_size: DeviceIntSize) {
         if !self.debug_flags.contains(DebugFlags::RENDER_TARGET_DBG) {
             return;
@@ -4063,6 +5836,11 @@ impl<B: hal::Backend> Renderer<B> {
 
         let textures =
             self.texture_resolver.render_target_pool.iter().collect::<Vec<&Texture>>();
+        #[cfg(feature = "replay")]
+        let textures = textures
+            .into_iter()
+            .chain(&self.captured_targets)
+            .collect::<Vec<&Texture>>();
 
         Self::do_debug_blit(
             &mut self.device,
@@ -4159,19 +5937,15 @@ impl<B: hal::Backend> Renderer<B> {
                 let text_margin = 1;
                 let text_height = 14; // Visually aproximated.
                 let tag_height = text_height + text_margin * 2;
-                let tag_rect = if cfg!(feature = "gleam") {
-                    rect(x, y, size, tag_height)
-                } else {
-                    rect(x, fb_height - (y + tag_height), size, tag_height)
-                };
+                let surface_origin = device.surface_origin();
+                // Top-relative.
+                let text_rect = rect(x, fb_height - (y + tag_height), size, tag_height);
+                let tag_rect = surface_origin.convert_rect(text_rect, fb_height);
                 let tag_color = select_color(texture);
                 device.clear_target(Some(tag_color), None, Some(tag_rect));
 
                 // Draw the dimensions onto the tag.
                 let dim = texture.get_dimensions();
-                let mut text_rect = tag_rect;
-                text_rect.origin.y =
-                    fb_height - text_rect.origin.y - text_rect.size.height; // Top-relative.
                 debug_renderer.add_text(
                     (x + text_margin) as f32,
                     (fb_height - y - text_margin) as f32, // Top-relative.
@@ -4180,20 +5954,106 @@ impl<B: hal::Backend> Renderer<B> {
                     Some(text_rect.to_f32())
                 );
 
-                // Blit the contents of the layer. We need to invert Y because
-                // we're blitting from a texture to the main framebuffer, which
-                // use different conventions.
-                let dest_rect = if cfg!(feature = "gleam") {
-                    rect(x, y + tag_height, size, size)
+                // Blit the contents of the layer. We need to invert Y if the
+                // main framebuffer uses a different convention than textures.
+                let dest_rect = surface_origin.convert_rect(
+                    rect(x, fb_height - (y + tag_height + size), size, size),
+                    fb_height,
+                );
+                if surface_origin.is_bottom_left() {
+                    device.blit_render_target_invert_y(src_rect, dest_rect);
                 } else {
-                    rect(x, fb_height - (y + tag_height + size), size, size)
-                };
-                device.blit_render_target_invert_y(src_rect, dest_rect);
+                    device.blit_render_target(src_rect, dest_rect);
+                }
                 i += 1;
             }
         }
     }
 
+    /// Magnifies the region of the main framebuffer around the debug cursor
+    /// into its bottom-right corner. The region is copied into a texture
+    /// first, since the framebuffer can't be blitted onto itself.
+    fn draw_zoom_debug(&mut self, framebuffer_size: DeviceIntSize) {
+        if !self.debug_flags.contains(DebugFlags::ZOOM_DBG) {
+            return;
+        }
+        let cursor = match self.debug_cursor {
+            Some(cursor) => cursor,
+            None => return,
+        };
+
+        let source_size = DeviceIntSize::new(ZOOM_DEBUG_SOURCE_SIZE, ZOOM_DEBUG_SOURCE_SIZE);
+        let target_size = source_size * ZOOM_DEBUG_FACTOR;
+        let margin = 16;
+        let target_rect = DeviceIntRect::new(
+            DeviceIntPoint::new(
+                framebuffer_size.width - target_size.width - margin,
+                framebuffer_size.height - target_size.height - margin,
+            ),
+            target_size,
+        );
+        if target_rect.origin.x < 0 || target_rect.origin.y < 0 {
+            return;
+        }
+        let source_rect = DeviceIntRect::new(
+            DeviceIntPoint::new(
+                (cursor.x - source_size.width / 2)
+                    .min(framebuffer_size.width - source_size.width)
+                    .max(0),
+                (cursor.y - source_size.height / 2)
+                    .min(framebuffer_size.height - source_size.height)
+                    .max(0),
+            ),
+            source_size,
+        );
+
+        if self.zoom_debug_texture.is_none() {
+            let texture = self.device.create_texture(
+                TextureTarget::Default,
+                ImageFormat::BGRA8,
+                source_size.width,
+                source_size.height,
+                TextureFilter::Nearest,
+                Some(RenderTargetInfo { has_depth: false }),
+                1,
+            );
+            self.zoom_debug_texture = Some(texture);
+        }
+
+        let fb_height = framebuffer_size.height;
+        let surface_origin = self.device.surface_origin();
+        let texture = self.zoom_debug_texture.as_ref().unwrap();
+        let texture_rect = DeviceIntRect::new(DeviceIntPoint::zero(), source_size);
+
+        self.device.bind_read_target(ReadTarget::Default);
+        self.device.bind_draw_target(DrawTarget::Texture { texture, layer: 0, with_depth: false });
+        self.device.blit_render_target_nearest(
+            surface_origin.convert_rect(source_rect, fb_height),
+            texture_rect,
+        );
+        self.device.bind_read_target(ReadTarget::Texture { texture, layer: 0 });
+        self.device.bind_draw_target(DrawTarget::Default(framebuffer_size));
+        self.device.blit_render_target_nearest(
+            texture_rect,
+            surface_origin.convert_rect(target_rect, fb_height),
+        );
+
+        let debug_renderer = match self.debug.get_mut(&mut self.device) {
+            Some(render) => render,
+            None => return,
+        };
+        let color = debug_colors::RED.into();
+        debug_renderer.add_rect(&source_rect.inflate(1, 1), color);
+        debug_renderer.add_rect(&target_rect.inflate(1, 1), color);
+        debug_renderer.add_text(
+            target_rect.origin.x as f32,
+            (target_rect.origin.y - 4) as f32,
+            &format!("x{} at ({}, {})", ZOOM_DEBUG_FACTOR, cursor.x, cursor.y),
+            color,
+            None,
+        );
+    }
+
     fn draw_epoch_debug(&mut self) {
         if !self.debug_flags.contains(DebugFlags::EPOCHS) {
             return;
@@ -4279,6 +6139,68 @@ impl<B: hal::Backend> Renderer<B> {
         pixels
     }
 
+    /// Starts reading back a rect of the last rendered frame as RGBA8 without
+    /// stalling on the GPU. Use `poll_readback` to get the pixels.
+    pub fn request_read_pixels(&mut self, rect: DeviceIntRect) -> ReadbackHandle {
+        self.device.request_read_pixels(rect, ReadPixelsFormat::Rgba8)
+    }
+
+    /// Returns the pixels of a read back once they are available, or the
+    /// handle to poll again later.
+    pub fn poll_readback(&mut self, handle: ReadbackHandle) -> Result<Vec<u8>, ReadbackHandle> {
+        self.device.poll_readback(handle)
+    }
+
+    /// Returns the rect of the pixel at `point` if it is inside of the last
+    /// rendered framebuffer.
+    fn pixel_rect(&self, point: DeviceIntPoint) -> Option<DeviceIntRect> {
+        let framebuffer_rect = DeviceIntRect::new(DeviceIntPoint::zero(), self.framebuffer_size?);
+        let rect = DeviceIntRect::new(point, DeviceIntSize::new(1, 1));
+        if framebuffer_rect.contains_rect(&rect) {
+            Some(rect)
+        } else {
+            None
+        }
+    }
+
+    /// Reads the color of a single pixel of the last presented frame. The
+    /// point follows the same convention as `read_pixels_into`. This waits
+    /// for the GPU, see `schedule_read_pixel_at` for a non-blocking version.
+    /// Returns `None` if the point is outside of the framebuffer.
+    pub fn read_pixel_at(&mut self, point: DeviceIntPoint) -> Option<ColorU> {
+        let rect = self.pixel_rect(point)?;
+        let mut pixel = [0; 4];
+        self.device.read_pixels_into(rect, ReadPixelsFormat::Rgba8, &mut pixel);
+        Some(ColorU::new(pixel[0], pixel[1], pixel[2], pixel[3]))
+    }
+
+    /// Reads the color of a single pixel once the next frame is presented,
+    /// without stalling on the GPU. Use `poll_pixel_read` to get the color.
+    /// Replaces any read that didn't start yet. Returns `false` without
+    /// scheduling anything if the previous read wasn't returned by
+    /// `poll_pixel_read` yet, or if the point is outside of the framebuffer.
+    pub fn schedule_read_pixel_at(&mut self, point: DeviceIntPoint) -> bool {
+        if self.pending_pixel_read.is_some() || self.pixel_rect(point).is_none() {
+            return false;
+        }
+        self.scheduled_pixel_read = Some(point);
+        true
+    }
+
+    /// Returns the color read by `schedule_read_pixel_at` once the GPU has
+    /// finished copying it.
+    pub fn poll_pixel_read(&mut self) -> Option<ColorU> {
+        let handle = self.pending_pixel_read.take()?;
+        match self.device.poll_readback(handle) {
+            Ok(pixel) => Some(ColorU::new(pixel[0], pixel[1], pixel[2], pixel[3])),
+            Err(handle) => {
+                self.pending_pixel_read = Some(handle);
+                None
+            }
+        }
+    }
+
+    /// Reads back the `RGBAF32` GPU cache texture.
     pub fn read_gpu_cache(&mut self) -> (DeviceIntSize, Vec<u8>) {
         let texture = self.gpu_cache_texture.texture.as_ref().unwrap();
         let size = texture.get_dimensions();
@@ -4297,6 +6219,9 @@ impl<B: hal::Backend> Renderer<B> {
 
     // De-initialize the Renderer safely, assuming the GL is still alive and active.
     pub fn deinit(mut self) {
+        if let Err(e) = self.stop_trace() {
+            error!("Unable to finish the trace: {:?}", e);
+        }
         //Note: this is a fake frame, only needed because texture deletion is require to happen inside a frame
         self.device.begin_frame();
         #[cfg(not(feature = "gleam"))]
@@ -4305,6 +6230,9 @@ impl<B: hal::Backend> Renderer<B> {
         if let Some(dither_matrix_texture) = self.dither_matrix_texture {
             self.device.delete_texture(dither_matrix_texture);
         }
+        if let Some(zoom_debug_texture) = self.zoom_debug_texture {
+            self.device.delete_texture(zoom_debug_texture);
+        }
         self.transforms_texture.deinit(&mut self.device);
         self.prim_header_f_texture.deinit(&mut self.device);
         self.prim_header_i_texture.deinit(&mut self.device);
@@ -4317,6 +6245,7 @@ impl<B: hal::Backend> Renderer<B> {
         self.device.delete_vao(self.vaos.line_vao);
         self.device.delete_vao(self.vaos.border_vao);
         self.device.delete_vao(self.vaos.scale_vao);
+        self.device.delete_vao(self.vaos.svg_filter_vao);
 
         self.debug.deinit(&mut self.device);
 
@@ -4328,38 +6257,50 @@ impl<B: hal::Backend> Renderer<B> {
         }
         #[cfg(feature = "capture")]
         self.device.delete_fbo(self.read_fbo);
+        #[cfg(feature = "capture")]
+        for (_, passes) in self.target_capture {
+            for (alpha, color) in passes {
+                for texture in alpha.into_iter().chain(color) {
+                    self.device.delete_texture(texture);
+                }
+            }
+        }
         #[cfg(feature = "replay")]
         for (_, ext) in self.owned_external_images {
             self.device.delete_external_texture(ext);
         }
+        #[cfg(feature = "replay")]
+        for texture in self.captured_targets {
+            self.device.delete_texture(texture);
+        }
         self.device.end_frame();
         #[cfg(not(feature = "gleam"))]
         self.device.deinit();
     }
 
     fn size_of<T>(&self, ptr: *const T) -> usize {
-        let op = self.size_of_ops.as_ref().unwrap().size_of_op;
-        unsafe { op(ptr as *const c_void) }
+        match self.size_of_ops {
+            Some(ref ops) => unsafe { (ops.size_of_op)(ptr as *const c_void) },
+            None => 0,
+        }
     }
 
     /// Collects a memory report.
+    ///
+    /// The GPU memory is always reported. The CPU heap sizes are only
+    /// measured if `RendererOptions::size_of_op` is set.
     pub fn report_memory(&self) -> MemoryReport {
         let mut report = MemoryReport::default();
 
         // GPU cache CPU memory.
-        match self.gpu_cache_texture.bus {
-            GpuCacheBus::PixelBuffer{ref rows, ..} => {
-                for row in rows.iter() {
-                    report.gpu_cache_cpu_mirror += self.size_of(&*row.cpu_blocks as *const _);
-                }
-            }
-            #[cfg(feature = "gleam")]
-            _ => {}
-        }
+        report.gpu_cache_cpu_mirror += self.gpu_cache_texture.staging
+            .size_of_rows(|blocks| self.size_of(blocks));
 
         // GPU cache GPU memory.
-        report.gpu_cache_textures +=
-            self.gpu_cache_texture.texture.as_ref().map_or(0, |t| t.size_in_bytes());
+        if let Some(ref texture) = self.gpu_cache_texture.texture {
+            report.gpu_cache_textures += texture.size_in_bytes();
+            report.texture_formats.add(texture.get_format(), texture.size_in_bytes());
+        }
 
         // Render task CPU memory.
         for (_id, doc) in &self.active_documents {
@@ -4368,15 +6309,16 @@ impl<B: hal::Backend> Renderer<B> {
         }
 
         // Vertex data GPU memory.
-        report.vertex_data_textures += self.prim_header_f_texture.size_in_bytes();
-        report.vertex_data_textures += self.prim_header_i_texture.size_in_bytes();
-        report.vertex_data_textures += self.transforms_texture.size_in_bytes();
-        report.vertex_data_textures += self.render_task_texture.size_in_bytes();
+        self.prim_header_f_texture.report_memory(&mut report);
+        self.prim_header_i_texture.report_memory(&mut report);
+        self.transforms_texture.report_memory(&mut report);
+        self.render_task_texture.report_memory(&mut report);
 
         // Texture cache and render target GPU memory.
         report += self.texture_resolver.report_memory();
 
-        // Textures held internally within the device layer.
+        // Depth targets, the swap chain and the vertex, instance and upload
+        // buffers held internally within the device layer.
         report += self.device.report_memory();
 
         report
@@ -4443,7 +6385,7 @@ impl<B: hal::Backend> Renderer<B> {
 
 pub enum ExternalImageSource<'a> {
     RawData(&'a [u8]),  // raw buffers.
-    NativeTexture(u32), // It's a gl::GLuint texture handle
+    NativeTexture(u32), // It's a gl::GLuint texture handle, or an imported image with gfx-hal
     Invalid,
 }
 
@@ -4459,6 +6401,30 @@ pub enum ExternalImageSource<'a> {
 pub struct ExternalImage<'a> {
     pub uv: TexelRect,
     pub source: ExternalImageSource<'a>,
+    /// Identifies the content of the image, or `None` if it can change
+    /// at any time. While a `NativeTexture` image is locked with the same
+    /// timestamp as in the previous frame, the renderer doesn't update its
+    /// UV rect in the GPU cache, and doesn't reset the device state after
+    /// `lock`, which must then not have changed it.
+    pub timestamp: Option<u64>,
+}
+
+/// The state of an external image when it was last resolved.
+struct ExternalImageStamp {
+    timestamp: u64,
+    uv: TexelRect,
+    /// The latest GPU cache frame uploaded when the image was resolved.
+    gpu_cache_frame_id: FrameId,
+}
+
+impl ExternalImageStamp {
+    /// Returns true if the UV rect written for `self` is still valid for
+    /// `other`.
+    fn has_same_uv_rect(&self, other: &ExternalImageStamp) -> bool {
+        self.uv.uv0 == other.uv.uv0 &&
+            self.uv.uv1 == other.uv.uv1 &&
+            self.gpu_cache_frame_id == other.gpu_cache_frame_id
+    }
 }
 
 /// The interfaces that an application can implement to support providing
@@ -4548,6 +6514,9 @@ pub struct RendererOptions {
     pub enable_dithering: bool,
     pub max_recorded_profiles: usize,
     pub precache_flags: ShaderPrecacheFlags,
+    /// Called once all shaders are built because of `precache_flags`. With
+    /// `ASYNC_COMPILE` the hal backend builds them after rendered frames.
+    pub shader_precache_callback: Option<Box<Fn()>>,
     pub renderer_kind: RendererKind,
     pub enable_subpixel_aa: bool,
     pub clear_color: Option<ColorF>,
@@ -4571,8 +6540,66 @@ pub struct RendererOptions {
     pub support_low_priority_transactions: bool,
     pub namespace_alloc_by_client: bool,
     pub enable_picture_caching: bool,
+    /// How pipelines marked as lost with `Transaction::mark_pipeline_lost`
+    /// are presented.
+    pub lost_pipeline_treatment: LostPipelineTreatment,
+    /// Overrides the batching parameters detected for the GPU vendor.
+    pub batching_parameters: Option<BatchingParameters>,
+    /// Copy small external textures that don't change every frame into the
+    /// texture cache, so that they batch with other images.
+    pub atlas_external_images: bool,
+    /// Don't block frame building on blob image tiles that weren't rasterized
+    /// ahead of time. They are rasterized on the (low priority, if supported)
+    /// scene builder thread instead, and a new frame is built once they are
+    /// done. See `RenderNotifier::blob_rasterization_pending`.
+    pub async_blob_rasterization: bool,
     #[cfg(not(feature = "gleam"))]
     pub heaps_config: HeapsConfig,
+    /// A directory the compiled pipelines are cached in across runs. Takes
+    /// precedence over the cache path of the `DeviceInit`.
+    #[cfg(not(feature = "gleam"))]
+    pub cached_pipelines_path: Option<PathBuf>,
+    /// Counts the validation errors of the backend per frame, and limits the
+    /// rate of its validation messages when they go through a
+    /// `ValidationLogSink`. The layers themselves are loaded when the
+    /// instance is created, see `enable_validation_layers`.
+    #[cfg(not(feature = "gleam"))]
+    pub enable_gpu_validation: bool,
+    /// The present mode of the main window swapchain. Falls back to the best
+    /// supported mode (`Mailbox`, then `Fifo`) if `None` or not supported.
+    #[cfg(not(feature = "gleam"))]
+    pub present_mode: Option<hal::window::PresentMode>,
+    /// The pixel format of the main framebuffer. Falls back to `Bgra8Unorm`
+    /// if the surface doesn't support it.
+    #[cfg(not(feature = "gleam"))]
+    pub output_format: OutputFormat,
+    /// The color space of the main framebuffer. HDR color spaces need the
+    /// `Rgba16Float` output format.
+    #[cfg(not(feature = "gleam"))]
+    pub color_space: ColorSpace,
+    /// Lets `Renderer::render_with_dirty_rects` redraw only the area of the
+    /// main framebuffer that changed.
+    #[cfg(not(feature = "gleam"))]
+    pub enable_partial_present: bool,
+    /// The maximum anisotropy used to sample images drawn under a perspective
+    /// or rotated transform. Values of 1 or less disable anisotropic filtering.
+    #[cfg(not(feature = "gleam"))]
+    pub max_anisotropy: u8,
+    /// GPU time budgets of the render passes, see `Renderer::set_pass_budgets`.
+    pub pass_budgets: Vec<PassBudget>,
+    /// The scale of the debug overlay text.
+    pub debug_text_scale: f32,
+    /// Overrides the color of the debug overlay text, if set.
+    pub debug_text_color: Option<ColorU>,
+    /// Blurs with a larger standard deviation (in device pixels) are rendered
+    /// at half resolution, repeatedly, until they fit, and then upscaled.
+    /// Lower values bound the size of the blur targets at the cost of quality.
+    pub max_blur_std_dev: f32,
+    /// Overrides the driver workarounds detected for the adapter, e.g. to
+    /// test a workaround on hardware that doesn't need it.
+    pub workarounds: Option<Workarounds>,
+    /// Presents the picture cache tiles, `DrawCompositor` if `None`.
+    pub compositor: Option<Box<Compositor>>,
 }
 
 impl Default for RendererOptions {
@@ -4585,6 +6612,7 @@ impl Default for RendererOptions {
             debug_flags: DebugFlags::empty(),
             max_recorded_profiles: 0,
             precache_flags: ShaderPrecacheFlags::empty(),
+            shader_precache_callback: None,
             renderer_kind: RendererKind::Native,
             enable_subpixel_aa: false,
             clear_color: Some(ColorF::new(1.0, 1.0, 1.0, 1.0)),
@@ -4610,11 +6638,35 @@ impl Default for RendererOptions {
             support_low_priority_transactions: false,
             namespace_alloc_by_client: false,
             enable_picture_caching: false,
+            lost_pipeline_treatment: LostPipelineTreatment::default(),
+            batching_parameters: None,
+            atlas_external_images: false,
+            async_blob_rasterization: false,
             #[cfg(not(feature = "gleam"))]
             heaps_config: HeapsConfig {
                 linear: None,
                 dynamic: None,
-            }
+            },
+            #[cfg(not(feature = "gleam"))]
+            cached_pipelines_path: None,
+            #[cfg(not(feature = "gleam"))]
+            enable_gpu_validation: false,
+            #[cfg(not(feature = "gleam"))]
+            present_mode: None,
+            #[cfg(not(feature = "gleam"))]
+            output_format: OutputFormat::default(),
+            #[cfg(not(feature = "gleam"))]
+            color_space: ColorSpace::default(),
+            #[cfg(not(feature = "gleam"))]
+            enable_partial_present: false,
+            #[cfg(not(feature = "gleam"))]
+            max_anisotropy: 1,
+            pass_budgets: Vec::new(),
+            debug_text_scale: 1.0,
+            debug_text_color: None,
+            max_blur_std_dev: MAX_BLUR_STD_DEVIATION,
+            workarounds: None,
+            compositor: None,
         }
     }
 }
@@ -4628,7 +6680,21 @@ impl DebugServer {
         DebugServer
     }
 
-    pub fn send(&mut self, _: String) {}
+    pub fn send(&mut self, _: &DebugCommand, _: String) {}
+}
+
+/// Draws the main framebuffer pass once for each half of the window, with
+/// different debug flags added for each half, to compare the output of two
+/// renderer-side code paths. For example, `DISABLE_BATCHING` on one side
+/// only checks that batching doesn't change the output. The off-screen
+/// passes and the batches themselves are shared by both halves, so settings
+/// applied at frame building (such as dual-source blending) can't be
+/// compared this way.
+#[cfg(not(feature = "gleam"))]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct SplitScreen {
+    pub left_flags: DebugFlags,
+    pub right_flags: DebugFlags,
 }
 
 // Some basic statistics about the rendered scene
@@ -4642,7 +6708,25 @@ pub struct RendererStats {
     pub color_target_count: usize,
     pub texture_upload_kb: usize,
     pub resource_upload_time: u64,
+    /// Time spent applying GPU cache updates to the CPU side of the cache,
+    /// mostly on the worker pool, in nanoseconds.
+    pub gpu_cache_update_time: u64,
+    /// Time spent copying GPU cache updates to the texture, in nanoseconds.
     pub gpu_cache_upload_time: u64,
+    /// Time spent waiting for the next swapchain image, in nanoseconds.
+    pub swapchain_acquire_time: u64,
+    /// Time spent presenting, which includes waiting for vsync with some
+    /// present modes, in nanoseconds.
+    pub vsync_wait_time: u64,
+    /// Time spent waiting for the GPU to finish an earlier frame, in
+    /// nanoseconds.
+    pub fence_wait_time: u64,
+    /// Estimated time the GPU was idle waiting for the CPU during the last
+    /// frame interval, in nanoseconds. Needs `DebugFlags::GPU_TIME_QUERIES`.
+    pub cpu_gpu_bubble_time: u64,
+    /// The number of alpha primitives that weren't drawn because opaque
+    /// primitives in front of them hide them entirely.
+    pub occluded_primitives: usize,
 }
 
 impl RendererStats {
@@ -4653,11 +6737,34 @@ impl RendererStats {
             color_target_count: 0,
             texture_upload_kb: 0,
             resource_upload_time: 0,
+            gpu_cache_update_time: 0,
             gpu_cache_upload_time: 0,
+            swapchain_acquire_time: 0,
+            vsync_wait_time: 0,
+            fence_wait_time: 0,
+            cpu_gpu_bubble_time: 0,
+            occluded_primitives: 0,
         }
     }
 }
 
+/// The timings of a rendered frame, passed to the frame pacing hook, in
+/// nanoseconds.
+#[derive(Clone, Copy, Debug)]
+pub struct FramePacingInfo {
+    /// The time since the previous frame was rendered.
+    pub frame_interval: u64,
+    /// The CPU time spent rendering the frame.
+    pub cpu_time: u64,
+    /// The GPU time of the latest frame with GPU timings, 0 unless
+    /// `DebugFlags::GPU_TIME_QUERIES` is set.
+    pub gpu_time: u64,
+    pub swapchain_acquire_time: u64,
+    pub vsync_wait_time: u64,
+    pub fence_wait_time: u64,
+    pub cpu_gpu_bubble_time: u64,
+}
+
 
 
 #[cfg(any(feature = "capture", feature = "replay"))]
@@ -4681,6 +6788,15 @@ struct PlainRenderer {
     external_images: Vec<ExternalCaptureImage>
 }
 
+/// The render targets of a pass, as saved with `CaptureBits::TARGETS`.
+#[cfg(any(feature = "capture", feature = "replay"))]
+#[cfg_attr(feature = "capture", derive(Serialize))]
+#[cfg_attr(feature = "replay", derive(Deserialize))]
+struct PlainPassTargets {
+    alpha: Option<PlainTexture>,
+    color: Option<PlainTexture>,
+}
+
 #[cfg(feature = "replay")]
 enum CapturedExternalImageData {
     NativeTexture(IdType),
@@ -4701,7 +6817,8 @@ impl ExternalImageHandler for DummyExternalImageHandler {
             source: match *captured_data {
                 CapturedExternalImageData::NativeTexture(tid) => ExternalImageSource::NativeTexture(tid),
                 CapturedExternalImageData::Buffer(ref arc) => ExternalImageSource::RawData(&*arc),
-            }
+            },
+            timestamp: None,
         }
     }
     fn unlock(&mut self, _key: ExternalImageId, _channel_index: u8) {}
@@ -4723,6 +6840,27 @@ pub struct PipelineInfo {
     pub removed_pipelines: Vec<PipelineId>,
 }
 
+impl PipelineInfo {
+    /// Drops the duplicate removed pipelines, and the ones that were added
+    /// again since, then the oldest ones if there are still too many.
+    fn compact(&mut self) {
+        let mut seen = FastHashSet::default();
+        let epochs = &self.epochs;
+        let mut removed: Vec<_> = self.removed_pipelines
+            .drain(..)
+            .rev()
+            .filter(|id| !epochs.contains_key(id) && seen.insert(*id))
+            .collect();
+        if removed.len() > MAX_REMOVED_PIPELINES {
+            warn!("Dropping {} removed pipelines, flush_pipeline_info isn't called",
+                removed.len() - MAX_REMOVED_PIPELINES);
+            removed.truncate(MAX_REMOVED_PIPELINES);
+        }
+        removed.reverse();
+        self.removed_pipelines = removed;
+    }
+}
+
 impl<B: hal::Backend> Renderer<B> {
     #[cfg(feature = "capture")]
     fn save_texture(
@@ -4733,7 +6871,6 @@ impl<B: hal::Backend> Renderer<B> {
 
         let short_path = format!("textures/{}.raw", name);
 
-        let bytes_per_pixel = texture.get_format().bytes_per_pixel();
         let read_format = ReadPixelsFormat::Standard(texture.get_format());
         let rect = DeviceIntRect::new(
             DeviceIntPoint::zero(),
@@ -4742,9 +6879,24 @@ impl<B: hal::Backend> Renderer<B> {
 
         let mut file = fs::File::create(root.join(&short_path))
             .expect(&format!("Unable to create {}", short_path));
-        let bytes_per_layer = (rect.size.width * rect.size.height * bytes_per_pixel) as usize;
+        let bytes_per_layer = texture.get_format().size_in_bytes(rect.size.width, rect.size.height);
         let mut data = vec![0; bytes_per_layer];
 
+        // Compressed textures can't be attached for reading.
+        if texture.get_format().is_compressed() {
+            warn!("Unable to read back compressed texture {}, saving it blank", name);
+            for _ in 0 .. texture.get_layer_count() {
+                file.write_all(&data)
+                    .unwrap();
+            }
+            return PlainTexture {
+                data: short_path,
+                size: (rect.size, texture.get_layer_count()),
+                format: texture.get_format(),
+                filter: texture.get_filter(),
+            };
+        }
+
         //TODO: instead of reading from an FBO with `read_pixels*`, we could
         // read from textures directly with `get_tex_image*`.
 
@@ -4782,6 +6934,91 @@ impl<B: hal::Backend> Renderer<B> {
         }
     }
 
+    /// Copies the targets of the pass that was just drawn, so that they can
+    /// be saved once the frame is rendered.
+    #[cfg(feature = "capture")]
+    fn copy_pass_targets_for_capture(
+        &mut self,
+        alpha: Option<&ActiveTexture>,
+        color: Option<&ActiveTexture>,
+    ) {
+        if alpha.is_none() && color.is_none() {
+            return;
+        }
+        let alpha = alpha.map(|at| self.copy_target_for_capture(&at.texture));
+        let color = color.map(|at| self.copy_target_for_capture(&at.texture));
+        if let Some((_, ref mut passes)) = self.target_capture {
+            passes.push((alpha, color));
+        }
+    }
+
+    #[cfg(feature = "capture")]
+    fn copy_target_for_capture(&mut self, texture: &Texture) -> Texture {
+        let dimensions = texture.get_dimensions();
+        let copy = self.device.create_texture(
+            TextureTarget::Array,
+            texture.get_format(),
+            dimensions.width,
+            dimensions.height,
+            texture.get_filter(),
+            Some(RenderTargetInfo { has_depth: false }),
+            texture.get_layer_count(),
+        );
+        let rect = DeviceIntRect::new(DeviceIntPoint::zero(), dimensions);
+        for layer in 0 .. texture.get_layer_count() as usize {
+            self.device.bind_read_target(ReadTarget::Texture { texture, layer });
+            self.device.bind_draw_target(DrawTarget::Texture {
+                texture: &copy,
+                layer,
+                with_depth: false,
+            });
+            self.device.blit_render_target(rect, rect);
+        }
+        copy
+    }
+
+    /// Saves the pass targets copied while rendering the frame, if a capture
+    /// is waiting for them.
+    #[cfg(feature = "capture")]
+    fn save_captured_targets(&mut self) {
+        use std::fs;
+
+        // Nothing was rendered, keep waiting for a frame.
+        if self.active_documents.is_empty() {
+            return;
+        }
+        let (config, passes) = match self.target_capture.take() {
+            Some(capture) => capture,
+            None => return,
+        };
+
+        info!("saving render targets");
+        let path_textures = config.root.join("textures");
+        if !path_textures.is_dir() {
+            fs::create_dir(&path_textures).unwrap();
+        }
+
+        self.device.begin_frame();
+        self.device.bind_read_target_impl(self.read_fbo);
+        let mut plain_passes = Vec::with_capacity(passes.len());
+        for (index, (alpha, color)) in passes.into_iter().enumerate() {
+            let mut save = |texture: Texture, kind: &str| {
+                let file_name = format!("pass-{}-{}", index, kind);
+                info!("\t{}", file_name);
+                let plain = Self::save_texture(&texture, &file_name, &config.root, &mut self.device);
+                self.device.delete_texture(texture);
+                plain
+            };
+            let alpha = alpha.map(|texture| save(texture, "alpha"));
+            let color = color.map(|texture| save(texture, "color"));
+            plain_passes.push(PlainPassTargets { alpha, color });
+        }
+        config.serialize(&plain_passes, "targets");
+        self.device.reset_read_target();
+        self.device.end_frame();
+        info!("done.");
+    }
+
     #[cfg(feature = "replay")]
     fn load_texture(
         target: TextureTarget,
@@ -4813,7 +7050,7 @@ impl<B: hal::Backend> Renderer<B> {
             },
             plain.size.1,
         );
-        device.upload_texture_immediate(&texture, &texels);
+        device.init_texture(&texture, Some(&texels));
 
         (texture, texels)
     }
@@ -4936,6 +7173,12 @@ impl<B: hal::Backend> Renderer<B> {
             config.serialize(&plain_self, "renderer");
         }
 
+        if config.bits.contains(CaptureBits::TARGETS) {
+            info!("render targets will be saved with the next frame");
+            self.target_capture = Some((config, Vec::new()));
+            self.content_changed = true;
+        }
+
         self.device.reset_read_target();
         self.device.end_frame();
         info!("done.");
@@ -4995,6 +7238,26 @@ impl<B: hal::Backend> Renderer<B> {
                 self.texture_resolver.texture_cache_map.insert(id, t.0);
             }
 
+            for texture in self.captured_targets.drain(..) {
+                self.device.delete_texture(texture);
+            }
+            if let Some(passes) = CaptureConfig::deserialize::<Vec<PlainPassTargets>, _>(&root, "targets") {
+                info!("loading render targets");
+                for pass in passes {
+                    for plain in pass.alpha.iter().chain(pass.color.iter()) {
+                        info!("\t{}", plain.data);
+                        let (texture, _) = Self::load_texture(
+                            TextureTarget::Array,
+                            plain,
+                            Some(RenderTargetInfo { has_depth: false }),
+                            &root,
+                            &mut self.device,
+                        );
+                        self.captured_targets.push(texture);
+                    }
+                }
+            }
+
             info!("loading gpu cache");
             if let Some(t) = self.gpu_cache_texture.texture.take() {
                 self.device.delete_texture(t);
@@ -5007,26 +7270,28 @@ impl<B: hal::Backend> Renderer<B> {
                 &mut self.device,
             );
             self.gpu_cache_texture.texture = Some(t);
-            match self.gpu_cache_texture.bus {
-                GpuCacheBus::PixelBuffer { ref mut rows, .. } => {
-                    let dim = self.gpu_cache_texture.texture.as_ref().unwrap().get_dimensions();
-                    let blocks = unsafe {
-                        slice::from_raw_parts(
-                            gpu_cache_data.as_ptr() as *const GpuBlockData,
-                            gpu_cache_data.len() / mem::size_of::<GpuBlockData>(),
-                        )
-                    };
-                    // fill up the CPU cache from the contents we just loaded
-                    rows.clear();
-                    rows.extend((0 .. dim.height).map(|_| CacheRow::new()));
-                    let chunks = blocks.chunks(MAX_VERTEX_TEXTURE_WIDTH);
-                    debug_assert_eq!(chunks.len(), rows.len());
-                    for (row, chunk) in rows.iter_mut().zip(chunks) {
-                        row.cpu_blocks.copy_from_slice(chunk);
+            {
+                let mut staged = self.gpu_cache_texture.staging.apply_pending();
+                match staged.rows {
+                    Some(ref mut rows) => {
+                        let dim = self.gpu_cache_texture.texture.as_ref().unwrap().get_dimensions();
+                        let blocks = unsafe {
+                            slice::from_raw_parts(
+                                gpu_cache_data.as_ptr() as *const GpuBlockData,
+                                gpu_cache_data.len() / mem::size_of::<GpuBlockData>(),
+                            )
+                        };
+                        // fill up the CPU cache from the contents we just loaded
+                        rows.clear();
+                        rows.extend((0 .. dim.height).map(|_| CacheRow::new()));
+                        let chunks = blocks.chunks(MAX_VERTEX_TEXTURE_WIDTH);
+                        debug_assert_eq!(chunks.len(), rows.len());
+                        for (row, chunk) in rows.iter_mut().zip(chunks) {
+                            row.cpu_blocks.copy_from_slice(chunk);
+                        }
                     }
+                    None => {}
                 }
-                #[cfg(feature = "gleam")]
-                GpuCacheBus::Scatter { .. } => {}
             }
             self.gpu_cache_frame_id = renderer.gpu_cache_frame_id;
 
@@ -5093,6 +7358,7 @@ fn get_vao<'a, B: hal::Backend>(
         VertexArrayKind::Border => &vaos.border_vao,
         VertexArrayKind::Scale => &vaos.scale_vao,
         VertexArrayKind::LineDecoration => &vaos.line_vao,
+        VertexArrayKind::SvgFilter => &vaos.svg_filter_vao,
     }
 }
 
@@ -5110,6 +7376,7 @@ fn get_vao<'a, B: hal::Backend>(
         VertexArrayKind::Border => &vaos.border_vao,
         VertexArrayKind::Scale => &vaos.scale_vao,
         VertexArrayKind::LineDecoration => &vaos.line_vao,
+        VertexArrayKind::SvgFilter => &vaos.svg_filter_vao,
     }
 }
 
@@ -5118,3 +7385,39 @@ enum FramebufferKind {
     Main,
     Other,
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn gpu_cache_blocks_keep_full_precision() {
+        // Rects, UVs and addresses above 2048 don't survive a reduced
+        // precision encoding, make sure the staged blocks are exact.
+        let block = GpuBlockData::from([2049.0, 4097.5, -65537.25, 1.0e7]);
+
+        for &use_rows in &[true, false] {
+            let staging = GpuCacheStaging::new(use_rows);
+            staging.pending.lock().unwrap().push(GpuCacheUpdateList {
+                frame_id: FrameId::INVALID,
+                clear: false,
+                height: 4,
+                updates: vec![GpuCacheUpdate::Copy {
+                    block_index: 0,
+                    block_count: 1,
+                    address: GpuCacheAddress { u: 3, v: 2 },
+                }],
+                blocks: vec![block],
+                debug_commands: Vec::new(),
+                requested_rows: 0,
+                coalesced_rows: 0,
+            });
+            let staged = staging.apply_pending();
+            assert_eq!(staged.regions.len(), 1);
+            let region = staged.regions[0];
+            let offset = (2 - region.origin.y) as usize * region.size.width as usize +
+                (3 - region.origin.x) as usize;
+            assert_eq!(staged.blocks[offset], block);
+        }
+    }
+}
@@ -0,0 +1,147 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+//! Standalone replay of the captures saved with the `capture` feature, e.g.
+//! by Gecko or Servo, to debug or benchmark them with a hal backend.
+//!
+//! ```rust,ignore
+//! let mut session = ReplaySession::from_capture(path, init, RendererOptions::default())?;
+//! for _ in 0 .. 100 {
+//!     let stats = session.step()?;
+//! }
+//! session.deinit();
+//! ```
+
+use api::{CapturedDocument, DeviceIntSize, DocumentId, RenderApi, RenderNotifier, Transaction};
+use device::DeviceInit;
+use hal;
+use renderer::{Renderer, RendererError, RendererOptions, RendererStats};
+use std::path::PathBuf;
+use std::sync::mpsc::{channel, Receiver, Sender};
+
+enum ReplayEvent {
+    FrameReady,
+    ShutDown,
+}
+
+struct ReplayNotifier(Sender<ReplayEvent>);
+
+impl RenderNotifier for ReplayNotifier {
+    fn clone(&self) -> Box<RenderNotifier> {
+        Box::new(ReplayNotifier(self.0.clone()))
+    }
+
+    fn wake_up(&self) {}
+
+    fn new_frame_ready(
+        &self,
+        _: DocumentId,
+        _scrolled: bool,
+        _composite_needed: bool,
+        _render_time_ns: Option<u64>,
+    ) {
+        let _ = self.0.send(ReplayEvent::FrameReady);
+    }
+
+    fn shut_down(&self) {
+        let _ = self.0.send(ReplayEvent::ShutDown);
+    }
+}
+
+/// A renderer playing back a capture. Each step rebuilds the scenes and the
+/// frames of the captured documents and renders them, so that the whole
+/// pipeline can be profiled.
+pub struct ReplaySession<B: hal::Backend> {
+    renderer: Renderer<B>,
+    api: RenderApi,
+    documents: Vec<CapturedDocument>,
+    events: Receiver<ReplayEvent>,
+    framebuffer_size: DeviceIntSize,
+}
+
+impl<B: hal::Backend> ReplaySession<B> {
+    /// Creates a renderer for `init` and loads the capture saved in `path`.
+    /// The framebuffer gets the window size of the first captured document.
+    pub fn from_capture<P: Into<PathBuf>>(
+        path: P,
+        init: DeviceInit<B>,
+        options: RendererOptions,
+    ) -> Result<Self, RendererError> {
+        let (tx, events) = channel();
+        let (renderer, sender) = Renderer::new(
+            init,
+            Box::new(ReplayNotifier(tx)),
+            options,
+            None,
+        )?;
+        let api = sender.create_api();
+        let documents = api.load_capture(path.into());
+        let framebuffer_size = documents
+            .first()
+            .map_or(DeviceIntSize::zero(), |document| document.window_size);
+
+        Ok(ReplaySession {
+            renderer,
+            api,
+            documents,
+            events,
+            framebuffer_size,
+        })
+    }
+
+    /// Rebuilds the scenes and the frames of the captured documents, then
+    /// renders them.
+    pub fn step(&mut self) -> Result<RendererStats, Vec<RendererError>> {
+        let mut pending_frames = 0;
+        for document in &self.documents {
+            let root_pipeline_id = match document.root_pipeline_id {
+                Some(root_pipeline_id) => root_pipeline_id,
+                None => continue,
+            };
+            let mut txn = Transaction::new();
+            txn.set_root_pipeline(root_pipeline_id);
+            txn.generate_frame();
+            self.api.send_transaction(document.document_id, txn);
+            pending_frames += 1;
+        }
+        while pending_frames > 0 {
+            match self.events.recv() {
+                Ok(ReplayEvent::FrameReady) => pending_frames -= 1,
+                Ok(ReplayEvent::ShutDown) | Err(_) => break,
+            }
+        }
+
+        self.renderer.update();
+        self.renderer.render(self.framebuffer_size)
+    }
+
+    /// The documents loaded from the capture.
+    pub fn documents(&self) -> &[CapturedDocument] {
+        &self.documents
+    }
+
+    pub fn renderer(&mut self) -> &mut Renderer<B> {
+        &mut self.renderer
+    }
+
+    pub fn api(&self) -> &RenderApi {
+        &self.api
+    }
+
+    /// Sets the size of the main framebuffer the frames are rendered into.
+    pub fn set_framebuffer_size(&mut self, size: DeviceIntSize) {
+        self.framebuffer_size = size;
+    }
+
+    /// Shuts the render backend down and deinitializes the renderer.
+    pub fn deinit(self) {
+        self.api.shut_down();
+        while let Ok(event) = self.events.recv() {
+            if let ReplayEvent::ShutDown = event {
+                break;
+            }
+        }
+        self.renderer.deinit();
+    }
+}
@@ -12,6 +12,7 @@ use api::{GlyphDimensions, IdNamespace};
 use api::{ImageData, ImageDescriptor, ImageKey, ImageRendering, ImageDirtyRect, DirtyRect};
 use api::{BlobImageKey, BlobDirtyRect, MemoryReport, VoidPtrToSizeFn};
 use api::{TileOffset, TileSize, TileRange, BlobImageData, LayoutIntRect, LayoutIntSize};
+use api::{ImageFormat, TextureTarget};
 use app_units::Au;
 #[cfg(feature = "capture")]
 use capture::ExternalCaptureImage;
@@ -19,6 +20,8 @@ use capture::ExternalCaptureImage;
 use capture::PlainExternalImage;
 #[cfg(any(feature = "replay", feature = "png"))]
 use capture::CaptureConfig;
+#[cfg(feature = "debugger")]
+use debug_server::MemoryBreakdownGroup;
 use device::TextureFilter;
 use euclid::{point2, size2};
 use glyph_cache::GlyphCache;
@@ -49,6 +52,12 @@ use util::drain_filter;
 
 const DEFAULT_TILE_SIZE: TileSize = 512;
 
+/// External textures up to this size can be atlased.
+const MAX_ATLASED_EXTERNAL_IMAGE_SIZE: i32 = 256;
+/// The number of frames an external texture has to go without updates
+/// before it gets atlased.
+const ATLASED_EXTERNAL_IMAGE_IDLE_FRAMES: usize = 4;
+
 #[cfg_attr(feature = "capture", derive(Serialize))]
 #[cfg_attr(feature = "replay", derive(Deserialize))]
 pub struct GlyphFetchResult {
@@ -173,6 +182,8 @@ struct ImageResource {
     descriptor: ImageDescriptor,
     tiling: Option<TileSize>,
     viewport_tiles: Option<TileRange>,
+    /// The frame in which the image was added or last updated.
+    last_update: FrameId,
 }
 
 #[derive(Clone, Debug)]
@@ -245,6 +256,11 @@ where
         }
     }
 
+    #[cfg(feature = "debugger")]
+    pub fn len(&self) -> usize {
+        self.resources.len()
+    }
+
     pub fn get(&self, key: &K) -> &V {
         self.resources.get(key)
             .expect("Didn't find a cached resource with that ID!")
@@ -447,6 +463,9 @@ pub struct ResourceCache {
     current_frame_id: FrameId,
 
     pub texture_cache: TextureCache,
+    /// Whether small external textures that don't change often get copied
+    /// into the texture cache. See `atlases_external_image`.
+    atlas_external_images: bool,
 
     /// TODO(gw): We should expire (parts of) this cache semi-regularly!
     cached_glyph_dimensions: GlyphDimensionsCache,
@@ -464,6 +483,18 @@ pub struct ResourceCache {
     /// If while building a frame we encounter blobs that we didn't already
     /// rasterize, add them to this list and rasterize them synchronously.
     missing_blob_images: Vec<BlobImageParams>,
+    /// Whether missing blob images are rasterized asynchronously instead,
+    /// leaving them out of the current frame.
+    async_blob_rasterization: bool,
+    /// The block compressed formats the device can sample. Images of other
+    /// compressed formats are dropped.
+    supported_compressed_formats: Vec<ImageFormat>,
+    /// Missing blob images that still need to be sent off for asynchronous
+    /// rasterization, see `create_async_blob_requests`.
+    deferred_blob_images: Vec<BlobImageParams>,
+    /// Blob images that are being rasterized asynchronously, so that we don't
+    /// request them again every frame until they are done.
+    blob_requests_in_flight: FastHashSet<BlobImageRequest>,
     /// The rasterizer associated with the current scene.
     blob_image_rasterizer: Option<Box<AsyncBlobImageRasterizer>>,
     /// An epoch of the stored blob image rasterizer, used to skip the ones
@@ -485,8 +516,12 @@ impl ResourceCache {
         texture_cache: TextureCache,
         glyph_rasterizer: GlyphRasterizer,
         blob_image_handler: Option<Box<BlobImageHandler>>,
+        atlas_external_images: bool,
+        async_blob_rasterization: bool,
+        supported_compressed_formats: Vec<ImageFormat>,
     ) -> Self {
         ResourceCache {
+            atlas_external_images,
             cached_glyphs: GlyphCache::new(),
             cached_images: ResourceClassCache::new(),
             cached_render_tasks: RenderTaskCache::new(),
@@ -501,6 +536,10 @@ impl ResourceCache {
             rasterized_blob_images: FastHashMap::default(),
             blob_image_templates: FastHashMap::default(),
             missing_blob_images: Vec::new(),
+            async_blob_rasterization,
+            supported_compressed_formats,
+            deferred_blob_images: Vec::new(),
+            blob_requests_in_flight: FastHashSet::default(),
             blob_image_rasterizer: None,
             blob_image_rasterizer_produced_epoch: BlobImageRasterizerEpoch(0),
             blob_image_rasterizer_consumed_epoch: BlobImageRasterizerEpoch(0),
@@ -525,6 +564,41 @@ impl ResourceCache {
         }
     }
 
+    /// Returns true if the image is an external texture that is copied into
+    /// the shared texture cache, instead of being bound directly.
+    ///
+    /// Binding external textures directly breaks batches between them, so
+    /// small ones are atlased when they haven't been updated for a few
+    /// frames. Large textures, and ones that are updated every frame, like
+    /// playing videos, are still bound directly, as copying them would cost
+    /// more than the batch breaks. Embedders have to update the image when
+    /// the content of its texture changes, for the copy to be refreshed.
+    fn atlases_external_image(&self, template: &ImageResource) -> bool {
+        if !self.atlas_external_images || template.tiling.is_some() {
+            return false;
+        }
+        match template.data {
+            CachedImageData::External(ExternalImageData {
+                image_type: ExternalImageType::TextureHandle(TextureTarget::Default),
+                ..
+            }) => {}
+            _ => return false,
+        }
+        let size = template.descriptor.size;
+        if size.width > MAX_ATLASED_EXTERNAL_IMAGE_SIZE ||
+            size.height > MAX_ATLASED_EXTERNAL_IMAGE_SIZE {
+            return false;
+        }
+        self.current_frame_id
+            .checked_sub(ATLASED_EXTERNAL_IMAGE_IDLE_FRAMES)
+            .map_or(false, |frame_id| frame_id >= template.last_update)
+    }
+
+    /// Returns true if the image is drawn from the texture cache.
+    fn uses_texture_cache(&self, template: &ImageResource) -> bool {
+        template.data.uses_texture_cache() || self.atlases_external_image(template)
+    }
+
     // Request the texture cache item for a cacheable render
     // task. If the item is already cached, the texture cache
     // handle will be returned. Otherwise, the user supplied
@@ -562,12 +636,20 @@ impl ResourceCache {
         for update in updates {
             match update {
                 ResourceUpdate::AddImage(img) => {
+                    if !self.is_compressed_image_supported(&img.descriptor, img.tiling) {
+                        continue;
+                    }
                     if let ImageData::Raw(ref bytes) = img.data {
                         profile_counters.image_templates.inc(bytes.len());
                     }
                     self.add_image_template(img.key, img.descriptor, img.data.into(), img.tiling);
                 }
                 ResourceUpdate::UpdateImage(img) => {
+                    let tiling = self.resources.image_templates.get(img.key)
+                        .and_then(|template| template.tiling);
+                    if !self.is_compressed_image_supported(&img.descriptor, tiling) {
+                        continue;
+                    }
                     self.update_image_template(img.key, img.descriptor, img.data.into(), &img.dirty_rect);
                 }
                 ResourceUpdate::AddBlobImage(img) => {
@@ -608,6 +690,32 @@ impl ResourceCache {
         }
     }
 
+    /// Returns false, with a warning, if the image is block compressed in a
+    /// format the device can't sample, or tiled with tiles that aren't made
+    /// of whole blocks.
+    fn is_compressed_image_supported(
+        &self,
+        descriptor: &ImageDescriptor,
+        tiling: Option<TileSize>,
+    ) -> bool {
+        let format = descriptor.format;
+        if !format.is_compressed() {
+            return true;
+        }
+        if !self.supported_compressed_formats.contains(&format) {
+            warn!("Dropping image, compressed format {:?} isn't supported", format);
+            return false;
+        }
+        if let Some(tile_size) = tiling {
+            if tile_size as i32 % format.block_size() != 0 {
+                warn!("Dropping image, tile size {} isn't a multiple of the {:?} block size",
+                      tile_size, format);
+                return false;
+            }
+        }
+        true
+    }
+
     pub fn pre_scene_building_update(
         &mut self,
         updates: &mut Vec<ResourceUpdate>,
@@ -719,8 +827,23 @@ impl ResourceCache {
         }
     }
 
-    pub fn add_rasterized_blob_images(&mut self, images: Vec<(BlobImageRequest, BlobImageResult)>) {
+    /// Returns true if some of the images were rasterized asynchronously
+    /// because a previous frame was missing them.
+    pub fn add_rasterized_blob_images(
+        &mut self,
+        images: Vec<(BlobImageRequest, BlobImageResult)>,
+    ) -> bool {
+        let mut completed_async_requests = false;
         for (request, result) in images {
+            if self.blob_requests_in_flight.remove(&request) {
+                completed_async_requests = true;
+            }
+
+            // The image may have been deleted while it was being rasterized.
+            if !self.blob_image_templates.contains_key(&request.key) {
+                continue;
+            }
+
             let data = match result {
                 Ok(data) => data,
                 Err(..) => {
@@ -756,6 +879,8 @@ impl ResourceCache {
                 }
             }
         }
+
+        completed_async_requests
     }
 
     pub fn add_font_template(&mut self, font_key: FontKey, template: FontTemplate) {
@@ -845,6 +970,7 @@ impl ResourceCache {
             data,
             tiling,
             viewport_tiles: None,
+            last_update: self.current_frame_id,
         };
 
         self.resources.image_templates.insert(image_key, resource);
@@ -858,6 +984,7 @@ impl ResourceCache {
         dirty_rect: &ImageDirtyRect,
     ) {
         let max_texture_size = self.max_texture_size();
+        let current_frame_id = self.current_frame_id;
         let image = match self.resources.image_templates.get_mut(image_key) {
             Some(res) => res,
             None => panic!("Attempt to update non-existent image"),
@@ -868,6 +995,20 @@ impl ResourceCache {
             tiling = Some(DEFAULT_TILE_SIZE);
         }
 
+        // Compressed images can only be uploaded in whole blocks, so grow the
+        // dirty rect to the blocks it touches.
+        let block_size = descriptor.format.block_size();
+        let dirty_rect = &dirty_rect.map(|rect| {
+            let x0 = rect.origin.x / block_size * block_size;
+            let y0 = rect.origin.y / block_size * block_size;
+            let x1 = (rect.max_x() + block_size - 1) / block_size * block_size;
+            let y1 = (rect.max_y() + block_size - 1) / block_size * block_size;
+            DeviceIntRect::new(
+                DeviceIntPoint::new(x0, y0),
+                DeviceIntSize::new(x1 - x0, y1 - y0),
+            )
+        });
+
         // Each cache entry stores its own copy of the image's dirty rect. This allows them to be
         // updated independently.
         match self.cached_images.try_get_mut(&image_key) {
@@ -903,6 +1044,7 @@ impl ResourceCache {
             data,
             tiling,
             viewport_tiles: image.viewport_tiles,
+            last_update: current_frame_id,
         };
     }
 
@@ -972,6 +1114,8 @@ impl ResourceCache {
                 self.deleted_blob_keys.back_mut().unwrap().push(blob_key);
                 self.blob_image_templates.remove(&blob_key);
                 self.rasterized_blob_images.remove(&blob_key);
+                self.blob_requests_in_flight.retain(|request| request.key != blob_key);
+                self.deferred_blob_images.retain(|params| params.request.key != blob_key);
             },
             None => {
                 warn!("Delete the non-exist key");
@@ -1023,7 +1167,7 @@ impl ResourceCache {
         };
 
         // Images that don't use the texture cache can early out.
-        if !template.data.uses_texture_cache() {
+        if !self.uses_texture_cache(template) {
             return;
         }
 
@@ -1108,6 +1252,7 @@ impl ResourceCache {
         }
 
         if template.data.is_blob() {
+            let image_request = request;
             let request: BlobImageRequest = request.into();
             let missing = match (self.rasterized_blob_images.get(&request.key), request.tile) {
                 (Some(RasterizedBlob::Tiled(tiles)), Some(tile)) => !tiles.contains_key(&tile),
@@ -1142,13 +1287,24 @@ impl ResourceCache {
                     panic!("already missing blob image key {:?} deleted: {:?}", request, self.deleted_blob_keys);
                 }
 
-                self.missing_blob_images.push(
-                    BlobImageParams {
-                        request,
-                        descriptor,
-                        dirty_rect: DirtyRect::All,
+                let params = BlobImageParams {
+                    request,
+                    descriptor,
+                    dirty_rect: DirtyRect::All,
+                };
+
+                if self.async_blob_rasterization {
+                    // Don't upload anything this frame. The primitive either
+                    // keeps showing the previous content of the texture cache
+                    // entry or is skipped until the tile comes back.
+                    self.pending_image_requests.remove(&image_request);
+                    if self.blob_requests_in_flight.insert(request) {
+                        self.deferred_blob_images.push(params);
                     }
-                );
+                    return;
+                }
+
+                self.missing_blob_images.push(params);
             }
         }
     }
@@ -1330,6 +1486,35 @@ impl ResourceCache {
         (Some((handler.create_blob_rasterizer(), info)), blob_request_params)
     }
 
+    /// Hands out the blob images that were missing from the last frame, along
+    /// with a rasterizer for them, when `async_blob_rasterization` is enabled.
+    /// The results are expected to come back through `add_rasterized_blob_images`.
+    pub fn create_async_blob_requests(
+        &mut self,
+    ) -> (Option<(Box<AsyncBlobImageRasterizer>, AsyncBlobImageInfo)>, Vec<BlobImageParams>) {
+        if self.deferred_blob_images.is_empty() {
+            return (None, Vec::new());
+        }
+
+        let blob_request_params = mem::replace(&mut self.deferred_blob_images, Vec::new());
+        // This rasterizer only snapshots the handler for the deferred requests,
+        // so it must not replace the one of the current scene. Epoch zero is
+        // never newer than the consumed epoch, see `set_blob_rasterizer`.
+        let info = AsyncBlobImageInfo {
+            epoch: BlobImageRasterizerEpoch(0),
+            clear_requests: Vec::new(),
+        };
+        let handler = self.blob_image_handler.as_mut().unwrap();
+        handler.prepare_resources(&self.resources, &blob_request_params);
+        (Some((handler.create_blob_rasterizer(), info)), blob_request_params)
+    }
+
+    /// Returns true if some blob images are still being rasterized
+    /// asynchronously, and the current frame is missing them.
+    pub fn has_pending_blob_rasterization(&self) -> bool {
+        !self.blob_requests_in_flight.is_empty()
+    }
+
     fn discard_tiles_outside_visible_area(
         &mut self,
         key: BlobImageKey,
@@ -1519,6 +1704,13 @@ impl ResourceCache {
     pub fn get_cached_image(&self, request: ImageRequest) -> Result<CacheItem, ()> {
         debug_assert_eq!(self.state, State::QueryResources);
         let image_info = self.get_image_info(request)?;
+        // Blob images that are being rasterized asynchronously may not have
+        // made it to the texture cache yet.
+        if self.async_blob_rasterization &&
+            self.blob_image_templates.contains_key(&BlobImageKey(request.key)) &&
+            !self.texture_cache.is_allocated(&image_info.texture_cache_handle) {
+            return Err(());
+        }
         Ok(self.get_texture_cache_item(&image_info.texture_cache_handle))
     }
 
@@ -1550,6 +1742,8 @@ impl ResourceCache {
 
         image_template.map(|image_template| {
             let external_image = match image_template.data {
+                // atlased external textures use resource_cache.
+                CachedImageData::External(..) if self.atlases_external_image(image_template) => None,
                 CachedImageData::External(ext_image) => match ext_image.image_type {
                     ExternalImageType::TextureHandle(_) => Some(ext_image),
                     // external buffer uses resource_cache.
@@ -1642,7 +1836,10 @@ impl ResourceCache {
     fn update_texture_cache(&mut self, gpu_cache: &mut GpuCache) {
         for request in self.pending_image_requests.drain() {
             let image_template = self.resources.image_templates.get_mut(request.key).unwrap();
-            debug_assert!(image_template.data.uses_texture_cache());
+            debug_assert!(
+                image_template.data.uses_texture_cache() ||
+                matches!(image_template.data, CachedImageData::External(..))
+            );
 
             let mut updates: SmallVec<[(CachedImageData, Option<DeviceIntRect>); 1]> = SmallVec::new();
 
@@ -1698,12 +1895,12 @@ impl ResourceCache {
                     // and offset.
                     let tiled_on_cpu = image_template.data.is_blob();
                     if !tiled_on_cpu {
-                        let bpp = descriptor.format.bytes_per_pixel();
                         let stride = descriptor.compute_stride();
                         descriptor.stride = Some(stride);
-                        descriptor.offset +=
-                            tile.y as i32 * tile_size as i32 * stride +
-                            tile.x as i32 * tile_size as i32 * bpp;
+                        descriptor.offset = descriptor.compute_offset(DeviceIntPoint::new(
+                            tile.x as i32 * tile_size as i32,
+                            tile.y as i32 * tile_size as i32,
+                        ));
                     }
 
                     descriptor.size = clipped_tile_size;
@@ -1716,6 +1913,14 @@ impl ResourceCache {
                     dirty_rect = DirtyRect::Partial(rect);
                 }
 
+                // Atlased external textures are always copied as a whole.
+                if let CachedImageData::External(ExternalImageData {
+                    image_type: ExternalImageType::TextureHandle(_),
+                    ..
+                }) = image_data {
+                    dirty_rect = DirtyRect::All;
+                }
+
                 let filter = match request.rendering {
                     ImageRendering::Pixelated => {
                         TextureFilter::Nearest
@@ -1728,7 +1933,10 @@ impl ResourceCache {
                         // that is > 512 in either dimension, so it should cover
                         // the most important use cases. We may want to support
                         // mip-maps on shared cache items in the future.
+                        // Mipmaps of compressed images can't be generated
+                        // on the GPU.
                         if descriptor.allow_mipmaps &&
+                           !descriptor.format.is_compressed() &&
                            descriptor.size.width > 512 &&
                            descriptor.size.height > 512 &&
                            !self.texture_cache.is_allowed_in_shared_cache(
@@ -1776,6 +1984,11 @@ impl ResourceCache {
         self.texture_cache.set_debug_flags(flags);
     }
 
+    /// See `TextureCache::set_budget`.
+    pub fn set_texture_cache_budget(&mut self, budget_bytes: Option<usize>) -> bool {
+        self.texture_cache.set_budget(budget_bytes)
+    }
+
     pub fn clear(&mut self, what: ClearCache) {
         if what.contains(ClearCache::IMAGES) {
             for (_key, mut cached) in self.cached_images.resources.drain() {
@@ -1865,6 +2078,38 @@ impl ResourceCache {
         report
     }
 
+    #[cfg(feature = "debugger")]
+    pub fn report_memory_breakdown(&self, group: &mut MemoryBreakdownGroup) {
+        self.cached_glyphs.report_memory_breakdown(group);
+        group.add::<(ImageKey, ImageResult)>("cached_images", self.cached_images.len());
+        self.cached_render_tasks.report_memory_breakdown(group);
+        group.add::<(FontKey, FontTemplate)>(
+            "font_templates",
+            self.resources.font_templates.len(),
+        );
+        group.add::<(FontInstanceKey, FontInstance)>(
+            "font_instances",
+            self.resources.font_instances.read().unwrap().len(),
+        );
+        group.add::<(ImageKey, ImageResource)>(
+            "image_templates",
+            self.resources.image_templates.images.len(),
+        );
+        group.add::<(BlobImageKey, RasterizedBlob)>(
+            "rasterized_blob_images",
+            self.rasterized_blob_images.len(),
+        );
+        group.add::<(BlobImageKey, BlobImageTemplate)>(
+            "blob_image_templates",
+            self.blob_image_templates.len(),
+        );
+        group.add::<((FontInstance, GlyphIndex), Option<GlyphDimensions>)>(
+            "cached_glyph_dimensions",
+            self.cached_glyph_dimensions.len(),
+        );
+        group.add::<ImageRequest>("pending_image_requests", self.pending_image_requests.len());
+    }
+
     /// Properly deletes all images matching the predicate.
     fn clear_images<F: Fn(&ImageKey) -> bool>(&mut self, f: F) {
         let keys = self.resources.image_templates.images.keys().filter(|k| f(*k))
@@ -2298,6 +2543,7 @@ impl ResourceCache {
                 descriptor: template.descriptor,
                 tiling: template.tiling,
                 viewport_tiles: None,
+                last_update: FrameId::INVALID,
             });
         }
 
@@ -2313,3 +2559,131 @@ impl ResourceCache {
 fn blob_size(device_size: DeviceIntSize) -> LayoutIntSize {
     size2(device_size.width, device_size.height)
 }
+
+#[cfg(test)]
+mod test {
+    use api::{AsyncBlobImageRasterizer, BlobDirtyRect, BlobImageData, BlobImageHandler};
+    use api::{BlobImageKey, BlobImageParams, BlobImageRequest, BlobImageResources};
+    use api::{BlobImageResult, DeviceIntRect, DocumentId, FontInstanceKey, FontKey};
+    use api::{IdNamespace, ImageDescriptor, ImageFormat, ImageKey, ImageRendering};
+    use api::{RasterizedBlobImage, TileSize};
+    use euclid::{point2, size2};
+    use glyph_rasterizer::GlyphRasterizer;
+    use gpu_cache::GpuCache;
+    use rayon::ThreadPoolBuilder;
+    use render_backend::FrameStamp;
+    use std::sync::Arc;
+    use super::{CachedImageData, ImageRequest, ResourceCache};
+    use texture_cache::TextureCache;
+
+    struct Rasterizer;
+
+    impl AsyncBlobImageRasterizer for Rasterizer {
+        fn rasterize(
+            &mut self,
+            requests: &[BlobImageParams],
+            _low_priority: bool,
+        ) -> Vec<(BlobImageRequest, BlobImageResult)> {
+            requests.iter().map(|params| (params.request, Ok(rasterized()))).collect()
+        }
+    }
+
+    struct Handler;
+
+    impl BlobImageHandler for Handler {
+        fn create_blob_rasterizer(&mut self) -> Box<AsyncBlobImageRasterizer> {
+            Box::new(Rasterizer)
+        }
+        fn prepare_resources(&mut self, _: &BlobImageResources, _: &[BlobImageParams]) {}
+        fn add(&mut self, _: BlobImageKey, _: Arc<BlobImageData>, _: Option<TileSize>) {}
+        fn update(&mut self, _: BlobImageKey, _: Arc<BlobImageData>, _: &BlobDirtyRect) {}
+        fn delete(&mut self, _: BlobImageKey) {}
+        fn delete_font(&mut self, _: FontKey) {}
+        fn delete_font_instance(&mut self, _: FontInstanceKey) {}
+        fn clear_namespace(&mut self, _: IdNamespace) {}
+    }
+
+    fn rasterized() -> RasterizedBlobImage {
+        RasterizedBlobImage {
+            rasterized_rect: DeviceIntRect::new(point2(0, 0), size2(16, 16)),
+            data: Arc::new(vec![0; 16 * 16 * 4]),
+        }
+    }
+
+    fn new_async_resource_cache() -> ResourceCache {
+        let workers = Arc::new(ThreadPoolBuilder::new().build().unwrap());
+        ResourceCache::new(
+            TextureCache::new_for_testing(2048, 1024),
+            GlyphRasterizer::new(workers).unwrap(),
+            Some(Box::new(Handler)),
+            false,
+            true,
+            Vec::new(),
+        )
+    }
+
+    fn add_blob(resource_cache: &mut ResourceCache, key: ImageKey) {
+        let descriptor = ImageDescriptor::new(16, 16, ImageFormat::BGRA8, false, false);
+        resource_cache.add_image_template(key, descriptor, CachedImageData::Blob, None);
+        resource_cache.add_blob_image(BlobImageKey(key), &descriptor, None, Arc::new(Vec::new()));
+    }
+
+    fn request(key: ImageKey) -> ImageRequest {
+        ImageRequest {
+            key,
+            rendering: ImageRendering::Auto,
+            tile: None,
+        }
+    }
+
+    #[test]
+    fn async_blob_requests_complete() {
+        let mut resource_cache = new_async_resource_cache();
+        let mut gpu_cache = GpuCache::new_for_testing();
+        let key = ImageKey(IdNamespace(0), 1);
+        add_blob(&mut resource_cache, key);
+
+        resource_cache.begin_frame(FrameStamp::first(DocumentId::INVALID));
+        resource_cache.request_image(request(key), &mut gpu_cache);
+        assert!(resource_cache.has_pending_blob_rasterization());
+
+        let (rasterizer, requests) = resource_cache.create_async_blob_requests();
+        assert_eq!(requests.len(), 1);
+        let (mut rasterizer, info) = rasterizer.unwrap();
+        let results = rasterizer.rasterize(&requests, true);
+
+        // The async rasterizer must not replace the one of the current scene.
+        resource_cache.set_blob_rasterizer(rasterizer, info);
+        assert!(resource_cache.blob_image_rasterizer.is_none());
+
+        assert!(resource_cache.add_rasterized_blob_images(results));
+        assert!(!resource_cache.has_pending_blob_rasterization());
+        assert!(resource_cache.rasterized_blob_images.contains_key(&BlobImageKey(key)));
+    }
+
+    #[test]
+    fn deleting_a_blob_drops_its_async_requests() {
+        let mut resource_cache = new_async_resource_cache();
+        let mut gpu_cache = GpuCache::new_for_testing();
+        let deferred_key = ImageKey(IdNamespace(0), 1);
+        let sent_key = ImageKey(IdNamespace(0), 2);
+        add_blob(&mut resource_cache, deferred_key);
+        add_blob(&mut resource_cache, sent_key);
+
+        resource_cache.begin_frame(FrameStamp::first(DocumentId::INVALID));
+        resource_cache.request_image(request(sent_key), &mut gpu_cache);
+        let (_, sent_requests) = resource_cache.create_async_blob_requests();
+        resource_cache.request_image(request(deferred_key), &mut gpu_cache);
+        assert!(resource_cache.has_pending_blob_rasterization());
+
+        resource_cache.delete_image_template(deferred_key);
+        resource_cache.delete_image_template(sent_key);
+        assert!(!resource_cache.has_pending_blob_rasterization());
+        assert!(resource_cache.create_async_blob_requests().0.is_none());
+
+        // Results for deleted blobs that arrive late are dropped.
+        let results = sent_requests.iter().map(|params| (params.request, Ok(rasterized()))).collect();
+        assert!(!resource_cache.add_rasterized_blob_images(results));
+        assert!(resource_cache.rasterized_blob_images.is_empty());
+    }
+}
@@ -2,20 +2,23 @@
  * License, v. 2.0. If a copy of the MPL was not distributed with this
  * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
 
-use api::{BuiltDisplayList, ColorF, DynamicProperties, Epoch, LayoutSize};
+use api::{BuiltDisplayList, ColorF, DynamicProperties, Epoch, ImageKey, LayoutRect, LayoutSize};
 use api::{FilterOp, LayoutTransform, PipelineId, PropertyBinding, PropertyBindingId};
 use api::{ItemRange, MixBlendMode, StackingContext};
-use internal_types::FastHashMap;
+use internal_types::{FastHashMap, FastHashSet};
 use std::sync::Arc;
 
 /// Stores a map of the animated property bindings for the current display list. These
-/// can be used to animate the transform and/or opacity of a display list without
-/// re-submitting the display list itself.
+/// can be used to animate the transform, opacity, solid colors, image keys and clip
+/// rects of a display list without re-submitting the display list itself.
 #[cfg_attr(feature = "capture", derive(Serialize))]
 #[cfg_attr(feature = "replay", derive(Deserialize))]
 pub struct SceneProperties {
     transform_properties: FastHashMap<PropertyBindingId, LayoutTransform>,
     float_properties: FastHashMap<PropertyBindingId, f32>,
+    color_properties: FastHashMap<PropertyBindingId, ColorF>,
+    image_properties: FastHashMap<PropertyBindingId, ImageKey>,
+    rect_properties: FastHashMap<PropertyBindingId, LayoutRect>,
     current_properties: DynamicProperties,
     pending_properties: Option<DynamicProperties>,
 }
@@ -25,6 +28,9 @@ impl SceneProperties {
         SceneProperties {
             transform_properties: FastHashMap::default(),
             float_properties: FastHashMap::default(),
+            color_properties: FastHashMap::default(),
+            image_properties: FastHashMap::default(),
+            rect_properties: FastHashMap::default(),
             current_properties: DynamicProperties::default(),
             pending_properties: None,
         }
@@ -43,6 +49,9 @@ impl SceneProperties {
 
         pending_properties.transforms.extend(properties.transforms);
         pending_properties.floats.extend(properties.floats);
+        pending_properties.colors.extend(properties.colors);
+        pending_properties.images.extend(properties.images);
+        pending_properties.rects.extend(properties.rects);
 
         self.pending_properties = Some(pending_properties);
     }
@@ -62,6 +71,9 @@ impl SceneProperties {
             if *pending_properties != self.current_properties {
                 self.transform_properties.clear();
                 self.float_properties.clear();
+                self.color_properties.clear();
+                self.image_properties.clear();
+                self.rect_properties.clear();
 
                 for property in &pending_properties.transforms {
                     self.transform_properties
@@ -73,6 +85,21 @@ impl SceneProperties {
                         .insert(property.key.id, property.value);
                 }
 
+                for property in &pending_properties.colors {
+                    self.color_properties
+                        .insert(property.key.id, property.value);
+                }
+
+                for property in &pending_properties.images {
+                    self.image_properties
+                        .insert(property.key.id, property.value);
+                }
+
+                for property in &pending_properties.rects {
+                    self.rect_properties
+                        .insert(property.key.id, property.value);
+                }
+
                 self.current_properties = pending_properties.clone();
                 properties_changed = true;
             }
@@ -81,6 +108,11 @@ impl SceneProperties {
         properties_changed
     }
 
+    /// Get the current values of all float properties.
+    pub fn float_properties(&self) -> &FastHashMap<PropertyBindingId, f32> {
+        &self.float_properties
+    }
+
     /// Get the current value for a transform property.
     pub fn resolve_layout_transform(
         &self,
@@ -113,8 +145,67 @@ impl SceneProperties {
         }
     }
 
-    pub fn float_properties(&self) -> &FastHashMap<PropertyBindingId, f32> {
-        &self.float_properties
+    /// Get the current values of all color properties.
+    pub fn color_properties(&self) -> &FastHashMap<PropertyBindingId, ColorF> {
+        &self.color_properties
+    }
+
+    /// Get the current value for a color property.
+    pub fn resolve_color(
+        &self,
+        property: &PropertyBinding<ColorF>
+    ) -> ColorF {
+        match *property {
+            PropertyBinding::Value(value) => value,
+            PropertyBinding::Binding(ref key, v) => {
+                self.color_properties
+                    .get(&key.id)
+                    .cloned()
+                    .unwrap_or(v)
+            }
+        }
+    }
+
+    /// Get the current values of all image key properties.
+    pub fn image_properties(&self) -> &FastHashMap<PropertyBindingId, ImageKey> {
+        &self.image_properties
+    }
+
+    /// Get the current value for an image key property.
+    pub fn resolve_image_key(
+        &self,
+        property: &PropertyBinding<ImageKey>
+    ) -> ImageKey {
+        match *property {
+            PropertyBinding::Value(value) => value,
+            PropertyBinding::Binding(ref key, v) => {
+                self.image_properties
+                    .get(&key.id)
+                    .cloned()
+                    .unwrap_or(v)
+            }
+        }
+    }
+
+    /// Get the current values of all rect properties.
+    pub fn rect_properties(&self) -> &FastHashMap<PropertyBindingId, LayoutRect> {
+        &self.rect_properties
+    }
+
+    /// Get the current value for a rect property.
+    pub fn resolve_layout_rect(
+        &self,
+        property: &PropertyBinding<LayoutRect>
+    ) -> LayoutRect {
+        match *property {
+            PropertyBinding::Value(value) => value,
+            PropertyBinding::Binding(ref key, v) => {
+                self.rect_properties
+                    .get(&key.id)
+                    .cloned()
+                    .unwrap_or(v)
+            }
+        }
     }
 }
 
@@ -138,6 +229,9 @@ pub struct Scene {
     pub root_pipeline_id: Option<PipelineId>,
     pub pipelines: FastHashMap<PipelineId, Arc<ScenePipeline>>,
     pub pipeline_epochs: FastHashMap<PipelineId, Epoch>,
+    /// Pipelines whose content process went away. They keep their last good
+    /// display list and epoch until they are removed.
+    pub lost_pipelines: FastHashSet<PipelineId>,
 }
 
 impl Scene {
@@ -146,6 +240,7 @@ impl Scene {
             root_pipeline_id: None,
             pipelines: FastHashMap::default(),
             pipeline_epochs: FastHashMap::default(),
+            lost_pipelines: FastHashSet::default(),
         }
     }
 
@@ -169,6 +264,10 @@ impl Scene {
         viewport_size: LayoutSize,
         content_size: LayoutSize,
     ) {
+        if self.lost_pipelines.contains(&pipeline_id) {
+            return;
+        }
+
         let new_pipeline = ScenePipeline {
             pipeline_id,
             viewport_size,
@@ -187,9 +286,21 @@ impl Scene {
         }
         self.pipelines.remove(&pipeline_id);
         self.pipeline_epochs.remove(&pipeline_id);
+        self.lost_pipelines.remove(&pipeline_id);
+    }
+
+    pub fn mark_pipeline_lost(&mut self, pipeline_id: PipelineId) {
+        self.lost_pipelines.insert(pipeline_id);
+    }
+
+    pub fn is_pipeline_lost(&self, pipeline_id: PipelineId) -> bool {
+        self.lost_pipelines.contains(&pipeline_id)
     }
 
     pub fn update_epoch(&mut self, pipeline_id: PipelineId, epoch: Epoch) {
+        if self.lost_pipelines.contains(&pipeline_id) {
+            return;
+        }
         self.pipeline_epochs.insert(pipeline_id, epoch);
     }
 
@@ -19,8 +19,8 @@ use malloc_size_of::{MallocSizeOf, MallocSizeOfOps};
 use prim_store::{PrimitiveKeyKind};
 use prim_store::PrimitiveStoreStats;
 use prim_store::borders::{ImageBorder, NormalBorderPrim};
-use prim_store::gradient::{LinearGradient, RadialGradient};
-use prim_store::image::{Image, YuvImage};
+use prim_store::gradient::{ConicGradient, LinearGradient, RadialGradient};
+use prim_store::image::{CrossFade, Image, YuvImage};
 use prim_store::line_dec::LineDecoration;
 use prim_store::picture::Picture;
 use prim_store::text_run::TextRun;
@@ -40,6 +40,7 @@ pub struct Transaction {
     pub document_id: DocumentId,
     pub display_list_updates: Vec<DisplayListUpdate>,
     pub removed_pipelines: Vec<PipelineId>,
+    pub lost_pipelines: Vec<PipelineId>,
     pub epoch_updates: Vec<(PipelineId, Epoch)>,
     pub request_scene_build: Option<SceneRequest>,
     pub blob_requests: Vec<BlobImageParams>,
@@ -59,12 +60,14 @@ impl Transaction {
             self.display_list_updates.is_empty() &&
             self.epoch_updates.is_empty() &&
             self.removed_pipelines.is_empty() &&
+            self.lost_pipelines.is_empty() &&
             self.blob_requests.is_empty() &&
             self.set_root_pipeline.is_none()
     }
 
     pub fn should_build_scene(&self) -> bool {
         !self.display_list_updates.is_empty() ||
+            !self.lost_pipelines.is_empty() ||
             self.set_root_pipeline.is_some()
     }
 
@@ -238,6 +241,7 @@ macro_rules! impl_interner_mut {
 }
 
 impl_interner_mut! {
+    CrossFade: cross_fade,
     Image: image,
     ImageBorder: image_border,
     LineDecoration: line_decoration,
@@ -246,6 +250,7 @@ impl_interner_mut! {
     Picture: picture,
     PrimitiveKeyKind: prim,
     RadialGradient: radial_grad,
+    ConicGradient: conic_grad,
     TextRun: text_run,
     YuvImage: yuv_image,
 }
@@ -487,6 +492,10 @@ impl SceneBuilder {
             scene.set_root_pipeline_id(id);
         }
 
+        for pipeline_id in &txn.lost_pipelines {
+            scene.mark_pipeline_lost(*pipeline_id)
+        }
+
         for pipeline_id in &txn.removed_pipelines {
             scene.remove_pipeline(*pipeline_id)
         }
@@ -70,6 +70,12 @@ const DUAL_SOURCE_FEATURE: &str = "DUAL_SOURCE_BLENDING";
 
 pub struct LazilyCompiledShader<B> {
     program: Option<Program>,
+    /// Set once the program failed to build, e.g. because it is missing from
+    /// the pipeline requirements. Batches that need it are skipped afterwards.
+    unavailable: bool,
+    /// The error the program failed to build with, reported when the shader
+    /// is first bound.
+    error: Option<ShaderError>,
     name: &'static str,
     kind: ShaderKind,
     features: Vec<&'static str>,
@@ -86,16 +92,30 @@ impl<B: hal::Backend> LazilyCompiledShader<B> {
     ) -> Result<Self, ShaderError> {
         let mut shader = LazilyCompiledShader {
             program: None,
+            unavailable: false,
+            error: None,
             name,
             kind,
             features: features.to_vec(),
             phantom_data: PhantomData,
         };
 
-        if precache_flags.intersects(ShaderPrecacheFlags::ASYNC_COMPILE | ShaderPrecacheFlags::FULL_COMPILE)
-            && cfg!(feature="gleam") {
+        // The hal backend builds all the pipelines of a program at once, so
+        // there `ASYNC_COMPILE` shaders are built between frames instead, see
+        // `Shaders::precache_step`.
+        let eager_flags = if cfg!(feature = "gleam") {
+            ShaderPrecacheFlags::ASYNC_COMPILE | ShaderPrecacheFlags::FULL_COMPILE
+        } else {
+            ShaderPrecacheFlags::FULL_COMPILE
+        };
+        if precache_flags.intersects(eager_flags) {
             let t0 = precise_time_ns();
-            shader.get_internal(device, precache_flags)?;
+            if let Err(e) = shader.get_internal(device, precache_flags) {
+                // Like a lazily built shader, a missing one only skips the
+                // batches using it instead of failing the renderer.
+                shader.set_unavailable(e);
+                return Ok(shader);
+            }
             let t1 = precise_time_ns();
             debug!("[C: {:.1} ms ] Precache {} {:?}",
                 (t1 - t0) as f64 / 1000000.0,
@@ -107,21 +127,39 @@ impl<B: hal::Backend> LazilyCompiledShader<B> {
         Ok(shader)
     }
 
+    /// Binds the program, building it first if needed. Returns `false` if the
+    /// shader is unavailable, in which case the caller should skip its draws.
     pub fn bind(
         &mut self,
         device: &mut Device<B>,
         projection: &Transform3D<f32>,
         renderer_errors: &mut Vec<RendererError>,
-    ) {
-        let program = match self.get(device) {
-            Ok(program) => program,
-            Err(e) => {
+    ) -> bool {
+        if !self.unavailable {
+            if let Err(e) = self.get(device) {
+                self.set_unavailable(e);
+            }
+        }
+        if self.unavailable {
+            if let Some(e) = self.error.take() {
                 renderer_errors.push(RendererError::from(e));
-                return;
             }
-        };
+            return false;
+        }
+        let program = self.program.as_ref().unwrap();
         device.bind_program(program);
         device.set_uniforms(program, projection);
+        true
+    }
+
+    fn set_unavailable(&mut self, error: ShaderError) {
+        warn!("Shader {} {:?} is unavailable, skipping batches using it: {:?}",
+            self.name,
+            self.features,
+            error,
+        );
+        self.unavailable = true;
+        self.error = Some(error);
     }
 
     fn get_internal(
@@ -146,6 +184,19 @@ impl<B: hal::Backend> LazilyCompiledShader<B> {
         self.get_internal(device, ShaderPrecacheFlags::FULL_COMPILE)
     }
 
+    /// Returns true if the program was built, or failed to build.
+    fn is_resolved(&self) -> bool {
+        self.program.is_some() || self.unavailable
+    }
+
+    /// Builds the program ahead of its first use. Failures are reported when
+    /// the shader is bound.
+    fn precache(&mut self, device: &mut Device<B>) {
+        if let Err(e) = self.get(device) {
+            self.set_unavailable(e);
+        }
+    }
+
     fn deinit(self, device: &mut Device<B>) {
         if let Some(program) = self.program {
             device.delete_program(program);
@@ -155,6 +206,8 @@ impl<B: hal::Backend> LazilyCompiledShader<B> {
     #[cfg(not(feature = "gleam"))]
     fn reset(&mut self) {
         self.program = None;
+        self.unavailable = false;
+        self.error = None;
     }
 }
 
@@ -266,14 +319,15 @@ impl<B: hal::Backend> BrushShader<B> {
         self.debug_overdraw.deinit(device);
     }
 
-    #[cfg(not(feature = "gleam"))]
-    fn reset(&mut self) {
-        self.alpha.reset();
-        self.opaque.reset();
+    fn collect_mut<'a>(&'a mut self, shaders: &mut Vec<&'a mut LazilyCompiledShader<B>>) {
+        shaders.push(&mut self.opaque);
+        shaders.push(&mut self.alpha);
         if let Some(ref mut dual_source) = self.dual_source {
-            dual_source.reset();
+            shaders.push(dual_source);
         }
+        shaders.push(&mut self.debug_overdraw);
     }
+
 }
 
 pub struct TextShader<B: hal::Backend> {
@@ -344,11 +398,10 @@ impl<B: hal::Backend> TextShader<B> {
         self.debug_overdraw.deinit(device);
     }
 
-    #[cfg(not(feature = "gleam"))]
-    fn reset(&mut self) {
-        self.simple.reset();
-        self.glyph_transform.reset();
-        self.debug_overdraw.reset();
+    fn collect_mut<'a>(&'a mut self, shaders: &mut Vec<&'a mut LazilyCompiledShader<B>>) {
+        shaders.push(&mut self.simple);
+        shaders.push(&mut self.glyph_transform);
+        shaders.push(&mut self.debug_overdraw);
     }
 }
 
@@ -364,6 +417,7 @@ pub struct Shaders<B: hal::Backend> {
     pub cs_border_solid: LazilyCompiledShader<B>,
     pub cs_scale_a8: LazilyCompiledShader<B>,
     pub cs_scale_rgba8: LazilyCompiledShader<B>,
+    pub cs_svg_filter: LazilyCompiledShader<B>,
     pub cs_line_decoration: LazilyCompiledShader<B>,
 
     // Brush shaders
@@ -371,8 +425,11 @@ pub struct Shaders<B: hal::Backend> {
     brush_image: Vec<Option<BrushShader<B>>>,
     brush_blend: BrushShader<B>,
     brush_mix_blend: BrushShader<B>,
+    brush_backdrop_filter: BrushShader<B>,
     brush_yuv_image: Vec<Option<BrushShader<B>>>,
+    brush_cross_fade: Vec<Option<BrushShader<B>>>,
     brush_radial_gradient: BrushShader<B>,
+    brush_conic_gradient: BrushShader<B>,
     brush_linear_gradient: BrushShader<B>,
 
     /// These are "cache clip shaders". These shaders are used to
@@ -401,6 +458,9 @@ impl<B: hal::Backend> Shaders<B> {
         _gl_type: GlType,
         options: &RendererOptions,
     ) -> Result<Self, ShaderError> {
+        let use_dual_source_blending = !options.disable_dual_source_blending &&
+            device.supports_dual_source_blending();
+
         let brush_solid = BrushShader::new(
             "brush_solid",
             device,
@@ -425,6 +485,14 @@ impl<B: hal::Backend> Shaders<B> {
             false,
         )?;
 
+        let brush_backdrop_filter = BrushShader::new(
+            "brush_backdrop_filter",
+            device,
+            &[],
+            options.precache_flags,
+            false,
+        )?;
+
         let brush_radial_gradient = BrushShader::new(
             "brush_radial_gradient",
             device,
@@ -437,6 +505,18 @@ impl<B: hal::Backend> Shaders<B> {
             false,
         )?;
 
+        let brush_conic_gradient = BrushShader::new(
+            "brush_conic_gradient",
+            device,
+            if options.enable_dithering {
+               &[DITHERING_FEATURE]
+            } else {
+               &[]
+            },
+            options.precache_flags,
+            false,
+        )?;
+
         let brush_linear_gradient = BrushShader::new(
             "brush_linear_gradient",
             device,
@@ -505,13 +585,21 @@ impl<B: hal::Backend> Shaders<B> {
             options.precache_flags,
         )?;
 
+        let cs_svg_filter = LazilyCompiledShader::new(
+            ShaderKind::Cache(VertexArrayKind::SvgFilter),
+            "cs_svg_filter",
+            &[],
+            device,
+            options.precache_flags,
+        )?;
+
         let ps_text_run = TextShader::new("ps_text_run",
             device,
             &[],
             options.precache_flags,
         )?;
 
-        let dual_source_precache_flags = if options.disable_dual_source_blending {
+        let dual_source_precache_flags = if !use_dual_source_blending {
             ShaderPrecacheFlags::empty()
         } else {
             options.precache_flags
@@ -544,7 +632,7 @@ impl<B: hal::Backend> Shaders<B> {
                     device,
                     &image_features,
                     options.precache_flags,
-                    !options.disable_dual_source_blending,
+                    use_dual_source_blending,
                 )?);
             }
             image_features.clear();
@@ -583,6 +671,33 @@ impl<B: hal::Backend> Shaders<B> {
             }
         }
 
+        // All cross-fade configuration.
+        let mut cross_fade_features = Vec::new();
+        let mut brush_cross_fade = Vec::new();
+        // PrimitiveShader is not clonable. Use push() to initialize the vec.
+        for _ in 0 .. IMAGE_BUFFER_KINDS.len() {
+            brush_cross_fade.push(None);
+        }
+        for buffer_kind in 0 .. IMAGE_BUFFER_KINDS.len() {
+            if IMAGE_BUFFER_KINDS[buffer_kind].has_platform_support(
+                #[cfg(feature = "gleam")]
+                    &_gl_type,
+            ) {
+                let feature_string = IMAGE_BUFFER_KINDS[buffer_kind].get_feature_string();
+                if feature_string != "" {
+                    cross_fade_features.push(feature_string);
+                }
+                brush_cross_fade[buffer_kind] = Some(BrushShader::new(
+                    "brush_cross_fade",
+                    device,
+                    &cross_fade_features,
+                    options.precache_flags,
+                    false,
+                )?);
+            }
+            cross_fade_features.clear();
+        }
+
         let cs_line_decoration = LazilyCompiledShader::new(
             ShaderKind::Cache(VertexArrayKind::LineDecoration),
             "cs_line_decoration",
@@ -623,12 +738,16 @@ impl<B: hal::Backend> Shaders<B> {
             cs_border_solid,
             cs_scale_a8,
             cs_scale_rgba8,
+            cs_svg_filter,
             brush_solid,
             brush_image,
             brush_blend,
             brush_mix_blend,
+            brush_backdrop_filter,
             brush_yuv_image,
+            brush_cross_fade,
             brush_radial_gradient,
+            brush_conic_gradient,
             brush_linear_gradient,
             cs_clip_rectangle,
             cs_clip_box_shadow,
@@ -653,7 +772,8 @@ impl<B: hal::Backend> Shaders<B> {
                     BrushBatchKind::Solid => {
                         &mut self.brush_solid
                     }
-                    BrushBatchKind::Image(image_buffer_kind) => {
+                    BrushBatchKind::Image(image_buffer_kind) |
+                    BrushBatchKind::AnisotropicImage(image_buffer_kind) => {
                         self.brush_image[image_buffer_kind as usize]
                             .as_mut()
                             .expect("Unsupported image shader kind")
@@ -664,9 +784,15 @@ impl<B: hal::Backend> Shaders<B> {
                     BrushBatchKind::MixBlend { .. } => {
                         &mut self.brush_mix_blend
                     }
+                    BrushBatchKind::BackdropFilter { .. } => {
+                        &mut self.brush_backdrop_filter
+                    }
                     BrushBatchKind::RadialGradient => {
                         &mut self.brush_radial_gradient
                     }
+                    BrushBatchKind::ConicGradient => {
+                        &mut self.brush_conic_gradient
+                    }
                     BrushBatchKind::LinearGradient => {
                         &mut self.brush_linear_gradient
                     }
@@ -677,6 +803,11 @@ impl<B: hal::Backend> Shaders<B> {
                             .as_mut()
                             .expect("Unsupported YUV shader kind")
                     }
+                    BrushBatchKind::CrossFade(image_buffer_kind) => {
+                        self.brush_cross_fade[image_buffer_kind as usize]
+                            .as_mut()
+                            .expect("Unsupported cross-fade shader kind")
+                    }
                 };
                 brush_shader.get(key.blend_mode, debug_flags)
             }
@@ -690,47 +821,76 @@ impl<B: hal::Backend> Shaders<B> {
         }
     }
 
-    #[cfg(not(feature = "gleam"))]
-    pub fn reset(&mut self) {
-        self.cs_scale_a8.reset();
-        self.cs_scale_rgba8.reset();
-        self.cs_blur_a8.reset();
-        self.cs_blur_rgba8.reset();
-        self.brush_solid.reset();
-        self.brush_blend.reset();
-        self.brush_mix_blend.reset();
-        self.brush_radial_gradient.reset();
-        self.brush_linear_gradient.reset();
-        self.cs_clip_rectangle.reset();
-        self.cs_clip_box_shadow.reset();
-        self.cs_clip_image.reset();
-        self.cs_line_decoration.reset();
-        self.ps_text_run.reset();
-        self.ps_text_run_dual_source.reset();
-        for mut shader in &mut self.brush_image {
-            if let Some(ref mut shader) = shader {
-                shader.reset();
-            }
+    /// Returns all the shaders, including the variants of brush and text
+    /// shaders.
+    fn all_mut(&mut self) -> Vec<&mut LazilyCompiledShader<B>> {
+        let mut shaders = vec![
+            &mut self.cs_blur_a8,
+            &mut self.cs_blur_rgba8,
+            &mut self.cs_border_segment,
+            &mut self.cs_border_solid,
+            &mut self.cs_scale_a8,
+            &mut self.cs_scale_rgba8,
+            &mut self.cs_svg_filter,
+            &mut self.cs_line_decoration,
+            &mut self.cs_clip_rectangle,
+            &mut self.cs_clip_box_shadow,
+            &mut self.cs_clip_image,
+            &mut self.ps_split_composite,
+        ];
+        self.brush_solid.collect_mut(&mut shaders);
+        self.brush_blend.collect_mut(&mut shaders);
+        self.brush_mix_blend.collect_mut(&mut shaders);
+        self.brush_backdrop_filter.collect_mut(&mut shaders);
+        self.brush_radial_gradient.collect_mut(&mut shaders);
+        self.brush_conic_gradient.collect_mut(&mut shaders);
+        self.brush_linear_gradient.collect_mut(&mut shaders);
+        let brush_variants = self.brush_image.iter_mut()
+            .chain(self.brush_yuv_image.iter_mut())
+            .chain(self.brush_cross_fade.iter_mut())
+            .filter_map(|shader| shader.as_mut());
+        for shader in brush_variants {
+            shader.collect_mut(&mut shaders);
         }
-        for mut shader in &mut self.brush_yuv_image {
-            if let Some(ref mut shader) = shader {
-                shader.reset();
+        self.ps_text_run.collect_mut(&mut shaders);
+        self.ps_text_run_dual_source.collect_mut(&mut shaders);
+        shaders
+    }
+
+    /// Builds the shaders that weren't used yet until `deadline_ns` (in
+    /// `precise_time_ns` time) passes. Returns true once all are built.
+    pub fn precache_step(&mut self, device: &mut Device<B>, deadline_ns: u64) -> bool {
+        for shader in self.all_mut() {
+            if shader.is_resolved() {
+                continue;
+            }
+            if precise_time_ns() >= deadline_ns {
+                return false;
             }
+            shader.precache(device);
+        }
+        true
+    }
+
+    #[cfg(not(feature = "gleam"))]
+    pub fn reset(&mut self) {
+        for shader in self.all_mut() {
+            shader.reset();
         }
-        self.cs_border_segment.reset();
-        self.cs_border_solid.reset();
-        self.ps_split_composite.reset();
     }
 
     pub fn deinit(self, device: &mut Device<B>) {
         self.cs_scale_a8.deinit(device);
         self.cs_scale_rgba8.deinit(device);
+        self.cs_svg_filter.deinit(device);
         self.cs_blur_a8.deinit(device);
         self.cs_blur_rgba8.deinit(device);
         self.brush_solid.deinit(device);
         self.brush_blend.deinit(device);
         self.brush_mix_blend.deinit(device);
+        self.brush_backdrop_filter.deinit(device);
         self.brush_radial_gradient.deinit(device);
+        self.brush_conic_gradient.deinit(device);
         self.brush_linear_gradient.deinit(device);
         self.cs_clip_rectangle.deinit(device);
         self.cs_clip_box_shadow.deinit(device);
@@ -747,6 +907,11 @@ impl<B: hal::Backend> Shaders<B> {
                 shader.deinit(device);
             }
         }
+        for shader in self.brush_cross_fade {
+            if let Some(shader) = shader {
+                shader.deinit(device);
+            }
+        }
         self.cs_border_solid.deinit(device);
         self.cs_line_decoration.deinit(device);
         self.cs_border_segment.deinit(device);
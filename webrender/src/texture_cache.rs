@@ -21,7 +21,7 @@ use std::time::{Duration, SystemTime};
 use std::rc::Rc;
 
 /// The size of each region/layer in shared cache texture arrays.
-const TEXTURE_REGION_DIMENSIONS: i32 = 512;
+pub const TEXTURE_REGION_DIMENSIONS: i32 = 512;
 
 /// The number of pixels in a region. Derived from the above.
 const TEXTURE_REGION_PIXELS: usize =
@@ -139,6 +139,11 @@ impl CacheEntry {
         }
     }
 
+    /// Returns the number of GPU bytes covered by this entry.
+    fn size_in_bytes(&self) -> usize {
+        self.format.size_in_bytes(self.size.width, self.size.height)
+    }
+
     fn evict(&self) {
         if let Some(eviction_notice) = self.eviction_notice.as_ref() {
             eviction_notice.notify();
@@ -208,6 +213,7 @@ struct SharedTextures {
     array_rgba8_nearest: TextureArray,
     array_a8_linear: TextureArray,
     array_a16_linear: TextureArray,
+    array_rg8_linear: TextureArray,
     array_rgba8_linear: TextureArray,
 }
 
@@ -228,6 +234,12 @@ impl SharedTextures {
                 ImageFormat::R16,
                 TextureFilter::Linear,
             ),
+            // Used for two-channel data, like the interleaved chroma plane of
+            // NV12 images, at half the memory of storing it as BGRA8.
+            array_rg8_linear: TextureArray::new(
+                ImageFormat::RG8,
+                TextureFilter::Linear,
+            ),
             // The primary cache for images, glyphs, etc.
             array_rgba8_linear: TextureArray::new(
                 ImageFormat::BGRA8,
@@ -247,6 +259,7 @@ impl SharedTextures {
     fn size_in_bytes(&self) -> usize {
         self.array_a8_linear.size_in_bytes() +
         self.array_a16_linear.size_in_bytes() +
+        self.array_rg8_linear.size_in_bytes() +
         self.array_rgba8_linear.size_in_bytes() +
         self.array_rgba8_nearest.size_in_bytes()
     }
@@ -255,6 +268,7 @@ impl SharedTextures {
     fn empty_region_bytes(&self) -> usize {
         self.array_a8_linear.empty_region_bytes() +
         self.array_a16_linear.empty_region_bytes() +
+        self.array_rg8_linear.empty_region_bytes() +
         self.array_rgba8_linear.empty_region_bytes() +
         self.array_rgba8_nearest.empty_region_bytes()
     }
@@ -263,6 +277,7 @@ impl SharedTextures {
     fn clear(&mut self, updates: &mut TextureUpdateList) {
         self.array_a8_linear.clear(updates);
         self.array_a16_linear.clear(updates);
+        self.array_rg8_linear.clear(updates);
         self.array_rgba8_linear.clear(updates);
         self.array_rgba8_nearest.clear(updates);
     }
@@ -272,6 +287,7 @@ impl SharedTextures {
         match (format, filter) {
             (ImageFormat::R8, TextureFilter::Linear) => &mut self.array_a8_linear,
             (ImageFormat::R16, TextureFilter::Linear) => &mut self.array_a16_linear,
+            (ImageFormat::RG8, TextureFilter::Linear) => &mut self.array_rg8_linear,
             (ImageFormat::BGRA8, TextureFilter::Linear) => &mut self.array_rgba8_linear,
             (ImageFormat::BGRA8, TextureFilter::Nearest) => &mut self.array_rgba8_nearest,
             (_, _) => unreachable!(),
@@ -398,7 +414,7 @@ impl EvictionThresholdBuilder {
         let max_frames = self.max_frames
             .map(|f| (f as f64 * pressure_factor) as usize)
             .unwrap_or(0)
-            .min(self.now.frame_id().as_usize() - 1);
+            .min(self.now.frame_id().as_usize().saturating_sub(1));
         let max_time_ms = self.max_time_ms
             .map(|f| (f as f64 * pressure_factor) as usize)
             .unwrap_or(0) as u64;
@@ -478,6 +494,10 @@ pub struct TextureCache {
     /// cache memory. `None if we haven't reached the threshold.
     reached_reclaim_threshold: Option<SystemTime>,
 
+    /// Upper bound on the GPU memory used by the cache, set by the embedder
+    /// through `RenderApi::set_texture_cache_budget`. `None` if unbounded.
+    budget_bytes: Option<usize>,
+
     /// Maintains the list of all current items in the texture cache.
     entries: FreeList<CacheEntry, CacheEntryMarker>,
 
@@ -494,36 +514,13 @@ pub struct TextureCache {
 }
 
 impl TextureCache {
-    pub fn new(max_texture_size: i32, mut max_texture_layers: usize) -> Self {
-        if cfg!(target_os = "macos") {
-            // On MBP integrated Intel GPUs, texture arrays appear to be
-            // implemented as a single texture of stacked layers, and that
-            // texture appears to be subject to the texture size limit. As such,
-            // allocating more than 32 512x512 regions results in a dimension
-            // longer than 16k (the max texture size), causing incorrect behavior.
-            //
-            // So we clamp the number of layers on mac. This results in maximum
-            // texture array size of 32MB, which isn't ideal but isn't terrible
-            // either. OpenGL on mac is not long for this earth, so this may be
-            // good enough until we have WebRender on gfx-rs (on Metal).
-            //
-            // Note that we could also define this more generally in terms of
-            // |max_texture_size / TEXTURE_REGION_DIMENSION|, except:
-            //   * max_texture_size is actually clamped beyond the device limit
-            //     by Gecko to 8192, so we'd need to thread the raw device value
-            //     here, and:
-            //   * The bug we're working around is likely specific to a single
-            //     driver family, and those drivers are also likely to share
-            //     the same max texture size of 16k. If we do encounter a driver
-            //     with the same bug but a lower max texture size, we might need
-            //     to rethink our strategy anyway, since a limit below 32MB might
-            //     start to introduce performance issues.
-            max_texture_layers = max_texture_layers.min(32);
-        }
-
+    /// `max_texture_layers` is expected to account for
+    /// `Workarounds::LIMIT_TEXTURE_ARRAY_LAYERS` already.
+    pub fn new(max_texture_size: i32, max_texture_layers: usize) -> Self {
         TextureCache {
             shared_textures: SharedTextures::new(),
             reached_reclaim_threshold: None,
+            budget_bytes: None,
             entries: FreeList::new(),
             max_texture_size,
             max_texture_layers,
@@ -552,6 +549,95 @@ impl TextureCache {
         self.debug_flags = flags;
     }
 
+    /// Sets the maximum number of GPU bytes the cache may hold, or lifts the
+    /// limit if `None`.
+    ///
+    /// Lowering the budget evicts the least recently used standalone entries
+    /// right away. If that isn't enough, the shared cache is cleared, which
+    /// lets its texture arrays shrink. Returns true if anything was evicted,
+    /// in which case frames built before this call may reference freed
+    /// textures and must not be rendered again.
+    pub fn set_budget(&mut self, budget_bytes: Option<usize>) -> bool {
+        debug_assert!(!self.now.is_valid());
+        self.budget_bytes = budget_bytes;
+
+        let budget = match budget_bytes {
+            Some(budget) => budget,
+            None => return false,
+        };
+        if self.debug_flags.contains(DebugFlags::TEXTURE_CACHE_DBG_DISABLE_SHRINK) {
+            return false;
+        }
+
+        let mut total_bytes = self.shared_textures.size_in_bytes() + self.standalone_bytes();
+        if total_bytes <= budget {
+            return false;
+        }
+
+        // Collect the evictable standalone entries of every document, oldest
+        // first.
+        let mut candidates = Vec::new();
+        for (document_id, doc_data) in &self.per_doc_data {
+            for (index, handle) in doc_data.handles.standalone.iter().enumerate() {
+                let entry = self.entries.get(handle);
+                if entry.eviction != Eviction::Manual {
+                    candidates.push((entry.last_access.time(), *document_id, index, entry.size_in_bytes()));
+                }
+            }
+        }
+        candidates.sort_by_key(|&(time, ..)| time);
+
+        let mut to_evict: FastHashMap<DocumentId, Vec<usize>> = FastHashMap::default();
+        for (_, document_id, index, bytes) in candidates {
+            if total_bytes <= budget {
+                break;
+            }
+            to_evict.entry(document_id).or_insert_with(Vec::new).push(index);
+            total_bytes -= bytes;
+        }
+
+        let mut per_doc_data = mem::replace(&mut self.per_doc_data, FastHashMap::default());
+        for (document_id, mut indices) in to_evict {
+            let handles = &mut per_doc_data.get_mut(&document_id).unwrap().handles.standalone;
+            // Remove in decreasing index order so that swap_remove doesn't
+            // move any entry we still need to visit.
+            indices.sort_unstable_by(|a, b| b.cmp(a));
+            for index in indices {
+                let entry = self.entries.free(handles.swap_remove(index));
+                entry.evict();
+                self.free(entry);
+            }
+        }
+        self.per_doc_data = per_doc_data;
+
+        if total_bytes > budget {
+            self.clear_shared();
+            self.reached_reclaim_threshold = None;
+        }
+
+        true
+    }
+
+    /// Returns the number of GPU bytes used by standalone entries across all
+    /// documents.
+    fn standalone_bytes(&self) -> usize {
+        self.per_doc_data
+            .values()
+            .chain(::std::iter::once(&self.doc_data))
+            .flat_map(|doc_data| doc_data.handles.standalone.iter())
+            .map(|handle| self.entries.get(handle).size_in_bytes())
+            .sum()
+    }
+
+    /// Returns true if the cache currently uses more GPU memory than the
+    /// budget allows.
+    fn is_over_budget(&self) -> bool {
+        match self.budget_bytes {
+            Some(budget) => self.shared_textures.size_in_bytes() + self.standalone_bytes() > budget,
+            None => false,
+        }
+    }
+
     /// Clear all standalone textures in the cache.
     pub fn clear_standalone(&mut self) {
         debug_assert!(!self.now.is_valid());
@@ -651,7 +737,10 @@ impl TextureCache {
             .unwrap_or(Duration::default());
         let do_periodic_gc = time_since_last_gc >= Duration::from_secs(5) &&
             self.shared_textures.size_in_bytes() >= RECLAIM_THRESHOLD_BYTES * 2;
-        if do_periodic_gc {
+        // Also collect eagerly if the embedder asked us to stay under a
+        // budget and we've outgrown it.
+        let over_budget = self.is_over_budget();
+        if do_periodic_gc || over_budget {
             let threshold = EvictionThresholdBuilder::new(self.now)
                 .max_frames(1)
                 .max_time_s(10)
@@ -675,7 +764,7 @@ impl TextureCache {
         }
         if let Some(t) = self.reached_reclaim_threshold {
             let dur = self.now.time().duration_since(t).unwrap_or(Duration::default());
-            if dur >= Duration::from_secs(5) {
+            if dur >= Duration::from_secs(5) || (over_budget && self.is_over_budget()) {
                 self.clear_shared();
                 self.reached_reclaim_threshold = None;
             }
@@ -694,11 +783,18 @@ impl TextureCache {
         // at an acceptable level.
         let threshold = self.default_eviction();
         self.expire_old_entries(EntryKind::Standalone, threshold);
+        if self.is_over_budget() {
+            // Anything not used by the frame we just built can go.
+            let threshold = EvictionThresholdBuilder::new(self.now).build();
+            self.expire_old_entries(EntryKind::Standalone, threshold);
+        }
 
         self.shared_textures.array_a8_linear
             .update_profile(&mut texture_cache_profile.pages_a8_linear);
         self.shared_textures.array_a16_linear
             .update_profile(&mut texture_cache_profile.pages_a16_linear);
+        self.shared_textures.array_rg8_linear
+            .update_profile(&mut texture_cache_profile.pages_rg8_linear);
         self.shared_textures.array_rgba8_linear
             .update_profile(&mut texture_cache_profile.pages_rgba8_linear);
         self.shared_textures.array_rgba8_nearest
@@ -1059,6 +1155,20 @@ impl TextureCache {
             allowed_in_shared_cache = false;
         }
 
+        // High bit depth and compressed images are rare enough to not warrant
+        // shared texture arrays of their own, and get standalone textures.
+        if descriptor.format.is_compressed() {
+            allowed_in_shared_cache = false;
+        }
+        match descriptor.format {
+            ImageFormat::RGBAF16 |
+            ImageFormat::RGBAF32 |
+            ImageFormat::RGBAI32 |
+            ImageFormat::RGB10A2 |
+            ImageFormat::RG16 => allowed_in_shared_cache = false,
+            _ => {}
+        }
+
         // TODO(gw): For now, anything that requests nearest filtering and isn't BGRA8
         //           just fails to allocate in a texture page, and gets a standalone
         //           texture. This is probably rare enough that it can be fixed up later.
@@ -1505,9 +1615,9 @@ impl TextureCacheUpdate {
                 panic!("The vector image should have been rasterized.");
             }
             CachedImageData::External(ext_image) => match ext_image.image_type {
-                ExternalImageType::TextureHandle(_) => {
-                    panic!("External texture handle should not go through texture_cache.");
-                }
+                // Atlased external textures are copied on the GPU by the
+                // renderer, once it locked them.
+                ExternalImageType::TextureHandle(_) |
                 ExternalImageType::Buffer => TextureUpdateSource::External {
                     id: ext_image.id,
                     channel_index: ext_image.channel_index,
@@ -1515,8 +1625,8 @@ impl TextureCacheUpdate {
             },
             CachedImageData::Raw(bytes) => {
                 let finish = descriptor.offset +
-                    descriptor.size.width * descriptor.format.bytes_per_pixel() +
-                    (descriptor.size.height - 1) * descriptor.compute_stride();
+                    descriptor.format.row_size_in_bytes(descriptor.size.width) +
+                    (descriptor.format.block_rows(descriptor.size.height) - 1) * descriptor.compute_stride();
                 assert!(bytes.len() >= finish as usize);
 
                 TextureUpdateSource::Bytes { data: bytes }
@@ -1527,7 +1637,7 @@ impl TextureCacheUpdate {
             DirtyRect::Partial(dirty) => {
                 // the dirty rectangle doesn't have to be within the area but has to intersect it, at least
                 let stride = descriptor.compute_stride();
-                let offset = descriptor.offset + dirty.origin.y * stride + dirty.origin.x * descriptor.format.bytes_per_pixel();
+                let offset = descriptor.compute_offset(dirty.origin);
 
                 TextureCacheUpdate {
                     id: texture_id,
@@ -1572,3 +1682,92 @@ fn quantize_dimension(size: i32) -> i32 {
         _ => panic!("Invalid dimensions for cache!"),
     }
 }
+
+#[cfg(test)]
+mod test {
+    use api::{DirtyRect, ImageDescriptor, ImageFormat};
+    use device::TextureFilter;
+    use gpu_cache::GpuCache;
+    use gpu_types::UvRectKind;
+    use internal_types::{TextureCacheAllocationKind, TextureSource};
+    use super::{Eviction, TextureCache, TextureCacheHandle};
+
+    #[test]
+    fn two_channel_and_alpha_images_get_shared_arrays_of_their_format() {
+        let mut texture_cache = TextureCache::new_for_testing(2048, 1024);
+        let mut gpu_cache = GpuCache::new_for_testing();
+
+        let mut allocate = |format| {
+            let mut handle = TextureCacheHandle::invalid();
+            texture_cache.update(
+                &mut handle,
+                ImageDescriptor::new(32, 32, format, false, false),
+                TextureFilter::Linear,
+                None,
+                [0.0; 3],
+                DirtyRect::All,
+                &mut gpu_cache,
+                None,
+                UvRectKind::Rect,
+                Eviction::Auto,
+            );
+            match texture_cache.get(&handle).texture_id {
+                TextureSource::TextureCache(id) => id,
+                source => panic!("Unexpected texture source {:?}", source),
+            }
+        };
+        let a8 = allocate(ImageFormat::R8);
+        let rg8 = allocate(ImageFormat::RG8);
+        let bgra8 = allocate(ImageFormat::BGRA8);
+        assert_ne!(a8, rg8);
+        assert_ne!(rg8, bgra8);
+
+        let updates = texture_cache.pending_updates();
+        for &(id, format) in &[(a8, ImageFormat::R8), (rg8, ImageFormat::RG8), (bgra8, ImageFormat::BGRA8)] {
+            let info = updates.allocations
+                .iter()
+                .find(|allocation| allocation.id == id)
+                .and_then(|allocation| match allocation.kind {
+                    TextureCacheAllocationKind::Alloc(ref info) => Some(info),
+                    _ => None,
+                })
+                .expect("The shared array wasn't allocated");
+            assert_eq!(info.format, format);
+            assert!(info.is_shared_cache);
+        }
+    }
+
+    #[test]
+    fn rgb10a2_images_get_standalone_textures() {
+        let mut texture_cache = TextureCache::new_for_testing(2048, 1024);
+        let mut gpu_cache = GpuCache::new_for_testing();
+
+        let descriptor = ImageDescriptor::new(32, 32, ImageFormat::RGB10A2, false, false);
+        assert_eq!(descriptor.compute_stride(), 32 * 4);
+        assert!(!texture_cache.is_allowed_in_shared_cache(TextureFilter::Linear, &descriptor));
+
+        let mut handle = TextureCacheHandle::invalid();
+        texture_cache.update(
+            &mut handle,
+            descriptor,
+            TextureFilter::Linear,
+            None,
+            [0.0; 3],
+            DirtyRect::All,
+            &mut gpu_cache,
+            None,
+            UvRectKind::Rect,
+            Eviction::Auto,
+        );
+
+        let updates = texture_cache.pending_updates();
+        assert_eq!(updates.allocations.len(), 1);
+        match updates.allocations[0].kind {
+            TextureCacheAllocationKind::Alloc(ref info) => {
+                assert_eq!(info.format, ImageFormat::RGB10A2);
+                assert!(!info.is_shared_cache);
+            }
+            ref kind => panic!("Unexpected allocation {:?}", kind),
+        }
+    }
+}
@@ -2,19 +2,21 @@
  * License, v. 2.0. If a copy of the MPL was not distributed with this
  * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
 
-use api::{ColorF, BorderStyle, DeviceIntPoint, DeviceIntRect, DeviceIntSize, DevicePixelScale};
-use api::{DocumentLayer, FilterOp, ImageFormat, DevicePoint};
-use api::{MixBlendMode, PipelineId, DeviceRect, LayoutSize, WorldRect};
-use batch::{AlphaBatchBuilder, AlphaBatchContainer, ClipBatcher, resolve_image};
+use api::{ColorF, BorderStyle, DebugFlags, DeviceIntPoint, DeviceIntRect, DeviceIntSize, DevicePixelScale};
+use api::{DocumentLayer, FilterOp, FilterPrimitive, ImageFormat, DevicePoint};
+use api::{MixBlendMode, PipelineId, DeviceRect, LayoutSize, PictureRect, WorldRect, BatchingParameters};
+use batch::{AlphaBatchBuilder, AlphaBatchContainer, BatchBreak, BatchRects, ClipBatcher};
+use batch::{resolve_image, BatchTextures};
 use clip::ClipStore;
 use clip_scroll_tree::{ClipScrollTree};
+use compositor::CompositeTile;
 use debug_render::DebugItem;
 use device::{Texture};
 #[cfg(feature = "pathfinder")]
 use euclid::{TypedPoint2D, TypedVector2D};
 use gpu_cache::{GpuCache};
 use gpu_types::{BorderInstance, BlurDirection, BlurInstance, PrimitiveHeaders, ScalingInstance};
-use gpu_types::{TransformData, TransformPalette, ZBufferIdGenerator};
+use gpu_types::{SvgFilterInstance, TransformData, TransformPalette, ZBufferIdGenerator};
 use internal_types::{CacheTextureId, FastHashMap, SavedTargetIndex, TextureSource};
 #[cfg(feature = "pathfinder")]
 use pathfinder_partitioner::mesh::Mesh;
@@ -53,11 +55,15 @@ pub struct RenderTargetContext<'a, 'rc> {
     pub prim_store: &'a PrimitiveStore,
     pub resource_cache: &'rc mut ResourceCache,
     pub use_dual_source_blending: bool,
+    pub batching_parameters: BatchingParameters,
     pub clip_scroll_tree: &'a ClipScrollTree,
     pub data_stores: &'a DataStores,
     pub surfaces: &'a [SurfaceInfo],
     pub scratch: &'a PrimitiveScratchBuffer,
     pub screen_world_rect: WorldRect,
+    pub debug_flags: DebugFlags,
+    pub native_compositor: bool,
+    pub anisotropic_filtering: bool,
 }
 
 /// Represents a number of rendering operations on a surface.
@@ -342,9 +348,22 @@ pub struct ColorRenderTarget {
     pub horizontal_blurs: Vec<BlurInstance>,
     pub readbacks: Vec<DeviceIntRect>,
     pub scalings: Vec<ScalingInstance>,
+    // SVG filter nodes, batched by the textures their inputs live in.
+    pub svg_filters: Vec<(BatchTextures, Vec<SvgFilterInstance>)>,
     pub blits: Vec<BlitJob>,
     // List of frame buffer outputs for this render target.
     pub outputs: Vec<FrameOutput>,
+    /// The batch breaks of the picture tasks in this target, recorded for
+    /// the `PRIMITIVE_DBG` overlay.
+    pub batch_breaks: Vec<BatchBreak<DeviceRect>>,
+    /// The primitive rects of the batches in this target, recorded for the
+    /// `SHOW_BATCH_RECTS` overlay.
+    pub batch_rects: Vec<BatchRects<DeviceRect>>,
+    /// The picture cache tiles to hand to a native compositor.
+    pub composite_tiles: Vec<CompositeTile>,
+    /// The number of alpha primitives culled because opaque primitives
+    /// hide them.
+    pub occluded_primitives: usize,
     alpha_tasks: Vec<RenderTaskId>,
     screen_size: DeviceIntSize,
     // Track the used rect of the render target, so that
@@ -361,8 +380,13 @@ impl RenderTarget for ColorRenderTarget {
             horizontal_blurs: Vec::new(),
             readbacks: Vec::new(),
             scalings: Vec::new(),
+            svg_filters: Vec::new(),
             blits: Vec::new(),
             outputs: Vec::new(),
+            batch_breaks: Vec::new(),
+            batch_rects: Vec::new(),
+            composite_tiles: Vec::new(),
+            occluded_primitives: 0,
             alpha_tasks: Vec::new(),
             screen_size,
             used_rect: DeviceIntRect::zero(),
@@ -407,6 +431,10 @@ impl RenderTarget for ColorRenderTarget {
                     let mut batch_builder = AlphaBatchBuilder::new(
                         self.screen_size,
                         scisor_rect,
+                        ctx.batching_parameters.batch_lookback_count,
+                        ctx.debug_flags.intersects(
+                            DebugFlags::PRIMITIVE_DBG | DebugFlags::SHOW_BATCH_RECTS
+                        ),
                     );
 
                     batch_builder.add_pic_to_batch(
@@ -422,9 +450,38 @@ impl RenderTarget for ColorRenderTarget {
                         z_generator,
                     );
 
-                    batch_builder.build(
+                    // Map the picture rects to the target, the same way
+                    // the brush shaders do.
+                    let offset = (target_rect.origin - pic_task.content_origin).to_f32();
+                    let scale = ctx.device_pixel_scale.0;
+                    let to_target = |rect: &PictureRect| {
+                        DeviceRect::from_untyped(&rect.to_untyped())
+                            .scale(scale, scale)
+                            .translate(&offset)
+                    };
+                    if ctx.debug_flags.contains(DebugFlags::PRIMITIVE_DBG) {
+                        for batch_break in batch_builder.batch_breaks() {
+                            self.batch_breaks.push(BatchBreak {
+                                reason: batch_break.reason,
+                                rect: to_target(&batch_break.rect),
+                            });
+                        }
+                    }
+                    if ctx.debug_flags.contains(DebugFlags::SHOW_BATCH_RECTS) {
+                        for batch_rects in batch_builder.batch_rects() {
+                            self.batch_rects.push(BatchRects {
+                                kind: batch_rects.kind,
+                                rects: batch_rects.rects.iter().map(&to_target).collect(),
+                            });
+                        }
+                    }
+
+                    self.composite_tiles.extend(batch_builder.composite_tiles.drain(..));
+
+                    self.occluded_primitives += batch_builder.build(
                         &mut self.alpha_batch_containers,
                         &mut merged_batches,
+                        *task_id,
                     );
                 }
                 _ => {
@@ -490,7 +547,7 @@ impl RenderTarget for ColorRenderTarget {
                 // FIXME(pcwalton): Support color glyphs.
                 panic!("Glyphs should not be added to color target!");
             }
-            RenderTaskKind::Readback(device_rect) => {
+            RenderTaskKind::BackdropCapture(device_rect) => {
                 self.readbacks.push(device_rect);
             }
             RenderTaskKind::Scaling(..) => {
@@ -499,6 +556,52 @@ impl RenderTarget for ColorRenderTarget {
                     src_task_address: render_tasks.get_task_address(task.children[0]),
                 });
             }
+            RenderTaskKind::SvgFilter(ref task_info) => {
+                // The previous node of the filter graph is the only child of
+                // this task. Any other input is read from a saved target.
+                let input_source = |input_task_id: RenderTaskId| {
+                    if task.children.contains(&input_task_id) {
+                        TextureSource::PrevPassColor
+                    } else {
+                        let saved_index = render_tasks[input_task_id].saved_index.expect("no saved index!?");
+                        debug_assert_ne!(saved_index, SavedTargetIndex::PENDING);
+                        TextureSource::RenderTaskCache(saved_index)
+                    }
+                };
+
+                let input_1_task_id = task_info.inputs[0];
+                let input_2_task_id = task_info.inputs.get(1).cloned().unwrap_or(input_1_task_id);
+                let textures = BatchTextures {
+                    colors: [
+                        input_source(input_1_task_id),
+                        input_source(input_2_task_id),
+                        TextureSource::Invalid,
+                    ],
+                };
+
+                let instance = SvgFilterInstance {
+                    task_address: render_tasks.get_task_address(task_id),
+                    input_1_task_address: render_tasks.get_task_address(input_1_task_id),
+                    input_2_task_address: render_tasks.get_task_address(input_2_task_id),
+                    kind: task_info.info.kind(),
+                    generic_int: task_info.info.generic_int(),
+                    extra_data_address: if task_info.info.has_extra_data() {
+                        task_info.extra_gpu_cache_handle.as_int(gpu_cache)
+                    } else {
+                        0
+                    },
+                };
+
+                match self.svg_filters.last_mut() {
+                    Some(&mut (ref batch_textures, ref mut instances))
+                        if batch_textures.colors == textures.colors => {
+                        instances.push(instance);
+                    }
+                    _ => {
+                        self.svg_filters.push((textures, vec![instance]));
+                    }
+                }
+            }
             RenderTaskKind::Blit(ref task_info) => {
                 match task_info.source {
                     BlitSource::Image { key } => {
@@ -617,11 +720,12 @@ impl RenderTarget for AlphaRenderTarget {
         }
 
         match task.kind {
-            RenderTaskKind::Readback(..) |
+            RenderTaskKind::BackdropCapture(..) |
             RenderTaskKind::Picture(..) |
             RenderTaskKind::Blit(..) |
             RenderTaskKind::Border(..) |
             RenderTaskKind::LineDecoration(..) |
+            RenderTaskKind::SvgFilter(..) |
             RenderTaskKind::Glyph(..) => {
                 panic!("BUG: should not be added to alpha target!");
             }
@@ -798,7 +902,8 @@ impl TextureCacheRenderTarget {
             RenderTaskKind::Picture(..) |
             RenderTaskKind::ClipRegion(..) |
             RenderTaskKind::CacheMask(..) |
-            RenderTaskKind::Readback(..) |
+            RenderTaskKind::BackdropCapture(..) |
+            RenderTaskKind::SvgFilter(..) |
             RenderTaskKind::Scaling(..) => {
                 panic!("BUG: unexpected task kind for texture cache target");
             }
@@ -1066,20 +1171,36 @@ pub struct CompositeOps {
     // Requires only a single texture as input (e.g. most filters)
     pub filters: Vec<FilterOp>,
 
+    // An SVG filter graph, applied after the CSS filters
+    pub filter_primitives: Vec<FilterPrimitive>,
+
+    // Filters applied to the backdrop behind the content
+    pub backdrop_filters: Vec<FilterOp>,
+
     // Requires two source textures (e.g. mix-blend-mode)
     pub mix_blend_mode: Option<MixBlendMode>,
 }
 
 impl CompositeOps {
-    pub fn new(filters: Vec<FilterOp>, mix_blend_mode: Option<MixBlendMode>) -> Self {
+    pub fn new(
+        filters: Vec<FilterOp>,
+        filter_primitives: Vec<FilterPrimitive>,
+        backdrop_filters: Vec<FilterOp>,
+        mix_blend_mode: Option<MixBlendMode>,
+    ) -> Self {
         CompositeOps {
             filters,
+            filter_primitives,
+            backdrop_filters,
             mix_blend_mode,
         }
     }
 
     pub fn is_empty(&self) -> bool {
-        self.filters.is_empty() && self.mix_blend_mode.is_none()
+        self.filters.is_empty() &&
+            self.filter_primitives.is_empty() &&
+            self.backdrop_filters.is_empty() &&
+            self.mix_blend_mode.is_none()
     }
 }
 
@@ -1121,6 +1242,11 @@ pub struct Frame {
 
     /// Debugging information to overlay for this frame.
     pub debug_items: Vec<DebugItem>,
+
+    /// The area of the screen affected by changes since the previous frame
+    /// of the same scene, in device pixels. None if the whole screen needs
+    /// to be repainted.
+    pub dirty_rect: Option<DeviceIntRect>,
 }
 
 impl Frame {
@@ -1129,6 +1255,146 @@ impl Frame {
     pub fn must_be_drawn(&self) -> bool {
         self.has_texture_cache_tasks && !self.has_been_rendered
     }
+
+    /// Writes the batches of this frame in a canonical text form: the
+    /// targets of each pass, with their batch keys and instance counts in
+    /// drawing order. Hash map contents are sorted and texture cache ids are
+    /// numbered by first use, so the output only changes if the batching does.
+    pub fn write_batch_lists(&self, output: &mut String) {
+        let mut writer = BatchListWriter {
+            output,
+            texture_ids: FastHashMap::default(),
+        };
+        for (pass_index, pass) in self.passes.iter().enumerate() {
+            match pass.kind {
+                RenderPassKind::MainFramebuffer(ref target) => {
+                    writer.line(0, &format!("pass {} main framebuffer", pass_index));
+                    writer.color_target(target);
+                }
+                RenderPassKind::OffScreen { ref alpha, ref color, ref texture_cache } => {
+                    writer.line(0, &format!("pass {} offscreen", pass_index));
+                    for (index, target) in alpha.targets.iter().enumerate() {
+                        writer.line(1, &format!("alpha target {} {:?}", index, alpha.format));
+                        writer.alpha_target(target);
+                    }
+                    for (index, target) in color.targets.iter().enumerate() {
+                        writer.line(1, &format!("color target {} {:?}", index, color.format));
+                        writer.color_target(target);
+                    }
+                    let mut texture_cache: Vec<_> = texture_cache.iter().collect();
+                    texture_cache.sort_by_key(|&(&(texture_id, layer), _)| (texture_id.0, layer));
+                    for (&(texture_id, layer), target) in texture_cache {
+                        let texture = writer.texture(TextureSource::TextureCache(texture_id));
+                        writer.line(1, &format!(
+                            "texture cache target {} layer {} {:?}",
+                            texture,
+                            layer,
+                            target.target_kind,
+                        ));
+                        writer.texture_cache_target(target);
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Helper of `Frame::write_batch_lists`.
+struct BatchListWriter<'a> {
+    output: &'a mut String,
+    /// Texture cache ids are allocated from a global counter, so they are
+    /// replaced by the order in which the textures are first used.
+    texture_ids: FastHashMap<CacheTextureId, usize>,
+}
+
+impl<'a> BatchListWriter<'a> {
+    fn line(&mut self, indent: usize, text: &str) {
+        for _ in 0 .. indent {
+            self.output.push_str("  ");
+        }
+        self.output.push_str(text);
+        self.output.push('\n');
+    }
+
+    fn count(&mut self, name: &str, count: usize) {
+        if count > 0 {
+            self.line(2, &format!("{} x{}", name, count));
+        }
+    }
+
+    fn texture(&mut self, source: TextureSource) -> String {
+        match source {
+            TextureSource::TextureCache(id) => {
+                let next_id = self.texture_ids.len();
+                let id = *self.texture_ids.entry(id).or_insert(next_id);
+                format!("TextureCache(#{})", id)
+            }
+            _ => format!("{:?}", source),
+        }
+    }
+
+    fn color_target(&mut self, target: &ColorRenderTarget) {
+        self.count("vertical blur", target.vertical_blurs.len());
+        self.count("horizontal blur", target.horizontal_blurs.len());
+        self.count("readback", target.readbacks.len());
+        self.count("scaling", target.scalings.len());
+        self.count("blit", target.blits.len());
+        for container in &target.alpha_batch_containers {
+            self.line(2, "alpha batch container");
+            let batch_lists = [
+                ("opaque", &container.opaque_batches),
+                ("alpha", &container.alpha_batches),
+            ];
+            for &(name, batches) in &batch_lists {
+                for batch in batches {
+                    let textures: Vec<_> = batch.key.textures.colors
+                        .iter()
+                        .map(|&source| self.texture(source))
+                        .collect();
+                    self.line(3, &format!(
+                        "{} {:?} {:?} [{}] x{}",
+                        name,
+                        batch.key.kind,
+                        batch.key.blend_mode,
+                        textures.join(", "),
+                        batch.instances.len(),
+                    ));
+                }
+            }
+        }
+    }
+
+    fn alpha_target(&mut self, target: &AlphaRenderTarget) {
+        self.count("zero clear", target.zero_clears.len());
+        self.count("vertical blur", target.vertical_blurs.len());
+        self.count("horizontal blur", target.horizontal_blurs.len());
+        self.count("scaling", target.scalings.len());
+        self.count("clip rectangle", target.clip_batcher.rectangles.len());
+        let clip_batches = [
+            ("clip box shadow", &target.clip_batcher.box_shadows),
+            ("clip image", &target.clip_batcher.images),
+        ];
+        for &(name, batches) in &clip_batches {
+            let mut batches: Vec<_> = batches
+                .iter()
+                .map(|(&source, instances)| (self.texture(source), instances.len()))
+                .collect();
+            batches.sort();
+            for (texture, count) in batches {
+                self.line(2, &format!("{} {} x{}", name, texture, count));
+            }
+        }
+    }
+
+    fn texture_cache_target(&mut self, target: &TextureCacheRenderTarget) {
+        self.count("clear", target.clears.len());
+        self.count("horizontal blur", target.horizontal_blurs.len());
+        self.count("blit", target.blits.len());
+        self.count("glyph", target.glyphs.len());
+        self.count("complex border segment", target.border_segments_complex.len());
+        self.count("solid border segment", target.border_segments_solid.len());
+        self.count("line decoration", target.line_decorations.len());
+    }
 }
 
 impl BlurTask {
@@ -401,6 +401,71 @@ pub fn pack_as_float(value: u32) -> f32 {
     value as f32 + 0.5
 }
 
+/// Converts an `f32` to the bits of the nearest IEEE 754 half-precision
+/// float, rounding ties to even. Values out of range saturate to infinity.
+pub fn f32_to_f16(value: f32) -> u16 {
+    let bits = value.to_bits();
+    let sign = ((bits >> 16) & 0x8000) as u16;
+    let exponent = ((bits >> 23) & 0xff) as i32;
+    let mantissa = bits & 0x007f_ffff;
+
+    if exponent == 0xff {
+        // Infinity, or a quiet NaN.
+        let nan = if mantissa != 0 { 0x0200 } else { 0 };
+        return sign | 0x7c00 | nan;
+    }
+
+    let exponent = exponent - 127 + 15;
+    if exponent >= 0x1f {
+        return sign | 0x7c00;
+    }
+
+    if exponent <= 0 {
+        // Subnormal half, or too small to be represented at all.
+        if exponent < -10 {
+            return sign;
+        }
+        let mantissa = mantissa | 0x0080_0000;
+        let shift = (14 - exponent) as u32;
+        let half = mantissa >> shift;
+        let remainder = mantissa & ((1 << shift) - 1);
+        let halfway = 1 << (shift - 1);
+        let round_up = remainder > halfway || (remainder == halfway && (half & 1) != 0);
+        return sign | (half + round_up as u32) as u16;
+    }
+
+    let half = ((exponent as u32) << 10) | (mantissa >> 13);
+    let remainder = mantissa & 0x1fff;
+    let round_up = remainder > 0x1000 || (remainder == 0x1000 && (half & 1) != 0);
+    // A carry out of the mantissa bumps the exponent, up to infinity.
+    sign | (half + round_up as u32) as u16
+}
+
+/// Converts the bits of an IEEE 754 half-precision float to an `f32`.
+pub fn f16_to_f32(half: u16) -> f32 {
+    let sign = ((half & 0x8000) as u32) << 16;
+    let exponent = ((half >> 10) & 0x1f) as u32;
+    let mantissa = (half & 0x03ff) as u32;
+
+    let bits = match exponent {
+        0 if mantissa == 0 => sign,
+        0 => {
+            // Renormalize the subnormal half.
+            let mut exponent: u32 = 127 - 15 + 1;
+            let mut mantissa = mantissa;
+            while mantissa & 0x0400 == 0 {
+                mantissa <<= 1;
+                exponent -= 1;
+            }
+            sign | (exponent << 23) | ((mantissa & 0x03ff) << 13)
+        }
+        0x1f => sign | 0x7f80_0000 | (mantissa << 13),
+        _ => sign | ((exponent + 127 - 15) << 23) | (mantissa << 13),
+    };
+
+    f32::from_bits(bits)
+}
+
 #[inline]
 fn extract_inner_rect_impl<U>(
     rect: &TypedRect<f32, U>,
@@ -440,7 +505,7 @@ pub fn extract_inner_rect_safe<U>(
 
 #[cfg(test)]
 pub mod test {
-    use api::{LayoutTransform, LayoutVector3D};
+    use api::{DeviceIntVector2D, LayoutTransform, LayoutVector3D};
     use super::*;
     use euclid::{Point2D, Angle, Transform3D};
     use std::f32::consts::PI;
@@ -518,6 +583,53 @@ pub mod test {
         assert!(x.approx_eq(&s), "{:?}\n{:?}", x, s);
     }
 
+    #[test]
+    fn half_float_round_trip() {
+        for &value in &[0.0, -0.0, 1.0, -2.0, 0.5, 0.1, 2048.0, 65504.0] {
+            let half = f32_to_f16(value);
+            assert!((f16_to_f32(half) - value).abs() <= value.abs() / 1024.0, "{}", value);
+        }
+
+        // Ties round to even.
+        assert_eq!(f16_to_f32(f32_to_f16(2049.0)), 2048.0);
+        assert_eq!(f16_to_f32(f32_to_f16(2051.0)), 2052.0);
+
+        // Subnormals.
+        let smallest = 2.0f32.powi(-24);
+        assert_eq!(f16_to_f32(f32_to_f16(smallest)), smallest);
+        assert_eq!(f32_to_f16(smallest / 4.0), 0);
+
+        // Out of range values.
+        assert_eq!(f16_to_f32(f32_to_f16(1.0e6)), ::std::f32::INFINITY);
+        assert_eq!(f16_to_f32(f32_to_f16(-1.0e6)), ::std::f32::NEG_INFINITY);
+        assert!(f16_to_f32(f32_to_f16(::std::f32::NAN)).is_nan());
+    }
+
+    #[test]
+    fn checked_rect_math() {
+        let rect = DeviceIntRect::new(DeviceIntPoint::new(10, 20), DeviceIntSize::new(30, 40));
+        let texture_size = DeviceIntSize::new(64, 64);
+
+        assert_eq!(rect.checked_max(), Some(DeviceIntPoint::new(40, 60)));
+        assert!(rect.is_within(texture_size));
+        assert!(!rect.is_within(DeviceIntSize::new(32, 64)));
+
+        let negative = DeviceIntRect::new(DeviceIntPoint::new(10, 20), DeviceIntSize::new(-1, 4));
+        assert_eq!(negative.checked_max(), None);
+        assert!(!negative.is_within(texture_size));
+
+        let huge = DeviceIntRect::new(DeviceIntPoint::new(i32::MAX - 5, 0), DeviceIntSize::new(10, 10));
+        assert_eq!(huge.checked_max(), None);
+        assert!(!huge.is_within(DeviceIntSize::new(i32::MAX, i32::MAX)));
+
+        let offset = DeviceIntVector2D::new(i32::MAX, 0);
+        assert_eq!(rect.checked_translate(&offset), None);
+        assert_eq!(
+            rect.checked_translate(&DeviceIntVector2D::new(-10, 5)),
+            Some(DeviceIntRect::new(DeviceIntPoint::new(0, 25), rect.size)),
+        );
+    }
+
     #[test]
     fn scale_offset_accumulate() {
         let x0 = LayoutTransform::create_translation(130.0, 200.0, 0.0);
@@ -557,6 +669,53 @@ impl<U> MaxRect for TypedRect<f32, U> {
     }
 }
 
+/// Overflow-aware integer rect math, for rects that are derived from data
+/// provided by the embedder and must not panic or wrap when they are offset
+/// or converted.
+pub trait CheckedRectHelpers<U>: Sized {
+    /// Returns the bottom-right corner, or `None` if it doesn't fit in an
+    /// `i32` or the size is negative.
+    fn checked_max(&self) -> Option<TypedPoint2D<i32, U>>;
+    /// Returns the rect translated by `offset`, or `None` on overflow.
+    fn checked_translate(&self, offset: &TypedVector2D<i32, U>) -> Option<Self>;
+    /// Returns true if the rect is well formed and lies entirely within
+    /// `(0, 0) .. size`.
+    fn is_within(&self, size: TypedSize2D<i32, U>) -> bool;
+}
+
+impl<U> CheckedRectHelpers<U> for TypedRect<i32, U> {
+    fn checked_max(&self) -> Option<TypedPoint2D<i32, U>> {
+        if self.size.width < 0 || self.size.height < 0 {
+            return None;
+        }
+        Some(TypedPoint2D::new(
+            self.origin.x.checked_add(self.size.width)?,
+            self.origin.y.checked_add(self.size.height)?,
+        ))
+    }
+
+    fn checked_translate(&self, offset: &TypedVector2D<i32, U>) -> Option<Self> {
+        let rect = TypedRect::new(
+            TypedPoint2D::new(
+                self.origin.x.checked_add(offset.x)?,
+                self.origin.y.checked_add(offset.y)?,
+            ),
+            self.size,
+        );
+        rect.checked_max().map(|_| rect)
+    }
+
+    fn is_within(&self, size: TypedSize2D<i32, U>) -> bool {
+        match self.checked_max() {
+            Some(max) => {
+                self.origin.x >= 0 && self.origin.y >= 0 &&
+                max.x <= size.width && max.y <= size.height
+            }
+            None => false,
+        }
+    }
+}
+
 /// An enum that tries to avoid expensive transformation matrix calculations
 /// when possible when dealing with non-perspective axis-aligned transformations.
 #[derive(Debug, MallocSizeOf)]
@@ -57,6 +57,10 @@ const SHADERS: &[Shader] = &[
         name: "cs_border_solid",
         features: CACHE_FEATURES,
     },
+    Shader {
+        name: "cs_svg_filter",
+        features: CACHE_FEATURES,
+    },
     // Prim shaders
     Shader {
         name: "ps_split_composite",
@@ -78,6 +82,10 @@ const SHADERS: &[Shader] = &[
             "YUV_NV12,ALPHA_PASS",
         ],
     },
+    Shader {
+        name: "brush_cross_fade",
+        features: BRUSH_FEATURES,
+    },
     Shader {
         name: "brush_solid",
         features: BRUSH_FEATURES,
@@ -94,10 +102,18 @@ const SHADERS: &[Shader] = &[
         name: "brush_mix_blend",
         features: BRUSH_FEATURES,
     },
+    Shader {
+        name: "brush_backdrop_filter",
+        features: BRUSH_FEATURES,
+    },
     Shader {
         name: "brush_radial_gradient",
         features: GRADIENT_FEATURES,
     },
+    Shader {
+        name: "brush_conic_gradient",
+        features: GRADIENT_FEATURES,
+    },
     Shader {
         name: "brush_linear_gradient",
         features: GRADIENT_FEATURES,
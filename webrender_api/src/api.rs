@@ -12,13 +12,15 @@ use std::marker::PhantomData;
 use std::os::raw::c_void;
 use std::path::PathBuf;
 use std::sync::Arc;
+use std::time::Duration;
 use std::u32;
-use {BuiltDisplayList, BuiltDisplayListDescriptor, ColorF, DeviceIntPoint, DeviceIntRect};
+use std::usize;
+use {BuiltDisplayList, BuiltDisplayListDescriptor, ColorF, ColorU, DeviceIntPoint, DeviceIntRect};
 use {DeviceIntSize, ExternalScrollId, FontInstanceKey, FontInstanceOptions};
 use {FontInstancePlatformOptions, FontKey, FontVariation, GlyphDimensions, GlyphIndex, ImageData};
-use {ImageDescriptor, ItemTag, LayoutPoint, LayoutSize, LayoutTransform, LayoutVector2D};
+use {ImageDescriptor, ImageFormat, ItemTag, LayoutPoint, LayoutRect, LayoutSize, LayoutTransform};
 use {BlobDirtyRect, ImageDirtyRect, ImageKey, BlobImageKey, BlobImageData};
-use {NativeFontHandle, WorldPoint};
+use {LayoutVector2D, NativeFontHandle, WorldPoint};
 
 pub type TileSize = u16;
 /// Documents are rendered in the ascending order of their associated layer values.
@@ -139,6 +141,15 @@ impl Transaction {
         self.scene_ops.push(SceneMsg::RemovePipeline(pipeline_id));
     }
 
+    /// Signals that the content process providing `pipeline_id` went away.
+    /// The pipeline is frozen at its last good epoch: further display lists
+    /// and epoch updates for it are ignored, and its content is presented
+    /// according to the renderer's `LostPipelineTreatment` until the pipeline
+    /// is removed.
+    pub fn mark_pipeline_lost(&mut self, pipeline_id: PipelineId) {
+        self.scene_ops.push(SceneMsg::MarkPipelineLost(pipeline_id));
+    }
+
     /// Supplies a new frame to WebRender.
     ///
     /// Non-blocking, it notifies a worker process which processes the display list.
@@ -230,6 +241,19 @@ impl Transaction {
         self.frame_ops.push(FrameMsg::ScrollNodeWithId(origin, id, clamp));
     }
 
+    /// Scrolls a node to `origin` smoothly over `duration`. The offset is
+    /// interpolated each time a frame is generated, including across scene
+    /// swaps, so the embedder needs to keep generating frames until it ends.
+    pub fn scroll_node_with_id_animated(
+        &mut self,
+        origin: LayoutPoint,
+        id: ExternalScrollId,
+        clamp: ScrollClamping,
+        duration: Duration,
+    ) {
+        self.frame_ops.push(FrameMsg::AnimateScrollNodeWithId(origin, id, clamp, duration));
+    }
+
     pub fn set_page_zoom(&mut self, page_zoom: ZoomFactor) {
         self.scene_ops.push(SceneMsg::SetPageZoom(page_zoom));
     }
@@ -577,6 +601,23 @@ pub struct AddFontInstance {
     pub variations: Vec<FontVariation>,
 }
 
+/// How the content of a pipeline whose content process went away is presented.
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Serialize)]
+pub enum LostPipelineTreatment {
+    /// Keep showing the last good content as is.
+    Freeze,
+    /// Draw the given (usually translucent) color on top of the last good content.
+    Tint(ColorF),
+    /// Replace the content with a solid color.
+    Placeholder(ColorF),
+}
+
+impl Default for LostPipelineTreatment {
+    fn default() -> Self {
+        LostPipelineTreatment::Tint(ColorF::new(0.5, 0.5, 0.5, 0.5))
+    }
+}
+
 // Frame messages affect building the scene.
 #[derive(Clone, Deserialize, Serialize)]
 pub enum SceneMsg {
@@ -584,6 +625,7 @@ pub enum SceneMsg {
     SetPageZoom(ZoomFactor),
     SetRootPipeline(PipelineId),
     RemovePipeline(PipelineId),
+    MarkPipelineLost(PipelineId),
     SetDisplayList {
         list_descriptor: BuiltDisplayListDescriptor,
         epoch: Epoch,
@@ -609,6 +651,7 @@ pub enum FrameMsg {
     EnableFrameOutput(PipelineId, bool),
     Scroll(ScrollLocation, WorldPoint),
     ScrollNodeWithId(LayoutPoint, ExternalScrollId, ScrollClamping),
+    AnimateScrollNodeWithId(LayoutPoint, ExternalScrollId, ScrollClamping, Duration),
     GetScrollNodeState(MsgSender<Vec<ScrollNodeState>>),
     UpdateDynamicProperties(DynamicProperties),
     AppendDynamicProperties(DynamicProperties),
@@ -622,6 +665,7 @@ impl fmt::Debug for SceneMsg {
             SceneMsg::SetDisplayList { .. } => "SceneMsg::SetDisplayList",
             SceneMsg::SetPageZoom(..) => "SceneMsg::SetPageZoom",
             SceneMsg::RemovePipeline(..) => "SceneMsg::RemovePipeline",
+            SceneMsg::MarkPipelineLost(..) => "SceneMsg::MarkPipelineLost",
             SceneMsg::SetWindowParameters { .. } => "SceneMsg::SetWindowParameters",
             SceneMsg::SetRootPipeline(..) => "SceneMsg::SetRootPipeline",
         })
@@ -636,6 +680,7 @@ impl fmt::Debug for FrameMsg {
             FrameMsg::SetPan(..) => "FrameMsg::SetPan",
             FrameMsg::Scroll(..) => "FrameMsg::Scroll",
             FrameMsg::ScrollNodeWithId(..) => "FrameMsg::ScrollNodeWithId",
+            FrameMsg::AnimateScrollNodeWithId(..) => "FrameMsg::AnimateScrollNodeWithId",
             FrameMsg::GetScrollNodeState(..) => "FrameMsg::GetScrollNodeState",
             FrameMsg::EnableFrameOutput(..) => "FrameMsg::EnableFrameOutput",
             FrameMsg::UpdateDynamicProperties(..) => "FrameMsg::UpdateDynamicProperties",
@@ -652,6 +697,10 @@ bitflags!{
     pub struct CaptureBits: u8 {
         const SCENE = 0x1;
         const FRAME = 0x2;
+        /// The render targets of every pass. They only hold a pass result
+        /// until another pass reuses them, so they are copied while the next
+        /// frame is rendered, and saved once it's done.
+        const TARGETS = 0x4;
     }
 }
 
@@ -681,6 +730,9 @@ pub struct CapturedDocument {
 pub enum DebugCommand {
     /// Sets the provided debug flags.
     SetFlags(DebugFlags),
+    /// Enables or disables the provided debug flags, keeping the other ones
+    /// as they are.
+    SetFlag(DebugFlags, bool),
     /// Configure if dual-source blending is used, if available.
     EnableDualSourceBlending(bool),
     /// Fetch current documents and display lists.
@@ -693,6 +745,12 @@ pub enum DebugCommand {
     FetchRenderTasks,
     /// Fetch screenshot.
     FetchScreenshot,
+    /// Fetch the texture cache and GPU cache uploads of the most recent frames.
+    FetchUploads,
+    /// Fetch element counts and approximate sizes of the backend data
+    /// structures (clip-scroll tree, primitive and clip stores, interning
+    /// tables, resource cache maps and render task tree).
+    FetchMemoryBreakdown,
     /// Save a capture of all the documents state.
     SaveCapture(PathBuf, CaptureBits),
     /// Load a capture of all the documents state.
@@ -707,6 +765,73 @@ pub enum DebugCommand {
     /// Causes the low priority scene builder to pause for a given amount of miliseconds
     /// each time it processes a transaction.
     SimulateLongLowPrioritySceneBuild(u32),
+    /// Overrides the batching parameters, or restores the defaults detected
+    /// for the GPU vendor if `None`. Takes effect on the next scene build.
+    SetBatchingParameters(Option<BatchingParameters>),
+    /// Sets where and how the profiler overlay is drawn.
+    SetProfilerOverlay(ProfilerOverlay),
+    /// Sets the position in framebuffer pixels, from the top-left corner,
+    /// that the `ZOOM_DBG` overlay magnifies, or hides the overlay if `None`.
+    SetDebugCursor(Option<DeviceIntPoint>),
+    /// Write the adapter info, the enabled features and workarounds, the
+    /// renderer options, the shader digest, the texture cache configuration
+    /// and the recent errors to a JSON file, to attach to bug reports.
+    DumpEnvironment(PathBuf),
+}
+
+/// Tunables for how primitives are grouped into draw calls.
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Serialize)]
+pub struct BatchingParameters {
+    /// The maximum number of instances submitted with a single draw call.
+    /// Larger batches are split into several draws.
+    pub max_instances_per_draw: usize,
+    /// How many of the most recent batches are searched for one compatible
+    /// with a new primitive, i.e. how many texture or shader switches a
+    /// batch may be reused across before a new batch is started.
+    pub batch_lookback_count: usize,
+}
+
+impl Default for BatchingParameters {
+    fn default() -> Self {
+        BatchingParameters {
+            max_instances_per_draw: usize::MAX,
+            batch_lookback_count: 10,
+        }
+    }
+}
+
+/// The corner of the framebuffer the profiler overlay is anchored to.
+#[derive(Clone, Copy, Debug, Deserialize, Eq, PartialEq, Serialize)]
+pub enum ProfilerOverlayCorner {
+    TopLeft,
+    TopRight,
+    BottomLeft,
+    BottomRight,
+}
+
+/// Placement of the profiler overlay on the main framebuffer.
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Serialize)]
+pub struct ProfilerOverlay {
+    pub corner: ProfilerOverlayCorner,
+    /// Scale of the overlay, e.g. the device pixel ratio on HiDPI screens.
+    /// The margin to the corner scales along.
+    pub scale: f32,
+    /// Opacity multiplied into the colors of the overlay.
+    pub opacity: f32,
+    /// The document whose frame counters are shown, all rendered documents
+    /// if `None`.
+    pub document: Option<DocumentId>,
+}
+
+impl Default for ProfilerOverlay {
+    fn default() -> Self {
+        ProfilerOverlay {
+            corner: ProfilerOverlayCorner::TopLeft,
+            scale: 1.0,
+            opacity: 1.0,
+            document: None,
+        }
+    }
 }
 
 #[derive(Clone, Deserialize, Serialize)]
@@ -739,8 +864,13 @@ pub enum ApiMsg {
     ClearNamespace(IdNamespace),
     /// Flush from the caches anything that isn't necessary, to free some memory.
     MemoryPressure,
+    /// Limits the GPU memory used by the texture cache, or lifts the limit.
+    SetTextureCacheBudget(Option<usize>),
     /// Collects a memory report.
     ReportMemory(MsgSender<MemoryReport>),
+    /// Stops building frames while the renderer is suspended, the documents
+    /// keep being updated. On resume, the frames requested meanwhile are built.
+    SetSuspended(bool),
     /// Change debugging options.
     DebugCommand(DebugCommand),
     /// Wakes the render backend's event loop up. Needed when an event is communicated
@@ -765,7 +895,9 @@ impl fmt::Debug for ApiMsg {
             ApiMsg::ExternalEvent(..) => "ApiMsg::ExternalEvent",
             ApiMsg::ClearNamespace(..) => "ApiMsg::ClearNamespace",
             ApiMsg::MemoryPressure => "ApiMsg::MemoryPressure",
+            ApiMsg::SetTextureCacheBudget(..) => "ApiMsg::SetTextureCacheBudget",
             ApiMsg::ReportMemory(..) => "ApiMsg::ReportMemory",
+            ApiMsg::SetSuspended(..) => "ApiMsg::SetSuspended",
             ApiMsg::DebugCommand(..) => "ApiMsg::DebugCommand",
             ApiMsg::ShutDown => "ApiMsg::ShutDown",
             ApiMsg::WakeUp => "ApiMsg::WakeUp",
@@ -831,9 +963,11 @@ macro_rules! enumerate_interners {
             image_border,
             image,
             yuv_image,
+            cross_fade,
             line_decoration,
             linear_grad,
             radial_grad,
+            conic_grad,
             picture,
             text_run,
         }
@@ -870,6 +1004,46 @@ impl ::std::ops::AddAssign for InterningMemoryReport {
     }
 }
 
+/// GPU memory consumed by textures, in bytes, broken down by format.
+#[repr(C)]
+#[derive(AddAssign, Clone, Debug, Default, Deserialize, Serialize)]
+pub struct TextureFormatMemoryReport {
+    pub r8: usize,
+    pub r16: usize,
+    pub bgra8: usize,
+    pub rgbaf32: usize,
+    pub rg8: usize,
+    pub rgbai32: usize,
+    pub rgba8: usize,
+    pub rgbaf16: usize,
+    pub rgb10a2: usize,
+    pub rg16: usize,
+    pub compressed: usize,
+}
+
+impl TextureFormatMemoryReport {
+    /// Accounts `bytes` of texture memory to `format`.
+    pub fn add(&mut self, format: ImageFormat, bytes: usize) {
+        *match format {
+            ImageFormat::R8 => &mut self.r8,
+            ImageFormat::R16 => &mut self.r16,
+            ImageFormat::BGRA8 => &mut self.bgra8,
+            ImageFormat::RGBAF32 => &mut self.rgbaf32,
+            ImageFormat::RG8 => &mut self.rg8,
+            ImageFormat::RGBAI32 => &mut self.rgbai32,
+            ImageFormat::RGBA8 => &mut self.rgba8,
+            ImageFormat::RGBAF16 => &mut self.rgbaf16,
+            ImageFormat::RGB10A2 => &mut self.rgb10a2,
+            ImageFormat::RG16 => &mut self.rg16,
+            ImageFormat::BC1 |
+            ImageFormat::BC3 |
+            ImageFormat::BC7 |
+            ImageFormat::ETC2 |
+            ImageFormat::ASTC4x4 => &mut self.compressed,
+        } += bytes;
+    }
+}
+
 /// Collection of heap sizes, in bytes.
 /// cbindgen:derive-eq=false
 #[repr(C)]
@@ -898,6 +1072,12 @@ pub struct MemoryReport {
     pub texture_cache_textures: usize,
     pub depth_target_textures: usize,
     pub swap_chain: usize,
+    pub vertex_buffers: usize,
+    pub instance_buffers: usize,
+    pub upload_buffers: usize,
+    /// The texture memory above, except for the depth targets and the swap
+    /// chain, broken down by format.
+    pub texture_formats: TextureFormatMemoryReport,
 }
 
 /// A C function that takes a pointer to a heap allocation and returns its size.
@@ -1021,8 +1201,17 @@ bitflags! {
         /// Show picture caching debug overlay
         const PICTURE_CACHING_DBG   = 1 << 15;
         const TEXTURE_CACHE_DBG_DISABLE_SHRINK = 1 << 16;
-        /// Highlight all primitives with colors based on kind.
+        /// Highlight all primitives with colors based on kind, and mark the
+        /// primitives starting a new batch with the reason of the break:
+        /// K(ind), B(lend mode), T(extures) or O(verlap).
         const PRIMITIVE_DBG = 1 << 17;
+        /// Outline the primitives of each batch in the color of its GPU
+        /// profiler tag, and label the batches of the main framebuffer
+        /// with their kind.
+        const SHOW_BATCH_RECTS = 1 << 18;
+        /// Magnify the region of the framebuffer around the debug cursor,
+        /// see `DebugCommand::SetDebugCursor`, into its bottom-right corner.
+        const ZOOM_DBG = 1 << 19;
     }
 }
 
@@ -1118,10 +1307,24 @@ impl RenderApi {
         self.api_sender.send(msg).unwrap();
     }
 
+    /// Releases as much memory as possible, e.g. in response to a low memory
+    /// signal of the OS: the resource and GPU caches are flushed, and the
+    /// renderer frees its pooled render targets, depth targets and the
+    /// instance buffers grown by heavier frames. The next frame has to
+    /// rebuild all of its resources.
     pub fn notify_memory_pressure(&self) {
         self.api_sender.send(ApiMsg::MemoryPressure).unwrap();
     }
 
+    /// Limits the GPU memory used by the texture cache to `budget_bytes`, or
+    /// lifts the limit if `None`. Useful to shrink the footprint of a
+    /// backgrounded application without recreating the renderer: lowering the
+    /// budget evicts the least recently used cache entries right away, and
+    /// the cache keeps collecting eagerly while it is over budget.
+    pub fn set_texture_cache_budget(&self, budget_bytes: Option<usize>) {
+        self.api_sender.send(ApiMsg::SetTextureCacheBudget(budget_bytes)).unwrap();
+    }
+
     pub fn report_memory(&self) -> MemoryReport {
         let (tx, rx) = channel::msg_channel().unwrap();
         self.api_sender.send(ApiMsg::ReportMemory(tx)).unwrap();
@@ -1133,6 +1336,20 @@ impl RenderApi {
         self.api_sender.send(ApiMsg::DebugCommand(cmd)).unwrap();
     }
 
+    /// Moves the region magnified by the `ZOOM_DBG` overlay, see
+    /// `DebugCommand::SetDebugCursor`.
+    pub fn set_debug_cursor(&self, position: Option<DeviceIntPoint>) {
+        let cmd = DebugCommand::SetDebugCursor(position);
+        self.api_sender.send(ApiMsg::DebugCommand(cmd)).unwrap();
+    }
+
+    /// Writes the environment of the renderer to `path` for bug reports,
+    /// see `DebugCommand::DumpEnvironment`.
+    pub fn dump_environment(&self, path: PathBuf) {
+        let cmd = DebugCommand::DumpEnvironment(path);
+        self.api_sender.send(ApiMsg::DebugCommand(cmd)).unwrap();
+    }
+
     pub fn shut_down(&self) {
         self.api_sender.send(ApiMsg::ShutDown).unwrap();
     }
@@ -1191,6 +1408,16 @@ impl RenderApi {
             .unwrap()
     }
 
+    /// Freezes a pipeline whose content process crashed, and renders a new
+    /// frame with the lost pipeline treatment applied.
+    /// See `Transaction::mark_pipeline_lost`.
+    pub fn mark_pipeline_lost(&self, document_id: DocumentId, pipeline_id: PipelineId) {
+        let mut transaction = Transaction::new();
+        transaction.mark_pipeline_lost(pipeline_id);
+        transaction.generate_frame();
+        self.send_transaction(document_id, transaction);
+    }
+
     pub fn send_transaction(&self, document_id: DocumentId, transaction: Transaction) {
         let (msg, payloads) = transaction.finalize();
         for payload in payloads {
@@ -1233,6 +1460,41 @@ impl RenderApi {
         );
     }
 
+    /// Sets the pinch zoom of a document and generates a frame with it. The
+    /// pictures rasterized in local space are only rasterized again when the
+    /// zoom crosses a raster scale step, so animating the zoom is cheap.
+    pub fn set_pinch_zoom(&self, document_id: DocumentId, pinch_zoom: ZoomFactor) {
+        let mut txn = Transaction::new();
+        txn.set_pinch_zoom(pinch_zoom);
+        txn.generate_frame();
+        self.send_transaction(document_id, txn);
+    }
+
+    /// Updates the values of the animated property bindings and generates a
+    /// frame with them, without rebuilding the scene.
+    pub fn update_dynamic_properties(&self, document_id: DocumentId, properties: DynamicProperties) {
+        let mut txn = Transaction::new();
+        txn.update_dynamic_properties(properties);
+        txn.generate_frame();
+        self.send_transaction(document_id, txn);
+    }
+
+    /// Scrolls a node to `origin` smoothly over `duration`, without going
+    /// through a scene build. See `Transaction::scroll_node_with_id_animated`.
+    pub fn set_scroll_offset_animated(
+        &self,
+        document_id: DocumentId,
+        id: ExternalScrollId,
+        origin: LayoutPoint,
+        clamp: ScrollClamping,
+        duration: Duration,
+    ) {
+        self.send_frame_msg(
+            document_id,
+            FrameMsg::AnimateScrollNodeWithId(origin, id, clamp, duration),
+        );
+    }
+
     pub fn get_scroll_node_state(&self, document_id: DocumentId) -> Vec<ScrollNodeState> {
         let (tx, rx) = channel::msg_channel().unwrap();
         self.send_frame_msg(document_id, FrameMsg::GetScrollNodeState(tx));
@@ -1339,7 +1601,7 @@ impl PropertyBindingId {
 /// A unique key that is used for connecting animated property
 /// values to bindings in the display list.
 #[repr(C)]
-#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Serialize)]
+#[derive(Clone, Copy, Debug, Deserialize, Eq, Hash, MallocSizeOf, PartialEq, Serialize)]
 pub struct PropertyBindingKey<T> {
     pub id: PropertyBindingId,
     _phantom: PhantomData<T>,
@@ -1368,7 +1630,7 @@ impl<T> PropertyBindingKey<T> {
 /// used for the case where the animation is still in-delay phase
 /// (i.e. the animation doesn't produce any animation values).
 #[repr(C)]
-#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Serialize)]
+#[derive(Clone, Copy, Debug, Deserialize, Eq, Hash, MallocSizeOf, PartialEq, Serialize)]
 pub enum PropertyBinding<T> {
     Value(T),
     Binding(PropertyBindingKey<T>, T),
@@ -1380,6 +1642,42 @@ impl<T> From<T> for PropertyBinding<T> {
     }
 }
 
+impl From<PropertyBindingKey<ColorF>> for PropertyBindingKey<ColorU> {
+    fn from(key: PropertyBindingKey<ColorF>) -> PropertyBindingKey<ColorU> {
+        PropertyBindingKey {
+            id: key.id,
+            _phantom: PhantomData,
+        }
+    }
+}
+
+impl From<PropertyBindingKey<ColorU>> for PropertyBindingKey<ColorF> {
+    fn from(key: PropertyBindingKey<ColorU>) -> PropertyBindingKey<ColorF> {
+        PropertyBindingKey {
+            id: key.id,
+            _phantom: PhantomData,
+        }
+    }
+}
+
+impl From<PropertyBinding<ColorF>> for PropertyBinding<ColorU> {
+    fn from(value: PropertyBinding<ColorF>) -> PropertyBinding<ColorU> {
+        match value {
+            PropertyBinding::Value(value) => PropertyBinding::Value(value.into()),
+            PropertyBinding::Binding(key, value) => PropertyBinding::Binding(key.into(), value.into()),
+        }
+    }
+}
+
+impl From<PropertyBinding<ColorU>> for PropertyBinding<ColorF> {
+    fn from(value: PropertyBinding<ColorU>) -> PropertyBinding<ColorF> {
+        match value {
+            PropertyBinding::Value(value) => PropertyBinding::Value(value.into()),
+            PropertyBinding::Binding(key, value) => PropertyBinding::Binding(key.into(), value.into()),
+        }
+    }
+}
+
 /// The current value of an animated property. This is
 /// supplied by the calling code.
 #[derive(Clone, Copy, Debug, Deserialize, Serialize, PartialEq)]
@@ -1395,15 +1693,33 @@ pub struct PropertyValue<T> {
 pub struct DynamicProperties {
     pub transforms: Vec<PropertyValue<LayoutTransform>>,
     pub floats: Vec<PropertyValue<f32>>,
+    /// Colors of solid rectangles added with `push_rect_with_animation`.
+    pub colors: Vec<PropertyValue<ColorF>>,
+    /// Keys of images added with `push_image_with_animation`.
+    pub images: Vec<PropertyValue<ImageKey>>,
+    /// Clip rects of clips defined with `define_clip_with_animation`.
+    pub rects: Vec<PropertyValue<LayoutRect>>,
 }
 
 pub trait RenderNotifier: Send {
     fn clone(&self) -> Box<RenderNotifier>;
     fn wake_up(&self);
     fn new_frame_ready(&self, DocumentId, scrolled: bool, composite_needed: bool, render_time_ns: Option<u64>);
+    /// Called when the renderer has to composite again even though no new
+    /// frame was built, e.g. because the debug overlays changed. Embedders
+    /// that skip `Renderer::render` while `Renderer::needs_render` returns
+    /// false should schedule a render here, after `Renderer::update`.
+    fn render_needed(&self) {
+        self.wake_up();
+    }
     fn external_event(&self, _evt: ExternalEvent) {
         unimplemented!()
     }
+    /// Called with `async_blob_rasterization` enabled when blob images that
+    /// the current frame is missing start (`true`) or finish (`false`) being
+    /// rasterized in the background. Embedders can hold back presenting
+    /// frames until this goes back to false.
+    fn blob_rasterization_pending(&self, _pending: bool) {}
     fn shut_down(&self) {}
 }
 
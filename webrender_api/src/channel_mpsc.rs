@@ -40,6 +40,13 @@ impl<T> MsgReceiver<T> {
         use std::error::Error;
         self.rx.recv().map_err(|e| io::Error::new(ErrorKind::Other, e.description()))
     }
+
+    /// Returns a message if one is pending, without blocking. Mirrors
+    /// `IpcReceiver::try_recv`.
+    pub fn try_recv(&self) -> Result<T, Error> {
+        use std::error::Error;
+        self.rx.try_recv().map_err(|e| io::Error::new(ErrorKind::Other, e.description()))
+    }
 }
 
 #[derive(Clone)]
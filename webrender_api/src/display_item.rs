@@ -8,7 +8,7 @@ use euclid::{SideOffsets2D, TypedRect};
 use std::ops::Not;
 use {ColorF, FontInstanceKey, GlyphOptions, ImageKey, LayoutPixel, LayoutPoint};
 use {LayoutRect, LayoutSize, LayoutTransform, LayoutVector2D, PipelineId, PropertyBinding};
-use LayoutSideOffsets;
+use {LayoutSideOffsets, PropertyBindingKey};
 use image::ColorDepth;
 
 // Maximum blur radius.
@@ -112,10 +112,12 @@ pub enum SpecificDisplayItem {
     Text(TextDisplayItem),
     Image(ImageDisplayItem),
     YuvImage(YuvImageDisplayItem),
+    CrossFade(CrossFadeDisplayItem),
     Border(BorderDisplayItem),
     BoxShadow(BoxShadowDisplayItem),
     Gradient(GradientDisplayItem),
     RadialGradient(RadialGradientDisplayItem),
+    ConicGradient(ConicGradientDisplayItem),
     ClipChain(ClipChainItem),
     Iframe(IframeDisplayItem),
     PushReferenceFrame(ReferenceFrameDisplayListItem),
@@ -146,14 +148,21 @@ pub enum CompletelySpecificDisplayItem {
     Text(TextDisplayItem, Vec<GlyphInstance>),
     Image(ImageDisplayItem),
     YuvImage(YuvImageDisplayItem),
+    CrossFade(CrossFadeDisplayItem),
     Border(BorderDisplayItem),
     BoxShadow(BoxShadowDisplayItem),
     Gradient(GradientDisplayItem),
     RadialGradient(RadialGradientDisplayItem),
+    ConicGradient(ConicGradientDisplayItem),
     Iframe(IframeDisplayItem),
     PushReferenceFrame(ReferenceFrameDisplayListItem),
     PopReferenceFrame,
-    PushStackingContext(PushStackingContextDisplayItem, Vec<FilterOp>),
+    PushStackingContext(
+        PushStackingContextDisplayItem,
+        Vec<FilterOp>,
+        Vec<FilterPrimitive>,
+        Vec<FilterOp>,
+    ),
     PopStackingContext,
     SetGradientStops(Vec<GradientStop>),
     PushShadow(Shadow),
@@ -166,6 +175,9 @@ pub enum CompletelySpecificDisplayItem {
 pub struct ClipDisplayItem {
     pub id: ClipId,
     pub image_mask: Option<ImageMask>,
+    /// Set if the clip rect of the item is the default value of an
+    /// animated property.
+    pub rect_binding: Option<PropertyBindingKey<LayoutRect>>,
 }
 
 /// The minimum and maximum allowable offset for a sticky frame in a single dimension.
@@ -235,7 +247,7 @@ pub struct ScrollFrameDisplayItem {
 
 #[derive(Clone, Copy, Debug, Deserialize, PartialEq, Serialize)]
 pub struct RectangleDisplayItem {
-    pub color: ColorF,
+    pub color: PropertyBinding<ColorF>,
 }
 
 #[derive(Clone, Copy, Debug, Deserialize, PartialEq, Serialize)]
@@ -501,6 +513,18 @@ pub struct RadialGradient {
     pub extend_mode: ExtendMode,
 } // IMPLICIT stops: Vec<GradientStop>
 
+/// A gradient whose colors sweep around a center, like CSS
+/// `conic-gradient()`. The stops are laid out clockwise from `angle`, in
+/// radians, where zero points up.
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Serialize)]
+pub struct ConicGradient {
+    pub center: LayoutPoint,
+    pub angle: f32,
+    pub start_offset: f32,
+    pub end_offset: f32,
+    pub extend_mode: ExtendMode,
+} // IMPLICIT stops: Vec<GradientStop>
+
 #[derive(Clone, Copy, Debug, Deserialize, PartialEq, Serialize)]
 pub struct ClipChainItem {
     pub id: ClipChainId,
@@ -514,6 +538,13 @@ pub struct RadialGradientDisplayItem {
     pub tile_spacing: LayoutSize,
 }
 
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Serialize)]
+pub struct ConicGradientDisplayItem {
+    pub gradient: ConicGradient,
+    pub tile_size: LayoutSize,
+    pub tile_spacing: LayoutSize,
+}
+
 #[derive(Clone, Copy, Debug, Deserialize, PartialEq, Serialize)]
 pub struct ReferenceFrameDisplayListItem {
     pub reference_frame: ReferenceFrame,
@@ -576,7 +607,9 @@ pub enum TransformStyle {
 #[repr(u32)]
 pub enum RasterSpace {
     // Rasterize in local-space, applying supplied scale to primitives.
-    // Best performance, but lower quality.
+    // Best performance, but lower quality. The scale is also used for the
+    // surface of a stacking context with a perspective transform, instead
+    // of the one picked from its transform.
     Local(f32),
 
     // Rasterize the picture in screen-space, including rotation / skew etc in
@@ -655,6 +688,75 @@ impl FilterOp {
     }
 }
 
+/// Where a filter primitive reads one of its inputs from.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Deserialize, Eq, Hash, MallocSizeOf, PartialEq, Serialize)]
+pub enum FilterPrimitiveInput {
+    /// The contents of the stacking context, before any filtering
+    /// (`SourceGraphic`).
+    Original,
+    /// The output of the primitive that precedes this one, or the
+    /// original contents for the first primitive.
+    Previous,
+    /// The output of an earlier primitive in the same list. The index
+    /// must be smaller than the index of the primitive reading it.
+    OutputOfPrimitiveIndex(usize),
+}
+
+/// The compositing operators of the SVG `feComposite` primitive.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, PartialEq, Deserialize, Serialize)]
+pub enum CompositeOperator {
+    Over,
+    In,
+    Out,
+    Atop,
+    Xor,
+    Lighter,
+    /// `k1 * i1 * i2 + k2 * i1 + k3 * i2 + k4`.
+    Arithmetic([f32; 4]),
+}
+
+/// A node of an SVG filter graph. The primitives of a stacking context
+/// are applied in order, after any `FilterOp`s, and the output of the
+/// last one is what gets composited.
+///
+/// All processing currently happens in the sRGB color space.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, PartialEq, Deserialize, Serialize)]
+pub enum FilterPrimitive {
+    /// `feGaussianBlur`, with the standard deviation in layout pixels.
+    Blur {
+        input: FilterPrimitiveInput,
+        std_deviation: f32,
+    },
+    /// `feColorMatrix`, with the same layout as `FilterOp::ColorMatrix`.
+    ColorMatrix {
+        input: FilterPrimitiveInput,
+        matrix: [f32; 20],
+    },
+    /// `feComposite`, drawing `input1` with `operator` over `input2`.
+    Composite {
+        input1: FilterPrimitiveInput,
+        input2: FilterPrimitiveInput,
+        operator: CompositeOperator,
+    },
+}
+
+impl FilterPrimitive {
+    /// Ensure that the parameters for a filter primitive
+    /// are sensible.
+    pub fn sanitize(self) -> FilterPrimitive {
+        match self {
+            FilterPrimitive::Blur { input, std_deviation } => FilterPrimitive::Blur {
+                input,
+                std_deviation: std_deviation.max(0.0).min(MAX_BLUR_RADIUS),
+            },
+            primitive => primitive,
+        }
+    }
+}
+
 #[derive(Clone, Copy, Debug, Deserialize, PartialEq, Serialize)]
 pub struct IframeDisplayItem {
     pub pipeline_id: PipelineId,
@@ -663,7 +765,7 @@ pub struct IframeDisplayItem {
 
 #[derive(Clone, Copy, Debug, Deserialize, PartialEq, Serialize)]
 pub struct ImageDisplayItem {
-    pub image_key: ImageKey,
+    pub image_key: PropertyBinding<ImageKey>,
     pub stretch_size: LayoutSize,
     pub tile_spacing: LayoutSize,
     pub image_rendering: ImageRendering,
@@ -690,6 +792,19 @@ pub struct YuvImageDisplayItem {
     pub yuv_data: YuvData,
     pub color_depth: ColorDepth,
     pub color_space: YuvColorSpace,
+    pub color_range: ColorRange,
+    pub image_rendering: ImageRendering,
+}
+
+/// Blends between two images, as `(1 - progress) * from + progress * to`.
+/// Both images are stretched to the item bounds, and should have the same
+/// buffer type.
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Serialize)]
+pub struct CrossFadeDisplayItem {
+    pub from_key: ImageKey,
+    pub to_key: ImageKey,
+    /// The blend factor, between 0 and 1. It can be animated.
+    pub progress: PropertyBinding<f32>,
     pub image_rendering: ImageRendering,
 }
 
@@ -698,6 +813,17 @@ pub struct YuvImageDisplayItem {
 pub enum YuvColorSpace {
     Rec601 = 0,
     Rec709 = 1,
+    Rec2020 = 2,
+}
+
+/// The range of the samples of a YUV image.
+#[repr(u8)]
+#[derive(Clone, Copy, Debug, Deserialize, Eq, Hash, MallocSizeOf, PartialEq, Serialize)]
+pub enum ColorRange {
+    /// Luma in [16, 235] and chroma in [16, 240], scaled to the color depth.
+    Limited = 0,
+    /// Luma and chroma use the whole range of the color depth.
+    Full = 1,
 }
 
 #[derive(Clone, Copy, Debug, Deserialize, Eq, Hash, PartialEq, Serialize)]
@@ -705,6 +831,8 @@ pub enum YuvData {
     NV12(ImageKey, ImageKey), // (Y channel, CbCr interleaved channel)
     PlanarYCbCr(ImageKey, ImageKey, ImageKey), // (Y channel, Cb channel, Cr Channel)
     InterleavedYCbCr(ImageKey), // (YCbCr interleaved channel)
+    P010(ImageKey, ImageKey), // (Y channel, CbCr interleaved channel), 10 bits in the high bits of 16
+    P016(ImageKey, ImageKey), // (Y channel, CbCr interleaved channel), 16 bits
 }
 
 impl YuvData {
@@ -713,6 +841,8 @@ impl YuvData {
             YuvData::NV12(..) => YuvFormat::NV12,
             YuvData::PlanarYCbCr(..) => YuvFormat::PlanarYCbCr,
             YuvData::InterleavedYCbCr(..) => YuvFormat::InterleavedYCbCr,
+            YuvData::P010(..) => YuvFormat::P010,
+            YuvData::P016(..) => YuvFormat::P016,
         }
     }
 }
@@ -722,6 +852,8 @@ pub enum YuvFormat {
     NV12 = 0,
     PlanarYCbCr = 1,
     InterleavedYCbCr = 2,
+    P010 = 3,
+    P016 = 4,
 }
 
 impl YuvFormat {
@@ -730,6 +862,7 @@ impl YuvFormat {
             YuvFormat::NV12 => 2,
             YuvFormat::PlanarYCbCr => 3,
             YuvFormat::InterleavedYCbCr => 1,
+            YuvFormat::P010 | YuvFormat::P016 => 2,
         }
     }
 }
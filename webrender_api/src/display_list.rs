@@ -16,17 +16,19 @@ use std::{io, mem, ptr, slice};
 use time::precise_time_ns;
 use {AlphaType, BorderDetails, BorderDisplayItem, BorderRadius, BoxShadowClipMode, CacheMarkerDisplayItem};
 use {BoxShadowDisplayItem, ClipChainId, ClipChainItem, ClipDisplayItem, ClipId};
-use {ColorF, ComplexClipRegion, DisplayItem, ExtendMode, ExternalScrollId, FilterOp};
-use {FontInstanceKey, GlyphInstance, GlyphOptions, RasterSpace, Gradient, GradientBuilder};
-use {GradientDisplayItem, GradientStop, IframeDisplayItem, ImageDisplayItem, ImageKey, ImageMask};
-use {ImageRendering, LayoutPoint, LayoutPrimitiveInfo, LayoutRect, LayoutSideOffsets, LayoutSize};
-use {LayoutTransform, LayoutVector2D, LineDisplayItem, LineOrientation, LineStyle, MixBlendMode};
+use {ColorF, ComplexClipRegion, CrossFadeDisplayItem, DisplayItem, ExtendMode, ExternalScrollId, FilterOp};
+use {FilterPrimitive, FontInstanceKey, GlyphInstance, GlyphOptions, RasterSpace, Gradient};
+use {GradientBuilder, GradientDisplayItem, GradientStop, IframeDisplayItem, ImageDisplayItem};
+use {ImageKey, ImageMask, ImageRendering, LayoutPoint, LayoutPrimitiveInfo, LayoutRect};
+use {LayoutSideOffsets, LayoutSize, LayoutTransform, LayoutVector2D, LineDisplayItem, LineOrientation};
+use {LineStyle, MixBlendMode};
 use {PipelineId, PropertyBinding, ReferenceFrameDisplayListItem};
+use {ConicGradient, ConicGradientDisplayItem};
 use {PushStackingContextDisplayItem, RadialGradient, RadialGradientDisplayItem};
 use {RectangleDisplayItem, ReferenceFrame, ReferenceFrameKind, ScrollFrameDisplayItem, ScrollSensitivity};
 use {SerializedDisplayItem, Shadow, SpaceAndClipInfo, SpatialId, SpecificDisplayItem};
 use {StackingContext, StickyFrameDisplayItem, StickyOffsetBounds};
-use {TextDisplayItem, TransformStyle, YuvColorSpace, YuvData, YuvImageDisplayItem, ColorDepth};
+use {TextDisplayItem, TransformStyle, YuvColorSpace, YuvData, YuvImageDisplayItem, ColorDepth, ColorRange};
 
 // We don't want to push a long text-run. If a text-run is too long, split it into several parts.
 // This needs to be set to (renderer::MAX_VERTEX_TEXTURE_WIDTH - VECS_PER_TEXT_RUN) * 2
@@ -99,6 +101,8 @@ pub struct BuiltDisplayListIter<'a> {
     cur_stops: ItemRange<GradientStop>,
     cur_glyphs: ItemRange<GlyphInstance>,
     cur_filters: ItemRange<FilterOp>,
+    cur_filter_primitives: ItemRange<FilterPrimitive>,
+    cur_backdrop_filters: ItemRange<FilterOp>,
     cur_clip_chain_items: ItemRange<ClipId>,
     cur_complex_clip: (ItemRange<ComplexClipRegion>, usize),
     peeking: Peek,
@@ -219,6 +223,8 @@ impl<'a> BuiltDisplayListIter<'a> {
             cur_stops: ItemRange::default(),
             cur_glyphs: ItemRange::default(),
             cur_filters: ItemRange::default(),
+            cur_filter_primitives: ItemRange::default(),
+            cur_backdrop_filters: ItemRange::default(),
             cur_clip_chain_items: ItemRange::default(),
             cur_complex_clip: (ItemRange::default(), 0),
             peeking: Peek::NotPeeking,
@@ -287,7 +293,11 @@ impl<'a> BuiltDisplayListIter<'a> {
                 self.cur_complex_clip = self.skip_slice::<ComplexClipRegion>()
             }
             Text(_) => self.cur_glyphs = self.skip_slice::<GlyphInstance>().0,
-            PushStackingContext(_) => self.cur_filters = self.skip_slice::<FilterOp>().0,
+            PushStackingContext(_) => {
+                self.cur_filters = self.skip_slice::<FilterOp>().0;
+                self.cur_filter_primitives = self.skip_slice::<FilterPrimitive>().0;
+                self.cur_backdrop_filters = self.skip_slice::<FilterOp>().0;
+            }
             _ => { /* do nothing */ }
         }
 
@@ -393,6 +403,14 @@ impl<'a, 'b> DisplayItemRef<'a, 'b> {
         self.iter.cur_filters
     }
 
+    pub fn filter_primitives(&self) -> ItemRange<FilterPrimitive> {
+        self.iter.cur_filter_primitives
+    }
+
+    pub fn backdrop_filters(&self) -> ItemRange<FilterOp> {
+        self.iter.cur_backdrop_filters
+    }
+
     pub fn clip_chain_items(&self) -> ItemRange<ClipId> {
         self.iter.cur_clip_chain_items
     }
@@ -484,16 +502,20 @@ impl Serialize for BuiltDisplayList {
                     ),
                     SpecificDisplayItem::Image(v) => Image(v),
                     SpecificDisplayItem::YuvImage(v) => YuvImage(v),
+                    SpecificDisplayItem::CrossFade(v) => CrossFade(v),
                     SpecificDisplayItem::Border(v) => Border(v),
                     SpecificDisplayItem::BoxShadow(v) => BoxShadow(v),
                     SpecificDisplayItem::Gradient(v) => Gradient(v),
                     SpecificDisplayItem::RadialGradient(v) => RadialGradient(v),
+                    SpecificDisplayItem::ConicGradient(v) => ConicGradient(v),
                     SpecificDisplayItem::Iframe(v) => Iframe(v),
                     SpecificDisplayItem::PushReferenceFrame(v) => PushReferenceFrame(v),
                     SpecificDisplayItem::PopReferenceFrame => PopReferenceFrame,
                     SpecificDisplayItem::PushStackingContext(v) => PushStackingContext(
                         v,
-                        item.iter.list.get(item.iter.cur_filters).collect()
+                        item.iter.list.get(item.iter.cur_filters).collect(),
+                        item.iter.list.get(item.iter.cur_filter_primitives).collect(),
+                        item.iter.list.get(item.iter.cur_backdrop_filters).collect()
                     ),
                     SpecificDisplayItem::PopStackingContext => PopStackingContext,
                     SpecificDisplayItem::SetGradientStops => SetGradientStops(
@@ -564,11 +586,14 @@ impl<'de> Deserialize<'de> for BuiltDisplayList {
                     },
                     Image(specific_item) => SpecificDisplayItem::Image(specific_item),
                     YuvImage(specific_item) => SpecificDisplayItem::YuvImage(specific_item),
+                    CrossFade(specific_item) => SpecificDisplayItem::CrossFade(specific_item),
                     Border(specific_item) => SpecificDisplayItem::Border(specific_item),
                     BoxShadow(specific_item) => SpecificDisplayItem::BoxShadow(specific_item),
                     Gradient(specific_item) => SpecificDisplayItem::Gradient(specific_item),
                     RadialGradient(specific_item) =>
                         SpecificDisplayItem::RadialGradient(specific_item),
+                    ConicGradient(specific_item) =>
+                        SpecificDisplayItem::ConicGradient(specific_item),
                     Iframe(specific_item) => {
                         total_clip_nodes += 1;
                         SpecificDisplayItem::Iframe(specific_item)
@@ -578,8 +603,10 @@ impl<'de> Deserialize<'de> for BuiltDisplayList {
                         SpecificDisplayItem::PushReferenceFrame(v)
                     }
                     PopReferenceFrame => SpecificDisplayItem::PopReferenceFrame,
-                    PushStackingContext(specific_item, filters) => {
+                    PushStackingContext(specific_item, filters, filter_primitives, backdrop_filters) => {
                         DisplayListBuilder::push_iter_impl(&mut temp, filters);
+                        DisplayListBuilder::push_iter_impl(&mut temp, filter_primitives);
+                        DisplayListBuilder::push_iter_impl(&mut temp, backdrop_filters);
                         SpecificDisplayItem::PushStackingContext(specific_item)
                     },
                     PopStackingContext => SpecificDisplayItem::PopStackingContext,
@@ -1038,6 +1065,18 @@ impl DisplayListBuilder {
         layout: &LayoutPrimitiveInfo,
         space_and_clip: &SpaceAndClipInfo,
         color: ColorF,
+    ) {
+        self.push_rect_with_animation(layout, space_and_clip, PropertyBinding::Value(color));
+    }
+
+    /// Pushes a solid rectangle whose color can be bound to a property, so
+    /// that it can be animated with `update_dynamic_properties` without
+    /// sending a new display list.
+    pub fn push_rect_with_animation(
+        &mut self,
+        layout: &LayoutPrimitiveInfo,
+        space_and_clip: &SpaceAndClipInfo,
+        color: PropertyBinding<ColorF>,
     ) {
         let item = SpecificDisplayItem::Rectangle(RectangleDisplayItem { color });
         self.push_item(&item, layout, space_and_clip);
@@ -1080,6 +1119,33 @@ impl DisplayListBuilder {
         alpha_type: AlphaType,
         key: ImageKey,
         color: ColorF,
+    ) {
+        self.push_image_with_animation(
+            layout,
+            space_and_clip,
+            stretch_size,
+            tile_spacing,
+            image_rendering,
+            alpha_type,
+            PropertyBinding::Value(key),
+            color,
+        );
+    }
+
+    /// Pushes an image whose key can be bound to a property, so that the
+    /// displayed image can be switched with `update_dynamic_properties`
+    /// without sending a new display list. All the images the property can
+    /// take should have the same size and format.
+    pub fn push_image_with_animation(
+        &mut self,
+        layout: &LayoutPrimitiveInfo,
+        space_and_clip: &SpaceAndClipInfo,
+        stretch_size: LayoutSize,
+        tile_spacing: LayoutSize,
+        image_rendering: ImageRendering,
+        alpha_type: AlphaType,
+        key: PropertyBinding<ImageKey>,
+        color: ColorF,
     ) {
         let item = SpecificDisplayItem::Image(ImageDisplayItem {
             image_key: key,
@@ -1101,12 +1167,34 @@ impl DisplayListBuilder {
         yuv_data: YuvData,
         color_depth: ColorDepth,
         color_space: YuvColorSpace,
+        color_range: ColorRange,
         image_rendering: ImageRendering,
     ) {
         let item = SpecificDisplayItem::YuvImage(YuvImageDisplayItem {
             yuv_data,
             color_depth,
             color_space,
+            color_range,
+            image_rendering,
+        });
+        self.push_item(&item, layout, space_and_clip);
+    }
+
+    /// Push a cross-fade between two images. Both images should use the
+    /// same buffer type.
+    pub fn push_cross_fade(
+        &mut self,
+        layout: &LayoutPrimitiveInfo,
+        space_and_clip: &SpaceAndClipInfo,
+        from_key: ImageKey,
+        to_key: ImageKey,
+        progress: PropertyBinding<f32>,
+        image_rendering: ImageRendering,
+    ) {
+        let item = SpecificDisplayItem::CrossFade(CrossFadeDisplayItem {
+            from_key,
+            to_key,
+            progress,
             image_rendering,
         });
         self.push_item(&item, layout, space_and_clip);
@@ -1163,6 +1251,21 @@ impl DisplayListBuilder {
         gradient
     }
 
+    /// NOTE: gradients must be pushed in the order they're created
+    /// because create_gradient stores the stops in anticipation.
+    pub fn create_conic_gradient(
+        &mut self,
+        center: LayoutPoint,
+        angle: f32,
+        stops: Vec<GradientStop>,
+        extend_mode: ExtendMode,
+    ) -> ConicGradient {
+        let mut builder = GradientBuilder::with_stops(stops);
+        let gradient = builder.conic_gradient(center, angle, extend_mode);
+        self.push_stops(builder.stops());
+        gradient
+    }
+
     pub fn push_border(
         &mut self,
         layout: &LayoutPrimitiveInfo,
@@ -1251,6 +1354,26 @@ impl DisplayListBuilder {
         self.push_item(&item, layout, space_and_clip);
     }
 
+    /// Pushes a conic gradient to be displayed.
+    ///
+    /// See [`push_gradient`](#method.push_gradient) for explanation.
+    pub fn push_conic_gradient(
+        &mut self,
+        layout: &LayoutPrimitiveInfo,
+        space_and_clip: &SpaceAndClipInfo,
+        gradient: ConicGradient,
+        tile_size: LayoutSize,
+        tile_spacing: LayoutSize,
+    ) {
+        let item = SpecificDisplayItem::ConicGradient(ConicGradientDisplayItem {
+            gradient,
+            tile_size,
+            tile_spacing,
+        });
+
+        self.push_item(&item, layout, space_and_clip);
+    }
+
     pub fn push_reference_frame(
         &mut self,
         rect: &LayoutRect,
@@ -1303,6 +1426,8 @@ impl DisplayListBuilder {
         transform_style: TransformStyle,
         mix_blend_mode: MixBlendMode,
         filters: &[FilterOp],
+        filter_primitives: &[FilterPrimitive],
+        backdrop_filters: &[FilterOp],
         raster_space: RasterSpace,
         cache_tiles: bool,
     ) {
@@ -1321,6 +1446,8 @@ impl DisplayListBuilder {
             clip_id: ClipId::invalid(),
         });
         self.push_iter(filters);
+        self.push_iter(filter_primitives);
+        self.push_iter(backdrop_filters);
     }
 
     /// Helper for examples/ code.
@@ -1346,6 +1473,8 @@ impl DisplayListBuilder {
             TransformStyle::Flat,
             MixBlendMode::Normal,
             filters,
+            &[],
+            &[],
             RasterSpace::Screen,
             /* cache_tiles = */ false,
         );
@@ -1441,10 +1570,39 @@ impl DisplayListBuilder {
         I: IntoIterator<Item = ComplexClipRegion>,
         I::IntoIter: ExactSizeIterator + Clone,
     {
+        self.define_clip_with_animation(
+            parent_space_and_clip,
+            PropertyBinding::Value(clip_rect),
+            complex_clips,
+            image_mask,
+        )
+    }
+
+    /// Defines a clip whose clip rect can be bound to a property, so that
+    /// it can be animated with `update_dynamic_properties` without sending
+    /// a new display list. The complex clips and the image mask are not
+    /// animated.
+    pub fn define_clip_with_animation<I>(
+        &mut self,
+        parent_space_and_clip: &SpaceAndClipInfo,
+        clip_rect: PropertyBinding<LayoutRect>,
+        complex_clips: I,
+        image_mask: Option<ImageMask>,
+    ) -> ClipId
+    where
+        I: IntoIterator<Item = ComplexClipRegion>,
+        I::IntoIter: ExactSizeIterator + Clone,
+    {
+        let (clip_rect, rect_binding) = match clip_rect {
+            PropertyBinding::Value(rect) => (rect, None),
+            PropertyBinding::Binding(key, rect) => (rect, Some(key)),
+        };
+
         let id = self.generate_clip_index();
         let item = SpecificDisplayItem::Clip(ClipDisplayItem {
             id,
             image_mask,
+            rect_binding,
         });
 
         self.push_item(
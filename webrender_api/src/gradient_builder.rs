@@ -2,7 +2,7 @@
  * License, v. 2.0. If a copy of the MPL was not distributed with this
  * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
 
-use {ExtendMode, Gradient, GradientStop, LayoutPoint, LayoutSize, RadialGradient};
+use {ConicGradient, ExtendMode, Gradient, GradientStop, LayoutPoint, LayoutSize, RadialGradient};
 
 
 /// Construct a gradient to be used in display lists.
@@ -98,6 +98,25 @@ impl GradientBuilder {
         }
     }
 
+    /// Produce a conic gradient, normalize the stops.
+    pub fn conic_gradient(
+        &mut self,
+        center: LayoutPoint,
+        angle: f32,
+        extend_mode: ExtendMode,
+    ) -> ConicGradient {
+        let (start_offset, end_offset) =
+            self.normalize(extend_mode);
+
+        ConicGradient {
+            center,
+            angle,
+            start_offset,
+            end_offset,
+            extend_mode,
+        }
+    }
+
     /// Gradients can be defined with stops outside the range of [0, 1]
     /// when this happens the gradient needs to be normalized by adjusting
     /// the gradient stops and gradient line into an equivalent gradient
@@ -130,11 +130,40 @@ pub enum ImageFormat {
     RGBAI32 = 6,
     /// Four channels, byte storage.
     RGBA8 = 7,
+    /// Four channels, half-float storage.
+    RGBAF16 = 8,
+    /// Four channels, 10 bits per color and 2 bits of alpha packed in 32 bits,
+    /// with red in the least significant bits. Only supported for RGBA
+    /// images, not for the planes of YUV images.
+    RGB10A2 = 9,
+    /// Block compressed (DXT1), 8 bytes per 4x4 block, with 1 bit alpha.
+    BC1 = 10,
+    /// Block compressed (DXT5), 16 bytes per 4x4 block.
+    BC3 = 11,
+    /// Block compressed, 16 bytes per 4x4 block.
+    BC7 = 12,
+    /// ETC2 with EAC alpha, 16 bytes per 4x4 block.
+    ETC2 = 13,
+    /// ASTC with 4x4 blocks of 16 bytes.
+    ASTC4x4 = 14,
+    /// Two channels, short storage. Used for the interleaved chroma plane of
+    /// P010 and P016 images.
+    RG16 = 15,
 }
 
 impl ImageFormat {
     /// Returns the number of bytes per pixel for the given format.
+    ///
+    /// Block compressed formats don't have a whole number of bytes per pixel,
+    /// and panic. Use `bytes_per_block` or the size helpers for them.
     pub fn bytes_per_pixel(self) -> i32 {
+        assert!(!self.is_compressed(), "{:?} has no bytes per pixel", self);
+        self.bytes_per_block()
+    }
+
+    /// Returns the number of bytes per block of `block_size` x `block_size`
+    /// pixels. For uncompressed formats, this is the number of bytes per pixel.
+    pub fn bytes_per_block(self) -> i32 {
         match self {
             ImageFormat::R8 => 1,
             ImageFormat::R16 => 2,
@@ -143,8 +172,53 @@ impl ImageFormat {
             ImageFormat::RG8 => 2,
             ImageFormat::RGBAI32 => 16,
             ImageFormat::RGBA8 => 4,
+            ImageFormat::RGBAF16 => 8,
+            ImageFormat::RGB10A2 => 4,
+            ImageFormat::RG16 => 4,
+            ImageFormat::BC1 => 8,
+            ImageFormat::BC3 |
+            ImageFormat::BC7 |
+            ImageFormat::ETC2 |
+            ImageFormat::ASTC4x4 => 16,
         }
     }
+
+    /// Returns the width and height, in pixels, of the blocks the texels of
+    /// the given format are stored in. 1 for uncompressed formats.
+    pub fn block_size(self) -> i32 {
+        match self {
+            ImageFormat::BC1 |
+            ImageFormat::BC3 |
+            ImageFormat::BC7 |
+            ImageFormat::ETC2 |
+            ImageFormat::ASTC4x4 => 4,
+            _ => 1,
+        }
+    }
+
+    /// Returns true if the given format stores pixels in compressed blocks.
+    pub fn is_compressed(self) -> bool {
+        self.block_size() > 1
+    }
+
+    /// Returns the number of rows of blocks covering `height` pixels.
+    pub fn block_rows(self, height: i32) -> i32 {
+        let block_size = self.block_size();
+        (height + block_size - 1) / block_size
+    }
+
+    /// Returns the number of bytes of a tightly packed row of blocks covering
+    /// `width` pixels.
+    pub fn row_size_in_bytes(self, width: i32) -> i32 {
+        let block_size = self.block_size();
+        (width + block_size - 1) / block_size * self.bytes_per_block()
+    }
+
+    /// Returns the number of bytes of tightly packed data for an image of the
+    /// given size.
+    pub fn size_in_bytes(self, width: i32, height: i32) -> usize {
+        self.row_size_in_bytes(width) as usize * self.block_rows(height) as usize
+    }
 }
 
 /// Specifies the color depth of an image. Currently only used for YUV images.
@@ -192,7 +266,8 @@ pub struct ImageDescriptor {
     pub size: DeviceIntSize,
     /// The number of bytes from the start of one row to the next. If non-None,
     /// `compute_stride` will return this value, otherwise it returns
-    /// `width * bpp`. Different source of images have different alignment
+    /// `width * bpp`, or the size of a row of blocks for compressed formats.
+    /// Different source of images have different alignment
     /// constraints for rows, so the stride isn't always equal to width * bpp.
     pub stride: Option<i32>,
     /// Offset in bytes of the first pixel of this image in its backing buffer.
@@ -234,12 +309,23 @@ impl ImageDescriptor {
     /// Returns the stride, either via an explicit stride stashed on the object
     /// or by the default computation.
     pub fn compute_stride(&self) -> i32 {
-        self.stride.unwrap_or(self.size.width * self.format.bytes_per_pixel())
+        self.stride.unwrap_or(self.format.row_size_in_bytes(self.size.width))
     }
 
     /// Computes the total size of the image, in bytes.
     pub fn compute_total_size(&self) -> i32 {
-        self.compute_stride() * self.size.height
+        self.compute_stride() * self.format.block_rows(self.size.height)
+    }
+
+    /// Computes the offset, in bytes, of the pixel at `point` from the start
+    /// of the image. For compressed formats, `point` has to be on the corner
+    /// of a block.
+    pub fn compute_offset(&self, point: DeviceIntPoint) -> i32 {
+        debug_assert_eq!(point.x % self.format.block_size(), 0);
+        debug_assert_eq!(point.y % self.format.block_size(), 0);
+        self.offset +
+            self.format.block_rows(point.y) * self.compute_stride() +
+            self.format.row_size_in_bytes(point.x)
     }
 
     /// Computes the bounding rectangle for the image, rooted at (0, 0).
@@ -0,0 +1,132 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+use NotifierEvent;
+use std::fs::{self, File};
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::Receiver;
+use webrender::api::*;
+use wrench::Wrench;
+use yaml_frame_reader::YamlFrameReader;
+
+/// Extension of the golden files, stored next to the YAML file of the scene.
+const GOLDEN_EXTENSION: &str = "batches";
+
+/// Renders scenes and compares the resulting batch lists with golden files,
+/// so that changes to batching show up even when the rendering doesn't.
+pub struct BatchtestHarness<'a> {
+    wrench: &'a mut Wrench,
+    rx: &'a Receiver<NotifierEvent>,
+}
+
+impl<'a> BatchtestHarness<'a> {
+    pub fn new(wrench: &'a mut Wrench, rx: &'a Receiver<NotifierEvent>) -> Self {
+        BatchtestHarness { wrench, rx }
+    }
+
+    /// Runs all the tests found at `path`, a YAML file or a directory. With
+    /// `update` set, the golden files are (re)written instead of compared.
+    /// Returns the number of failing tests. A missing `path` counts as a
+    /// failure, so that a wrong working directory doesn't pass silently.
+    pub fn run(mut self, path: &Path, update: bool) -> usize {
+        if !path.exists() {
+            println!("BATCHTEST TEST-UNEXPECTED-FAIL | {} | not found", path.display());
+            return 1;
+        }
+
+        let mut tests = Vec::new();
+        find_tests(path, &mut tests);
+        tests.sort();
+
+        let mut failing = Vec::new();
+        for test in &tests {
+            if !self.run_batchtest(test, update) {
+                failing.push(test);
+            }
+        }
+
+        println!(
+            "BATCHTEST INFO | {} passing, {} failing",
+            tests.len() - failing.len(),
+            failing.len()
+        );
+        for test in &failing {
+            println!("\t{}", test.display());
+        }
+
+        failing.len()
+    }
+
+    fn run_batchtest(&mut self, filename: &Path, update: bool) -> bool {
+        let golden_path = filename.with_extension(GOLDEN_EXTENSION);
+        let batch_lists = self.render_yaml(filename);
+
+        if update {
+            File::create(&golden_path)
+                .and_then(|mut file| file.write_all(batch_lists.as_bytes()))
+                .expect("Unable to write the golden file");
+            println!("BATCHTEST UPDATED | {}", golden_path.display());
+            return true;
+        }
+
+        let mut golden = String::new();
+        if File::open(&golden_path).and_then(|mut file| file.read_to_string(&mut golden)).is_err() {
+            println!("BATCHTEST TEST-UNEXPECTED-FAIL | {} | missing {}", filename.display(), golden_path.display());
+            return false;
+        }
+
+        if golden != batch_lists {
+            println!("BATCHTEST TEST-UNEXPECTED-FAIL | {} | batch lists differ", filename.display());
+            for (line, (expected, actual)) in golden.lines().zip(batch_lists.lines()).enumerate() {
+                if expected != actual {
+                    println!("BATCHTEST   line {}: expected `{}`, got `{}`", line + 1, expected, actual);
+                    break;
+                }
+            }
+            println!(
+                "BATCHTEST   {} expected lines, {} actual lines",
+                golden.lines().count(),
+                batch_lists.lines().count()
+            );
+            return false;
+        }
+
+        println!("BATCHTEST TEST-PASS | {}", filename.display());
+        true
+    }
+
+    fn render_yaml(&mut self, filename: &Path) -> String {
+        // Texture cache state from previous tests would change the batches.
+        self.wrench
+            .api
+            .send_debug_cmd(
+                DebugCommand::ClearCaches(ClearCache::all())
+            );
+
+        let mut reader = YamlFrameReader::new(filename);
+        reader.do_frame(self.wrench);
+
+        self.wrench.api.flush_scene_builder();
+
+        // wait for the frame
+        self.rx.recv().unwrap();
+        self.wrench.render();
+
+        let batch_lists = self.wrench.renderer.get_batch_lists();
+        reader.deinit(self.wrench);
+        batch_lists
+    }
+}
+
+fn find_tests(path: &Path, tests: &mut Vec<PathBuf>) {
+    if path.is_dir() {
+        let entries = fs::read_dir(path).expect("Unable to read the batchtest directory");
+        for entry in entries {
+            find_tests(&entry.unwrap().path(), tests);
+        }
+    } else if path.extension().map_or(false, |ext| ext == "yaml") {
+        tests.push(path.to_owned());
+    }
+}
@@ -75,6 +75,7 @@ cfg_if! {
     }
 }
 
+mod batchtest;
 mod binary_frame_reader;
 mod blob;
 mod json_frame_writer;
@@ -93,6 +94,7 @@ mod yaml_helper;
 #[cfg(target_os = "macos")]
 mod cgfont_to_data;
 
+use batchtest::BatchtestHarness;
 use binary_frame_reader::BinaryFrameReader;
 use perf::PerfHarness;
 use png::save_flipped;
@@ -106,6 +108,8 @@ use std::path::{Path, PathBuf};
 use std::process;
 use std::sync::mpsc::{channel, Sender, Receiver};
 use webrender::DebugFlags;
+#[cfg(not(feature = "gl"))]
+use webrender::SplitScreen;
 use webrender::api::*;
 use winit::dpi::{LogicalPosition, LogicalSize};
 use winit::VirtualKeyCode;
@@ -506,6 +510,18 @@ fn reftest<'a>(
     num_failures
 }
 
+fn batchtest<'a>(
+    mut wrench: Wrench,
+    subargs: &clap::ArgMatches<'a>,
+    rx: Receiver<NotifierEvent>
+) -> usize {
+    let path = Path::new(subargs.value_of("BATCHTEST").unwrap_or("batchtests"));
+    let num_failures = BatchtestHarness::new(&mut wrench, &rx)
+        .run(path, subargs.is_present("update"));
+    wrench.shut_down(rx);
+    num_failures
+}
+
 #[cfg(any(feature = "gfx", feature = "gl"))]
 fn main() {
     #[cfg(feature = "env_logger")]
@@ -577,7 +593,7 @@ fn main() {
         (dp_ratio, dim)
     };
 
-    let needs_frame_notifier = ["perf", "reftest", "png", "rawtest"]
+    let needs_frame_notifier = ["perf", "reftest", "batchtest", "png", "rawtest"]
         .iter()
         .any(|s| args.subcommand_matches(s).is_some());
     let (notifier, rx) = if needs_frame_notifier {
@@ -655,6 +671,9 @@ fn main() {
         // Exit with an error code in order to ensure the CI job fails.
         let _ = reftest(wrench, &mut window, subargs, rx.unwrap());
         process::exit(0);
+    } else if let Some(subargs) = args.subcommand_matches("batchtest") {
+        let num_failures = batchtest(wrench, subargs, rx.unwrap());
+        process::exit(if num_failures > 0 { 1 } else { 0 });
     } else if let Some(_) = args.subcommand_matches("rawtest") {
         rawtest(wrench, &mut window, rx.unwrap());
         return;
@@ -740,6 +759,8 @@ fn render<'a>(
     thing.do_frame(wrench);
 
     let mut debug_flags = DebugFlags::empty();
+    #[cfg(not(feature = "gl"))]
+    let mut split_screen = false;
     let mut body = |wrench: &mut Wrench, global_event: winit::Event| {
         let mut do_frame = false;
         let mut do_render = false;
@@ -758,6 +779,10 @@ fn render<'a>(
                 }
                 winit::WindowEvent::CursorMoved { position: LogicalPosition { x, y }, .. } => {
                     cursor_position = WorldPoint::new(x as f32, y as f32);
+                    if debug_flags.contains(DebugFlags::ZOOM_DBG) {
+                        let position = cursor_position * wrench.device_pixel_ratio;
+                        wrench.api.set_debug_cursor(Some(DeviceIntPoint::new(position.x as i32, position.y as i32)));
+                    }
                     do_render = true;
                 }
                 winit::WindowEvent::KeyboardInput {
@@ -812,6 +837,13 @@ fn render<'a>(
 
                         do_frame = true;
                     }
+                    VirtualKeyCode::Z => {
+                        debug_flags.toggle(DebugFlags::ZOOM_DBG);
+                        wrench.api.send_debug_cmd(DebugCommand::SetFlags(debug_flags));
+                        let position = cursor_position * wrench.device_pixel_ratio;
+                        wrench.api.set_debug_cursor(Some(DeviceIntPoint::new(position.x as i32, position.y as i32)));
+                        do_render = true;
+                    }
                     VirtualKeyCode::R => {
                         wrench.set_page_zoom(ZoomFactor::new(1.0));
                         do_frame = true;
@@ -820,6 +852,19 @@ fn render<'a>(
                         wrench.api.notify_memory_pressure();
                         do_render = true;
                     }
+                    #[cfg(not(feature = "gl"))]
+                    VirtualKeyCode::D => {
+                        split_screen = !split_screen;
+                        wrench.renderer.set_split_screen(if split_screen {
+                            Some(SplitScreen {
+                                left_flags: DebugFlags::empty(),
+                                right_flags: DebugFlags::DISABLE_BATCHING,
+                            })
+                        } else {
+                            None
+                        });
+                        do_render = true;
+                    }
                     VirtualKeyCode::L => {
                         do_loop = !do_loop;
                         do_render = true;
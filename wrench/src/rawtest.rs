@@ -9,6 +9,9 @@ use std::sync::Arc;
 use std::sync::atomic::{AtomicIsize, Ordering};
 use std::sync::mpsc::Receiver;
 use webrender::api::*;
+use webrender::{ExternalImage, ExternalImageHandler, ExternalImageSource};
+#[cfg(not(feature = "gl"))]
+use webrender::{DeviceCommand, RendererOptions, ShaderPrecacheFlags, Shaders};
 use wrench::Wrench;
 
 pub struct RawtestHarness<'a> {
@@ -29,6 +32,20 @@ fn rect<T: Copy, U>(x: T, y: T, width: T, height: T) -> TypedRect<T, U> {
     TypedRect::new(point(x, y), size(width, height))
 }
 
+/// Hands out the same invalid image for every external image.
+struct InvalidExternalImageHandler;
+
+impl ExternalImageHandler for InvalidExternalImageHandler {
+    fn lock(&mut self, _key: ExternalImageId, _channel_index: u8, _rendering: ImageRendering) -> ExternalImage {
+        ExternalImage {
+            uv: TexelRect::new(0.0, 0.0, 1.0, 1.0),
+            source: ExternalImageSource::Invalid,
+            timestamp: None,
+        }
+    }
+    fn unlock(&mut self, _key: ExternalImageId, _channel_index: u8) {}
+}
+
 impl<'a> RawtestHarness<'a> {
     pub fn new(wrench: &'a mut Wrench,
                window: &'a mut WindowWrapper,
@@ -53,6 +70,11 @@ impl<'a> RawtestHarness<'a> {
         self.test_blur_cache();
         self.test_capture();
         self.test_zero_height_window();
+        self.test_cross_fade_external_image();
+        #[cfg(not(feature = "gl"))]
+        self.test_replay_frame();
+        #[cfg(not(feature = "gl"))]
+        self.test_missing_shader();
     }
 
     fn render_and_get_pixels(&mut self, window_rect: DeviceIntRect) -> Vec<u8> {
@@ -935,6 +957,160 @@ impl<'a> RawtestHarness<'a> {
         assert_ne!(first, second);
     }
 
+    fn test_cross_fade_external_image(&mut self) {
+        println!("\tcross-fade external image...");
+        let window_size = self.window.get_inner_size();
+
+        let test_size = DeviceIntSize::new(400, 400);
+        let window_rect = DeviceIntRect::new(
+            DeviceIntPoint::new(0, window_size.height - test_size.height),
+            test_size,
+        );
+        let layout_size = LayoutSize::new(400., 400.);
+
+        self.wrench.renderer.set_external_image_handler(Box::new(InvalidExternalImageHandler));
+
+        // The external image lives in a 2D texture, while the regular one
+        // is in the texture cache array.
+        let mut txn = Transaction::new();
+        let external_image = self.wrench.api.generate_image_key();
+        txn.add_image(
+            external_image,
+            ImageDescriptor::new(100, 100, ImageFormat::BGRA8, true, false),
+            ImageData::External(ExternalImageData {
+                id: ExternalImageId(0),
+                channel_index: 0,
+                image_type: ExternalImageType::TextureHandle(TextureTarget::Default),
+            }),
+            None,
+        );
+        let image = self.wrench.api.generate_image_key();
+        txn.add_image(
+            image,
+            ImageDescriptor::new(1, 1, ImageFormat::BGRA8, true, false),
+            ImageData::new(vec![0xFF, 0, 0, 0xFF]),
+            None,
+        );
+
+        let mut builder = DisplayListBuilder::new(self.wrench.root_pipeline_id, layout_size);
+        builder.push_cross_fade(
+            &PrimitiveInfo::new(rect(50., 50., 100., 100.)),
+            &SpaceAndClipInfo::root_scroll(self.wrench.root_pipeline_id),
+            external_image,
+            image,
+            PropertyBinding::Value(0.5),
+            ImageRendering::Auto,
+        );
+
+        self.submit_dl(&mut Epoch(0), layout_size, builder, &txn.resource_updates);
+
+        // The images can't be sampled by the same shader, so the cross-fade
+        // is skipped rather than drawn with the wrong texture.
+        let pixels = self.render_and_get_pixels(window_rect);
+        assert!(pixels.iter().all(|&p| p == 0xFF));
+
+        let mut txn = Transaction::new();
+        txn.delete_image(external_image);
+        txn.delete_image(image);
+        self.wrench.api.update_resources(txn.resource_updates);
+    }
+
+    #[cfg(not(feature = "gl"))]
+    fn test_replay_frame(&mut self) {
+        println!("\treplay frame...");
+        let window_size = self.window.get_inner_size();
+
+        let test_size = DeviceIntSize::new(400, 400);
+        let window_rect = DeviceIntRect::new(
+            DeviceIntPoint::new(0, window_size.height - test_size.height),
+            test_size,
+        );
+        let layout_size = LayoutSize::new(400., 400.);
+        let space_and_clip = SpaceAndClipInfo::root_scroll(self.wrench.root_pipeline_id);
+
+        let mut builder = DisplayListBuilder::new(self.wrench.root_pipeline_id, layout_size);
+        builder.push_rect(
+            &PrimitiveInfo::new(rect(50., 50., 200., 100.)),
+            &space_and_clip,
+            ColorF::new(0.0, 1.0, 0.0, 1.0),
+        );
+        builder.push_rect(
+            &PrimitiveInfo::new(rect(100., 100., 100., 200.)),
+            &space_and_clip,
+            ColorF::new(0.0, 0.0, 1.0, 0.5),
+        );
+
+        let txn = Transaction::new();
+        self.submit_dl(&mut Epoch(0), layout_size, builder, &txn.resource_updates);
+
+        // Record the frame, then replay it right away, while everything it
+        // references is still alive. The replay draws into the next
+        // swapchain image, so it has to reproduce the whole frame.
+        self.wrench.renderer.record_next_frame();
+        let recorded_pixels = self.render_and_get_pixels(window_rect);
+        let stream = self.wrench.renderer
+            .take_recorded_frame()
+            .expect("no frame was recorded");
+        assert!(self.wrench.renderer.take_recorded_frame().is_none());
+
+        // Every draw happens with a draw target and a program bound, after
+        // uploading its instances. The instance uploads are stored back to
+        // back, in submission order.
+        let mut has_draw_target = false;
+        let mut has_program = false;
+        let mut has_instances = false;
+        let mut instance_end = 0;
+        for command in &stream.commands {
+            match *command {
+                DeviceCommand::BindDrawTarget { .. } => has_draw_target = true,
+                DeviceCommand::BindProgram(..) => {
+                    has_program = true;
+                    has_instances = false;
+                }
+                DeviceCommand::UpdateInstances(ref range) => {
+                    assert_eq!(range.start, instance_end);
+                    assert!(range.end > range.start);
+                    instance_end = range.end;
+                    has_instances = true;
+                }
+                DeviceCommand::Draw => {
+                    assert!(has_draw_target && has_program && has_instances);
+                }
+                _ => {}
+            }
+        }
+        assert_eq!(instance_end, stream.instance_data.len());
+
+        // The opaque and the alpha rect are drawn in separate batches.
+        assert!(stream.draw_call_count() >= 2);
+
+        let stats = self.wrench.renderer
+            .replay_frame(&stream)
+            .expect("errors encountered during replay");
+        assert_eq!(stats.total_draw_calls, stream.draw_call_count());
+        let replayed_pixels = self.wrench.renderer.read_pixels_rgba8(window_rect);
+
+        assert!(recorded_pixels == replayed_pixels);
+    }
+
+    #[cfg(not(feature = "gl"))]
+    fn test_missing_shader(&mut self) {
+        println!("\tmissing shader...");
+        let options = RendererOptions {
+            precache_flags: ShaderPrecacheFlags::FULL_COMPILE,
+            ..Default::default()
+        };
+
+        // A shader missing from the shader pack only makes the batches using
+        // it skipped, even when all the shaders are built up front.
+        let device = &mut self.wrench.renderer.device;
+        let shaders = device.without_pipeline_requirements("cs_svg_filter", |device| {
+            Shaders::new(device, (), &options)
+        });
+        let shaders = shaders.expect("a missing shader failed the renderer");
+        shaders.deinit(device);
+    }
+
     fn test_capture(&mut self) {
         println!("\tcapture...");
         let path = "../captures/test";
@@ -3,17 +3,20 @@
  * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
 
 use std::collections::HashMap;
-use webrender::api::{BuiltDisplayList, ColorF, Epoch};
-use webrender::api::{LayoutSize, PipelineId};
+use webrender::api::{BuiltDisplayList, ColorF, Epoch, ImageKey};
+use webrender::api::{LayoutRect, LayoutSize, PipelineId};
 use webrender::api::{PropertyBinding, PropertyBindingId, LayoutTransform, DynamicProperties};
 
 /// Stores a map of the animated property bindings for the current display list. These
-/// can be used to animate the transform and/or opacity of a display list without
-/// re-submitting the display list itself.
+/// can be used to animate the transform, opacity, solid colors, image keys and clip
+/// rects of a display list without re-submitting the display list itself.
 #[derive(Default)]
 pub struct SceneProperties {
     transform_properties: HashMap<PropertyBindingId, LayoutTransform>,
     float_properties: HashMap<PropertyBindingId, f32>,
+    color_properties: HashMap<PropertyBindingId, ColorF>,
+    image_properties: HashMap<PropertyBindingId, ImageKey>,
+    rect_properties: HashMap<PropertyBindingId, LayoutRect>,
 }
 
 impl SceneProperties {
@@ -21,6 +24,9 @@ impl SceneProperties {
     pub fn set_properties(&mut self, properties: &DynamicProperties) {
         self.transform_properties.clear();
         self.float_properties.clear();
+        self.color_properties.clear();
+        self.image_properties.clear();
+        self.rect_properties.clear();
 
         for property in &properties.transforms {
             self.transform_properties
@@ -31,6 +37,21 @@ impl SceneProperties {
             self.float_properties
                 .insert(property.key.id, property.value);
         }
+
+        for property in &properties.colors {
+            self.color_properties
+                .insert(property.key.id, property.value);
+        }
+
+        for property in &properties.images {
+            self.image_properties
+                .insert(property.key.id, property.value);
+        }
+
+        for property in &properties.rects {
+            self.rect_properties
+                .insert(property.key.id, property.value);
+        }
     }
 
     /// Get the current value for a transform property.
@@ -57,6 +78,39 @@ impl SceneProperties {
                 .unwrap_or(v),
         }
     }
+
+    /// Get the current value for a color property.
+    pub fn resolve_color(&self, property: &PropertyBinding<ColorF>) -> ColorF {
+        match *property {
+            PropertyBinding::Value(value) => value,
+            PropertyBinding::Binding(ref key, v) => self.color_properties
+                .get(&key.id)
+                .cloned()
+                .unwrap_or(v),
+        }
+    }
+
+    /// Get the current value for an image key property.
+    pub fn resolve_image_key(&self, property: &PropertyBinding<ImageKey>) -> ImageKey {
+        match *property {
+            PropertyBinding::Value(value) => value,
+            PropertyBinding::Binding(ref key, v) => self.image_properties
+                .get(&key.id)
+                .cloned()
+                .unwrap_or(v),
+        }
+    }
+
+    /// Get the current value for a rect property.
+    pub fn resolve_layout_rect(&self, property: &PropertyBinding<LayoutRect>) -> LayoutRect {
+        match *property {
+            PropertyBinding::Value(value) => value,
+            PropertyBinding::Binding(ref key, v) => self.rect_properties
+                .get(&key.id)
+                .cloned()
+                .unwrap_or(v),
+        }
+    }
 }
 
 /// A representation of the layout within the display port for a given document or iframe.
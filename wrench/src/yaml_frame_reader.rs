@@ -192,8 +192,16 @@ fn is_image_opaque(format: ImageFormat, bytes: &[u8]) -> bool {
         ImageFormat::RG8 => true,
         ImageFormat::R8 => false,
         ImageFormat::R16 => false,
+        ImageFormat::RG16 => true,
         ImageFormat::RGBAF32 |
-        ImageFormat::RGBAI32 => unreachable!(),
+        ImageFormat::RGBAF16 |
+        ImageFormat::RGB10A2 |
+        ImageFormat::RGBAI32 |
+        ImageFormat::BC1 |
+        ImageFormat::BC3 |
+        ImageFormat::BC7 |
+        ImageFormat::ETC2 |
+        ImageFormat::ASTC4x4 => unreachable!(),
     }
 }
 
@@ -750,6 +758,34 @@ impl YamlFrameReader {
         dl.create_radial_gradient(center, radius, stops, extend_mode)
     }
 
+    fn to_conic_gradient(&mut self, dl: &mut DisplayListBuilder, item: &Yaml) -> ConicGradient {
+        let center = item["center"].as_point().expect("conic gradient must have center");
+        // The angle is given in degrees, clockwise from the top.
+        let angle = item["angle"].as_force_f32().unwrap_or(0.0).to_radians();
+        let stops = item["stops"]
+            .as_vec()
+            .expect("conic gradient must have stops")
+            .chunks(2)
+            .map(|chunk| {
+                GradientStop {
+                    offset: chunk[0]
+                        .as_force_f32()
+                        .expect("gradient stop offset is not f32"),
+                    color: chunk[1]
+                        .as_colorf()
+                        .expect("gradient stop color is not color"),
+                }
+            })
+            .collect::<Vec<_>>();
+        let extend_mode = if item["repeat"].as_bool().unwrap_or(false) {
+            ExtendMode::Repeat
+        } else {
+            ExtendMode::Clamp
+        };
+
+        dl.create_conic_gradient(center, angle, stops, extend_mode)
+    }
+
     fn handle_rect(
         &mut self,
         dl: &mut DisplayListBuilder,
@@ -891,6 +927,34 @@ impl YamlFrameReader {
         );
     }
 
+    fn handle_conic_gradient(
+        &mut self,
+        dl: &mut DisplayListBuilder,
+        item: &Yaml,
+        info: &mut LayoutPrimitiveInfo,
+    ) {
+        let bounds_key = if item["type"].is_badvalue() {
+            "conic-gradient"
+        } else {
+            "bounds"
+        };
+        let bounds = item[bounds_key]
+            .as_rect()
+            .expect("conic gradient must have bounds");
+        info.rect = bounds;
+        let gradient = self.to_conic_gradient(dl, item);
+        let tile_size = item["tile-size"].as_size().unwrap_or(bounds.size);
+        let tile_spacing = item["tile-spacing"].as_size().unwrap_or(LayoutSize::zero());
+
+        dl.push_conic_gradient(
+            &info,
+            &self.top_space_and_clip(),
+            gradient,
+            tile_size,
+            tile_spacing,
+        );
+    }
+
     fn handle_border(
         &mut self,
         dl: &mut DisplayListBuilder,
@@ -1109,9 +1173,19 @@ impl YamlFrameReader {
         item: &Yaml,
         info: &mut LayoutPrimitiveInfo,
     ) {
-        // TODO(gw): Support other YUV color depth and spaces.
+        // TODO(gw): Support other YUV color depths.
         let color_depth = ColorDepth::Color8;
-        let color_space = YuvColorSpace::Rec709;
+        let color_space = match item["color-space"].as_str() {
+            Some("rec601") => YuvColorSpace::Rec601,
+            Some("rec709") | None => YuvColorSpace::Rec709,
+            Some("rec2020") => YuvColorSpace::Rec2020,
+            Some(s) => panic!("unexpected yuv color space {}", s),
+        };
+        let color_range = match item["color-range"].as_str() {
+            Some("limited") | None => ColorRange::Limited,
+            Some("full") => ColorRange::Full,
+            Some(s) => panic!("unexpected yuv color range {}", s),
+        };
 
         let yuv_data = match item["format"].as_str().expect("no format supplied") {
             "planar" => {
@@ -1158,10 +1232,49 @@ impl YamlFrameReader {
             yuv_data,
             color_depth,
             color_space,
+            color_range,
             ImageRendering::Auto,
         );
     }
 
+    fn handle_cross_fade(
+        &mut self,
+        dl: &mut DisplayListBuilder,
+        wrench: &mut Wrench,
+        item: &Yaml,
+        info: &mut LayoutPrimitiveInfo,
+    ) {
+        let from_path = rsrc_path(&item["from"], &self.aux_dir);
+        let (from_key, _) = self.add_or_get_image(&from_path, None, wrench);
+
+        let to_path = rsrc_path(&item["to"], &self.aux_dir);
+        let (to_key, _) = self.add_or_get_image(&to_path, None, wrench);
+
+        info.rect = item["bounds"]
+            .as_rect()
+            .expect("cross-fade type must have bounds");
+
+        let progress = item["progress"].as_f32().unwrap_or(0.5);
+        let rendering = match item["rendering"].as_str() {
+            Some("auto") | None => ImageRendering::Auto,
+            Some("crisp-edges") => ImageRendering::CrispEdges,
+            Some("pixelated") => ImageRendering::Pixelated,
+            Some(_) => panic!(
+                "ImageRendering can be auto, crisp-edges, or pixelated -- got {:?}",
+                item
+            ),
+        };
+
+        dl.push_cross_fade(
+            &info,
+            &self.top_space_and_clip(),
+            from_key,
+            to_key,
+            PropertyBinding::Value(progress),
+            rendering,
+        );
+    }
+
     fn handle_image(
         &mut self,
         dl: &mut DisplayListBuilder,
@@ -1379,6 +1492,7 @@ impl YamlFrameReader {
             "border",
             "gradient",
             "radial-gradient",
+            "conic-gradient",
         ];
 
         for shorthand in shorthands.iter() {
@@ -1454,6 +1568,7 @@ impl YamlFrameReader {
                 "line" => self.handle_line(dl, item, &mut info),
                 "image" => self.handle_image(dl, wrench, item, &mut info),
                 "yuv-image" => self.handle_yuv_image(dl, wrench, item, &mut info),
+                "cross-fade" => self.handle_cross_fade(dl, wrench, item, &mut info),
                 "text" | "glyphs" => self.handle_text(dl, wrench, item, &mut info),
                 "scroll-frame" => self.handle_scroll_frame(dl, wrench, item),
                 "sticky-frame" => self.handle_sticky_frame(dl, wrench, item),
@@ -1462,6 +1577,7 @@ impl YamlFrameReader {
                 "border" => self.handle_border(dl, wrench, item, &mut info),
                 "gradient" => self.handle_gradient(dl, item, &mut info),
                 "radial-gradient" => self.handle_radial_gradient(dl, item, &mut info),
+                "conic-gradient" => self.handle_conic_gradient(dl, item, &mut info),
                 "box-shadow" => self.handle_box_shadow(dl, item, &mut info),
                 "iframe" => self.handle_iframe(dl, item, &mut info),
                 "stacking-context" => {
@@ -1766,6 +1882,8 @@ impl YamlFrameReader {
         }
 
         let filters = yaml["filters"].as_vec_filter_op().unwrap_or(vec![]);
+        let filter_primitives = yaml["filter-primitives"].as_vec_filter_primitive().unwrap_or(vec![]);
+        let backdrop_filters = yaml["backdrop-filters"].as_vec_filter_op().unwrap_or(vec![]);
 
         info.rect = bounds;
         info.clip_rect = bounds;
@@ -1777,6 +1895,8 @@ impl YamlFrameReader {
             transform_style,
             mix_blend_mode,
             &filters,
+            &filter_primitives,
+            &backdrop_filters,
             raster_space,
             /* cache_tiles = */ false,
         );
@@ -217,6 +217,8 @@ fn write_stacking_context(
     sc: &StackingContext,
     properties: &SceneProperties,
     filter_iter: AuxIter<FilterOp>,
+    filter_primitive_iter: AuxIter<FilterPrimitive>,
+    backdrop_filter_iter: AuxIter<FilterOp>,
 ) {
     enum_node(parent, "transform-style", sc.transform_style);
 
@@ -235,41 +237,95 @@ fn write_stacking_context(
         enum_node(parent, "mix-blend-mode", sc.mix_blend_mode)
     }
     // filters
-    let mut filters = vec![];
-    for filter in filter_iter {
-        match filter {
-            FilterOp::Identity => { filters.push(Yaml::String("identity".into())) }
-            FilterOp::Blur(x) => { filters.push(Yaml::String(format!("blur({})", x))) }
-            FilterOp::Brightness(x) => { filters.push(Yaml::String(format!("brightness({})", x))) }
-            FilterOp::Contrast(x) => { filters.push(Yaml::String(format!("contrast({})", x))) }
-            FilterOp::Grayscale(x) => { filters.push(Yaml::String(format!("grayscale({})", x))) }
-            FilterOp::HueRotate(x) => { filters.push(Yaml::String(format!("hue-rotate({})", x))) }
-            FilterOp::Invert(x) => { filters.push(Yaml::String(format!("invert({})", x))) }
-            FilterOp::Opacity(x, _) => {
-                filters.push(Yaml::String(format!("opacity({})",
-                                                  properties.resolve_float(&x))))
-            }
-            FilterOp::Saturate(x) => { filters.push(Yaml::String(format!("saturate({})", x))) }
-            FilterOp::Sepia(x) => { filters.push(Yaml::String(format!("sepia({})", x))) }
-            FilterOp::DropShadow(offset, blur, color) => {
-                filters.push(Yaml::String(format!("drop-shadow([{},{}],{},[{}])",
-                                                  offset.x, offset.y,
-                                                  blur,
-                                                  color_to_string(color))))
-            }
-            FilterOp::ColorMatrix(matrix) => {
-                filters.push(Yaml::String(format!("color-matrix({:?})", matrix)))
+    let filters = filter_iter
+        .map(|filter| filter_to_yaml(filter, properties))
+        .collect();
+
+    yaml_node(parent, "filters", Yaml::Array(filters));
+
+    let backdrop_filters: Vec<Yaml> = backdrop_filter_iter
+        .map(|filter| filter_to_yaml(filter, properties))
+        .collect();
+
+    if !backdrop_filters.is_empty() {
+        yaml_node(parent, "backdrop-filters", Yaml::Array(backdrop_filters));
+    }
+
+    let mut filter_primitives = vec![];
+    for filter_primitive in filter_primitive_iter {
+        let mut table = new_table();
+        match filter_primitive {
+            FilterPrimitive::Blur { input, std_deviation } => {
+                str_node(&mut table, "type", "blur");
+                yaml_node(&mut table, "in", filter_primitive_input_to_yaml(input));
+                f32_node(&mut table, "std-deviation", std_deviation);
             }
-            FilterOp::SrgbToLinear => {
-                filters.push(Yaml::String("srgb-to-linear".to_string()))
+            FilterPrimitive::ColorMatrix { input, matrix } => {
+                str_node(&mut table, "type", "color-matrix");
+                yaml_node(&mut table, "in", filter_primitive_input_to_yaml(input));
+                f32_vec_node(&mut table, "matrix", &matrix);
             }
-            FilterOp::LinearToSrgb => {
-                filters.push(Yaml::String("linear-to-srgb".to_string()))
+            FilterPrimitive::Composite { input1, input2, operator } => {
+                str_node(&mut table, "type", "composite");
+                yaml_node(&mut table, "in", filter_primitive_input_to_yaml(input1));
+                yaml_node(&mut table, "in2", filter_primitive_input_to_yaml(input2));
+                let operator = match operator {
+                    CompositeOperator::Over => "over",
+                    CompositeOperator::In => "in",
+                    CompositeOperator::Out => "out",
+                    CompositeOperator::Atop => "atop",
+                    CompositeOperator::Xor => "xor",
+                    CompositeOperator::Lighter => "lighter",
+                    CompositeOperator::Arithmetic(k) => {
+                        f32_vec_node(&mut table, "k", &k);
+                        "arithmetic"
+                    }
+                };
+                str_node(&mut table, "operator", operator);
             }
         }
+        filter_primitives.push(Yaml::Hash(table));
     }
 
-    yaml_node(parent, "filters", Yaml::Array(filters));
+    if !filter_primitives.is_empty() {
+        yaml_node(parent, "filter-primitives", Yaml::Array(filter_primitives));
+    }
+}
+
+fn filter_to_yaml(filter: FilterOp, properties: &SceneProperties) -> Yaml {
+    match filter {
+        FilterOp::Identity => Yaml::String("identity".into()),
+        FilterOp::Blur(x) => Yaml::String(format!("blur({})", x)),
+        FilterOp::Brightness(x) => Yaml::String(format!("brightness({})", x)),
+        FilterOp::Contrast(x) => Yaml::String(format!("contrast({})", x)),
+        FilterOp::Grayscale(x) => Yaml::String(format!("grayscale({})", x)),
+        FilterOp::HueRotate(x) => Yaml::String(format!("hue-rotate({})", x)),
+        FilterOp::Invert(x) => Yaml::String(format!("invert({})", x)),
+        FilterOp::Opacity(x, _) => {
+            Yaml::String(format!("opacity({})", properties.resolve_float(&x)))
+        }
+        FilterOp::Saturate(x) => Yaml::String(format!("saturate({})", x)),
+        FilterOp::Sepia(x) => Yaml::String(format!("sepia({})", x)),
+        FilterOp::DropShadow(offset, blur, color) => {
+            Yaml::String(format!("drop-shadow([{},{}],{},[{}])",
+                                 offset.x, offset.y,
+                                 blur,
+                                 color_to_string(color)))
+        }
+        FilterOp::ColorMatrix(matrix) => {
+            Yaml::String(format!("color-matrix({:?})", matrix))
+        }
+        FilterOp::SrgbToLinear => Yaml::String("srgb-to-linear".to_string()),
+        FilterOp::LinearToSrgb => Yaml::String("linear-to-srgb".to_string()),
+    }
+}
+
+fn filter_primitive_input_to_yaml(input: FilterPrimitiveInput) -> Yaml {
+    match input {
+        FilterPrimitiveInput::Original => Yaml::String("original".into()),
+        FilterPrimitiveInput::Previous => Yaml::String("previous".into()),
+        FilterPrimitiveInput::OutputOfPrimitiveIndex(index) => Yaml::Integer(index as i64),
+    }
 }
 
 #[cfg(target_os = "macos")]
@@ -335,6 +391,30 @@ fn radial_gradient_to_yaml(
     bool_node(table, "repeat", gradient.extend_mode == ExtendMode::Repeat);
 }
 
+fn conic_gradient_to_yaml(
+    table: &mut Table,
+    gradient: &webrender::api::ConicGradient,
+    stops_range: ItemRange<GradientStop>,
+    display_list: &BuiltDisplayList
+) {
+    point_node(table, "center", &gradient.center);
+    f32_node(table, "angle", gradient.angle.to_degrees());
+
+    let first_offset = gradient.start_offset;
+    let last_offset = gradient.end_offset;
+    let stops_delta = last_offset - first_offset;
+    assert!(first_offset <= last_offset);
+
+    let mut denormalized_stops = vec![];
+    for stop in display_list.get(stops_range) {
+        let denormalized_stop = (stop.offset * stops_delta) + first_offset;
+        denormalized_stops.push(Yaml::Real(denormalized_stop.to_string()));
+        denormalized_stops.push(Yaml::String(color_to_string(stop.color)));
+    }
+    yaml_node(table, "stops", Yaml::Array(denormalized_stops));
+    bool_node(table, "repeat", gradient.extend_mode == ExtendMode::Repeat);
+}
+
 enum CachedFont {
     Native(NativeFontHandle, Option<PathBuf>),
     Raw(Option<Vec<u8>>, u32, Option<PathBuf>),
@@ -533,9 +613,7 @@ impl YamlFrameWriter {
                           }
                     }
 
-                    let stride = img.descriptor.stride.unwrap_or(
-                        img.descriptor.size.width * img.descriptor.format.bytes_per_pixel(),
-                    );
+                    let stride = img.descriptor.compute_stride();
                     let bytes = match img.data {
                         ImageData::Raw(ref v) => (**v).clone(),
                         ImageData::External(_) => {
@@ -765,7 +843,7 @@ impl YamlFrameWriter {
             match *base.item() {
                 Sdi::Rectangle(item) => {
                     str_node(&mut v, "type", "rect");
-                    color_node(&mut v, "color", item.color);
+                    color_node(&mut v, "color", scene.properties.resolve_color(&item.color));
                 }
                 Sdi::ClearRectangle => {
                     str_node(&mut v, "type", "clear-rect");;
@@ -834,13 +912,14 @@ impl YamlFrameWriter {
                     }
                 }
                 Sdi::Image(item) => {
-                    if let Some(path) = self.path_for_image(item.image_key) {
+                    let image_key = scene.properties.resolve_image_key(&item.image_key);
+                    if let Some(path) = self.path_for_image(image_key) {
                         path_node(&mut v, "image", &path);
                     }
                     if let Some(&CachedImage {
                         tiling: Some(tile_size),
                         ..
-                    }) = self.images.get(&item.image_key)
+                    }) = self.images.get(&image_key)
                     {
                         u32_node(&mut v, "tile-size", tile_size as u32);
                     }
@@ -861,6 +940,21 @@ impl YamlFrameWriter {
                     // TODO
                     println!("TODO YAML YuvImage");
                 }
+                Sdi::CrossFade(item) => {
+                    str_node(&mut v, "type", "cross-fade");
+                    if let Some(path) = self.path_for_image(item.from_key) {
+                        path_node(&mut v, "from", &path);
+                    }
+                    if let Some(path) = self.path_for_image(item.to_key) {
+                        path_node(&mut v, "to", &path);
+                    }
+                    f32_node(&mut v, "progress", scene.properties.resolve_float(&item.progress));
+                    match item.image_rendering {
+                        ImageRendering::Auto => (),
+                        ImageRendering::CrispEdges => str_node(&mut v, "rendering", "crisp-edges"),
+                        ImageRendering::Pixelated => str_node(&mut v, "rendering", "pixelated"),
+                    };
+                }
                 Sdi::Border(item) => {
                     str_node(&mut v, "type", "border");
                     match item.details {
@@ -1021,6 +1115,17 @@ impl YamlFrameWriter {
                         display_list
                     );
                 }
+                Sdi::ConicGradient(item) => {
+                    str_node(&mut v, "type", "conic-gradient");
+                    size_node(&mut v, "tile-size", &item.tile_size);
+                    size_node(&mut v, "tile-spacing", &item.tile_spacing);
+                    conic_gradient_to_yaml(
+                        &mut v,
+                        &item.gradient,
+                        base.gradient_stops(),
+                        display_list
+                    );
+                }
                 Sdi::Iframe(item) => {
                     str_node(&mut v, "type", "iframe");
                     u32_vec_node(&mut v, "id", &[item.pipeline_id.0, item.pipeline_id.1]);
@@ -1029,11 +1134,15 @@ impl YamlFrameWriter {
                 Sdi::PushStackingContext(item) => {
                     str_node(&mut v, "type", "stacking-context");
                     let filters = display_list.get(base.filters());
+                    let filter_primitives = display_list.get(base.filter_primitives());
+                    let backdrop_filters = display_list.get(base.backdrop_filters());
                     write_stacking_context(
                         &mut v,
                         &item.stacking_context,
                         &scene.properties,
                         filters,
+                        filter_primitives,
+                        backdrop_filters,
                     );
 
                     let mut sub_iter = base.sub_iter();
@@ -1057,6 +1166,11 @@ impl YamlFrameWriter {
                     str_node(&mut v, "type", "clip");
                     usize_node(&mut v, "id", clip_id_mapper.add_clip_id(item.id));
 
+                    if let Some(key) = item.rect_binding {
+                        let binding = PropertyBinding::Binding(key, *base.clip_rect());
+                        rect_node(&mut v, "clip-rect", &scene.properties.resolve_layout_rect(&binding));
+                    }
+
                     let (complex_clips, complex_clip_count) = base.complex_clip();
                     if let Some(complex) = self.make_complex_clips_node(
                         complex_clip_count,
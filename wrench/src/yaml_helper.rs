@@ -36,6 +36,9 @@ pub trait YamlHelper {
     fn as_mix_blend_mode(&self) -> Option<MixBlendMode>;
     fn as_filter_op(&self) -> Option<FilterOp>;
     fn as_vec_filter_op(&self) -> Option<Vec<FilterOp>>;
+    fn as_filter_primitive_input(&self) -> Option<FilterPrimitiveInput>;
+    fn as_filter_primitive(&self) -> Option<FilterPrimitive>;
+    fn as_vec_filter_primitive(&self) -> Option<Vec<FilterPrimitive>>;
 }
 
 fn string_to_color(color: &str) -> Option<ColorF> {
@@ -606,4 +609,69 @@ impl YamlHelper for Yaml {
             self.as_filter_op().map(|op| vec![op])
         }
     }
+
+    fn as_filter_primitive_input(&self) -> Option<FilterPrimitiveInput> {
+        if self.is_badvalue() {
+            return Some(FilterPrimitiveInput::Previous);
+        }
+        if let Some(index) = self.as_i64() {
+            return Some(FilterPrimitiveInput::OutputOfPrimitiveIndex(index as usize));
+        }
+        match self.as_str() {
+            Some("original") => Some(FilterPrimitiveInput::Original),
+            Some("previous") => Some(FilterPrimitiveInput::Previous),
+            _ => None,
+        }
+    }
+
+    fn as_filter_primitive(&self) -> Option<FilterPrimitive> {
+        match self["type"].as_str() {
+            Some("blur") => Some(FilterPrimitive::Blur {
+                input: self["in"].as_filter_primitive_input()?,
+                std_deviation: self["std-deviation"].as_force_f32()?,
+            }),
+            Some("color-matrix") => {
+                let m = self["matrix"].as_vec_f32()?;
+                if m.len() != 20 {
+                    return None;
+                }
+                let mut matrix: [f32; 20] = [0.0; 20];
+                matrix.clone_from_slice(&m);
+                Some(FilterPrimitive::ColorMatrix {
+                    input: self["in"].as_filter_primitive_input()?,
+                    matrix,
+                })
+            }
+            Some("composite") => {
+                let operator = match self["operator"].as_str().unwrap_or("over") {
+                    "over" => CompositeOperator::Over,
+                    "in" => CompositeOperator::In,
+                    "out" => CompositeOperator::Out,
+                    "atop" => CompositeOperator::Atop,
+                    "xor" => CompositeOperator::Xor,
+                    "lighter" => CompositeOperator::Lighter,
+                    "arithmetic" => {
+                        let k = self["k"].as_vec_f32()?;
+                        if k.len() != 4 {
+                            return None;
+                        }
+                        CompositeOperator::Arithmetic([k[0], k[1], k[2], k[3]])
+                    }
+                    _ => return None,
+                };
+                Some(FilterPrimitive::Composite {
+                    input1: self["in"].as_filter_primitive_input()?,
+                    input2: self["in2"].as_filter_primitive_input()?,
+                    operator,
+                })
+            }
+            _ => None,
+        }
+    }
+
+    fn as_vec_filter_primitive(&self) -> Option<Vec<FilterPrimitive>> {
+        self.as_vec().map(|v| {
+            v.iter().map(|x| x.as_filter_primitive().unwrap()).collect()
+        })
+    }
 }